@@ -3,7 +3,7 @@
 //! This also includes a license text viewer. The license text viewer is needed
 //! on Android, where the command-line way to view license text doesn't exist.
 
-use crate::bundle::Bundle;
+use crate::bundle::{check_minimum_os_version, Bundle};
 use crate::frameworks::core_graphics::cg_bitmap_context::{
     CGBitmapContextCreate, CGBitmapContextCreateImage,
 };
@@ -35,7 +35,7 @@ use crate::paths;
 use crate::window::DeviceOrientation;
 use crate::Environment;
 use std::collections::HashMap;
-use std::ffi::OsStr;
+use std::ffi::{OsStr, OsString};
 use std::num::NonZeroU32;
 use std::path::{Path, PathBuf};
 
@@ -43,6 +43,9 @@ struct AppInfo {
     path: PathBuf,
     display_name: String,
     icon: Option<Image>,
+    /// Explanation of why the app can't be launched, if it requires a newer OS
+    /// version than is supported.
+    unsupported_os: Option<String>,
     /// `NSString*`
     display_name_ns_string: Option<id>,
     /// `UIImage*`
@@ -58,7 +61,7 @@ pub fn app_picker(
     let apps: Result<Vec<AppInfo>, String> = if !apps_dir.is_dir() {
         Err(format!("The {} directory couldn't be found. Check you're running touchHLE from the right directory.", apps_dir.display()))
     } else {
-        enumerate_apps(&apps_dir)
+        enumerate_apps(&apps_dir, options.force_unsupported_os)
             .map_err(|err| {
                 format!(
                     "Couldn't get list of apps in the {} directory: {}.",
@@ -81,7 +84,10 @@ pub fn app_picker(
     show_app_picker_gui(options, option_args, apps)
 }
 
-fn enumerate_apps(apps_dir: &Path) -> Result<Vec<AppInfo>, std::io::Error> {
+fn enumerate_apps(
+    apps_dir: &Path,
+    force_unsupported_os: bool,
+) -> Result<Vec<AppInfo>, std::io::Error> {
     let mut apps = Vec::new();
     for app in std::fs::read_dir(apps_dir)? {
         let app_path = app?.path();
@@ -117,20 +123,61 @@ fn enumerate_apps(apps_dir: &Path) -> Result<Vec<AppInfo>, std::io::Error> {
             }
         };
 
+        let unsupported_os =
+            check_minimum_os_version(bundle.minimum_os_version(), force_unsupported_os).err();
+
         apps.push(AppInfo {
             path: app_path,
             display_name,
             icon,
+            unsupported_os,
             display_name_ns_string: None,
             icon_ui_image: None,
         });
     }
 
     apps.sort_by_key(|app| app.display_name.to_uppercase());
+    move_last_app_to_front(&mut apps, read_last_app().as_deref());
 
     Ok(apps)
 }
 
+/// Get the file name of the app that was last launched from the app picker, if
+/// it has been recorded.
+fn read_last_app() -> Option<OsString> {
+    let path = paths::user_data_base_path().join(paths::LAST_APP_FILE);
+    let name = std::fs::read_to_string(path).ok()?;
+    let name = name.trim();
+    (!name.is_empty()).then(|| OsString::from(name))
+}
+
+/// Record the app that was launched from the app picker, so it can be shown
+/// first next time.
+fn write_last_app(app_path: &Path) {
+    let Some(name) = app_path.file_name().and_then(|name| name.to_str()) else {
+        return;
+    };
+    let path = paths::user_data_base_path().join(paths::LAST_APP_FILE);
+    if let Err(e) = std::fs::write(&path, name) {
+        log!("Warning: couldn't write {}: {}", path.display(), e);
+    }
+}
+
+/// Move the app with the file name `last_app` to the front of the list, keeping
+/// the order of the others. Nothing happens if there's no such app, e.g.
+/// because it has since been removed.
+fn move_last_app_to_front(apps: &mut [AppInfo], last_app: Option<&OsStr>) {
+    let Some(last_app) = last_app else {
+        return;
+    };
+    if let Some(idx) = apps
+        .iter()
+        .position(|app| app.path.file_name() == Some(last_app))
+    {
+        apps[..=idx].rotate_right(1);
+    }
+}
+
 #[derive(Default)]
 struct AppPickerDelegateHostObject {
     icon_tapped: id,
@@ -162,6 +209,12 @@ pub const CLASSES: ClassExports = objc_classes! {
 
 @implementation _touchHLE_AppPickerDelegate: NSObject
 
+// UITextFieldDelegate implementation for the search field
+- (bool)textFieldShouldReturn:(id)text_field { // UITextField*
+    let _: bool = msg![env; text_field resignFirstResponder];
+    true
+}
+
 - (())iconTapped:(id)sender {
     // There is no allocWithZone: that creates AppPickerDelegateHostObject, so
     // this downcast effectively acts as an assertion that this class is being
@@ -435,8 +488,18 @@ fn show_app_picker_gui(
 
     let divider = app_frame.size.height - 100.0;
 
+    let mut search_field = None;
+    let mut search_query = String::new();
+
     let mut icon_grid_stuff = match &mut apps {
         Ok(ref mut apps) => {
+            search_field = Some(make_search_field(
+                env,
+                delegate,
+                main_view,
+                app_frame,
+                have_wallpaper,
+            ));
             let mut icon_grid_stuff = make_icon_grid(
                 env,
                 delegate,
@@ -471,6 +534,38 @@ fn show_app_picker_gui(
         }
     };
 
+    // Explanation shown when an app that can't be launched is tapped
+    let status_label = {
+        let label_frame = CGRect {
+            origin: CGPoint {
+                x: 10.0,
+                y: divider - 45.0,
+            },
+            size: CGSize {
+                width: app_frame.size.width - 20.0,
+                height: 40.0,
+            },
+        };
+        let label: id = msg_class![env; UILabel alloc];
+        let label: id = msg![env; label initWithFrame:label_frame];
+        () = msg![env; label setTextAlignment:UITextAlignmentCenter];
+        () = msg![env; label setNumberOfLines:0]; // unlimited
+        let font_size: CGFloat = 11.0;
+        let font: id = msg_class![env; UIFont systemFontOfSize:font_size];
+        () = msg![env; label setFont:font];
+        let text_color: id = if have_wallpaper {
+            msg_class![env; UIColor whiteColor]
+        } else {
+            msg_class![env; UIColor lightGrayColor]
+        };
+        () = msg![env; label setTextColor:text_color];
+        let bg_color: id = msg_class![env; UIColor clearColor];
+        () = msg![env; label setBackgroundColor:bg_color];
+        () = msg![env; label setHidden:true];
+        () = msg![env; main_view addSubview:label];
+        label
+    };
+
     let buttons_row_center = divider + (app_frame.size.height - divider) / 4.0;
     let buttons_row2_center = divider + (app_frame.size.height - divider) / 1.6;
     make_button_row(
@@ -557,16 +652,44 @@ fn show_app_picker_gui(
     // process exits.
     let app_path = loop {
         run_run_loop_single_iteration(env, main_run_loop);
+
+        if let Some(search_field) = search_field {
+            let text: id = msg![env; search_field text];
+            let text = if text == nil {
+                String::new()
+            } else {
+                ns_string::to_rust_string(env, text).into_owned()
+            };
+            if text != search_query {
+                let apps = apps.as_mut().unwrap();
+                let icon_grid_stuff = icon_grid_stuff.as_mut().unwrap();
+                set_icon_grid_filter(icon_grid_stuff, filter_apps(apps, &text));
+                update_icon_grid(env, icon_grid_stuff, apps, 0);
+                () = msg![env; status_label setHidden:true];
+                search_query = text;
+            }
+        }
+
         let host_obj = env.objc.borrow_mut::<AppPickerDelegateHostObject>(delegate);
         let icon_tapped = std::mem::take(&mut host_obj.icon_tapped);
         if icon_tapped != nil {
             match icon_grid_stuff.as_ref().unwrap().icon_map.get(&icon_tapped) {
                 Some(&TappedIcon::App(app_idx)) => {
-                    let app_path = &apps.as_ref().unwrap()[app_idx].path;
-                    echo!("Picked: {}", app_path.display());
-                    break app_path.clone();
+                    let app = &apps.as_ref().unwrap()[app_idx];
+                    if let Some(ref reason) = app.unsupported_os {
+                        echo!("Can't launch {}: {}", app.path.display(), reason);
+                        let text = format!("{}: {}", app.display_name, reason);
+                        let text = ns_string::from_rust_string(env, text);
+                        () = msg![env; status_label setText:text];
+                        () = msg![env; status_label setHidden:false];
+                        continue;
+                    }
+                    echo!("Picked: {}", app.path.display());
+                    write_last_app(&app.path);
+                    break app.path.clone();
                 }
                 Some(&TappedIcon::ChangePage(page_idx)) => {
+                    () = msg![env; status_label setHidden:true];
                     update_icon_grid(
                         env,
                         icon_grid_stuff.as_mut().unwrap(),
@@ -721,6 +844,9 @@ struct IconGridStuff {
     placeholder_icon: Option<id>,
     prev_icon: Option<id>,
     next_icon: Option<id>,
+    /// Indices of the apps that match the search query.
+    visible_apps: Vec<usize>,
+    /// Ranges of [IconGridStuff::visible_apps] shown on each page.
     pages: Vec<std::ops::Range<usize>>,
     icon_map: HashMap<id, TappedIcon>,
 }
@@ -735,7 +861,8 @@ fn make_icon_grid(
 ) -> IconGridStuff {
     let num_cols = 4;
     let num_cols_f = num_cols as CGFloat;
-    let num_rows = 4;
+    // There would be room for a fourth row if not for the search field.
+    let num_rows = 3;
     let label_size = CGSize {
         width: 74.0,
         height: 13.0,
@@ -745,7 +872,7 @@ fn make_icon_grid(
     let icon_grid_width = (ICON_SIZE.width * num_cols_f) + icon_gap_x * (num_cols_f - 1.0);
     let icon_grid_origin = CGPoint {
         x: (app_frame.size.width - icon_grid_width) / 2.0,
-        y: 12.0,
+        y: SEARCH_FIELD_ORIGIN.y + SEARCH_FIELD_HEIGHT + 12.0,
     };
 
     let icon_tapped_sel = env.objc.lookup_selector("iconTapped:").unwrap();
@@ -803,31 +930,114 @@ fn make_icon_grid(
         icon_buttons_and_labels.push((icon_button, label));
     }
 
+    let mut icon_grid_stuff = IconGridStuff {
+        icon_buttons_and_labels,
+        placeholder_icon: None,
+        prev_icon: None,
+        next_icon: None,
+        visible_apps: Vec::new(),
+        pages: Vec::new(),
+        icon_map: HashMap::new(),
+    };
+    set_icon_grid_filter(&mut icon_grid_stuff, (0..total_app_count).collect());
+    icon_grid_stuff
+}
+
+/// Split `app_count` apps into pages of icons for a grid with `slot_count`
+/// spaces. There is always at least one page, even if it's empty.
+fn paginate(app_count: usize, slot_count: usize) -> Vec<std::ops::Range<usize>> {
     // TODO: Use UIScrollView pagination and UIPageControl once available.
     let mut pages = Vec::new();
     let mut start = 0;
-    while start < total_app_count {
-        let mut end = start + icon_buttons_and_labels.len();
+    while start < app_count {
+        let mut end = start + slot_count;
         if start > 0 {
             end -= 1; // one icon space taken by "previous" button
         }
-        if end < total_app_count {
+        if end < app_count {
             end -= 1; // one icon space taken by "next" button
         } else {
-            end = total_app_count;
+            end = app_count;
         }
         pages.push(start..end);
         start = end;
     }
-
-    IconGridStuff {
-        icon_buttons_and_labels,
-        placeholder_icon: None,
-        prev_icon: None,
-        next_icon: None,
-        pages,
-        icon_map: HashMap::new(),
+    if pages.is_empty() {
+        pages.push(0..0);
     }
+    pages
+}
+
+/// Get the indices of the apps whose display names contain `query`, ignoring
+/// case. An empty query matches every app.
+fn filter_apps(apps: &[AppInfo], query: &str) -> Vec<usize> {
+    let query = query.trim().to_lowercase();
+    apps.iter()
+        .enumerate()
+        .filter(|(_, app)| app.display_name.to_lowercase().contains(&query))
+        .map(|(app_idx, _)| app_idx)
+        .collect()
+}
+
+/// Change which apps are shown by the icon grid. [update_icon_grid] must be
+/// called afterwards, with a page index of 0.
+fn set_icon_grid_filter(icon_grid_stuff: &mut IconGridStuff, visible_apps: Vec<usize>) {
+    icon_grid_stuff.pages = paginate(
+        visible_apps.len(),
+        icon_grid_stuff.icon_buttons_and_labels.len(),
+    );
+    icon_grid_stuff.visible_apps = visible_apps;
+}
+
+/// Position of the search field's top-left corner. Its label goes to the left.
+const SEARCH_FIELD_ORIGIN: CGPoint = CGPoint { x: 70.0, y: 10.0 };
+const SEARCH_FIELD_HEIGHT: CGFloat = 24.0;
+
+/// Make the text field used to filter the apps by name, and its label.
+fn make_search_field(
+    env: &mut Environment,
+    delegate: id,
+    main_view: id,
+    app_frame: CGRect,
+    have_wallpaper: bool,
+) -> id {
+    let label_frame = CGRect {
+        origin: CGPoint {
+            x: 10.0,
+            y: SEARCH_FIELD_ORIGIN.y,
+        },
+        size: CGSize {
+            width: SEARCH_FIELD_ORIGIN.x - 15.0,
+            height: SEARCH_FIELD_HEIGHT,
+        },
+    };
+    let label: id = msg_class![env; UILabel alloc];
+    let label: id = msg![env; label initWithFrame:label_frame];
+    let text = ns_string::get_static_str(env, "Search:");
+    () = msg![env; label setText:text];
+    () = msg![env; label setTextAlignment:UITextAlignmentRight];
+    let text_color: id = if have_wallpaper {
+        msg_class![env; UIColor whiteColor]
+    } else {
+        msg_class![env; UIColor lightGrayColor]
+    };
+    () = msg![env; label setTextColor:text_color];
+    let bg_color: id = msg_class![env; UIColor clearColor];
+    () = msg![env; label setBackgroundColor:bg_color];
+    () = msg![env; main_view addSubview:label];
+
+    let field_frame = CGRect {
+        origin: SEARCH_FIELD_ORIGIN,
+        size: CGSize {
+            width: app_frame.size.width - SEARCH_FIELD_ORIGIN.x - 10.0,
+            height: SEARCH_FIELD_HEIGHT,
+        },
+    };
+    let field: id = msg_class![env; UITextField alloc];
+    let field: id = msg![env; field initWithFrame:field_frame];
+    () = msg![env; field setDelegate:delegate];
+    () = msg![env; main_view addSubview:field];
+    field
 }
 
 fn make_icon_from_glyph(
@@ -898,9 +1108,10 @@ fn update_icon_grid(
 ) {
     icon_grid_stuff.icon_map.clear();
 
-    let app_idx_range = icon_grid_stuff.pages[page_idx].clone();
+    let visible_range = icon_grid_stuff.pages[page_idx].clone();
     let have_prev_icon = page_idx != 0;
-    let have_next_icon = app_idx_range.end != apps.len();
+    let have_next_icon = visible_range.end != icon_grid_stuff.visible_apps.len();
+    let app_idxs = icon_grid_stuff.visible_apps[visible_range].to_vec();
 
     let mut icon_iter = icon_grid_stuff.icon_buttons_and_labels.iter();
 
@@ -910,13 +1121,14 @@ fn update_icon_grid(
             make_icon_from_glyph(env, '←', 50.0, -9.0, (0.25, 0.25, 0.25, 1.0))
         });
         () = msg![env; icon_button setImage:image forState:UIControlStateNormal];
+        () = msg![env; icon_button setAlpha:(1.0 as CGFloat)];
         () = msg![env; label setText:(ns_string::get_static_str(env, ""))];
         icon_grid_stuff
             .icon_map
             .insert(icon_button, TappedIcon::ChangePage(page_idx - 1));
     }
 
-    for app_idx in app_idx_range.clone() {
+    for app_idx in app_idxs {
         let app = &mut apps[app_idx];
 
         let &(icon_button, label) = icon_iter.next().unwrap();
@@ -933,6 +1145,13 @@ fn update_icon_grid(
             })
         });
         () = msg![env; icon_button setImage:image forState:UIControlStateNormal];
+        // Apps that can't be launched are dimmed
+        let alpha: CGFloat = if app.unsupported_os.is_some() {
+            0.4
+        } else {
+            1.0
+        };
+        () = msg![env; icon_button setAlpha:alpha];
 
         let text = *app
             .display_name_ns_string
@@ -950,6 +1169,7 @@ fn update_icon_grid(
             make_icon_from_glyph(env, '→', 50.0, -9.0, (0.25, 0.25, 0.25, 1.0))
         });
         () = msg![env; icon_button setImage:image forState:UIControlStateNormal];
+        () = msg![env; icon_button setAlpha:(1.0 as CGFloat)];
         () = msg![env; label setText:(ns_string::get_static_str(env, ""))];
         icon_grid_stuff
             .icon_map
@@ -1350,3 +1570,71 @@ fn setup_quick_options(
         fullscreen_buttons: button_rows.get(2).map(|r| r[..].try_into().unwrap()),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_apps() -> Vec<AppInfo> {
+        [
+            "Super Monkey Ball",
+            "Crash Bandicoot Nitro Kart 3D",
+            "Monkey Island",
+            "Spore Origins",
+        ]
+        .iter()
+        .map(|&name| AppInfo {
+            path: PathBuf::from(format!("{}.app", name)),
+            display_name: name.to_string(),
+            icon: None,
+            unsupported_os: None,
+            display_name_ns_string: None,
+            icon_ui_image: None,
+        })
+        .collect()
+    }
+
+    #[test]
+    fn app_filtering() {
+        let apps = sample_apps();
+        assert_eq!(filter_apps(&apps, ""), [0, 1, 2, 3]);
+        assert_eq!(filter_apps(&apps, "monkey"), [0, 2]);
+        assert_eq!(filter_apps(&apps, "MONKEY "), [0, 2]);
+        assert_eq!(filter_apps(&apps, "o"), [0, 1, 2, 3]);
+        assert_eq!(filter_apps(&apps, "3d"), [1]);
+        assert!(filter_apps(&apps, "tetris").is_empty());
+    }
+
+    #[test]
+    fn last_app_first() {
+        let mut apps = sample_apps();
+        move_last_app_to_front(&mut apps, Some(OsStr::new("Monkey Island.app")));
+        let names: Vec<_> = apps.iter().map(|app| app.display_name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "Monkey Island",
+                "Super Monkey Ball",
+                "Crash Bandicoot Nitro Kart 3D",
+                "Spore Origins"
+            ]
+        );
+
+        // The recorded app might no longer exist
+        let mut apps = sample_apps();
+        move_last_app_to_front(&mut apps, Some(OsStr::new("Deleted.ipa")));
+        move_last_app_to_front(&mut apps, None);
+        assert_eq!(apps[0].display_name, "Super Monkey Ball");
+        assert_eq!(apps[3].display_name, "Spore Origins");
+    }
+
+    #[test]
+    fn pagination() {
+        assert_eq!(paginate(0, 12), [0..0]);
+        assert_eq!(paginate(12, 12), [0..12]);
+        // Later pages lose a space to the "previous" button, and all but the
+        // last page lose one to the "next" button.
+        assert_eq!(paginate(13, 12), [0..11, 11..13]);
+        assert_eq!(paginate(30, 12), [0..11, 11..21, 21..30]);
+    }
+}
@@ -13,13 +13,18 @@
 //! - [Apple Core Audio Format Specification 1.0](https://developer.apple.com/library/archive/documentation/MusicAudio/Reference/CAFSpec/CAF_intro/CAF_intro.html)
 
 mod ima4;
+mod output_device;
+mod panning;
 mod symphonia_formats;
 
 pub use ima4::decode_ima4;
+pub use output_device::{open_output_device, OutputDevice};
+pub use panning::set_source_pan;
 pub use touchHLE_openal_soft_wrapper as openal;
 
 use crate::fs::{Fs, GuestPath};
 use std::io::Cursor;
+use std::time::Duration;
 
 #[derive(Debug)]
 pub enum AudioFileOpenError {
@@ -53,7 +58,7 @@ pub struct AudioFile(AudioFileInner);
 enum AudioFileInner {
     Wave(hound::WavReader<Cursor<Vec<u8>>>),
     Caf(caf::CafPacketReader<Cursor<Vec<u8>>>),
-    Symphonia(symphonia_formats::SymphoniaDecodedToPcm),
+    Symphonia(symphonia_formats::SymphoniaDecodedOnDemand),
 }
 
 impl AudioFile {
@@ -78,6 +83,10 @@ impl AudioFile {
         }
     }
 
+    /// Open an audio file from its contents. The format is detected by
+    /// sniffing the container: WAVE and CAF are tried first, then Symphonia's
+    /// probe, which recognises MP3, ADTS AAC and MPEG-4 (`.m4a`/`.mp4`) files.
+    /// AAC support is limited to the LC profile.
     pub fn read_from_vec(bytes: Vec<u8>) -> Result<Self, AudioFileOpenError> {
         // Both WavReader::new() and CafPacketReader::new() consume the reader
         // (in this case, a Cursor) passed to them. This is a bit annoying
@@ -92,10 +101,11 @@ impl AudioFile {
             let reader = caf::CafPacketReader::new(Cursor::new(bytes), vec![]).unwrap();
             Ok(AudioFile(AudioFileInner::Caf(reader)))
         // TODO: Real MP3/MP4/Non-linear PCM container handling. Currently we
-        // are immediately decoding the entire file to PCM and acting as if
-        // it's a PCM file, simply because because this is easier. Full MP3
-        // support would require a lot of changes in Audio Toolbox.
-        } else if let Ok(pcm) = symphonia_formats::decode_symphonia_to_pcm(Cursor::new(bytes)) {
+        // are decoding the file to PCM as it is read and acting as if it's a
+        // PCM file, simply because because this is easier. Full MP3 support
+        // would require a lot of changes in Audio Toolbox.
+        } else if let Ok(pcm) = symphonia_formats::SymphoniaDecodedOnDemand::new(Cursor::new(bytes))
+        {
             Ok(AudioFile(AudioFileInner::Symphonia(pcm)))
         } else {
             Err(AudioFileOpenError::FileDecodeError)
@@ -168,7 +178,7 @@ impl AudioFile {
                     bits_per_channel,
                 }
             }
-            AudioFileInner::Symphonia(symphonia_formats::SymphoniaDecodedToPcm {
+            AudioFileInner::Symphonia(symphonia_formats::SymphoniaDecodedOnDemand {
                 sample_rate,
                 channels,
                 ..
@@ -200,7 +210,8 @@ impl AudioFile {
         ((bytes_per_packet / frames_per_packet) / channels_per_frame).into()
     }
 
-    pub fn byte_count(&self) -> u64 {
+    /// Note that for some formats, this requires decoding the entire file.
+    pub fn byte_count(&mut self) -> u64 {
         match self.0 {
             AudioFileInner::Wave(ref wave_reader) => {
                 let sample_count = wave_reader.len(); // position-independent
@@ -210,17 +221,14 @@ impl AudioFile {
                 // variable size not implemented
                 u64::from(self.packet_size_fixed()) * self.packet_count()
             }
-            AudioFileInner::Symphonia(symphonia_formats::SymphoniaDecodedToPcm {
-                ref bytes,
-                ..
-            }) => bytes.len() as u64,
+            AudioFileInner::Symphonia(ref mut pcm) => pcm.decode_all().len() as u64,
         }
     }
 
-    pub fn packet_count(&self) -> u64 {
+    /// Note that for some formats, this requires decoding the entire file.
+    pub fn packet_count(&mut self) -> u64 {
         match self.0 {
-            AudioFileInner::Wave(_)
-            | AudioFileInner::Symphonia(symphonia_formats::SymphoniaDecodedToPcm { .. }) => {
+            AudioFileInner::Wave(_) | AudioFileInner::Symphonia(_) => {
                 // never variable-size
                 self.byte_count() / u64::from(self.packet_size_fixed())
             }
@@ -230,6 +238,18 @@ impl AudioFile {
         }
     }
 
+    /// How long the audio plays for. Note that for some formats, this requires
+    /// decoding the entire file.
+    pub fn duration(&mut self) -> Duration {
+        let AudioDescription {
+            sample_rate,
+            frames_per_packet,
+            ..
+        } = self.audio_description();
+        let frame_count = self.packet_count() * u64::from(frames_per_packet);
+        Duration::from_secs_f64(frame_count as f64 / sample_rate)
+    }
+
     /// Returns the packet size if this audio format has a constant packet size,
     /// panics if not.
     pub fn packet_size_fixed(&self) -> u32 {
@@ -313,11 +333,9 @@ impl AudioFile {
                 }
                 Ok(byte_offset)
             }
-            AudioFileInner::Symphonia(symphonia_formats::SymphoniaDecodedToPcm {
-                ref bytes,
-                ..
-            }) => {
-                let bytes = bytes.get(offset as usize..).ok_or(())?;
+            AudioFileInner::Symphonia(ref mut pcm) => {
+                let end = (offset as usize).saturating_add(buffer.len());
+                let bytes = pcm.decode_up_to(end).get(offset as usize..).ok_or(())?;
                 let bytes_to_read = buffer.len().min(bytes.len());
                 let bytes = &bytes[..bytes_to_read];
                 buffer[..bytes_to_read].copy_from_slice(bytes);
@@ -326,3 +344,64 @@ impl AudioFile {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wave_duration() {
+        let spec = hound::WavSpec {
+            channels: 2,
+            sample_rate: 8000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut bytes = Vec::new();
+        let mut writer = hound::WavWriter::new(Cursor::new(&mut bytes), spec).unwrap();
+        // 1.5 seconds of silence
+        for _ in 0..(8000 * 3 / 2 * 2) {
+            writer.write_sample(0i16).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        let mut file = AudioFile::read_from_vec(bytes).unwrap();
+        assert_eq!(file.duration(), Duration::from_millis(1500));
+    }
+
+    /// Build an ADTS AAC file consisting of silent AAC-LC frames (44100Hz,
+    /// mono). Each frame's raw data block is a single channel element with no
+    /// scale factor bands, which decodes to 1024 frames of silence.
+    fn silent_adts(frame_count: usize) -> Vec<u8> {
+        const HEADER: [u8; 7] = [
+            0xFF, 0xF1, // syncword, MPEG-4, no CRC
+            0x50, // AAC-LC, 44100Hz
+            0x40, 0x01, 0x7F, 0xFC, // mono, 11-byte frame, VBR
+        ];
+        // SCE with global_gain 100 and max_sfb 0, followed by END.
+        const RAW_DATA_BLOCK: [u8; 4] = [0x00, 0xC8, 0x00, 0x07];
+        let mut bytes = Vec::with_capacity((HEADER.len() + RAW_DATA_BLOCK.len()) * frame_count);
+        for _ in 0..frame_count {
+            bytes.extend_from_slice(&HEADER);
+            bytes.extend_from_slice(&RAW_DATA_BLOCK);
+        }
+        bytes
+    }
+
+    #[test]
+    fn adts_aac() {
+        let mut file = AudioFile::read_from_vec(silent_adts(10)).unwrap();
+        assert!(matches!(file.0, AudioFileInner::Symphonia(_)));
+
+        let description = file.audio_description();
+        assert_eq!(description.sample_rate, 44100.0);
+        assert_eq!(description.channels_per_frame, 1);
+        assert_eq!(description.bits_per_channel, 16);
+
+        // Every AAC frame decodes to 1024 PCM frames.
+        assert_eq!(file.packet_count(), 10 * 1024);
+        let mut pcm = vec![0xAA; 2 * 10 * 1024];
+        assert_eq!(file.read_bytes(0, &mut pcm), Ok(pcm.len()));
+        assert!(pcm.iter().all(|&byte| byte == 0));
+    }
+}
@@ -49,6 +49,7 @@ pub const ALC_FALSE: ALCboolean = 0;
 pub const ALC_TRUE: ALCboolean = 1;
 
 pub const ALC_DEVICE_SPECIFIER: ALCenum = 0x1005;
+pub const ALC_FREQUENCY: ALCenum = 0x1007;
 
 extern "C" {
     pub fn alcOpenDevice(devicename: *const ALCchar) -> *mut ALCdevice;
@@ -69,6 +70,19 @@ extern "C" {
     pub fn alcGetString(device: *mut ALCdevice, param: ALCenum) -> *const ALCchar;
 }
 
+// === alext.h ===
+
+// ALC_SOFT_loopback
+pub const ALC_FORMAT_CHANNELS_SOFT: ALCenum = 0x1990;
+pub const ALC_FORMAT_TYPE_SOFT: ALCenum = 0x1991;
+pub const ALC_SHORT_SOFT: ALCenum = 0x1402;
+pub const ALC_STEREO_SOFT: ALCenum = 0x1501;
+
+extern "C" {
+    pub fn alcLoopbackOpenDeviceSOFT(deviceName: *const ALCchar) -> *mut ALCdevice;
+    pub fn alcRenderSamplesSOFT(device: *mut ALCdevice, buffer: *mut ALCvoid, samples: ALCsizei);
+}
+
 // === al.h ===
 
 #[allow(dead_code)]
@@ -93,8 +107,22 @@ use al_types::*;
 
 pub const AL_NO_ERROR: ALenum = 0;
 
+pub const AL_FALSE: ALboolean = 0;
+pub const AL_TRUE: ALboolean = 1;
+
+pub const AL_SOURCE_RELATIVE: ALenum = 0x202;
+
+pub const AL_POSITION: ALenum = 0x1004;
+pub const AL_VELOCITY: ALenum = 0x1006;
+
+pub const AL_GAIN: ALenum = 0x100A;
+
 pub const AL_MAX_GAIN: ALenum = 0x100E;
 
+pub const AL_ORIENTATION: ALenum = 0x100F;
+
+pub const AL_LOOPING: ALenum = 0x1007;
+
 pub const AL_SOURCE_STATE: ALenum = 0x1010;
 
 pub const AL_INITIAL: ALenum = 0x1011;
@@ -0,0 +1,259 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! Opening of the host OpenAL output device, with fallbacks.
+//!
+//! The default device can fail to open, for example when another program has
+//! exclusive access to it. Rather than crashing, we try every other device
+//! OpenAL Soft can enumerate, and if none of them work, we use a loopback
+//! device whose output is discarded, so the app can run without audio.
+
+use super::openal as al;
+use super::openal::alc_types::{ALCcontext, ALCdevice, ALCint, ALCsizei};
+use std::ffi::{CStr, CString};
+use std::time::{Duration, Instant};
+
+/// Sample rate of the silent fallback device.
+const SILENT_FREQUENCY: u32 = 44100;
+/// Context attributes for the silent fallback device. Loopback devices
+/// require the output format to be specified, but since the output is
+/// discarded, the values don't matter much.
+const SILENT_CONTEXT_ATTRIBUTES: &[ALCint] = &[
+    al::ALC_FORMAT_CHANNELS_SOFT,
+    al::ALC_STEREO_SOFT,
+    al::ALC_FORMAT_TYPE_SOFT,
+    al::ALC_SHORT_SOFT,
+    al::ALC_FREQUENCY,
+    SILENT_FREQUENCY as ALCint,
+    0,
+];
+/// Number of frames rendered at once by [OutputDevice::render_if_silent].
+const SILENT_CHUNK_FRAMES: usize = 1024;
+
+/// A host OpenAL device opened by [open_output_device].
+#[derive(Copy, Clone, Debug)]
+pub struct OutputDevice {
+    pub raw: *mut ALCdevice,
+    /// [Some] if this is the silent fallback device.
+    silent: Option<SilentProgress>,
+}
+
+/// How far the silent fallback device has been rendered.
+#[derive(Copy, Clone, Debug)]
+struct SilentProgress {
+    opened_at: Instant,
+    frames_rendered: u64,
+}
+
+impl OutputDevice {
+    /// Wrapper for `alcCreateContext()` that provides the attributes needed for
+    /// the silent fallback device. May return null.
+    pub fn create_context(&self) -> *mut ALCcontext {
+        let attributes = if self.silent.is_some() {
+            SILENT_CONTEXT_ATTRIBUTES.as_ptr()
+        } else {
+            std::ptr::null()
+        };
+        unsafe { al::alcCreateContext(self.raw, attributes) }
+    }
+
+    /// If this is the silent fallback device, render and discard as much audio
+    /// as a real device would have played since it was opened. A loopback
+    /// device doesn't make progress on its own, so without this, sources would
+    /// never finish playing and queued buffers would never be processed. This
+    /// should be called regularly.
+    pub fn render_if_silent(&mut self) {
+        self.render_silent_until(Instant::now())
+    }
+
+    fn render_silent_until(&mut self, now: Instant) {
+        let Some(ref mut progress) = self.silent else {
+            return;
+        };
+        let due = frames_due(now.saturating_duration_since(progress.opened_at));
+        let mut scratch = [0i16; SILENT_CHUNK_FRAMES * 2]; // stereo
+        while progress.frames_rendered < due {
+            let frames = (due - progress.frames_rendered).min(SILENT_CHUNK_FRAMES as u64);
+            unsafe {
+                al::alcRenderSamplesSOFT(self.raw, scratch.as_mut_ptr().cast(), frames as ALCsizei)
+            };
+            progress.frames_rendered += frames;
+        }
+    }
+}
+
+/// Number of frames the silent fallback device should have rendered after
+/// `elapsed` has passed.
+fn frames_due(elapsed: Duration) -> u64 {
+    (elapsed.as_nanos() * u128::from(SILENT_FREQUENCY) / 1_000_000_000)
+        .try_into()
+        .unwrap()
+}
+
+/// Open an OpenAL device for audio output. This tries the default device, then
+/// the other enumerated devices, and finally falls back to a silent device.
+pub fn open_output_device() -> OutputDevice {
+    let device_names = unsafe { enumerate_device_names() };
+    let opened = select_device(&device_names, |name| {
+        let device = unsafe { al::alcOpenDevice(name.map_or(std::ptr::null(), CStr::as_ptr)) };
+        if device.is_null() {
+            log!("Couldn't open OpenAL device {:?}", name);
+            None
+        } else {
+            Some(device)
+        }
+    });
+    if let Some((name, raw)) = opened {
+        match name {
+            Some(name) => log!("Using OpenAL device {:?}", name),
+            None => log_dbg!("Using default OpenAL device"),
+        }
+        return OutputDevice { raw, silent: None };
+    }
+
+    log!("Warning: No OpenAL device could be opened, audio will be silent!");
+    open_silent_device()
+}
+
+fn open_silent_device() -> OutputDevice {
+    let raw = unsafe { al::alcLoopbackOpenDeviceSOFT(std::ptr::null()) };
+    assert!(!raw.is_null());
+    OutputDevice {
+        raw,
+        silent: Some(SilentProgress {
+            opened_at: Instant::now(),
+            frames_rendered: 0,
+        }),
+    }
+}
+
+/// Get the list of device names from `ALC_DEVICE_SPECIFIER`, which is a
+/// sequence of null-terminated strings ending with an empty string.
+unsafe fn enumerate_device_names() -> Vec<CString> {
+    let mut names = Vec::new();
+    let mut ptr = al::alcGetString(std::ptr::null_mut(), al::ALC_DEVICE_SPECIFIER);
+    if ptr.is_null() {
+        return names;
+    }
+    loop {
+        let name = CStr::from_ptr(ptr);
+        if name.to_bytes().is_empty() {
+            break;
+        }
+        ptr = ptr.add(name.to_bytes_with_nul().len());
+        names.push(name.to_owned());
+    }
+    names
+}
+
+/// Find the first device that `try_open` succeeds for, trying the default
+/// device (represented by [None]) before each of the named devices in order.
+fn select_device<'a, D>(
+    device_names: &'a [CString],
+    mut try_open: impl FnMut(Option<&'a CStr>) -> Option<D>,
+) -> Option<(Option<&'a CStr>, D)> {
+    std::iter::once(None)
+        .chain(device_names.iter().map(|name| Some(name.as_c_str())))
+        .find_map(|name| try_open(name).map(|device| (name, device)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(names: &[&str]) -> Vec<CString> {
+        names
+            .iter()
+            .map(|&name| CString::new(name).unwrap())
+            .collect()
+    }
+
+    fn as_str(name: Option<&CStr>) -> Option<&str> {
+        name.map(|name| name.to_str().unwrap())
+    }
+
+    #[test]
+    fn default_device_preferred() {
+        let names = names(&["Speakers", "Headphones"]);
+        let mut tried = Vec::new();
+        let res = select_device(&names, |name| {
+            tried.push(as_str(name));
+            Some(())
+        });
+        assert_eq!(res, Some((None, ())));
+        assert_eq!(tried, [None]);
+    }
+
+    #[test]
+    fn busy_devices_skipped() {
+        let names = names(&["Busy", "Also busy", "Free", "Unused"]);
+        let mut tried = Vec::new();
+        let res = select_device(&names, |name| {
+            tried.push(as_str(name));
+            (as_str(name) == Some("Free")).then_some(42)
+        });
+        let (name, device) = res.unwrap();
+        assert_eq!(as_str(name), Some("Free"));
+        assert_eq!(device, 42);
+        assert_eq!(tried, [None, Some("Busy"), Some("Also busy"), Some("Free")]);
+    }
+
+    #[test]
+    fn all_devices_busy() {
+        let names = names(&["Busy"]);
+        let res = select_device(&names, |_| None::<()>);
+        assert!(res.is_none());
+    }
+
+    #[test]
+    fn silent_frames_due() {
+        assert_eq!(frames_due(Duration::ZERO), 0);
+        assert_eq!(frames_due(Duration::from_millis(10)), 441);
+        assert_eq!(frames_due(Duration::from_secs(2)), 88200);
+    }
+
+    #[test]
+    fn silent_device_plays_sources() {
+        let mut device = open_silent_device();
+        let context = device.create_context();
+        assert!(!context.is_null());
+        unsafe {
+            assert_eq!(al::alcMakeContextCurrent(context), al::ALC_TRUE);
+
+            // 0.1 seconds of mono audio
+            let samples = [0i16; SILENT_FREQUENCY as usize / 10];
+            let mut buffer = 0;
+            al::alGenBuffers(1, &mut buffer);
+            al::alBufferData(
+                buffer,
+                al::AL_FORMAT_MONO16,
+                samples.as_ptr().cast(),
+                std::mem::size_of_val(&samples) as _,
+                SILENT_FREQUENCY as _,
+            );
+            let mut source = 0;
+            al::alGenSources(1, &mut source);
+            al::alSourceQueueBuffers(source, 1, &buffer);
+            al::alSourcePlay(source);
+
+            let get_state = || {
+                let mut state = 0;
+                al::alGetSourcei(source, al::AL_SOURCE_STATE, &mut state);
+                state
+            };
+            let opened_at = device.silent.unwrap().opened_at;
+            device.render_silent_until(opened_at + Duration::from_millis(50));
+            assert_eq!(get_state(), al::AL_PLAYING);
+            device.render_silent_until(opened_at + Duration::from_millis(200));
+            assert_eq!(get_state(), al::AL_STOPPED);
+
+            al::alDeleteSources(1, &source);
+            al::alDeleteBuffers(1, &buffer);
+            al::alcMakeContextCurrent(std::ptr::null_mut());
+            al::alcDestroyContext(context);
+            al::alcCloseDevice(device.raw);
+        }
+    }
+}
@@ -0,0 +1,51 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! Stereo panning on top of OpenAL's 3D positioning.
+//!
+//! OpenAL has no concept of a pan value, so it is simulated by placing the
+//! source on a unit circle around the listener. The source is made relative
+//! to the listener, which is at the origin facing down the -Z axis (OpenAL's
+//! default orientation), so +X is to the right. A pan of -1 is fully left, 0
+//! is straight ahead and +1 is fully right. Keeping the distance constant
+//! means that panning doesn't affect the volume.
+//!
+//! Note that OpenAL only spatializes mono sources, so this has no effect on
+//! stereo audio.
+
+use super::openal as al;
+use super::openal::al_types::ALuint;
+
+/// Convert a pan value in the range [-1, 1] to a source position.
+pub fn pan_to_position(pan: f32) -> [f32; 3] {
+    let x = pan.clamp(-1.0, 1.0);
+    [x, 0.0, -(1.0 - x * x).sqrt()]
+}
+
+/// Set the pan of an OpenAL source. There must be a current context.
+pub fn set_source_pan(source: ALuint, pan: f32) {
+    let [x, y, z] = pan_to_position(pan);
+    unsafe {
+        al::alSourcei(source, al::AL_SOURCE_RELATIVE, al::AL_TRUE.into());
+        al::alSource3f(source, al::AL_POSITION, x, y, z);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pan_mapping() {
+        assert_eq!(pan_to_position(-1.0), [-1.0, 0.0, -0.0]);
+        assert_eq!(pan_to_position(0.0), [0.0, 0.0, -1.0]);
+        assert_eq!(pan_to_position(1.0), [1.0, 0.0, -0.0]);
+        // Out-of-range values are clamped
+        assert_eq!(pan_to_position(2.0), pan_to_position(1.0));
+        // The distance from the listener is always the same
+        let [x, y, z] = pan_to_position(0.5);
+        assert!(((x * x + y * y + z * z) - 1.0).abs() < 1e-6);
+    }
+}
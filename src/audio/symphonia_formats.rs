@@ -6,12 +6,13 @@
 //! Quick-and-dirty decoding of miscellaneous formats (MP3, AAC) to linear PCM.
 //!
 //! This should be the only module in touchHLE that makes use of [symphonia].
-//! For AAC, Only the LC profile and MPEG-4 container format are supported (see
-//! feature list in Cargo.toml).
+//! For AAC, Only the LC profile and the MPEG-4 and ADTS container formats are
+//! supported (see feature list in Cargo.toml).
 
 use std::io::Cursor;
 use symphonia::core::audio::{RawSampleBuffer, SignalSpec};
-use symphonia::core::codecs::{CODEC_TYPE_AAC, CODEC_TYPE_MP3};
+use symphonia::core::codecs::{Decoder, CODEC_TYPE_AAC, CODEC_TYPE_MP3};
+use symphonia::core::formats::FormatReader;
 use symphonia::core::io::MediaSourceStream;
 
 /// PCM data decoded from an miscellaneous format file.
@@ -25,75 +26,256 @@ pub struct SymphoniaDecodedToPcm {
     pub channels: u32,
 }
 
+/// Decode an entire miscellaneous format file to PCM at once.
+///
+/// For long files, it is better to use [SymphoniaDecodedOnDemand] or
+/// [SymphoniaDecoder] to avoid a stall.
+#[allow(dead_code)] // kept for callers that need the whole file at once
 pub fn decode_symphonia_to_pcm(file: Cursor<Vec<u8>>) -> Result<SymphoniaDecodedToPcm, ()> {
-    let mss = MediaSourceStream::new(Box::new(file), Default::default());
-
-    // If this failed, the container format is not supported.
-    let probed = symphonia::default::get_probe()
-        .format(
-            &Default::default(),
-            mss,
-            &Default::default(),
-            &Default::default(),
-        )
-        .map_err(|_| ())?;
-
-    // If this failed, no audio track with a relevant format was found.
-    let mut format = probed.format;
-    let track = format
-        .tracks()
-        .iter()
-        .find(|t| t.codec_params.codec == CODEC_TYPE_AAC || t.codec_params.codec == CODEC_TYPE_MP3)
-        .ok_or(())?;
-    let track_id = track.id;
-
-    // Not sure why this would fail, maybe an unusual AAC track.
-    let mut decoder = symphonia::default::get_codecs()
-        .make(&track.codec_params, &Default::default())
-        .map_err(|_| ())?;
-
-    let mut out_pcm = Vec::<u8>::new();
-    let mut signal_spec: Option<SignalSpec> = None;
-    {
-        let mut tmp_raw_s16_buf: Option<RawSampleBuffer<i16>> = None;
+    let mut decoder = SymphoniaDecoder::new(file)?;
+    let bytes = decoder.decode_next_frames(usize::MAX)?;
+    Ok(SymphoniaDecodedToPcm {
+        bytes,
+        sample_rate: decoder.sample_rate(),
+        channels: decoder.channels(),
+    })
+}
+
+/// Incremental decoder for a miscellaneous format file. The PCM output has the
+/// same format as [SymphoniaDecodedToPcm::bytes].
+pub struct SymphoniaDecoder {
+    format: Box<dyn FormatReader>,
+    decoder: Box<dyn Decoder>,
+    track_id: u32,
+    /// For some reason, the "signal spec" (number of channels etc) is reported
+    /// per-packet, so this is only known once the first packet is decoded.
+    signal_spec: Option<SignalSpec>,
+    tmp_raw_s16_buf: Option<RawSampleBuffer<i16>>,
+    /// PCM from decoded packets that hasn't been returned yet.
+    pending: Vec<u8>,
+    finished: bool,
+}
+
+impl SymphoniaDecoder {
+    pub fn new(file: Cursor<Vec<u8>>) -> Result<Self, ()> {
+        let mss = MediaSourceStream::new(Box::new(file), Default::default());
+
+        // If this failed, the container format is not supported.
+        let probed = symphonia::default::get_probe()
+            .format(
+                &Default::default(),
+                mss,
+                &Default::default(),
+                &Default::default(),
+            )
+            .map_err(|_| ())?;
+
+        // If this failed, no audio track with a relevant format was found.
+        let format = probed.format;
+        let track = format
+            .tracks()
+            .iter()
+            .find(|t| {
+                t.codec_params.codec == CODEC_TYPE_AAC || t.codec_params.codec == CODEC_TYPE_MP3
+            })
+            .ok_or(())?;
+        let track_id = track.id;
+
+        // Not sure why this would fail, maybe an unusual AAC track.
+        let decoder = symphonia::default::get_codecs()
+            .make(&track.codec_params, &Default::default())
+            .map_err(|_| ())?;
+
+        let mut decoder = SymphoniaDecoder {
+            format,
+            decoder,
+            track_id,
+            signal_spec: None,
+            tmp_raw_s16_buf: None,
+            pending: Vec::new(),
+            finished: false,
+        };
+        // Decode the first packet so the signal spec is known.
+        while decoder.signal_spec.is_none() {
+            if !decoder.decode_packet()? {
+                return Err(());
+            }
+        }
+        Ok(decoder)
+    }
+
+    /// Sample rate in Hz.
+    pub fn sample_rate(&self) -> u32 {
+        self.signal_spec.unwrap().rate
+    }
+
+    /// Channel count.
+    pub fn channels(&self) -> u32 {
+        self.signal_spec
+            .unwrap()
+            .channels
+            .count()
+            .try_into()
+            .unwrap()
+    }
+
+    fn bytes_per_frame(&self) -> usize {
+        self.channels() as usize * std::mem::size_of::<i16>()
+    }
+
+    /// Decode the next packet of the track and append the PCM to `pending`.
+    /// Returns [false] if the end of the file has been reached.
+    fn decode_packet(&mut self) -> Result<bool, ()> {
+        if self.finished {
+            return Ok(false);
+        }
         loop {
-            let packet = match format.next_packet() {
+            let packet = match self.format.next_packet() {
                 Ok(packet) => packet,
                 // Assume I/O errors can only mean end-of-file, because the
                 // entire file is in-memory.
-                Err(symphonia::core::errors::Error::IoError(_)) => break,
+                Err(symphonia::core::errors::Error::IoError(_)) => {
+                    self.finished = true;
+                    return Ok(false);
+                }
                 Err(_) => return Err(()),
             };
 
-            if packet.track_id() != track_id {
+            if packet.track_id() != self.track_id {
                 continue;
             }
-            let Ok(decoded_packet) = decoder.decode(&packet) else {
-                break;
+            let Ok(decoded_packet) = self.decoder.decode(&packet) else {
+                self.finished = true;
+                return Ok(false);
             };
 
-            // For some reason, the "signal spec" (number of channels etc)
-            // is reported per-packet? This is weird because it must be the same
-            // for all of them.
-            let signal_spec = *signal_spec.get_or_insert_with(|| *decoded_packet.spec());
+            let signal_spec = *self
+                .signal_spec
+                .get_or_insert_with(|| *decoded_packet.spec());
             assert_eq!(signal_spec, *decoded_packet.spec());
 
             // Note that this assumes every packet's buffer's capacity is the
             // same, which is a dubious assumption, but Symphonia's own example
             // code does it, so maybe it's fine?
-            let tmp_raw_s16_buf = tmp_raw_s16_buf.get_or_insert_with(|| {
+            let tmp_raw_s16_buf = self.tmp_raw_s16_buf.get_or_insert_with(|| {
                 RawSampleBuffer::new(decoded_packet.capacity() as _, signal_spec)
             });
             tmp_raw_s16_buf.clear();
             tmp_raw_s16_buf.copy_interleaved_ref(decoded_packet);
 
-            out_pcm.extend_from_slice(tmp_raw_s16_buf.as_bytes());
+            self.pending.extend_from_slice(tmp_raw_s16_buf.as_bytes());
+            return Ok(true);
         }
     }
-    let signal_spec = signal_spec.ok_or(())?;
-    Ok(SymphoniaDecodedToPcm {
-        bytes: out_pcm,
-        sample_rate: signal_spec.rate,
-        channels: signal_spec.channels.count().try_into().unwrap(),
-    })
+
+    /// Decode up to `max_frames` more frames of PCM. The result is only shorter
+    /// than requested if the end of the file has been reached.
+    pub fn decode_next_frames(&mut self, max_frames: usize) -> Result<Vec<u8>, ()> {
+        let max_bytes = max_frames.saturating_mul(self.bytes_per_frame());
+        while self.pending.len() < max_bytes && self.decode_packet()? {}
+        let len = max_bytes.min(self.pending.len());
+        if len == self.pending.len() {
+            Ok(std::mem::take(&mut self.pending))
+        } else {
+            Ok(self.pending.drain(..len).collect())
+        }
+    }
+}
+
+/// A miscellaneous format file that is decoded to PCM as it is read. The PCM
+/// decoded so far is kept so that it can be read from any offset.
+pub struct SymphoniaDecodedOnDemand {
+    /// [None] once the whole file has been decoded.
+    decoder: Option<SymphoniaDecoder>,
+    /// Same format as [SymphoniaDecodedToPcm::bytes].
+    bytes: Vec<u8>,
+    /// Sample rate in Hz.
+    pub sample_rate: u32,
+    /// Channel count.
+    pub channels: u32,
+}
+
+impl SymphoniaDecodedOnDemand {
+    /// How many frames to decode at a time. This is a bit more than a second
+    /// at typical sample rates, which is more than an audio queue buffer is
+    /// likely to need.
+    const CHUNK_FRAMES: usize = 64 * 1024;
+
+    pub fn new(file: Cursor<Vec<u8>>) -> Result<Self, ()> {
+        let decoder = SymphoniaDecoder::new(file)?;
+        Ok(SymphoniaDecodedOnDemand {
+            sample_rate: decoder.sample_rate(),
+            channels: decoder.channels(),
+            decoder: Some(decoder),
+            bytes: Vec::new(),
+        })
+    }
+
+    /// Decode until at least `len` bytes of PCM are available, or the end of
+    /// the file is reached. Returns all the PCM decoded so far.
+    pub fn decode_up_to(&mut self, len: usize) -> &[u8] {
+        while self.bytes.len() < len {
+            let Some(ref mut decoder) = self.decoder else {
+                break;
+            };
+            match decoder.decode_next_frames(Self::CHUNK_FRAMES) {
+                Ok(chunk) if !chunk.is_empty() => self.bytes.extend_from_slice(&chunk),
+                // Errors partway through are treated like the end of the file,
+                // as SymphoniaDecoder does for undecodable packets.
+                _ => self.decoder = None,
+            }
+        }
+        &self.bytes
+    }
+
+    /// Decode the rest of the file and return all of its PCM.
+    pub fn decode_all(&mut self) -> &[u8] {
+        self.decode_up_to(usize::MAX)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Build an MP3 file consisting of silent MPEG-1 Layer III frames
+    /// (128kbps, 44100Hz, stereo, no CRC). Frames where all of the side
+    /// information and main data are zero are valid and decode to silence.
+    fn silent_mp3(frame_count: usize) -> Vec<u8> {
+        const FRAME_SIZE: usize = 144 * 128000 / 44100;
+        let mut bytes = Vec::with_capacity(FRAME_SIZE * frame_count);
+        for _ in 0..frame_count {
+            let start = bytes.len();
+            bytes.extend_from_slice(&[0xFF, 0xFB, 0x90, 0x00]);
+            bytes.resize(start + FRAME_SIZE, 0);
+        }
+        bytes
+    }
+
+    #[test]
+    fn chunked_decode_matches_one_shot() {
+        let file = silent_mp3(20);
+
+        let one_shot = decode_symphonia_to_pcm(Cursor::new(file.clone())).unwrap();
+        assert_eq!(one_shot.sample_rate, 44100);
+        assert_eq!(one_shot.channels, 2);
+        assert!(!one_shot.bytes.is_empty());
+
+        let mut decoder = SymphoniaDecoder::new(Cursor::new(file.clone())).unwrap();
+        let mut chunked = Vec::new();
+        loop {
+            // Deliberately not a multiple of the MP3 frame size (1152)
+            let chunk = decoder.decode_next_frames(1000).unwrap();
+            assert!(chunk.len() <= 1000 * 4);
+            if chunk.is_empty() {
+                break;
+            }
+            chunked.extend_from_slice(&chunk);
+        }
+        assert_eq!(chunked, one_shot.bytes);
+
+        let mut on_demand = SymphoniaDecodedOnDemand::new(Cursor::new(file)).unwrap();
+        assert!(on_demand.decode_up_to(16).len() >= 16);
+        assert_eq!(on_demand.decode_all(), one_shot.bytes);
+    }
 }
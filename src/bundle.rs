@@ -15,8 +15,78 @@ use crate::fs::{BundleData, Fs, GuestPath, GuestPathBuf};
 use crate::image::Image;
 use plist::dictionary::Dictionary;
 use plist::Value;
+use std::fmt;
 use std::io::Cursor;
 
+/// An iPhone OS version number, e.g. from an app's `MinimumOSVersion`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct OsVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl OsVersion {
+    /// Parse a version number like `3`, `3.1` or `3.1.3`.
+    pub fn parse(version: &str) -> Option<OsVersion> {
+        let mut parts = version.trim().split('.');
+        let mut next_part = |required| match parts.next() {
+            Some(part) => part.parse().ok(),
+            None if required => None,
+            None => Some(0),
+        };
+        let parsed = OsVersion {
+            major: next_part(true)?,
+            minor: next_part(false)?,
+            patch: next_part(false)?,
+        };
+        parts.next().is_none().then_some(parsed)
+    }
+
+    /// Whether touchHLE supports apps requiring this OS version. So far, only
+    /// iPhone OS 2.x and 3.0 are supported.
+    pub fn is_supported(self) -> bool {
+        (self.major, self.minor) <= (3, 0)
+    }
+}
+
+impl fmt::Display for OsVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)?;
+        if self.patch != 0 {
+            write!(f, ".{}", self.patch)?;
+        }
+        Ok(())
+    }
+}
+
+/// Decide whether to launch an app, given its `MinimumOSVersion` (if any) and
+/// whether the `--force-unsupported-os` option was used.
+///
+/// If the app should be launched, the result may contain a warning to show
+/// the user. Otherwise, the error explains why it won't be launched.
+pub fn check_minimum_os_version(
+    minimum_os_version: Option<&str>,
+    force_unsupported_os: bool,
+) -> Result<Option<String>, String> {
+    let Some(version_str) = minimum_os_version else {
+        return Ok(None);
+    };
+    let Some(version) = OsVersion::parse(version_str) else {
+        return Ok(Some(format!(
+            "Couldn't parse the app's minimum OS version {:?}, trying to run it anyway.",
+            version_str
+        )));
+    };
+    if version.is_supported() {
+        Ok(None)
+    } else if force_unsupported_os {
+        Ok(Some(format!("App requires iPhone OS {}, which isn't supported. Running it anyway because --force-unsupported-os was used.", version)))
+    } else {
+        Err(format!("This app requires iPhone OS {}. Only apps for iPhone OS 2.x and iPhone OS 3.0 are currently supported. Use the --force-unsupported-os option to try running it anyway.", version))
+    }
+}
+
 #[derive(Debug)]
 pub struct Bundle {
     path: GuestPathBuf,
@@ -109,6 +179,25 @@ impl Bundle {
             .map(|v| v.as_string().unwrap())
     }
 
+    /// Names of the `UIInterfaceOrientation`s listed in the
+    /// `UISupportedInterfaceOrientations` key, or the `UIInterfaceOrientation`
+    /// key if that's missing. Empty if neither is present.
+    pub fn supported_interface_orientations(&self) -> Vec<&str> {
+        if let Some(orientations) = self
+            .plist
+            .get("UISupportedInterfaceOrientations")
+            .and_then(|v| v.as_array())
+        {
+            orientations.iter().filter_map(|v| v.as_string()).collect()
+        } else {
+            self.plist
+                .get("UIInterfaceOrientation")
+                .and_then(|v| v.as_string())
+                .into_iter()
+                .collect()
+        }
+    }
+
     pub fn executable_path(&self) -> GuestPathBuf {
         // FIXME: Is this key optional? All iPhone apps seem to have it.
         self.path
@@ -167,3 +256,51 @@ impl Bundle {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn os_version_parsing() {
+        let v = |major, minor, patch| {
+            Some(OsVersion {
+                major,
+                minor,
+                patch,
+            })
+        };
+        assert_eq!(OsVersion::parse("2"), v(2, 0, 0));
+        assert_eq!(OsVersion::parse("2.2.1"), v(2, 2, 1));
+        assert_eq!(OsVersion::parse("3.0"), v(3, 0, 0));
+        assert_eq!(OsVersion::parse(" 3.1.3\n"), v(3, 1, 3));
+        assert_eq!(OsVersion::parse(""), None);
+        assert_eq!(OsVersion::parse("3."), None);
+        assert_eq!(OsVersion::parse("3.x"), None);
+        assert_eq!(OsVersion::parse("3.1.2.1"), None);
+        assert!(OsVersion::parse("3.1").unwrap() > OsVersion::parse("3.0.9").unwrap());
+        assert!(OsVersion::parse("10.0").unwrap() > OsVersion::parse("9.3").unwrap());
+        assert_eq!(OsVersion::parse("3.1.0").unwrap().to_string(), "3.1");
+        assert_eq!(OsVersion::parse("3.1.3").unwrap().to_string(), "3.1.3");
+    }
+
+    #[test]
+    fn minimum_os_version_decision() {
+        for version in [None, Some("2.0"), Some("2.2.1"), Some("3.0"), Some("3.0.1")] {
+            assert_eq!(check_minimum_os_version(version, false), Ok(None));
+            assert_eq!(check_minimum_os_version(version, true), Ok(None));
+        }
+        for version in ["3.1", "3.1.3", "4.0", "10.0"] {
+            assert!(check_minimum_os_version(Some(version), false).is_err());
+            assert!(matches!(
+                check_minimum_os_version(Some(version), true),
+                Ok(Some(_))
+            ));
+        }
+        // Unparseable versions only produce a warning.
+        assert!(matches!(
+            check_minimum_os_version(Some("three"), false),
+            Ok(Some(_))
+        ));
+    }
+}
@@ -11,7 +11,9 @@
 //! For the moment, only ARMv6 has been tested.
 
 use crate::abi::GuestFunction;
-use crate::mem::{ConstPtr, GuestUSize, Mem, MutPtr, Ptr, SafeRead, SafeWrite};
+use crate::mem::{
+    guest_size_of, ConstPtr, GuestUSize, Mem, MutPtr, Ptr, SafeRead, SafeWrite, WatchpointKind,
+};
 
 // Import functions from C++
 use touchHLE_dynarmic_wrapper::*;
@@ -39,12 +41,18 @@ fn touchHLE_cpu_read_impl<T: SafeRead + Default>(
     let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
         let mem = unsafe { &mut *mem.cast::<Mem>() };
         let ptr: ConstPtr<T> = Ptr::from_bits(addr);
-        mem.read(ptr)
+        let value = mem.read(ptr);
+        // A watchpoint hit is reported to the C++ code in the same way as an
+        // error, but the real value is still returned. See also
+        // Cpu::run_or_step.
+        let hit = mem.check_watchpoints(addr, guest_size_of::<T>(), false);
+        (value, hit)
     }));
+    let (value, halt) = res.unwrap_or((T::default(), true));
     unsafe {
-        error.write(res.is_err());
+        error.write(halt);
     }
-    res.unwrap_or_default()
+    value
 }
 
 fn touchHLE_cpu_write_impl<T: SafeWrite>(mem: *mut touchHLE_Mem, addr: VAddr, value: T) -> bool {
@@ -52,9 +60,10 @@ fn touchHLE_cpu_write_impl<T: SafeWrite>(mem: *mut touchHLE_Mem, addr: VAddr, va
     let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
         let mem = unsafe { &mut *mem.cast::<Mem>() };
         let ptr: MutPtr<T> = Ptr::from_bits(addr);
-        mem.write(ptr, value)
+        mem.write(ptr, value);
+        mem.check_watchpoints(addr, guest_size_of::<T>(), true)
     }));
-    res.is_err()
+    res.unwrap_or(true)
 }
 
 // Export functions for use by C++
@@ -142,6 +151,9 @@ pub enum CpuError {
     UndefinedInstruction,
     /// Breakpoint (`bkpt` instruction).
     Breakpoint,
+    /// The CPU accessed memory watched by the debugger, see
+    /// [Mem::add_watchpoint]. The address is that of the watched data.
+    Watchpoint(WatchpointKind, VAddr),
 }
 
 impl Cpu {
@@ -228,6 +240,28 @@ impl Cpu {
         unsafe { touchHLE_DynarmicWrapper_set_cpsr(self.dynarmic_wrapper, cpsr) }
     }
 
+    /// Get the VFP extension registers D0-D31, as pairs of 32-bit words. The
+    /// first 32 words are also S0-S31.
+    pub fn ext_regs(&self) -> &[u32; 64] {
+        unsafe {
+            let ptr = touchHLE_DynarmicWrapper_ext_regs_const(self.dynarmic_wrapper);
+            &*(ptr as *const [u32; 64])
+        }
+    }
+    pub fn ext_regs_mut(&mut self) -> &mut [u32; 64] {
+        unsafe {
+            let ptr = touchHLE_DynarmicWrapper_ext_regs_mut(self.dynarmic_wrapper);
+            &mut *(ptr as *mut [u32; 64])
+        }
+    }
+
+    pub fn fpscr(&self) -> u32 {
+        unsafe { touchHLE_DynarmicWrapper_fpscr(self.dynarmic_wrapper) }
+    }
+    pub fn set_fpscr(&mut self, fpscr: u32) {
+        unsafe { touchHLE_DynarmicWrapper_set_fpscr(self.dynarmic_wrapper, fpscr) }
+    }
+
     /// Swap the current state of the CPU (registers etc) with the state stored
     /// in the context object.
     pub fn swap_context(&mut self, context: &mut CpuContext) {
@@ -272,6 +306,21 @@ impl Cpu {
         }
     }
 
+    /// Enable or disable direct memory access for the page containing `addr`.
+    /// When disabled, the CPU's accesses to that page go through [Mem], which
+    /// is slower, but lets watchpoints be checked (see
+    /// [Mem::add_watchpoint]). This does nothing if direct memory access isn't
+    /// in use, and direct access can't be enabled for the null segment.
+    pub fn set_page_direct_access(&mut self, addr: VAddr, enabled: bool) {
+        unsafe {
+            touchHLE_DynarmicWrapper_set_page_direct_access(
+                self.dynarmic_wrapper,
+                addr / 0x1000,
+                enabled,
+            )
+        }
+    }
+
     /// Start CPU execution.
     ///
     /// If `ticks` is [Some], it is used as an abstract time limit. The value
@@ -298,7 +347,12 @@ impl Cpu {
         };
         match res {
             -1 => CpuState::Normal,
-            -2 => CpuState::Error(CpuError::MemoryError),
+            // Watchpoint hits are signalled like memory errors, since both
+            // are detected in the memory access callbacks.
+            -2 => match mem.take_watchpoint_hit() {
+                Some((kind, addr)) => CpuState::Error(CpuError::Watchpoint(kind, addr)),
+                None => CpuState::Error(CpuError::MemoryError),
+            },
             -3 => CpuState::Error(CpuError::UndefinedInstruction),
             -4 => CpuState::Error(CpuError::Breakpoint),
             _ if res < -4 => panic!("Unexpected CPU execution result"),
@@ -306,3 +360,60 @@ impl Cpu {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_watchpoint() {
+        let mut mem = Mem::new();
+        mem.set_null_segment_size(0x1000);
+
+        let code: MutPtr<u32> = mem.alloc(16).cast();
+        let program = [
+            0xE3A0002A, // mov r0, #42
+            0xE5910000, // ldr r0, [r1]
+            0xE5820000, // str r0, [r2]
+            0xE5810000, // str r0, [r1]
+        ];
+        for (i, &instruction) in program.iter().enumerate() {
+            mem.write(code + i as u32, instruction);
+        }
+        let unwatched: MutPtr<u32> = mem.alloc(4).cast();
+        mem.write(unwatched, 7);
+        let watched: MutPtr<u32> = mem.alloc(0x2000).cast();
+        let watched = watched + 0x1000 / 4;
+
+        let mut cpu = Cpu::new(Some(&mut mem));
+        cpu.set_cpsr(Cpu::CPSR_USER_MODE);
+        cpu.branch(GuestFunction::from_addr_with_thumb_bit(code.to_bits()));
+        cpu.regs_mut()[1] = unwatched.to_bits();
+        cpu.regs_mut()[2] = watched.to_bits();
+
+        mem.add_watchpoint(WatchpointKind::Write, watched.to_bits() + 2, 1);
+        assert!(mem.page_has_watchpoint(watched.to_bits()));
+        assert!(!mem.page_has_watchpoint(unwatched.to_bits()));
+        cpu.set_page_direct_access(watched.to_bits(), false);
+
+        let mut ticks = 100;
+        let CpuState::Error(CpuError::Watchpoint(kind, addr)) =
+            cpu.run_or_step(&mut mem, Some(&mut ticks))
+        else {
+            panic!();
+        };
+        assert_eq!(kind, WatchpointKind::Write);
+        assert_eq!(addr, watched.to_bits() + 2);
+        assert_eq!(mem.read(watched), 7);
+        // Reads and writes of unwatched memory don't stop execution.
+        assert_eq!(mem.read(unwatched), 7);
+        assert_eq!(cpu.regs()[Cpu::PC], (code + 2).to_bits());
+
+        assert!(mem.remove_watchpoint(WatchpointKind::Write, watched.to_bits() + 2, 1));
+        assert!(!mem.page_has_watchpoint(watched.to_bits()));
+        cpu.set_page_direct_access(watched.to_bits(), true);
+        assert!(matches!(cpu.run_or_step(&mut mem, None), CpuState::Normal));
+        assert!(matches!(cpu.run_or_step(&mut mem, None), CpuState::Normal));
+        assert_eq!(cpu.regs()[Cpu::PC], (code + 4).to_bits());
+    }
+}
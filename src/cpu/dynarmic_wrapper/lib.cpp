@@ -139,9 +139,13 @@ class DynarmicWrapper {
   std::unique_ptr<Dynarmic::A32::Jit> cpu;
   std::array<std::uint8_t *, Dynarmic::A32::UserConfig::NUM_PAGE_TABLE_ENTRIES>
       page_table;
+  std::uint8_t *direct_memory_access_ptr;
+  size_t null_page_count;
 
 public:
-  DynarmicWrapper(void *direct_memory_access_ptr, size_t null_page_count) {
+  DynarmicWrapper(void *direct_memory_access_ptr, size_t null_page_count)
+      : direct_memory_access_ptr((std::uint8_t *)direct_memory_access_ptr),
+        null_page_count(null_page_count) {
     Dynarmic::A32::UserConfig user_config;
     user_config.callbacks = &env;
     // TODO: only do this in debug builds? it's probably expensive
@@ -174,13 +178,28 @@ public:
   const std::uint32_t *regs() const { return &cpu->Regs().front(); }
   std::uint32_t *regs() { return &cpu->Regs().front(); }
 
+  const std::uint32_t *ext_regs() const { return &cpu->ExtRegs().front(); }
+  std::uint32_t *ext_regs() { return &cpu->ExtRegs().front(); }
+
   std::uint32_t cpsr() const { return cpu->Cpsr(); }
   void set_cpsr(std::uint32_t cpsr) { cpu->SetCpsr(cpsr); }
 
+  std::uint32_t fpscr() const { return cpu->Fpscr(); }
+  void set_fpscr(std::uint32_t fpscr) { cpu->SetFpscr(fpscr); }
+
   void invalidate_cache_range(VAddr start, std::uint32_t size) {
     cpu->InvalidateCacheRange(start, size);
   }
 
+  void set_page_direct_access(std::uint32_t page, bool enabled) {
+    if (!direct_memory_access_ptr || page < null_page_count) {
+      return;
+    }
+    // The page table is consulted at runtime by the JIT-compiled code, so
+    // there's no need to invalidate the cache.
+    page_table[page] = enabled ? direct_memory_access_ptr : nullptr;
+  }
+
   void swap_context(void *context) {
     Dynarmic::A32::Context tmp = cpu->SaveContext();
     cpu->LoadContext(*(Dynarmic::A32::Context *)context);
@@ -235,6 +254,14 @@ std::uint32_t *touchHLE_DynarmicWrapper_regs_mut(DynarmicWrapper *cpu) {
   return cpu->regs();
 }
 
+const std::uint32_t *
+touchHLE_DynarmicWrapper_ext_regs_const(const DynarmicWrapper *cpu) {
+  return cpu->ext_regs();
+}
+std::uint32_t *touchHLE_DynarmicWrapper_ext_regs_mut(DynarmicWrapper *cpu) {
+  return cpu->ext_regs();
+}
+
 std::uint32_t touchHLE_DynarmicWrapper_cpsr(const DynarmicWrapper *cpu) {
   return cpu->cpsr();
 }
@@ -243,6 +270,20 @@ void touchHLE_DynarmicWrapper_set_cpsr(DynarmicWrapper *cpu,
   cpu->set_cpsr(cpsr);
 }
 
+std::uint32_t touchHLE_DynarmicWrapper_fpscr(const DynarmicWrapper *cpu) {
+  return cpu->fpscr();
+}
+void touchHLE_DynarmicWrapper_set_fpscr(DynarmicWrapper *cpu,
+                                        std::uint32_t fpscr) {
+  cpu->set_fpscr(fpscr);
+}
+
+void touchHLE_DynarmicWrapper_set_page_direct_access(DynarmicWrapper *cpu,
+                                                     std::uint32_t page,
+                                                     bool enabled) {
+  cpu->set_page_direct_access(page, enabled);
+}
+
 void touchHLE_DynarmicWrapper_swap_context(DynarmicWrapper *cpu,
                                            void *context) {
   cpu->swap_context(context);
@@ -27,8 +27,9 @@ use crate::abi::{CallFromGuest, GuestFunction};
 use crate::cpu::Cpu;
 use crate::frameworks::foundation::ns_string;
 use crate::mach_o::{MachO, SectionType};
-use crate::mem::{ConstVoidPtr, GuestUSize, Mem, MutPtr, Ptr};
+use crate::mem::{ConstVoidPtr, GuestUSize, Mem, MemSnapshot, MutPtr, Ptr};
 use crate::objc::{nil, ObjC};
+use crate::unimplemented::{describe_function, UnimplementedLog};
 use crate::Environment;
 use std::collections::HashMap;
 
@@ -141,6 +142,12 @@ pub fn search_lists<T>(
         .find(|&(sym, _)| *sym == symbol)
 }
 
+/// Used in place of unimplemented functions with `--log-unimplemented`.
+/// Returning a 64-bit value means both `r0` and `r1` are zeroed.
+fn unimplemented_function_stub(_env: &mut Environment) -> u64 {
+    0
+}
+
 fn encode_a32_svc(imm: u32) -> u32 {
     assert!(imm & 0xff000000 == 0);
     imm | 0xef000000
@@ -211,6 +218,49 @@ impl Dyld {
         self.thread_exit_routine.unwrap()
     }
 
+    /// Forget host function wrappers that won't exist once `mem` is restored,
+    /// see [crate::Environment::load_snapshot].
+    pub fn forget_pointers_not_in(&mut self, mem: &MemSnapshot) {
+        self.non_lazy_host_functions
+            .retain(|_, f| mem.is_used(f.addr_without_thumb_bit()));
+    }
+
+    /// Work out the order the static initializers of the binaries should be
+    /// run in: each binary comes after the dylibs it depends on, and the app
+    /// binary (index 0) comes last. The dylibs are identified by file name.
+    ///
+    /// Circular dependencies are logged and broken by ignoring the dependency
+    /// that closes the cycle, so the binary found first in the load order is
+    /// initialized last.
+    pub fn static_initializer_order(bins: &[MachO]) -> Vec<usize> {
+        let dependencies: Vec<Vec<usize>> = bins
+            .iter()
+            .map(|bin| {
+                bin.dynamic_libraries
+                    .iter()
+                    .filter_map(|path| {
+                        let file_name = path.rsplit('/').next().unwrap();
+                        // The app binary can't be a dependency.
+                        bins.iter()
+                            .skip(1)
+                            .position(|dylib| dylib.name == file_name)
+                    })
+                    .map(|dylib_idx| dylib_idx + 1)
+                    .collect()
+            })
+            .collect();
+
+        let (order, cycles) = dependency_order(&dependencies);
+        for cycle in cycles {
+            let names: Vec<&str> = cycle.iter().map(|&idx| bins[idx].name.as_str()).collect();
+            log!(
+                "Warning: circular dependency between binaries: {}. Static initializers may run in the wrong order.",
+                names.join(" -> ")
+            );
+        }
+        order
+    }
+
     /// Do linking-related tasks that need doing right after loading the
     /// binaries.
     pub fn do_initial_linking(&mut self, bins: &[MachO], mem: &mut Mem, objc: &mut ObjC) {
@@ -339,6 +389,15 @@ impl Dyld {
             {
                 // Often used for C++ RTTI
                 Ptr::from_bits(external_addr)
+            } else if bin.weak_imports.contains(name) {
+                log!(
+                    "Weak external relocation {:?} in {:?} is unavailable, resolving to NULL",
+                    name,
+                    bin.name
+                );
+                // The offset shouldn't be applied to a missing symbol.
+                mem.write(ptr_ptr, Ptr::null());
+                continue;
             } else {
                 unhandled_relocations
                     .entry(name)
@@ -416,6 +475,17 @@ impl Dyld {
                 continue;
             }
 
+            if bin.weak_imports.contains(symbol) {
+                log!(
+                    "Weak non-lazy symbol {:?} at {:?} in \"{}\" is unavailable, resolving to NULL",
+                    symbol,
+                    ptr_ptr,
+                    bin.name
+                );
+                mem.write(ptr_ptr, Ptr::null());
+                continue;
+            }
+
             log!(
                 "Warning: unhandled non-lazy symbol {:?} at {:?} in \"{}\"",
                 symbol,
@@ -454,16 +524,20 @@ impl Dyld {
     /// Return a host function that can be called to handle an SVC instruction
     /// encountered during CPU emulation. If `None` is returned, the execution
     /// needs to resume at `svc_pc`.
+    ///
+    /// If `unimplemented_log` is provided, calls to unimplemented functions
+    /// are recorded there instead of causing a panic.
     pub fn get_svc_handler(
         &mut self,
         bins: &[MachO],
         mem: &mut Mem,
         cpu: &mut Cpu,
+        unimplemented_log: Option<&mut UnimplementedLog>,
         svc_pc: u32,
         svc: u32,
     ) -> Option<HostFunction> {
         match svc {
-            Self::SVC_LAZY_LINK => self.do_lazy_link(bins, mem, cpu, svc_pc),
+            Self::SVC_LAZY_LINK => self.do_lazy_link(bins, mem, cpu, unimplemented_log, svc_pc),
             Self::SVC_THREAD_EXIT | Self::SVC_RETURN_TO_HOST => unreachable!(), // don't handle here
             Self::SVC_LINKED_FUNCTIONS_BASE.. => {
                 let f = self
@@ -483,6 +557,7 @@ impl Dyld {
         bins: &[MachO],
         mem: &mut Mem,
         cpu: &mut Cpu,
+        unimplemented_log: Option<&mut UnimplementedLog>,
         svc_pc: u32,
     ) -> Option<HostFunction> {
         // Links by restoring the original stub function, then updating
@@ -560,7 +635,32 @@ impl Dyld {
             return None;
         }
 
-        if let Some(&(symbol, f)) = search_lists(function_lists::FUNCTION_LISTS, symbol) {
+        let found = search_lists(function_lists::FUNCTION_LISTS, symbol).copied();
+        let found = found.or_else(|| {
+            // With --log-unimplemented, an unimplemented function is linked to
+            // a stub that returns zero, unless a dylib has an implementation.
+            let log = unimplemented_log?;
+            if bins
+                .iter()
+                .any(|bin| bin.exported_symbols.contains_key(symbol))
+            {
+                return None;
+            }
+            let description = describe_function(symbol);
+            if log.record(description.clone()) {
+                log!(
+                    "Warning: call to unimplemented function {}, returning zero",
+                    description
+                );
+            }
+            let stub: HostFunction = &(unimplemented_function_stub as fn(&mut Environment) -> u64);
+            // Leaking is fine: this only happens once per symbol, and only
+            // when debugging.
+            let symbol: &'static str = Box::leak(symbol.to_string().into_boxed_str());
+            Some((symbol, stub))
+        });
+
+        if let Some((symbol, f)) = found {
             // Allocate an SVC ID for this host function
             let idx: u32 = self.linked_host_functions.len().try_into().unwrap();
             let svc = idx + Self::SVC_LINKED_FUNCTIONS_BASE;
@@ -601,7 +701,10 @@ impl Dyld {
             }
         }
 
-        panic!("Call to unimplemented function {}", symbol);
+        panic!(
+            "Call to unimplemented function {}",
+            describe_function(symbol)
+        );
     }
 
     /// Creates a guest function that will call a host function with the name
@@ -660,3 +763,141 @@ impl Dyld {
         GuestFunction::from_addr_with_thumb_bit(function_ptr.to_bits())
     }
 }
+
+/// Depth-first topological sort for [Dyld::static_initializer_order], given
+/// the indices of the binaries each binary depends on. Returns the order and
+/// any cycles that had to be broken. Each cycle starts and ends with the same
+/// binary.
+fn dependency_order(dependencies: &[Vec<usize>]) -> (Vec<usize>, Vec<Vec<usize>>) {
+    fn visit(
+        dependencies: &[Vec<usize>],
+        bin_idx: usize,
+        stack: &mut Vec<usize>,
+        order: &mut Vec<usize>,
+        cycles: &mut Vec<Vec<usize>>,
+    ) {
+        if order.contains(&bin_idx) {
+            return;
+        }
+        if let Some(cycle_start) = stack.iter().position(|&idx| idx == bin_idx) {
+            let mut cycle = stack[cycle_start..].to_vec();
+            cycle.push(bin_idx);
+            cycles.push(cycle);
+            return;
+        }
+        stack.push(bin_idx);
+        for &dependency_idx in &dependencies[bin_idx] {
+            visit(dependencies, dependency_idx, stack, order, cycles);
+        }
+        stack.pop();
+        order.push(bin_idx);
+    }
+
+    let mut stack = Vec::new();
+    let mut order = Vec::with_capacity(dependencies.len());
+    let mut cycles = Vec::new();
+    // Visiting the app binary last means it comes last even if it doesn't
+    // list all the dylibs as dependencies.
+    for bin_idx in (1..dependencies.len()).chain(0..1) {
+        visit(dependencies, bin_idx, &mut stack, &mut order, &mut cycles);
+    }
+    (order, cycles)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mach_o::{DyldIndirectSymbolInfo, Section};
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_dependency_order() {
+        // App only
+        assert_eq!(dependency_order(&[vec![]]), (vec![0], vec![]));
+        // Independent libraries keep their order
+        assert_eq!(
+            dependency_order(&[vec![1, 2], vec![], vec![]]),
+            (vec![1, 2, 0], vec![])
+        );
+        // Diamond: app -> 1, 2; 1 -> 3; 2 -> 3; plus an unrelated library 4
+        // that comes before its dependent in the list.
+        assert_eq!(
+            dependency_order(&[vec![1, 2, 4], vec![3], vec![3, 4], vec![], vec![]]),
+            (vec![3, 1, 4, 2, 0], vec![])
+        );
+    }
+
+    #[test]
+    fn test_dependency_cycle() {
+        // app -> 1 -> 2 -> 3 -> 1
+        assert_eq!(
+            dependency_order(&[vec![1], vec![2], vec![3], vec![1]]),
+            (vec![3, 2, 1, 0], vec![vec![1, 2, 3, 1]])
+        );
+    }
+
+    #[test]
+    fn test_static_initializer_order() {
+        fn bin(name: &str, dynamic_libraries: &[&str]) -> MachO {
+            MachO {
+                name: name.to_string(),
+                dynamic_libraries: dynamic_libraries.iter().map(|s| s.to_string()).collect(),
+                sections: Vec::new(),
+                exported_symbols: HashMap::new(),
+                external_relocations: Vec::new(),
+                weak_imports: HashSet::new(),
+                entry_point_pc: None,
+            }
+        }
+
+        let bins = [
+            bin(
+                "TestApp",
+                &["/usr/lib/libSystem.B.dylib", "/usr/lib/libstdc++.6.dylib"],
+            ),
+            bin("libstdc++.6.dylib", &["/usr/lib/libgcc_s.1.dylib"]),
+            bin("libgcc_s.1.dylib", &["/usr/lib/libSystem.B.dylib"]),
+        ];
+        assert_eq!(Dyld::static_initializer_order(&bins), vec![2, 1, 0]);
+    }
+
+    #[test]
+    fn test_weak_imports() {
+        let mut mem = Mem::new();
+        let mut objc = ObjC::new();
+        let mut dyld = Dyld::new();
+
+        // One non-lazy symbol pointer and one external relocation, both
+        // pre-filled with junk that should be overwritten.
+        let nl_symbol_ptr: MutPtr<u32> = mem.alloc(4).cast();
+        let reloc_ptr: MutPtr<u32> = mem.alloc(4).cast();
+        mem.write(nl_symbol_ptr, 0xdeadbeef);
+        mem.write(reloc_ptr, 0xdeadbeef);
+
+        let bins = [MachO {
+            name: "TestApp".to_string(),
+            dynamic_libraries: Vec::new(),
+            sections: vec![Section {
+                name: "__nl_symbol_ptr".to_string(),
+                addr: nl_symbol_ptr.to_bits(),
+                size: 4,
+                type_: SectionType::NonLazySymbolPointers,
+                dyld_indirect_symbol_info: Some(DyldIndirectSymbolInfo {
+                    entry_size: 4,
+                    indirect_undef_symbols: vec![Some("_NewInOS4Function".to_string())],
+                }),
+            }],
+            exported_symbols: HashMap::new(),
+            external_relocations: vec![(reloc_ptr.to_bits(), "_NewInOS4Constant".to_string())],
+            weak_imports: HashSet::from([
+                "_NewInOS4Function".to_string(),
+                "_NewInOS4Constant".to_string(),
+            ]),
+            entry_point_pc: None,
+        }];
+        dyld.do_non_lazy_linking(&bins[0], &bins, &mut mem, &mut objc);
+
+        assert_eq!(mem.read(nl_symbol_ptr), 0);
+        assert_eq!(mem.read(reloc_ptr), 0);
+    }
+}
@@ -13,7 +13,9 @@ use crate::libc;
 
 /// All the lists of constants that the linker should search through.
 pub const CONSTANT_LISTS: &[super::ConstantExports] = &[
+    libc::blocks::CONSTANTS,
     libc::ctype::CONSTANTS,
+    libc::dispatch::CONSTANTS,
     libc::stdio::CONSTANTS,
     libc::mach_init::CONSTANTS,
     core_animation::ca_layer::CONSTANTS,
@@ -19,6 +19,7 @@ pub const FUNCTION_LISTS: &[super::FunctionExports] = &[
     libc::cxxabi::FUNCTIONS,
     libc::crypto::FUNCTIONS,
     libc::dirent::FUNCTIONS,
+    libc::dispatch::FUNCTIONS,
     libc::dlfcn::FUNCTIONS,
     libc::errno::FUNCTIONS,
     libc::ifaddrs::FUNCTIONS,
@@ -43,6 +44,7 @@ pub const FUNCTION_LISTS: &[super::FunctionExports] = &[
     libc::semaphore::FUNCTIONS,
     libc::setjmp::FUNCTIONS,
     libc::signal::FUNCTIONS,
+    libc::socket::FUNCTIONS,
     libc::stdio::FUNCTIONS,
     libc::stdio::printf::FUNCTIONS,
     libc::stdlib::FUNCTIONS,
@@ -55,6 +57,7 @@ pub const FUNCTION_LISTS: &[super::FunctionExports] = &[
     libc::time::FUNCTIONS,
     libc::unistd::FUNCTIONS,
     libc::wchar::FUNCTIONS,
+    libc::zlib::FUNCTIONS,
     crate::objc::FUNCTIONS,
     audio_toolbox::audio_components::FUNCTIONS,
     audio_toolbox::audio_file::FUNCTIONS,
@@ -68,6 +71,7 @@ pub const FUNCTION_LISTS: &[super::FunctionExports] = &[
     core_foundation::cf_data::FUNCTIONS,
     core_foundation::cf_locale::FUNCTIONS,
     core_foundation::cf_run_loop::FUNCTIONS,
+    core_foundation::cf_run_loop_source::FUNCTIONS,
     core_foundation::cf_run_loop_timer::FUNCTIONS,
     core_foundation::cf_string::FUNCTIONS,
     core_foundation::cf_type::FUNCTIONS,
@@ -93,4 +97,5 @@ pub const FUNCTION_LISTS: &[super::FunctionExports] = &[
     uikit::ui_application::FUNCTIONS,
     uikit::ui_geometry::FUNCTIONS,
     uikit::ui_graphics::FUNCTIONS,
+    uikit::ui_image::FUNCTIONS,
 ];
@@ -8,21 +8,24 @@
 //! Unlike its siblings, this module should be considered private and only used
 //! via the re-exports one level up.
 
+pub mod clock;
 mod mutex;
 
 use crate::abi::{CallFromHost, GuestRet};
+use crate::libc::posix_io::FileDescriptor;
 use crate::libc::semaphore::sem_t;
-use crate::mem::{GuestUSize, MutPtr, MutVoidPtr};
+use crate::libc::socket::SocketWait;
+use crate::mem::{GuestUSize, MutPtr, MutVoidPtr, Ptr};
 use crate::{
-    abi, bundle, cpu, dyld, frameworks, fs, gdb, image, libc, mach_o, mem, objc, options, stack,
-    window,
+    abi, bundle, cpu, dyld, frameworks, fs, gdb, image, libc, mach_o, mem, objc, options, paths,
+    snapshot, stack, unimplemented, window,
 };
 use std::collections::HashMap;
 use std::net::TcpListener;
 use std::time::{Duration, Instant};
 
 use crate::libc::pthread::cond::pthread_cond_t;
-pub use mutex::{MutexId, MutexType, PTHREAD_MUTEX_DEFAULT};
+pub use mutex::{MutexId, MutexSnapshot, MutexType, PTHREAD_MUTEX_DEFAULT};
 
 /// Index into the [Vec] of threads. Thread 0 is always the main thread.
 pub type ThreadId = usize;
@@ -66,6 +69,12 @@ pub struct Thread {
     /// Address range of this thread's stack, used to check if addresses are in
     /// range while producing a stack trace.
     stack: Option<std::ops::RangeInclusive<u32>>,
+    /// Number of host-to-guest calls ([Environment::run_call]) in progress on
+    /// this thread. While this is non-zero, the thread has host functions on
+    /// its call stack, so it can't be cancelled.
+    host_to_guest_calls: u32,
+    /// Set by `pthread_cancel`. See [Environment::request_thread_cancellation].
+    cancel_requested: bool,
 }
 
 impl Thread {
@@ -77,11 +86,14 @@ impl Thread {
 /// The struct containing the entire emulator state. Methods are provided for
 /// execution and management of threads.
 pub struct Environment {
-    /// Reference point for various timing functions.
+    /// Reference point for various timing functions. This is a guest time,
+    /// see [clock].
     pub startup_time: Instant,
+    pub clock: clock::GuestClock,
     pub bundle: bundle::Bundle,
     pub fs: fs::Fs,
-    /// The window is only absent when running in headless mode.
+    /// The window is only absent when running in headless mode (see
+    /// [options::Options::creates_window]).
     pub window: Option<window::Window>,
     pub mem: mem::Mem,
     /// Loaded binaries. Index `0` is always the app binary, other entries are
@@ -98,6 +110,11 @@ pub struct Environment {
     pub options: options::Options,
     gdb_server: Option<gdb::GdbServer>,
     pub env_vars: HashMap<Vec<u8>, MutPtr<u8>>,
+    /// Only present with `--log-unimplemented`.
+    pub unimplemented_log: Option<unimplemented::UnimplementedLog>,
+    /// When the heap statistics should next be logged. Only present with
+    /// `--heap-stats`.
+    heap_stats_next_log: Option<Instant>,
 }
 
 /// What to do next when executing this thread.
@@ -129,6 +146,8 @@ pub enum ThreadBlock {
     Joining(ThreadId, MutPtr<MutVoidPtr>),
     // Deferred guest-to-host return
     DeferredReturn,
+    // Thread is waiting for a socket to become ready.
+    Socket(FileDescriptor, SocketWait),
 }
 
 impl Environment {
@@ -142,12 +161,14 @@ impl Environment {
     /// when allocating a second [mem::Mem] instance.
     pub fn new(
         bundle: bundle::Bundle,
-        fs: fs::Fs,
+        mut fs: fs::Fs,
         options: options::Options,
         env_for_salvage: Option<Environment>,
     ) -> Result<Environment, String> {
         let startup_time = Instant::now();
 
+        fs.set_case_insensitive_fallback(options.case_insensitive_fs);
+
         // Extract things to salvage from the old environment, and then drop it.
         // This needs to be done before creating a new window, because SDL2 only
         // allows one window at once.
@@ -159,7 +180,27 @@ impl Environment {
             None
         };
 
-        let window = if options.headless {
+        // The launch image is decoded in the background while the executable
+        // is loaded and linked, since that can take a noticeable amount of
+        // time for large images. It's displayed once both are done.
+        let launch_image = if !options.creates_window() {
+            None
+        } else {
+            let launch_image_path = bundle.launch_image_path();
+            if fs.is_file(&launch_image_path) {
+                match fs.read(launch_image_path) {
+                    Ok(bytes) => Some(image::Image::from_bytes_async(bytes)),
+                    Err(_) => {
+                        log!("Warning: Could not read launch image file");
+                        None
+                    }
+                }
+            } else {
+                None
+            }
+        };
+
+        let window = if !options.creates_window() {
             None
         } else {
             let icon = bundle.load_icon(&fs);
@@ -167,23 +208,6 @@ impl Environment {
                 log!("Warning: {}", e);
             }
 
-            let launch_image_path = bundle.launch_image_path();
-            let launch_image = if fs.is_file(&launch_image_path) {
-                let res = fs
-                    .read(launch_image_path)
-                    .map_err(|_| "Could not read launch image file".to_string())
-                    .and_then(|bytes| {
-                        image::Image::from_bytes(&bytes)
-                            .map_err(|e| format!("Could not parse launch image: {}", e))
-                    });
-                if let Err(ref e) = res {
-                    log!("Warning: {}", e);
-                };
-                res.ok()
-            } else {
-                None
-            };
-
             Some(window::Window::new(
                 &format!(
                     "{} (touchHLE {}{}{})",
@@ -197,7 +221,6 @@ impl Environment {
                     super::VERSION
                 ),
                 icon.ok(),
-                launch_image,
                 &options,
             ))
         };
@@ -207,6 +230,7 @@ impl Environment {
         } else {
             mem::Mem::new()
         };
+        mem.set_debug_alloc(options.debug_alloc);
 
         let executable = mach_o::MachO::load_from_file(bundle.executable_path(), &fs, &mut mem)
             .map_err(|e| format!("Could not load executable: {}", e))?;
@@ -248,6 +272,10 @@ impl Environment {
         let mut bins = dylibs;
         bins.insert(0, executable);
 
+        // Static initializers for libraries must be run before the initializer
+        // in the binaries that depend on them.
+        let init_order = dyld::Dyld::static_initializer_order(&bins);
+
         let mut objc = objc::ObjC::new();
 
         let mut dyld = dyld::Dyld::new();
@@ -266,10 +294,13 @@ impl Environment {
             in_host_function: false,
             context: None,
             stack: Some(mem::Mem::MAIN_THREAD_STACK_LOW_END..=0u32.wrapping_sub(1)),
+            host_to_guest_calls: 0,
+            cancel_requested: false,
         };
 
         let mut env = Environment {
             startup_time,
+            clock: clock::GuestClock::new(startup_time),
             bundle,
             fs,
             window,
@@ -283,6 +314,8 @@ impl Environment {
             libc_state: Default::default(),
             mutex_state: Default::default(),
             framework_state: Default::default(),
+            unimplemented_log: options.log_unimplemented.then(Default::default),
+            heap_stats_next_log: options.heap_stats.then(Instant::now),
             options,
             gdb_server: None,
             env_vars: Default::default(),
@@ -290,8 +323,17 @@ impl Environment {
 
         env.set_up_initial_env_vars();
 
+        libc::stdlib::init_rngs(&mut env);
+
         dyld::Dyld::do_late_linking(&mut env);
 
+        if let Some(launch_image) = launch_image {
+            match launch_image.join() {
+                Ok(image) => env.window_mut().set_splash_image(image),
+                Err(e) => log!("Warning: Could not parse launch image: {}", e),
+            }
+        }
+
         {
             let bin_path = env.bundle.executable_path();
 
@@ -336,7 +378,7 @@ impl Environment {
                 .accept()
                 .map_err(|e| format!("Could not accept connection: {}", e))?;
             echo!("Debugger client connected on {}.", client_addr);
-            let mut gdb_server = gdb::GdbServer::new(client);
+            let mut gdb_server = gdb::GdbServer::new(client, &env.bins);
             let step = gdb_server.wait_for_debugger(None, &mut env.cpu, &mut env.mem);
             assert!(!step, "Can't step right now!"); // TODO?
             env.gdb_server = Some(gdb_server);
@@ -344,15 +386,8 @@ impl Environment {
 
         echo!("CPU emulation begins now.");
 
-        // Static initializers for libraries must be run before the initializer
-        // in the app binary.
-        // TODO: once we support more libraries, replace this hard-coded order
-        //       with e.g. a topological sort.
-        assert!(env.bins.len() <= 3);
-        for bin_idx in [1, 2, 0] {
-            let Some(bin) = env.bins.get(bin_idx) else {
-                continue;
-            };
+        for bin_idx in init_order {
+            let bin = &env.bins[bin_idx];
             let Some(section) = bin.get_section(mach_o::SectionType::ModInitFuncPointers) else {
                 continue;
             };
@@ -388,9 +423,7 @@ impl Environment {
 
         let startup_time = Instant::now();
 
-        let launch_image = None;
-
-        assert!(!options.headless);
+        assert!(options.creates_window());
         let window = Some(window::Window::new(
             &format!(
                 "touchHLE {}{}{}",
@@ -403,11 +436,11 @@ impl Environment {
                 super::VERSION
             ),
             Some(icon),
-            launch_image,
             &options,
         ));
 
         let mut mem = mem::Mem::new();
+        mem.set_debug_alloc(options.debug_alloc);
 
         let bins = Vec::new();
 
@@ -429,10 +462,13 @@ impl Environment {
             in_host_function: false,
             context: None,
             stack: Some(mem::Mem::MAIN_THREAD_STACK_LOW_END..=0u32.wrapping_sub(1)),
+            host_to_guest_calls: 0,
+            cancel_requested: false,
         };
 
         let mut env = Environment {
             startup_time,
+            clock: clock::GuestClock::new(startup_time),
             bundle,
             fs,
             window,
@@ -446,6 +482,8 @@ impl Environment {
             libc_state: Default::default(),
             mutex_state: Default::default(),
             framework_state: Default::default(),
+            unimplemented_log: options.log_unimplemented.then(Default::default),
+            heap_stats_next_log: options.heap_stats.then(Instant::now),
             options,
             gdb_server: None,
             env_vars: Default::default(),
@@ -557,6 +595,8 @@ impl Environment {
             in_host_function: false,
             context: Some(cpu::CpuContext::new()),
             stack: Some(stack_alloc.to_bits()..=(stack_high_addr - 1)),
+            host_to_guest_calls: 0,
+            cancel_requested: false,
         });
         let new_thread_id = self.threads.len() - 1;
 
@@ -593,7 +633,7 @@ impl Environment {
             self.current_thread,
             duration
         );
-        let until = Instant::now().checked_add(duration).unwrap();
+        let until = self.clock.now().checked_add(duration).unwrap();
         self.threads[self.current_thread].blocked_by = ThreadBlock::Sleeping(until);
         // For non tail-call sleeps (such as in NSRunLoop), we want to poll
         // other threads but can't return back to the run loop, since it would
@@ -719,12 +759,117 @@ impl Environment {
         self.threads[self.current_thread].blocked_by = ThreadBlock::Joining(joinee_thread, ptr);
     }
 
+    /// Block the current thread until a socket is ready for the operation it
+    /// is waiting for, running other threads in the meantime. Unlike
+    /// [Self::sleep], this only returns once the thread has been unblocked, so
+    /// the caller can retry the operation straight away.
+    pub fn block_on_socket(&mut self, fd: FileDescriptor, wait: SocketWait) {
+        assert!(matches!(
+            self.threads[self.current_thread].blocked_by,
+            ThreadBlock::NotBlocked
+        ));
+        log_dbg!(
+            "Thread {} blocking on socket {} ({:?}).",
+            self.current_thread,
+            fd,
+            wait
+        );
+        self.threads[self.current_thread].blocked_by = ThreadBlock::Socket(fd, wait);
+        // See the comment in Self::sleep.
+        let old_pc = self.cpu.pc_with_thumb_bit();
+        self.cpu.branch(self.dyld.return_to_host_routine());
+        self.run_call();
+        self.cpu.branch(old_pc);
+    }
+
+    /// Request cancellation of a thread (`pthread_cancel`). Only deferred
+    /// cancellation is supported: the thread is cancelled once it is blocked
+    /// at a cancellation point (sleeping, or waiting on a semaphore, condition
+    /// variable or another thread) and has no host functions on its call stack.
+    /// Blocking on a mutex is not a cancellation point.
+    pub fn request_thread_cancellation(&mut self, thread: ThreadId) {
+        self.threads[thread].cancel_requested = true;
+    }
+
+    /// Check whether cancellation of a thread has been requested, see
+    /// [Self::request_thread_cancellation].
+    pub fn thread_cancellation_requested(&self, thread: ThreadId) -> bool {
+        self.threads[thread].cancel_requested
+    }
+
+    /// Get the number of host-to-guest calls ([Self::run_call]) in progress on
+    /// the current thread, i.e. how many host functions are on its call stack
+    /// below the current guest function.
+    pub fn host_to_guest_call_depth(&self) -> u32 {
+        self.threads[self.current_thread].host_to_guest_calls
+    }
+
+    /// If a thread is blocked at a cancellation point, unblock it so it can be
+    /// cancelled, undoing any side-effects of blocking. Returns [None] if the
+    /// thread can't be cancelled right now, otherwise returns the mutex the
+    /// thread must relock, if any.
+    fn unblock_for_cancellation(&mut self, thread: ThreadId) -> Option<Option<MutexId>> {
+        let mut mutex_to_relock = None;
+        match self.threads[thread].blocked_by {
+            ThreadBlock::Sleeping(_) | ThreadBlock::Joining(..) => (),
+            ThreadBlock::Semaphore(sem) => {
+                let host_sem_rc = &self.libc_state.semaphore.open_semaphores[&sem];
+                let mut host_sem = (*host_sem_rc).borrow_mut();
+                host_sem.value += 1;
+                host_sem.waiting.remove(&thread);
+            }
+            ThreadBlock::Condition(cond) => {
+                // The thread has to hold the mutex again when its cleanup
+                // handlers run, so it has to wait until it's unlocked.
+                let mutex_id = self.libc_state.pthread.cond.mutexes[&cond].mutex_id;
+                if self.mutex_state.mutex_is_locked(mutex_id) {
+                    return None;
+                }
+                self.libc_state.pthread.cond.mutexes.remove(&cond);
+                mutex_to_relock = Some(mutex_id);
+            }
+            // A thread blocked on a socket has a host function on its call
+            // stack, so it can't be cancelled.
+            ThreadBlock::NotBlocked
+            | ThreadBlock::Mutex(_)
+            | ThreadBlock::DeferredReturn
+            | ThreadBlock::Socket(..) => return None,
+        }
+        self.threads[thread].blocked_by = ThreadBlock::NotBlocked;
+        Some(mutex_to_relock)
+    }
+
+    /// Cancel the current thread: run its cleanup handlers and then end it.
+    /// See [Self::request_thread_cancellation].
+    fn cancel_current_thread(&mut self) {
+        log_dbg!("Thread {} is being cancelled.", self.current_thread);
+        assert!(self.threads[self.current_thread].in_start_routine);
+        self.threads[self.current_thread].cancel_requested = false;
+        libc::pthread::thread::run_cleanup_handlers(self);
+        self.finish_current_thread(libc::pthread::thread::PTHREAD_CANCELED);
+    }
+
+    /// Mark the current (secondary) thread as finished, with the given return
+    /// value, and free its stack. Destructors for its thread-specific data are
+    /// called first.
+    fn finish_current_thread(&mut self, return_value: MutVoidPtr) {
+        libc::pthread::key::run_destructors(self);
+        let curr_thread = &mut self.threads[self.current_thread];
+        curr_thread.return_value = Some(return_value);
+        curr_thread.active = false;
+        let stack = curr_thread.stack.take().unwrap();
+        let stack: mem::MutVoidPtr = mem::Ptr::from_bits(*stack.start());
+        log_dbg!("Freeing thread {} stack {:?}", self.current_thread, stack);
+        self.mem.free(stack);
+    }
+
     /// Run the emulator. This is the main loop and won't return until app exit.
     /// Only `main.rs` should call this.
     pub fn run(&mut self) {
         // I'm not sure if this actually is unwind-safe, but considering
         // the emulator will crash anyway, maybe this is okay.
         let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| self.run_inner(true)));
+        self.print_exit_summaries();
         if let Err(e) = res {
             echo!("Register state immediately after panic:");
             self.cpu.dump_regs();
@@ -733,6 +878,58 @@ impl Environment {
         }
     }
 
+    /// Print the summaries for `--log-unimplemented` and `--heap-stats`, if
+    /// they're in use. This should be called before touchHLE exits.
+    pub fn print_exit_summaries(&self) {
+        if let Some(summary) = self.unimplemented_log.as_ref().and_then(|l| l.summary()) {
+            echo!("{}", summary);
+        }
+        if self.options.heap_stats {
+            echo!("{}", libc::stdlib::heap_stats_summary(self));
+        }
+    }
+
+    /// Speed up or slow down the app's clock (see [clock]) if the user has
+    /// started or stopped fast-forwarding. This should be called after polling
+    /// for events.
+    pub fn update_fast_forward(&mut self) {
+        let fast_forwarding = self
+            .window
+            .as_ref()
+            .is_some_and(|window| window.is_fast_forwarding());
+        let speed = if fast_forwarding {
+            self.options.fast_forward_speed
+        } else {
+            1
+        };
+        if speed != self.clock.speed() {
+            self.clock.set_speed(speed);
+            if self.options.fast_forward_mute {
+                frameworks::openal::set_muted(self, fast_forwarding);
+            }
+        }
+    }
+
+    /// For `--heap-stats`: log the heap statistics if it's time to.
+    fn log_heap_stats_if_due(&mut self) {
+        let Some(next_log) = self.heap_stats_next_log else {
+            return;
+        };
+        let now = Instant::now();
+        if now < next_log {
+            return;
+        }
+        let stats = self.mem.heap_stats();
+        log!(
+            "Heap: {} bytes live in {} allocations; {} bytes allocated in total by {} allocations",
+            stats.live_bytes,
+            stats.live_allocations,
+            stats.total_allocated,
+            stats.allocation_count
+        );
+        self.heap_stats_next_log = Some(now + Duration::from_secs(10));
+    }
+
     /// Run the emulator until the app returns control to the host. This is for
     /// host-to-guest function calls (see [abi::CallFromHost::call_from_host]).
     ///
@@ -742,8 +939,10 @@ impl Environment {
         let was_in_host_function = self.threads[self.current_thread].in_host_function;
         let old_thread = self.current_thread;
         self.threads[self.current_thread].in_host_function = false;
+        self.threads[self.current_thread].host_to_guest_calls += 1;
         self.run_inner(false);
         assert!(self.current_thread == old_thread);
+        self.threads[self.current_thread].host_to_guest_calls -= 1;
         self.threads[self.current_thread].in_host_function = was_in_host_function;
     }
 
@@ -791,6 +990,265 @@ impl Environment {
         self.enter_debugger(Some(error))
     }
 
+    /// Run `f` with the CPU state of `thread` loaded into [Self::cpu], even if
+    /// it isn't the current thread.
+    fn with_thread_cpu<T>(&mut self, thread: ThreadId, f: impl FnOnce(&mut cpu::Cpu) -> T) -> T {
+        if thread == self.current_thread {
+            return f(&mut self.cpu);
+        }
+        let mut context = self.threads[thread].context.take().unwrap();
+        self.cpu.swap_context(&mut context);
+        let result = f(&mut self.cpu);
+        self.cpu.swap_context(&mut context);
+        self.threads[thread].context = Some(context);
+        result
+    }
+
+    /// Check whether the current state can be saved to or replaced by a
+    /// snapshot made at `resume_point`, see [crate::snapshot].
+    fn check_snapshot_supported(&self, resume_point: snapshot::ResumePoint) -> Result<(), String> {
+        let in_main_run_loop = resume_point == snapshot::ResumePoint::MainRunLoop;
+        if in_main_run_loop && self.current_thread != 0 {
+            return Err("Only the main thread's run loop is supported".to_string());
+        }
+        for (thread_id, thread) in self.threads.iter().enumerate() {
+            // In the main run loop, the main thread is always in the host
+            // function that runs it (e.g. UIApplicationMain()), which is in the
+            // same state at the start of each iteration.
+            let expect_host_function = in_main_run_loop && thread_id == 0;
+            if thread.in_host_function != expect_host_function || thread.host_to_guest_calls != 0 {
+                return Err(format!(
+                    "Thread {} has a host function on its call stack",
+                    thread_id
+                ));
+            }
+            if !matches!(
+                thread.blocked_by,
+                ThreadBlock::NotBlocked
+                    | ThreadBlock::Sleeping(_)
+                    | ThreadBlock::Mutex(_)
+                    | ThreadBlock::Condition(_)
+                    | ThreadBlock::Joining(..)
+            ) {
+                return Err(format!(
+                    "Thread {} is waiting for something that can't be saved",
+                    thread_id
+                ));
+            }
+        }
+
+        let unsupported = [
+            (libc::posix_io::has_open_files(self), "open files"),
+            (libc::dirent::has_open_dirs(self), "open directories"),
+            (libc::mmap::has_mappings(self), "mmap()"),
+            (libc::socket::has_open_sockets(self), "sockets"),
+            (libc::semaphore::has_open_semaphores(self), "semaphores"),
+            (
+                libc::mach_semaphore::has_semaphores(self),
+                "Mach semaphores",
+            ),
+            (
+                libc::stdio::has_buffered_data(self),
+                "unflushed stdio buffers",
+            ),
+            (
+                libc::stdlib::has_aligned_allocations(self),
+                "posix_memalign()",
+            ),
+            (libc::zlib::has_open_streams(self), "zlib streams"),
+            (libc::dispatch::has_queues(self), "dispatch queues"),
+            (frameworks::opengles::eagl::has_contexts(self), "OpenGL ES"),
+            (frameworks::openal::has_devices(self), "OpenAL"),
+            (
+                frameworks::audio_toolbox::has_open_objects(self),
+                "Audio Toolbox",
+            ),
+            (
+                frameworks::media_player::movie_player::has_players(self),
+                "movie players",
+            ),
+        ];
+        if let Some((_, what)) = unsupported.iter().find(|&&(in_use, _)| in_use) {
+            return Err(format!(
+                "The app is using {}, which can't be saved in a snapshot yet",
+                what
+            ));
+        }
+        Ok(())
+    }
+
+    /// Save a snapshot of the app's state to a file, see [crate::snapshot].
+    ///
+    /// `resume_point` must say where this is being called from. Host functions
+    /// can't be saved, so this fails if any thread is executing one (other
+    /// than the main run loop's). It also fails if the app is using any of the
+    /// subsystems listed in `check_snapshot_supported()`, since their state
+    /// isn't saved either.
+    pub fn save_snapshot(
+        &mut self,
+        path: &std::path::Path,
+        resume_point: snapshot::ResumePoint,
+    ) -> Result<(), String> {
+        self.check_snapshot_supported(resume_point)?;
+
+        let now = self.clock.now();
+        let mut threads = Vec::with_capacity(self.threads.len());
+        for thread_id in 0..self.threads.len() {
+            let thread = &self.threads[thread_id];
+            let blocked_by = match thread.blocked_by {
+                ThreadBlock::NotBlocked => snapshot::BlockSnapshot::NotBlocked,
+                ThreadBlock::Sleeping(until) => {
+                    snapshot::BlockSnapshot::Sleeping(until.saturating_duration_since(now))
+                }
+                ThreadBlock::Mutex(mutex_id) => snapshot::BlockSnapshot::Mutex(mutex_id),
+                ThreadBlock::Condition(cond) => snapshot::BlockSnapshot::Condition(cond.to_bits()),
+                ThreadBlock::Joining(joinee, retval) => {
+                    snapshot::BlockSnapshot::Joining(joinee, retval.to_bits())
+                }
+                _ => unreachable!(), // checked by check_snapshot_supported()
+            };
+            let (active, in_start_routine) = (thread.active, thread.in_start_routine);
+            let return_value = thread.return_value.map(|value| value.to_bits());
+            let cpu = self.with_thread_cpu(thread_id, |cpu| snapshot::CpuSnapshot::capture(cpu));
+            threads.push(snapshot::ThreadSnapshot {
+                active,
+                in_start_routine,
+                return_value,
+                blocked_by,
+                cpu,
+            });
+        }
+
+        let snapshot = snapshot::Snapshot {
+            bundle_id: self.bundle.bundle_identifier().to_string(),
+            resume_point,
+            threads,
+            mem: self.mem.snapshot(),
+            mutexes: self.mutex_state.snapshot(),
+            pthread: self.libc_state.pthread.snapshot(),
+            objc: self.objc.snapshot(&self.mem),
+        };
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| format!("Couldn't create {}: {}", dir.display(), e))?;
+        }
+        std::fs::write(path, snapshot.to_bytes())
+            .map_err(|e| format!("Couldn't write {}: {}", path.display(), e))
+    }
+
+    /// Replace the app's state with a snapshot loaded from a file, see
+    /// [crate::snapshot]. The same restrictions apply as for
+    /// [Self::save_snapshot], and the snapshot must have been made at the same
+    /// kind of `resume_point`, with the same threads and Objective-C objects.
+    pub fn load_snapshot(
+        &mut self,
+        path: &std::path::Path,
+        resume_point: snapshot::ResumePoint,
+    ) -> Result<(), String> {
+        self.check_snapshot_supported(resume_point)?;
+
+        let bytes =
+            std::fs::read(path).map_err(|e| format!("Couldn't read {}: {}", path.display(), e))?;
+        let snapshot = snapshot::Snapshot::from_bytes(&bytes)?;
+
+        if snapshot.bundle_id != self.bundle.bundle_identifier() {
+            return Err(format!(
+                "Snapshot is for a different app ({})",
+                snapshot.bundle_id
+            ));
+        }
+        if snapshot.mem.null_segment_size != self.mem.null_segment_size() {
+            return Err("Snapshot is for a different version of the app".to_string());
+        }
+        if snapshot.resume_point != resume_point {
+            return Err(format!(
+                "Snapshot was made at a different point ({:?}, but this is {:?})",
+                snapshot.resume_point, resume_point
+            ));
+        }
+        if snapshot.threads.len() != self.threads.len() {
+            return Err(format!(
+                "Snapshot has {} threads, but the app has {}",
+                snapshot.threads.len(),
+                self.threads.len()
+            ));
+        }
+        self.libc_state.pthread.check_snapshot(&snapshot.pthread)?;
+        self.objc.check_snapshot(&snapshot.objc, &self.mem)?;
+
+        self.mem.restore_snapshot(&snapshot.mem);
+        // The code in memory may have changed.
+        self.cpu.invalidate_cache_range(0, u32::MAX);
+
+        let now = self.clock.now();
+        for (thread_id, thread_snapshot) in snapshot.threads.iter().enumerate() {
+            let thread = &mut self.threads[thread_id];
+            thread.active = thread_snapshot.active;
+            thread.in_start_routine = thread_snapshot.in_start_routine;
+            thread.return_value = thread_snapshot.return_value.map(Ptr::from_bits);
+            thread.blocked_by = match thread_snapshot.blocked_by {
+                snapshot::BlockSnapshot::NotBlocked => ThreadBlock::NotBlocked,
+                snapshot::BlockSnapshot::Sleeping(duration) => {
+                    ThreadBlock::Sleeping(now + duration)
+                }
+                snapshot::BlockSnapshot::Mutex(mutex_id) => ThreadBlock::Mutex(mutex_id),
+                snapshot::BlockSnapshot::Condition(cond) => {
+                    ThreadBlock::Condition(Ptr::from_bits(cond))
+                }
+                snapshot::BlockSnapshot::Joining(joinee, retval) => {
+                    ThreadBlock::Joining(joinee, Ptr::from_bits(retval))
+                }
+            };
+            self.with_thread_cpu(thread_id, |cpu| thread_snapshot.cpu.restore(cpu));
+        }
+        self.mutex_state.restore_snapshot(&snapshot.mutexes);
+        self.libc_state.pthread.restore_snapshot(&snapshot.pthread);
+        self.objc.restore_snapshot(&snapshot.objc, &snapshot.mem);
+
+        // Host-side caches of things allocated in guest memory after the
+        // snapshot was made would otherwise be left dangling.
+        self.dyld.forget_pointers_not_in(&snapshot.mem);
+        self.libc_state.forget_pointers_not_in(&snapshot.mem);
+        self.framework_state.forget_pointers_not_in(&snapshot.mem);
+        self.env_vars
+            .retain(|_, value| snapshot.mem.is_used(value.to_bits()));
+        Ok(())
+    }
+
+    /// Save or load a snapshot if the user has pressed the relevant key (see
+    /// `--snapshot-keys`). `resume_point` says where this is being called
+    /// from, or is [None] if a snapshot can't be saved or loaded there.
+    pub fn handle_snapshot_request(&mut self, resume_point: Option<snapshot::ResumePoint>) {
+        let Some(request) = self
+            .window
+            .as_mut()
+            .and_then(|window| window.take_snapshot_request())
+        else {
+            return;
+        };
+        let path = paths::user_data_base_path()
+            .join(paths::SNAPSHOTS_DIR)
+            .join(format!("{}.snapshot", self.bundle.bundle_identifier()));
+        let (action, verb) = match request {
+            window::SnapshotRequest::Save => ("save", "Saved"),
+            window::SnapshotRequest::Load => ("load", "Loaded"),
+        };
+        let result = if let Some(resume_point) = resume_point {
+            // Buffered output isn't saved, but it can just be written out.
+            libc::stdio::flush_all(self);
+            match request {
+                window::SnapshotRequest::Save => self.save_snapshot(&path, resume_point),
+                window::SnapshotRequest::Load => self.load_snapshot(&path, resume_point),
+            }
+        } else {
+            Err("A host function (e.g. a nested run loop) is executing".to_string())
+        };
+        match result {
+            Ok(()) => echo!("{} snapshot: {}", verb, path.display()),
+            Err(e) => echo!("Couldn't {} snapshot: {}", action, e),
+        }
+    }
+
     /// Used to check whether a debugger is connected, and therefore whether
     /// [Environment::enter_debugger] will do something.
     pub fn is_debugging_enabled(&self) -> bool {
@@ -840,13 +1298,7 @@ impl Environment {
                                 self.current_thread,
                                 initial_thread
                             );
-                            let curr_thread = &mut self.threads[self.current_thread];
-                            curr_thread.return_value = Some(GuestRet::from_regs(self.cpu.regs()));
-                            curr_thread.active = false;
-                            let stack = curr_thread.stack.take().unwrap();
-                            let stack: mem::MutVoidPtr = mem::Ptr::from_bits(*stack.start());
-                            log_dbg!("Freeing thread {} stack {:?}", self.current_thread, stack);
-                            self.mem.free(stack);
+                            self.finish_current_thread(GuestRet::from_regs(self.cpu.regs()));
                             ThreadNextAction::Yield
                         }
                     }
@@ -884,6 +1336,7 @@ impl Environment {
                             &self.bins,
                             &mut self.mem,
                             &mut self.cpu,
+                            self.unimplemented_log.as_mut(),
                             svc_pc,
                             svc,
                         ) {
@@ -919,17 +1372,19 @@ impl Environment {
         assert!(self.threads[initial_thread].context.is_none());
 
         loop {
-            // 100,000 ticks is an arbitrary number. It needs to be reasonably
-            // large so we aren't jumping in and out of dynarmic or trying to
-            // poll for events too often. At the same time, very large values
-            // are bad for responsiveness.
+            // The tick slice (100,000 ticks by default) is an arbitrary number.
+            // It needs to be reasonably large so we aren't jumping in and out
+            // of dynarmic or trying to poll for events too often. At the same
+            // time, very large values are bad for responsiveness. The best
+            // tradeoff depends on the host, so it's configurable. It's also
+            // multiplied while the user is fast-forwarding.
             let mut ticks = if self.threads[self.current_thread].is_blocked() {
                 // The current thread might be asleep, in which case we want to
                 // immediately switch to another thread. This only happens when
                 // called from Self::sleep().
                 0
             } else {
-                100_000
+                self.options.tick_slice(self.clock.speed() > 1)
             };
             let mut step_and_debug = false;
             while ticks > 0 {
@@ -967,6 +1422,25 @@ impl Environment {
             // thread, lest every single callback call pay this cost.
             if let Some(ref mut window) = self.window {
                 window.poll_for_events(&self.options);
+                // Requests made while a host function is executing are left
+                // for the run loop to handle, see ns_run_loop.
+                if root {
+                    self.handle_snapshot_request(Some(snapshot::ResumePoint::Root));
+                }
+                self.update_fast_forward();
+            }
+            self.log_heap_stats_if_due();
+            // With --pause-in-background, no guest code runs while the window
+            // is minimized, but we still need to poll so we notice when it's
+            // restored.
+            if let Some(ref mut window) = self.window {
+                if window.is_paused_in_background() {
+                    while window.is_paused_in_background() {
+                        std::thread::sleep(Duration::from_millis(1000 / 60));
+                        window.poll_for_events(&self.options);
+                    }
+                    echo!("App is no longer in the background, resuming.");
+                }
             }
 
             loop {
@@ -975,6 +1449,7 @@ impl Environment {
                 let mut suitable_thread: Option<ThreadId> = None;
                 let mut next_awakening: Option<Instant> = None;
                 let mut mutex_to_relock: Option<MutexId> = None;
+                let mut cancel_suitable_thread = false;
                 for i in 0..self.threads.len() {
                     let i = (self.current_thread + 1 + i) % self.threads.len();
                     let candidate = &mut self.threads[i];
@@ -982,9 +1457,19 @@ impl Environment {
                     if !candidate.active || candidate.in_host_function {
                         continue;
                     }
+                    if candidate.cancel_requested && candidate.host_to_guest_calls == 0 {
+                        if let Some(mutex_id) = self.unblock_for_cancellation(i) {
+                            log_dbg!("Thread {} was unblocked to be cancelled.", i);
+                            suitable_thread = Some(i);
+                            mutex_to_relock = mutex_id;
+                            cancel_suitable_thread = true;
+                            break;
+                        }
+                    }
+                    let candidate = &mut self.threads[i];
                     match candidate.blocked_by {
                         ThreadBlock::Sleeping(sleeping_until) => {
-                            if sleeping_until <= Instant::now() {
+                            if sleeping_until <= self.clock.now() {
                                 log_dbg!("Thread {} finished sleeping.", i);
                                 candidate.blocked_by = ThreadBlock::NotBlocked;
                                 suitable_thread = Some(i);
@@ -1078,6 +1563,22 @@ impl Environment {
                                 return;
                             }
                         }
+                        ThreadBlock::Socket(fd, wait) => {
+                            if libc::socket::socket_is_ready(self, fd, wait) {
+                                log_dbg!("Thread {} was unblocked by socket {}.", i, fd);
+                                self.threads[i].blocked_by = ThreadBlock::NotBlocked;
+                                suitable_thread = Some(i);
+                                break;
+                            } else {
+                                // Sockets are polled, so treat this like a
+                                // short sleep.
+                                let poll_at = self.clock.now() + libc::socket::SOCKET_POLL_INTERVAL;
+                                next_awakening = match next_awakening {
+                                    None => Some(poll_at),
+                                    Some(other) => Some(other.min(poll_at)),
+                                };
+                            }
+                        }
                         ThreadBlock::NotBlocked => {
                             suitable_thread = Some(i);
                             break;
@@ -1093,11 +1594,18 @@ impl Environment {
                     if let Some(mutex_id) = mutex_to_relock {
                         self.relock_unblocked_mutex(mutex_id);
                     }
+                    if cancel_suitable_thread {
+                        self.cancel_current_thread();
+                        // Find another thread to execute.
+                        continue;
+                    }
                     break;
                 // All suitable threads are blocked and at least one is asleep.
                 // Sleep until one of them wakes up.
                 } else if let Some(next_awakening) = next_awakening {
-                    let duration = next_awakening.duration_since(Instant::now());
+                    let duration = self
+                        .clock
+                        .host_duration(next_awakening.saturating_duration_since(self.clock.now()));
                     log_dbg!("All threads blocked/asleep, sleeping for {:?}.", duration);
                     std::thread::sleep(duration);
                     // Try again, there should be some thread awake now (or
@@ -1117,10 +1625,24 @@ impl Environment {
         // TODO: Provide all the system environment variables an app might
         // expect to find.
 
-        // Initialize HOME envvar
-        let home_value_cstr = self
-            .mem
-            .alloc_and_write_cstr(self.fs.home_directory().as_str().as_bytes());
-        self.env_vars.insert(b"HOME".to_vec(), home_value_cstr);
+        let home = self.fs.home_directory().as_str();
+        // These are what iPhone OS provides to apps.
+        let defaults = [
+            ("HOME", home.to_string()),
+            ("CFFIXED_USER_HOME", home.to_string()),
+            ("TMPDIR", format!("{}/tmp/", home)),
+            ("USER", "mobile".to_string()),
+            ("LOGNAME", "mobile".to_string()),
+            ("SHELL", "/bin/sh".to_string()),
+            ("PATH", "/usr/bin:/bin:/usr/sbin:/sbin".to_string()),
+        ];
+        let defaults = defaults.map(|(name, value)| (name.to_string(), value));
+
+        for (name, value) in defaults.iter().chain(self.options.env_vars.iter()) {
+            let value_cstr = self.mem.alloc_and_write_cstr(value.as_bytes());
+            if let Some(old) = self.env_vars.insert(name.as_bytes().to_vec(), value_cstr) {
+                self.mem.free(old.cast());
+            }
+        }
     }
 }
@@ -0,0 +1,105 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! The app's view of time.
+//!
+//! Normally the app's clock runs at the same speed as the host's, but while the
+//! user is fast-forwarding (see `--fast-forward-key=`), it runs faster. Guest
+//! time is represented by [Instant]s that may be ahead of the host clock, so
+//! they must only be compared with [GuestClock::now], never [Instant::now].
+//! Everything the app can use to measure or wait for time
+//! (`mach_absolute_time()`, `gettimeofday()`, `NSDate`, `NSTimer`, `sleep()`
+//! and so on) uses this clock, so that they stay consistent with each other.
+//!
+//! Audio isn't sped up, so sounds and music still take as long to play as
+//! usual.
+
+use std::time::{Duration, Instant, SystemTime};
+
+pub struct GuestClock {
+    /// Host time at which the current speed took effect.
+    host_base: Instant,
+    /// Guest time corresponding to [Self::host_base].
+    guest_base: Instant,
+    /// How many times faster than the host clock the guest clock is running.
+    speed: u32,
+}
+
+impl GuestClock {
+    pub fn new(now: Instant) -> GuestClock {
+        GuestClock {
+            host_base: now,
+            guest_base: now,
+            speed: 1,
+        }
+    }
+
+    fn guest_time_at(&self, host_time: Instant) -> Instant {
+        let host_elapsed = host_time.saturating_duration_since(self.host_base);
+        self.guest_base + host_elapsed * self.speed
+    }
+
+    /// The current guest time.
+    pub fn now(&self) -> Instant {
+        self.guest_time_at(Instant::now())
+    }
+
+    /// The current guest wall-clock time. This is the host's wall-clock time,
+    /// plus however far the guest clock has got ahead of the host clock.
+    pub fn system_time(&self) -> SystemTime {
+        let host_now = Instant::now();
+        SystemTime::now()
+            + self
+                .guest_time_at(host_now)
+                .saturating_duration_since(host_now)
+    }
+
+    pub fn speed(&self) -> u32 {
+        self.speed
+    }
+
+    /// Change how many times faster than the host clock the guest clock runs.
+    pub fn set_speed(&mut self, speed: u32) {
+        self.set_speed_at(Instant::now(), speed)
+    }
+
+    fn set_speed_at(&mut self, host_time: Instant, speed: u32) {
+        assert!(speed >= 1);
+        self.guest_base = self.guest_time_at(host_time);
+        self.host_base = host_time;
+        self.speed = speed;
+    }
+
+    /// How long the host needs to wait for `guest_duration` to pass on the
+    /// guest clock, at the current speed.
+    pub fn host_duration(&self, guest_duration: Duration) -> Duration {
+        guest_duration / self.speed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn speed_changes_are_continuous() {
+        let start = Instant::now();
+        let mut clock = GuestClock::new(start);
+        let secs = Duration::from_secs;
+
+        assert_eq!(clock.guest_time_at(start + secs(1)), start + secs(1));
+
+        clock.set_speed_at(start + secs(2), 4);
+        assert_eq!(clock.guest_time_at(start + secs(2)), start + secs(2));
+        assert_eq!(clock.guest_time_at(start + secs(3)), start + secs(6));
+        assert_eq!(clock.host_duration(secs(8)), secs(2));
+
+        // Going back to normal speed keeps the time that was gained.
+        clock.set_speed_at(start + secs(4), 1);
+        assert_eq!(clock.guest_time_at(start + secs(4)), start + secs(10));
+        assert_eq!(clock.guest_time_at(start + secs(5)), start + secs(11));
+        assert_eq!(clock.host_duration(secs(8)), secs(8));
+    }
+}
@@ -102,6 +102,47 @@ impl MutexState {
             .get(&mutex_id)
             .map_or(false, |mutex| mutex.locked.is_some())
     }
+
+    /// Copy the state of all mutexes for [crate::snapshot].
+    pub fn snapshot(&self) -> MutexSnapshot {
+        let mut mutexes: Vec<_> = self
+            .mutexes
+            .iter()
+            .map(|(&mutex_id, mutex)| (mutex_id, mutex.type_, mutex.locked, mutex.waiting_count))
+            .collect();
+        mutexes.sort_by_key(|&(mutex_id, _, _, _)| mutex_id);
+        MutexSnapshot {
+            mutex_count: self.mutex_count,
+            mutexes,
+        }
+    }
+
+    /// Replace the state of all mutexes with a snapshot made by
+    /// [Self::snapshot].
+    pub fn restore_snapshot(&mut self, snapshot: &MutexSnapshot) {
+        self.mutex_count = snapshot.mutex_count;
+        self.mutexes = snapshot
+            .mutexes
+            .iter()
+            .map(|&(mutex_id, type_, locked, waiting_count)| {
+                let mutex = Mutex {
+                    type_,
+                    waiting_count,
+                    locked,
+                };
+                (mutex_id, mutex)
+            })
+            .collect();
+    }
+}
+
+/// Copy of [MutexState], see [MutexState::snapshot].
+#[derive(Debug, PartialEq)]
+pub struct MutexSnapshot {
+    pub mutex_count: u64,
+    /// ID, type, owning thread and lock count, and number of waiting threads
+    /// of each mutex, in ascending ID order.
+    pub mutexes: Vec<(MutexId, MutexType, Option<(ThreadId, NonZeroU32)>, u32)>,
 }
 
 impl Environment {
@@ -12,13 +12,23 @@
 //! code has its own, not particularly good implementation. We might want to
 //! switch to something like cosmic-text in future, but that has a _lot_ more
 //! dependencies.
+//!
+//! Each [Font] can have a chain of fallback fonts that are consulted, in order,
+//! for characters the primary font has no glyph for. This is how CJK text is
+//! supported, since the default fonts only cover Latin-like scripts.
+//!
+//! Right-to-left text is reordered and shaped one line at a time, after line
+//! breaking (see [bidi]).
+
+mod bidi;
 
 use crate::paths;
-use rusttype::{Point, Scale};
+use rusttype::{GlyphId, Point, PositionedGlyph, Scale};
 use std::io::Read;
 
 pub struct Font {
     font: rusttype::Font<'static>,
+    fallbacks: Vec<rusttype::Font<'static>>,
 }
 
 pub enum TextAlignment {
@@ -83,7 +93,17 @@ impl Font {
             panic!("Couldn't parse bundled font file {:?}. This probably means the file is corrupt. Try re-downloading it.", path);
         };
 
-        Font { font }
+        Font {
+            font,
+            fallbacks: Vec::new(),
+        }
+    }
+
+    /// Add a font to the end of the fallback chain. This is cheap: the font
+    /// data is shared, not copied.
+    pub fn with_fallback(mut self, fallback: &Font) -> Font {
+        self.fallbacks.push(fallback.font.clone());
+        self
     }
 
     pub fn mono_regular() -> Font {
@@ -129,6 +149,64 @@ impl Font {
         Self::from_resource_file("NotoSansJP-Bold.otf")
     }
 
+    /// Find which face should be used to draw a character: 0 is the primary
+    /// font, 1 is the first fallback, and so on. If no font has a glyph for
+    /// the character, the primary font's "missing glyph" is used.
+    fn face_index_for_char(&self, c: char) -> usize {
+        // Glyph 0 is always .notdef, i.e. the character isn't supported.
+        if c.is_control() || self.font.glyph(c).id() != GlyphId(0) {
+            return 0;
+        }
+        self.fallbacks
+            .iter()
+            .position(|fallback| fallback.glyph(c).id() != GlyphId(0))
+            .map_or(0, |i| i + 1)
+    }
+
+    fn face(&self, index: usize) -> &rusttype::Font<'static> {
+        match index {
+            0 => &self.font,
+            _ => &self.fallbacks[index - 1],
+        }
+    }
+
+    /// Lay out a single line of text, picking the face for each character from
+    /// the fallback chain. This is equivalent to [rusttype::Font::layout] for
+    /// text that doesn't need any fallbacks.
+    fn layout_line<'a>(
+        &'a self,
+        font_size: f32,
+        line: &'a str,
+        start: Point<f32>,
+    ) -> impl Iterator<Item = PositionedGlyph<'static>> + 'a {
+        let scale = scale(font_size);
+        let mut caret = 0.0;
+        let mut last_glyph: Option<(usize, GlyphId)> = None;
+        line.chars().map(move |c| {
+            let face_index = self.face_index_for_char(c);
+            let face = self.face(face_index);
+            let glyph = face.glyph(c).scaled(scale);
+            // Kerning only makes sense between glyphs from the same face.
+            if let Some((last_face_index, last_id)) = last_glyph {
+                if last_face_index == face_index {
+                    caret += face.pair_kerning(scale, last_id, glyph.id());
+                }
+            }
+            let glyph = glyph.positioned(Point {
+                x: start.x + caret,
+                y: start.y,
+            });
+            caret += glyph.unpositioned().h_metrics().advance_width;
+            last_glyph = Some((face_index, glyph.id()));
+            glyph
+        })
+    }
+
+    /// Get the distance from the top of a line to its baseline.
+    pub fn ascent(&self, font_size: f32) -> f32 {
+        self.font.v_metrics(scale(font_size)).ascent
+    }
+
     fn line_height_and_gap(&self, font_size: f32) -> (f32, f32) {
         let v_metrics = self.font.v_metrics(scale(font_size));
         (v_metrics.ascent - v_metrics.descent, v_metrics.line_gap)
@@ -136,10 +214,12 @@ impl Font {
 
     /// Calculate the width of a line. This does not handle newlines!
     fn calculate_line_width(&self, font_size: f32, line: &str) -> f32 {
+        let line = bidi::to_visual_order(line);
+
         let mut line_x_min: f32 = 0.0;
         let mut line_x_max: f32 = 0.0;
 
-        for glyph in self.font.layout(line, scale(font_size), Default::default()) {
+        for glyph in self.layout_line(font_size, &line, Point { x: 0.0, y: 0.0 }) {
             let position = glyph.position();
             let h_metrics = glyph.unpositioned().h_metrics();
 
@@ -340,9 +420,10 @@ impl Font {
                 TextAlignment::Center => -line_width / 2.0,
                 TextAlignment::Right => -line_width,
             };
-            for glyph in self.font.layout(
-                line_text,
-                scale(font_size),
+            let line_text = bidi::to_visual_order(line_text);
+            for glyph in self.layout_line(
+                font_size,
+                &line_text,
                 Point {
                     x: origin.0 + line_x_offset,
                     y: 0.0,
@@ -391,3 +472,22 @@ impl Font {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cjk_fallback() {
+        let font = Font::sans_regular().with_fallback(&Font::sans_regular_ja());
+        let faces: Vec<usize> = "A\u{65e5}b\u{3042} "
+            .chars()
+            .map(|c| font.face_index_for_char(c))
+            .collect();
+        assert_eq!(faces, [0, 1, 0, 1, 0]);
+
+        // Without a fallback, the primary font's missing glyph is used.
+        let font = Font::sans_regular();
+        assert_eq!(font.face_index_for_char('\u{65e5}'), 0);
+    }
+}
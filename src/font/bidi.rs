@@ -0,0 +1,421 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! Bidirectional text reordering and Arabic contextual shaping.
+//!
+//! This implements a small subset of the Unicode Bidirectional Algorithm
+//! ([UAX #9](https://www.unicode.org/reports/tr9/)): there is no support for
+//! explicit embeddings, overrides or isolates, and only a few of the weak type
+//! rules are implemented. That's enough for the usual case of a line of Hebrew
+//! or Arabic, possibly with some numbers and Latin words mixed in.
+//!
+//! Arabic shaping picks the isolated, final, initial or medial form of each
+//! letter from the Arabic Presentation Forms-B block, so that the result can be
+//! drawn by a font without any shaping support of its own. The mandatory
+//! lam-alef ligatures are also applied.
+
+use std::borrow::Cow;
+
+/// Simplified bidirectional character types.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum BidiClass {
+    /// Strong left-to-right.
+    L,
+    /// Strong right-to-left (e.g. Hebrew).
+    R,
+    /// Strong right-to-left, Arabic letter.
+    AL,
+    /// European number.
+    EN,
+    /// Arabic number.
+    AN,
+    /// Everything else: whitespace, punctuation, symbols.
+    N,
+}
+
+fn bidi_class(c: char) -> BidiClass {
+    match c as u32 {
+        // Fast path for ASCII and Latin-1
+        0x30..=0x39 => BidiClass::EN,
+        0..=0x58F if c.is_alphabetic() => BidiClass::L,
+        0..=0x58F => BidiClass::N,
+        // Hebrew, NKo, Samaritan, Mandaic, Hebrew presentation forms
+        0x590..=0x5FF | 0x7C0..=0x85F | 0xFB1D..=0xFB4F => BidiClass::R,
+        // Arabic-Indic digits and separators
+        0x660..=0x669 | 0x66B..=0x66C => BidiClass::AN,
+        // Extended Arabic-Indic digits
+        0x6F0..=0x6F9 => BidiClass::EN,
+        // Arabic, Syriac, Thaana, Arabic presentation forms
+        0x600..=0x7BF | 0x860..=0x8FF | 0xFB50..=0xFDFF | 0xFE70..=0xFEFE => BidiClass::AL,
+        _ if c.is_alphabetic() => BidiClass::L,
+        _ => BidiClass::N,
+    }
+}
+
+fn is_rtl(c: char) -> bool {
+    // Nothing below Hebrew can be right-to-left, so this check is cheap for
+    // Latin text.
+    (c as u32) >= 0x590 && matches!(bidi_class(c), BidiClass::R | BidiClass::AL | BidiClass::AN)
+}
+
+/// Mirrored equivalents of characters, used for right-to-left runs (rule L4).
+fn mirror(c: char) -> char {
+    match c {
+        '(' => ')',
+        ')' => '(',
+        '[' => ']',
+        ']' => '[',
+        '{' => '}',
+        '}' => '{',
+        '<' => '>',
+        '>' => '<',
+        '\u{AB}' => '\u{BB}',
+        '\u{BB}' => '\u{AB}',
+        _ => c,
+    }
+}
+
+/// Resolve the embedding level of each character in a line (rules P2–P3,
+/// W2–W7, N1–N2, I1–I2 and L1). Returns the levels and the paragraph
+/// level.
+fn resolve_levels(chars: &[char]) -> (Vec<u8>, u8) {
+    let mut classes: Vec<BidiClass> = chars.iter().map(|&c| bidi_class(c)).collect();
+
+    // P2, P3: the first strong character determines the paragraph direction.
+    let paragraph_level = match classes
+        .iter()
+        .find(|class| matches!(class, BidiClass::L | BidiClass::R | BidiClass::AL))
+    {
+        Some(BidiClass::R | BidiClass::AL) => 1,
+        _ => 0,
+    };
+    let sos = if paragraph_level == 1 {
+        BidiClass::R
+    } else {
+        BidiClass::L
+    };
+
+    // W2, W3, W7: European numbers take on the type of the preceding strong
+    // character if that is Arabic or left-to-right.
+    let mut last_strong = sos;
+    for class in classes.iter_mut() {
+        match *class {
+            BidiClass::L | BidiClass::R => last_strong = *class,
+            BidiClass::AL => {
+                last_strong = BidiClass::AL;
+                *class = BidiClass::R;
+            }
+            BidiClass::EN if last_strong == BidiClass::AL => *class = BidiClass::AN,
+            BidiClass::EN if last_strong == BidiClass::L => *class = BidiClass::L,
+            _ => (),
+        }
+    }
+
+    // N1, N2: neutrals between characters of the same direction take on that
+    // direction, otherwise they take on the paragraph direction. Numbers count
+    // as right-to-left here.
+    let strong_direction = |class: BidiClass| match class {
+        BidiClass::L => Some(BidiClass::L),
+        BidiClass::R | BidiClass::EN | BidiClass::AN => Some(BidiClass::R),
+        _ => None,
+    };
+    let mut i = 0;
+    while i < classes.len() {
+        if classes[i] != BidiClass::N {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        while i < classes.len() && classes[i] == BidiClass::N {
+            i += 1;
+        }
+        let before = if start == 0 {
+            sos
+        } else {
+            strong_direction(classes[start - 1]).unwrap()
+        };
+        let after = if i == classes.len() {
+            sos
+        } else {
+            strong_direction(classes[i]).unwrap()
+        };
+        let resolved = if before == after { before } else { sos };
+        classes[start..i].fill(resolved);
+    }
+
+    // I1, I2
+    let mut levels: Vec<u8> = classes
+        .iter()
+        .map(|class| match (paragraph_level, class) {
+            (0, BidiClass::R) => 1,
+            (0, BidiClass::EN | BidiClass::AN) => 2,
+            (0, _) => 0,
+            (_, BidiClass::L | BidiClass::EN | BidiClass::AN) => 2,
+            (_, _) => 1,
+        })
+        .collect();
+
+    // L1: trailing whitespace is reset to the paragraph level.
+    for (level, c) in levels.iter_mut().zip(chars).rev() {
+        if !c.is_whitespace() {
+            break;
+        }
+        *level = paragraph_level;
+    }
+
+    (levels, paragraph_level)
+}
+
+/// Reorder a line of text from logical order into visual (left-to-right
+/// display) order, shaping any Arabic text. Text without any right-to-left
+/// characters is returned unchanged without any extra work being done.
+pub fn to_visual_order(line: &str) -> Cow<'_, str> {
+    if !line.chars().any(is_rtl) {
+        return Cow::Borrowed(line);
+    }
+
+    let chars = shape_arabic(&line.chars().collect::<Vec<_>>());
+    let (levels, paragraph_level) = resolve_levels(&chars);
+
+    // L4
+    let mut chars: Vec<char> = chars
+        .iter()
+        .zip(&levels)
+        .map(|(&c, &level)| if level % 2 == 1 { mirror(c) } else { c })
+        .collect();
+
+    // L2: from the highest level to the lowest odd level, reverse any
+    // contiguous sequence of characters at that level or higher.
+    let max_level = levels.iter().copied().max().unwrap_or(0);
+    let lowest_odd_level = paragraph_level | 1;
+    for level in (lowest_odd_level..=max_level).rev() {
+        let mut i = 0;
+        while i < chars.len() {
+            if levels[i] < level {
+                i += 1;
+                continue;
+            }
+            let start = i;
+            while i < chars.len() && levels[i] >= level {
+                i += 1;
+            }
+            chars[start..i].reverse();
+        }
+    }
+
+    Cow::Owned(chars.into_iter().collect())
+}
+
+/// How an Arabic character connects to its neighbours.
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Joining {
+    /// Doesn't connect to anything.
+    None,
+    /// Only connects to the preceding (right-hand) character.
+    Right,
+    /// Connects on both sides.
+    Dual,
+    /// Causes joining on both sides but has no forms of its own (tatweel).
+    Causing,
+    /// Ignored for joining purposes (vowel marks).
+    Transparent,
+}
+
+/// Joining type and first presentation form (isolated; followed by the final,
+/// initial and medial forms where applicable) of each letter in the range
+/// U+0621 to U+064A.
+const ARABIC_LETTERS: [(Joining, u16); 42] = {
+    use Joining::*;
+    [
+        (None, 0xFE80),  // HAMZA
+        (Right, 0xFE81), // ALEF WITH MADDA ABOVE
+        (Right, 0xFE83), // ALEF WITH HAMZA ABOVE
+        (Right, 0xFE85), // WAW WITH HAMZA ABOVE
+        (Right, 0xFE87), // ALEF WITH HAMZA BELOW
+        (Dual, 0xFE89),  // YEH WITH HAMZA ABOVE
+        (Right, 0xFE8D), // ALEF
+        (Dual, 0xFE8F),  // BEH
+        (Right, 0xFE93), // TEH MARBUTA
+        (Dual, 0xFE95),  // TEH
+        (Dual, 0xFE99),  // THEH
+        (Dual, 0xFE9D),  // JEEM
+        (Dual, 0xFEA1),  // HAH
+        (Dual, 0xFEA5),  // KHAH
+        (Right, 0xFEA9), // DAL
+        (Right, 0xFEAB), // THAL
+        (Right, 0xFEAD), // REH
+        (Right, 0xFEAF), // ZAIN
+        (Dual, 0xFEB1),  // SEEN
+        (Dual, 0xFEB5),  // SHEEN
+        (Dual, 0xFEB9),  // SAD
+        (Dual, 0xFEBD),  // DAD
+        (Dual, 0xFEC1),  // TAH
+        (Dual, 0xFEC5),  // ZAH
+        (Dual, 0xFEC9),  // AIN
+        (Dual, 0xFECD),  // GHAIN
+        (Dual, 0),       // KEHEH WITH TWO DOTS ABOVE
+        (Dual, 0),       // KEHEH WITH THREE DOTS BELOW
+        (Dual, 0),       // FARSI YEH WITH INVERTED V
+        (Dual, 0),       // FARSI YEH WITH TWO DOTS ABOVE
+        (Dual, 0),       // FARSI YEH WITH THREE DOTS ABOVE
+        (Causing, 0),    // TATWEEL
+        (Dual, 0xFED1),  // FEH
+        (Dual, 0xFED5),  // QAF
+        (Dual, 0xFED9),  // KAF
+        (Dual, 0xFEDD),  // LAM
+        (Dual, 0xFEE1),  // MEEM
+        (Dual, 0xFEE5),  // NOON
+        (Dual, 0xFEE9),  // HEH
+        (Right, 0xFEED), // WAW
+        (Right, 0xFEEF), // ALEF MAKSURA
+        (Dual, 0xFEF1),  // YEH
+    ]
+};
+
+fn arabic_letter(c: char) -> Option<(Joining, u16)> {
+    match c as u32 {
+        c @ 0x621..=0x64A => Some(ARABIC_LETTERS[(c - 0x621) as usize]),
+        0x64B..=0x65F | 0x670 => Some((Joining::Transparent, 0)),
+        _ => None,
+    }
+}
+
+fn joining(c: char) -> Joining {
+    arabic_letter(c).map_or(Joining::None, |(joining, _)| joining)
+}
+
+/// Isolated form of the lam-alef ligature for a given alef, if any. The final
+/// form follows it.
+fn lam_alef_ligature(alef: char) -> Option<u16> {
+    match alef {
+        '\u{622}' => Some(0xFEF5),
+        '\u{623}' => Some(0xFEF7),
+        '\u{625}' => Some(0xFEF9),
+        '\u{627}' => Some(0xFEFB),
+        _ => None,
+    }
+}
+
+/// Replace Arabic letters (in logical order) with their contextual forms.
+fn shape_arabic(chars: &[char]) -> Vec<char> {
+    // Find the nearest neighbour that isn't a vowel mark.
+    let neighbour = |indices: &mut dyn Iterator<Item = usize>| {
+        indices
+            .map(|i| chars[i])
+            .find(|&c| joining(c) != Joining::Transparent)
+    };
+
+    let mut shaped = Vec::with_capacity(chars.len());
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let Some((c_joining, first_form)) = arabic_letter(c) else {
+            shaped.push(c);
+            i += 1;
+            continue;
+        };
+        if first_form == 0 {
+            shaped.push(c);
+            i += 1;
+            continue;
+        }
+
+        let prev = neighbour(&mut (0..i).rev());
+        let joins_prev =
+            prev.is_some_and(|p| matches!(joining(p), Joining::Dual | Joining::Causing));
+
+        if c == '\u{644}' {
+            if let Some(ligature) = chars.get(i + 1).and_then(|&next| lam_alef_ligature(next)) {
+                let form = if joins_prev { ligature + 1 } else { ligature };
+                shaped.push(char::from_u32(form.into()).unwrap());
+                i += 2;
+                continue;
+            }
+        }
+
+        let next = neighbour(&mut (i + 1..chars.len()));
+        let joins_next = c_joining == Joining::Dual
+            && next.is_some_and(|n| {
+                matches!(
+                    joining(n),
+                    Joining::Right | Joining::Dual | Joining::Causing
+                )
+            });
+
+        let form_offset = match (c_joining, joins_prev, joins_next) {
+            (Joining::None, _, _) => 0,
+            (_, false, false) => 0, // isolated
+            (_, true, false) => 1,  // final
+            (_, false, true) => 2,  // initial
+            (_, true, true) => 3,   // medial
+        };
+        shaped.push(char::from_u32((first_form + form_offset).into()).unwrap());
+        i += 1;
+    }
+    shaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ltr_is_untouched() {
+        assert!(matches!(
+            to_visual_order("Hello, world! 123"),
+            Cow::Borrowed("Hello, world! 123")
+        ));
+        assert!(matches!(
+            to_visual_order("\u{65e5}\u{672c}"),
+            Cow::Borrowed(_)
+        ));
+    }
+
+    #[test]
+    fn hebrew_reordering() {
+        // "shalom" (שלום)
+        assert_eq!(
+            to_visual_order("\u{5e9}\u{5dc}\u{5d5}\u{5dd}"),
+            "\u{5dd}\u{5d5}\u{5dc}\u{5e9}"
+        );
+        // Numbers and Latin words keep their own direction.
+        assert_eq!(
+            to_visual_order("\u{5d0}\u{5d1} 123 abc"),
+            "abc 123 \u{5d1}\u{5d0}"
+        );
+        // Right-to-left run inside left-to-right text, with mirroring.
+        assert_eq!(
+            to_visual_order("x (\u{5d0}\u{5d1}) y"),
+            "x (\u{5d1}\u{5d0}) y"
+        );
+        assert_eq!(to_visual_order("\u{5d0} (b)"), "(b) \u{5d0}");
+    }
+
+    #[test]
+    fn arabic_shaping() {
+        // "bab" (باب): initial beh, final alef, isolated beh
+        assert_eq!(
+            to_visual_order("\u{628}\u{627}\u{628}"),
+            "\u{FE8F}\u{FE8E}\u{FE91}"
+        );
+        // "bbb": initial, medial, final
+        assert_eq!(
+            to_visual_order("\u{628}\u{628}\u{628}"),
+            "\u{FE90}\u{FE92}\u{FE91}"
+        );
+        // Vowel marks don't break joining.
+        assert_eq!(
+            shape_arabic(&['\u{628}', '\u{64E}', '\u{628}']),
+            ['\u{FE91}', '\u{64E}', '\u{FE90}']
+        );
+        // "la" (لا) is a ligature
+        assert_eq!(to_visual_order("\u{644}\u{627}"), "\u{FEFB}");
+        // Arabic-Indic digits stay left-to-right.
+        assert_eq!(
+            to_visual_order("\u{628} \u{661}\u{662}"),
+            "\u{661}\u{662} \u{FE8F}"
+        );
+    }
+}
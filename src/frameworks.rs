@@ -48,3 +48,11 @@ pub struct State {
     opengles: opengles::State,
     uikit: uikit::State,
 }
+impl State {
+    /// Forget allocations that won't exist once `mem` is restored, see
+    /// [crate::Environment::load_snapshot].
+    pub fn forget_pointers_not_in(&mut self, mem: &crate::mem::MemSnapshot) {
+        self.foundation.forget_pointers_not_in(mem);
+        self.opengles.forget_pointers_not_in(mem);
+    }
+}
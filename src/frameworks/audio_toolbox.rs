@@ -6,7 +6,8 @@
 //! The Audio Toolbox framework.
 
 use crate::audio::openal as al;
-use crate::audio::openal::alc_types::{ALCcontext, ALCdevice};
+use crate::audio::openal::alc_types::ALCcontext;
+use crate::audio::{open_output_device, OutputDevice};
 
 /// Macro for checking if an argument is null and returning `paramErr` if so.
 /// This seems to be what the real Audio Toolbox does, and some apps rely on it.
@@ -37,29 +38,61 @@ pub struct State {
     audio_queue: audio_queue::State,
     audio_components: audio_components::State,
     audio_session: audio_session::State,
-    al_device_and_context: Option<(*mut ALCdevice, *mut ALCcontext)>,
+    al_device_and_context: Option<(OutputDevice, *mut ALCcontext)>,
+    /// See [Self::set_muted].
+    muted: bool,
 }
 impl State {
     pub fn make_al_context_current(&mut self) -> ContextManager {
         if self.al_device_and_context.is_none() {
-            let device = unsafe { al::alcOpenDevice(std::ptr::null()) };
-            assert!(!device.is_null());
-            let context = unsafe { al::alcCreateContext(device, std::ptr::null()) };
+            let device = open_output_device();
+            let context = device.create_context();
             assert!(!context.is_null());
             log_dbg!(
                 "New internal OpenAL device ({:?}) and context ({:?})",
-                device,
+                device.raw,
                 context
             );
             self.al_device_and_context = Some((device, context));
+            if self.muted {
+                let _context_manager = ContextManager::make_active(context);
+                unsafe { al::alListenerf(al::AL_GAIN, 0.0) };
+            }
         }
         let (device, context) = self.al_device_and_context.unwrap();
-        assert!(!device.is_null() && !context.is_null());
+        assert!(!device.raw.is_null() && !context.is_null());
 
         // This object will make sure the existing context, which will belong
         // to the guest app, is restored once we're done.
         ContextManager::make_active(context)
     }
+
+    /// Mute or unmute the internal OpenAL context, including if it's created
+    /// later. See [crate::frameworks::openal::set_muted].
+    pub fn set_muted(&mut self, muted: bool) {
+        self.muted = muted;
+        if let Some((_device, context)) = self.al_device_and_context {
+            let _context_manager = ContextManager::make_active(context);
+            // The app can't change the gain of this context, so it's always 1.
+            let gain = if muted { 0.0 } else { 1.0 };
+            unsafe { al::alListenerf(al::AL_GAIN, gain) };
+        }
+    }
+
+    /// See [crate::frameworks::openal::render_silent_devices].
+    pub fn render_silent_device(&mut self) {
+        if let Some((ref mut device, _context)) = self.al_device_and_context {
+            device.render_if_silent();
+        }
+    }
+}
+
+/// Returns [true] if the app has any audio files, queues or units open.
+pub fn has_open_objects(env: &crate::Environment) -> bool {
+    let state = &env.framework_state.audio_toolbox;
+    !state.audio_file.audio_files.is_empty()
+        || state.audio_queue.has_queues()
+        || !state.audio_components.audio_component_instances.is_empty()
 }
 
 #[must_use]
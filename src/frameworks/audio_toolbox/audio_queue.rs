@@ -7,18 +7,23 @@
 //!
 //! The audio playback here is mapped onto OpenAL Soft for convenience.
 //! Apple's implementation probably uses Core Audio instead.
+//!
+//! Recording (input audio queues) uses the host's audio capture device, if
+//! allowed with the `--enable-microphone` option. Otherwise, silence is
+//! recorded.
 
 use crate::abi::{CallFromHost, GuestFunction};
-use crate::audio::decode_ima4;
 use crate::audio::openal as al;
 use crate::audio::openal::al_types::*;
+use crate::audio::{decode_ima4, set_source_pan};
 use crate::dyld::{export_c_func, FunctionExports};
 use crate::frameworks::audio_toolbox::ContextManager;
 use crate::frameworks::carbon_core::OSStatus;
 use crate::frameworks::core_audio_types::{
     debug_fourcc, fourcc, kAudioFormatAppleIMA4, kAudioFormatFlagIsBigEndian,
-    kAudioFormatFlagIsFloat, kAudioFormatFlagIsPacked, kAudioFormatLinearPCM,
-    AudioStreamBasicDescription,
+    kAudioFormatFlagIsFloat, kAudioFormatFlagIsPacked, kAudioFormatFlagIsSignedInteger,
+    kAudioFormatLinearPCM, kAudioTimeStampSampleTimeValid, AudioStreamBasicDescription,
+    AudioTimeStamp,
 };
 use crate::frameworks::core_foundation::cf_run_loop::{
     kCFRunLoopCommonModes, CFRunLoopGetMain, CFRunLoopMode, CFRunLoopRef,
@@ -29,8 +34,10 @@ use crate::mem::{
     guest_size_of, ConstPtr, ConstVoidPtr, GuestUSize, Mem, MutPtr, MutVoidPtr, Ptr, SafeRead,
 };
 use crate::objc::msg;
+use crate::window::AudioCapture;
 use crate::Environment;
 use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
 
 #[derive(Default)]
 pub struct State {
@@ -40,6 +47,9 @@ impl State {
     fn get(framework_state: &mut crate::frameworks::State) -> &mut Self {
         &mut framework_state.audio_toolbox.audio_queue
     }
+    pub fn has_queues(&self) -> bool {
+        !self.audio_queues.is_empty()
+    }
 }
 
 struct AudioQueueHostObject {
@@ -49,6 +59,8 @@ struct AudioQueueHostObject {
     /// Weak reference
     run_loop: CFRunLoopRef,
     volume: f32,
+    /// -1 is fully left, 1 is fully right.
+    pan: f32,
     buffers: Vec<AudioQueueBufferRef>,
     /// There is also a queue of OpenAL buffers, which must be kept in sync:
     /// the nth item in this queue must also be the nth item in the OpenAL
@@ -59,6 +71,18 @@ struct AudioQueueHostObject {
     al_unused_buffers: Vec<ALuint>,
     aq_is_running_proc: Option<AudioQueuePropertyListenerProc>,
     aq_is_running_user_data: Option<MutVoidPtr>,
+    /// Only present for input (recording) audio queues, which don't use
+    /// OpenAL.
+    input: Option<AudioQueueInput>,
+}
+
+struct AudioQueueInput {
+    /// Opened when the queue is started, closed when it is stopped.
+    capture: Option<Capture>,
+    /// Passed to the callback, so it's in guest memory.
+    time_stamp: MutPtr<AudioTimeStamp>,
+    /// Number of frames delivered to the app so far.
+    sample_time: u64,
 }
 
 /// Track whether the audio queue is meant to be running, in order to handle
@@ -98,9 +122,15 @@ pub type AudioQueueBufferRef = MutPtr<AudioQueueBuffer>;
 
 /// (*void)(void *in_user_data, AudioQueueRef in_aq, AudioQueueBufferRef in_buf)
 pub type AudioQueueOutputCallback = GuestFunction;
+/// (*void)(void *in_user_data, AudioQueueRef in_aq, AudioQueueBufferRef in_buf,
+///         const AudioTimeStamp *in_start_time,
+///         UInt32 in_number_packet_descriptions,
+///         const AudioStreamPacketDescription *in_packet_descs)
+pub type AudioQueueInputCallback = GuestFunction;
 
 type AudioQueueParameterID = u32;
 pub const kAudioQueueParam_Volume: AudioQueueParameterID = 1;
+pub const kAudioQueueParam_Pan: AudioQueueParameterID = 13;
 
 type AudioQueueParameterValue = f32;
 
@@ -113,6 +143,7 @@ type AudioQueuePropertyListenerProc = GuestFunction;
 const kAudioQueueErr_InvalidBuffer: OSStatus = -66687;
 const kAudioQueueErr_InvalidPropertySize: OSStatus = -66683;
 const kAudioQueueErr_BufferInQueue: OSStatus = -66679;
+const kAudioFormatUnsupportedDataFormatError: OSStatus = fourcc(b"fmt?") as _;
 
 pub fn AudioQueueNewOutput(
     env: &mut Environment,
@@ -126,22 +157,8 @@ pub fn AudioQueueNewOutput(
 ) -> OSStatus {
     // reserved
     assert!(in_flags == 0);
-    // NULL is a synonym of kCFRunLoopCommonModes here
-    assert!(
-        in_callback_run_loop_mode.is_null() || {
-            let common_modes = get_static_str(env, kCFRunLoopCommonModes);
-            msg![env; in_callback_run_loop_mode isEqualTo:common_modes]
-        }
-    );
-
-    let in_callback_run_loop = if in_callback_run_loop.is_null() {
-        // FIXME: According to the documentation, "one of the audio queue's
-        // internal threads" should be used if you don't specify a run loop.
-        // We should have an "internal thread" instead of using the main thread.
-        CFRunLoopGetMain(env)
-    } else {
-        in_callback_run_loop
-    };
+    let in_callback_run_loop =
+        callback_run_loop(env, in_callback_run_loop, in_callback_run_loop_mode);
 
     let mut format = env.mem.read(in_format);
     if env
@@ -170,6 +187,7 @@ pub fn AudioQueueNewOutput(
         callback_user_data: in_user_data,
         run_loop: in_callback_run_loop,
         volume: 1.0,
+        pan: 0.0,
         buffers: Vec::new(),
         buffer_queue: VecDeque::new(),
         is_running: AudioQueueIsRunning::Stopped,
@@ -177,15 +195,10 @@ pub fn AudioQueueNewOutput(
         al_unused_buffers: Vec::new(),
         aq_is_running_proc: None,
         aq_is_running_user_data: None,
+        input: None,
     };
 
-    let aq_ref = env.mem.alloc_and_write(OpaqueAudioQueue { _filler: 0 });
-    State::get(&mut env.framework_state)
-        .audio_queues
-        .insert(aq_ref, host_object);
-    env.mem.write(out_aq, aq_ref);
-
-    ns_run_loop::add_audio_queue(env, in_callback_run_loop, aq_ref);
+    let aq_ref = register_audio_queue(env, host_object, out_aq);
 
     log_if_broken_audio_format(&format);
 
@@ -202,6 +215,105 @@ pub fn AudioQueueNewOutput(
     0 // success
 }
 
+pub fn AudioQueueNewInput(
+    env: &mut Environment,
+    in_format: ConstPtr<AudioStreamBasicDescription>,
+    in_callback_proc: AudioQueueInputCallback,
+    in_user_data: MutVoidPtr,
+    in_callback_run_loop: CFRunLoopRef,
+    in_callback_run_loop_mode: CFRunLoopMode,
+    in_flags: u32,
+    out_aq: MutPtr<AudioQueueRef>,
+) -> OSStatus {
+    // reserved
+    assert!(in_flags == 0);
+
+    let format = env.mem.read(in_format);
+    if !is_supported_input_format(&format) {
+        log!(
+            "TODO: AudioQueueNewInput() for unsupported format {:#?}",
+            format
+        );
+        return kAudioFormatUnsupportedDataFormatError;
+    }
+
+    let in_callback_run_loop =
+        callback_run_loop(env, in_callback_run_loop, in_callback_run_loop_mode);
+
+    let time_stamp = env.mem.alloc_and_write(AudioTimeStamp::default());
+    let host_object = AudioQueueHostObject {
+        format,
+        callback_proc: in_callback_proc,
+        callback_user_data: in_user_data,
+        run_loop: in_callback_run_loop,
+        volume: 1.0,
+        pan: 0.0,
+        buffers: Vec::new(),
+        buffer_queue: VecDeque::new(),
+        is_running: AudioQueueIsRunning::Stopped,
+        al_source: None,
+        al_unused_buffers: Vec::new(),
+        aq_is_running_proc: None,
+        aq_is_running_user_data: None,
+        input: Some(AudioQueueInput {
+            capture: None,
+            time_stamp,
+            sample_time: 0,
+        }),
+    };
+
+    let aq_ref = register_audio_queue(env, host_object, out_aq);
+
+    log_dbg!(
+        "AudioQueueNewInput() for format {:#?}, new audio queue handle: {:?}",
+        format,
+        aq_ref,
+    );
+
+    0 // success
+}
+
+/// Get the run loop that an audio queue's callbacks should be called on.
+fn callback_run_loop(
+    env: &mut Environment,
+    in_callback_run_loop: CFRunLoopRef,
+    in_callback_run_loop_mode: CFRunLoopMode,
+) -> CFRunLoopRef {
+    // NULL is a synonym of kCFRunLoopCommonModes here
+    assert!(
+        in_callback_run_loop_mode.is_null() || {
+            let common_modes = get_static_str(env, kCFRunLoopCommonModes);
+            msg![env; in_callback_run_loop_mode isEqualTo:common_modes]
+        }
+    );
+
+    if in_callback_run_loop.is_null() {
+        // FIXME: According to the documentation, "one of the audio queue's
+        // internal threads" should be used if you don't specify a run loop.
+        // We should have an "internal thread" instead of using the main thread.
+        CFRunLoopGetMain(env)
+    } else {
+        in_callback_run_loop
+    }
+}
+
+fn register_audio_queue(
+    env: &mut Environment,
+    host_object: AudioQueueHostObject,
+    out_aq: MutPtr<AudioQueueRef>,
+) -> AudioQueueRef {
+    let run_loop = host_object.run_loop;
+    let aq_ref = env.mem.alloc_and_write(OpaqueAudioQueue { _filler: 0 });
+    State::get(&mut env.framework_state)
+        .audio_queues
+        .insert(aq_ref, host_object);
+    env.mem.write(out_aq, aq_ref);
+
+    ns_run_loop::add_audio_queue(env, run_loop, aq_ref);
+
+    aq_ref
+}
+
 pub fn AudioQueueGetParameter(
     env: &mut Environment,
     in_aq: AudioQueueRef,
@@ -210,12 +322,15 @@ pub fn AudioQueueGetParameter(
 ) -> OSStatus {
     return_if_null!(in_aq);
 
-    assert!(in_param_id == kAudioQueueParam_Volume); // others unimplemented
-
     let state = State::get(&mut env.framework_state);
     let host_object = state.audio_queues.get_mut(&in_aq).unwrap();
 
-    env.mem.write(out_value, host_object.volume);
+    let value = match in_param_id {
+        kAudioQueueParam_Volume => host_object.volume,
+        kAudioQueueParam_Pan => host_object.pan,
+        _ => unimplemented!("AudioQueueGetParameter() for {}", in_param_id),
+    };
+    env.mem.write(out_value, value);
 
     0 // success
 }
@@ -228,18 +343,24 @@ pub fn AudioQueueSetParameter(
 ) -> OSStatus {
     return_if_null!(in_aq);
 
-    assert!(in_param_id == kAudioQueueParam_Volume); // others unimplemented
-
     let state = State::get(&mut env.framework_state);
     let host_object = state.audio_queues.get_mut(&in_aq).unwrap();
 
-    host_object.volume = in_value;
+    match in_param_id {
+        kAudioQueueParam_Volume => host_object.volume = in_value,
+        kAudioQueueParam_Pan => host_object.pan = in_value,
+        _ => unimplemented!("AudioQueueSetParameter() for {}", in_param_id),
+    }
     if let Some(al_source) = host_object.al_source {
         let _context_manager = env.framework_state.audio_toolbox.make_al_context_current();
-        unsafe {
-            al::alSourcef(al_source, al::AL_MAX_GAIN, in_value);
-            assert!(al::alGetError() == 0);
+        match in_param_id {
+            kAudioQueueParam_Volume => unsafe {
+                al::alSourcef(al_source, al::AL_MAX_GAIN, in_value);
+            },
+            kAudioQueueParam_Pan => set_source_pan(al_source, in_value),
+            _ => unreachable!(),
         }
+        assert!(unsafe { al::alGetError() } == 0);
     }
 
     0 // success
@@ -475,6 +596,231 @@ pub fn is_supported_audio_format(format: &AudioStreamBasicDescription) -> bool {
     }
 }
 
+/// Check if the format requested for an input audio queue is one we can record
+/// in. Currently this is only 16-bit signed integer PCM, like the hardware.
+fn is_supported_input_format(format: &AudioStreamBasicDescription) -> bool {
+    let &AudioStreamBasicDescription {
+        sample_rate,
+        format_id,
+        format_flags,
+        frames_per_packet,
+        bytes_per_frame,
+        channels_per_frame,
+        bits_per_channel,
+        ..
+    } = format;
+    format_id == kAudioFormatLinearPCM
+        && sample_rate > 0.0
+        && (channels_per_frame == 1 || channels_per_frame == 2)
+        && bits_per_channel == 16
+        && frames_per_packet == 1
+        && bytes_per_frame == 2 * channels_per_frame
+        && (format_flags & kAudioFormatFlagIsSignedInteger) != 0
+        && (format_flags & kAudioFormatFlagIsBigEndian) == 0
+        && (format_flags & kAudioFormatFlagIsFloat) == 0
+}
+
+/// Source of recorded audio for input audio queues. This is a trait so that
+/// the buffer filling logic can be tested without a real device.
+trait CaptureSource {
+    /// Sample rate and channel count of the samples returned by [Self::read].
+    fn format(&self) -> (u32, u8);
+    /// Get the interleaved 16-bit samples recorded since the last call.
+    fn read(&mut self) -> Vec<i16>;
+    fn pause(&mut self);
+    fn resume(&mut self);
+}
+impl CaptureSource for AudioCapture {
+    fn format(&self) -> (u32, u8) {
+        AudioCapture::format(self)
+    }
+    fn read(&mut self) -> Vec<i16> {
+        AudioCapture::read(self)
+    }
+    fn pause(&mut self) {
+        AudioCapture::pause(self)
+    }
+    fn resume(&mut self) {
+        AudioCapture::resume(self)
+    }
+}
+
+/// Used when there's no microphone available: produces silence at the same
+/// rate a real device would produce samples.
+struct SilentCaptureSource {
+    sample_rate: u32,
+    channels: u8,
+    /// [None] if paused.
+    last_read: Option<Instant>,
+}
+impl CaptureSource for SilentCaptureSource {
+    fn format(&self) -> (u32, u8) {
+        (self.sample_rate, self.channels)
+    }
+    fn read(&mut self) -> Vec<i16> {
+        let Some(last_read) = self.last_read else {
+            return Vec::new();
+        };
+        let frames = (last_read.elapsed().as_secs_f64() * f64::from(self.sample_rate)) as u64;
+        // Only the time for whole frames is consumed, so there's no drift.
+        self.last_read =
+            Some(last_read + Duration::from_secs_f64(frames as f64 / f64::from(self.sample_rate)));
+        vec![0; frames as usize * usize::from(self.channels)]
+    }
+    fn pause(&mut self) {
+        self.last_read = None;
+    }
+    fn resume(&mut self) {
+        self.last_read.get_or_insert_with(Instant::now);
+    }
+}
+
+/// Open the source of recorded audio for an input audio queue, preferring the
+/// format the app asked for.
+fn open_capture_source(
+    env: &Environment,
+    format: &AudioStreamBasicDescription,
+) -> Box<dyn CaptureSource> {
+    let sample_rate = format.sample_rate as u32;
+    let channels = format.channels_per_frame as u8;
+    if !env.options.microphone_access {
+        log!("Warning: The app tried to record audio, but microphone access is disabled. Use the --enable-microphone option to allow this. Silence will be recorded instead.");
+    } else if let Some(window) = env.window.as_ref() {
+        match window.open_audio_capture(sample_rate, channels) {
+            Ok(capture) => return Box::new(capture),
+            Err(e) => log!(
+                "Warning: Couldn't open audio capture device ({}), silence will be recorded instead.",
+                e
+            ),
+        }
+    }
+    Box::new(SilentCaptureSource {
+        sample_rate,
+        channels,
+        last_read: None,
+    })
+}
+
+/// An open [CaptureSource] and an [InputConverter] for its format.
+struct Capture {
+    source: Box<dyn CaptureSource>,
+    converter: InputConverter,
+}
+impl Capture {
+    fn new(source: Box<dyn CaptureSource>, format: &AudioStreamBasicDescription) -> Capture {
+        let converter = InputConverter::new(source.format(), format);
+        Capture { source, converter }
+    }
+
+    /// Read newly recorded audio from the source, then fill as many buffers as
+    /// possible, given their capacities in bytes. Buffers are only ever filled
+    /// completely, so the result may have fewer items than `capacities`.
+    fn fill_buffers(&mut self, capacities: &[GuestUSize]) -> Vec<Vec<u8>> {
+        let samples = self.source.read();
+        self.converter.push(&samples);
+        capacities
+            .iter()
+            .map_while(|&capacity| self.converter.take_buffer(capacity))
+            .collect()
+    }
+}
+
+/// Converts recorded audio (interleaved 16-bit samples) from the capture
+/// source's sample rate and channel count to the ones the app asked for.
+struct InputConverter {
+    source_channels: usize,
+    dest_channels: usize,
+    /// Source frames per destination frame.
+    step: f64,
+    /// Source frames waiting to be resampled, already converted to the
+    /// destination channel count.
+    pending: VecDeque<i16>,
+    /// Position of the next destination frame relative to the first pending
+    /// frame. Linear interpolation is used for fractional positions.
+    position: f64,
+    /// Resampled samples that haven't been put in a buffer yet.
+    output: VecDeque<i16>,
+}
+impl InputConverter {
+    fn new(source_format: (u32, u8), dest_format: &AudioStreamBasicDescription) -> Self {
+        let (source_rate, source_channels) = source_format;
+        if f64::from(source_rate) != dest_format.sample_rate {
+            log_dbg!(
+                "Resampling recorded audio from {} Hz to {} Hz",
+                source_rate,
+                { dest_format.sample_rate }
+            );
+        }
+        InputConverter {
+            source_channels: source_channels.into(),
+            dest_channels: dest_format.channels_per_frame.try_into().unwrap(),
+            step: f64::from(source_rate) / dest_format.sample_rate,
+            pending: VecDeque::new(),
+            position: 0.0,
+            output: VecDeque::new(),
+        }
+    }
+
+    fn push(&mut self, samples: &[i16]) {
+        for frame in samples.chunks_exact(self.source_channels) {
+            if self.dest_channels == self.source_channels {
+                self.pending.extend(frame);
+            } else if self.dest_channels == 1 {
+                let sum: i32 = frame.iter().map(|&sample| i32::from(sample)).sum();
+                self.pending.push_back((sum / frame.len() as i32) as i16);
+            } else {
+                // Mono to stereo
+                self.pending
+                    .extend(std::iter::repeat(frame[0]).take(self.dest_channels));
+            }
+        }
+
+        let channels = self.dest_channels;
+        let frames = self.pending.len() / channels;
+        loop {
+            let index = self.position as usize;
+            let fraction = self.position - index as f64;
+            // The next frame is needed for interpolation, unless this is
+            // exactly on a frame.
+            if index >= frames || (fraction != 0.0 && index + 1 >= frames) {
+                break;
+            }
+            for channel in 0..channels {
+                let a = f64::from(self.pending[index * channels + channel]);
+                let sample = if fraction == 0.0 {
+                    a
+                } else {
+                    let b = f64::from(self.pending[(index + 1) * channels + channel]);
+                    a + (b - a) * fraction
+                };
+                self.output.push_back(sample.round() as i16);
+            }
+            self.position += self.step;
+        }
+
+        // Drop frames that won't be needed again.
+        let consumed = (self.position as usize).min(frames);
+        self.pending.drain(..consumed * channels);
+        self.position -= consumed as f64;
+    }
+
+    /// If there's enough audio to fill a buffer with the given capacity in
+    /// bytes, take it as little-endian 16-bit samples.
+    fn take_buffer(&mut self, capacity: GuestUSize) -> Option<Vec<u8>> {
+        let frame_size = self.dest_channels * 2;
+        let samples = capacity as usize / frame_size * self.dest_channels;
+        if samples == 0 || self.output.len() < samples {
+            return None;
+        }
+        Some(
+            self.output
+                .drain(..samples)
+                .flat_map(i16::to_le_bytes)
+                .collect(),
+        )
+    }
+}
+
 /// Decode an [AudioQueueBuffer] or [super::audio_unit::AudioBuffer]'s content
 /// to raw PCM suitable for an OpenAL buffer.
 pub fn decode_buffer(
@@ -603,7 +949,8 @@ fn prime_audio_queue(
     let state = State::get(&mut env.framework_state);
     let host_object = state.audio_queues.get_mut(&in_aq).unwrap();
 
-    if !is_supported_audio_format(&host_object.format) {
+    // Input audio queues have nothing to play.
+    if host_object.input.is_some() || !is_supported_audio_format(&host_object.format) {
         return context_manager;
     }
 
@@ -612,8 +959,11 @@ fn prime_audio_queue(
         unsafe {
             al::alGenSources(1, &mut al_source);
             al::alSourcef(al_source, al::AL_MAX_GAIN, host_object.volume);
-            assert!(al::alGetError() == 0);
         };
+        if host_object.pan != 0.0 {
+            set_source_pan(al_source, host_object.pan);
+        }
+        assert!(unsafe { al::alGetError() } == 0);
         host_object.al_source = Some(al_source);
     }
     let al_source = host_object.al_source.unwrap();
@@ -707,6 +1057,12 @@ fn unqueue_buffers<F: FnMut(ALuint)>(al_source: ALuint, mut callback: F) {
 /// For use by `NSRunLoop`: check the status of an audio queue, recycle buffers,
 /// call callbacks, push new buffers etc.
 pub fn handle_audio_queue(env: &mut Environment, in_aq: AudioQueueRef) {
+    let state = State::get(&mut env.framework_state);
+    if state.audio_queues[&in_aq].input.is_some() {
+        handle_input_audio_queue(env, in_aq);
+        return;
+    }
+
     // Collect used buffers and call the user callback so the app can provide
     // new buffers.
 
@@ -789,6 +1145,91 @@ pub fn handle_audio_queue(env: &mut Environment, in_aq: AudioQueueRef) {
     }
 }
 
+/// Counterpart of [handle_audio_queue] for input audio queues: fill buffers
+/// with recorded audio and pass them to the app's callback.
+fn handle_input_audio_queue(env: &mut Environment, in_aq: AudioQueueRef) {
+    let host_object = State::get(&mut env.framework_state)
+        .audio_queues
+        .get_mut(&in_aq)
+        .unwrap();
+
+    match host_object.is_running {
+        AudioQueueIsRunning::Running => (),
+        AudioQueueIsRunning::Stopping => {
+            // Partially-filled buffers aren't delivered, so there's nothing to
+            // wait for.
+            finish_stopping_audio_queue(env, in_aq);
+            return;
+        }
+        AudioQueueIsRunning::Stopped => return,
+    }
+
+    let input = host_object.input.as_mut().unwrap();
+    let Some(capture) = input.capture.as_mut() else {
+        return;
+    };
+
+    let capacities: Vec<GuestUSize> = host_object
+        .buffer_queue
+        .iter()
+        .map(|&buffer_ref| env.mem.read(buffer_ref).audio_data_bytes_capacity)
+        .collect();
+    let filled = capture.fill_buffers(&capacities);
+
+    let mut buffers_to_deliver = Vec::new();
+    for data in filled {
+        let buffer_ref = host_object.buffer_queue.pop_front().unwrap();
+        let mut buffer = env.mem.read(buffer_ref);
+        let size: GuestUSize = data.len().try_into().unwrap();
+        env.mem
+            .bytes_at_mut(buffer.audio_data.cast(), size)
+            .copy_from_slice(&data);
+        buffer.audio_data_byte_size = size;
+        env.mem.write(buffer_ref, buffer);
+
+        let start_time = input.sample_time;
+        let frames = u64::from(size / host_object.format.bytes_per_frame);
+        input.sample_time += frames;
+        buffers_to_deliver.push((buffer_ref, start_time));
+    }
+
+    let time_stamp = input.time_stamp;
+    let &mut AudioQueueHostObject {
+        callback_proc,
+        callback_user_data,
+        ..
+    } = host_object;
+
+    for (buffer_ref, start_time) in buffers_to_deliver {
+        log_dbg!(
+            "Delivering recorded buffer {:?} for queue {:?}. Calling callback {:?} with user data {:?}.",
+            buffer_ref,
+            in_aq,
+            callback_proc,
+            callback_user_data
+        );
+        env.mem.write(
+            time_stamp,
+            AudioTimeStamp {
+                sample_time: start_time as f64,
+                flags: kAudioTimeStampSampleTimeValid,
+                ..Default::default()
+            },
+        );
+        let () = callback_proc.call_from_host(
+            env,
+            (
+                callback_user_data,
+                in_aq,
+                buffer_ref,
+                time_stamp.cast_const(),
+                0u32,
+                ConstVoidPtr::null(),
+            ),
+        );
+    }
+}
+
 fn AudioQueuePrime(
     env: &mut Environment,
     in_aq: AudioQueueRef,
@@ -828,6 +1269,13 @@ pub fn AudioQueueStart(
 
     assert!(in_device_start_time.is_null()); // TODO
 
+    let state = State::get(&mut env.framework_state);
+    if state.audio_queues[&in_aq].input.is_some() {
+        start_input_audio_queue(env, in_aq);
+        notify_aq_is_running(env, in_aq);
+        return 0; // success
+    }
+
     let _context_manager = prime_audio_queue(env, in_aq, None);
 
     let host_object = State::get(&mut env.framework_state)
@@ -853,6 +1301,25 @@ pub fn AudioQueueStart(
     0 // success
 }
 
+fn start_input_audio_queue(env: &mut Environment, in_aq: AudioQueueRef) {
+    let host_object = &State::get(&mut env.framework_state).audio_queues[&in_aq];
+    let format = host_object.format;
+    let new_capture = if host_object.input.as_ref().unwrap().capture.is_none() {
+        Some(Capture::new(open_capture_source(env, &format), &format))
+    } else {
+        None
+    };
+
+    let host_object = State::get(&mut env.framework_state)
+        .audio_queues
+        .get_mut(&in_aq)
+        .unwrap();
+    let input = host_object.input.as_mut().unwrap();
+    let capture = input.capture.get_or_insert_with(|| new_capture.unwrap());
+    capture.source.resume();
+    host_object.is_running = AudioQueueIsRunning::Running;
+}
+
 pub fn AudioQueuePause(env: &mut Environment, in_aq: AudioQueueRef) -> OSStatus {
     return_if_null!(in_aq);
 
@@ -863,6 +1330,9 @@ pub fn AudioQueuePause(env: &mut Environment, in_aq: AudioQueueRef) -> OSStatus
     let host_object = state.audio_queues.get_mut(&in_aq).unwrap();
     // FIXME: is this correct? is it notifiable?
     host_object.is_running = AudioQueueIsRunning::Stopped;
+    if let Some(capture) = host_object.input.as_mut().and_then(|i| i.capture.as_mut()) {
+        capture.source.pause();
+    }
     if let Some(al_source) = host_object.al_source {
         unsafe { al::alSourcePause(al_source) };
         assert!(unsafe { al::alGetError() } == 0);
@@ -876,11 +1346,16 @@ fn finish_stopping_audio_queue(env: &mut Environment, in_aq: AudioQueueRef) {
     // of an asynchronous stop, where the audio queue stopping is triggered by
     // the OpenAL queue stopping.
     AudioQueueReset(env, in_aq);
-    State::get(&mut env.framework_state)
+    let host_object = State::get(&mut env.framework_state)
         .audio_queues
         .get_mut(&in_aq)
-        .unwrap()
-        .is_running = AudioQueueIsRunning::Stopped;
+        .unwrap();
+    host_object.is_running = AudioQueueIsRunning::Stopped;
+    if let Some(input) = host_object.input.as_mut() {
+        // Close the capture device so the host doesn't think the microphone is
+        // still in use.
+        input.capture = None;
+    }
     notify_aq_is_running(env, in_aq);
 }
 
@@ -1012,6 +1487,10 @@ pub fn AudioQueueDispose(
         env.mem.free(buffer_ptr.cast());
     }
 
+    if let Some(input) = host_object.input {
+        env.mem.free(input.time_stamp.cast());
+    }
+
     if let Some(al_source) = host_object.al_source {
         let _context_manager = env.framework_state.audio_toolbox.make_al_context_current();
 
@@ -1040,6 +1519,7 @@ pub fn AudioQueueDispose(
 
 pub const FUNCTIONS: FunctionExports = &[
     export_c_func!(AudioQueueNewOutput(_, _, _, _, _, _, _)),
+    export_c_func!(AudioQueueNewInput(_, _, _, _, _, _, _)),
     export_c_func!(AudioQueueGetParameter(_, _, _)),
     export_c_func!(AudioQueueSetParameter(_, _, _)),
     export_c_func!(AudioQueueAllocateBufferWithPacketDescriptions(_, _, _, _)),
@@ -1058,3 +1538,101 @@ pub const FUNCTIONS: FunctionExports = &[
     export_c_func!(AudioQueueFreeBuffer(_, _)),
     export_c_func!(AudioQueueDispose(_, _)),
 ];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct MockCaptureSource {
+        format: (u32, u8),
+        reads: VecDeque<Vec<i16>>,
+    }
+    impl CaptureSource for MockCaptureSource {
+        fn format(&self) -> (u32, u8) {
+            self.format
+        }
+        fn read(&mut self) -> Vec<i16> {
+            self.reads.pop_front().unwrap_or_default()
+        }
+        fn pause(&mut self) {}
+        fn resume(&mut self) {}
+    }
+
+    fn pcm16_format(sample_rate: f64, channels: u32) -> AudioStreamBasicDescription {
+        AudioStreamBasicDescription {
+            sample_rate,
+            format_id: kAudioFormatLinearPCM,
+            format_flags: kAudioFormatFlagIsSignedInteger | kAudioFormatFlagIsPacked,
+            bytes_per_packet: 2 * channels,
+            frames_per_packet: 1,
+            bytes_per_frame: 2 * channels,
+            channels_per_frame: channels,
+            bits_per_channel: 16,
+            _reserved: 0,
+        }
+    }
+
+    fn samples(bytes: &[u8]) -> Vec<i16> {
+        bytes
+            .chunks_exact(2)
+            .map(|pair| i16::from_le_bytes([pair[0], pair[1]]))
+            .collect()
+    }
+
+    #[test]
+    fn input_formats() {
+        assert!(is_supported_input_format(&pcm16_format(44100.0, 1)));
+        assert!(is_supported_input_format(&pcm16_format(8000.0, 2)));
+        let mut float_format = pcm16_format(44100.0, 1);
+        float_format.format_flags = kAudioFormatFlagIsFloat;
+        float_format.bits_per_channel = 32;
+        assert!(!is_supported_input_format(&float_format));
+        assert!(!is_supported_input_format(&pcm16_format(44100.0, 3)));
+    }
+
+    #[test]
+    fn capture_fills_whole_buffers() {
+        let source = MockCaptureSource {
+            format: (8000, 1),
+            reads: VecDeque::from([vec![1, 2, 3], vec![4, 5, 6, 7, 8], vec![9]]),
+        };
+        let mut capture = Capture::new(Box::new(source), &pcm16_format(8000.0, 1));
+
+        // Two 4-frame buffers queued, but only 3 frames recorded so far.
+        assert!(capture.fill_buffers(&[8, 8]).is_empty());
+        // 8 frames are now available: both buffers can be filled.
+        let filled = capture.fill_buffers(&[8, 8]);
+        assert_eq!(filled.len(), 2);
+        assert_eq!(samples(&filled[0]), [1, 2, 3, 4]);
+        assert_eq!(samples(&filled[1]), [5, 6, 7, 8]);
+        // The leftover frame is kept for the next buffer, which must be big
+        // enough to be filled by it.
+        assert!(capture.fill_buffers(&[4]).is_empty());
+        assert_eq!(samples(&capture.converter.take_buffer(2).unwrap()), [9]);
+    }
+
+    #[test]
+    fn capture_converts_format() {
+        // Stereo at 8 kHz, but the app wants mono at 16 kHz.
+        let source = MockCaptureSource {
+            format: (8000, 2),
+            reads: VecDeque::from([vec![0, 100, 200, 300, 400, 500]]),
+        };
+        let mut capture = Capture::new(Box::new(source), &pcm16_format(16000.0, 1));
+        let filled = capture.fill_buffers(&[10]);
+        assert_eq!(filled.len(), 1);
+        // Channels are averaged, then new frames are interpolated between the
+        // original ones.
+        assert_eq!(samples(&filled[0]), [50, 150, 250, 350, 450]);
+
+        // Mono at 16 kHz, but the app wants stereo at 8 kHz.
+        let source = MockCaptureSource {
+            format: (16000, 1),
+            reads: VecDeque::from([vec![10, 20, 30, 40]]),
+        };
+        let mut capture = Capture::new(Box::new(source), &pcm16_format(8000.0, 2));
+        let filled = capture.fill_buffers(&[8]);
+        assert_eq!(filled.len(), 1);
+        assert_eq!(samples(&filled[0]), [10, 10, 30, 30]);
+    }
+}
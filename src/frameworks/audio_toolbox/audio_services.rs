@@ -10,6 +10,7 @@ use crate::frameworks::carbon_core::OSStatus;
 use crate::frameworks::core_audio_types::fourcc;
 use crate::mem::{MutPtr, MutVoidPtr};
 use crate::Environment;
+use std::time::Duration;
 
 /// Usually a FourCC.
 type AudioServicesPropertyID = u32;
@@ -35,10 +36,16 @@ fn AudioServicesGetProperty(
     }
 }
 
-fn AudioServicesPlaySystemSound(_env: &mut Environment, in_system_sound_id: SystemSoundID) {
+fn AudioServicesPlaySystemSound(env: &mut Environment, in_system_sound_id: SystemSoundID) {
     assert_eq!(in_system_sound_id, kSystemSoundID_Vibrate);
-    log!("TODO: vibration (AudioServicesPlaySystemSound)");
     // TODO: implement other system sounds
+    if !env.options.haptics {
+        return;
+    }
+    if let Some(window) = env.window.as_mut() {
+        // The iPhone's vibration is a fixed-strength buzz of about this length.
+        window.vibrate(1.0, Duration::from_millis(400));
+    }
 }
 
 pub const FUNCTIONS: FunctionExports = &[
@@ -273,6 +273,19 @@ pub const CLASSES: ClassExports = objc_classes! {
     env.objc.dealloc_object(this, &mut env.mem)
 }
 
+- (NSTimeInterval)duration {
+    let Some(audio_file_id) = env.objc.borrow::<AVAudioPlayerHostObject>(this).audio_file_id else {
+        return 0.0;
+    };
+    audio_file::State::get(&mut env.framework_state)
+        .audio_files
+        .get_mut(&audio_file_id)
+        .unwrap()
+        .audio_file
+        .duration()
+        .as_secs_f64()
+}
+
 - (NSTimeInterval)currentTime {
     let host_object = env.objc.borrow::<AVAudioPlayerHostObject>(this);
     let current_time = if let Some(audio_desc) = host_object.audio_desc {
@@ -289,7 +302,7 @@ pub const CLASSES: ClassExports = objc_classes! {
     let host_object = env.objc.borrow_mut::<AVAudioPlayerHostObject>(this);
     host_object.set_current_time = currentTime;
     if let (Some(audio_desc), Some(audio_file_id)) = (host_object.audio_desc, host_object.audio_file_id) {
-        let total_packets = audio_file::State::get(&mut env.framework_state).audio_files.get(&audio_file_id).unwrap().audio_file.packet_count();
+        let total_packets = audio_file::State::get(&mut env.framework_state).audio_files.get_mut(&audio_file_id).unwrap().audio_file.packet_count();
         let total_frames = total_packets * audio_desc.frames_per_packet as u64;
         let new_current_frame = audio_desc.sample_rate * currentTime;
         if new_current_frame < 0.0 || new_current_frame > total_frames as f64 {
@@ -8,6 +8,7 @@
 //! Useful resources:
 //! - Apple's [Core Animation Programming Guide](https://developer.apple.com/library/archive/documentation/Cocoa/Conceptual/CoreAnimation_guide/Introduction/Introduction.html)
 
+pub mod ca_display_link;
 pub mod ca_eagl_layer;
 pub mod ca_layer;
 
@@ -0,0 +1,240 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `CADisplayLink`.
+//!
+//! A display link is much like a repeating `NSTimer`, except that it fires in
+//! step with the display's refresh rate (60Hz on the iPhone). The firing is
+//! done by `NSRunLoop`, see [handle_display_link].
+
+use crate::frameworks::core_foundation::time::CFTimeInterval;
+use crate::frameworks::foundation::ns_run_loop::{self, NSRunLoopMode};
+use crate::frameworks::foundation::{ns_string, NSInteger};
+use crate::objc::{
+    autorelease, id, msg, msg_class, msg_send, nil, objc_classes, release, retain, ClassExports,
+    HostObject, SEL,
+};
+use crate::Environment;
+use std::time::{Duration, Instant};
+
+/// Time between refreshes of the display (60Hz).
+const REFRESH_PERIOD: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+struct CADisplayLinkHostObject {
+    /// Strong reference
+    target: id,
+    selector: SEL,
+    /// Number of display refreshes between each firing.
+    frame_interval: NSInteger,
+    /// Time of the frame the display link last fired for, in seconds, using
+    /// the same clock as `mach_absolute_time()`.
+    timestamp: CFTimeInterval,
+    /// [None] if the display link isn't in a run loop.
+    due_by: Option<Instant>,
+    /// Weak reference
+    run_loop: id,
+}
+impl HostObject for CADisplayLinkHostObject {}
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+@implementation CADisplayLink: NSObject
+
++ (id)displayLinkWithTarget:(id)target
+                   selector:(SEL)selector {
+    retain(env, target);
+
+    let host_object = Box::new(CADisplayLinkHostObject {
+        target,
+        selector,
+        frame_interval: 1,
+        timestamp: 0.0,
+        due_by: None,
+        run_loop: nil,
+    });
+    let new = env.objc.alloc_object(this, host_object, &mut env.mem);
+
+    log_dbg!(
+        "New display link {:?}, target [{:?} {}]",
+        new,
+        target,
+        selector.as_str(&env.mem),
+    );
+
+    autorelease(env, new)
+}
+
+- (())dealloc {
+    let target = env.objc.borrow::<CADisplayLinkHostObject>(this).target;
+    release(env, target);
+    env.objc.dealloc_object(this, &mut env.mem)
+}
+
+- (())addToRunLoop:(id)run_loop // NSRunLoop*
+           forMode:(NSRunLoopMode)mode {
+    let default_mode = ns_string::get_static_str(env, ns_run_loop::NSDefaultRunLoopMode);
+    let common_modes = ns_string::get_static_str(env, ns_run_loop::NSRunLoopCommonModes);
+    // TODO: handle other modes
+    assert!(msg![env; mode isEqualToString:default_mode] || msg![env; mode isEqualToString:common_modes]);
+
+    let host_object = env.objc.borrow_mut::<CADisplayLinkHostObject>(this);
+    // TODO: support adding to several run loops or modes
+    assert!(host_object.run_loop == nil);
+    host_object.run_loop = run_loop;
+    host_object.due_by = Some(env.clock.now() + frame_duration(host_object.frame_interval));
+
+    log_dbg!("Adding display link {:?} to run loop {:?}", this, run_loop);
+    ns_run_loop::add_display_link(env, run_loop, this);
+}
+
+- (())removeFromRunLoop:(id)run_loop // NSRunLoop*
+                forMode:(NSRunLoopMode)_mode {
+    assert!(env.objc.borrow::<CADisplayLinkHostObject>(this).run_loop == run_loop);
+    () = msg![env; this invalidate];
+}
+
+- (())invalidate {
+    let host_object = env.objc.borrow_mut::<CADisplayLinkHostObject>(this);
+    host_object.due_by = None;
+    let run_loop = std::mem::replace(&mut host_object.run_loop, nil);
+    // Display link might already be invalid, don't try to remove it twice.
+    if run_loop != nil {
+        ns_run_loop::remove_display_link(env, run_loop, this);
+    }
+}
+
+- (NSInteger)frameInterval {
+    env.objc.borrow::<CADisplayLinkHostObject>(this).frame_interval
+}
+- (())setFrameInterval:(NSInteger)frame_interval {
+    if frame_interval < 1 {
+        log!(
+            "Warning: Ignoring invalid frame interval {} for display link {:?}",
+            frame_interval,
+            this
+        );
+        return;
+    }
+    env.objc.borrow_mut::<CADisplayLinkHostObject>(this).frame_interval = frame_interval;
+}
+
+- (CFTimeInterval)timestamp {
+    env.objc.borrow::<CADisplayLinkHostObject>(this).timestamp
+}
+- (CFTimeInterval)duration {
+    REFRESH_PERIOD.as_secs_f64()
+}
+
+// TODO: paused property
+
+@end
+
+};
+
+fn frame_duration(frame_interval: NSInteger) -> Duration {
+    REFRESH_PERIOD * frame_interval.try_into().unwrap()
+}
+
+/// Decide whether a display link that is due at `due_by` should fire at `now`.
+/// If it should, returns the time of the frame it fires for and when it is next
+/// due. Like with `NSTimer`, missed frames are skipped rather than caught up.
+fn advance_frame(due_by: Instant, now: Instant, interval: Duration) -> Option<(Instant, Instant)> {
+    if due_by > now {
+        return None;
+    }
+    let missed = now.duration_since(due_by).as_nanos() / interval.as_nanos();
+    let frame = due_by + interval * u32::try_from(missed).unwrap();
+    Some((frame, frame + interval))
+}
+
+/// For use by `NSRunLoop`: check if a display link is due to fire and fire it
+/// if necessary.
+///
+/// Returns the next firing time, if any.
+pub fn handle_display_link(env: &mut Environment, display_link: id) -> Option<Instant> {
+    let &CADisplayLinkHostObject {
+        target,
+        selector,
+        frame_interval,
+        due_by,
+        ..
+    } = env.objc.borrow(display_link);
+
+    // invalidated display links should have already been removed from the
+    // run loop
+    let due_by = due_by.unwrap();
+
+    let (frame, new_due_by) =
+        match advance_frame(due_by, env.clock.now(), frame_duration(frame_interval)) {
+            Some(result) => result,
+            None => return Some(due_by),
+        };
+
+    let timestamp = frame.duration_since(env.startup_time).as_secs_f64();
+    let host_object = env.objc.borrow_mut::<CADisplayLinkHostObject>(display_link);
+    host_object.timestamp = timestamp;
+    host_object.due_by = Some(new_due_by);
+
+    log_dbg!(
+        "Display link {:?} fired, sending {:?} message to {:?}",
+        display_link,
+        selector.as_str(&env.mem),
+        target
+    );
+
+    // The display link may be invalidated and released by its target.
+    retain(env, display_link);
+
+    let pool: id = msg_class![env; NSAutoreleasePool new];
+
+    // Signature should be `- (void)displayLinkDidFire:(CADisplayLink *)sender`.
+    let _: () = msg_send(env, (target, selector, display_link));
+
+    release(env, pool);
+    release(env, display_link);
+
+    Some(new_due_by)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Simulates a run loop that iterates once per display refresh, and checks
+    /// which iterations the display link fires on.
+    fn fired_iterations(frame_interval: NSInteger, iterations: &[u32]) -> Vec<u32> {
+        let start = Instant::now();
+        let interval = frame_duration(frame_interval);
+        let mut due_by = start + interval;
+        let mut fired = Vec::new();
+        for &i in iterations {
+            let now = start + REFRESH_PERIOD * i;
+            if let Some((frame, new_due_by)) = advance_frame(due_by, now, interval) {
+                assert!(frame <= now && now < new_due_by);
+                fired.push(i);
+                due_by = new_due_by;
+            }
+        }
+        fired
+    }
+
+    #[test]
+    fn fires_every_frame_interval() {
+        let iterations: Vec<u32> = (1..=8).collect();
+        assert_eq!(fired_iterations(1, &iterations), iterations);
+        assert_eq!(fired_iterations(2, &iterations), [2, 4, 6, 8]);
+        assert_eq!(fired_iterations(3, &iterations), [3, 6]);
+    }
+
+    #[test]
+    fn skips_missed_frames() {
+        // The run loop stalls between the 2nd and 7th refreshes: the display
+        // link fires once when it catches up, then stays on the original
+        // cadence.
+        assert_eq!(fired_iterations(2, &[1, 2, 7, 8, 9, 10]), [2, 7, 8, 10]);
+    }
+}
@@ -5,6 +5,7 @@
  */
 //! `CALayer`.
 
+use super::composition;
 use crate::dyld::{ConstantExports, HostConstant};
 use crate::frameworks::core_graphics::cg_bitmap_context::{
     CGBitmapContextCreate, CGBitmapContextGetHeight, CGBitmapContextGetWidth,
@@ -372,6 +373,10 @@ pub const CLASSES: ClassExports = objc_classes! {
     CGContextTranslateCTM(env, cg_context, origin.x, origin.y);
 }
 
+- (())renderInContext:(CGContextRef)context {
+    composition::render_in_context(env, this, context);
+}
+
 // CGImageRef*
 - (id)contents {
     env.objc.borrow::<CALayerHostObject>(this).contents
@@ -11,15 +11,24 @@
 
 use super::ca_eagl_layer::find_fullscreen_eagl_layer;
 use super::ca_layer::CALayerHostObject;
+use crate::frameworks::core_graphics::cg_affine_transform::CGAffineTransformIdentity;
+use crate::frameworks::core_graphics::cg_bitmap_context::CGBitmapContextDrawer;
+use crate::frameworks::core_graphics::cg_context::{
+    CGContextConcatCTM, CGContextDrawImage, CGContextFillRect, CGContextRef,
+    CGContextRestoreGState, CGContextSaveGState, CGContextSetRGBFillColor, CGContextTranslateCTM,
+};
 use crate::frameworks::core_graphics::{
     cg_bitmap_context, cg_color, cg_image, CGFloat, CGPoint, CGRect, CGSize,
 };
+use crate::frameworks::opengles::eagl;
 use crate::gles::gles11_raw as gles11; // constants only
 use crate::gles::gles11_raw::types::*;
-use crate::gles::present::{present_frame, FpsCounter};
+use crate::gles::present::{present_frame, save_screenshot, FpsCounter};
 use crate::gles::GLES;
+use crate::image::Image;
 use crate::mem::Mem;
-use crate::objc::{id, msg, msg_class, nil, ObjC};
+use crate::objc::{id, msg, msg_class, nil, Class, ObjC};
+use crate::options::ScaleHack;
 use crate::Environment;
 use std::time::{Duration, Instant};
 
@@ -67,7 +76,7 @@ pub fn recomposite_if_necessary(env: &mut Environment) -> Option<Instant> {
             .count_frame(format_args!("Core Animation compositor"));
     }
 
-    let now = Instant::now();
+    let now = env.clock.now();
     let interval = 1.0 / 60.0; // 60Hz
     let new_recomposite_next = if let Some(recomposite_next) = env
         .framework_state
@@ -111,14 +120,15 @@ pub fn recomposite_if_necessary(env: &mut Environment) -> Option<Instant> {
         let screen: id = msg_class![env; UIScreen mainScreen];
         msg![env; screen bounds]
     };
-    let scale_hack: u32 = env.options.scale_hack.get();
-    let fb_width = screen_bounds.size.width as u32 * scale_hack;
-    let fb_height = screen_bounds.size.height as u32 * scale_hack;
+    let scale_hack = env.options.scale_hack;
+    let fb_width = scale_hack.scale(screen_bounds.size.width as i32) as u32;
+    let fb_height = scale_hack.scale(screen_bounds.size.height as i32) as u32;
     let present_frame_args = (
         env.window().viewport(),
         env.window().rotation_matrix(),
         env.window().virtual_cursor_visible_at(),
     );
+    let take_screenshot = env.window().take_screenshot_request();
 
     // TODO: draw status bar if it's not hidden
 
@@ -242,6 +252,9 @@ pub fn recomposite_if_necessary(env: &mut Environment) -> Option<Instant> {
             present_frame_args.1,
             present_frame_args.2,
         );
+        if take_screenshot {
+            save_screenshot(gles, present_frame_args.0);
+        }
     }
     env.window().swap_window();
 
@@ -274,6 +287,112 @@ fn display_layers(env: &mut Environment, root_layer: id) {
     }
 }
 
+/// Implementation of `renderInContext:`: draws a layer and its sublayers into a
+/// `CGContextRef`, in the coordinate space of the layer's bounds.
+///
+/// This is a software equivalent of [composite_layer_recursive]. The content of
+/// a `CAEAGLLayer` is read back from its renderbuffer with `glReadPixels()`.
+pub(super) fn render_in_context(env: &mut Environment, layer: id, context: CGContextRef) {
+    // Ensure layer bitmaps are up to date.
+    display_layers(env, layer);
+
+    render_layer_in_context_recursive(env, layer, context);
+}
+
+/// Traverses the layer tree and draws each layer into a `CGContextRef`.
+fn render_layer_in_context_recursive(env: &mut Environment, layer: id, context: CGContextRef) {
+    // TODO: opacity, masksToBounds, layer transforms
+
+    let host_obj = env.objc.borrow::<CALayerHostObject>(layer);
+    if host_obj.hidden {
+        return;
+    }
+    let bounds = host_obj.bounds;
+    let background_color = host_obj.background_color;
+    let contents = host_obj.contents;
+    let cg_context = host_obj.cg_context;
+    let opaque = host_obj.opaque;
+
+    // Draw background color, if any
+    if background_color != nil {
+        let (r, g, b, a) = cg_color::to_rgba(&env.objc, background_color);
+        CGContextSaveGState(env, context);
+        CGContextSetRGBFillColor(env, context, r, g, b, a);
+        CGContextFillRect(env, context, bounds);
+        CGContextRestoreGState(env, context);
+    }
+
+    let ca_eagl_layer_class: Class = msg_class![env; CAEAGLLayer class];
+    let pixels = if msg![env; layer isKindOfClass:ca_eagl_layer_class] {
+        // Prefer the current content of the renderbuffer, but if that isn't
+        // available, use whatever was last presented (slow path).
+        eagl::read_drawable_pixels(env, layer).or_else(|| {
+            env.objc
+                .borrow::<CALayerHostObject>(layer)
+                .presented_pixels
+                .clone()
+        })
+    } else if contents != nil {
+        None
+    } else if let Some(cg_context) = cg_context {
+        // Make sure this is in sync with the code in ca_layer.rs that
+        // sets up the context!
+        let (width, height, data) = cg_bitmap_context::get_data(&env.objc, cg_context);
+        let pixels = env.mem.bytes_at(data.cast(), width * height * 4).to_vec();
+        Some((pixels, width, height))
+    } else {
+        None
+    };
+
+    if let Some((mut pixels, width, height)) = pixels {
+        // See composite_layer_recursive for why this is done.
+        if opaque {
+            for pixel in pixels.chunks_exact_mut(4) {
+                pixel[3] = 255;
+            }
+        }
+        // These pixels have bottom-to-top rows, so they are drawn upright in
+        // the layer's y-down coordinate space.
+        let image = Image::from_pixel_vec(pixels, (width, height));
+        CGBitmapContextDrawer::new(&env.objc, &mut env.mem, context).draw_image(bounds, &image);
+    } else if contents != nil {
+        // Normal images have top-to-bottom rows, so flip them.
+        CGContextSaveGState(env, context);
+        CGContextConcatCTM(
+            env,
+            context,
+            CGAffineTransformIdentity
+                .translate(0.0, 2.0 * bounds.origin.y + bounds.size.height)
+                .scale(1.0, -1.0),
+        );
+        CGContextDrawImage(env, context, bounds, contents);
+        CGContextRestoreGState(env, context);
+    }
+
+    let sublayers = env
+        .objc
+        .borrow::<CALayerHostObject>(layer)
+        .sublayers
+        .clone();
+    for sublayer in sublayers {
+        let &CALayerHostObject {
+            bounds: sub_bounds,
+            position,
+            anchor_point,
+            ..
+        } = env.objc.borrow(sublayer);
+        CGContextSaveGState(env, context);
+        CGContextTranslateCTM(
+            env,
+            context,
+            position.x - sub_bounds.size.width * anchor_point.x - sub_bounds.origin.x,
+            position.y - sub_bounds.size.height * anchor_point.y - sub_bounds.origin.y,
+        );
+        render_layer_in_context_recursive(env, sublayer, context);
+        CGContextRestoreGState(env, context);
+    }
+}
+
 /// Traverses the layer tree and draws each layer.
 unsafe fn composite_layer_recursive(
     gles: &mut dyn GLES,
@@ -283,7 +402,7 @@ unsafe fn composite_layer_recursive(
     origin: CGPoint,
     clip_to: CGRect,
     opacity: CGFloat,
-    scale_hack: u32,
+    scale_hack: ScaleHack,
     fb_height: u32,
 ) {
     // TODO: this can't handle zPosition, non-AABB layer transforms, rounded
@@ -501,13 +620,14 @@ fn clip_rects(a_clip: CGRect, b_clip: CGRect) -> CGRect {
 
 fn gl_rect_from_cg_rect(
     rect: CGRect,
-    scale_hack: u32,
+    scale_hack: ScaleHack,
     fb_height: u32,
 ) -> (GLint, GLint, GLint, GLint) {
-    let x = (rect.origin.x * scale_hack as f32).round() as GLint;
-    let y = (rect.origin.y * scale_hack as f32).round() as GLint;
-    let w = (rect.size.width * scale_hack as f32).round() as GLint;
-    let h = (rect.size.height * scale_hack as f32).round() as GLint;
+    let scale_hack = scale_hack.get();
+    let x = (rect.origin.x * scale_hack).round() as GLint;
+    let y = (rect.origin.y * scale_hack).round() as GLint;
+    let w = (rect.size.width * scale_hack).round() as GLint;
+    let h = (rect.size.height * scale_hack).round() as GLint;
     // y points up in OpenGL ES, but down in UIKit and Core Animation
     (x, fb_height as GLint - h - y, w, h)
 }
@@ -79,6 +79,23 @@ impl std::fmt::Debug for AudioStreamBasicDescription {
     }
 }
 
+#[derive(Copy, Clone, Default)]
+#[repr(C, packed)]
+pub struct AudioTimeStamp {
+    pub sample_time: f64,
+    pub host_time: u64,
+    pub rate_scalar: f64,
+    pub word_clock_time: u64,
+    /// Should be a `SMPTETime`, but that's not implemented yet.
+    pub _smpte_time: [u8; 24],
+    pub flags: AudioTimeStampFlags,
+    pub _reserved: u32,
+}
+unsafe impl SafeRead for AudioTimeStamp {}
+
+pub type AudioTimeStampFlags = u32;
+pub const kAudioTimeStampSampleTimeValid: AudioTimeStampFlags = 1 << 0;
+
 /// Usually a FourCC.
 pub type AudioFormatID = u32;
 pub const kAudioFormatLinearPCM: AudioFormatID = fourcc(b"lpcm");
@@ -23,6 +23,7 @@ pub mod cf_data;
 pub mod cf_dictionary;
 pub mod cf_locale;
 pub mod cf_run_loop;
+pub mod cf_run_loop_source;
 pub mod cf_run_loop_timer;
 pub mod cf_string;
 pub mod cf_type;
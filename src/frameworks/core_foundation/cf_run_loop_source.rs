@@ -0,0 +1,230 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `CFRunLoopSource`.
+//!
+//! Only version 0 sources (signalled manually by the app, with a `perform`
+//! callback) are supported. There's no `NSRunLoop` equivalent, so this is
+//! implemented as a private class.
+
+use crate::abi::{CallFromHost, GuestFunction};
+use crate::dyld::{export_c_func, FunctionExports};
+use crate::frameworks::core_foundation::cf_allocator::{kCFAllocatorDefault, CFAllocatorRef};
+use crate::frameworks::core_foundation::cf_run_loop::{CFRunLoopMode, CFRunLoopRef};
+use crate::frameworks::core_foundation::CFIndex;
+use crate::frameworks::foundation::ns_run_loop;
+use crate::mem::{MutPtr, MutVoidPtr, SafeRead};
+use crate::objc::{
+    id, msg_class, objc_classes, release, retain, ClassExports, HostObject, NSZonePtr,
+};
+use crate::Environment;
+
+pub type CFRunLoopSourceRef = super::CFTypeRef;
+
+#[repr(C, packed)]
+pub struct CFRunLoopSourceContext {
+    version: CFIndex,
+    info: MutVoidPtr,
+    // const void *(*retain)(const void *info);
+    retain_callback: GuestFunction,
+    // void (*release)(const void *info);
+    release_callback: GuestFunction,
+    copy_desc_callback: GuestFunction,
+    equal_callback: GuestFunction,
+    hash_callback: GuestFunction,
+    // void (*schedule)(void *info, CFRunLoopRef rl, CFRunLoopMode mode);
+    schedule_callback: GuestFunction,
+    // void (*cancel)(void *info, CFRunLoopRef rl, CFRunLoopMode mode);
+    cancel_callback: GuestFunction,
+    // void (*perform)(void *info);
+    perform_callback: GuestFunction,
+}
+unsafe impl SafeRead for CFRunLoopSourceContext {}
+
+/// Belongs to _touchHLE_CFRunLoopSource
+struct CFRunLoopSourceHostObject {
+    info: MutVoidPtr,
+    release_callback: Option<GuestFunction>,
+    schedule_callback: Option<GuestFunction>,
+    cancel_callback: Option<GuestFunction>,
+    perform_callback: Option<GuestFunction>,
+    /// The run loop and mode the source was added to, if any. Weak reference.
+    scheduled_in: Option<(CFRunLoopRef, CFRunLoopMode)>,
+    signaled: bool,
+    valid: bool,
+}
+impl HostObject for CFRunLoopSourceHostObject {}
+
+fn non_null(callback: GuestFunction) -> Option<GuestFunction> {
+    (!callback.to_ptr().is_null()).then_some(callback)
+}
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+@implementation _touchHLE_CFRunLoopSource: NSObject
+
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::new(CFRunLoopSourceHostObject {
+        info: MutVoidPtr::null(),
+        release_callback: None,
+        schedule_callback: None,
+        cancel_callback: None,
+        perform_callback: None,
+        scheduled_in: None,
+        signaled: false,
+        valid: true,
+    });
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
+- (())dealloc {
+    let &CFRunLoopSourceHostObject {
+        info,
+        release_callback,
+        ..
+    } = env.objc.borrow(this);
+    if let Some(release_callback) = release_callback {
+        () = release_callback.call_from_host(env, (info,));
+    }
+    env.objc.dealloc_object(this, &mut env.mem)
+}
+
+@end
+
+};
+
+fn CFRunLoopSourceCreate(
+    env: &mut Environment,
+    allocator: CFAllocatorRef,
+    order: CFIndex,
+    context_ptr: MutPtr<CFRunLoopSourceContext>,
+) -> CFRunLoopSourceRef {
+    assert_eq!(allocator, kCFAllocatorDefault); // unimplemented
+    assert_eq!(order, 0); // TODO
+
+    let context = env.mem.read(context_ptr);
+    let version = context.version;
+    assert_eq!(version, 0); // TODO: version 1 (Mach port) sources
+
+    let mut info = context.info;
+    if let Some(retain_callback) = non_null(context.retain_callback) {
+        info = retain_callback.call_from_host(env, (info,));
+    }
+
+    let source: id = msg_class![env; _touchHLE_CFRunLoopSource alloc];
+    let host_object = env.objc.borrow_mut::<CFRunLoopSourceHostObject>(source);
+    host_object.info = info;
+    host_object.release_callback = non_null(context.release_callback);
+    host_object.schedule_callback = non_null(context.schedule_callback);
+    host_object.cancel_callback = non_null(context.cancel_callback);
+    host_object.perform_callback = non_null(context.perform_callback);
+    log_dbg!("CFRunLoopSourceCreate() => {:?}", source);
+    source
+}
+
+fn CFRunLoopSourceSignal(env: &mut Environment, source: CFRunLoopSourceRef) {
+    let host_object = env.objc.borrow_mut::<CFRunLoopSourceHostObject>(source);
+    if host_object.valid {
+        host_object.signaled = true;
+    }
+}
+
+fn CFRunLoopSourceIsValid(env: &mut Environment, source: CFRunLoopSourceRef) -> bool {
+    env.objc.borrow::<CFRunLoopSourceHostObject>(source).valid
+}
+
+fn CFRunLoopSourceInvalidate(env: &mut Environment, source: CFRunLoopSourceRef) {
+    if !CFRunLoopSourceIsValid(env, source) {
+        return;
+    }
+    if let Some((run_loop, mode)) = env
+        .objc
+        .borrow::<CFRunLoopSourceHostObject>(source)
+        .scheduled_in
+    {
+        CFRunLoopRemoveSource(env, run_loop, source, mode);
+    }
+    let host_object = env.objc.borrow_mut::<CFRunLoopSourceHostObject>(source);
+    host_object.valid = false;
+    host_object.signaled = false;
+}
+
+fn CFRunLoopAddSource(
+    env: &mut Environment,
+    run_loop: CFRunLoopRef,
+    source: CFRunLoopSourceRef,
+    mode: CFRunLoopMode,
+) {
+    let host_object = env.objc.borrow::<CFRunLoopSourceHostObject>(source);
+    if !host_object.valid || host_object.scheduled_in.is_some() {
+        // TODO: support adding a source to several modes or run loops
+        return;
+    }
+    let schedule_callback = host_object.schedule_callback;
+    let info = host_object.info;
+
+    // TODO: handle modes
+    ns_run_loop::add_source(env, run_loop, source);
+    env.objc
+        .borrow_mut::<CFRunLoopSourceHostObject>(source)
+        .scheduled_in = Some((run_loop, mode));
+
+    if let Some(schedule_callback) = schedule_callback {
+        () = schedule_callback.call_from_host(env, (info, run_loop, mode));
+    }
+}
+
+fn CFRunLoopRemoveSource(
+    env: &mut Environment,
+    run_loop: CFRunLoopRef,
+    source: CFRunLoopSourceRef,
+    mode: CFRunLoopMode,
+) {
+    let host_object = env.objc.borrow_mut::<CFRunLoopSourceHostObject>(source);
+    if host_object.scheduled_in.map(|(rl, _)| rl) != Some(run_loop) {
+        return;
+    }
+    host_object.scheduled_in = None;
+    let cancel_callback = host_object.cancel_callback;
+    let info = host_object.info;
+
+    // Keep the source alive while the callback runs, since the run loop might
+    // have held the last reference.
+    retain(env, source);
+    ns_run_loop::remove_source(env, run_loop, source);
+    if let Some(cancel_callback) = cancel_callback {
+        () = cancel_callback.call_from_host(env, (info, run_loop, mode));
+    }
+    release(env, source);
+}
+
+/// For use by `NSRunLoop`: perform the source's callback if it has been
+/// signalled. Returns [true] if the source was handled.
+pub fn handle_source(env: &mut Environment, source: CFRunLoopSourceRef) -> bool {
+    let host_object = env.objc.borrow_mut::<CFRunLoopSourceHostObject>(source);
+    if !host_object.valid || !host_object.signaled {
+        return false;
+    }
+    host_object.signaled = false;
+    let perform_callback = host_object.perform_callback;
+    let info = host_object.info;
+
+    log_dbg!("Handling run loop source {:?}", source);
+    if let Some(perform_callback) = perform_callback {
+        () = perform_callback.call_from_host(env, (info,));
+    }
+    true
+}
+
+pub const FUNCTIONS: FunctionExports = &[
+    export_c_func!(CFRunLoopSourceCreate(_, _, _)),
+    export_c_func!(CFRunLoopSourceSignal(_)),
+    export_c_func!(CFRunLoopSourceIsValid(_)),
+    export_c_func!(CFRunLoopSourceInvalidate(_)),
+    export_c_func!(CFRunLoopAddSource(_, _, _)),
+    export_c_func!(CFRunLoopRemoveSource(_, _, _)),
+];
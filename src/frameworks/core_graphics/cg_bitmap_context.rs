@@ -9,15 +9,16 @@ use super::cg_affine_transform::{CGAffineTransform, CGAffineTransformIdentity};
 use super::cg_color_space::{
     kCGColorSpaceGenericGray, kCGColorSpaceGenericRGB, CGColorSpaceHostObject, CGColorSpaceRef,
 };
-use super::cg_context::{CGContextHostObject, CGContextRef, CGContextSubclass};
+use super::cg_context::{kCGTextFill, CGContextHostObject, CGContextRef, CGContextSubclass};
 use super::cg_image::{
     self, kCGBitmapAlphaInfoMask, kCGBitmapByteOrderMask, kCGImageAlphaFirst, kCGImageAlphaLast,
     kCGImageAlphaNone, kCGImageAlphaNoneSkipFirst, kCGImageAlphaNoneSkipLast, kCGImageAlphaOnly,
     kCGImageAlphaPremultipliedFirst, kCGImageAlphaPremultipliedLast, kCGImageByteOrder32Big,
     kCGImageByteOrderDefault, CGBitmapInfo, CGImageAlphaInfo, CGImageRef,
 };
-use super::{CGFloat, CGPoint, CGRect};
+use super::{CGFloat, CGPoint, CGRect, CGSize};
 use crate::dyld::{export_c_func, FunctionExports};
+use crate::font::{Font, TextAlignment};
 use crate::image::{gamma_decode, gamma_encode, Image};
 use crate::mem::{GuestUSize, Mem, MutVoidPtr};
 use crate::objc::ObjC;
@@ -84,6 +85,11 @@ pub fn CGBitmapContextCreate(
         rgb_fill_color: (0.0, 0.0, 0.0, 0.0),
         transform: CGAffineTransformIdentity,
         state_stack: Vec::new(),
+        font_name: None,
+        // TODO: is this the correct default?
+        font_size: 12.0,
+        text_matrix: CGAffineTransformIdentity,
+        text_drawing_mode: kCGTextFill,
     };
     let isa = env
         .objc
@@ -459,13 +465,87 @@ impl CGBitmapContextDrawer<'_> {
             })
         })
     }
+
+    /// Draw an image into `rect`, as `CGContextDrawImage` does.
+    ///
+    /// Note that because the image's rows are top-to-bottom, but the bitmap's
+    /// are bottom-to-top, in a context with y pointing down (like UIKit's) the
+    /// image is drawn upside-down. Conversely, that means pixels with
+    /// bottom-to-top rows (e.g. from `glReadPixels()`) are drawn upright.
+    pub fn draw_image(&mut self, rect: CGRect, image: &Image) {
+        let (image_width, image_height) = image.dimensions();
+
+        // TODO: non-nearest-neighbour filtering? (what does CG actually do?)
+
+        for ((x, y), (texel_x, texel_y)) in self.iter_transformed_pixels(rect) {
+            let texel_x = (image_width as f32 * texel_x) as i32;
+            // Image is in top-to-bottom order, but the bitmap is bottom-to-top
+            let texel_y = (image_height as f32 * (1.0 - texel_y)) as i32;
+            // FIXME: might need alpha format conversion here
+            if let Some(color) = image.get_pixel((texel_x, texel_y)) {
+                self.put_pixel((x, y), color, /* blend: */ true)
+            }
+        }
+    }
+
+    /// Fill a line of text, as `CGContextShowText` does: the baseline starts
+    /// at the origin of the text space given by `text_matrix`, which is in
+    /// turn transformed by the current transform. Returns the width of the
+    /// text in text space, which is how far the text position should advance.
+    pub fn show_text(
+        &mut self,
+        font: &Font,
+        font_size: CGFloat,
+        text_matrix: CGAffineTransform,
+        text: &str,
+    ) -> CGFloat {
+        // Font::draw lays out text with y pointing down from the top of the
+        // line, but text space has y pointing up from the baseline. Offsetting
+        // the origin by the ascent puts the baseline at y = 0, so the rest is
+        // just a flip.
+        let flip = CGAffineTransformIdentity.scale(1.0, -1.0);
+        let ctm = self.transform;
+        self.transform = flip.concat(text_matrix).concat(ctm);
+
+        let fill_color = self.rgb_fill_color();
+        let ascent = font.ascent(font_size);
+        font.draw(
+            font_size,
+            text,
+            (0.0, -ascent),
+            /* wrap: */ None,
+            TextAlignment::Left,
+            |raster_glyph| {
+                let (x, y) = raster_glyph.origin();
+                let (width, height) = raster_glyph.dimensions();
+                let glyph_rect = CGRect {
+                    origin: CGPoint { x, y },
+                    size: CGSize {
+                        width: width as f32,
+                        height: height as f32,
+                    },
+                };
+                for ((x, y), (tex_x, tex_y)) in self.iter_transformed_pixels(glyph_rect) {
+                    // TODO: bilinear sampling
+                    let coverage = raster_glyph.pixel_at((
+                        (tex_x * glyph_rect.size.width - 0.5).round() as i32,
+                        (tex_y * glyph_rect.size.height - 0.5).round() as i32,
+                    ));
+                    let (r, g, b, a) = fill_color;
+                    let color = (r * coverage, g * coverage, b * coverage, a * coverage);
+                    self.put_pixel((x, y), color, /* blend: */ true);
+                }
+            },
+        );
+
+        self.transform = ctm;
+        font.calculate_text_size(font_size, text, None).0
+    }
 }
 
 #[cfg(test)]
 #[test]
 fn test_iter_transformed_pixels() {
-    use super::CGSize;
-
     fn make_context(
         width: GuestUSize,
         height: GuestUSize,
@@ -557,6 +637,119 @@ fn test_iter_transformed_pixels() {
         .eq(inverted_square_2x2_at_0_0.clone().into_iter()));
 }
 
+#[cfg(test)]
+#[test]
+fn test_show_text() {
+    let (width, height) = (64, 32);
+    let mut pixels = vec![0u8; (width * height * 4) as usize];
+    let mut drawer = CGBitmapContextDrawer {
+        bitmap_info: CGBitmapContextData {
+            data: crate::mem::Ptr::null(),
+            data_is_owned: false,
+            width,
+            height,
+            bits_per_component: 8,
+            bytes_per_row: 4 * width,
+            color_space: kCGColorSpaceGenericRGB,
+            alpha_info: kCGImageAlphaPremultipliedLast,
+        },
+        rgb_fill_color: (1.0, 1.0, 1.0, 1.0),
+        transform: CGAffineTransformIdentity,
+        pixels: &mut pixels,
+    };
+
+    // Baseline starts at (4, 8).
+    let text_matrix = CGAffineTransformIdentity.translate(4.0, 8.0);
+    let advance = drawer.show_text(&Font::sans_regular(), 16.0, text_matrix, "Hi");
+    assert!(advance > 8.0 && advance < 40.0);
+
+    // Rows are stored top-to-bottom.
+    let coverage_at =
+        |x: GuestUSize, y: GuestUSize| pixels[((height - 1 - y) * width + x) as usize * 4 + 3];
+    let mut covered = 0;
+    for y in 0..height {
+        for x in 0..width {
+            if coverage_at(x, y) == 0 {
+                continue;
+            }
+            covered += 1;
+            // "Hi" has no descenders, so nothing is drawn below the baseline,
+            // and the text is upright, so it doesn't extend far above it.
+            assert!((7..24).contains(&y), "unexpected coverage at {:?}", (x, y));
+            assert!(
+                x >= 3 && (x as f32) < 5.0 + advance,
+                "unexpected coverage at {:?}",
+                (x, y)
+            );
+        }
+    }
+    assert!(covered > 20);
+}
+
+#[cfg(test)]
+#[test]
+fn test_draw_gl_pixels() {
+    let (width, height) = (16, 16);
+    let mut pixels = vec![0u8; (width * height * 4) as usize];
+    // Flipped, like UIKit's contexts.
+    let transform = CGAffineTransformIdentity
+        .translate(0.0, height as f32)
+        .scale(1.0, -1.0);
+    let mut drawer = CGBitmapContextDrawer {
+        bitmap_info: CGBitmapContextData {
+            data: crate::mem::Ptr::null(),
+            data_is_owned: false,
+            width,
+            height,
+            bits_per_component: 8,
+            bytes_per_row: 4 * width,
+            color_space: kCGColorSpaceGenericRGB,
+            alpha_info: kCGImageAlphaPremultipliedLast,
+        },
+        rgb_fill_color: (0.0, 0.0, 0.0, 0.0),
+        transform,
+        pixels: &mut pixels,
+    };
+
+    // An 8x8 GL framebuffer (e.g. a 4x4 layer with a 2x scale hack), as
+    // returned by glReadPixels(): bottom-to-top, so the top half is red.
+    const RED: [u8; 4] = [255, 0, 0, 255];
+    const BLUE: [u8; 4] = [0, 0, 255, 255];
+    let gl_pixels: Vec<u8> = (0..8)
+        .flat_map(|row| [if row < 4 { BLUE } else { RED }; 8])
+        .flatten()
+        .collect();
+    let layer_rect = CGRect {
+        origin: CGPoint { x: 4.0, y: 4.0 },
+        size: CGSize {
+            width: 4.0,
+            height: 4.0,
+        },
+    };
+    drawer.draw_image(layer_rect, &Image::from_pixel_vec(gl_pixels, (8, 8)));
+
+    // Rows are stored top-to-bottom.
+    let pixel_at = |x: GuestUSize, y: GuestUSize| {
+        let idx = ((y * width + x) * 4) as usize;
+        [
+            pixels[idx],
+            pixels[idx + 1],
+            pixels[idx + 2],
+            pixels[idx + 3],
+        ]
+    };
+    for y in 0..height {
+        for x in 0..width {
+            let expected = match (x, y) {
+                (4..=7, 4..=5) => RED,
+                (4..=7, 6..=7) => BLUE,
+                _ => [0, 0, 0, 0],
+            };
+            assert_eq!(pixel_at(x, y), expected, "at {:?}", (x, y));
+        }
+    }
+}
+
 /// Implementation of `CGContextFillRect` (`clear` == [false]) and
 /// `CGContextClearRect` (`clear` == [true]) for `CGBitmapContext`.
 pub(super) fn fill_rect(env: &mut Environment, context: CGContextRef, rect: CGRect, clear: bool) {
@@ -601,19 +794,7 @@ pub(super) fn draw_image(
     //  &drawer.pixels
     //);
 
-    let (image_width, image_height) = image.dimensions();
-
-    // TODO: non-nearest-neighbour filtering? (what does CG actually do?)
-
-    for ((x, y), (texel_x, texel_y)) in drawer.iter_transformed_pixels(rect) {
-        let texel_x = (image_width as f32 * texel_x) as i32;
-        // Image is in top-to-bottom order, but the bitmap is bottom-to-top
-        let texel_y = (image_height as f32 * (1.0 - texel_y)) as i32;
-        // FIXME: might need alpha format conversion here
-        if let Some(color) = image.get_pixel((texel_x, texel_y)) {
-            drawer.put_pixel((x, y), color, /* blend: */ true)
-        }
-    }
+    drawer.draw_image(rect, image);
 
     //let _ = std::fs::write(
     //  format!(
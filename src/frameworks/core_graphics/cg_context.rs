@@ -7,9 +7,11 @@
 
 use super::cg_affine_transform::CGAffineTransform;
 use super::cg_image::CGImageRef;
-use super::{cg_bitmap_context, CGFloat, CGRect};
+use super::{cg_bitmap_context, CGFloat, CGPoint, CGRect};
 use crate::dyld::{export_c_func, FunctionExports};
 use crate::frameworks::core_foundation::{CFRelease, CFRetain, CFTypeRef};
+use crate::frameworks::uikit::ui_font;
+use crate::mem::{ConstPtr, GuestUSize};
 use crate::objc::{objc_classes, ClassExports, HostObject};
 use crate::Environment;
 
@@ -43,6 +45,12 @@ pub(super) struct CGContextHostObject {
     pub(super) transform: CGAffineTransform,
     // TODO: keep more states saved once they are implemented
     pub(super) state_stack: Vec<((CGFloat, CGFloat, CGFloat, CGFloat), CGAffineTransform)>,
+    /// Font name set by `CGContextSelectFont`, if any.
+    pub(super) font_name: Option<String>,
+    pub(super) font_size: CGFloat,
+    /// Text matrix, which includes the text position (`tx` and `ty`).
+    pub(super) text_matrix: CGAffineTransform,
+    pub(super) text_drawing_mode: CGTextDrawingMode,
 }
 impl HostObject for CGContextHostObject {}
 
@@ -52,6 +60,15 @@ pub(super) enum CGContextSubclass {
 
 pub type CGContextRef = CFTypeRef;
 
+pub type CGTextEncoding = i32;
+#[allow(dead_code)]
+pub const kCGEncodingFontSpecific: CGTextEncoding = 0;
+pub const kCGEncodingMacRoman: CGTextEncoding = 1;
+
+pub type CGTextDrawingMode = i32;
+pub const kCGTextFill: CGTextDrawingMode = 0;
+pub const kCGTextInvisible: CGTextDrawingMode = 3;
+
 pub fn CGContextRelease(env: &mut Environment, c: CGContextRef) {
     if !c.is_null() {
         CFRelease(env, c);
@@ -143,20 +160,139 @@ pub fn CGContextDrawImage(
     cg_bitmap_context::draw_image(env, context, rect, image);
 }
 
-fn CGContextSaveGState(env: &mut Environment, context: CGContextRef) {
+pub fn CGContextSaveGState(env: &mut Environment, context: CGContextRef) {
     let host_obj = env.objc.borrow_mut::<CGContextHostObject>(context);
     host_obj
         .state_stack
         .push((host_obj.rgb_fill_color, host_obj.transform));
 }
 
-fn CGContextRestoreGState(env: &mut Environment, context: CGContextRef) {
+pub fn CGContextRestoreGState(env: &mut Environment, context: CGContextRef) {
     let host_obj = env.objc.borrow_mut::<CGContextHostObject>(context);
     let state = host_obj.state_stack.pop().unwrap();
     host_obj.rgb_fill_color = state.0;
     host_obj.transform = state.1;
 }
 
+fn CGContextSelectFont(
+    env: &mut Environment,
+    context: CGContextRef,
+    name: ConstPtr<u8>,
+    size: CGFloat,
+    text_encoding: CGTextEncoding,
+) {
+    let name = env.mem.cstr_at_utf8(name).unwrap().to_string();
+    log_dbg!(
+        "CGContextSelectFont({:?}, {:?}, {})",
+        name,
+        size,
+        text_encoding
+    );
+    if text_encoding != kCGEncodingMacRoman {
+        log!(
+            "TODO: CGContextSelectFont() text encoding {}",
+            text_encoding
+        );
+    }
+    let host_obj = env.objc.borrow_mut::<CGContextHostObject>(context);
+    host_obj.font_name = Some(name);
+    host_obj.font_size = size;
+}
+fn CGContextSetFontSize(env: &mut Environment, context: CGContextRef, size: CGFloat) {
+    env.objc
+        .borrow_mut::<CGContextHostObject>(context)
+        .font_size = size;
+}
+
+fn CGContextSetTextDrawingMode(
+    env: &mut Environment,
+    context: CGContextRef,
+    mode: CGTextDrawingMode,
+) {
+    if mode != kCGTextFill && mode != kCGTextInvisible {
+        log!(
+            "TODO: CGContextSetTextDrawingMode({}), text will be filled",
+            mode
+        );
+    }
+    env.objc
+        .borrow_mut::<CGContextHostObject>(context)
+        .text_drawing_mode = mode;
+}
+
+fn CGContextSetTextMatrix(env: &mut Environment, context: CGContextRef, t: CGAffineTransform) {
+    env.objc
+        .borrow_mut::<CGContextHostObject>(context)
+        .text_matrix = t;
+}
+fn CGContextGetTextMatrix(env: &mut Environment, context: CGContextRef) -> CGAffineTransform {
+    env.objc.borrow::<CGContextHostObject>(context).text_matrix
+}
+
+fn CGContextSetTextPosition(env: &mut Environment, context: CGContextRef, x: CGFloat, y: CGFloat) {
+    let host_obj = env.objc.borrow_mut::<CGContextHostObject>(context);
+    host_obj.text_matrix.tx = x;
+    host_obj.text_matrix.ty = y;
+}
+fn CGContextGetTextPosition(env: &mut Environment, context: CGContextRef) -> CGPoint {
+    let text_matrix = env.objc.borrow::<CGContextHostObject>(context).text_matrix;
+    CGPoint {
+        x: text_matrix.tx,
+        y: text_matrix.ty,
+    }
+}
+
+fn CGContextShowText(
+    env: &mut Environment,
+    context: CGContextRef,
+    string: ConstPtr<u8>,
+    length: GuestUSize,
+) {
+    // TODO: Decode MacRoman properly. This is only correct for ASCII.
+    let text: String = env
+        .mem
+        .bytes_at(string, length)
+        .iter()
+        .map(|&byte| byte as char)
+        .collect();
+    log_dbg!("CGContextShowText({:?}, {:?})", context, text);
+
+    let host_obj = env.objc.borrow::<CGContextHostObject>(context);
+    let Some(font_name) = host_obj.font_name.clone() else {
+        log!("Warning: CGContextShowText() called with no font selected, ignoring");
+        return;
+    };
+    let font_size = host_obj.font_size;
+    let text_matrix = host_obj.text_matrix;
+    let visible = host_obj.text_drawing_mode != kCGTextInvisible;
+
+    let advance = ui_font::show_cg_text(
+        env,
+        context,
+        &font_name,
+        font_size,
+        text_matrix,
+        &text,
+        visible,
+    );
+
+    // The text position moves to the end of the text.
+    env.objc
+        .borrow_mut::<CGContextHostObject>(context)
+        .text_matrix = text_matrix.translate(advance, 0.0);
+}
+fn CGContextShowTextAtPoint(
+    env: &mut Environment,
+    context: CGContextRef,
+    x: CGFloat,
+    y: CGFloat,
+    string: ConstPtr<u8>,
+    length: GuestUSize,
+) {
+    CGContextSetTextPosition(env, context, x, y);
+    CGContextShowText(env, context, string, length);
+}
+
 pub const FUNCTIONS: FunctionExports = &[
     export_c_func!(CGContextRetain(_)),
     export_c_func!(CGContextRelease(_)),
@@ -172,4 +308,13 @@ pub const FUNCTIONS: FunctionExports = &[
     export_c_func!(CGContextDrawImage(_, _, _)),
     export_c_func!(CGContextSaveGState(_)),
     export_c_func!(CGContextRestoreGState(_)),
+    export_c_func!(CGContextSelectFont(_, _, _, _)),
+    export_c_func!(CGContextSetFontSize(_, _)),
+    export_c_func!(CGContextSetTextDrawingMode(_, _)),
+    export_c_func!(CGContextSetTextMatrix(_, _)),
+    export_c_func!(CGContextGetTextMatrix(_)),
+    export_c_func!(CGContextSetTextPosition(_, _, _)),
+    export_c_func!(CGContextGetTextPosition(_)),
+    export_c_func!(CGContextShowText(_, _, _)),
+    export_c_func!(CGContextShowTextAtPoint(_, _, _, _, _)),
 ];
@@ -38,6 +38,7 @@ pub mod ns_notification_center;
 pub mod ns_null;
 pub mod ns_objc_runtime;
 pub mod ns_object;
+pub mod ns_operation;
 pub mod ns_process_info;
 pub mod ns_property_list_serialization;
 pub mod ns_run_loop;
@@ -49,6 +50,7 @@ pub mod ns_timer;
 pub mod ns_url;
 pub mod ns_url_connection;
 pub mod ns_url_request;
+pub mod ns_url_response;
 pub mod ns_user_defaults;
 pub mod ns_value;
 pub mod ns_xml_parser;
@@ -61,10 +63,19 @@ pub struct State {
     ns_locale: ns_locale::State,
     ns_notification_center: ns_notification_center::State,
     ns_null: ns_null::State,
+    ns_operation: ns_operation::State,
     ns_run_loop: ns_run_loop::State,
     ns_string: ns_string::State,
+    ns_thread: ns_thread::State,
     ns_user_defaults: ns_user_defaults::State,
 }
+impl State {
+    /// Forget allocations that won't exist once `mem` is restored, see
+    /// [crate::Environment::load_snapshot].
+    pub fn forget_pointers_not_in(&mut self, mem: &crate::mem::MemSnapshot) {
+        self.ns_operation.forget_pointers_not_in(mem);
+    }
+}
 
 pub type NSInteger = i32;
 pub type NSUInteger = u32;
@@ -5,8 +5,10 @@
  */
 //! `NSData` and `NSMutableData`.
 
+use super::ns_error::{set_error, NSCocoaErrorDomain, NSURLErrorDomain};
 use super::ns_string::to_rust_string;
-use super::{NSRange, NSUInteger};
+use super::ns_url_connection::send_request;
+use super::{NSInteger, NSRange, NSUInteger};
 use crate::fs::GuestPath;
 use crate::mem::{ConstPtr, ConstVoidPtr, MutPtr, MutVoidPtr, Ptr};
 use crate::objc::{
@@ -14,6 +16,13 @@ use crate::objc::{
 };
 use crate::{msg_class, Environment};
 
+/// `NSDataReadingOptions`
+type NSDataReadingOptions = NSUInteger;
+
+// Error codes from `FoundationErrors.h`
+const NSFileReadNoSuchFileError: NSInteger = 260;
+const NSFileReadUnsupportedSchemeError: NSInteger = 262;
+
 struct NSDataHostObject {
     bytes: MutVoidPtr,
     length: NSUInteger,
@@ -55,6 +64,14 @@ pub const CLASSES: ClassExports = objc_classes! {
     autorelease(env, new)
 }
 
++ (id)dataWithContentsOfFile:(id)path
+                    options:(NSDataReadingOptions)options
+                      error:(MutPtr<id>)error { // NSError**
+    let new: id = msg![env; this alloc];
+    let new: id = msg![env; new initWithContentsOfFile:path options:options error:error];
+    autorelease(env, new)
+}
+
 + (id)dataWithContentsOfMappedFile:(id)path {
     let new: id = msg![env; this alloc];
     let new: id = msg![env; new initWithContentsOfMappedFile:path];
@@ -67,6 +84,14 @@ pub const CLASSES: ClassExports = objc_classes! {
     autorelease(env, new)
 }
 
++ (id)dataWithContentsOfURL:(id)url // NSURL*
+                    options:(NSDataReadingOptions)options
+                      error:(MutPtr<id>)error { // NSError**
+    let new: id = msg![env; this alloc];
+    let new: id = msg![env; new initWithContentsOfURL:url options:options error:error];
+    autorelease(env, new)
+}
+
 // Calling the standard `init` is also allowed, in which case we just get data
 // of size 0.
 
@@ -91,34 +116,59 @@ pub const CLASSES: ClassExports = objc_classes! {
 }
 
 - (id)initWithContentsOfURL:(id)url { // NSURL *
-    let path: id = msg![env; url absoluteString];
-    let path = to_rust_string(env, path);
-    // TODO: file URL case
-    assert!(path.starts_with("http"));
-    log!("TODO: ignoring [(NSData*){:?} initWithContentsOfURL:{:?}]", this, path);
-    // TODO: actually load data once we have proper network support
-    nil
+    msg![env; this initWithContentsOfURL:url options:0u32 error:(MutPtr::<id>::null())]
+}
+
+- (id)initWithContentsOfURL:(id)url // NSURL *
+                    options:(NSDataReadingOptions)options
+                      error:(MutPtr<id>)error { // NSError**
+    if url == nil {
+        release(env, this);
+        return nil;
+    }
+    if msg![env; url isFileURL] {
+        let path: id = msg![env; url path];
+        return msg![env; this initWithContentsOfFile:path options:options error:error];
+    }
+
+    let url_str: id = msg![env; url description];
+    let url_str = to_rust_string(env, url_str).into_owned();
+    log_dbg!("[(NSData*){:?} initWithContentsOfURL:{:?}]", this, url_str);
+    if !url_str.starts_with("http:") && !url_str.starts_with("https:") {
+        log!("Warning: Can't load data from {:?}, unsupported URL scheme. Returning nil.", url_str);
+        set_error(env, error, NSCocoaErrorDomain, NSFileReadUnsupportedSchemeError);
+        release(env, this);
+        return nil;
+    }
+    // This is subject to --enable-networking, like NSURLConnection.
+    match send_request(env, &url_str, /* timeout: */ 60.0) {
+        Ok(response) => init_with_contents(env, this, &response.body),
+        Err(code) => {
+            set_error(env, error, NSURLErrorDomain, code);
+            release(env, this);
+            nil
+        }
+    }
 }
 
 - (id)initWithContentsOfFile:(id)path {
+    msg![env; this initWithContentsOfFile:path options:0u32 error:(MutPtr::<id>::null())]
+}
+
+- (id)initWithContentsOfFile:(id)path // NSString*
+                     options:(NSDataReadingOptions)_options
+                       error:(MutPtr<id>)error { // NSError**
     if path == nil {
         return nil;
     }
     let path = to_rust_string(env, path);
     log_dbg!("[(NSData*){:?} initWithContentsOfFile:{:?}]", this, path);
     let Ok(bytes) = env.fs.read(GuestPath::new(&path)) else {
+        set_error(env, error, NSCocoaErrorDomain, NSFileReadNoSuchFileError);
         release(env, this);
         return nil;
     };
-    let size = bytes.len().try_into().unwrap();
-    let alloc = env.mem.alloc(size);
-    let slice = env.mem.bytes_at_mut(alloc.cast(), size);
-    slice.copy_from_slice(&bytes);
-
-    let host_object = env.objc.borrow_mut::<NSDataHostObject>(this);
-    host_object.bytes = alloc;
-    host_object.length = size;
-    this
+    init_with_contents(env, this, &bytes)
 }
 
 - (id)initWithContentsOfMappedFile:(id)path {
@@ -238,6 +288,20 @@ pub const CLASSES: ClassExports = objc_classes! {
 
 };
 
+/// Initialize a newly allocated `NSData` with a copy of some bytes read by the
+/// host (e.g. a file's contents).
+fn init_with_contents(env: &mut Environment, this: id, bytes: &[u8]) -> id {
+    let size = bytes.len().try_into().unwrap();
+    let alloc = env.mem.alloc(size);
+    let slice = env.mem.bytes_at_mut(alloc.cast(), size);
+    slice.copy_from_slice(bytes);
+
+    let host_object = env.objc.borrow_mut::<NSDataHostObject>(this);
+    host_object.bytes = alloc;
+    host_object.length = size;
+    this
+}
+
 pub fn to_rust_slice(env: &mut Environment, data: id) -> &[u8] {
     let borrowed_data = env.objc.borrow::<NSDataHostObject>(data);
     assert!(!borrowed_data.bytes.is_null() && borrowed_data.length != 0);
@@ -24,7 +24,7 @@ pub const CLASSES: ClassExports = objc_classes! {
 @implementation NSDate: NSObject
 
 + (NSTimeInterval)timeIntervalSinceReferenceDate {
-    SystemTime::now()
+    env.clock.system_time()
         .duration_since(apple_epoch())
         .unwrap()
         .as_secs_f64()
@@ -33,7 +33,7 @@ pub const CLASSES: ClassExports = objc_classes! {
 + (id)date {
     // "Date objects are immutable, representing an invariant time interval
     // relative to an absolute reference date (00:00:00 UTC on 1 January 2001)."
-    let time_interval = SystemTime::now()
+    let time_interval = env.clock.system_time()
         .duration_since(apple_epoch())
         .unwrap()
         .as_secs_f64();
@@ -49,7 +49,7 @@ pub const CLASSES: ClassExports = objc_classes! {
     // As of 2024, this approximately corresponds to 20 years into the future.
     // While `distantFuture` docs are talking in terms of centuries,
     // this should be OK to use for our purposes.
-    let time_interval = SystemTime::now()
+    let time_interval = env.clock.system_time()
         .duration_since(apple_epoch())
         .unwrap()
         .as_secs_f64() * 2.0;
@@ -98,7 +98,7 @@ pub const CLASSES: ClassExports = objc_classes! {
 
 - (NSTimeInterval)timeIntervalSinceNow {
     let host_object = env.objc.borrow::<NSDateHostObject>(this);
-    let time_interval = SystemTime::now()
+    let time_interval = env.clock.system_time()
         .duration_since(apple_epoch())
         .unwrap()
         .as_secs_f64();
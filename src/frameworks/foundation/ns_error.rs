@@ -4,15 +4,21 @@
  * file, You can obtain one at https://mozilla.org/MPL/2.0/.
  */
 
+use super::ns_string::get_static_str;
 use crate::dyld::{ConstantExports, HostConstant};
 use crate::frameworks::foundation::NSInteger;
-use crate::objc::{id, nil, release, retain, ClassExports, HostObject, NSZonePtr};
-use crate::objc_classes;
+use crate::mem::MutPtr;
+use crate::objc::{
+    autorelease, id, msg, msg_class, nil, release, retain, ClassExports, HostObject, NSZonePtr,
+};
+use crate::{objc_classes, Environment};
 
 /// `NSString*`
 pub type NSErrorDomain = id;
 
+pub const NSCocoaErrorDomain: &str = "NSCocoaErrorDomain";
 pub const NSOSStatusErrorDomain: &str = "NSOSStatusErrorDomain";
+pub const NSURLErrorDomain: &str = "NSURLErrorDomain";
 
 struct ErrorHostObject {
     domain: NSErrorDomain,
@@ -57,12 +63,43 @@ pub const CLASSES: ClassExports = objc_classes! {
     env.objc.dealloc_object(this, &mut env.mem);
 }
 
+- (NSErrorDomain)domain {
+    env.objc.borrow::<ErrorHostObject>(this).domain
+}
+
+- (NSInteger)code {
+    env.objc.borrow::<ErrorHostObject>(this).code
+}
+
 
 @end
 
 };
 
+/// Shortcut for host code: if `error_ptr` (an `NSError**` out-parameter) is
+/// not NULL, write a new autoreleased error with the given domain and code to
+/// it.
+pub fn set_error(
+    env: &mut Environment,
+    error_ptr: MutPtr<id>,
+    domain: &'static str,
+    code: NSInteger,
+) {
+    if error_ptr.is_null() {
+        return;
+    }
+    let domain = get_static_str(env, domain);
+    let error = msg_class![env; NSError alloc];
+    let error = msg![env; error initWithDomain:domain code:code userInfo:nil];
+    let error = autorelease(env, error);
+    env.mem.write(error_ptr, error);
+}
+
 pub const CONSTANTS: ConstantExports = &[
+    (
+        "_NSCocoaErrorDomain",
+        HostConstant::NSString(NSCocoaErrorDomain),
+    ),
     (
         "_NSLocalizedDescriptionKey",
         HostConstant::NSString("NSLocalizedDescriptionKey"),
@@ -71,4 +108,8 @@ pub const CONSTANTS: ConstantExports = &[
         "_NSOSStatusErrorDomain",
         HostConstant::NSString(NSOSStatusErrorDomain),
     ),
+    (
+        "_NSURLErrorDomain",
+        HostConstant::NSString(NSURLErrorDomain),
+    ),
 ];
@@ -0,0 +1,432 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `NSOperation.h` (`NSOperation`, `NSInvocationOperation`, `NSBlockOperation`
+//! and `NSOperationQueue`).
+//!
+//! Each queue runs its operations on worker threads created with
+//! `pthread_create()`, up to its maximum concurrent operation count. Workers
+//! exit once the queue has no more operations for them. Dependencies and KVO
+//! notifications are not supported yet.
+
+use super::{NSInteger, NSUInteger};
+use crate::abi::{CallFromHost, GuestFunction};
+use crate::dyld::HostFunction;
+use crate::libc::blocks::{block_invoke_function, copy_block, release_block};
+use crate::libc::pthread::thread::create_detached_thread;
+use crate::mem::{ConstVoidPtr, MemSnapshot};
+use crate::objc::{
+    autorelease, id, msg, msg_class, msg_send, nil, objc_classes, release, retain, ClassExports,
+    HostObject, NSZonePtr, SEL,
+};
+use crate::Environment;
+use std::collections::VecDeque;
+use std::time::Duration;
+
+const NSOperationQueueDefaultMaxConcurrentOperationCount: NSInteger = -1;
+
+/// How many workers a queue uses if the app doesn't set a limit.
+const DEFAULT_MAX_CONCURRENT_OPERATIONS: usize = 4;
+
+/// How often threads waiting for operations check whether they're done.
+const WAIT_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+#[derive(Default)]
+pub struct State {
+    /// Guest function for [_touchHLE_NSOperationQueueWorker], created lazily.
+    worker_function: Option<GuestFunction>,
+}
+impl State {
+    fn get(env: &mut Environment) -> &mut Self {
+        &mut env.framework_state.foundation.ns_operation
+    }
+    /// Forget allocations that won't exist once `mem` is restored, see
+    /// [crate::Environment::load_snapshot].
+    pub fn forget_pointers_not_in(&mut self, mem: &MemSnapshot) {
+        self.worker_function = self
+            .worker_function
+            .filter(|f| mem.is_used(f.addr_without_thumb_bit()));
+    }
+}
+
+enum OperationAction {
+    /// For `NSOperation` subclasses that override `main`.
+    None,
+    /// `NSInvocationOperation`. Target and argument are strong references.
+    Invocation {
+        target: id,
+        selector: SEL,
+        argument: id,
+    },
+    /// `NSBlockOperation`. These are copies owned by the operation.
+    Blocks(Vec<ConstVoidPtr>),
+}
+
+struct NSOperationHostObject {
+    action: OperationAction,
+    executing: bool,
+    finished: bool,
+    cancelled: bool,
+}
+impl HostObject for NSOperationHostObject {}
+
+struct NSOperationQueueHostObject {
+    /// Operations that haven't started yet. Strong references.
+    pending: VecDeque<id>,
+    /// Number of operations currently being run by workers.
+    running: usize,
+    /// Number of worker threads that haven't exited yet.
+    workers: usize,
+    max_concurrent: NSInteger,
+    suspended: bool,
+    /// Strong reference
+    name: id,
+}
+impl HostObject for NSOperationQueueHostObject {}
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+@implementation NSOperation: NSObject
+
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::new(NSOperationHostObject {
+        action: OperationAction::None,
+        executing: false,
+        finished: false,
+        cancelled: false,
+    });
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
+- (())dealloc {
+    let host_object = env.objc.borrow_mut::<NSOperationHostObject>(this);
+    match std::mem::replace(&mut host_object.action, OperationAction::None) {
+        OperationAction::None => (),
+        OperationAction::Invocation { target, argument, .. } => {
+            release(env, target);
+            release(env, argument);
+        }
+        OperationAction::Blocks(blocks) => {
+            for block in blocks {
+                release_block(env, block);
+            }
+        }
+    }
+    env.objc.dealloc_object(this, &mut env.mem)
+}
+
+- (())start {
+    let host_object = env.objc.borrow_mut::<NSOperationHostObject>(this);
+    assert!(!host_object.executing && !host_object.finished);
+    if !host_object.cancelled {
+        host_object.executing = true;
+        () = msg![env; this main];
+    }
+    let host_object = env.objc.borrow_mut::<NSOperationHostObject>(this);
+    host_object.executing = false;
+    host_object.finished = true;
+}
+
+- (())main {
+    // Subclasses are meant to override this.
+}
+
+- (())cancel {
+    env.objc.borrow_mut::<NSOperationHostObject>(this).cancelled = true;
+}
+
+- (bool)isCancelled {
+    env.objc.borrow::<NSOperationHostObject>(this).cancelled
+}
+- (bool)isExecuting {
+    env.objc.borrow::<NSOperationHostObject>(this).executing
+}
+- (bool)isFinished {
+    env.objc.borrow::<NSOperationHostObject>(this).finished
+}
+- (bool)isConcurrent {
+    false
+}
+- (bool)isReady {
+    true
+}
+
+- (())addDependency:(id)operation { // NSOperation *
+    log!("TODO: [(NSOperation*){:?} addDependency:{:?}] (ignored)", this, operation);
+}
+
+- (())waitUntilFinished {
+    while !env.objc.borrow::<NSOperationHostObject>(this).finished {
+        env.sleep(WAIT_POLL_INTERVAL, false);
+    }
+}
+
+@end
+
+@implementation NSInvocationOperation: NSOperation
+
+- (id)initWithTarget:(id)target
+            selector:(SEL)selector
+              object:(id)argument {
+    retain(env, target);
+    retain(env, argument);
+    env.objc.borrow_mut::<NSOperationHostObject>(this).action = OperationAction::Invocation {
+        target,
+        selector,
+        argument,
+    };
+    this
+}
+
+- (())main {
+    let OperationAction::Invocation { target, selector, argument } =
+        env.objc.borrow::<NSOperationHostObject>(this).action
+    else {
+        panic!();
+    };
+    () = msg_send(env, (target, selector, argument));
+}
+
+@end
+
+@implementation NSBlockOperation: NSOperation
+
++ (id)blockOperationWithBlock:(ConstVoidPtr)block { // void (^)(void)
+    let new: id = msg![env; this new];
+    () = msg![env; new addExecutionBlock:block];
+    autorelease(env, new)
+}
+
+- (())addExecutionBlock:(ConstVoidPtr)block { // void (^)(void)
+    let block = copy_block(env, block);
+    let host_object = env.objc.borrow_mut::<NSOperationHostObject>(this);
+    if let OperationAction::Blocks(ref mut blocks) = host_object.action {
+        blocks.push(block);
+    } else {
+        assert!(matches!(host_object.action, OperationAction::None));
+        host_object.action = OperationAction::Blocks(vec![block]);
+    }
+}
+
+- (())main {
+    // Blocks could be run concurrently, but running them in order is allowed.
+    let mut i = 0;
+    loop {
+        let OperationAction::Blocks(ref blocks) =
+            env.objc.borrow::<NSOperationHostObject>(this).action
+        else {
+            return;
+        };
+        let Some(&block) = blocks.get(i) else {
+            return;
+        };
+        let invoke = block_invoke_function(&env.mem, block);
+        () = invoke.call_from_host(env, (block,));
+        i += 1;
+    }
+}
+
+@end
+
+@implementation NSOperationQueue: NSObject
+
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::new(NSOperationQueueHostObject {
+        pending: VecDeque::new(),
+        running: 0,
+        workers: 0,
+        max_concurrent: NSOperationQueueDefaultMaxConcurrentOperationCount,
+        suspended: false,
+        name: nil,
+    });
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
+- (())dealloc {
+    let host_object = env.objc.borrow_mut::<NSOperationQueueHostObject>(this);
+    // Workers keep the queue alive, so there can only be pending operations
+    // if the queue is suspended.
+    assert!(host_object.workers == 0);
+    let pending = std::mem::take(&mut host_object.pending);
+    let name = host_object.name;
+    for operation in pending {
+        release(env, operation);
+    }
+    release(env, name);
+    env.objc.dealloc_object(this, &mut env.mem)
+}
+
+- (())addOperation:(id)operation { // NSOperation *
+    retain(env, operation);
+    env.objc.borrow_mut::<NSOperationQueueHostObject>(this).pending.push_back(operation);
+    spawn_workers(env, this);
+}
+
+- (())addOperationWithBlock:(ConstVoidPtr)block { // void (^)(void)
+    let operation: id = msg_class![env; NSBlockOperation blockOperationWithBlock:block];
+    () = msg![env; this addOperation:operation];
+}
+
+- (id)operations {
+    // Running operations aren't tracked, so only pending ones are returned.
+    let pending: Vec<id> = env
+        .objc
+        .borrow::<NSOperationQueueHostObject>(this)
+        .pending
+        .iter()
+        .copied()
+        .collect();
+    let array: id = msg_class![env; NSMutableArray new];
+    for operation in pending {
+        () = msg![env; array addObject:operation];
+    }
+    autorelease(env, array)
+}
+
+- (NSUInteger)operationCount {
+    let host_object = env.objc.borrow::<NSOperationQueueHostObject>(this);
+    (host_object.pending.len() + host_object.running).try_into().unwrap()
+}
+
+- (NSInteger)maxConcurrentOperationCount {
+    env.objc.borrow::<NSOperationQueueHostObject>(this).max_concurrent
+}
+- (())setMaxConcurrentOperationCount:(NSInteger)count {
+    assert!(count >= NSOperationQueueDefaultMaxConcurrentOperationCount);
+    env.objc.borrow_mut::<NSOperationQueueHostObject>(this).max_concurrent = count;
+    spawn_workers(env, this);
+}
+
+- (bool)isSuspended {
+    env.objc.borrow::<NSOperationQueueHostObject>(this).suspended
+}
+- (())setSuspended:(bool)suspended {
+    env.objc.borrow_mut::<NSOperationQueueHostObject>(this).suspended = suspended;
+    spawn_workers(env, this);
+}
+
+- (id)name {
+    env.objc.borrow::<NSOperationQueueHostObject>(this).name
+}
+- (())setName:(id)name { // NSString *
+    let name: id = msg![env; name copy];
+    let host_object = env.objc.borrow_mut::<NSOperationQueueHostObject>(this);
+    let old_name = std::mem::replace(&mut host_object.name, name);
+    release(env, old_name);
+}
+
+- (())cancelAllOperations {
+    let pending: Vec<id> = env
+        .objc
+        .borrow::<NSOperationQueueHostObject>(this)
+        .pending
+        .iter()
+        .copied()
+        .collect();
+    for operation in pending {
+        () = msg![env; operation cancel];
+    }
+}
+
+- (())waitUntilAllOperationsAreFinished {
+    loop {
+        let host_object = env.objc.borrow::<NSOperationQueueHostObject>(this);
+        let suspended = host_object.suspended && host_object.running == 0;
+        if (host_object.pending.is_empty() && host_object.running == 0) || suspended {
+            break;
+        }
+        env.sleep(WAIT_POLL_INTERVAL, false);
+    }
+}
+
+@end
+
+};
+
+/// Maximum number of workers for a queue, given its
+/// `maxConcurrentOperationCount`.
+fn max_workers(max_concurrent: NSInteger) -> usize {
+    if max_concurrent == NSOperationQueueDefaultMaxConcurrentOperationCount {
+        DEFAULT_MAX_CONCURRENT_OPERATIONS
+    } else {
+        max_concurrent.try_into().unwrap()
+    }
+}
+
+/// Start enough worker threads for the queue's pending operations, within the
+/// queue's limit.
+fn spawn_workers(env: &mut Environment, queue: id) {
+    loop {
+        let host_object = env.objc.borrow_mut::<NSOperationQueueHostObject>(queue);
+        if host_object.suspended
+            || host_object.workers >= host_object.pending.len() + host_object.running
+            || host_object.workers >= max_workers(host_object.max_concurrent)
+        {
+            return;
+        }
+        host_object.workers += 1;
+        // Released by the worker when it exits.
+        retain(env, queue);
+
+        let worker_function = if let Some(f) = State::get(env).worker_function {
+            f
+        } else {
+            let symb = "__touchHLE_NSOperationQueueWorker";
+            let hf: HostFunction =
+                &(_touchHLE_NSOperationQueueWorker as fn(&mut Environment, _) -> _);
+            let f = env.dyld.create_guest_function(&mut env.mem, symb, hf);
+            State::get(env).worker_function = Some(f);
+            f
+        };
+
+        create_detached_thread(env, worker_function, queue.cast());
+    }
+}
+
+/// Start routine of an `NSOperationQueue` worker thread. It runs the queue's
+/// pending operations until there are none left (or it's no longer allowed to
+/// run more), then exits.
+pub fn _touchHLE_NSOperationQueueWorker(env: &mut Environment, queue: id) {
+    log_dbg!("NSOperationQueue {:?}: worker started", queue);
+    loop {
+        let host_object = env.objc.borrow_mut::<NSOperationQueueHostObject>(queue);
+        let can_run =
+            !host_object.suspended && host_object.running < max_workers(host_object.max_concurrent);
+        let Some(operation) = can_run.then(|| host_object.pending.pop_front()).flatten() else {
+            host_object.workers -= 1;
+            break;
+        };
+        host_object.running += 1;
+
+        log_dbg!("NSOperationQueue {:?}: running {:?}", queue, operation);
+        let pool: id = msg_class![env; NSAutoreleasePool new];
+        () = msg![env; operation start];
+        release(env, pool);
+        release(env, operation);
+
+        env.objc
+            .borrow_mut::<NSOperationQueueHostObject>(queue)
+            .running -= 1;
+    }
+    log_dbg!("NSOperationQueue {:?}: worker exiting", queue);
+    release(env, queue);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn worker_limits() {
+        assert_eq!(
+            max_workers(NSOperationQueueDefaultMaxConcurrentOperationCount),
+            DEFAULT_MAX_CONCURRENT_OPERATIONS
+        );
+        assert_eq!(max_workers(1), 1);
+        assert_eq!(max_workers(0), 0);
+    }
+}
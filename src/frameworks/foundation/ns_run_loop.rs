@@ -13,10 +13,15 @@ use crate::dyld::{ConstantExports, HostConstant};
 use crate::frameworks::audio_toolbox::audio_queue::{handle_audio_queue, AudioQueueRef};
 use crate::frameworks::audio_toolbox::audio_unit::{render_audio_unit, AudioUnit};
 use crate::frameworks::core_foundation::cf_run_loop::{
-    kCFRunLoopCommonModes, kCFRunLoopDefaultMode, CFRunLoopRef,
+    kCFRunLoopCommonModes, kCFRunLoopDefaultMode, kCFRunLoopRunFinished,
+    kCFRunLoopRunHandledSource, kCFRunLoopRunStopped, kCFRunLoopRunTimedOut, CFRunLoopRef,
+    CFRunLoopRunResult,
 };
-use crate::frameworks::{core_animation, media_player, uikit};
+use crate::frameworks::core_foundation::cf_run_loop_source::handle_source;
+use crate::frameworks::{core_animation, media_player, openal, uikit};
+use crate::libc::dispatch;
 use crate::objc::{id, msg, objc_classes, release, retain, ClassExports, HostObject};
+use crate::snapshot::ResumePoint;
 use crate::Environment;
 use std::time::{Duration, Instant};
 
@@ -50,6 +55,14 @@ struct NSRunLoopHostObject {
     /// Strong references to `NSTimer*` in no particular order. Timers are owned
     /// by the run loop. The timer must remove itself when invalidated.
     timers: Vec<id>,
+    /// Strong references to `CADisplayLink*` in no particular order. The
+    /// display link must remove itself when invalidated.
+    display_links: Vec<id>,
+    /// Strong references to `CFRunLoopSourceRef`s in the order they were
+    /// added. The source must remove itself when invalidated.
+    sources: Vec<id>,
+    /// Set by `CFRunLoopStop()`.
+    stopped: bool,
 }
 impl HostObject for NSRunLoopHostObject {}
 
@@ -67,6 +80,9 @@ pub const CLASSES: ClassExports = objc_classes! {
             audio_units: Vec::new(),
             audio_queues: Vec::new(),
             timers: Vec::new(),
+            display_links: Vec::new(),
+            sources: Vec::new(),
+            stopped: false,
         });
         let new = env.objc.alloc_static_object(this, host_object, &mut env.mem);
         env.framework_state.foundation.ns_run_loop.main_thread_run_loop = Some(new);
@@ -113,7 +129,7 @@ pub const CLASSES: ClassExports = objc_classes! {
 }
 
 - (())run {
-    run_run_loop(env, this, /* single_iteration: */ false);
+    run_run_loop(env, this, RunUntil::Forever);
 }
 // TODO: other run methods
 
@@ -180,25 +196,121 @@ pub(super) fn remove_timer(env: &mut Environment, run_loop: id, timer: id) {
     }
 }
 
+/// For use by `CADisplayLink`.
+pub fn add_display_link(env: &mut Environment, run_loop: id, display_link: id) {
+    retain(env, display_link);
+    let display_links = &mut env
+        .objc
+        .borrow_mut::<NSRunLoopHostObject>(run_loop)
+        .display_links;
+    assert!(!display_links.contains(&display_link));
+    display_links.push(display_link);
+}
+
+/// For use by `CADisplayLink` so it can remove itself once it's invalidated.
+pub fn remove_display_link(env: &mut Environment, run_loop: id, display_link: id) {
+    let display_links = &mut env
+        .objc
+        .borrow_mut::<NSRunLoopHostObject>(run_loop)
+        .display_links;
+    let idx = display_links
+        .iter()
+        .position(|&item| item == display_link)
+        .unwrap();
+    display_links.swap_remove(idx);
+    release(env, display_link);
+}
+
+/// For use by `CFRunLoopSource`.
+pub fn add_source(env: &mut Environment, run_loop: id, source: id) {
+    let sources = &mut env.objc.borrow_mut::<NSRunLoopHostObject>(run_loop).sources;
+    if sources.contains(&source) {
+        return;
+    }
+    sources.push(source);
+    retain(env, source);
+}
+
+/// For use by `CFRunLoopSource`. Returns [false] if the source wasn't in the
+/// run loop.
+pub fn remove_source(env: &mut Environment, run_loop: id, source: id) -> bool {
+    let sources = &mut env.objc.borrow_mut::<NSRunLoopHostObject>(run_loop).sources;
+    let Some(idx) = sources.iter().position(|&item| item == source) else {
+        return false;
+    };
+    sources.remove(idx);
+    release(env, source);
+    true
+}
+
+/// For use by `CFRunLoopStop()`. This only affects runs that can be stopped,
+/// see [run_run_loop_until_stopped].
+pub fn stop_run_loop(env: &mut Environment, run_loop: id) {
+    env.objc.borrow_mut::<NSRunLoopHostObject>(run_loop).stopped = true;
+}
+
+/// How [run_run_loop] decides when to return.
+#[derive(Copy, Clone)]
+enum RunUntil {
+    /// Never return (`-[NSRunLoop run]`).
+    Forever,
+    /// Return after one iteration.
+    SingleIteration,
+    /// Return when stopped by `CFRunLoopStop()`, when the deadline (if any)
+    /// has passed, or optionally once a source has been handled.
+    Stopped {
+        deadline: Option<Instant>,
+        return_after_source_handled: bool,
+    },
+}
+
 /// Run the run loop for just a single iteration. This is a special mode just
 /// for the app picker, since we don't have `runMode:beforeDate:` or
 /// `runUntilDate:` yet. (TODO: implement those to replace this.)
 pub fn run_run_loop_single_iteration(env: &mut Environment, run_loop: id) {
-    run_run_loop(env, run_loop, /* single_iteration: */ true)
+    run_run_loop(env, run_loop, RunUntil::SingleIteration);
 }
 
-fn run_run_loop(env: &mut Environment, run_loop: id, single_iteration: bool) {
-    if single_iteration {
-        log_dbg!("Entering run loop {:?} (single iteration)", run_loop);
-    } else {
-        log_dbg!("Entering run loop {:?} (indefinitely)", run_loop);
+/// Run the run loop until it is stopped with `CFRunLoopStop()`, or until the
+/// deadline, if any. If `return_after_source_handled` is [true], it also
+/// returns after a `CFRunLoopSource` has been handled. This is the
+/// implementation of `CFRunLoopRun()` and `CFRunLoopRunInMode()`.
+pub fn run_run_loop_until_stopped(
+    env: &mut Environment,
+    run_loop: id,
+    deadline: Option<Instant>,
+    return_after_source_handled: bool,
+) -> CFRunLoopRunResult {
+    run_run_loop(
+        env,
+        run_loop,
+        RunUntil::Stopped {
+            deadline,
+            return_after_source_handled,
+        },
+    )
+}
+
+fn run_run_loop(env: &mut Environment, run_loop: id, until: RunUntil) -> CFRunLoopRunResult {
+    match until {
+        RunUntil::Forever => log_dbg!("Entering run loop {:?} (indefinitely)", run_loop),
+        RunUntil::SingleIteration => {
+            log_dbg!("Entering run loop {:?} (single iteration)", run_loop)
+        }
+        RunUntil::Stopped { deadline, .. } => log_dbg!(
+            "Entering run loop {:?} (until stopped or {:?})",
+            run_loop,
+            deadline
+        ),
     }
 
     // Temporary vectors used to track things without needing a reference to the
     // environment or to lock the object. Re-used each iteration for efficiency.
     let mut timers_tmp = Vec::new();
+    let mut display_links_tmp = Vec::new();
     let mut audio_queues_tmp = Vec::new();
     let mut audio_units_tmp = Vec::new();
+    let mut sources_tmp = Vec::new();
 
     fn limit_sleep_time(current: &mut Option<Instant>, new: Option<Instant>) {
         if let Some(new) = new {
@@ -208,14 +320,23 @@ fn run_run_loop(env: &mut Environment, run_loop: id, single_iteration: bool) {
 
     loop {
         let mut sleep_until = None;
-
-        env.window
-            .as_mut()
-            .expect("NSRunLoop not supported in headless mode")
-            .poll_for_events(&env.options);
-
-        let next_due = uikit::handle_events(env);
-        limit_sleep_time(&mut sleep_until, next_due);
+        let mut handled_source = false;
+
+        // There are no events in headless mode, but running the run loop is
+        // still useful for timers and sources.
+        if let Some(window) = env.window.as_mut() {
+            window.poll_for_events(&env.options);
+            env.update_fast_forward();
+            // Only the outermost run loop of the main thread (e.g. the one
+            // UIApplicationMain() runs) can be snapshotted. Nested run loops
+            // have host functions below them on the stack.
+            env.handle_snapshot_request(
+                matches!(until, RunUntil::Forever).then_some(ResumePoint::MainRunLoop),
+            );
+
+            let next_due = uikit::handle_events(env);
+            limit_sleep_time(&mut sleep_until, next_due);
+        }
 
         let next_due = core_animation::recomposite_if_necessary(env);
         limit_sleep_time(&mut sleep_until, next_due);
@@ -224,10 +345,50 @@ fn run_run_loop(env: &mut Environment, run_loop: id, single_iteration: bool) {
         timers_tmp.extend_from_slice(&env.objc.borrow::<NSRunLoopHostObject>(run_loop).timers);
 
         for timer in timers_tmp.drain(..) {
+            // An earlier timer might have invalidated this one.
+            if !env
+                .objc
+                .borrow::<NSRunLoopHostObject>(run_loop)
+                .timers
+                .contains(&timer)
+            {
+                continue;
+            }
             let next_due = ns_timer::handle_timer(env, timer);
             limit_sleep_time(&mut sleep_until, next_due);
         }
 
+        assert!(sources_tmp.is_empty());
+        sources_tmp.extend_from_slice(&env.objc.borrow::<NSRunLoopHostObject>(run_loop).sources);
+
+        for source in sources_tmp.drain(..) {
+            // An earlier source might have removed this one.
+            if !env
+                .objc
+                .borrow::<NSRunLoopHostObject>(run_loop)
+                .sources
+                .contains(&source)
+            {
+                continue;
+            }
+            handled_source |= handle_source(env, source);
+        }
+
+        assert!(display_links_tmp.is_empty());
+        display_links_tmp.extend_from_slice(
+            &env.objc
+                .borrow::<NSRunLoopHostObject>(run_loop)
+                .display_links,
+        );
+
+        for display_link in display_links_tmp.drain(..) {
+            let next_due = core_animation::ca_display_link::handle_display_link(env, display_link);
+            limit_sleep_time(&mut sleep_until, next_due);
+        }
+
+        // Only does anything if no audio device could be opened.
+        openal::render_silent_devices(env);
+
         assert!(audio_queues_tmp.is_empty());
         audio_queues_tmp.extend_from_slice(
             &env.objc
@@ -249,6 +410,8 @@ fn run_run_loop(env: &mut Environment, run_loop: id, single_iteration: bool) {
 
         media_player::handle_players(env);
 
+        dispatch::drain_main_queue(env);
+
         // Unfortunately, touchHLE has to poll for certain things repeatedly;
         // it can't just wait until the next event appears.
         //
@@ -264,13 +427,47 @@ fn run_run_loop(env: &mut Environment, run_loop: id, single_iteration: bool) {
         // or until the next scheduled event, whichever is sooner. iPhone OS
         // apps can't do more than 60fps so this should be fine.
         let limit = Duration::from_millis(1000 / 60);
+
+        let deadline = match until {
+            RunUntil::Forever => None,
+            RunUntil::SingleIteration => {
+                let now = env.clock.now();
+                env.sleep(
+                    sleep_until.map_or(limit, |i| i.duration_since(now).min(limit)),
+                    false,
+                );
+                return kCFRunLoopRunFinished;
+            }
+            RunUntil::Stopped {
+                deadline,
+                return_after_source_handled,
+            } => {
+                let host_object = env.objc.borrow_mut::<NSRunLoopHostObject>(run_loop);
+                if host_object.stopped {
+                    host_object.stopped = false;
+                    log_dbg!("Run loop {:?} stopped", run_loop);
+                    return kCFRunLoopRunStopped;
+                }
+                if handled_source && return_after_source_handled {
+                    return kCFRunLoopRunHandledSource;
+                }
+                deadline
+            }
+        };
+
+        // The sleep is deliberately not limited by the deadline: apps that
+        // call CFRunLoopRunInMode() with a zero timeout every frame rely on
+        // it sleeping like a normal iteration. A source that is signalled
+        // while sleeping won't be handled until the sleep ends, which is fine
+        // given how short it is.
+        let now = env.clock.now();
         env.sleep(
-            sleep_until.map_or(limit, |i| i.duration_since(Instant::now()).min(limit)),
+            sleep_until.map_or(limit, |i| i.saturating_duration_since(now).min(limit)),
             false,
         );
 
-        if single_iteration {
-            break;
+        if deadline.is_some_and(|deadline| env.clock.now() >= deadline) {
+            return kCFRunLoopRunTimedOut;
         }
     }
 }
@@ -7,6 +7,7 @@
 
 use super::NSTimeInterval;
 use crate::dyld::HostFunction;
+use crate::environment::ThreadId;
 use crate::frameworks::core_foundation::CFTypeRef;
 use crate::libc::pthread::thread::{
     pthread_attr_init, pthread_attr_setdetachstate, pthread_attr_t, pthread_create, pthread_t,
@@ -19,8 +20,33 @@ use crate::objc::{
 };
 use crate::Environment;
 use crate::{msg, msg_class};
+use std::collections::HashMap;
 use std::time::Duration;
 
+#[derive(Default)]
+pub struct State {
+    /// The `NSThread*` for each thread that has one. Strong references.
+    /// TODO: remove entries for threads created with `pthread_create()` once
+    /// they exit.
+    threads: HashMap<ThreadId, id>,
+}
+impl State {
+    fn get(env: &mut Environment) -> &mut Self {
+        &mut env.framework_state.foundation.ns_thread
+    }
+}
+
+/// Get the `NSThread*` for a thread, creating it if necessary (e.g. for the
+/// main thread, or threads created with `pthread_create()`).
+fn thread_object(env: &mut Environment, thread: ThreadId) -> id {
+    if let Some(&object) = State::get(env).threads.get(&thread) {
+        return object;
+    }
+    let object: id = msg_class![env; NSThread new];
+    State::get(env).threads.insert(thread, object);
+    object
+}
+
 struct NSThreadHostObject {
     target: id,
     selector: Option<SEL>,
@@ -57,10 +83,19 @@ pub const CLASSES: ClassExports = objc_classes! {
 }
 
 + (id)currentThread {
-    // Simple hack to make the `setThreadPriority:` work as an instance method
-    // (it's both a class and an instance method). Must be replaced if we ever
-    // need to support other methods.
-    this
+    thread_object(env, env.current_thread)
+}
+
++ (id)mainThread {
+    thread_object(env, 0)
+}
+
++ (bool)isMainThread {
+    env.current_thread == 0
+}
+
++ (bool)isMultiThreaded {
+    env.threads.len() > 1
 }
 
 + (id)callStackReturnAddresses {
@@ -76,17 +111,31 @@ pub const CLASSES: ClassExports = objc_classes! {
 + (())detachNewThreadSelector:(SEL)selector
                        toTarget:(id)target
                      withObject:(id)object {
-    let host_object = Box::new(NSThreadHostObject {
-        target,
-        selector: Some(selector),
-        object,
-        thread_dictionary: nil,
-    });
-    let this = env.objc.alloc_object(this, host_object, &mut env.mem);
-    retain(env, this);
+    let new: id = msg![env; this alloc];
+    let new: id = msg![env; new initWithTarget:target selector:selector object:object];
+    () = msg![env; new start];
+    release(env, new);
+}
 
+- (id)initWithTarget:(id)target
+            selector:(SEL)selector
+              object:(id)object {
     retain(env, target);
     retain(env, object);
+    let host_object = env.objc.borrow_mut::<NSThreadHostObject>(this);
+    host_object.target = target;
+    host_object.selector = Some(selector);
+    host_object.object = object;
+    this
+}
+
+- (())start {
+    assert!(
+        env.objc.borrow::<NSThreadHostObject>(this).selector.is_some(),
+        "TODO: NSThread subclasses overriding main"
+    );
+    // Released by the thread once it's done.
+    retain(env, this);
 
     let symb = "__touchHLE_NSThreadInvocationHelper";
     let hf: HostFunction = &(_touchHLE_NSThreadInvocationHelper as fn(&mut Environment, _) -> _);
@@ -105,6 +154,20 @@ pub const CLASSES: ClassExports = objc_classes! {
     // TODO: post NSWillBecomeMultiThreadedNotification
 }
 
+- (bool)isMainThread {
+    State::get(env).threads.get(&0) == Some(&this)
+}
+
+- (f64)threadPriority {
+    log!("TODO: [(NSThread*){:?} threadPriority] (not implemented yet)", this);
+    1.0
+}
+
+- (bool)setThreadPriority:(f64)priority {
+    log!("TODO: [(NSThread*){:?} setThreadPriority:{:?}] (ignored)", this, priority);
+    true
+}
+
 // TODO: construction etc
 - (id)threadDictionary {
     // Initialize lazily in case the thread is started with pthread_create
@@ -123,8 +186,15 @@ pub const CLASSES: ClassExports = objc_classes! {
 
 - (())dealloc {
     log_dbg!("[(NSThread*){:?} dealloc]", this);
-    let host_object = env.objc.borrow::<NSThreadHostObject>(this);
-    release(env, host_object.thread_dictionary);
+    let &NSThreadHostObject {
+        target,
+        object,
+        thread_dictionary,
+        ..
+    } = env.objc.borrow(this);
+    release(env, target);
+    release(env, object);
+    release(env, thread_dictionary);
     env.objc.dealloc_object(this, &mut env.mem)
 }
 
@@ -148,11 +218,22 @@ pub fn _touchHLE_NSThreadInvocationHelper(env: &mut Environment, ns_thread_obj:
         object,
         thread_dictionary: _,
     } = env.objc.borrow(ns_thread_obj);
-    () = msg_send(env, (target, selector.unwrap(), object));
 
-    release(env, object);
-    release(env, target);
+    // The thread's NSThread object is now the current thread, and it's
+    // retained by the state until the thread exits (the retain from -start is
+    // handed over to it).
+    let current_thread = env.current_thread;
+    State::get(env)
+        .threads
+        .insert(current_thread, ns_thread_obj);
+
+    // Apps expect the new thread to have an autorelease pool, so objects
+    // autoreleased by the selector don't leak.
+    let pool: id = msg_class![env; NSAutoreleasePool new];
+    () = msg_send(env, (target, selector.unwrap(), object));
+    release(env, pool);
 
+    State::get(env).threads.remove(&current_thread);
     release(env, ns_thread_obj);
 
     // TODO: NSThread exit
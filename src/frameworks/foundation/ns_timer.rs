@@ -8,23 +8,36 @@
 use super::ns_run_loop::NSDefaultRunLoopMode;
 use super::NSTimeInterval;
 use super::{ns_run_loop, ns_string};
+use crate::abi::CallFromHost;
+use crate::libc::blocks::{block_invoke_function, copy_block, release_block};
+use crate::mem::ConstVoidPtr;
 use crate::objc::{
-    autorelease, id, msg, msg_class, msg_send, nil, objc_classes, release, retain, ClassExports,
-    HostObject, SEL,
+    autorelease, id, msg, msg_class, msg_send, nil, objc_classes, release, retain, Class,
+    ClassExports, HostObject, SEL,
 };
 use crate::Environment;
 use std::time::{Duration, Instant};
 
+/// What a timer does when it fires.
+#[derive(Clone, Copy)]
+enum TimerAction {
+    /// Send a message to a target, which is a strong reference.
+    Message(id, SEL),
+    /// Call a block. This is a copy owned by the timer.
+    Block(ConstVoidPtr),
+}
+
 struct NSTimerHostObject {
     ns_interval: NSTimeInterval,
     /// Copy of `ns_interval` in Rust's type for time intervals. Keep in sync!
     rust_interval: Duration,
-    /// Strong reference
-    target: id,
-    selector: SEL,
+    action: TimerAction,
     /// Strong reference
     user_info: id,
     repeats: bool,
+    /// This is only stored so it can be read back: touchHLE always tries to
+    /// fire timers on time.
+    tolerance: NSTimeInterval,
     due_by: Option<Instant>,
     /// Weak reference
     run_loop: id,
@@ -43,35 +56,16 @@ pub const CLASSES: ClassExports = objc_classes! {
                    selector:(SEL)selector
                    userInfo:(id)user_info
                     repeats:(bool)repeats {
-    let ns_interval = ns_interval.max(0.0001);
-    let rust_interval = Duration::from_secs_f64(ns_interval);
-
     retain(env, target);
-    retain(env, user_info);
-
-    let host_object = Box::new(NSTimerHostObject {
-        ns_interval,
-        rust_interval,
-        target,
-        selector,
-        user_info,
-        repeats,
-        due_by: Some(Instant::now().checked_add(rust_interval).unwrap()),
-        run_loop: nil,
-    });
-    let new = env.objc.alloc_object(this, host_object, &mut env.mem);
-
-    log_dbg!(
-        "New {} timer {:?}, interval {}s, target [{:?} {}], user info {:?}",
-        if repeats { "repeating" } else { "single-use" },
-        new,
-        ns_interval,
-        target,
-        selector.as_str(&env.mem),
-        user_info,
-    );
+    let action = TimerAction::Message(target, selector);
+    new_timer(env, this, ns_interval, action, user_info, repeats)
+}
 
-    autorelease(env, new)
++ (id)timerWithTimeInterval:(NSTimeInterval)ns_interval
+                    repeats:(bool)repeats
+                      block:(ConstVoidPtr)block { // void (^)(NSTimer *)
+    let action = TimerAction::Block(copy_block(env, block));
+    new_timer(env, this, ns_interval, action, nil, repeats)
 }
 
 + (id)scheduledTimerWithTimeInterval:(NSTimeInterval)interval
@@ -84,21 +78,30 @@ pub const CLASSES: ClassExports = objc_classes! {
                                             selector:selector
                                             userInfo:user_info
                                              repeats:repeats];
+    schedule_timer(env, timer);
+    timer
+}
 
-    let run_loop: id = msg_class![env; NSRunLoop currentRunLoop];
-    let mode: id = ns_string::get_static_str(env, NSDefaultRunLoopMode);
-    let _: () = msg![env; run_loop addTimer:timer forMode:mode];
-
++ (id)scheduledTimerWithTimeInterval:(NSTimeInterval)interval
+                             repeats:(bool)repeats
+                               block:(ConstVoidPtr)block { // void (^)(NSTimer *)
+    let timer = msg![env; this timerWithTimeInterval:interval
+                                             repeats:repeats
+                                               block:block];
+    schedule_timer(env, timer);
     timer
 }
 
 - (())dealloc {
     let &NSTimerHostObject {
-        target,
+        action,
         user_info,
         ..
     } = env.objc.borrow(this);
-    release(env, target);
+    match action {
+        TimerAction::Message(target, _) => release(env, target),
+        TimerAction::Block(block) => release_block(env, block),
+    }
     release(env, user_info);
     env.objc.dealloc_object(this, &mut env.mem)
 }
@@ -117,33 +120,32 @@ pub const CLASSES: ClassExports = objc_classes! {
 - (bool)isValid {
     env.objc.borrow::<NSTimerHostObject>(this).due_by.is_some()
 }
+- (NSTimeInterval)tolerance {
+    env.objc.borrow::<NSTimerHostObject>(this).tolerance
+}
+- (())setTolerance:(NSTimeInterval)tolerance {
+    env.objc.borrow_mut::<NSTimerHostObject>(this).tolerance = tolerance.max(0.0);
+}
 
 - (())invalidate {
-    // Timer might already be invalid, don't try to remove it twice.
-    if env.objc.borrow_mut::<NSTimerHostObject>(this).due_by.take().is_some() {
-        let run_loop: id = msg_class![env; NSRunLoop currentRunLoop];
+    // Timer might already be invalid, don't try to remove it twice. It might
+    // also never have been added to a run loop.
+    let host_object = env.objc.borrow_mut::<NSTimerHostObject>(this);
+    if host_object.due_by.take().is_some() && host_object.run_loop != nil {
+        let run_loop = host_object.run_loop;
         ns_run_loop::remove_timer(env, run_loop, this);
     }
 }
 
 - (())fire {
-    let &NSTimerHostObject {
-        target,
-        selector,
-        repeats,
-        ..
-    } = env.objc.borrow(this);
-
-    let pool: id = msg_class![env; NSAutoreleasePool new];
-
-    // Signature should be `- (void)timerDidFire:(NSTimer *)which`.
-    let _: () = msg_send(env, (target, selector, this));
-
-    release(env, pool);
+    let repeats = env.objc.borrow::<NSTimerHostObject>(this).repeats;
 
+    retain(env, this);
+    send_timer_action(env, this);
     if !repeats {
         () = msg![env; this invalidate];
     }
+    release(env, this);
 }
 
 // TODO: more constructors
@@ -153,6 +155,116 @@ pub const CLASSES: ClassExports = objc_classes! {
 
 };
 
+fn new_timer(
+    env: &mut Environment,
+    class: Class,
+    ns_interval: NSTimeInterval,
+    action: TimerAction,
+    user_info: id,
+    repeats: bool,
+) -> id {
+    let ns_interval = ns_interval.max(0.0001);
+    let rust_interval = Duration::from_secs_f64(ns_interval);
+
+    retain(env, user_info);
+
+    let host_object = Box::new(NSTimerHostObject {
+        ns_interval,
+        rust_interval,
+        action,
+        user_info,
+        repeats,
+        tolerance: 0.0,
+        due_by: Some(env.clock.now().checked_add(rust_interval).unwrap()),
+        run_loop: nil,
+    });
+    let new = env.objc.alloc_object(class, host_object, &mut env.mem);
+
+    match action {
+        TimerAction::Message(target, selector) => log_dbg!(
+            "New {} timer {:?}, interval {}s, target [{:?} {}], user info {:?}",
+            if repeats { "repeating" } else { "single-use" },
+            new,
+            ns_interval,
+            target,
+            selector.as_str(&env.mem),
+            user_info,
+        ),
+        TimerAction::Block(block) => log_dbg!(
+            "New {} timer {:?}, interval {}s, block {:?}",
+            if repeats { "repeating" } else { "single-use" },
+            new,
+            ns_interval,
+            block,
+        ),
+    }
+
+    autorelease(env, new)
+}
+
+fn schedule_timer(env: &mut Environment, timer: id) {
+    let run_loop: id = msg_class![env; NSRunLoop currentRunLoop];
+    let mode: id = ns_string::get_static_str(env, NSDefaultRunLoopMode);
+    let _: () = msg![env; run_loop addTimer:timer forMode:mode];
+}
+
+/// Call the timer's block, or send its message to its target. The caller must
+/// make sure the timer stays alive, since it may be invalidated by this.
+fn send_timer_action(env: &mut Environment, timer: id) {
+    let action = env.objc.borrow::<NSTimerHostObject>(timer).action;
+
+    let pool: id = msg_class![env; NSAutoreleasePool new];
+
+    match action {
+        TimerAction::Message(target, selector) => {
+            log_dbg!(
+                "Timer {:?} fired, sending {:?} message to {:?}",
+                timer,
+                selector.as_str(&env.mem),
+                target
+            );
+            // Signature should be `- (void)timerDidFire:(NSTimer *)which`.
+            let _: () = msg_send(env, (target, selector, timer));
+        }
+        TimerAction::Block(block) => {
+            log_dbg!("Timer {:?} fired, calling block {:?}", timer, block);
+            let invoke = block_invoke_function(&env.mem, block);
+            let _: () = invoke.call_from_host(env, (block, timer));
+        }
+    }
+
+    release(env, pool);
+}
+
+/// Work out when a repeating timer with the given interval should next fire,
+/// given when it was due and the current time. Also returns the number of
+/// intervals the timer was advanced by, which is more than 1 if it has missed
+/// some.
+///
+/// When rescheduling a repeating timer, the next firing should be based on
+/// when the timer should have fired, not when it actually fired, so that there
+/// is no drift over time.
+///
+/// For example, if a timer has an interval of 60s and starts at 00:00, the
+/// first firing would be scheduled for 01:00, and the second firing should be
+/// scheduled for 02:00, even if the first firing was at 01:01.
+///
+/// However: if the timer handling is delayed past a whole interval, it should
+/// not try to catch up. For example, if the first firing is scheduled for 01:00
+/// but happens at 02:30, then the next firing should be scheduled for 03:00.
+fn next_due_by(due_by: Instant, now: Instant, interval: Duration) -> (Instant, u32) {
+    let overdue_by = now.saturating_duration_since(due_by).as_nanos();
+    let interval_nanos = interval.as_nanos();
+    let advance_by = overdue_by.div_ceil(interval_nanos).max(1);
+    let advance_by = u32::try_from(advance_by).unwrap();
+    (
+        due_by
+            .checked_add(interval.checked_mul(advance_by).unwrap())
+            .unwrap(),
+        advance_by,
+    )
+}
+
 /// For use by `NSRunLoop`
 pub(super) fn set_run_loop(env: &mut Environment, timer: id, run_loop: id) {
     let host_object = env.objc.borrow_mut::<NSTimerHostObject>(timer);
@@ -166,10 +278,7 @@ pub(super) fn set_run_loop(env: &mut Environment, timer: id, run_loop: id) {
 /// Returns the next firing time, if any.
 pub(super) fn handle_timer(env: &mut Environment, timer: id) -> Option<Instant> {
     let &NSTimerHostObject {
-        ns_interval,
         rust_interval,
-        target,
-        selector,
         repeats,
         due_by,
         run_loop,
@@ -179,14 +288,12 @@ pub(super) fn handle_timer(env: &mut Environment, timer: id) -> Option<Instant>
     // invalidated timers should have already been removed from the run loop
     let due_by = due_by.unwrap();
 
-    let now = Instant::now();
+    let now = env.clock.now();
 
     if due_by > now {
         return Some(due_by);
     }
 
-    let overdue_by = now.duration_since(due_by);
-
     // Timer may be released when it's invalidated, so we need to retain it so
     // it's still around to pass to the timer target.
     retain(env, timer);
@@ -194,47 +301,65 @@ pub(super) fn handle_timer(env: &mut Environment, timer: id) -> Option<Instant>
     // Advancing the timer before sending its message seems like a good idea
     // considering this function is potentially re-entrant.
     let new_due_by = if repeats {
-        // When rescheduling a repeating timer, the next firing should be based
-        // on when the timer should have fired, not when it actually fired, so
-        // that there is no drift over time.
-        //
-        // For example, if a timer has an interval of 60s and starts at 00:00,
-        // the first firing would be scheduled for 01:00, and the second firing
-        // should be scheduled for 02:00, even if the first firing was at 01:01.
-        //
-        // However: if the timer handling is delayed past a whole interval, it
-        // should not try to catch up. For example, if the first firing is
-        // scheduled for 01:00 but happens at 02:30, then the next firing should
-        // be scheduled for 03:00.
-        // TODO: Use `.div_duration_f64()` once that is stabilized.
-        let advance_by = (overdue_by.as_secs_f64() / ns_interval).max(1.0).ceil();
-        assert!(advance_by == (advance_by as u32) as f64);
-        let advance_by = advance_by as u32;
+        let (new_due_by, advance_by) = next_due_by(due_by, now, rust_interval);
         if advance_by > 1 {
-            log_dbg!("Warning: Timer {:?} is lagging. It is overdue by {}s and has missed {} interval(s)!", timer, overdue_by.as_secs_f64(), advance_by - 1);
+            log_dbg!("Warning: Timer {:?} is lagging. It is overdue by {}s and has missed {} interval(s)!", timer, now.duration_since(due_by).as_secs_f64(), advance_by - 1);
         }
-        let advance_by = rust_interval.checked_mul(advance_by).unwrap();
-        Some(due_by.checked_add(advance_by).unwrap())
+        Some(new_due_by)
     } else {
         ns_run_loop::remove_timer(env, run_loop, timer);
         None
     };
     env.objc.borrow_mut::<NSTimerHostObject>(timer).due_by = new_due_by;
 
-    log_dbg!(
-        "Timer {:?} fired, sending {:?} message to {:?}",
-        timer,
-        selector.as_str(&env.mem),
-        target
-    );
-
-    let pool: id = msg_class![env; NSAutoreleasePool new];
+    send_timer_action(env, timer);
 
-    // Signature should be `- (void)timerDidFire:(NSTimer *)which`.
-    let _: () = msg_send(env, (target, selector, timer));
+    // The timer might have been invalidated by its target.
+    let new_due_by = env.objc.borrow::<NSTimerHostObject>(timer).due_by;
 
     release(env, timer);
-    release(env, pool);
 
     new_due_by
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repeating_timer_does_not_drift() {
+        // A 10Hz timer whose firings are always handled a little late should
+        // still fire 10 times a second, on the original schedule.
+        let start = Instant::now();
+        let interval = Duration::from_millis(100);
+        let lateness = Duration::from_millis(7);
+        let mut due_by = start + interval;
+        let mut fires = 0;
+        while due_by < start + Duration::from_secs(10) {
+            let (new_due_by, advance_by) = next_due_by(due_by, due_by + lateness, interval);
+            assert_eq!(advance_by, 1);
+            due_by = new_due_by;
+            fires += 1;
+        }
+        assert_eq!(fires, 99);
+        assert_eq!(due_by, start + Duration::from_secs(10));
+    }
+
+    #[test]
+    fn repeating_timer_skips_missed_intervals() {
+        let start = Instant::now();
+        let interval = Duration::from_secs(60);
+        // Handled on time
+        assert_eq!(next_due_by(start, start, interval), (start + interval, 1));
+        // Handled late, but within the interval
+        assert_eq!(
+            next_due_by(start, start + Duration::from_secs(1), interval),
+            (start + interval, 1)
+        );
+        // Handled 1.5 intervals late: one interval was missed
+        assert_eq!(
+            next_due_by(start, start + Duration::from_secs(90), interval),
+            (start + interval * 2, 2)
+        );
+    }
+}
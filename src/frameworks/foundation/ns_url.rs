@@ -97,7 +97,17 @@ pub const CLASSES: ClassExports = objc_classes! {
     }
 
     // FIXME: this should parse the URL
-    assert!(!to_rust_string(env, url).starts_with("file:")); // TODO
+    let url_str = to_rust_string(env, url);
+    if url_str.starts_with("file:") {
+        let Some(path) = file_url_to_path(&url_str) else {
+            log!("TODO: Unsupported file URL {:?}, returning nil", url_str);
+            release(env, this);
+            return nil;
+        };
+        let path = from_rust_string(env, path);
+        let path = autorelease(env, path);
+        return msg![env; this initFileURLWithPath:path];
+    }
     let url: id = msg![env; url copy];
     *env.objc.borrow_mut(this) = NSURLHostObject::OtherURL { ns_string: url };
     this
@@ -151,6 +161,10 @@ pub const CLASSES: ClassExports = objc_classes! {
     this
 }
 
+- (bool)isFileURL {
+    matches!(env.objc.borrow(this), NSURLHostObject::FileURL { .. })
+}
+
 - (bool)getFileSystemRepresentation:(MutPtr<u8>)buffer
                           maxLength:(NSUInteger)buffer_size {
     let &NSURLHostObject::FileURL { ns_string, .. } = env.objc.borrow(this) else {
@@ -177,3 +191,46 @@ pub fn to_rust_path(env: &mut Environment, url: id) -> Cow<'static, GuestPath> {
         Cow::Owned(path_buf) => Cow::Owned(path_buf.into()),
     }
 }
+
+/// Convert a `file://` URL string, e.g. `file:///foo/bar%20baz` or
+/// `file://localhost/foo/bar%20baz`, to a path (`/foo/bar baz`). Returns [None]
+/// if it's not a file URL for the local host or can't be decoded.
+pub fn file_url_to_path(url: &str) -> Option<String> {
+    let rest = url.strip_prefix("file://")?;
+    let rest = rest.strip_prefix("localhost").unwrap_or(rest);
+    if !rest.starts_with('/') {
+        return None;
+    }
+    let mut bytes = Vec::with_capacity(rest.len());
+    let mut iter = rest.bytes();
+    while let Some(byte) = iter.next() {
+        if byte == b'%' {
+            let hex = [iter.next()?, iter.next()?];
+            let hex = std::str::from_utf8(&hex).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+        } else {
+            bytes.push(byte);
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_urls() {
+        assert_eq!(
+            file_url_to_path("file:///foo/bar").as_deref(),
+            Some("/foo/bar")
+        );
+        assert_eq!(
+            file_url_to_path("file://localhost/foo/bar%20baz").as_deref(),
+            Some("/foo/bar baz")
+        );
+        assert_eq!(file_url_to_path("file://example.com/foo"), None);
+        assert_eq!(file_url_to_path("file:///foo%2"), None);
+        assert_eq!(file_url_to_path("http://localhost/foo"), None);
+    }
+}
@@ -4,9 +4,31 @@
  * file, You can obtain one at https://mozilla.org/MPL/2.0/.
  */
 //! `NSURLConnection`.
+//!
+//! Only synchronous GET requests to `http://` URLs are supported so far, using
+//! the minimal client in [crate::http]. Like sockets, this only works if
+//! networking has been enabled with the `--enable-networking` option.
 
-use crate::objc::{id, nil, ClassExports};
-use crate::objc_classes;
+use super::ns_error::NSURLErrorDomain;
+use super::ns_string::{get_static_str, to_rust_string};
+use super::ns_url_response::new_http_response;
+use super::{NSInteger, NSTimeInterval};
+use crate::http;
+use crate::libc::socket::SOCKET_POLL_INTERVAL;
+use crate::mem::{GuestUSize, MutPtr, Ptr};
+use crate::objc::{autorelease, id, msg, msg_class, nil, objc_classes, ClassExports};
+use crate::Environment;
+use std::io::ErrorKind;
+use std::sync::mpsc::{channel, TryRecvError};
+use std::time::Duration;
+
+// Error codes from `NSURLError.h`
+const NSURLErrorTimedOut: NSInteger = -1001;
+const NSURLErrorUnsupportedURL: NSInteger = -1002;
+const NSURLErrorCannotFindHost: NSInteger = -1003;
+const NSURLErrorCannotConnectToHost: NSInteger = -1004;
+const NSURLErrorNotConnectedToInternet: NSInteger = -1009;
+const NSURLErrorBadServerResponse: NSInteger = -1011;
 
 pub const CLASSES: ClassExports = objc_classes! {
 
@@ -14,6 +36,48 @@ pub const CLASSES: ClassExports = objc_classes! {
 
 @implementation NSURLConnection: NSObject
 
++ (id)sendSynchronousRequest:(id)request // NSURLRequest *
+           returningResponse:(MutPtr<id>)response_ptr // NSURLResponse **
+                       error:(MutPtr<id>)error_ptr { // NSError **
+    let url: id = msg![env; request URL];
+    let timeout: NSTimeInterval = msg![env; request timeoutInterval];
+    let url_str: id = msg![env; url description];
+    let url_str = to_rust_string(env, url_str);
+    log_dbg!("[NSURLConnection sendSynchronousRequest:{:?} ...] to {}", request, url_str);
+
+    match send_request(env, &url_str, timeout) {
+        Ok(response) => {
+            if !response_ptr.is_null() {
+                let content_length = response.body.len().try_into().unwrap();
+                let ns_response =
+                    new_http_response(env, url, response.status_code.into(), content_length);
+                env.mem.write(response_ptr, ns_response);
+            }
+            let length: GuestUSize = response.body.len().try_into().unwrap();
+            let bytes = if length == 0 {
+                Ptr::null()
+            } else {
+                let bytes = env.mem.alloc(length);
+                env.mem
+                    .bytes_at_mut(bytes.cast(), length)
+                    .copy_from_slice(&response.body);
+                bytes
+            };
+            msg_class![env; NSData dataWithBytesNoCopy:bytes length:length]
+        }
+        Err(code) => {
+            if !error_ptr.is_null() {
+                let domain = get_static_str(env, NSURLErrorDomain);
+                let error = msg_class![env; NSError alloc];
+                let error = msg![env; error initWithDomain:domain code:code userInfo:nil];
+                let error = autorelease(env, error);
+                env.mem.write(error_ptr, error);
+            }
+            nil
+        }
+    }
+}
+
 - (id)initWithRequest:(id)request // NSURLRequest *
              delegate:(id)delegate
      startImmediately:(bool)start_immediately {
@@ -30,3 +94,54 @@ pub const CLASSES: ClassExports = objc_classes! {
 @end
 
 };
+
+/// Make a GET request on a host thread, running other guest threads while
+/// waiting for it to finish. On failure, the `NSURLError` code is returned.
+pub(super) fn send_request(
+    env: &mut Environment,
+    url_str: &str,
+    timeout: NSTimeInterval,
+) -> Result<http::Response, NSInteger> {
+    let Ok(url) = http::parse_url(url_str) else {
+        log!("TODO: Unsupported URL for NSURLConnection: {:?}", url_str);
+        return Err(NSURLErrorUnsupportedURL);
+    };
+
+    if !env.options.network_access {
+        log!(
+            "Warning: The app tried to fetch {}, but networking is disabled. Use the --enable-networking option to allow this.",
+            url_str
+        );
+        return Err(NSURLErrorNotConnectedToInternet);
+    }
+
+    // A timeout of zero isn't accepted by the host, and isn't useful anyway.
+    let timeout = Duration::from_secs_f64(if timeout > 0.0 { timeout } else { 60.0 });
+    let (sender, receiver) = channel();
+    std::thread::spawn(move || {
+        // Nobody is listening any more if the emulator exited meanwhile.
+        let _ = sender.send(http::get(&url, timeout));
+    });
+    let result = loop {
+        match receiver.try_recv() {
+            Ok(result) => break result,
+            Err(TryRecvError::Empty) => env.sleep(SOCKET_POLL_INTERVAL, false),
+            Err(TryRecvError::Disconnected) => panic!("HTTP request thread went away"),
+        }
+    };
+
+    result.map_err(|e| {
+        log!("Warning: Request to {} failed: {:?}", url_str, e);
+        match e {
+            http::Error::UnsupportedUrl => NSURLErrorUnsupportedURL,
+            http::Error::HostNotFound => NSURLErrorCannotFindHost,
+            http::Error::Io(e)
+                if matches!(e.kind(), ErrorKind::TimedOut | ErrorKind::WouldBlock) =>
+            {
+                NSURLErrorTimedOut
+            }
+            http::Error::Io(_) => NSURLErrorCannotConnectToHost,
+            http::Error::BadResponse => NSURLErrorBadServerResponse,
+        }
+    })
+}
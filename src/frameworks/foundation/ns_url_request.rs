@@ -5,12 +5,24 @@
  */
 //! `NSURLRequest and NSMutableURLRequest`.
 
+use super::ns_string::{get_static_str, to_rust_string};
 use super::{NSTimeInterval, NSUInteger};
-use crate::frameworks::foundation::ns_string::to_rust_string;
-use crate::msg;
-use crate::objc::{id, nil, objc_classes, ClassExports};
+use crate::objc::{
+    autorelease, id, msg, nil, objc_classes, release, retain, ClassExports, HostObject, NSZonePtr,
+};
 
 type NSURLRequestCachePolicy = NSUInteger;
+const NSURLRequestUseProtocolCachePolicy: NSURLRequestCachePolicy = 0;
+
+/// Apple's default timeout, in seconds.
+const DEFAULT_TIMEOUT_INTERVAL: NSTimeInterval = 60.0;
+
+struct NSURLRequestHostObject {
+    /// Strong reference
+    url: id,
+    timeout_interval: NSTimeInterval,
+}
+impl HostObject for NSURLRequestHostObject {}
 
 pub const CLASSES: ClassExports = objc_classes! {
 
@@ -18,20 +30,80 @@ pub const CLASSES: ClassExports = objc_classes! {
 
 @implementation NSURLRequest: NSObject
 
-+ (id)requestWithURL:(id)url
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::new(NSURLRequestHostObject {
+        url: nil,
+        timeout_interval: DEFAULT_TIMEOUT_INTERVAL,
+    });
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
++ (id)requestWithURL:(id)url { // NSURL *
+    let new: id = msg![env; this alloc];
+    let new: id = msg![env; new initWithURL:url];
+    autorelease(env, new)
+}
+
++ (id)requestWithURL:(id)url // NSURL *
          cachePolicy:(NSURLRequestCachePolicy)cache_policy
      timeoutInterval:(NSTimeInterval)timeout_interval {
+    let new: id = msg![env; this alloc];
+    let new: id = msg![env; new initWithURL:url
+                                cachePolicy:cache_policy
+                            timeoutInterval:timeout_interval];
+    autorelease(env, new)
+}
+
+- (id)initWithURL:(id)url { // NSURL *
+    msg![env; this initWithURL:url
+                   cachePolicy:NSURLRequestUseProtocolCachePolicy
+               timeoutInterval:DEFAULT_TIMEOUT_INTERVAL]
+}
+
+- (id)initWithURL:(id)url // NSURL *
+      cachePolicy:(NSURLRequestCachePolicy)cache_policy
+  timeoutInterval:(NSTimeInterval)timeout_interval {
     if url == nil {
+        release(env, this);
         return nil;
     }
-    let url_str: id = msg![env; url path];
-    log!(
-        "TODO: [NSURLRequest requestWithURL:{} cachePolicy:{} timeoutInterval:{}]",
-        to_rust_string(env, url_str),
-        cache_policy,
-        timeout_interval,
-    );
-    nil
+    if cache_policy != NSURLRequestUseProtocolCachePolicy {
+        let url_str: id = msg![env; url description];
+        log!(
+            "TODO: ignoring cache policy {} for request to {}",
+            cache_policy,
+            to_rust_string(env, url_str),
+        );
+    }
+    let url: id = msg![env; url copy];
+    let host_object = env.objc.borrow_mut::<NSURLRequestHostObject>(this);
+    host_object.url = url;
+    host_object.timeout_interval = timeout_interval;
+    this
+}
+
+- (())dealloc {
+    let url = env.objc.borrow::<NSURLRequestHostObject>(this).url;
+    release(env, url);
+    env.objc.dealloc_object(this, &mut env.mem)
+}
+
+// NSCopying implementation
+- (id)copyWithZone:(NSZonePtr)_zone {
+    retain(env, this)
+}
+
+- (id)URL {
+    env.objc.borrow::<NSURLRequestHostObject>(this).url
+}
+
+- (NSTimeInterval)timeoutInterval {
+    env.objc.borrow::<NSURLRequestHostObject>(this).timeout_interval
+}
+
+- (id)HTTPMethod {
+    // TODO: other methods
+    get_static_str(env, "GET")
 }
 
 @end
@@ -0,0 +1,84 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `NSURLResponse` and `NSHTTPURLResponse`.
+
+use super::NSInteger;
+use crate::objc::{
+    autorelease, id, nil, objc_classes, release, retain, ClassExports, HostObject, NSZonePtr,
+};
+use crate::{msg_class, Environment};
+
+struct NSURLResponseHostObject {
+    /// Strong reference
+    url: id,
+    expected_content_length: i64,
+    /// Only meaningful for `NSHTTPURLResponse`.
+    status_code: NSInteger,
+}
+impl HostObject for NSURLResponseHostObject {}
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+@implementation NSURLResponse: NSObject
+
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::new(NSURLResponseHostObject {
+        url: nil,
+        // NSURLResponseUnknownLength
+        expected_content_length: -1,
+        status_code: 0,
+    });
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
+- (())dealloc {
+    let url = env.objc.borrow::<NSURLResponseHostObject>(this).url;
+    release(env, url);
+    env.objc.dealloc_object(this, &mut env.mem)
+}
+
+// NSCopying implementation
+- (id)copyWithZone:(NSZonePtr)_zone {
+    retain(env, this)
+}
+
+- (id)URL {
+    env.objc.borrow::<NSURLResponseHostObject>(this).url
+}
+
+- (i64)expectedContentLength {
+    env.objc.borrow::<NSURLResponseHostObject>(this).expected_content_length
+}
+
+@end
+
+@implementation NSHTTPURLResponse: NSURLResponse
+
+- (NSInteger)statusCode {
+    env.objc.borrow::<NSURLResponseHostObject>(this).status_code
+}
+
+@end
+
+};
+
+/// Create an autoreleased `NSHTTPURLResponse`, for use by `NSURLConnection`.
+pub fn new_http_response(
+    env: &mut Environment,
+    url: id, // NSURL *
+    status_code: NSInteger,
+    content_length: i64,
+) -> id {
+    let response: id = msg_class![env; NSHTTPURLResponse alloc];
+    retain(env, url);
+    let host_object = env.objc.borrow_mut::<NSURLResponseHostObject>(response);
+    host_object.url = url;
+    host_object.expected_content_length = content_length;
+    host_object.status_code = status_code;
+    autorelease(env, response)
+}
@@ -26,6 +26,14 @@ impl State {
     }
 }
 
+/// Save the standard user defaults, if they have been used. This is done when
+/// the app exits.
+pub fn synchronize_standard_defaults(env: &mut Environment) {
+    if let Some(defaults) = State::get(env).standard_defaults {
+        let _: bool = msg![env; defaults synchronize];
+    }
+}
+
 struct NSUserDefaultsHostObject {
     /// Defaults meant to be seen by all applications.
     /// *Does NOT* persist on disk.
@@ -88,6 +96,14 @@ pub const CLASSES: ClassExports = objc_classes! {
     let dict: id = msg_class![env; NSDictionary dictionaryWithContentsOfFile:plist_file_path];
 
     let dict: id = if dict == nil {
+        // The file is usually just absent, but if it's unreadable, the app
+        // should still be able to start, just with its settings reset.
+        if env.fs.exists(&plist_file_path_buf) {
+            log!(
+                "Warning: Couldn't load user defaults from {:?}, ignoring them.",
+                plist_file_path_buf
+            );
+        }
         msg_class![env; NSMutableDictionary new]
     } else {
         msg![env; dict mutableCopy]
@@ -4,15 +4,21 @@
  * file, You can obtain one at https://mozilla.org/MPL/2.0/.
  */
 //! `MPMoviePlayerController` etc.
+//!
+//! There is no video playback yet. The movie's audio track, if any, is played
+//! using `AVAudioPlayer`, and playback is considered finished once the audio
+//! has had time to finish.
 
 use crate::dyld::{ConstantExports, HostConstant};
-use crate::frameworks::foundation::{ns_string, ns_url, NSInteger};
+use crate::frameworks::foundation::{ns_string, ns_url, NSInteger, NSTimeInterval};
 use crate::frameworks::uikit::ui_device::UIDeviceOrientation;
+use crate::mem::MutPtr;
 use crate::objc::{
     id, msg, msg_class, nil, objc_classes, release, retain, ClassExports, HostObject, NSZonePtr,
 };
 use crate::Environment;
 use std::collections::VecDeque;
+use std::time::{Duration, Instant};
 
 #[derive(Default)]
 pub struct State {
@@ -62,9 +68,20 @@ pub const CONSTANTS: ConstantExports = &[
 struct MPMoviePlayerControllerHostObject {
     // NSURL *
     content_url: id,
+    /// `AVAudioPlayer*` for the movie's audio track, or `nil` if there is no
+    /// playable audio or playback hasn't started yet.
+    audio_player: id,
+    /// When the current playback will finish, if playing.
+    finishes_at: Option<Instant>,
 }
 impl HostObject for MPMoviePlayerControllerHostObject {}
 
+/// Returns [true] if the app has any movie players.
+pub fn has_players(env: &Environment) -> bool {
+    env.objc
+        .has_host_objects_of_type::<MPMoviePlayerControllerHostObject>()
+}
+
 pub const CLASSES: ClassExports = objc_classes! {
 
 (env, this, _cmd);
@@ -76,6 +93,8 @@ pub const CLASSES: ClassExports = objc_classes! {
 + (id)allocWithZone:(NSZonePtr)_zone {
     let host_object = Box::new(MPMoviePlayerControllerHostObject {
         content_url: nil,
+        audio_player: nil,
+        finishes_at: None,
     });
     env.objc.alloc_object(this, host_object, &mut env.mem)
 }
@@ -100,8 +119,13 @@ pub const CLASSES: ClassExports = objc_classes! {
 }
 
 - (())dealloc {
-    let url = env.objc.borrow::<MPMoviePlayerControllerHostObject>(this).content_url;
-    release(env, url);
+    let &MPMoviePlayerControllerHostObject {
+        content_url,
+        audio_player,
+        ..
+    } = env.objc.borrow(this);
+    release(env, content_url);
+    release(env, audio_player);
 
     env.objc.dealloc_object(this, &mut env.mem);
 }
@@ -143,7 +167,7 @@ pub const CLASSES: ClassExports = objc_classes! {
 
 // MPMediaPlayback implementation
 - (())play {
-    log!("TODO: [(MPMoviePlayerController*){:?} play]", this);
+    log!("TODO: [(MPMoviePlayerController*){:?} play] (audio only)", this);
     if let Some(old) = env.framework_state.media_player.movie_player.active_player {
         let _: () = msg![env; old stop];
     }
@@ -152,14 +176,35 @@ pub const CLASSES: ClassExports = objc_classes! {
     retain(env, this);
     env.framework_state.media_player.movie_player.active_player = Some(this);
 
-    // Act as if playback immediately completed (various apps wait for this).
-    State::get(env).pending_notifications.push_back(
-        (MPMoviePlayerPlaybackDidFinishNotification, this)
-    );
+    let mut audio_player = env.objc.borrow::<MPMoviePlayerControllerHostObject>(this).audio_player;
+    if audio_player == nil {
+        let url = env.objc.borrow::<MPMoviePlayerControllerHostObject>(this).content_url;
+        let new: id = msg_class![env; AVAudioPlayer alloc];
+        audio_player = msg![env; new initWithContentsOfURL:url error:(MutPtr::<id>::null())];
+        env.objc.borrow_mut::<MPMoviePlayerControllerHostObject>(this).audio_player = audio_player;
+    }
+
+    if audio_player == nil {
+        // Act as if playback immediately completed (various apps wait for
+        // this).
+        log!("Couldn't play the movie's audio, finishing playback immediately.");
+        State::get(env).pending_notifications.push_back(
+            (MPMoviePlayerPlaybackDidFinishNotification, this)
+        );
+        return;
+    }
+
+    () = msg![env; audio_player setCurrentTime:0.0];
+    let duration: NSTimeInterval = msg![env; audio_player duration];
+    let _: bool = msg![env; audio_player play];
+    log_dbg!("Movie {:?} will finish in {}s", this, duration);
+    env.objc.borrow_mut::<MPMoviePlayerControllerHostObject>(this).finishes_at =
+        Some(finish_time(env.clock.now(), duration));
 }
 
 - (())stop {
-    log!("TODO: [(MPMoviePlayerController*){:?} stop]", this);
+    log_dbg!("[(MPMoviePlayerController*){:?} stop]", this);
+    stop_audio(env, this);
     assert!(this == env.framework_state.media_player.movie_player.active_player.take().unwrap());
     release(env, this);
 }
@@ -168,9 +213,40 @@ pub const CLASSES: ClassExports = objc_classes! {
 
 };
 
+/// When playback that started at `start` will finish. Nonsensical durations
+/// mean finishing immediately.
+fn finish_time(start: Instant, duration: NSTimeInterval) -> Instant {
+    start + Duration::try_from_secs_f64(duration).unwrap_or(Duration::ZERO)
+}
+
+fn stop_audio(env: &mut Environment, player: id) {
+    let host_object = env
+        .objc
+        .borrow_mut::<MPMoviePlayerControllerHostObject>(player);
+    host_object.finishes_at = None;
+    let audio_player = host_object.audio_player;
+    if audio_player != nil {
+        () = msg![env; audio_player stop];
+    }
+}
+
 /// For use by `NSRunLoop` via [super::handle_players]: check movie players'
 /// status, send notifications if necessary.
 pub(super) fn handle_players(env: &mut Environment) {
+    if let Some(player) = State::get(env).active_player {
+        let finishes_at = env
+            .objc
+            .borrow::<MPMoviePlayerControllerHostObject>(player)
+            .finishes_at;
+        if finishes_at.is_some_and(|finishes_at| env.clock.now() >= finishes_at) {
+            log_dbg!("Movie {:?} finished playing", player);
+            stop_audio(env, player);
+            State::get(env)
+                .pending_notifications
+                .push_back((MPMoviePlayerPlaybackDidFinishNotification, player));
+        }
+    }
+
     while let Some(notif) = State::get(env).pending_notifications.pop_front() {
         let (name, object) = notif;
         let name = ns_string::get_static_str(env, name);
@@ -179,3 +255,17 @@ pub(super) fn handle_players(env: &mut Environment) {
         let _: () = msg![env; center postNotificationName:name object:object];
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finishes_after_duration() {
+        let start = Instant::now();
+        assert_eq!(finish_time(start, 2.5), start + Duration::from_millis(2500));
+        assert_eq!(finish_time(start, 0.0), start);
+        assert_eq!(finish_time(start, -1.0), start);
+        assert_eq!(finish_time(start, f64::NAN), start);
+    }
+}
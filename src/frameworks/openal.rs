@@ -14,9 +14,11 @@
 use crate::audio::openal as al;
 use crate::audio::openal::al_types::*;
 use crate::audio::openal::alc_types::*;
+use crate::audio::{open_output_device, OutputDevice};
 use crate::dyld::{export_c_func, FunctionExports};
+use crate::frameworks::audio_toolbox::ContextManager;
 use crate::libc::string::strcmp;
-use crate::mem::{ConstPtr, ConstVoidPtr, GuestUSize, MutPtr, MutVoidPtr, Ptr, SafeWrite};
+use crate::mem::{ConstPtr, ConstVoidPtr, GuestUSize, Mem, MutPtr, MutVoidPtr, Ptr, SafeWrite};
 use crate::Environment;
 use std::collections::HashMap;
 use std::ffi::{CStr, CString};
@@ -24,8 +26,25 @@ use touchHLE_openal_soft_wrapper::ALC_DEVICE_SPECIFIER;
 
 #[derive(Default)]
 pub struct State {
-    devices: HashMap<MutPtr<GuestALCdevice>, *mut ALCdevice>,
+    devices: HashMap<MutPtr<GuestALCdevice>, OutputDevice>,
     contexts: HashMap<MutPtr<GuestALCcontext>, *mut ALCcontext>,
+    sources: HashMap<ALuint, SourceInfo>,
+    /// See [set_muted].
+    muted: bool,
+    /// Listener gain of each context before it was muted, see [set_muted].
+    muted_gains: HashMap<*mut ALCcontext, ALfloat>,
+}
+
+/// What touchHLE knows about how the app is using a source, beyond what
+/// OpenAL Soft tracks.
+#[derive(Default, Copy, Clone)]
+struct SourceInfo {
+    /// The app has called `alSourcePlay` and hasn't stopped, paused or
+    /// rewound the source since.
+    playing: bool,
+    /// The app has unqueued buffers from the source while playing it, so it's
+    /// presumably streaming audio by refilling and requeueing them.
+    streaming: bool,
 }
 impl State {
     fn get(env: &mut Environment) -> &mut Self {
@@ -44,6 +63,66 @@ struct GuestALCcontext {
 }
 impl SafeWrite for GuestALCcontext {}
 
+/// Returns [true] if the app has any OpenAL devices or contexts open.
+pub fn has_devices(env: &Environment) -> bool {
+    let state = &env.framework_state.openal;
+    !state.devices.is_empty() || !state.contexts.is_empty()
+}
+
+/// Mute or unmute all audio output, both the app's OpenAL contexts and
+/// touchHLE's internal one (used by Audio Toolbox), including contexts created
+/// while muted. This is used while fast-forwarding (see
+/// [crate::options::Options::fast_forward_mute]).
+///
+/// Muting sets each context's listener gain to zero, and unmuting restores it,
+/// unless the app has set a new gain in the meantime.
+pub fn set_muted(env: &mut Environment, muted: bool) {
+    env.framework_state.audio_toolbox.set_muted(muted);
+
+    let state = State::get(env);
+    state.muted = muted;
+    let host_contexts: Vec<*mut ALCcontext> = state.contexts.values().copied().collect();
+    for host_context in host_contexts {
+        if muted {
+            mute_context(&mut state.muted_gains, host_context);
+        } else if let Some(gain) = state.muted_gains.remove(&host_context) {
+            let _context_manager = ContextManager::make_active(host_context);
+            let mut current_gain: ALfloat = 0.0;
+            unsafe { al::alGetListenerf(al::AL_GAIN, &mut current_gain) };
+            if current_gain == 0.0 {
+                unsafe { al::alListenerf(al::AL_GAIN, gain) };
+            }
+        }
+    }
+    log_dbg!("set_muted({})", muted);
+}
+
+/// Render the output of any devices that are silent fallbacks (see
+/// [OutputDevice::render_if_silent]), including touchHLE's internal one (used
+/// by Audio Toolbox), so that playback makes progress without audio output.
+/// This is called regularly by the run loop.
+pub fn render_silent_devices(env: &mut Environment) {
+    env.framework_state.audio_toolbox.render_silent_device();
+
+    for device in State::get(env).devices.values_mut() {
+        device.render_if_silent();
+    }
+}
+
+fn mute_context(
+    muted_gains: &mut HashMap<*mut ALCcontext, ALfloat>,
+    host_context: *mut ALCcontext,
+) {
+    if muted_gains.contains_key(&host_context) {
+        return;
+    }
+    let _context_manager = ContextManager::make_active(host_context);
+    let mut gain: ALfloat = 1.0;
+    unsafe { al::alGetListenerf(al::AL_GAIN, &mut gain) };
+    muted_gains.insert(host_context, gain);
+    unsafe { al::alListenerf(al::AL_GAIN, 0.0) };
+}
+
 // === alc.h ===
 
 fn alcOpenDevice(env: &mut Environment, devicename: ConstPtr<u8>) -> MutPtr<GuestALCdevice> {
@@ -56,27 +135,24 @@ fn alcOpenDevice(env: &mut Environment, devicename: ConstPtr<u8>) -> MutPtr<Gues
         env.mem.free(d_name.cast_mut().cast());
     }
 
-    let res = unsafe { al::alcOpenDevice(std::ptr::null()) };
-    if res.is_null() {
-        log_dbg!("alcOpenDevice(NULL) returned NULL");
-        return Ptr::null();
-    }
+    // This never fails, there's a silent fallback if no real device works.
+    let res = open_output_device();
 
     let guest_res = env.mem.alloc_and_write(GuestALCdevice { _filler: 0 });
     State::get(env).devices.insert(guest_res, res);
-    log_dbg!("alcOpenDevice(NULL) => {:?} (host: {:?})", guest_res, res,);
+    log_dbg!("alcOpenDevice(NULL) => {:?} (host: {:?})", guest_res, res);
     guest_res
 }
 fn alcCloseDevice(env: &mut Environment, device: MutPtr<GuestALCdevice>) -> bool {
     let host_device = State::get(env).devices.remove(&device).unwrap();
     env.mem.free(device.cast());
-    let res = unsafe { al::alcCloseDevice(host_device) };
+    let res = unsafe { al::alcCloseDevice(host_device.raw) };
     log_dbg!("alcCloseDevice({:?}) => {:?}", device, res,);
     res != al::ALC_FALSE
 }
 
 fn alcGetError(env: &mut Environment, device: MutPtr<GuestALCdevice>) -> i32 {
-    let &host_device = State::get(env).devices.get(&device).unwrap();
+    let host_device = State::get(env).devices.get(&device).unwrap().raw;
 
     let res = unsafe { al::alcGetError(host_device) };
     log_dbg!("alcGetError({:?}) => {:#x}", host_device, res);
@@ -104,16 +180,20 @@ fn alcCreateContext(
 ) -> MutPtr<GuestALCcontext> {
     assert!(attrlist.is_null()); // unimplemented
 
-    let &host_device = State::get(env).devices.get(&device).unwrap();
+    let host_device = *State::get(env).devices.get(&device).unwrap();
 
-    let res = unsafe { al::alcCreateContext(host_device, std::ptr::null()) };
+    let res = host_device.create_context();
     if res.is_null() {
         log_dbg!("alcCreateContext({:?}, NULL) returned NULL", device);
         return Ptr::null();
     }
 
     let guest_res = env.mem.alloc_and_write(GuestALCcontext { _filler: 0 });
-    State::get(env).contexts.insert(guest_res, res);
+    let state = State::get(env);
+    state.contexts.insert(guest_res, res);
+    if state.muted {
+        mute_context(&mut state.muted_gains, res);
+    }
     log_dbg!(
         "alcCreateContext({:?}, NULL) => {:?} (host: {:?})",
         device,
@@ -124,6 +204,7 @@ fn alcCreateContext(
 }
 fn alcDestroyContext(env: &mut Environment, context: MutPtr<GuestALCcontext>) {
     let host_context = State::get(env).contexts.remove(&context).unwrap();
+    State::get(env).muted_gains.remove(&host_context);
     env.mem.free(context.cast());
     unsafe { al::alcDestroyContext(host_context) };
     log_dbg!("alcDestroyContext({:?})", context);
@@ -180,7 +261,7 @@ fn alcGetContextsDevice(
     *State::get(env)
         .devices
         .iter()
-        .find(|(&_guest, &host)| host == host_device)
+        .find(|(&_guest, &host)| host.raw == host_device)
         .unwrap()
         .0
 }
@@ -246,13 +327,73 @@ fn alIsSource(_env: &mut Environment, source: ALuint) -> ALboolean {
     unsafe { al::alIsSource(source) }
 }
 
+/// Number of values taken by a listener parameter, e.g. 6 for
+/// `AL_ORIENTATION` (an "at" vector followed by an "up" vector).
+fn listener_value_count(param: ALenum) -> GuestUSize {
+    match param {
+        al::AL_GAIN => 1,
+        al::AL_POSITION | al::AL_VELOCITY => 3,
+        al::AL_ORIENTATION => 6,
+        _ => {
+            log!(
+                "Warning: Unknown OpenAL listener parameter {:#x}, assuming 3 values",
+                param
+            );
+            3
+        }
+    }
+}
+
+/// The OpenAL functions that the floating-point listener parameters are
+/// forwarded to. This is a trait so that the forwarding can be tested without
+/// OpenAL Soft.
+trait ListenerBackend {
+    fn listenerfv(&mut self, param: ALenum, values: &[ALfloat]);
+    fn get_listenerfv(&mut self, param: ALenum, values: &mut [ALfloat]);
+}
+struct OpenALSoft;
+impl ListenerBackend for OpenALSoft {
+    fn listenerfv(&mut self, param: ALenum, values: &[ALfloat]) {
+        assert_eq!(values.len(), listener_value_count(param) as usize);
+        unsafe { al::alListenerfv(param, values.as_ptr()) };
+    }
+    fn get_listenerfv(&mut self, param: ALenum, values: &mut [ALfloat]) {
+        assert_eq!(values.len(), listener_value_count(param) as usize);
+        unsafe { al::alGetListenerfv(param, values.as_mut_ptr()) };
+    }
+}
+
+/// Shared implementation of `alListenerfv`, which reads as many values as
+/// `param` takes from guest memory.
+fn set_listenerfv(
+    backend: &mut impl ListenerBackend,
+    mem: &Mem,
+    param: ALenum,
+    values: ConstPtr<ALfloat>,
+) {
+    let count = listener_value_count(param);
+    let values = mem.ptr_at(values, count);
+    let values = unsafe { std::slice::from_raw_parts(values, count as usize) };
+    backend.listenerfv(param, values);
+}
+/// Shared implementation of `alGetListenerfv`.
+fn get_listenerfv(
+    backend: &mut impl ListenerBackend,
+    mem: &mut Mem,
+    param: ALenum,
+    values: MutPtr<ALfloat>,
+) {
+    let count = listener_value_count(param);
+    let values = mem.ptr_at_mut(values, count);
+    let values = unsafe { std::slice::from_raw_parts_mut(values, count as usize) };
+    backend.get_listenerfv(param, values);
+}
+
 fn alListenerf(_env: &mut Environment, param: ALenum, value: ALfloat) {
     unsafe { al::alListenerf(param, value) };
 }
 fn alListenerfv(env: &mut Environment, param: ALenum, values: ConstPtr<ALfloat>) {
-    // we assume that at least 1 parameter should be passed
-    let values = env.mem.ptr_at(values, 1);
-    unsafe { al::alListenerfv(param, values) };
+    set_listenerfv(&mut OpenALSoft, &env.mem, param, values);
 }
 fn alListener3f(
     _env: &mut Environment,
@@ -278,7 +419,7 @@ fn alListener3i(
     unsafe { al::alListener3i(param, value1, value2, value3) };
 }
 fn alListeneriv(env: &mut Environment, param: ALenum, values: ConstPtr<ALint>) {
-    let values = env.mem.ptr_at(values, 3); // upper bound
+    let values = env.mem.ptr_at(values, listener_value_count(param));
     unsafe { al::alListeneriv(param, values) };
 }
 
@@ -300,8 +441,7 @@ fn alGetListener3f(
     env.mem.write(value3, values[2]);
 }
 fn alGetListenerfv(env: &mut Environment, param: ALenum, values: MutPtr<ALfloat>) {
-    let values = env.mem.ptr_at_mut(values, 3); // upper bound
-    unsafe { al::alGetListenerfv(param, values) };
+    get_listenerfv(&mut OpenALSoft, &mut env.mem, param, values);
 }
 fn alGetListeneri(env: &mut Environment, param: ALenum, value: MutPtr<ALint>) {
     unsafe { al::alGetListeneri(param, env.mem.ptr_at_mut(value, 1)) };
@@ -321,7 +461,7 @@ fn alGetListener3i(
     env.mem.write(value3, values[2]);
 }
 fn alGetListeneriv(env: &mut Environment, param: ALenum, values: MutPtr<ALint>) {
-    let values = env.mem.ptr_at_mut(values, 3); // upper bound
+    let values = env.mem.ptr_at_mut(values, listener_value_count(param));
     unsafe { al::alGetListeneriv(param, values) };
 }
 
@@ -332,6 +472,10 @@ fn alGenSources(env: &mut Environment, n: ALsizei, sources: MutPtr<ALuint>) {
 }
 fn alDeleteSources(env: &mut Environment, n: ALsizei, sources: ConstPtr<ALuint>) {
     let n_usize: GuestUSize = n.try_into().unwrap();
+    for i in 0..n_usize {
+        let source = env.mem.read(sources + i);
+        State::get(env).sources.remove(&source);
+    }
     let sources = env.mem.ptr_at(sources, n_usize);
     unsafe { al::alDeleteSources(n, sources) };
 }
@@ -431,19 +575,52 @@ fn alGetSourceiv(env: &mut Environment, source: ALuint, param: ALenum, values: M
     unsafe { al::alGetSourceiv(source, param, values) };
 }
 
-fn alSourcePlay(_env: &mut Environment, source: ALuint) {
+fn alSourcePlay(env: &mut Environment, source: ALuint) {
+    State::get(env).sources.insert(
+        source,
+        SourceInfo {
+            playing: true,
+            streaming: false,
+        },
+    );
     unsafe { al::alSourcePlay(source) };
 }
-fn alSourcePause(_env: &mut Environment, source: ALuint) {
+fn alSourcePause(env: &mut Environment, source: ALuint) {
+    State::get(env).sources.remove(&source);
     unsafe { al::alSourcePause(source) };
 }
-fn alSourceStop(_env: &mut Environment, source: ALuint) {
+fn alSourceStop(env: &mut Environment, source: ALuint) {
+    State::get(env).sources.remove(&source);
     unsafe { al::alSourceStop(source) };
 }
-fn alSourceRewind(_env: &mut Environment, source: ALuint) {
+fn alSourceRewind(env: &mut Environment, source: ALuint) {
+    State::get(env).sources.remove(&source);
     unsafe { al::alSourceRewind(source) };
 }
 
+/// Queue buffers on a source. If the source stopped because it ran out of
+/// buffers while the app was streaming to it, it's restarted.
+///
+/// This matters because touchHLE is slower to get back to the app than a
+/// real device, so a streaming source is more likely to run dry before the app
+/// refills it. On a real device the app would probably have been in time, so
+/// some apps don't check whether the source is still playing.
+unsafe fn queue_buffers(source: ALuint, info: SourceInfo, buffers: &[ALuint]) {
+    al::alSourceQueueBuffers(source, buffers.len().try_into().unwrap(), buffers.as_ptr());
+    if !(info.playing && info.streaming) || buffers.is_empty() {
+        return;
+    }
+    let mut state = 0;
+    al::alGetSourcei(source, al::AL_SOURCE_STATE, &mut state);
+    if state == al::AL_STOPPED {
+        log_dbg!(
+            "Source {} ran out of buffers while streaming, restarting it",
+            source
+        );
+        al::alSourcePlay(source);
+    }
+}
+
 fn alSourceQueueBuffers(
     env: &mut Environment,
     source: ALuint,
@@ -451,8 +628,14 @@ fn alSourceQueueBuffers(
     buffers: ConstPtr<ALuint>,
 ) {
     let nb_usize: GuestUSize = nb.try_into().unwrap();
+    let info = State::get(env)
+        .sources
+        .get(&source)
+        .copied()
+        .unwrap_or_default();
     let buffers = env.mem.ptr_at(buffers, nb_usize);
-    unsafe { al::alSourceQueueBuffers(source, nb, buffers) }
+    let buffers = unsafe { std::slice::from_raw_parts(buffers, nb_usize as usize) };
+    unsafe { queue_buffers(source, info, buffers) }
 }
 fn alSourceUnqueueBuffers(
     env: &mut Environment,
@@ -487,6 +670,12 @@ fn alSourceUnqueueBuffers(
         nb
     };
 
+    if nb > 0 {
+        if let Some(info) = State::get(env).sources.get_mut(&source) {
+            info.streaming = true;
+        }
+    }
+
     let nb_usize: GuestUSize = nb.try_into().unwrap();
     let buffers = env.mem.ptr_at_mut(buffers, nb_usize);
     unsafe { al::alSourceUnqueueBuffers(source, nb, buffers) }
@@ -748,3 +937,158 @@ pub const FUNCTIONS: FunctionExports = &[
     export_c_func!(alSourceRewindv(_, _)),
     export_c_func!(alSpeedOfSound(_)),
 ];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Stands in for OpenAL Soft, storing the listener parameters it's given.
+    #[derive(Default)]
+    struct MockBackend {
+        params: HashMap<ALenum, Vec<ALfloat>>,
+    }
+    impl ListenerBackend for MockBackend {
+        fn listenerfv(&mut self, param: ALenum, values: &[ALfloat]) {
+            self.params.insert(param, values.to_vec());
+        }
+        fn get_listenerfv(&mut self, param: ALenum, values: &mut [ALfloat]) {
+            values.copy_from_slice(&self.params[&param]);
+        }
+    }
+
+    #[test]
+    fn listener_parameter_forwarding() {
+        let mut mem = Mem::new();
+        mem.set_null_segment_size(0x1000);
+        let mut backend = MockBackend::default();
+        let values: MutPtr<ALfloat> = mem.alloc(6 * 4).cast();
+
+        // "at" vector followed by "up" vector
+        let orientation = [0.0, 0.0, -1.0, 0.0, 1.0, 0.0];
+        for (i, &value) in orientation.iter().enumerate() {
+            mem.write(values + i as GuestUSize, value);
+        }
+        set_listenerfv(&mut backend, &mem, al::AL_ORIENTATION, values.cast_const());
+        assert_eq!(backend.params[&al::AL_ORIENTATION], orientation);
+
+        // Only as many values as the parameter takes are read
+        set_listenerfv(&mut backend, &mem, al::AL_VELOCITY, values.cast_const());
+        assert_eq!(backend.params[&al::AL_VELOCITY], [0.0, 0.0, -1.0]);
+        set_listenerfv(&mut backend, &mem, al::AL_GAIN, values.cast_const());
+        assert_eq!(backend.params[&al::AL_GAIN], [0.0]);
+
+        backend.params.insert(al::AL_POSITION, vec![1.0, 2.0, 3.0]);
+        get_listenerfv(&mut backend, &mut mem, al::AL_POSITION, values);
+        assert_eq!(mem.read(values + 2), 3.0);
+        // The rest of the guest array is untouched
+        assert_eq!(mem.read(values + 3), orientation[3]);
+        assert_eq!(mem.read(values + 4), orientation[4]);
+
+        get_listenerfv(&mut backend, &mut mem, al::AL_ORIENTATION, values);
+        for (i, &value) in orientation.iter().enumerate() {
+            assert_eq!(mem.read(values + i as GuestUSize), value);
+        }
+    }
+
+    /// Get an integer source property from OpenAL Soft.
+    unsafe fn source_int(source: ALuint, param: ALenum) -> ALint {
+        let mut value = 0;
+        al::alGetSourcei(source, param, &mut value);
+        assert_eq!(al::alGetError(), al::AL_NO_ERROR);
+        value
+    }
+
+    #[test]
+    fn streaming_queue_bookkeeping() {
+        const FRAMES_PER_BUFFER: usize = 1000;
+        unsafe {
+            // Nothing is played: the loopback device only mixes when asked to.
+            let device = al::alcLoopbackOpenDeviceSOFT(std::ptr::null());
+            assert!(!device.is_null());
+            let attributes = [
+                al::ALC_FORMAT_CHANNELS_SOFT,
+                al::ALC_STEREO_SOFT,
+                al::ALC_FORMAT_TYPE_SOFT,
+                al::ALC_SHORT_SOFT,
+                al::ALC_FREQUENCY,
+                44100,
+                0,
+            ];
+            let context = al::alcCreateContext(device, attributes.as_ptr());
+            assert!(!context.is_null());
+            assert_eq!(al::alcMakeContextCurrent(context), al::ALC_TRUE);
+            let mut output = vec![0i16; 2 * 10 * FRAMES_PER_BUFFER];
+            let mut render = |frames: usize| {
+                al::alcRenderSamplesSOFT(
+                    device,
+                    output.as_mut_ptr().cast(),
+                    frames.try_into().unwrap(),
+                )
+            };
+
+            let mut source = 0;
+            al::alGenSources(1, &mut source);
+            let mut buffers = [0; 3];
+            al::alGenBuffers(3, buffers.as_mut_ptr());
+            let samples = vec![0i16; FRAMES_PER_BUFFER];
+            for &buffer in &buffers {
+                al::alBufferData(
+                    buffer,
+                    al::AL_FORMAT_MONO16,
+                    samples.as_ptr().cast(),
+                    (samples.len() * 2).try_into().unwrap(),
+                    44100,
+                );
+            }
+
+            let mut info = SourceInfo::default();
+            queue_buffers(source, info, &buffers);
+            al::alSourcePlay(source);
+            info.playing = true;
+            assert_eq!(source_int(source, al::AL_BUFFERS_QUEUED), 3);
+            assert_eq!(source_int(source, al::AL_BUFFERS_PROCESSED), 0);
+
+            // Consuming the first buffer
+            render(FRAMES_PER_BUFFER * 3 / 2);
+            assert_eq!(source_int(source, al::AL_BUFFERS_PROCESSED), 1);
+            let mut recycled = 0;
+            al::alSourceUnqueueBuffers(source, 1, &mut recycled);
+            info.streaming = true;
+            assert_eq!(recycled, buffers[0]);
+            assert_eq!(source_int(source, al::AL_BUFFERS_QUEUED), 2);
+            assert_eq!(source_int(source, al::AL_BUFFERS_PROCESSED), 0);
+            queue_buffers(source, info, &[recycled]);
+            assert_eq!(source_int(source, al::AL_BUFFERS_QUEUED), 3);
+            assert_eq!(source_int(source, al::AL_SOURCE_STATE), al::AL_PLAYING);
+
+            // Running out of buffers: the source stops, but is restarted once
+            // the app requeues them.
+            render(FRAMES_PER_BUFFER * 4);
+            assert_eq!(source_int(source, al::AL_SOURCE_STATE), al::AL_STOPPED);
+            assert_eq!(source_int(source, al::AL_BUFFERS_PROCESSED), 3);
+            let mut recycled = [0; 3];
+            al::alSourceUnqueueBuffers(source, 3, recycled.as_mut_ptr());
+            assert_eq!(source_int(source, al::AL_BUFFERS_QUEUED), 0);
+            queue_buffers(source, info, &recycled);
+            assert_eq!(source_int(source, al::AL_SOURCE_STATE), al::AL_PLAYING);
+            assert_eq!(source_int(source, al::AL_BUFFERS_QUEUED), 3);
+            assert_eq!(source_int(source, al::AL_BUFFERS_PROCESSED), 0);
+
+            // A looping source never finishes, and its buffers are never
+            // processed.
+            al::alSourceStop(source);
+            al::alSourcei(source, al::AL_LOOPING, al::AL_TRUE.into());
+            al::alSourcePlay(source);
+            render(FRAMES_PER_BUFFER * 10);
+            assert_eq!(source_int(source, al::AL_SOURCE_STATE), al::AL_PLAYING);
+            assert_eq!(source_int(source, al::AL_BUFFERS_PROCESSED), 0);
+
+            al::alSourceStop(source);
+            al::alDeleteSources(1, &source);
+            al::alDeleteBuffers(3, buffers.as_ptr());
+            al::alcMakeContextCurrent(std::ptr::null_mut());
+            al::alcDestroyContext(context);
+            al::alcCloseDevice(device);
+        }
+    }
+}
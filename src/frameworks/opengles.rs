@@ -24,8 +24,18 @@ pub struct State {
     /// Which thread's EAGLContext is currently active
     current_ctx_thread: Option<crate::ThreadId>,
     strings_cache: std::collections::HashMap<GLenum, ConstPtr<u8>>,
+    /// Errors collected by `--gl-debug` checks, which have yet to be returned
+    /// to the app by `glGetError()`.
+    pending_gl_errors: Vec<GLenum>,
 }
 impl State {
+    /// Forget allocations that won't exist once `mem` is restored, see
+    /// [crate::Environment::load_snapshot].
+    pub fn forget_pointers_not_in(&mut self, mem: &crate::mem::MemSnapshot) {
+        self.strings_cache
+            .retain(|_, string| mem.is_used(string.to_bits()));
+    }
+
     fn current_ctx_for_thread(&mut self, thread: crate::ThreadId) -> &mut Option<crate::objc::id> {
         self.current_ctxs.entry(thread).or_insert(None);
         self.current_ctxs.get_mut(&thread).unwrap()
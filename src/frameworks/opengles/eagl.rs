@@ -13,12 +13,13 @@ use crate::frameworks::foundation::ns_string::get_static_str;
 use crate::frameworks::foundation::NSUInteger;
 use crate::gles::gles11_raw as gles11; // constants only
 use crate::gles::gles11_raw::types::*;
-use crate::gles::present::{present_frame, FpsCounter};
+use crate::gles::present::{present_frame, save_screenshot, FpsCounter};
 use crate::gles::{create_gles1_ctx, gles1_on_gl2, GLES};
 use crate::mem::MutPtr;
 use crate::objc::{id, msg, nil, objc_classes, release, retain, ClassExports, HostObject};
 use crate::options::Options;
 use crate::window::Window;
+use crate::Environment;
 use std::collections::HashMap;
 use std::time::{Duration, Instant};
 
@@ -231,7 +232,8 @@ pub const CLASSES: ClassExports = objc_classes! {
 
     // The presented frame should be displayed ASAP, but the next one must be
     // delayed, so this needs to be checked before returning.
-    let sleep_for = limit_framerate(&mut env.objc.borrow_mut::<EAGLContextHostObject>(this).next_frame_due, &env.options);
+    let fast_forwarding = env.clock.speed() > 1;
+    let sleep_for = limit_framerate(&mut env.objc.borrow_mut::<EAGLContextHostObject>(this).next_frame_due, &env.options, fast_forwarding);
 
     if env.options.print_fps {
         env
@@ -325,6 +327,46 @@ pub const CLASSES: ClassExports = objc_classes! {
 
 };
 
+/// Returns [true] if the app has any OpenGL ES contexts.
+pub fn has_contexts(env: &Environment) -> bool {
+    env.objc.has_host_objects_of_type::<EAGLContextHostObject>()
+}
+
+/// Read back the content of the renderbuffer bound to `drawable` (a
+/// `CAEAGLLayer*`) in the current context, for use by `renderInContext:`.
+///
+/// Returns [None] if there's no current context or no renderbuffer is bound to
+/// the drawable in it. The format is the same as for [read_renderbuffer], i.e.
+/// the rows are bottom-to-top and the size is affected by the scale hack.
+pub fn read_drawable_pixels(env: &mut Environment, drawable: id) -> Option<(Vec<u8>, u32, u32)> {
+    let context = (*env
+        .framework_state
+        .opengles
+        .current_ctx_for_thread(env.current_thread))?;
+    let &renderbuffer = env
+        .objc
+        .borrow::<EAGLContextHostObject>(context)
+        .renderbuffer_drawable_bindings
+        .iter()
+        .find(|&(_, &bound_drawable)| bound_drawable == drawable)?
+        .0;
+
+    let window = env.window.as_mut()?;
+    let gles = super::sync_context(
+        &mut env.framework_state.opengles,
+        &mut env.objc,
+        window,
+        env.current_thread,
+    );
+    unsafe {
+        let old_renderbuffer: GLuint = get_int(gles, gles11::RENDERBUFFER_BINDING_OES) as _;
+        gles.BindRenderbufferOES(gles11::RENDERBUFFER_OES, renderbuffer);
+        let result = read_renderbuffer(gles, Vec::new());
+        gles.BindRenderbufferOES(gles11::RENDERBUFFER_OES, old_renderbuffer);
+        Some(result)
+    }
+}
+
 /// Implement framerate limiting.
 ///
 /// The real iPhone OS seems to force 60Hz v-sync in `presentRenderbuffer:`.
@@ -343,21 +385,37 @@ pub const CLASSES: ClassExports = objc_classes! {
 /// an interval's worth of accumulated slop. Allowing infinite accumulation of
 /// slop is not desirable, because if the game is running slowly for a long time
 /// and suddenly speeds back up, it will then run too fast for a long time.
-fn limit_framerate(next_frame_due: &mut Option<Instant>, options: &Options) -> Option<Duration> {
-    let interval = if let Some(fps) = options.fps_limit {
-        1.0 / fps
-    } else {
+///
+/// While fast-forwarding, there's no limit.
+fn limit_framerate(
+    next_frame_due: &mut Option<Instant>,
+    options: &Options,
+    fast_forwarding: bool,
+) -> Option<Duration> {
+    let fps = options.fps_limit?;
+    if fast_forwarding {
+        // Start pacing afresh afterwards, rather than trying to catch up.
+        *next_frame_due = None;
         return None;
-    };
+    }
+    pace_frame(next_frame_due, Instant::now(), 1.0 / fps)
+}
+
+/// The calculation for [limit_framerate], for a frame presented at `now` with
+/// a limit of one frame per `interval` seconds. Returns how long to sleep for.
+fn pace_frame(
+    next_frame_due: &mut Option<Instant>,
+    now: Instant,
+    interval: f64,
+) -> Option<Duration> {
     let interval_rust = Duration::from_secs_f64(interval);
 
     let &mut Some(current_frame_due) = next_frame_due else {
         // First frame presented: no delay yet.
-        *next_frame_due = Some(Instant::now() + interval_rust);
+        *next_frame_due = Some(now + interval_rust);
         return None;
     };
 
-    let now = Instant::now();
     *next_frame_due = if now > current_frame_due + interval_rust {
         // Too much slop has accumulated. Make the next frame wait for the next
         // interval.
@@ -700,6 +758,10 @@ unsafe fn present_renderbuffer(gles: &mut dyn GLES, window: &mut Window) {
         old_tex_env_mode_arr.as_ptr().cast(),
     );
 
+    if window.take_screenshot_request() {
+        save_screenshot(gles, window.viewport());
+    }
+
     // SDL2's documentation warns 0 should be bound to the draw framebuffer
     // when swapping the window, so this is the perfect moment.
     window.swap_window();
@@ -710,3 +772,42 @@ unsafe fn present_renderbuffer(gles: &mut dyn GLES, window: &mut Window) {
 
     //{ let err = gl21::GetError(); if err != 0 { panic!("{:#x}", err); } }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_pacing() {
+        let interval = 1.0 / 50.0;
+        let ms = Duration::from_millis;
+        let start = Instant::now();
+        let mut next_frame_due = None;
+
+        // First frame: no delay.
+        assert_eq!(pace_frame(&mut next_frame_due, start, interval), None);
+        assert_eq!(next_frame_due, Some(start + ms(20)));
+
+        // Early frame: delayed until it's due.
+        assert_eq!(
+            pace_frame(&mut next_frame_due, start + ms(5), interval),
+            Some(ms(15))
+        );
+        assert_eq!(next_frame_due, Some(start + ms(40)));
+
+        // Slightly late frame: no delay, and the next frame is still due on the
+        // original schedule.
+        assert_eq!(
+            pace_frame(&mut next_frame_due, start + ms(45), interval),
+            None
+        );
+        assert_eq!(next_frame_due, Some(start + ms(60)));
+
+        // Very late frame: the missed intervals are skipped.
+        assert_eq!(
+            pace_frame(&mut next_frame_due, start + ms(130), interval),
+            None
+        );
+        assert_eq!(next_frame_due, Some(start + ms(140)));
+    }
+}
@@ -23,14 +23,15 @@ use crate::gles::gles11_raw as gles11; // constants only
 use crate::gles::GLES;
 use crate::mem::{ConstPtr, ConstVoidPtr, GuestISize, GuestUSize, Mem, MutPtr, MutVoidPtr, Ptr};
 use crate::objc::nil;
+use crate::options::GLDebug;
 use crate::Environment;
 
 use std::slice::from_raw_parts;
 
 // These types are the same size in guest code (32-bit) and host code (64-bit).
 use crate::gles::gles11_raw::types::{
-    GLbitfield, GLboolean, GLclampf, GLclampx, GLenum, GLfixed, GLfloat, GLint, GLsizei, GLubyte,
-    GLuint, GLvoid,
+    GLbitfield, GLboolean, GLclampf, GLclampx, GLenum, GLfixed, GLfloat, GLint, GLshort, GLsizei,
+    GLubyte, GLuint, GLvoid,
 };
 // These types have different sizes, so some care is needed.
 use crate::gles::gles11_raw::types::{GLintptr as HostGLintptr, GLsizeiptr as HostGLsizeiptr};
@@ -38,13 +39,15 @@ type GuestGLsizeiptr = GuestISize;
 type GuestGLintptr = GuestISize;
 
 /// List of compressed formats supported by our emulation.
-/// Currently, it's all the PVRTC and all paletted ones.
+/// Currently, it's all the PVRTC, ETC1 and all paletted ones.
 const SUPPORTED_COMPRESSED_TEXTURE_FORMATS: &[GLenum] = &[
     // PVRTC
     gles11::COMPRESSED_RGBA_PVRTC_2BPPV1_IMG,
     gles11::COMPRESSED_RGBA_PVRTC_4BPPV1_IMG,
     gles11::COMPRESSED_RGB_PVRTC_2BPPV1_IMG,
     gles11::COMPRESSED_RGB_PVRTC_4BPPV1_IMG,
+    // ETC1
+    gles11::ETC1_RGB8_OES,
     // Paletted texture
     gles11::PALETTE4_R5_G6_B5_OES,
     gles11::PALETTE4_RGB5_A1_OES,
@@ -58,6 +61,112 @@ const SUPPORTED_COMPRESSED_TEXTURE_FORMATS: &[GLenum] = &[
     gles11::PALETTE8_RGBA8_OES,
 ];
 
+/// The `GL_EXTENSIONS` string from the iPod touch 2nd gen, iOS 4.2.1. This is
+/// only used with `--legacy-gl-extensions`, because touchHLE doesn't implement
+/// all of these.
+const LEGACY_EXTENSIONS: &str = "GL_APPLE_framebuffer_multisample GL_APPLE_texture_max_level GL_EXT_discard_framebuffer GL_EXT_texture_filter_anisotropic GL_EXT_texture_lod_bias GL_IMG_read_format GL_IMG_texture_compression_pvrtc GL_IMG_texture_format_BGRA8888 GL_OES_blend_subtract GL_OES_compressed_ETC1_RGB8_texture GL_OES_compressed_paletted_texture GL_OES_depth24 GL_OES_draw_texture GL_OES_framebuffer_object GL_OES_mapbuffer GL_OES_matrix_palette GL_OES_point_size_array GL_OES_point_sprite GL_OES_read_format GL_OES_rgb8_rgba8 GL_OES_texture_mirrored_repeat GL_OES_vertex_array_object ";
+
+/// Extensions from [LEGACY_EXTENSIONS] that touchHLE can advertise, with the
+/// functions each one adds. An extension is only advertised if all of its
+/// functions are implemented.
+///
+/// Missing: `GL_APPLE_texture_max_level` (`GL_TEXTURE_MAX_LEVEL_APPLE` isn't
+/// accepted by `glTexParameter` yet).
+const EXTENSIONS: &[(&str, &[&str])] = &[
+    (
+        "GL_APPLE_framebuffer_multisample",
+        &[
+            "glRenderbufferStorageMultisampleAPPLE",
+            "glResolveMultisampleFramebufferAPPLE",
+        ],
+    ),
+    ("GL_EXT_discard_framebuffer", &["glDiscardFramebufferEXT"]),
+    ("GL_EXT_texture_filter_anisotropic", &[]),
+    ("GL_EXT_texture_lod_bias", &[]),
+    ("GL_IMG_read_format", &[]),
+    ("GL_IMG_texture_compression_pvrtc", &[]),
+    ("GL_IMG_texture_format_BGRA8888", &[]),
+    ("GL_OES_blend_subtract", &["glBlendEquationOES"]),
+    ("GL_OES_compressed_ETC1_RGB8_texture", &[]),
+    ("GL_OES_compressed_paletted_texture", &[]),
+    ("GL_OES_depth24", &[]),
+    (
+        "GL_OES_draw_texture",
+        &[
+            "glDrawTexfOES",
+            "glDrawTexiOES",
+            "glDrawTexsOES",
+            "glDrawTexxOES",
+            "glDrawTexfvOES",
+            "glDrawTexivOES",
+            "glDrawTexsvOES",
+            "glDrawTexxvOES",
+        ],
+    ),
+    (
+        "GL_OES_framebuffer_object",
+        &[
+            "glGenFramebuffersOES",
+            "glGenRenderbuffersOES",
+            "glBindFramebufferOES",
+            "glBindRenderbufferOES",
+            "glRenderbufferStorageOES",
+            "glFramebufferRenderbufferOES",
+            "glFramebufferTexture2DOES",
+            "glGetFramebufferAttachmentParameterivOES",
+            "glGetRenderbufferParameterivOES",
+            "glCheckFramebufferStatusOES",
+            "glDeleteFramebuffersOES",
+            "glDeleteRenderbuffersOES",
+            "glGenerateMipmapOES",
+        ],
+    ),
+    (
+        "GL_OES_mapbuffer",
+        &[
+            "glMapBufferOES",
+            "glUnmapBufferOES",
+            "glGetBufferPointervOES",
+        ],
+    ),
+    (
+        "GL_OES_matrix_palette",
+        &[
+            "glCurrentPaletteMatrixOES",
+            "glLoadPaletteFromModelViewMatrixOES",
+            "glMatrixIndexPointerOES",
+            "glWeightPointerOES",
+        ],
+    ),
+    ("GL_OES_point_size_array", &["glPointSizePointerOES"]),
+    ("GL_OES_point_sprite", &[]),
+    ("GL_OES_read_format", &[]),
+    ("GL_OES_rgb8_rgba8", &[]),
+    ("GL_OES_texture_mirrored_repeat", &[]),
+    (
+        "GL_OES_vertex_array_object",
+        &[
+            "glBindVertexArrayOES",
+            "glDeleteVertexArraysOES",
+            "glGenVertexArraysOES",
+            "glIsVertexArrayOES",
+        ],
+    ),
+];
+
+/// Build a `GL_EXTENSIONS` string listing the extensions from [EXTENSIONS]
+/// whose functions are all implemented, according to `is_implemented`.
+fn extensions_string(is_implemented: impl Fn(&str) -> bool) -> String {
+    let mut string = String::new();
+    for &(extension, functions) in EXTENSIONS {
+        if functions.iter().all(|&f| is_implemented(f)) {
+            string.push_str(extension);
+            string.push(' ');
+        }
+    }
+    string
+}
+
 fn with_ctx_and_mem<T, U>(env: &mut Environment, f: T) -> U
 where
     T: FnOnce(&mut dyn GLES, &mut Mem) -> U,
@@ -71,39 +180,91 @@ where
         env.current_thread,
     );
 
-    //panic_on_gl_errors(&mut **gles);
-    let res = f(gles, &mut env.mem);
-    //panic_on_gl_errors(&mut **gles);
-    #[allow(clippy::let_and_return)]
-    res
+    let mode = env.options.gl_debug;
+    if mode == GLDebug::Off {
+        return f(gles, &mut env.mem);
+    }
+    let mem = &mut env.mem;
+    call_checking_gl_errors(
+        gles,
+        |gles| unsafe { gles.GetError() },
+        |gles| f(gles, mem),
+        gl_function_name::<T>(),
+        mode,
+        &mut env.framework_state.opengles.pending_gl_errors,
+    )
 }
 
-/// Useful for debugging
-#[allow(dead_code)]
-fn panic_on_gl_errors(gles: &mut dyn GLES) {
-    let mut did_error = false;
-    loop {
-        let err = unsafe { gles.GetError() };
-        if err == 0 {
-            break;
+/// Get the name of the function a closure passed to [with_ctx_and_mem] was
+/// defined in, e.g. `glEnable`.
+fn gl_function_name<T>() -> &'static str {
+    // e.g. "touchHLE::frameworks::opengles::gles_guest::glEnable::{{closure}}"
+    let type_name = std::any::type_name::<T>();
+    type_name
+        .split("::")
+        .take_while(|part| !part.starts_with('{'))
+        .last()
+        .unwrap_or(type_name)
+}
+
+/// Implementation of `--gl-debug`: call `f` and report any errors it raised,
+/// blaming `function`. Errors are kept in `pending` so that `glGetError()` can
+/// still return them to the app.
+fn call_checking_gl_errors<C: ?Sized, U>(
+    ctx: &mut C,
+    get_error: impl Fn(&mut C) -> GLenum,
+    f: impl FnOnce(&mut C) -> U,
+    function: &str,
+    mode: GLDebug,
+    pending: &mut Vec<GLenum>,
+) -> U {
+    let collect_errors = |ctx: &mut C, pending: &mut Vec<GLenum>| {
+        let mut new_errors = Vec::new();
+        // Each kind of error should only be returned once, but the limit
+        // protects against looping forever if the driver is misbehaving.
+        for _ in 0..16 {
+            let err = get_error(ctx);
+            if err == gles11::NO_ERROR {
+                break;
+            }
+            if !pending.contains(&err) {
+                pending.push(err);
+                new_errors.push(err);
+            }
         }
-        did_error = true;
-        echo!("glGetError() => {:#x}", err);
+        new_errors
+    };
+
+    // Errors raised before now weren't caused by an app's call.
+    for err in collect_errors(ctx, pending) {
+        log_dbg!("GL error {:#x} raised outside of an app's call", err);
+    }
+    let res = f(ctx);
+    let errors = collect_errors(ctx, pending);
+    for &err in &errors {
+        log!("GL error {:#x} raised by {}()", err, function);
     }
-    if did_error {
-        panic!();
+    if mode == GLDebug::Panic && !errors.is_empty() {
+        panic!("GL error raised by {}() with --gl-debug=panic", function);
     }
+    res
 }
 
 // Generic state manipulation
 fn glGetError(env: &mut Environment) -> GLenum {
-    with_ctx_and_mem(env, |gles, _mem| {
-        let err = unsafe { gles.GetError() };
-        if err != 0 {
-            log!("Warning: glGetError() returned {:#x}", err);
+    let mut err = with_ctx_and_mem(env, |gles, _mem| unsafe { gles.GetError() });
+    // Errors collected by --gl-debug checks take priority.
+    let pending = &mut env.framework_state.opengles.pending_gl_errors;
+    if !pending.is_empty() {
+        if err != gles11::NO_ERROR && !pending.contains(&err) {
+            pending.push(err);
         }
-        err
-    })
+        err = pending.remove(0);
+    }
+    if err != 0 {
+        log!("Warning: glGetError() returned {:#x}", err);
+    }
+    err
 }
 fn glEnable(env: &mut Environment, cap: GLenum) {
     with_ctx_and_mem(env, |gles, _mem| {
@@ -139,6 +300,14 @@ fn glGetBooleanv(env: &mut Environment, pname: GLenum, params: MutPtr<GLboolean>
         unsafe { gles.GetBooleanv(pname, params) };
     });
 }
+fn glGetFixedv(env: &mut Environment, pname: GLenum, params: MutPtr<GLfixed>) {
+    assert_ne!(gles11::NUM_COMPRESSED_TEXTURE_FORMATS, pname);
+    assert_ne!(gles11::COMPRESSED_TEXTURE_FORMATS, pname);
+    with_ctx_and_mem(env, |gles, mem| {
+        let params = mem.ptr_at_mut(params, 16 /* upper bound */);
+        unsafe { gles.GetFixedv(pname, params) };
+    });
+}
 fn glGetFloatv(env: &mut Environment, pname: GLenum, params: MutPtr<GLfloat>) {
     assert_ne!(gles11::NUM_COMPRESSED_TEXTURE_FORMATS, pname);
     assert_ne!(gles11::COMPRESSED_TEXTURE_FORMATS, pname);
@@ -166,9 +335,16 @@ fn glGetIntegerv(env: &mut Environment, pname: GLenum, params: MutPtr<GLint>) {
     });
 }
 fn glGetPointerv(env: &mut Environment, pname: GLenum, params: MutPtr<ConstVoidPtr>) {
-    use crate::gles::gles1_on_gl2::{ArrayInfo, ARRAYS};
-    let &ArrayInfo { buffer_binding, .. } =
-        ARRAYS.iter().find(|info| info.pointer == pname).unwrap();
+    use crate::gles::gles1_on_gl2::ARRAYS;
+    let buffer_binding = if pname == gles11::POINT_SIZE_ARRAY_POINTER_OES {
+        gles11::POINT_SIZE_ARRAY_BUFFER_BINDING_OES
+    } else {
+        ARRAYS
+            .iter()
+            .find(|info| info.pointer == pname)
+            .unwrap()
+            .buffer_binding
+    };
     with_ctx_and_mem(env, |gles, mem| {
         // params always points to just one pointer for this function
         let mut host_pointer_or_offset = std::ptr::null();
@@ -205,20 +381,22 @@ fn glGetString(env: &mut Environment, name: GLenum) -> ConstPtr<GLubyte> {
     let res = if let Some(&str) = env.framework_state.opengles.strings_cache.get(&name) {
         str
     } else {
+        let legacy_extensions = env.options.legacy_gl_extensions;
         let new_str = with_ctx_and_mem(env, |_gles, mem| {
             // Those values are extracted from the iPod touch 2nd gen, iOS 4.2.1
+            let extensions;
             let s: &[u8] = match name {
-                gles11::VENDOR => {
-                    b"Imagination Technologies"
-                }
-                gles11::RENDERER => {
-                    b"PowerVR MBXLite with VGPLite"
-                }
-                gles11::VERSION => {
-                    b"OpenGL ES-CM 1.1 (76)"
-                }
+                gles11::VENDOR => b"Imagination Technologies",
+                gles11::RENDERER => b"PowerVR MBXLite with VGPLite",
+                gles11::VERSION => b"OpenGL ES-CM 1.1 (76)",
+                gles11::EXTENSIONS if legacy_extensions => LEGACY_EXTENSIONS.as_bytes(),
                 gles11::EXTENSIONS => {
-                    b"GL_APPLE_framebuffer_multisample GL_APPLE_texture_max_level GL_EXT_discard_framebuffer GL_EXT_texture_filter_anisotropic GL_EXT_texture_lod_bias GL_IMG_read_format GL_IMG_texture_compression_pvrtc GL_IMG_texture_format_BGRA8888 GL_OES_blend_subtract GL_OES_compressed_paletted_texture GL_OES_depth24 GL_OES_draw_texture GL_OES_framebuffer_object GL_OES_mapbuffer GL_OES_matrix_palette GL_OES_point_size_array GL_OES_point_sprite GL_OES_read_format GL_OES_rgb8_rgba8 GL_OES_texture_mirrored_repeat GL_OES_vertex_array_object "
+                    extensions = extensions_string(|function| {
+                        FUNCTIONS
+                            .iter()
+                            .any(|&(symbol, _)| symbol.strip_prefix('_') == Some(function))
+                    });
+                    extensions.as_bytes()
                 }
                 _ => unreachable!(),
             };
@@ -234,6 +412,15 @@ fn glGetString(env: &mut Environment, name: GLenum) -> ConstPtr<GLubyte> {
     res
 }
 
+// Compatibility shim: these are desktop OpenGL functions, not OpenGL ES 1.1
+// ones, but some apps call them anyway.
+fn glPushAttrib(env: &mut Environment, mask: GLbitfield) {
+    with_ctx_and_mem(env, |gles, _mem| unsafe { gles.PushAttrib(mask) })
+}
+fn glPopAttrib(env: &mut Environment) {
+    with_ctx_and_mem(env, |gles, _mem| unsafe { gles.PopAttrib() })
+}
+
 // Other state manipulation
 fn glAlphaFunc(env: &mut Environment, func: GLenum, ref_: GLclampf) {
     with_ctx_and_mem(env, |gles, _mem| unsafe { gles.AlphaFunc(func, ref_) })
@@ -246,6 +433,17 @@ fn glBlendFunc(env: &mut Environment, sfactor: GLenum, dfactor: GLenum) {
         gles.BlendFunc(sfactor, dfactor)
     })
 }
+fn glBlendFuncSeparateOES(
+    env: &mut Environment,
+    src_rgb: GLenum,
+    dst_rgb: GLenum,
+    src_alpha: GLenum,
+    dst_alpha: GLenum,
+) {
+    with_ctx_and_mem(env, |gles, _mem| unsafe {
+        gles.BlendFuncSeparateOES(src_rgb, dst_rgb, src_alpha, dst_alpha)
+    })
+}
 fn glColorMask(
     env: &mut Environment,
     red: GLboolean,
@@ -291,9 +489,7 @@ fn glShadeModel(env: &mut Environment, mode: GLenum) {
 fn glScissor(env: &mut Environment, x: GLint, y: GLint, width: GLsizei, height: GLsizei) {
     // apply scale hack: assume framebuffer's size is larger than the app thinks
     // and scale scissor appropriately
-    let factor = env.options.scale_hack.get() as GLsizei;
-    let (x, y) = (x * factor, y * factor);
-    let (width, height) = (width * factor, height * factor);
+    let (x, y, width, height) = env.options.scale_hack.scale_rect(x, y, width, height);
     with_ctx_and_mem(env, |gles, _mem| unsafe {
         gles.Scissor(x, y, width, height)
     })
@@ -301,9 +497,7 @@ fn glScissor(env: &mut Environment, x: GLint, y: GLint, width: GLsizei, height:
 fn glViewport(env: &mut Environment, x: GLint, y: GLint, width: GLsizei, height: GLsizei) {
     // apply scale hack: assume framebuffer's size is larger than the app thinks
     // and scale viewport appropriately
-    let factor = env.options.scale_hack.get() as GLsizei;
-    let (x, y) = (x * factor, y * factor);
-    let (width, height) = (width * factor, height * factor);
+    let (x, y, width, height) = env.options.scale_hack.scale_rect(x, y, width, height);
     with_ctx_and_mem(env, |gles, _mem| unsafe {
         gles.Viewport(x, y, width, height)
     })
@@ -438,6 +632,30 @@ fn glMaterialxv(env: &mut Environment, face: GLenum, pname: GLenum, params: Cons
         unsafe { gles.Materialxv(face, pname, params) }
     })
 }
+fn glGetLightfv(env: &mut Environment, light: GLenum, pname: GLenum, params: MutPtr<GLfloat>) {
+    with_ctx_and_mem(env, |gles, mem| {
+        let params = mem.ptr_at_mut(params, 4 /* upper bound */);
+        unsafe { gles.GetLightfv(light, pname, params) }
+    })
+}
+fn glGetLightxv(env: &mut Environment, light: GLenum, pname: GLenum, params: MutPtr<GLfixed>) {
+    with_ctx_and_mem(env, |gles, mem| {
+        let params = mem.ptr_at_mut(params, 4 /* upper bound */);
+        unsafe { gles.GetLightxv(light, pname, params) }
+    })
+}
+fn glGetMaterialfv(env: &mut Environment, face: GLenum, pname: GLenum, params: MutPtr<GLfloat>) {
+    with_ctx_and_mem(env, |gles, mem| {
+        let params = mem.ptr_at_mut(params, 4 /* upper bound */);
+        unsafe { gles.GetMaterialfv(face, pname, params) }
+    })
+}
+fn glGetMaterialxv(env: &mut Environment, face: GLenum, pname: GLenum, params: MutPtr<GLfixed>) {
+    with_ctx_and_mem(env, |gles, mem| {
+        let params = mem.ptr_at_mut(params, 4 /* upper bound */);
+        unsafe { gles.GetMaterialxv(face, pname, params) }
+    })
+}
 
 // Textures
 fn glGenBuffers(env: &mut Environment, n: GLsizei, buffers: MutPtr<GLuint>) {
@@ -534,8 +752,7 @@ unsafe fn translate_pointer_or_offset_to_host(
     let mut buffer_binding = 0;
     gles.GetIntegerv(which_binding, &mut buffer_binding);
     if buffer_binding != 0 {
-        let offset = pointer_or_offset.to_bits();
-        offset as usize as *const _
+        buffer_offset_to_host(pointer_or_offset)
     } else if pointer_or_offset.is_null() {
         std::ptr::null()
     } else {
@@ -565,8 +782,7 @@ unsafe fn translate_pointer_or_offset_to_guest(
     let mut buffer_binding = 0;
     gles.GetIntegerv(which_binding, &mut buffer_binding);
     if buffer_binding != 0 {
-        let offset = pointer_or_offset as usize;
-        Ptr::from_bits(u32::try_from(offset).unwrap())
+        buffer_offset_to_guest(pointer_or_offset)
     } else if pointer_or_offset.is_null() {
         Ptr::null()
     } else {
@@ -575,6 +791,17 @@ unsafe fn translate_pointer_or_offset_to_guest(
     }
 }
 
+/// When a buffer object is bound, the "pointer" for an array is actually an
+/// offset into the buffer object, which must be passed through unchanged.
+fn buffer_offset_to_host(offset: ConstVoidPtr) -> *const GLvoid {
+    offset.to_bits() as usize as *const _
+}
+
+/// Reverse of [buffer_offset_to_host].
+fn buffer_offset_to_guest(offset: *const GLvoid) -> ConstVoidPtr {
+    Ptr::from_bits(u32::try_from(offset as usize).unwrap())
+}
+
 fn glColorPointer(
     env: &mut Environment,
     size: GLint,
@@ -621,6 +848,18 @@ fn glVertexPointer(
         gles.VertexPointer(size, type_, stride, pointer)
     })
 }
+fn glPointSizePointerOES(
+    env: &mut Environment,
+    type_: GLenum,
+    stride: GLsizei,
+    pointer: ConstVoidPtr,
+) {
+    with_ctx_and_mem(env, |gles, mem| unsafe {
+        let pointer =
+            translate_pointer_or_offset_to_host(gles, mem, pointer, gles11::ARRAY_BUFFER_BINDING);
+        gles.PointSizePointerOES(type_, stride, pointer)
+    })
+}
 
 // Drawing
 fn glDrawArrays(env: &mut Environment, mode: GLenum, first: GLint, count: GLsizei) {
@@ -650,6 +889,80 @@ fn glDrawElements(
     })
 }
 
+// OES_draw_texture
+fn glDrawTexfOES(
+    env: &mut Environment,
+    x: GLfloat,
+    y: GLfloat,
+    z: GLfloat,
+    width: GLfloat,
+    height: GLfloat,
+) {
+    // apply scale hack: assume framebuffer's size is larger than the app thinks
+    // and scale the rectangle appropriately
+    let factor = env.options.scale_hack.get();
+    let (x, y, width, height) = (x * factor, y * factor, width * factor, height * factor);
+    with_ctx_and_mem(env, |gles, _mem| unsafe {
+        gles.DrawTexfOES(x, y, z, width, height)
+    })
+}
+fn glDrawTexiOES(env: &mut Environment, x: GLint, y: GLint, z: GLint, width: GLint, height: GLint) {
+    // See above.
+    let (x, y, width, height) = env.options.scale_hack.scale_rect(x, y, width, height);
+    with_ctx_and_mem(env, |gles, _mem| unsafe {
+        gles.DrawTexiOES(x, y, z, width, height)
+    })
+}
+fn glDrawTexsOES(
+    env: &mut Environment,
+    x: GLshort,
+    y: GLshort,
+    z: GLshort,
+    width: GLshort,
+    height: GLshort,
+) {
+    // See above. The values are passed on as integers so that scaling them
+    // can't overflow.
+    glDrawTexiOES(
+        env,
+        x.into(),
+        y.into(),
+        z.into(),
+        width.into(),
+        height.into(),
+    )
+}
+fn glDrawTexxOES(
+    env: &mut Environment,
+    x: GLfixed,
+    y: GLfixed,
+    z: GLfixed,
+    width: GLfixed,
+    height: GLfixed,
+) {
+    // See above. Fixed-point values scale just like integers.
+    let (x, y, width, height) = env.options.scale_hack.scale_rect(x, y, width, height);
+    with_ctx_and_mem(env, |gles, _mem| unsafe {
+        gles.DrawTexxOES(x, y, z, width, height)
+    })
+}
+fn glDrawTexfvOES(env: &mut Environment, coords: ConstPtr<GLfloat>) {
+    let [x, y, z, width, height] = std::array::from_fn(|i| env.mem.read(coords + i as GuestUSize));
+    glDrawTexfOES(env, x, y, z, width, height)
+}
+fn glDrawTexivOES(env: &mut Environment, coords: ConstPtr<GLint>) {
+    let [x, y, z, width, height] = std::array::from_fn(|i| env.mem.read(coords + i as GuestUSize));
+    glDrawTexiOES(env, x, y, z, width, height)
+}
+fn glDrawTexsvOES(env: &mut Environment, coords: ConstPtr<GLshort>) {
+    let [x, y, z, width, height] = std::array::from_fn(|i| env.mem.read(coords + i as GuestUSize));
+    glDrawTexsOES(env, x, y, z, width, height)
+}
+fn glDrawTexxvOES(env: &mut Environment, coords: ConstPtr<GLfixed>) {
+    let [x, y, z, width, height] = std::array::from_fn(|i| env.mem.read(coords + i as GuestUSize));
+    glDrawTexxOES(env, x, y, z, width, height)
+}
+
 // Clearing
 fn glClear(env: &mut Environment, mask: GLbitfield) {
     with_ctx_and_mem(env, |gles, _mem| unsafe { gles.Clear(mask) });
@@ -818,6 +1131,32 @@ fn glTranslatex(env: &mut Environment, x: GLfixed, y: GLfixed, z: GLfixed) {
 fn glPixelStorei(env: &mut Environment, pname: GLenum, param: GLint) {
     with_ctx_and_mem(env, |gles, _mem| unsafe { gles.PixelStorei(pname, param) })
 }
+fn glReadPixels(
+    env: &mut Environment,
+    x: GLint,
+    y: GLint,
+    width: GLsizei,
+    height: GLsizei,
+    format: GLenum,
+    type_: GLenum,
+    pixels: MutVoidPtr,
+) {
+    with_ctx_and_mem(env, |gles, mem| unsafe {
+        if width <= 0 || height <= 0 {
+            return;
+        }
+        let mut alignment = 0;
+        gles.GetIntegerv(gles11::PACK_ALIGNMENT, &mut alignment);
+        let alignment: GuestUSize = alignment.try_into().unwrap();
+        let row_size = image_size_estimate(width.try_into().unwrap(), format, type_);
+        let row_stride = row_size.div_ceil(alignment) * alignment;
+        // The last row doesn't need to be padded.
+        let height: GuestUSize = height.try_into().unwrap();
+        let size = row_stride.checked_mul(height - 1).unwrap() + row_size;
+        let pixels = mem.ptr_at_mut(pixels.cast::<u8>(), size).cast::<GLvoid>();
+        gles.ReadPixels(x, y, width, height as GLsizei, format, type_, pixels)
+    })
+}
 fn glGenTextures(env: &mut Environment, n: GLsizei, textures: MutPtr<GLuint>) {
     with_ctx_and_mem(env, |gles, mem| {
         let n_usize: GuestUSize = n.try_into().unwrap();
@@ -843,9 +1182,16 @@ fn glBindTexture(env: &mut Environment, target: GLenum, texture: GLuint) {
         gles.BindTexture(target, texture)
     })
 }
+fn tex_param_count(pname: GLenum) -> GuestUSize {
+    if pname == gles11::TEXTURE_CROP_RECT_OES {
+        4
+    } else {
+        1
+    }
+}
 fn glTexParameteri(env: &mut Environment, target: GLenum, pname: GLenum, param: GLint) {
-    // So long as we haven't implemented glDrawTexOES yet, we can just ignore
-    // this parameter, because it doesn't do anything for normal texture use.
+    // This parameter has four components, so it can't be set with the scalar
+    // setters. Ignore attempts to do so, like we used to for the vector ones.
     if pname == gles11::TEXTURE_CROP_RECT_OES {
         return;
     }
@@ -872,12 +1218,8 @@ fn glTexParameterx(env: &mut Environment, target: GLenum, pname: GLenum, param:
     })
 }
 fn glTexParameteriv(env: &mut Environment, target: GLenum, pname: GLenum, params: ConstPtr<GLint>) {
-    // See above.
-    if pname == gles11::TEXTURE_CROP_RECT_OES {
-        return;
-    }
     with_ctx_and_mem(env, |gles, mem| unsafe {
-        let params = mem.ptr_at(params, 1 /* upper bound */);
+        let params = mem.ptr_at(params, tex_param_count(pname));
         gles.TexParameteriv(target, pname, params)
     })
 }
@@ -887,12 +1229,8 @@ fn glTexParameterfv(
     pname: GLenum,
     params: ConstPtr<GLfloat>,
 ) {
-    // See above.
-    if pname == gles11::TEXTURE_CROP_RECT_OES {
-        return;
-    }
     with_ctx_and_mem(env, |gles, mem| unsafe {
-        let params = mem.ptr_at(params, 1 /* upper bound */);
+        let params = mem.ptr_at(params, tex_param_count(pname));
         gles.TexParameterfv(target, pname, params)
     })
 }
@@ -902,15 +1240,33 @@ fn glTexParameterxv(
     pname: GLenum,
     params: ConstPtr<GLfixed>,
 ) {
-    // See above.
-    if pname == gles11::TEXTURE_CROP_RECT_OES {
-        return;
-    }
     with_ctx_and_mem(env, |gles, mem| unsafe {
-        let params = mem.ptr_at(params, 1 /* upper bound */);
+        let params = mem.ptr_at(params, tex_param_count(pname));
         gles.TexParameterxv(target, pname, params)
     })
 }
+fn glGetTexParameteriv(
+    env: &mut Environment,
+    target: GLenum,
+    pname: GLenum,
+    params: MutPtr<GLint>,
+) {
+    with_ctx_and_mem(env, |gles, mem| unsafe {
+        let params = mem.ptr_at_mut(params, tex_param_count(pname));
+        gles.GetTexParameteriv(target, pname, params)
+    })
+}
+fn glGetTexParameterfv(
+    env: &mut Environment,
+    target: GLenum,
+    pname: GLenum,
+    params: MutPtr<GLfloat>,
+) {
+    with_ctx_and_mem(env, |gles, mem| unsafe {
+        let params = mem.ptr_at_mut(params, tex_param_count(pname));
+        gles.GetTexParameterfv(target, pname, params)
+    })
+}
 fn image_size_estimate(pixel_count: GuestUSize, format: GLenum, type_: GLenum) -> GuestUSize {
     let bytes_per_pixel: GuestUSize = match type_ {
         gles11::UNSIGNED_BYTE => match format {
@@ -1056,24 +1412,21 @@ fn glTexEnvi(env: &mut Environment, target: GLenum, pname: GLenum, param: GLint)
     })
 }
 fn glTexEnvfv(env: &mut Environment, target: GLenum, pname: GLenum, params: ConstPtr<GLfloat>) {
-    // TODO: GL_POINT_SPRITE_OES
-    assert!(target == gles11::TEXTURE_ENV);
+    assert!(target == gles11::TEXTURE_ENV || target == gles11::POINT_SPRITE_OES);
     with_ctx_and_mem(env, |gles, mem| {
         let params = mem.ptr_at(params, 4 /* upper bound */);
         unsafe { gles.TexEnvfv(target, pname, params) }
     })
 }
 fn glTexEnvxv(env: &mut Environment, target: GLenum, pname: GLenum, params: ConstPtr<GLfixed>) {
-    // TODO: GL_POINT_SPRITE_OES
-    assert!(target == gles11::TEXTURE_ENV);
+    assert!(target == gles11::TEXTURE_ENV || target == gles11::POINT_SPRITE_OES);
     with_ctx_and_mem(env, |gles, mem| {
         let params = mem.ptr_at(params, 4 /* upper bound */);
         unsafe { gles.TexEnvxv(target, pname, params) }
     })
 }
 fn glTexEnviv(env: &mut Environment, target: GLenum, pname: GLenum, params: ConstPtr<GLint>) {
-    // TODO: GL_POINT_SPRITE_OES
-    assert!(target == gles11::TEXTURE_ENV);
+    assert!(target == gles11::TEXTURE_ENV || target == gles11::POINT_SPRITE_OES);
     with_ctx_and_mem(env, |gles, mem| {
         let params = mem.ptr_at(params, 4 /* upper bound */);
         unsafe { gles.TexEnviv(target, pname, params) }
@@ -1113,8 +1466,8 @@ fn glRenderbufferStorageOES(
     height: GLsizei,
 ) {
     // apply scale hack: give the app a larger framebuffer than it asked for
-    let factor = env.options.scale_hack.get() as GLsizei;
-    let (width, height) = (width * factor, height * factor);
+    let scale_hack = env.options.scale_hack;
+    let (width, height) = (scale_hack.scale(width), scale_hack.scale(height));
     with_ctx_and_mem(env, |gles, _mem| unsafe {
         gles.RenderbufferStorageOES(target, internalformat, width, height)
     })
@@ -1160,14 +1513,14 @@ fn glGetRenderbufferParameterivOES(
     pname: GLenum,
     params: MutPtr<GLint>,
 ) {
-    let factor = env.options.scale_hack.get() as GLint;
+    let scale_hack = env.options.scale_hack;
     with_ctx_and_mem(env, |gles, mem| {
         let params = mem.ptr_at_mut(params, 1);
         unsafe { gles.GetRenderbufferParameterivOES(target, pname, params) };
         // apply scale hack: scale down the reported size of the framebuffer,
         // assuming the framebuffer's true size is larger than it should be
         if pname == gles11::RENDERBUFFER_WIDTH_OES || pname == gles11::RENDERBUFFER_HEIGHT_OES {
-            unsafe { params.write_unaligned(params.read_unaligned() / factor) }
+            unsafe { params.write_unaligned(scale_hack.unscale(params.read_unaligned())) }
         }
     })
 }
@@ -1327,6 +1680,7 @@ pub const FUNCTIONS: FunctionExports = &[
     export_c_func!(glEnableClientState(_)),
     export_c_func!(glDisableClientState(_)),
     export_c_func!(glGetBooleanv(_, _)),
+    export_c_func!(glGetFixedv(_, _)),
     export_c_func!(glGetFloatv(_, _)),
     export_c_func!(glGetIntegerv(_, _)),
     export_c_func!(glGetPointerv(_, _)),
@@ -1336,10 +1690,13 @@ pub const FUNCTIONS: FunctionExports = &[
     export_c_func!(glFinish()),
     export_c_func!(glFlush()),
     export_c_func!(glGetString(_)),
+    export_c_func!(glPushAttrib(_)),
+    export_c_func!(glPopAttrib()),
     // Other state manipulation
     export_c_func!(glAlphaFunc(_, _)),
     export_c_func!(glAlphaFuncx(_, _)),
     export_c_func!(glBlendFunc(_, _)),
+    export_c_func!(glBlendFuncSeparateOES(_, _, _, _)),
     export_c_func!(glColorMask(_, _, _, _)),
     export_c_func!(glCullFace(_)),
     export_c_func!(glDepthFunc(_)),
@@ -1381,6 +1738,10 @@ pub const FUNCTIONS: FunctionExports = &[
     export_c_func!(glMaterialx(_, _, _)),
     export_c_func!(glMaterialfv(_, _, _)),
     export_c_func!(glMaterialxv(_, _, _)),
+    export_c_func!(glGetLightfv(_, _, _)),
+    export_c_func!(glGetLightxv(_, _, _)),
+    export_c_func!(glGetMaterialfv(_, _, _)),
+    export_c_func!(glGetMaterialxv(_, _, _)),
     // Buffers
     export_c_func!(glGenBuffers(_, _)),
     export_c_func!(glDeleteBuffers(_, _)),
@@ -1398,9 +1759,19 @@ pub const FUNCTIONS: FunctionExports = &[
     export_c_func!(glNormalPointer(_, _, _)),
     export_c_func!(glTexCoordPointer(_, _, _, _)),
     export_c_func!(glVertexPointer(_, _, _, _)),
+    export_c_func!(glPointSizePointerOES(_, _, _)),
     // Drawing
     export_c_func!(glDrawArrays(_, _, _)),
     export_c_func!(glDrawElements(_, _, _, _)),
+    // OES_draw_texture
+    export_c_func!(glDrawTexfOES(_, _, _, _, _)),
+    export_c_func!(glDrawTexiOES(_, _, _, _, _)),
+    export_c_func!(glDrawTexsOES(_, _, _, _, _)),
+    export_c_func!(glDrawTexxOES(_, _, _, _, _)),
+    export_c_func!(glDrawTexfvOES(_)),
+    export_c_func!(glDrawTexivOES(_)),
+    export_c_func!(glDrawTexsvOES(_)),
+    export_c_func!(glDrawTexxvOES(_)),
     // Clearing
     export_c_func!(glClear(_)),
     export_c_func!(glClearColor(_, _, _, _)),
@@ -1429,6 +1800,7 @@ pub const FUNCTIONS: FunctionExports = &[
     export_c_func!(glTranslatex(_, _, _)),
     // Textures
     export_c_func!(glPixelStorei(_, _)),
+    export_c_func!(glReadPixels(_, _, _, _, _, _, _)),
     export_c_func!(glGenTextures(_, _)),
     export_c_func!(glDeleteTextures(_, _)),
     export_c_func!(glActiveTexture(_)),
@@ -1440,6 +1812,8 @@ pub const FUNCTIONS: FunctionExports = &[
     export_c_func!(glTexParameteriv(_, _, _)),
     export_c_func!(glTexParameterfv(_, _, _)),
     export_c_func!(glTexParameterxv(_, _, _)),
+    export_c_func!(glGetTexParameteriv(_, _, _)),
+    export_c_func!(glGetTexParameterfv(_, _, _)),
     export_c_func!(glTexImage2D(_, _, _, _, _, _, _, _, _)),
     export_c_func!(glTexSubImage2D(_, _, _, _, _, _, _, _, _)),
     export_c_func!(glCompressedTexImage2D(_, _, _, _, _, _, _, _)),
@@ -1490,3 +1864,110 @@ fn _get_buffer_size(env: &mut Environment, target: GLenum) -> GLint {
         buffer_size
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Fake context whose only state is its error flags.
+    struct FakeContext(Vec<GLenum>);
+    fn fake_get_error(ctx: &mut FakeContext) -> GLenum {
+        if ctx.0.is_empty() {
+            gles11::NO_ERROR
+        } else {
+            ctx.0.remove(0)
+        }
+    }
+
+    /// Like [with_ctx_and_mem], but for a [FakeContext].
+    fn with_fake_ctx<T, U>(
+        ctx: &mut FakeContext,
+        pending: &mut Vec<GLenum>,
+        mode: GLDebug,
+        f: T,
+    ) -> U
+    where
+        T: FnOnce(&mut FakeContext) -> U,
+    {
+        let name = gl_function_name::<T>();
+        call_checking_gl_errors(ctx, fake_get_error, f, name, mode, pending)
+    }
+
+    fn glFakeCall(ctx: &mut FakeContext, pending: &mut Vec<GLenum>, mode: GLDebug) -> i32 {
+        with_fake_ctx(ctx, pending, mode, |ctx| {
+            // Injected error
+            ctx.0.push(gles11::INVALID_ENUM);
+            123
+        })
+    }
+
+    #[test]
+    fn gl_error_checking() {
+        // An error left over from before is kept, but not blamed on the call.
+        let mut ctx = FakeContext(vec![gles11::INVALID_VALUE]);
+        let mut pending = Vec::new();
+        assert_eq!(glFakeCall(&mut ctx, &mut pending, GLDebug::Log), 123);
+        assert_eq!(pending, [gles11::INVALID_VALUE, gles11::INVALID_ENUM]);
+        assert!(ctx.0.is_empty());
+
+        // The same error isn't recorded twice.
+        glFakeCall(&mut ctx, &mut pending, GLDebug::Log);
+        assert_eq!(pending, [gles11::INVALID_VALUE, gles11::INVALID_ENUM]);
+
+        // No errors
+        let res = with_fake_ctx(&mut ctx, &mut pending, GLDebug::Panic, |_| 456);
+        assert_eq!(res, 456);
+    }
+
+    #[test]
+    #[should_panic(expected = "GL error raised by glFakeCall()")]
+    fn gl_error_checking_panic() {
+        glFakeCall(
+            &mut FakeContext(Vec::new()),
+            &mut Vec::new(),
+            GLDebug::Panic,
+        );
+    }
+
+    #[test]
+    fn buffer_offset_round_trip() {
+        // glBindBuffer(GL_ARRAY_BUFFER, buffer);
+        // glVertexPointer(3, GL_FLOAT, 0, (void *)16);
+        let offset: ConstVoidPtr = Ptr::from_bits(16);
+        let host_offset = buffer_offset_to_host(offset);
+        assert_eq!(host_offset as usize, 16);
+        // glGetPointerv(GL_VERTEX_ARRAY_POINTER, &pointer);
+        assert_eq!(buffer_offset_to_guest(host_offset), offset);
+
+        let offset: ConstVoidPtr = Ptr::from_bits(0xFFFF_FFF0);
+        assert_eq!(
+            buffer_offset_to_guest(buffer_offset_to_host(offset)),
+            offset
+        );
+    }
+
+    #[test]
+    fn extensions_only_if_implemented() {
+        let partial_mapbuffer = ["glMapBufferOES", "glUnmapBufferOES"];
+        let extensions = extensions_string(|f| partial_mapbuffer.contains(&f));
+        let extensions: Vec<&str> = extensions.split_whitespace().collect();
+        assert!(!extensions.contains(&"GL_OES_mapbuffer"));
+        // Extensions without functions are always included
+        assert!(extensions.contains(&"GL_OES_depth24"));
+
+        let full_mapbuffer = [
+            "glMapBufferOES",
+            "glUnmapBufferOES",
+            "glGetBufferPointervOES",
+        ];
+        let extensions = extensions_string(|f| full_mapbuffer.contains(&f));
+        let extensions: Vec<&str> = extensions.split_whitespace().collect();
+        assert!(extensions.contains(&"GL_OES_mapbuffer"));
+        assert!(!extensions.contains(&"GL_APPLE_framebuffer_multisample"));
+
+        // Everything advertised must also be in the real device's list
+        for extension in extensions {
+            assert!(LEGACY_EXTENSIONS.split(' ').any(|e| e == extension));
+        }
+    }
+}
@@ -9,7 +9,7 @@
 //! likely to use UIKit in very simple and limited ways, so this implementation
 //! will probably take a lot of shortcuts.
 
-use crate::{msg, Environment};
+use crate::Environment;
 use std::time::Instant;
 
 pub mod ui_accelerometer;
@@ -24,6 +24,7 @@ pub mod ui_graphics;
 pub mod ui_image;
 pub mod ui_image_picker_controller;
 pub mod ui_nib;
+pub mod ui_pasteboard;
 pub mod ui_responder;
 pub mod ui_screen;
 pub mod ui_touch;
@@ -38,6 +39,7 @@ pub struct State {
     ui_device: ui_device::State,
     ui_font: ui_font::State,
     ui_graphics: ui_graphics::State,
+    ui_pasteboard: ui_pasteboard::State,
     ui_screen: ui_screen::State,
     ui_touch: ui_touch::State,
     pub ui_view: ui_view::State,
@@ -50,7 +52,6 @@ pub struct State {
 /// time an accelerometer input is due.
 pub fn handle_events(env: &mut Environment) -> Option<Instant> {
     use crate::window::Event;
-    use crate::window::TextInputEvent;
 
     loop {
         // NSRunLoop will never call this function in headless mode.
@@ -98,25 +99,9 @@ pub fn handle_events(env: &mut Environment) -> Option<Instant> {
                     log!("Ignoring EnterDebugger event: no debugger connected.");
                 }
             }
-            Event::TextInput(text_event) => {
-                let responder = env.framework_state.uikit.ui_responder.first_responder;
-                let class = msg![env; responder class];
-                let ui_text_field_class = env.objc.get_known_class("UITextField", &mut env.mem);
-                if !responder.is_null() && env.objc.class_is_subclass_of(class, ui_text_field_class)
-                {
-                    match text_event {
-                        TextInputEvent::Text(text) => {
-                            ui_view::ui_control::ui_text_field::handle_text(env, responder, text)
-                        }
-                        TextInputEvent::Backspace => {
-                            ui_view::ui_control::ui_text_field::handle_backspace(env, responder)
-                        }
-                        TextInputEvent::Return => {
-                            ui_view::ui_control::ui_text_field::handle_return(env, responder)
-                        }
-                    }
-                }
-            }
+            Event::TextInput(text_event) => ui_responder::handle_text_input(env, text_event),
+            Event::RotateDevice => ui_device::rotate_to_next_orientation(env),
+            Event::AppStateChanged(new_state) => ui_application::set_app_state(env, new_state),
         }
     }
 
@@ -24,12 +24,25 @@ pub struct State {
     delegate: Option<id>,
     update_interval: Option<NSTimeInterval>,
     due_by: Option<Instant>,
+    /// The last values sent to the delegate, used for smoothing.
+    last_acceleration: Option<Acceleration>,
 }
 
 type UIAccelerationValue = f64;
 
+type Acceleration = (
+    UIAccelerationValue,
+    UIAccelerationValue,
+    UIAccelerationValue,
+);
+
 const DEFAULT_UPDATE_INTERVAL: f64 = 1.0 / 60.0;
 
+/// The largest magnitude of acceleration reported on each axis, in g. The
+/// original iPhone's accelerometer has a ±2g range, but reports values up to
+/// about 2.3g.
+const MAX_ACCELERATION: UIAccelerationValue = 2.3;
+
 struct UIAccelerationHostObject {
     x: UIAccelerationValue,
     y: UIAccelerationValue,
@@ -84,7 +97,11 @@ pub const CLASSES: ClassExports = objc_classes! {
     // The system can limit this value, and must (some apps pass 0 and this can
     // cause a division-by-zero. 60Hz has been chosen here to match 60fps.
     let interval = interval.max(1.0 / 60.0);
-    env.framework_state.uikit.ui_accelerometer.update_interval = Some(interval);
+    let state = &mut env.framework_state.uikit.ui_accelerometer;
+    state.update_interval = Some(interval);
+    // Reschedule the next update, otherwise it would take effect only after
+    // the next update at the old interval.
+    state.due_by = None;
 }
 
 @end
@@ -130,50 +147,27 @@ pub(super) fn handle_accelerometer(env: &mut Environment) -> Option<Instant> {
     let ns_interval = state.update_interval.unwrap_or(DEFAULT_UPDATE_INTERVAL);
     let rust_interval = Duration::from_secs_f64(ns_interval);
 
-    let now = Instant::now();
-    if let Some(due_by) = state.due_by {
-        if due_by > now {
-            return Some(due_by);
-        }
-
-        // See NSTimer implementation for a discussion of what this does.
-        // I don't know if iPhone OS uses this approach for accelerometer
-        // updates, but there's no obvious reason not to.
-        let overdue_by = now.duration_since(due_by);
-        // TODO: Use `.div_duration_f64()` once that is stabilized.
-        let advance_by = (overdue_by.as_secs_f64() / ns_interval).max(1.0).ceil();
-        assert!(advance_by == (advance_by as u32) as f64);
-        let advance_by = advance_by as u32;
-        if advance_by > 1 {
-            log_dbg!("Warning: Accelerometer is lagging. It is overdue by {}s and has missed {} interval(s)!", overdue_by.as_secs_f64(), advance_by - 1);
-        }
-        let advance_by = rust_interval.checked_mul(advance_by).unwrap();
-        let new_due_by = due_by.checked_add(advance_by).unwrap();
-        state.due_by = Some(new_due_by);
-    } else {
-        // In Resident Evil 4 the delegate is set before it fully initializes.
-        // If the first message is sent immediately, it crashes.
-        // This change prevents it by not sending the first message until the
-        // time interval first passes
-        let new_due_by = now.checked_add(rust_interval).unwrap();
-        state.due_by = Some(new_due_by);
-        if new_due_by > now {
-            return Some(new_due_by);
-        }
-    };
+    let (update_now, new_due_by) = schedule_update(state.due_by, env.clock.now(), rust_interval);
+    state.due_by = Some(new_due_by);
+    if !update_now {
+        return Some(new_due_by);
+    }
 
     // UIKit creates and drains autorelease pools when handling events.
     let pool: id = msg_class![env; NSAutoreleasePool new];
 
     let (x, y, z) = env.window().get_acceleration(&env.options);
+    let smoothing = env.options.accelerometer_smoothing;
+    let state = &mut env.framework_state.uikit.ui_accelerometer;
+    let (x, y, z) = filter_acceleration(
+        state.last_acceleration,
+        (x.into(), y.into(), z.into()),
+        smoothing.into(),
+    );
+    state.last_acceleration = Some((x, y, z));
     let timestamp: NSTimeInterval = msg_class![env; NSProcessInfo systemUptime];
     let acceleration: id = msg_class![env; UIAcceleration alloc];
-    *env.objc.borrow_mut(acceleration) = UIAccelerationHostObject {
-        x: x.into(),
-        y: y.into(),
-        z: z.into(),
-        timestamp,
-    };
+    *env.objc.borrow_mut(acceleration) = UIAccelerationHostObject { x, y, z, timestamp };
     autorelease(env, acceleration);
 
     let accelerometer: id = msg_class![env; UIAccelerometer sharedAccelerometer];
@@ -197,3 +191,115 @@ pub(super) fn handle_accelerometer(env: &mut Environment) -> Option<Instant> {
 
     env.framework_state.uikit.ui_accelerometer.due_by
 }
+
+/// Decide whether an accelerometer update should be sent at `now`, given when
+/// the next update was due ([None] if none has been scheduled yet). Returns
+/// whether to send an update, and when the next one is due.
+fn schedule_update(due_by: Option<Instant>, now: Instant, interval: Duration) -> (bool, Instant) {
+    let Some(due_by) = due_by else {
+        // In Resident Evil 4 the delegate is set before it fully initializes.
+        // If the first message is sent immediately, it crashes.
+        // This change prevents it by not sending the first message until the
+        // time interval first passes
+        return (false, now.checked_add(interval).unwrap());
+    };
+    if due_by > now {
+        return (false, due_by);
+    }
+
+    // See NSTimer implementation for a discussion of what this does.
+    // I don't know if iPhone OS uses this approach for accelerometer
+    // updates, but there's no obvious reason not to.
+    let overdue_by = now.duration_since(due_by);
+    let advance_by = overdue_by.as_nanos().div_ceil(interval.as_nanos()).max(1);
+    let advance_by = u32::try_from(advance_by).unwrap();
+    if advance_by > 1 {
+        log_dbg!("Warning: Accelerometer is lagging. It is overdue by {}s and has missed {} interval(s)!", overdue_by.as_secs_f64(), advance_by - 1);
+    }
+    let advance_by = interval.checked_mul(advance_by).unwrap();
+    (true, due_by.checked_add(advance_by).unwrap())
+}
+
+/// Clamp a new accelerometer reading to the range the iPhone can report, and
+/// smooth it with a low-pass filter: each value is `smoothing` times the
+/// previous value plus `1 - smoothing` times the new one. A `smoothing` of 0
+/// disables the filter.
+fn filter_acceleration(
+    previous: Option<Acceleration>,
+    new: Acceleration,
+    smoothing: f64,
+) -> Acceleration {
+    let clamp = |value: UIAccelerationValue| value.clamp(-MAX_ACCELERATION, MAX_ACCELERATION);
+    let new = (clamp(new.0), clamp(new.1), clamp(new.2));
+    let Some(previous) = previous else {
+        return new;
+    };
+    let mix = |previous: UIAccelerationValue, new: UIAccelerationValue| {
+        previous * smoothing + new * (1.0 - smoothing)
+    };
+    (
+        mix(previous.0, new.0),
+        mix(previous.1, new.1),
+        mix(previous.2, new.2),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn update_interval_scheduling() {
+        let start = Instant::now();
+        let interval = Duration::from_millis(100);
+
+        // The first update is delayed by one interval.
+        let (update_now, due_by) = schedule_update(None, start, interval);
+        assert!(!update_now);
+        assert_eq!(due_by, start + interval);
+
+        // Polling early doesn't send an update.
+        let early = start + Duration::from_millis(50);
+        assert_eq!(
+            schedule_update(Some(due_by), early, interval),
+            (false, due_by)
+        );
+
+        // Updates are sent at the interval, without drifting when late.
+        let late = due_by + Duration::from_millis(10);
+        let (update_now, due_by) = schedule_update(Some(due_by), late, interval);
+        assert!(update_now);
+        assert_eq!(due_by, start + interval * 2);
+
+        // Missed updates are skipped.
+        let very_late = due_by + Duration::from_millis(250);
+        let (update_now, due_by) = schedule_update(Some(due_by), very_late, interval);
+        assert!(update_now);
+        assert_eq!(due_by, start + interval * 5);
+    }
+
+    #[test]
+    fn smoothing_filter() {
+        // No smoothing, but values are clamped.
+        assert_eq!(
+            filter_acceleration(Some((1.0, 1.0, 1.0)), (0.5, -3.0, 3.0), 0.0),
+            (0.5, -MAX_ACCELERATION, MAX_ACCELERATION)
+        );
+        // The first reading is used as-is.
+        assert_eq!(
+            filter_acceleration(None, (0.5, 0.0, -1.0), 0.75),
+            (0.5, 0.0, -1.0)
+        );
+        // Smoothing mixes in the previous value.
+        assert_eq!(
+            filter_acceleration(Some((1.0, 0.0, -1.0)), (0.0, 1.0, -1.0), 0.75),
+            (0.75, 0.25, -1.0)
+        );
+        // Repeated readings converge on the new value.
+        let mut value = (0.0, 0.0, 0.0);
+        for _ in 0..100 {
+            value = filter_acceleration(Some(value), (1.0, 1.0, 1.0), 0.5);
+        }
+        assert!((value.0 - 1.0).abs() < 1e-6);
+    }
+}
@@ -7,21 +7,31 @@
 
 use super::ui_device::*;
 use crate::dyld::{export_c_func, ConstantExports, FunctionExports, HostConstant};
-use crate::frameworks::foundation::{ns_array, ns_string, NSInteger, NSUInteger};
+use crate::frameworks::foundation::{ns_array, ns_string, ns_user_defaults, NSInteger, NSUInteger};
 use crate::frameworks::uikit::ui_nib::load_main_nib_file;
+use crate::libc::stdio;
 use crate::mem::MutPtr;
 use crate::objc::{
-    autorelease, id, msg, msg_class, nil, objc_classes, release, retain, ClassExports, HostObject,
-    NSZonePtr,
+    autorelease, id, msg, msg_class, msg_send, nil, objc_classes, release, retain, ClassExports,
+    HostObject, NSZonePtr,
 };
-use crate::window::DeviceOrientation;
+use crate::window::{AppState, DeviceOrientation};
 use crate::Environment;
 
-#[derive(Default)]
 pub struct State {
     /// [UIApplication sharedApplication]
     shared_application: Option<id>,
     pub(super) status_bar_hidden: bool,
+    app_state: AppState,
+}
+impl Default for State {
+    fn default() -> Self {
+        State {
+            shared_application: None,
+            status_bar_hidden: false,
+            app_state: AppState::Active,
+        }
+    }
 }
 
 struct UIApplicationHostObject {
@@ -31,6 +41,11 @@ struct UIApplicationHostObject {
 impl HostObject for UIApplicationHostObject {}
 
 type UIInterfaceOrientation = UIDeviceOrientation;
+
+pub type UIApplicationState = NSInteger;
+pub const UIApplicationStateActive: UIApplicationState = 0;
+pub const UIApplicationStateInactive: UIApplicationState = 1;
+pub const UIApplicationStateBackground: UIApplicationState = 2;
 type UIRemoteNotificationType = NSUInteger;
 
 pub const CLASSES: ClassExports = objc_classes! {
@@ -89,6 +104,14 @@ pub const CLASSES: ClassExports = objc_classes! {
     msg![env; this setStatusBarHidden:hidden]
 }
 
+- (UIApplicationState)applicationState {
+    match env.framework_state.uikit.ui_application.app_state {
+        AppState::Active => UIApplicationStateActive,
+        AppState::Inactive => UIApplicationStateInactive,
+        AppState::Background => UIApplicationStateBackground,
+    }
+}
+
 - (UIInterfaceOrientation)statusBarOrientation {
     match env.window().current_rotation() {
         DeviceOrientation::Portrait => UIDeviceOrientationPortrait,
@@ -317,6 +340,82 @@ pub(super) fn UIApplicationMain(
     let _: () = msg![env; run_loop run];
 }
 
+/// A change in the app's lifecycle that the app delegate and any observers
+/// are told about.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum LifecycleEvent {
+    WillResignActive,
+    DidEnterBackground,
+    WillEnterForeground,
+    DidBecomeActive,
+}
+impl LifecycleEvent {
+    fn delegate_method(self) -> &'static str {
+        match self {
+            LifecycleEvent::WillResignActive => "applicationWillResignActive:",
+            LifecycleEvent::DidEnterBackground => "applicationDidEnterBackground:",
+            LifecycleEvent::WillEnterForeground => "applicationWillEnterForeground:",
+            LifecycleEvent::DidBecomeActive => "applicationDidBecomeActive:",
+        }
+    }
+    fn notification_name(self) -> &'static str {
+        match self {
+            LifecycleEvent::WillResignActive => UIApplicationWillResignActiveNotification,
+            LifecycleEvent::DidEnterBackground => UIApplicationDidEnterBackgroundNotification,
+            LifecycleEvent::WillEnterForeground => UIApplicationWillEnterForegroundNotification,
+            LifecycleEvent::DidBecomeActive => UIApplicationDidBecomeActiveNotification,
+        }
+    }
+}
+
+/// The lifecycle events for going from one [AppState] to another, in order.
+fn lifecycle_events(from: AppState, to: AppState) -> Vec<LifecycleEvent> {
+    let mut events = Vec::new();
+    if from == AppState::Active && to != AppState::Active {
+        events.push(LifecycleEvent::WillResignActive);
+    }
+    if from != AppState::Background && to == AppState::Background {
+        events.push(LifecycleEvent::DidEnterBackground);
+    }
+    if from == AppState::Background && to != AppState::Background {
+        events.push(LifecycleEvent::WillEnterForeground);
+    }
+    if from != AppState::Active && to == AppState::Active {
+        events.push(LifecycleEvent::DidBecomeActive);
+    }
+    events
+}
+
+/// Tell the app its state has changed (e.g. because the window was minimized)
+/// by calling the delegate methods and posting the notifications.
+pub(super) fn set_app_state(env: &mut Environment, new_state: AppState) {
+    let old_state = std::mem::replace(
+        &mut env.framework_state.uikit.ui_application.app_state,
+        new_state,
+    );
+    let ui_application: id = msg_class![env; UIApplication sharedApplication];
+    for event in lifecycle_events(old_state, new_state) {
+        log_dbg!("Sending lifecycle event {:?}", event);
+        let pool: id = msg_class![env; NSAutoreleasePool new];
+        let delegate: id = msg![env; ui_application delegate];
+        if env
+            .objc
+            .object_has_method_named(&env.mem, delegate, event.delegate_method())
+        {
+            let sel = env.objc.lookup_selector(event.delegate_method()).unwrap();
+            () = msg_send(env, (delegate, sel, ui_application));
+        }
+        let center: id = msg_class![env; NSNotificationCenter defaultCenter];
+        let name = ns_string::get_static_str(env, event.notification_name());
+        () = msg![env; center postNotificationName:name object:ui_application];
+        let _: () = msg![env; pool drain];
+    }
+
+    if new_state == AppState::Background && env.options.pause_in_background {
+        env.window_mut().pause_while_in_background();
+    }
+}
+
 /// Tell the app it's about to quit and then exit.
 pub(super) fn exit(env: &mut Environment) {
     let ui_application: id = msg_class![env; UIApplication sharedApplication];
@@ -347,11 +446,25 @@ pub(super) fn exit(env: &mut Environment) {
         let _: () = msg![env; pool drain];
     };
 
+    // Like on iOS, the user defaults are saved when the app exits, so the app
+    // doesn't need to call synchronize itself.
+    ns_user_defaults::synchronize_standard_defaults(env);
+
+    stdio::flush_all(env);
+    env.print_exit_summaries();
     std::process::exit(0);
 }
 
 pub const UIApplicationDidReceiveMemoryWarningNotification: &str =
     "UIApplicationDidReceiveMemoryWarningNotification";
+pub const UIApplicationWillResignActiveNotification: &str =
+    "UIApplicationWillResignActiveNotification";
+pub const UIApplicationDidEnterBackgroundNotification: &str =
+    "UIApplicationDidEnterBackgroundNotification";
+pub const UIApplicationWillEnterForegroundNotification: &str =
+    "UIApplicationWillEnterForegroundNotification";
+pub const UIApplicationDidBecomeActiveNotification: &str =
+    "UIApplicationDidBecomeActiveNotification";
 pub const UIApplicationLaunchOptionsRemoteNotificationKey: &str =
     "UIApplicationLaunchOptionsRemoteNotificationKey";
 
@@ -365,6 +478,57 @@ pub const CONSTANTS: ConstantExports = &[
         "_UIApplicationLaunchOptionsRemoteNotificationKey",
         HostConstant::NSString(UIApplicationLaunchOptionsRemoteNotificationKey),
     ),
+    (
+        "_UIApplicationWillResignActiveNotification",
+        HostConstant::NSString(UIApplicationWillResignActiveNotification),
+    ),
+    (
+        "_UIApplicationDidEnterBackgroundNotification",
+        HostConstant::NSString(UIApplicationDidEnterBackgroundNotification),
+    ),
+    (
+        "_UIApplicationWillEnterForegroundNotification",
+        HostConstant::NSString(UIApplicationWillEnterForegroundNotification),
+    ),
+    (
+        "_UIApplicationDidBecomeActiveNotification",
+        HostConstant::NSString(UIApplicationDidBecomeActiveNotification),
+    ),
 ];
 
 pub const FUNCTIONS: FunctionExports = &[export_c_func!(UIApplicationMain(_, _, _, _))];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lifecycle_event_mapping() {
+        use AppState::*;
+        use LifecycleEvent::*;
+        assert_eq!(lifecycle_events(Active, Active), []);
+        assert_eq!(lifecycle_events(Active, Inactive), [WillResignActive]);
+        assert_eq!(
+            lifecycle_events(Active, Background),
+            [WillResignActive, DidEnterBackground]
+        );
+        assert_eq!(lifecycle_events(Inactive, Background), [DidEnterBackground]);
+        assert_eq!(
+            lifecycle_events(Background, Inactive),
+            [WillEnterForeground]
+        );
+        assert_eq!(
+            lifecycle_events(Background, Active),
+            [WillEnterForeground, DidBecomeActive]
+        );
+        assert_eq!(lifecycle_events(Inactive, Active), [DidBecomeActive]);
+        assert_eq!(
+            WillResignActive.notification_name(),
+            "UIApplicationWillResignActiveNotification"
+        );
+        assert_eq!(
+            DidEnterBackground.delegate_method(),
+            "applicationDidEnterBackground:"
+        );
+    }
+}
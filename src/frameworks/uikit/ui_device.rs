@@ -9,8 +9,9 @@ use crate::dyld::ConstantExports;
 use crate::dyld::HostConstant;
 use crate::frameworks::foundation::ns_string;
 use crate::frameworks::foundation::NSInteger;
-use crate::objc::{id, objc_classes, ClassExports, TrivialHostObject};
+use crate::objc::{id, msg, msg_class, nil, objc_classes, ClassExports, TrivialHostObject};
 use crate::window::DeviceOrientation;
+use crate::Environment;
 
 pub const UIDeviceOrientationDidChangeNotification: &str =
     "UIDeviceOrientationDidChangeNotification";
@@ -31,6 +32,116 @@ pub const UIDeviceOrientationFaceDown: UIDeviceOrientation = 6;
 #[derive(Default)]
 pub struct State {
     current_device: Option<id>,
+    /// Number of unbalanced `beginGeneratingDeviceOrientationNotifications`
+    /// calls.
+    orientation_notification_count: u32,
+    /// Used instead of the window's orientation in headless mode.
+    headless_orientation: Option<DeviceOrientation>,
+}
+
+/// Get the [DeviceOrientation] for the name of a `UIInterfaceOrientation`, as
+/// used in `Info.plist`. Note that the interface orientation is the opposite
+/// of the device orientation for landscape, e.g. rotating the device to the
+/// left means the interface is rotated to the right.
+fn orientation_for_interface_orientation_name(name: &str) -> Option<DeviceOrientation> {
+    match name {
+        "UIInterfaceOrientationPortrait" => Some(DeviceOrientation::Portrait),
+        "UIInterfaceOrientationLandscapeRight" => Some(DeviceOrientation::LandscapeLeft),
+        "UIInterfaceOrientationLandscapeLeft" => Some(DeviceOrientation::LandscapeRight),
+        // TODO: UIInterfaceOrientationPortraitUpsideDown
+        _ => None,
+    }
+}
+
+/// The orientation after `current` when cycling through the `supported`
+/// orientations. Returns `current` if there's nothing else to rotate to.
+fn next_orientation(
+    current: DeviceOrientation,
+    supported: &[DeviceOrientation],
+) -> DeviceOrientation {
+    const CYCLE: [DeviceOrientation; 3] = [
+        DeviceOrientation::Portrait,
+        DeviceOrientation::LandscapeLeft,
+        DeviceOrientation::LandscapeRight,
+    ];
+    let idx = CYCLE.iter().position(|&o| o == current).unwrap();
+    (1..CYCLE.len())
+        .map(|i| CYCLE[(idx + i) % CYCLE.len()])
+        .find(|o| supported.contains(o))
+        .unwrap_or(current)
+}
+
+/// The orientations the app supports according to its `Info.plist`. All
+/// orientations are assumed to be supported if it doesn't say.
+fn supported_orientations(env: &Environment) -> Vec<DeviceOrientation> {
+    let mut supported: Vec<DeviceOrientation> = env
+        .bundle
+        .supported_interface_orientations()
+        .into_iter()
+        .filter_map(orientation_for_interface_orientation_name)
+        .collect();
+    if supported.is_empty() {
+        supported = vec![
+            DeviceOrientation::Portrait,
+            DeviceOrientation::LandscapeLeft,
+            DeviceOrientation::LandscapeRight,
+        ];
+    }
+    supported
+}
+
+pub fn current_orientation(env: &Environment) -> DeviceOrientation {
+    if let Some(window) = env.window.as_ref() {
+        window.current_rotation()
+    } else {
+        env.framework_state
+            .uikit
+            .ui_device
+            .headless_orientation
+            .unwrap_or(env.options.initial_orientation)
+    }
+}
+
+/// Rotate the device, posting `UIDeviceOrientationDidChangeNotification` if
+/// the app asked for it.
+pub fn set_orientation(env: &mut Environment, new_orientation: DeviceOrientation) {
+    if new_orientation == current_orientation(env) {
+        return;
+    }
+    log_dbg!("Rotating device to {:?}", new_orientation);
+    if let Some(window) = env.window.as_mut() {
+        window.rotate_device(new_orientation);
+    } else {
+        env.framework_state.uikit.ui_device.headless_orientation = Some(new_orientation);
+    }
+
+    if env
+        .framework_state
+        .uikit
+        .ui_device
+        .orientation_notification_count
+        == 0
+    {
+        return;
+    }
+    let device: id = msg_class![env; UIDevice currentDevice];
+    let center: id = msg_class![env; NSNotificationCenter defaultCenter];
+    let name = ns_string::get_static_str(env, UIDeviceOrientationDidChangeNotification);
+    let _: () = msg![env; center postNotificationName:name object:device userInfo:nil];
+}
+
+/// Handle the user asking to rotate the device (see
+/// [crate::window::Event::RotateDevice]). Orientations the app doesn't
+/// support are skipped.
+pub fn rotate_to_next_orientation(env: &mut Environment) {
+    let current = current_orientation(env);
+    let new_orientation = next_orientation(current, &supported_orientations(env));
+    if new_orientation == current {
+        log!("The app doesn't support any other orientations, not rotating.");
+        return;
+    }
+    echo!("Rotating device to {:?}.", new_orientation);
+    set_orientation(env, new_orientation);
 }
 
 pub const CONSTANTS: ConstantExports = &[(
@@ -59,11 +170,16 @@ pub const CLASSES: ClassExports = objc_classes! {
 }
 
 - (())beginGeneratingDeviceOrientationNotifications {
-    log!("TODO: beginGeneratingDeviceOrientationNotifications");
+    env.framework_state.uikit.ui_device.orientation_notification_count += 1;
 }
 - (())endGeneratingDeviceOrientationNotifications {
-    log!("TODO: endGeneratingDeviceOrientationNotifications");
+    let count = &mut env.framework_state.uikit.ui_device.orientation_notification_count;
+    *count = count.saturating_sub(1);
 }
+- (bool)isGeneratingDeviceOrientationNotifications {
+    env.framework_state.uikit.ui_device.orientation_notification_count > 0
+}
+
 - (id)model {
     // TODO: Hardcoded to iPhone for now
     ns_string::get_static_str(env, "iPhone")
@@ -94,13 +210,53 @@ pub const CLASSES: ClassExports = objc_classes! {
 }
 
 - (UIDeviceOrientation)orientation {
-    match env.window().current_rotation() {
+    match current_orientation(env) {
         DeviceOrientation::Portrait => UIDeviceOrientationPortrait,
         DeviceOrientation::LandscapeLeft => UIDeviceOrientationLandscapeLeft,
         DeviceOrientation::LandscapeRight => UIDeviceOrientationLandscapeRight
     }
 }
+// Private API, but some old apps use it to force an orientation.
+- (())setOrientation:(UIDeviceOrientation)orientation {
+    let new_orientation = match orientation {
+        UIDeviceOrientationPortrait => DeviceOrientation::Portrait,
+        UIDeviceOrientationLandscapeLeft => DeviceOrientation::LandscapeLeft,
+        UIDeviceOrientationLandscapeRight => DeviceOrientation::LandscapeRight,
+        _ => {
+            log!("TODO: setOrientation:{}, ignoring", orientation);
+            return;
+        }
+    };
+    set_orientation(env, new_orientation);
+}
 
 @end
 
 };
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn orientation_cycling() {
+        use DeviceOrientation::*;
+        let all = [Portrait, LandscapeLeft, LandscapeRight];
+        assert!(next_orientation(Portrait, &all) == LandscapeLeft);
+        assert!(next_orientation(LandscapeLeft, &all) == LandscapeRight);
+        assert!(next_orientation(LandscapeRight, &all) == Portrait);
+        let landscape = [LandscapeLeft, LandscapeRight];
+        assert!(next_orientation(LandscapeRight, &landscape) == LandscapeLeft);
+        assert!(next_orientation(Portrait, &landscape) == LandscapeLeft);
+        assert!(next_orientation(Portrait, &[Portrait]) == Portrait);
+    }
+
+    #[test]
+    fn interface_orientation_names() {
+        assert!(
+            orientation_for_interface_orientation_name("UIInterfaceOrientationLandscapeRight")
+                == Some(DeviceOrientation::LandscapeLeft)
+        );
+        assert!(orientation_for_interface_orientation_name("bogus").is_none());
+    }
+}
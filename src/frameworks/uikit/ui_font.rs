@@ -7,7 +7,9 @@
 
 use super::ui_graphics::UIGraphicsGetCurrentContext;
 use crate::font::{Font, TextAlignment, WrapMode};
+use crate::frameworks::core_graphics::cg_affine_transform::CGAffineTransform;
 use crate::frameworks::core_graphics::cg_bitmap_context::CGBitmapContextDrawer;
+use crate::frameworks::core_graphics::cg_context::CGContextRef;
 use crate::frameworks::core_graphics::{CGFloat, CGPoint, CGRect, CGSize};
 use crate::frameworks::foundation::ns_string::to_rust_string;
 use crate::frameworks::foundation::NSInteger;
@@ -24,9 +26,8 @@ pub(super) struct State {
 }
 impl State {
     fn get_font_by_kind(&mut self, font_kind: FontKind) -> &Font {
-        self.fonts
-            .entry(font_kind)
-            .or_insert_with(|| match font_kind {
+        if !self.fonts.contains_key(&font_kind) {
+            let font = match font_kind {
                 FontKind::MonoRegular => Font::mono_regular(),
                 FontKind::MonoBold => Font::mono_bold(),
                 FontKind::MonoBoldItalic => Font::mono_bold_italic(),
@@ -39,7 +40,29 @@ impl State {
                 FontKind::SerifBold => Font::serif_bold(),
                 FontKind::SerifBoldItalic => Font::serif_bold_italic(),
                 FontKind::SerifItalic => Font::serif_italic(),
-            })
+            };
+            // The default fonts (see font.rs) are the Liberation family, which
+            // are a good substitute for Helvetica, the iPhone OS system font.
+            // Unfortunately, there is no CJK support in these fonts, so Noto
+            // Sans JP is used for any characters they're missing. It has no
+            // italic variant, so italic styles fall back to upright glyphs.
+            let fallback = match font_kind {
+                FontKind::MonoBold
+                | FontKind::MonoBoldItalic
+                | FontKind::SansBold
+                | FontKind::SansBoldItalic
+                | FontKind::SerifBold
+                | FontKind::SerifBoldItalic => {
+                    self.sans_bold_ja.get_or_insert_with(Font::sans_bold_ja)
+                }
+                _ => self
+                    .sans_regular_ja
+                    .get_or_insert_with(Font::sans_regular_ja),
+            };
+            let font = font.with_fallback(fallback);
+            self.fonts.insert(font_kind, font);
+        }
+        &self.fonts[&font_kind]
     }
 }
 
@@ -148,41 +171,6 @@ fn convert_line_break_mode(ui_mode: UILineBreakMode) -> WrapMode {
     }
 }
 
-#[rustfmt::skip]
-fn get_font<'a>(state: &'a mut State, kind: FontKind, text: &str) -> &'a Font {
-    // The default fonts (see font.rs) are the Liberation family, which are a
-    // good substitute for Helvetica, the iPhone OS system font. Unfortunately,
-    // there is no CJK support in these fonts. To support Super Monkey Ball in
-    // Japanese, let's fall back to Noto Sans JP when necessary.
-    // FIXME: This heuristic is incomplete and a proper font fallback system
-    // should be used instead.
-    for c in text.chars() {
-        let c = c as u32;
-        if (0x3000..=0x30FF).contains(&c) || // JA punctuation, kana
-           (0xFF00..=0xFFEF).contains(&c) || // full-width/half-width chars
-           (0x4e00..=0x9FA0).contains(&c) || // various kanji
-           (0x3400..=0x4DBF).contains(&c) { // more kanji
-            match kind {
-                // CJK has no italic equivalent
-                FontKind::MonoRegular | FontKind::MonoItalic | FontKind::SansRegular | FontKind::SansItalic | FontKind::SerifRegular | FontKind::SerifItalic => {
-                    if state.sans_regular_ja.is_none() {
-                        state.sans_regular_ja = Some(Font::sans_regular_ja());
-                    }
-                    return state.sans_regular_ja.as_ref().unwrap();
-                },
-                FontKind::MonoBold | FontKind::MonoBoldItalic | FontKind::SansBold | FontKind::SansBoldItalic | FontKind::SerifBold | FontKind::SerifBoldItalic => {
-                    if state.sans_bold_ja.is_none() {
-                        state.sans_bold_ja = Some(Font::sans_bold_ja());
-                    }
-                    return state.sans_bold_ja.as_ref().unwrap();
-                },
-            }
-        }
-    }
-
-    state.get_font_by_kind(kind)
-}
-
 /// Called by the `sizeWithFont:` method family on `NSString`.
 pub fn size_with_font(
     env: &mut Environment,
@@ -192,11 +180,11 @@ pub fn size_with_font(
 ) -> CGSize {
     let host_object = env.objc.borrow::<UIFontHostObject>(font);
 
-    let font = get_font(
-        &mut env.framework_state.uikit.ui_font,
-        host_object.kind,
-        text,
-    );
+    let font = env
+        .framework_state
+        .uikit
+        .ui_font
+        .get_font_by_kind(host_object.kind);
 
     let wrap = constrained.map(|(size, ui_mode)| (size.width, convert_line_break_mode(ui_mode)));
 
@@ -259,6 +247,32 @@ fn draw_font_glyph(
     }
 }
 
+/// Called by `CGContextShowText` and `CGContextShowTextAtPoint`, which aren't
+/// part of UIKit, but use the same fonts. Returns the width of the text.
+pub fn show_cg_text(
+    env: &mut Environment,
+    context: CGContextRef,
+    font_name: &str,
+    font_size: CGFloat,
+    text_matrix: CGAffineTransform,
+    text: &str,
+    visible: bool,
+) -> CGFloat {
+    let kind = get_equivalent_font(font_name).unwrap_or_else(|| {
+        log!(
+            "No replacement found for font {}. Using system font instead.",
+            font_name
+        );
+        FontKind::SansRegular
+    });
+    let font = env.framework_state.uikit.ui_font.get_font_by_kind(kind);
+    if !visible {
+        return font.calculate_text_size(font_size, text, None).0;
+    }
+    let mut drawer = CGBitmapContextDrawer::new(&env.objc, &mut env.mem, context);
+    drawer.show_text(font, font_size, text_matrix, text)
+}
+
 /// Called by the `drawAtPoint:` method family on `NSString`.
 pub fn draw_at_point(
     env: &mut Environment,
@@ -271,11 +285,11 @@ pub fn draw_at_point(
 
     let host_object = env.objc.borrow::<UIFontHostObject>(font);
 
-    let font = get_font(
-        &mut env.framework_state.uikit.ui_font,
-        host_object.kind,
-        text,
-    );
+    let font = env
+        .framework_state
+        .uikit
+        .ui_font
+        .get_font_by_kind(host_object.kind);
 
     let width_and_line_break_mode =
         width_and_line_break_mode.map(|(width, ui_mode)| (width, convert_line_break_mode(ui_mode)));
@@ -321,11 +335,11 @@ pub fn draw_in_rect(
 
     let host_object = env.objc.borrow::<UIFontHostObject>(font);
 
-    let font = get_font(
-        &mut env.framework_state.uikit.ui_font,
-        host_object.kind,
-        text,
-    );
+    let font = env
+        .framework_state
+        .uikit
+        .ui_font
+        .get_font_by_kind(host_object.kind);
 
     let mut drawer = CGBitmapContextDrawer::new(&env.objc, &mut env.mem, context);
     let fill_color = drawer.rgb_fill_color();
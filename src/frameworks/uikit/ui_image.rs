@@ -5,20 +5,30 @@
  */
 //! `UIImage`.
 
+use crate::dyld::{export_c_func, FunctionExports};
 use crate::frameworks::core_graphics::cg_context::CGContextDrawImage;
 use crate::frameworks::core_graphics::cg_image::{self, CGImageRef, CGImageRelease, CGImageRetain};
-use crate::frameworks::core_graphics::{CGRect, CGSize};
-use crate::frameworks::foundation::{ns_data, ns_string, NSInteger};
+use crate::frameworks::core_graphics::{CGFloat, CGRect, CGSize};
+use crate::frameworks::foundation::{
+    ns_array, ns_data, ns_string, NSInteger, NSTimeInterval, NSUInteger,
+};
 use crate::frameworks::uikit::ui_graphics::UIGraphicsGetCurrentContext;
 use crate::fs::GuestPath;
-use crate::image::Image;
+use crate::image::{encode_jpeg, encode_png, Frame, Image};
+use crate::mem::GuestUSize;
 use crate::objc::{
-    autorelease, id, msg, msg_class, nil, objc_classes, release, ClassExports, HostObject,
+    autorelease, id, msg, msg_class, nil, objc_classes, release, retain, ClassExports, HostObject,
     NSZonePtr,
 };
+use crate::Environment;
+use std::time::Duration;
 
 struct UIImageHostObject {
     cg_image: CGImageRef,
+    /// `NSArray*` of `UIImage*` frames, if this is an animated image. Strong
+    /// reference.
+    images: id,
+    duration: NSTimeInterval,
 }
 impl HostObject for UIImageHostObject {}
 
@@ -29,7 +39,11 @@ pub const CLASSES: ClassExports = objc_classes! {
 @implementation UIImage: NSObject
 
 + (id)allocWithZone:(NSZonePtr)_zone {
-    let host_object = Box::new(UIImageHostObject { cg_image: nil });
+    let host_object = Box::new(UIImageHostObject {
+        cg_image: nil,
+        images: nil,
+        duration: 0.0,
+    });
     env.objc.alloc_object(this, host_object, &mut env.mem)
 }
 
@@ -62,9 +76,91 @@ pub const CLASSES: ClassExports = objc_classes! {
     autorelease(env, new)
 }
 
++ (id)animatedImageWithImages:(id)images // NSArray* of UIImage*
+                     duration:(NSTimeInterval)duration {
+    let count: NSUInteger = msg![env; images count];
+    if count == 0 {
+        return nil;
+    }
+    let first: id = msg![env; images objectAtIndex:0u32];
+    let cg_image: CGImageRef = msg![env; first CGImage];
+    let new: id = msg![env; this alloc];
+    let new: id = msg![env; new initWithCGImage:cg_image];
+    let images: id = msg![env; images copy];
+    let host_object = env.objc.borrow_mut::<UIImageHostObject>(new);
+    host_object.images = images;
+    host_object.duration = duration;
+    autorelease(env, new)
+}
+
++ (id)animatedImageNamed:(id)name // NSString*
+                duration:(NSTimeInterval)duration {
+    let name_str = ns_string::to_rust_string(env, name);
+    let bundle: id = msg_class![env; NSBundle mainBundle];
+
+    // The frames are normally separate files: name0.png, name1.png, etc.
+    let png = ns_string::get_static_str(env, "png");
+    let mut images = Vec::new();
+    for i in 0..1024 {
+        let frame_name = ns_string::from_rust_string(env, format!("{}{}", name_str, i));
+        let path: id = msg![env; bundle pathForResource:frame_name ofType:png];
+        release(env, frame_name);
+        if path == nil {
+            break;
+        }
+        let image: id = msg![env; this imageWithContentsOfFile:path];
+        images.push(image);
+    }
+    if !images.is_empty() {
+        for &image in &images {
+            retain(env, image);
+        }
+        let images = ns_array::from_vec(env, images);
+        let new: id = msg![env; this animatedImageWithImages:images duration:duration];
+        release(env, images);
+        return new;
+    }
+
+    // touchHLE extension: the name can also be an animated GIF file. Its own
+    // timing is used if no duration is specified.
+    let path: id = msg![env; bundle pathForResource:name ofType:nil];
+    let frames = if path == nil {
+        None
+    } else {
+        let path = ns_string::to_rust_string(env, path);
+        env.fs
+            .read(GuestPath::new(&path))
+            .ok()
+            .and_then(|bytes| Image::frames_from_bytes(&bytes).ok())
+    };
+    let Some(frames) = frames else {
+        log!("Warning: [UIImage animatedImageNamed:{:?} duration:{}] => nil", name_str, duration);
+        return nil;
+    };
+    let total_delay: Duration = frames.iter().map(|frame| frame.delay).sum();
+    let duration = if duration > 0.0 { duration } else { total_delay.as_secs_f64() };
+    let images = frames
+        .into_iter()
+        .map(|Frame { image, .. }| {
+            let cg_image = cg_image::from_image(env, image);
+            let new: id = msg![env; this alloc];
+            let new: id = msg![env; new initWithCGImage:cg_image];
+            CGImageRelease(env, cg_image);
+            new
+        })
+        .collect();
+    let images = ns_array::from_vec(env, images);
+    let new: id = msg![env; this animatedImageWithImages:images duration:duration];
+    release(env, images);
+    new
+}
+
 - (())dealloc {
-    let &UIImageHostObject { cg_image } = env.objc.borrow(this);
+    let &UIImageHostObject {
+        cg_image, images, ..
+    } = env.objc.borrow(this);
     CGImageRelease(env, cg_image);
+    release(env, images);
 
     env.objc.dealloc_object(this, &mut env.mem)
 }
@@ -107,6 +203,14 @@ pub const CLASSES: ClassExports = objc_classes! {
     env.objc.borrow::<UIImageHostObject>(this).cg_image
 }
 
+- (id)images {
+    env.objc.borrow::<UIImageHostObject>(this).images
+}
+
+- (NSTimeInterval)duration {
+    env.objc.borrow::<UIImageHostObject>(this).duration
+}
+
 // TODO: should have UIImageOrientation type
 - (NSInteger)imageOrientation {
     // FIXME: load image orientation info from file?
@@ -131,3 +235,52 @@ pub const CLASSES: ClassExports = objc_classes! {
 @end
 
 };
+
+/// Shared implementation of `UIImagePNGRepresentation` and
+/// `UIImageJPEGRepresentation`: encode the image and return it as an
+/// autoreleased `NSData`, or `nil` if that fails.
+fn encode_representation(
+    env: &mut Environment,
+    image: id, // UIImage*
+    encode: impl FnOnce(&Image) -> Result<Vec<u8>, String>,
+) -> id {
+    if image == nil {
+        return nil;
+    }
+    let cg_image = env.objc.borrow::<UIImageHostObject>(image).cg_image;
+    let encoded = match encode(cg_image::borrow_image(&env.objc, cg_image)) {
+        Ok(encoded) => encoded,
+        Err(e) => {
+            log!("Warning: Couldn't encode {:?}: {}, returning nil", image, e);
+            return nil;
+        }
+    };
+    let length: GuestUSize = encoded.len().try_into().unwrap();
+    let bytes = env.mem.alloc(length);
+    env.mem
+        .bytes_at_mut(bytes.cast(), length)
+        .copy_from_slice(&encoded);
+    msg_class![env; NSData dataWithBytesNoCopy:bytes length:length]
+}
+
+fn UIImagePNGRepresentation(env: &mut Environment, image: id) -> id {
+    encode_representation(env, image, |image| {
+        // PNG files use straight alpha, but UIImage's pixels are premultiplied.
+        let pixels = crate::image::unpremultiply(image.pixels());
+        encode_png(&pixels, image.dimensions(), false)
+    })
+}
+
+fn UIImageJPEGRepresentation(env: &mut Environment, image: id, compression_quality: CGFloat) -> id {
+    // JPEG has no alpha channel. Premultiplied pixels have the same color
+    // values as if the image had been composited onto black.
+    let quality = (compression_quality * 100.0).round().clamp(1.0, 100.0) as u8;
+    encode_representation(env, image, |image| {
+        encode_jpeg(image.pixels(), image.dimensions(), quality)
+    })
+}
+
+pub const FUNCTIONS: FunctionExports = &[
+    export_c_func!(UIImagePNGRepresentation(_)),
+    export_c_func!(UIImageJPEGRepresentation(_, _)),
+];
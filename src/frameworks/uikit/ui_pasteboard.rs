@@ -0,0 +1,197 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `UIPasteboard`.
+//!
+//! Only the general pasteboard and plain text are supported. The general
+//! pasteboard is backed by the host's clipboard, or in headless mode, by a
+//! clipboard private to touchHLE.
+
+use crate::frameworks::foundation::{ns_array, ns_dictionary, ns_string, NSUInteger};
+use crate::objc::{autorelease, id, msg, nil, objc_classes, ClassExports, TrivialHostObject};
+use crate::window::Clipboard;
+use crate::Environment;
+
+/// Uniform Type Identifier for plain text, used as a key in `items`.
+const PLAIN_TEXT_TYPE: &str = "public.utf8-plain-text";
+
+#[derive(Default)]
+pub struct State {
+    general_pasteboard: Option<id>,
+    /// Used instead of the host clipboard if there's no window, or if the
+    /// host refused to store some text.
+    private_text: Option<String>,
+}
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+@implementation UIPasteboard: NSObject
+
++ (id)generalPasteboard {
+    if let Some(pasteboard) = env.framework_state.uikit.ui_pasteboard.general_pasteboard {
+        pasteboard
+    } else {
+        let new = env.objc.alloc_static_object(
+            this,
+            Box::new(TrivialHostObject),
+            &mut env.mem
+        );
+        env.framework_state.uikit.ui_pasteboard.general_pasteboard = Some(new);
+        new
+    }
+}
+
+- (id)string {
+    match get_text(env) {
+        Some(text) => {
+            let string = ns_string::from_rust_string(env, text);
+            autorelease(env, string)
+        }
+        None => nil,
+    }
+}
+
+- (())setString:(id)string { // NSString *
+    if string == nil {
+        log!("Warning: [(UIPasteboard*){:?} setString:nil], ignoring", this);
+        return;
+    }
+    let text = ns_string::to_rust_string(env, string).into_owned();
+    set_text(env, text);
+}
+
+- (id)strings {
+    let strings = match get_text(env) {
+        Some(text) => vec![ns_string::from_rust_string(env, text)],
+        None => Vec::new(),
+    };
+    let array = ns_array::from_vec(env, strings);
+    autorelease(env, array)
+}
+
+- (id)items {
+    let items = match get_text(env) {
+        Some(text) => {
+            let key = ns_string::get_static_str(env, PLAIN_TEXT_TYPE);
+            let string = ns_string::from_rust_string(env, text);
+            let item = ns_dictionary::dict_from_keys_and_objects(env, &[(key, string)]);
+            autorelease(env, string);
+            vec![item]
+        }
+        None => Vec::new(),
+    };
+    let array = ns_array::from_vec(env, items);
+    autorelease(env, array)
+}
+
+- (bool)containsPasteboardTypes:(id)types { // NSArray<NSString *> *
+    if get_text(env).is_none() {
+        return false;
+    }
+    let count: NSUInteger = msg![env; types count];
+    (0..count).any(|i| {
+        let type_: id = msg![env; types objectAtIndex:i];
+        ns_string::to_rust_string(env, type_) == PLAIN_TEXT_TYPE
+    })
+}
+
+@end
+
+};
+
+fn get_text(env: &mut Environment) -> Option<String> {
+    let host = env.window.as_ref().map(|window| window as &dyn Clipboard);
+    read_clipboard(host, &env.framework_state.uikit.ui_pasteboard)
+}
+
+fn set_text(env: &mut Environment, text: String) {
+    let host = env.window.as_ref().map(|window| window as &dyn Clipboard);
+    write_clipboard(host, &mut env.framework_state.uikit.ui_pasteboard, text)
+}
+
+fn read_clipboard(host: Option<&dyn Clipboard>, state: &State) -> Option<String> {
+    host.and_then(|host| host.get_text())
+        .or_else(|| state.private_text.clone())
+}
+
+fn write_clipboard(host: Option<&dyn Clipboard>, state: &mut State, text: String) {
+    if let Some(host) = host {
+        match host.set_text(&text) {
+            Ok(()) => {
+                state.private_text = None;
+                return;
+            }
+            Err(e) => log!("Warning: Couldn't copy text to host clipboard: {}", e),
+        }
+    }
+    state.private_text = Some(text);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    #[derive(Default)]
+    struct MockClipboard {
+        text: RefCell<Option<String>>,
+        read_only: bool,
+    }
+    impl Clipboard for MockClipboard {
+        fn get_text(&self) -> Option<String> {
+            self.text.borrow().clone()
+        }
+        fn set_text(&self, text: &str) -> Result<(), String> {
+            if self.read_only {
+                return Err("read-only".to_string());
+            }
+            *self.text.borrow_mut() = Some(text.to_string());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn host_clipboard() {
+        let host = MockClipboard::default();
+        let mut state = State::default();
+        assert_eq!(read_clipboard(Some(&host), &state), None);
+
+        write_clipboard(Some(&host), &mut state, "copied in app".to_string());
+        assert_eq!(host.get_text().as_deref(), Some("copied in app"));
+        assert_eq!(
+            read_clipboard(Some(&host), &state).as_deref(),
+            Some("copied in app")
+        );
+
+        // Copied by the user on the host
+        host.set_text("copied on host").unwrap();
+        assert_eq!(
+            read_clipboard(Some(&host), &state).as_deref(),
+            Some("copied on host")
+        );
+    }
+
+    #[test]
+    fn private_clipboard() {
+        let mut state = State::default();
+        assert_eq!(read_clipboard(None, &state), None);
+        write_clipboard(None, &mut state, "headless".to_string());
+        assert_eq!(read_clipboard(None, &state).as_deref(), Some("headless"));
+
+        // If the host clipboard can't be written to, the text is kept anyway.
+        let host = MockClipboard {
+            read_only: true,
+            ..Default::default()
+        };
+        write_clipboard(Some(&host), &mut state, "refused".to_string());
+        assert_eq!(host.get_text(), None);
+        assert_eq!(
+            read_clipboard(Some(&host), &state).as_deref(),
+            Some("refused")
+        );
+    }
+}
@@ -5,7 +5,10 @@
  */
 //! `UIResponder`.
 
-use crate::objc::{id, msg, nil, objc_classes, ClassExports};
+use crate::frameworks::foundation::ns_string;
+use crate::objc::{id, msg, nil, objc_classes, release, ClassExports, SEL};
+use crate::window::TextInputEvent;
+use crate::Environment;
 
 #[derive(Default)]
 pub struct State {
@@ -90,3 +93,104 @@ pub const CLASSES: ClassExports = objc_classes! {
 @end
 
 };
+
+/// A key press to deliver to the first responder using the `UIKeyInput`
+/// protocol.
+#[derive(Debug, PartialEq)]
+enum KeyInput {
+    /// `insertText:`
+    Text(String),
+    /// `deleteBackward`
+    DeleteBackward,
+}
+
+/// Translate text input from the host keyboard into `UIKeyInput` key presses.
+/// Each character is inserted separately, like with the iPhone's on-screen
+/// keyboard. Only printable ASCII characters are supported for now. Return is
+/// inserted as a newline, which text fields treat as the return key.
+fn key_inputs_for_event(event: TextInputEvent) -> Vec<KeyInput> {
+    match event {
+        TextInputEvent::Text(text) => text
+            .chars()
+            .filter(|&c| {
+                let supported = c.is_ascii() && !c.is_ascii_control();
+                if !supported {
+                    log!("TODO: Ignoring unsupported text input character {:?}", c);
+                }
+                supported
+            })
+            .map(|c| KeyInput::Text(c.to_string()))
+            .collect(),
+        TextInputEvent::Backspace => vec![KeyInput::DeleteBackward],
+        TextInputEvent::Return => vec![KeyInput::Text("\n".to_string())],
+    }
+}
+
+/// For use by [super::handle_events]: deliver text input from the host
+/// keyboard to the first responder, if it implements `UIKeyInput`.
+pub(super) fn handle_text_input(env: &mut Environment, event: TextInputEvent) {
+    let responder = env.framework_state.uikit.ui_responder.first_responder;
+    if responder == nil {
+        log_dbg!(
+            "Ignoring text input {:?}, there's no first responder",
+            event
+        );
+        return;
+    }
+    let sel: SEL = env
+        .objc
+        .register_host_selector("insertText:".to_string(), &mut env.mem);
+    if !msg![env; responder respondsToSelector:sel] {
+        log_dbg!(
+            "Ignoring text input {:?}, first responder {:?} doesn't support it",
+            event,
+            responder
+        );
+        return;
+    }
+
+    for key_input in key_inputs_for_event(event) {
+        log_dbg!("Sending {:?} to first responder {:?}", key_input, responder);
+        match key_input {
+            KeyInput::Text(text) => {
+                let text = ns_string::from_rust_string(env, text);
+                () = msg![env; responder insertText:text];
+                release(env, text);
+            }
+            KeyInput::DeleteBackward => {
+                () = msg![env; responder deleteBackward];
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_input_translation() {
+        let text = |s: &str| KeyInput::Text(s.to_string());
+        assert_eq!(
+            key_inputs_for_event(TextInputEvent::Text("AB c1!".to_string())),
+            [
+                text("A"),
+                text("B"),
+                text(" "),
+                text("c"),
+                text("1"),
+                text("!")
+            ]
+        );
+        // Non-ASCII and control characters are dropped.
+        assert_eq!(
+            key_inputs_for_event(TextInputEvent::Text("é\tZ".to_string())),
+            [text("Z")]
+        );
+        assert_eq!(
+            key_inputs_for_event(TextInputEvent::Backspace),
+            [KeyInput::DeleteBackward]
+        );
+        assert_eq!(key_inputs_for_event(TextInputEvent::Return), [text("\n")]);
+    }
+}
@@ -180,6 +180,24 @@ pub const CLASSES: ClassExports = objc_classes! {
     log!("TODO: setEnablesReturnKeyAutomatically:{}", enables);
 }
 
+// UIKeyInput implementation
+- (bool)hasText {
+    let text_label = env.objc.borrow::<UITextFieldHostObject>(this).text_label;
+    let text: id = msg![env; text_label text];
+    let len: NSUInteger = msg![env; text length];
+    len != 0
+}
+- (())insertText:(id)text { // NSString*
+    if ns_string::to_rust_string(env, text) == "\n" {
+        handle_return(env, this);
+    } else {
+        handle_text(env, this, text);
+    }
+}
+- (())deleteBackward {
+    handle_backspace(env, this);
+}
+
 - (())touchesBegan:(id)_touches // NSSet* of UITouch*
          withEvent:(id)_event { // UIEvent*
     let _: bool = msg![env; this becomeFirstResponder];
@@ -280,11 +298,12 @@ pub const CLASSES: ClassExports = objc_classes! {
 
 };
 
-pub fn handle_text(env: &mut Environment, text_field: id, text: String) {
-    log_dbg!("Calling handle_text for {:?} with '{}'", text_field, text);
-    let txt = ns_string::from_rust_string(env, text);
-    let txt_len: NSUInteger = msg![env; txt length];
-    assert_eq!(txt_len, 1);
+fn handle_text(env: &mut Environment, text_field: id, txt: id) {
+    log_dbg!(
+        "Calling handle_text for {:?} with '{}'",
+        text_field,
+        ns_string::to_rust_string(env, txt)
+    );
 
     let text_label = env
         .objc
@@ -328,10 +347,9 @@ pub fn handle_text(env: &mut Environment, text_field: id, text: String) {
         () = msg![env; text_field setNeedsDisplay];
         release(env, new_text);
     }
-    release(env, txt);
 }
 
-pub fn handle_backspace(env: &mut Environment, text_field: id) {
+fn handle_backspace(env: &mut Environment, text_field: id) {
     log_dbg!("Calling handle_backspace for {:?}", text_field);
     let text_label = env
         .objc
@@ -374,7 +392,7 @@ pub fn handle_backspace(env: &mut Environment, text_field: id) {
     }
 }
 
-pub fn handle_return(env: &mut Environment, text_field: id) {
+fn handle_return(env: &mut Environment, text_field: id) {
     log_dbg!("Calling handle_return for {:?}", text_field);
     let delegate: id = env
         .objc
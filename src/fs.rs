@@ -293,6 +293,32 @@ pub fn resolve_path<'a>(path: &'a GuestPath, relative_to: Option<&'a GuestPath>)
     components
 }
 
+/// Find the name of a child of a directory that matches `name` apart from case,
+/// if `enabled` is [true] and there is one. This should only be used if there's
+/// no exact match. See [Fs::set_case_insensitive_fallback].
+fn case_insensitive_match(
+    enabled: bool,
+    children: &HashMap<String, FsNode>,
+    name: &str,
+) -> Option<String> {
+    if !enabled {
+        return None;
+    }
+    let name_lowercase = name.to_lowercase();
+    // If there are several matches, which is possible if the host filesystem
+    // is case-sensitive, pick one consistently.
+    let found = children
+        .keys()
+        .filter(|child_name| child_name.to_lowercase() == name_lowercase)
+        .min()?;
+    log!(
+        "Warning: No file or directory named {:?} exists, using {:?} instead because of case-insensitivity.",
+        name,
+        found
+    );
+    Some(found.clone())
+}
+
 /// Like [std::fs::OpenOptions] but for the guest filesystem.
 /// TODO: `create_new`.
 #[derive(Debug)]
@@ -453,6 +479,8 @@ pub struct Fs {
     root: FsNode,
     working_directory: GuestPathBuf,
     home_directory: GuestPathBuf,
+    /// See [Self::set_case_insensitive_fallback].
+    case_insensitive_fallback: bool,
 }
 impl Fs {
     /// Construct a filesystem containing a home directory for the app, its
@@ -573,6 +601,7 @@ impl Fs {
             root,
             working_directory,
             home_directory,
+            case_insensitive_fallback: true,
         };
         assert!(fs.lookup_node(&bundle_guest_path).is_some());
         (fs, bundle_guest_path)
@@ -584,9 +613,18 @@ impl Fs {
             root: FsNode::dir(),
             working_directory: GuestPathBuf::from(String::new()),
             home_directory: GuestPathBuf::from(String::new()),
+            case_insensitive_fallback: true,
         }
     }
 
+    /// Set whether a path component that doesn't match any file or directory
+    /// exactly may match one whose name only differs in case. This is on by
+    /// default, because iPhone OS's filesystem (HFS+) is case-insensitive, and
+    /// some apps rely on that, but the host filesystem might not be.
+    pub fn set_case_insensitive_fallback(&mut self, enabled: bool) {
+        self.case_insensitive_fallback = enabled;
+    }
+
     /// Get the absolute path of the guest app's (sandboxed) home directory.
     pub fn home_directory(&self) -> &GuestPath {
         &self.home_directory
@@ -632,7 +670,17 @@ impl Fs {
             else {
                 return None;
             };
-            node = children.get(*component)?
+            node = match children.get(*component) {
+                Some(child) => child,
+                None => {
+                    let name = case_insensitive_match(
+                        self.case_insensitive_fallback,
+                        children,
+                        component,
+                    )?;
+                    children.get(&name).unwrap()
+                }
+            };
         }
         Some(node)
     }
@@ -650,6 +698,7 @@ impl Fs {
         let components = resolve_path(path, Some(&self.working_directory));
         let (&final_component, parent_components) = components.split_last()?;
 
+        let fallback = self.case_insensitive_fallback;
         let mut parent = &mut self.root;
         for &component in parent_components {
             let FsNode::Directory {
@@ -659,10 +708,28 @@ impl Fs {
             else {
                 return None;
             };
-            parent = children.get_mut(component)?
+            parent = if children.contains_key(component) {
+                children.get_mut(component).unwrap()
+            } else {
+                let name = case_insensitive_match(fallback, children, component)?;
+                children.get_mut(&name).unwrap()
+            };
         }
 
-        Some((parent, final_component.to_string()))
+        // If there's an existing file whose name differs only in case, that
+        // file should be replaced, rather than creating a new one.
+        let final_component = match &*parent {
+            FsNode::Directory {
+                children,
+                writeable: _,
+            } if !children.contains_key(final_component) => {
+                case_insensitive_match(fallback, children, final_component)
+                    .unwrap_or_else(|| final_component.to_string())
+            }
+            _ => final_component.to_string(),
+        };
+
+        Some((parent, final_component))
     }
 
     /// Like [Path::exists] but for the guest filesystem.
@@ -1079,3 +1146,53 @@ impl Fs {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fs_with_mismatched_case() -> Fs {
+        let file = || FsNode::resource_file(String::new());
+        Fs {
+            root: FsNode::dir().with_child(
+                "Test.app",
+                FsNode::dir()
+                    .with_child("Default.png", file())
+                    .with_child("Images", FsNode::dir().with_child("Icon.PNG", file())),
+            ),
+            working_directory: GuestPathBuf::from("/Test.app".to_string()),
+            home_directory: GuestPathBuf::from("/".to_string()),
+            case_insensitive_fallback: true,
+        }
+    }
+
+    #[test]
+    fn case_insensitive_fallback() {
+        let mut fs = fs_with_mismatched_case();
+        // Exact matches
+        assert!(fs.is_file(GuestPath::new("/Test.app/Default.png")));
+        assert!(fs.is_file(GuestPath::new("Images/Icon.PNG")));
+        // Mismatched case
+        assert!(fs.is_file(GuestPath::new("/Test.app/Default.PNG")));
+        assert!(fs.is_file(GuestPath::new("/test.APP/images/icon.png")));
+        assert!(fs.is_dir(GuestPath::new("IMAGES")));
+        assert!(!fs.is_file(GuestPath::new("/Test.app/Default.jpg")));
+        // The existing file is replaced, not duplicated
+        let (_, name) = fs
+            .lookup_parent_node(GuestPath::new("images/ICON.png"))
+            .unwrap();
+        assert_eq!(name, "Icon.PNG");
+        let (_, name) = fs
+            .lookup_parent_node(GuestPath::new("images/new.png"))
+            .unwrap();
+        assert_eq!(name, "new.png");
+
+        fs.set_case_insensitive_fallback(false);
+        assert!(fs.is_file(GuestPath::new("/Test.app/Default.png")));
+        assert!(!fs.is_file(GuestPath::new("/Test.app/Default.PNG")));
+        assert!(!fs.is_file(GuestPath::new("/test.app/Images/Icon.PNG")));
+        assert!(fs
+            .lookup_parent_node(GuestPath::new("images/Icon.PNG"))
+            .is_none());
+    }
+}
@@ -7,9 +7,9 @@
 //! filesystem.
 use crate::fs::{FsNode, GuestPath};
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 use std::fmt::Debug;
-use std::io::{Cursor, Read};
+use std::io::{Cursor, Read, Seek};
 use std::path::{Path, PathBuf};
 use std::rc::Rc;
 use zip::result::ZipError;
@@ -72,27 +72,45 @@ pub enum BundleData {
     Zip {
         zip: ZipArchive<std::fs::File>,
         /// Path to the app bundle inside the zip file.
-        /// It should be `"Payload/<app name>.app"` (no trailing slash!).
+        /// It should be `"Payload/<app name>.app"` (no trailing slash!), but
+        /// there may be other directories in between.
         bundle_path: String,
     },
 }
 
 impl BundleData {
-    fn find_bundle_path_in_archive(zip: &mut ZipArchive<std::fs::File>) -> Result<String, String> {
+    /// Find the app bundle inside an IPA archive. It's normally at
+    /// `Payload/<app name>.app`, but it's accepted anywhere under `Payload/`.
+    /// Other files in the archive, like `iTunesArtwork` and
+    /// `iTunesMetadata.plist`, are ignored.
+    fn find_bundle_path_in_archive<R: Read + Seek>(
+        zip: &mut ZipArchive<R>,
+    ) -> Result<String, String> {
+        let mut bundle_paths = BTreeSet::new();
         for i in 0..zip.len() {
             let file = zip
                 .by_index(i)
                 .map_err(|e| format!("Could not open IPA archive entry: {e}"))?;
-            let path = file.name();
-            if let Some(name) = path
-                .strip_prefix("Payload/")
-                .and_then(|path| path.split_once('/'))
-                .and_then(|(name, _)| name.strip_suffix(".app"))
-            {
-                return Ok(format!("Payload/{name}.app"));
+            let Some(path) = file.name().strip_prefix("Payload/") else {
+                continue;
+            };
+            // The last component is a file name, or empty for a directory.
+            let components: Vec<&str> = path.split('/').collect();
+            let directories = &components[..components.len() - 1];
+            // If there's an app bundle inside the app bundle, the outer one
+            // is the one we want.
+            if let Some(app_idx) = directories.iter().position(|dir| dir.ends_with(".app")) {
+                bundle_paths.insert(format!("Payload/{}", directories[..=app_idx].join("/")));
             }
         }
-        Err("no app bundle found in the IPA archive".to_string())
+        match bundle_paths.len() {
+            0 => Err("no app bundle found in the IPA archive".to_string()),
+            1 => Ok(bundle_paths.into_iter().next().unwrap()),
+            _ => Err(format!(
+                "found multiple app bundles in the IPA archive: {}",
+                bundle_paths.into_iter().collect::<Vec<_>>().join(", ")
+            )),
+        }
     }
 
     pub fn bundle_name(&self) -> &str {
@@ -153,7 +171,10 @@ impl BundleData {
                 for i in 0..archive_guard.len() {
                     let file = archive_guard.by_index(i).unwrap(); // TODO: report IO error?
                     let name = file.name();
-                    if let Some(path) = name.strip_prefix(&bundle_path) {
+                    if let Some(path) = name
+                        .strip_prefix(&bundle_path)
+                        .filter(|path| path.starts_with('/'))
+                    {
                         let path = GuestPath::new(path);
                         if file.is_dir() {
                             builder.add_directory(path);
@@ -266,3 +287,66 @@ impl std::io::Seek for IpaFile {
         self.file.seek(pos)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use zip::write::{FileOptions, ZipWriter};
+
+    fn make_ipa(paths: &[&str]) -> ZipArchive<Cursor<Vec<u8>>> {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        for &path in paths {
+            if let Some(path) = path.strip_suffix('/') {
+                writer.add_directory(path, FileOptions::default()).unwrap();
+            } else {
+                writer.start_file(path, FileOptions::default()).unwrap();
+                writer.write_all(b"test").unwrap();
+            }
+        }
+        ZipArchive::new(writer.finish().unwrap()).unwrap()
+    }
+
+    fn find_bundle_path(paths: &[&str]) -> Result<String, String> {
+        BundleData::find_bundle_path_in_archive(&mut make_ipa(paths))
+    }
+
+    #[test]
+    fn well_formed_ipa() {
+        assert_eq!(
+            find_bundle_path(&[
+                "Payload/",
+                "Payload/Foo.app/",
+                "Payload/Foo.app/Info.plist",
+                "Payload/Foo.app/Foo",
+                "Payload/Foo.app/Plugin.app/Info.plist",
+            ]),
+            Ok("Payload/Foo.app".to_string())
+        );
+    }
+
+    #[test]
+    fn ipa_with_metadata() {
+        assert_eq!(
+            find_bundle_path(&[
+                "iTunesArtwork",
+                "iTunesMetadata.plist",
+                "Payload/Foo.app/Info.plist",
+            ]),
+            Ok("Payload/Foo.app".to_string())
+        );
+        // The app isn't directly under Payload/
+        assert_eq!(
+            find_bundle_path(&["iTunesArtwork", "Payload/Foo/Foo.app/Info.plist"]),
+            Ok("Payload/Foo/Foo.app".to_string())
+        );
+    }
+
+    #[test]
+    fn ipa_without_single_app() {
+        assert!(find_bundle_path(&[]).is_err());
+        assert!(find_bundle_path(&["iTunesArtwork", "Payload/Foo.app"]).is_err());
+        assert!(find_bundle_path(&["Foo.app/Info.plist"]).is_err());
+        assert!(find_bundle_path(&["Payload/Foo.app/Foo", "Payload/Bar.app/Bar"]).is_err());
+    }
+}
@@ -13,7 +13,8 @@
 //!   - `gdb/arch/arm.h` for ARMv6 register numbers
 
 use crate::cpu::{Cpu, CpuError};
-use crate::mem::{GuestUSize, Mem, Ptr};
+use crate::mach_o::MachO;
+use crate::mem::{GuestUSize, Mem, Ptr, WatchpointKind};
 use std::fmt::Write as _;
 use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
 use std::net::TcpStream;
@@ -27,15 +28,69 @@ const TARGET_XML: &str = r#"
 </target>
 "#;
 
+/// Generate the XML for `qXfer:libraries:read`, listing each loaded Mach-O
+/// binary so that GDB can load symbols for it.
+///
+/// GDB treats all the sections of a non-ELF binary as one segment, whose
+/// address is that of the lowest section, so that's what is reported.
+fn libraries_xml(bins: &[MachO]) -> String {
+    let mut xml = String::from("<library-list>\n");
+    for bin in bins {
+        let Some(addr) = bin.sections.iter().map(|section| section.addr).min() else {
+            continue;
+        };
+        // The XML is kept ASCII-only, see [GdbServer::send_xfer_data].
+        let mut name = String::with_capacity(bin.name.len());
+        for c in bin.name.chars() {
+            match c {
+                '&' => name.push_str("&amp;"),
+                '<' => name.push_str("&lt;"),
+                '>' => name.push_str("&gt;"),
+                '"' => name.push_str("&quot;"),
+                _ if !c.is_ascii() => write!(name, "&#x{:x};", u32::from(c)).unwrap(),
+                _ => name.push(c),
+            }
+        }
+        writeln!(
+            xml,
+            "    <library name=\"{}\"><segment address=\"{:#x}\"/></library>",
+            name, addr
+        )
+        .unwrap();
+    }
+    xml.push_str("</library-list>\n");
+    xml
+}
+
+/// Generate the XML for `qXfer:memory-map:read`. All of the address space
+/// except the null segment is reported as RAM, since memory can be allocated
+/// after GDB has read the map.
+fn memory_map_xml(mem: &Mem) -> String {
+    let start = mem.null_segment_size();
+    let length = (1u64 << 32) - u64::from(start);
+    format!(
+        concat!(
+            "<memory-map>\n",
+            "    <memory type=\"ram\" start=\"{:#x}\" length=\"{:#x}\"/>\n",
+            "</memory-map>\n",
+        ),
+        start, length
+    )
+}
+
 /// GDB Remote Serial Protocol handler, implementing a server.
 pub struct GdbServer {
     reader: BufReader<TcpStream>,
     first_halt: bool,
+    /// See [libraries_xml]. The loaded binaries never change, so this is only
+    /// generated once.
+    libraries_xml: String,
 }
 
 impl GdbServer {
-    /// Create the handler from a TCP connection.
-    pub fn new(mut connection: TcpStream) -> GdbServer {
+    /// Create the handler from a TCP connection. `bins` are the loaded Mach-O
+    /// binaries, which are reported to the debugger.
+    pub fn new(mut connection: TcpStream, bins: &[MachO]) -> GdbServer {
         connection
             .set_read_timeout(Some(Duration::from_secs(3)))
             .unwrap();
@@ -54,6 +109,7 @@ impl GdbServer {
         GdbServer {
             reader: BufReader::with_capacity(4096, connection),
             first_halt: true,
+            libraries_xml: libraries_xml(bins),
         }
     }
 
@@ -126,6 +182,36 @@ impl GdbServer {
         log_dbg!("Sent packet: {:?}", body);
     }
 
+    /// Send part of the data for a `qXfer` read request.
+    fn send_xfer_data(&mut self, data: &str, offset: usize, length: usize) {
+        let Some(data) = data.as_bytes().get(offset..) else {
+            // Invalid offset
+            self.send_packet("E00");
+            return;
+        };
+        let length_read = length.min(data.len());
+        let mut packet = String::with_capacity(1 + length_read);
+        if length_read < length {
+            // Read data, none left
+            packet.push('l');
+        } else {
+            // Read data, more remains
+            packet.push('m');
+        }
+        // This packet uses the modern style of binary data where most bytes
+        // are unescaped. All the XML we send is ASCII, but library names might
+        // contain characters that need escaping.
+        for &byte in &data[..length_read] {
+            if matches!(byte, b'#' | b'$' | b'}' | b'*') {
+                packet.push('}');
+                packet.push(char::from(byte ^ 0x20));
+            } else {
+                packet.push(char::from(byte));
+            }
+        }
+        self.send_packet(&packet);
+    }
+
     /// Communciates with the debugger, returning only once it requests
     /// execution should continue. Returns [true] if the CPU should step and
     /// then resume debugging, or [false] if it should resume normal execution.
@@ -162,6 +248,14 @@ impl GdbServer {
             Some(CpuError::MemoryError) => {
                 self.send_packet("S0b"); // SIGSEGV
             }
+            Some(CpuError::Watchpoint(kind, addr)) => {
+                let kind = match kind {
+                    WatchpointKind::Write => "watch",
+                    WatchpointKind::Read => "rwatch",
+                    WatchpointKind::Access => "awatch",
+                };
+                self.send_packet(&format!("T05{}:{:x};", kind, addr)); // SIGTRAP
+            }
         }
 
         let do_step = loop {
@@ -290,6 +384,51 @@ impl GdbServer {
                         }
                     }
                 }
+                // Insert or remove breakpoint/watchpoint
+                b'Z' | b'z' => {
+                    let insert = p.as_bytes()[0] == b'Z';
+                    let (type_, params) = p[1..].split_once(',').unwrap();
+                    let (addr, length) = params.split_once(',').unwrap();
+                    // Conditions and commands aren't supported.
+                    let length = length.split_once(';').map_or(length, |(length, _)| length);
+                    let addr = GuestUSize::from_str_radix(addr, 16).unwrap();
+                    let length = GuestUSize::from_str_radix(length, 16).unwrap();
+                    let kind = match type_ {
+                        "2" => Some(WatchpointKind::Write),
+                        "3" => Some(WatchpointKind::Read),
+                        "4" => Some(WatchpointKind::Access),
+                        // Software and hardware breakpoints aren't supported.
+                        // GDB will implement software breakpoints for us with
+                        // trap instructions instead.
+                        _ => None,
+                    };
+                    match kind {
+                        Some(kind) if length > 0 && addr.checked_add(length - 1).is_some() => {
+                            let ok = if insert {
+                                mem.add_watchpoint(kind, addr, length);
+                                true
+                            } else {
+                                mem.remove_watchpoint(kind, addr, length)
+                            };
+                            // Watchpoints are only checked for pages that the
+                            // CPU doesn't access directly.
+                            for page in (addr / 0x1000)..=((addr + (length - 1)) / 0x1000) {
+                                let page_addr = page * 0x1000;
+                                let watched = mem.page_has_watchpoint(page_addr);
+                                cpu.set_page_direct_access(page_addr, !watched);
+                            }
+                            self.send_packet(if ok { "OK" } else { "E00" });
+                        }
+                        Some(_) => {
+                            // Error 0
+                            self.send_packet("E00");
+                        }
+                        None => {
+                            log_dbg!("Unsupported breakpoint type.");
+                            self.send_packet("");
+                        }
+                    }
+                }
                 // Continue or Step
                 b'c' | b's' => {
                     let addr = &p[1..];
@@ -319,42 +458,33 @@ impl GdbServer {
                         self.send_packet("0");
                     // Query for supported features
                     } else if p == "qSupported" || p.starts_with("qSupported:") {
-                        // Tell GDB we can send it an XML target description.
-                        self.send_packet("qXfer:features:read+");
-                    // Read XML target description
-                    } else if let Some(params) = p.strip_prefix("qXfer:features:read:") {
-                        let (annex, params) = params.split_once(':').unwrap();
-                        let (offset, length) = params.split_once(',').unwrap();
-                        let offset = usize::from_str_radix(offset, 16).unwrap();
-                        let length = usize::from_str_radix(length, 16).unwrap();
-                        let bytes = TARGET_XML.as_bytes();
-                        if annex == "target.xml" && offset <= bytes.len() {
-                            let bytes = &bytes[offset..];
-                            let length_read = length.min(bytes.len());
-                            let mut packet = String::with_capacity(1 + length_read);
-                            if length_read < length {
-                                // Read data, more remains
-                                packet.push('l');
-                            } else {
-                                // Read data, none left
-                                packet.push('m');
-                            }
-                            // This packet uses the modern style of binary
-                            // data where most bytes are unescaped.
-                            // We happen to know none of the bytes in the XML
-                            // need escaping, and that they're all ASCII.
-                            packet.push_str(std::str::from_utf8(&bytes[..length_read]).unwrap());
-                            self.send_packet(&packet);
+                        // Tell GDB we can send it an XML target description,
+                        // a list of loaded binaries and a memory map.
+                        self.send_packet(
+                            "qXfer:features:read+;qXfer:libraries:read+;qXfer:memory-map:read+",
+                        );
+                    // Read XML target description, library list or memory map
+                    } else if let Some(params) = p.strip_prefix("qXfer:") {
+                        let (object, params) = params.split_once(":read:").unwrap_or(("", ""));
+                        let (annex, params) = params.split_once(':').unwrap_or(("", ""));
+                        let (offset, length) = params.split_once(',').unwrap_or(("", ""));
+                        let offset = usize::from_str_radix(offset, 16);
+                        let length = usize::from_str_radix(length, 16);
+                        let data = match (object, annex) {
+                            ("features", "target.xml") => Some(TARGET_XML.to_string()),
+                            ("libraries", "") => Some(self.libraries_xml.clone()),
+                            ("memory-map", "") => Some(memory_map_xml(mem)),
+                            _ => None,
+                        };
+                        if let (Some(data), Ok(offset), Ok(length)) = (data, offset, length) {
+                            self.send_xfer_data(&data, offset, length);
                         } else {
-                            // Unsupported annex or invalid offset
+                            // Unsupported object or annex, or invalid request
                             self.send_packet("E00");
                         }
                     } else {
                         log_dbg!("Unhandled packet.");
                         // Tell GDB we don't understand this packet.
-                        // In some cases this causes convenient fallbacks:
-                        // Since we don't support 'Z', GDB will implement
-                        // software breakpoints for us with trap instructions.
                         self.send_packet("");
                     }
                 }
@@ -369,3 +499,47 @@ impl GdbServer {
         do_step
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mach_o::{Section, SectionType};
+
+    fn bin(name: &str, section_addrs: &[u32]) -> MachO {
+        MachO {
+            name: name.to_string(),
+            dynamic_libraries: Vec::new(),
+            sections: section_addrs
+                .iter()
+                .map(|&addr| Section {
+                    name: "__text".to_string(),
+                    addr,
+                    size: 0x100,
+                    type_: SectionType::Normal,
+                    dyld_indirect_symbol_info: None,
+                })
+                .collect(),
+            exported_symbols: Default::default(),
+            external_relocations: Vec::new(),
+            weak_imports: Default::default(),
+            entry_point_pc: None,
+        }
+    }
+
+    #[test]
+    fn library_list() {
+        let bins = [
+            bin("Café & Co", &[0x3000, 0x2000, 0x5000]),
+            bin("libstdc++.6.dylib", &[0x30000000]),
+        ];
+        assert_eq!(
+            libraries_xml(&bins),
+            "\
+<library-list>
+    <library name=\"Caf&#xe9; &amp; Co\"><segment address=\"0x2000\"/></library>
+    <library name=\"libstdc++.6.dylib\"><segment address=\"0x30000000\"/></library>
+</library-list>
+"
+        );
+    }
+}
@@ -102,13 +102,17 @@ impl GLESImplementation {
         }
     }
     /// See [GLES::new].
-    pub fn construct(self, window: &mut crate::window::Window) -> Result<Box<dyn GLES>, String> {
+    pub fn construct(
+        self,
+        window: &mut crate::window::Window,
+        options: &crate::options::Options,
+    ) -> Result<Box<dyn GLES>, String> {
         fn boxer<T: GLES + 'static>(ctx: T) -> Box<dyn GLES> {
             Box::new(ctx)
         }
         match self {
-            Self::GLES1Native => GLES1Native::new(window).map(boxer),
-            Self::GLES1OnGL2 => GLES1OnGL2::new(window).map(boxer),
+            Self::GLES1Native => GLES1Native::new(window, options).map(boxer),
+            Self::GLES1OnGL2 => GLES1OnGL2::new(window, options).map(boxer),
         }
     }
 }
@@ -128,7 +132,7 @@ pub fn create_gles1_ctx(
     let mut gles1_ctx = None;
     for implementation in list {
         log!("Trying: {}", implementation.description());
-        match implementation.construct(window) {
+        match implementation.construct(window, options) {
             Ok(ctx) => {
                 log!("=> Success!");
                 gles1_ctx = Some(ctx);
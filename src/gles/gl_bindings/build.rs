@@ -38,13 +38,18 @@ fn main() {
             "GL_OES_rgb8_rgba8",
             "GL_EXT_texture_filter_anisotropic",
             "GL_IMG_texture_compression_pvrtc",
+            "GL_OES_compressed_ETC1_RGB8_texture",
             "GL_EXT_texture_lod_bias",
             "GL_EXT_texture_format_BGRA8888",
             "GL_OES_draw_texture",
             "GL_OES_mapbuffer",
+            "GL_OES_blend_func_separate",
             // Part of the OpenGL ES 1.1 common profile.
             "GL_OES_compressed_paletted_texture",
             "GL_OES_matrix_palette",
+            "GL_OES_point_size_array",
+            "GL_OES_point_sprite",
+            "GL_OES_read_format",
         ],
     )
     .write_bindings(GlobalGenerator, &mut file)
@@ -14,23 +14,43 @@
 
 use super::gles11_raw as gles11;
 use super::gles11_raw::types::*;
-use super::util::{try_decode_pvrtc, PalettedTextureFormat};
+use super::util::{
+    is_pvrtc_format, try_decode_etc1, try_decode_pvrtc, use_native_pvrtc, AttribStack,
+    GLCapabilities, PalettedTextureFormat,
+};
 use super::GLES;
+use crate::options::Options;
 use crate::window::{GLContext, GLVersion, Window};
 use std::ffi::CStr;
 
 pub struct GLES1Native {
     gl_ctx: GLContext,
+    /// Whether PVRTC textures are passed to the host as-is, see
+    /// [use_native_pvrtc].
+    native_pvrtc: bool,
+    /// OpenGL ES 1.1 has no `glPushAttrib()`, so it must be emulated.
+    attrib_stack: AttribStack,
 }
 impl GLES for GLES1Native {
     fn description() -> &'static str {
         "Native OpenGL ES 1.1"
     }
 
-    fn new(window: &mut Window) -> Result<Self, String> {
-        Ok(Self {
+    fn new(window: &mut Window, options: &Options) -> Result<Self, String> {
+        let mut gles = Self {
             gl_ctx: window.create_gl_context(GLVersion::GLES11)?,
-        })
+            native_pvrtc: false,
+            attrib_stack: AttribStack::default(),
+        };
+        gles.make_current(window);
+        let extensions =
+            unsafe { CStr::from_ptr(gles11::GetString(gles11::EXTENSIONS) as *const _) };
+        let capabilities = GLCapabilities::from_extensions(&extensions.to_string_lossy());
+        gles.native_pvrtc = use_native_pvrtc(&capabilities, options.force_pvrtc_cpu_decode);
+        if gles.native_pvrtc {
+            log!("Host OpenGL ES supports PVRTC textures, they won't be decoded on the CPU.");
+        }
+        Ok(gles)
     }
 
     fn make_current(&self, window: &Window) {
@@ -77,6 +97,9 @@ impl GLES for GLES1Native {
     unsafe fn GetBooleanv(&mut self, pname: GLenum, params: *mut GLboolean) {
         gles11::GetBooleanv(pname, params)
     }
+    unsafe fn GetFixedv(&mut self, pname: GLenum, params: *mut GLfixed) {
+        gles11::GetFixedv(pname, params)
+    }
     unsafe fn GetFloatv(&mut self, pname: GLenum, params: *mut GLfloat) {
         gles11::GetFloatv(pname, params)
     }
@@ -108,6 +131,20 @@ impl GLES for GLES1Native {
         gles11::GetString(name)
     }
 
+    unsafe fn PushAttrib(&mut self, mask: GLbitfield) {
+        self.attrib_stack
+            .push(mask, |cap| gles11::IsEnabled(cap) != gles11::FALSE)
+    }
+    unsafe fn PopAttrib(&mut self) {
+        self.attrib_stack.pop(|cap, enabled| {
+            if enabled {
+                gles11::Enable(cap)
+            } else {
+                gles11::Disable(cap)
+            }
+        })
+    }
+
     // Other state manipulation
     unsafe fn AlphaFunc(&mut self, func: GLenum, ref_: GLclampf) {
         gles11::AlphaFunc(func, ref_)
@@ -118,6 +155,24 @@ impl GLES for GLES1Native {
     unsafe fn BlendFunc(&mut self, sfactor: GLenum, dfactor: GLenum) {
         gles11::BlendFunc(sfactor, dfactor)
     }
+    unsafe fn BlendFuncSeparateOES(
+        &mut self,
+        src_rgb: GLenum,
+        dst_rgb: GLenum,
+        src_alpha: GLenum,
+        dst_alpha: GLenum,
+    ) {
+        if gles11::BlendFuncSeparateOES::is_loaded() {
+            gles11::BlendFuncSeparateOES(src_rgb, dst_rgb, src_alpha, dst_alpha)
+        } else {
+            log!(
+                "Warning: host OpenGL ES doesn't support OES_blend_func_separate, alpha blend factors {:#x} and {:#x} will be ignored",
+                src_alpha,
+                dst_alpha
+            );
+            gles11::BlendFunc(src_rgb, dst_rgb)
+        }
+    }
     unsafe fn ColorMask(
         &mut self,
         red: GLboolean,
@@ -245,6 +300,18 @@ impl GLES for GLES1Native {
     unsafe fn Materialxv(&mut self, face: GLenum, pname: GLenum, params: *const GLfixed) {
         gles11::Materialxv(face, pname, params)
     }
+    unsafe fn GetLightfv(&mut self, light: GLenum, pname: GLenum, params: *mut GLfloat) {
+        gles11::GetLightfv(light, pname, params)
+    }
+    unsafe fn GetLightxv(&mut self, light: GLenum, pname: GLenum, params: *mut GLfixed) {
+        gles11::GetLightxv(light, pname, params)
+    }
+    unsafe fn GetMaterialfv(&mut self, face: GLenum, pname: GLenum, params: *mut GLfloat) {
+        gles11::GetMaterialfv(face, pname, params)
+    }
+    unsafe fn GetMaterialxv(&mut self, face: GLenum, pname: GLenum, params: *mut GLfixed) {
+        gles11::GetMaterialxv(face, pname, params)
+    }
 
     // Buffers
     unsafe fn GenBuffers(&mut self, n: GLsizei, buffers: *mut GLuint) {
@@ -327,6 +394,14 @@ impl GLES for GLES1Native {
     ) {
         gles11::VertexPointer(size, type_, stride, pointer)
     }
+    unsafe fn PointSizePointerOES(
+        &mut self,
+        type_: GLenum,
+        stride: GLsizei,
+        pointer: *const GLvoid,
+    ) {
+        gles11::PointSizePointerOES(type_, stride, pointer)
+    }
 
     // Drawing
     unsafe fn DrawArrays(&mut self, mode: GLenum, first: GLint, count: GLsizei) {
@@ -423,6 +498,12 @@ impl GLES for GLES1Native {
     unsafe fn TexParameterxv(&mut self, target: GLenum, pname: GLenum, params: *const GLfixed) {
         gles11::TexParameterxv(target, pname, params)
     }
+    unsafe fn GetTexParameteriv(&mut self, target: GLenum, pname: GLenum, params: *mut GLint) {
+        gles11::GetTexParameteriv(target, pname, params)
+    }
+    unsafe fn GetTexParameterfv(&mut self, target: GLenum, pname: GLenum, params: *mut GLfloat) {
+        gles11::GetTexParameterfv(target, pname, params)
+    }
     unsafe fn TexImage2D(
         &mut self,
         target: GLenum,
@@ -486,9 +567,25 @@ impl GLES for GLES1Native {
     ) {
         let data = unsafe { std::slice::from_raw_parts(data.cast::<u8>(), image_size as usize) };
         // IMG_texture_compression_pvrtc (only on Imagination/Apple GPUs)
-        // TODO: It would be more efficient to use hardware decoding where
-        // available (I just don't have a suitable device to try this on)
-        if try_decode_pvrtc(
+        if !self.native_pvrtc
+            && try_decode_pvrtc(
+                self,
+                target,
+                level,
+                internalformat,
+                width,
+                height,
+                border,
+                data,
+            )
+        {
+            log_dbg!("Decoded PVRTC");
+            return;
+        }
+
+        // OES_compressed_ETC1_RGB8_texture isn't universally supported, so
+        // always decode it.
+        if try_decode_etc1(
             self,
             target,
             level,
@@ -498,13 +595,15 @@ impl GLES for GLES1Native {
             border,
             data,
         ) {
-            log_dbg!("Decoded PVRTC");
+            log_dbg!("Decoded ETC1");
             return;
         }
 
         // OES_compressed_paletted_texture is in the common profile of OpenGL ES
         // 1.1, so we can reasonably assume it's supported.
-        if PalettedTextureFormat::get_info(internalformat).is_none() {
+        if !is_pvrtc_format(internalformat)
+            && PalettedTextureFormat::get_info(internalformat).is_none()
+        {
             unimplemented!("CompressedTexImage2D internalformat: {:#x}", internalformat);
         }
         log_dbg!("Directly supported texture format: {:#x}", internalformat);
@@ -652,6 +751,53 @@ impl GLES for GLES1Native {
         gles11::Translatex(x, y, z)
     }
 
+    // OES_draw_texture
+    unsafe fn DrawTexfOES(
+        &mut self,
+        x: GLfloat,
+        y: GLfloat,
+        z: GLfloat,
+        width: GLfloat,
+        height: GLfloat,
+    ) {
+        gles11::DrawTexfOES(x, y, z, width, height)
+    }
+    unsafe fn DrawTexiOES(&mut self, x: GLint, y: GLint, z: GLint, width: GLint, height: GLint) {
+        gles11::DrawTexiOES(x, y, z, width, height)
+    }
+    unsafe fn DrawTexsOES(
+        &mut self,
+        x: GLshort,
+        y: GLshort,
+        z: GLshort,
+        width: GLshort,
+        height: GLshort,
+    ) {
+        gles11::DrawTexsOES(x, y, z, width, height)
+    }
+    unsafe fn DrawTexxOES(
+        &mut self,
+        x: GLfixed,
+        y: GLfixed,
+        z: GLfixed,
+        width: GLfixed,
+        height: GLfixed,
+    ) {
+        gles11::DrawTexxOES(x, y, z, width, height)
+    }
+    unsafe fn DrawTexfvOES(&mut self, coords: *const GLfloat) {
+        gles11::DrawTexfvOES(coords)
+    }
+    unsafe fn DrawTexivOES(&mut self, coords: *const GLint) {
+        gles11::DrawTexivOES(coords)
+    }
+    unsafe fn DrawTexsvOES(&mut self, coords: *const GLshort) {
+        gles11::DrawTexsvOES(coords)
+    }
+    unsafe fn DrawTexxvOES(&mut self, coords: *const GLfixed) {
+        gles11::DrawTexxvOES(coords)
+    }
+
     // OES_framebuffer_object -> EXT_framebuffer_object
     unsafe fn GenFramebuffersOES(&mut self, n: GLsizei, framebuffers: *mut GLuint) {
         gles11::GenFramebuffersOES(n, framebuffers)
@@ -22,12 +22,13 @@ use super::gl21compat_raw as gl21;
 use super::gl21compat_raw::types::*;
 use super::gles11_raw as gles11; // constants only
 use super::util::{
-    fixed_to_float, matrix_fixed_to_float, try_decode_pvrtc, PalettedTextureFormat, ParamTable,
-    ParamType,
+    fixed_to_float, is_pvrtc_format, matrix_fixed_to_float, try_decode_etc1, try_decode_pvrtc,
+    use_native_pvrtc, GLCapabilities, PalettedTextureFormat, ParamTable, ParamType,
 };
 use super::GLES;
+use crate::options::Options;
 use crate::window::{GLContext, GLVersion, Window};
-use std::collections::HashSet;
+use std::collections::HashMap;
 use std::ffi::CStr;
 
 /// List of capabilities shared by OpenGL ES 1.1 and OpenGL 2.1.
@@ -70,6 +71,53 @@ pub const CAPABILITIES: &[GLenum] = &[
     gl21::POINT_SPRITE,
 ];
 
+/// Check that a pair of blend factors for `glBlendFunc` or
+/// `glBlendFuncSeparateOES` is one OpenGL ES 1.1 allows.
+fn check_blend_factors(sfactor: GLenum, dfactor: GLenum) {
+    let common_factors = [
+        gl21::ZERO,
+        gl21::ONE,
+        gl21::SRC_ALPHA,
+        gl21::ONE_MINUS_SRC_ALPHA,
+        gl21::DST_ALPHA,
+        gl21::ONE_MINUS_DST_ALPHA,
+    ];
+    let sfactors = [
+        gl21::DST_COLOR,
+        gl21::ONE_MINUS_DST_COLOR,
+        gl21::SRC_ALPHA_SATURATE,
+    ];
+    let dfactors = [gl21::SRC_COLOR, gl21::ONE_MINUS_SRC_COLOR];
+    assert!(
+        common_factors.contains(&sfactor)
+            || sfactors.contains(&sfactor)
+            || dfactors.contains(&sfactor)
+    );
+    assert!(
+        common_factors.contains(&dfactor)
+            || sfactors.contains(&dfactor)
+            || dfactors.contains(&dfactor)
+    );
+    if sfactors.contains(&dfactor) {
+        log_dbg!("Tolerating sfactor {:#x} in dfactor argument", dfactor);
+    }
+    if dfactors.contains(&sfactor) {
+        log_dbg!("Tolerating dfactor {:#x} in sfactor argument", sfactor);
+    }
+}
+
+/// Host capabilities that must be enabled or disabled along with a guest
+/// capability from [CAPABILITIES].
+fn linked_capabilities(cap: GLenum) -> &'static [GLenum] {
+    match cap {
+        // Point sprites should be sized by glPointSize() or a point size
+        // array, but some OpenGL 2.1 drivers render point sprites as single
+        // pixels unless this is also enabled.
+        gl21::POINT_SPRITE => &[gl21::VERTEX_PROGRAM_POINT_SIZE],
+        _ => &[],
+    }
+}
+
 pub const UNSUPPORTED_CAPABILITIES: &[GLenum] = &[
     0x8620, // GL_VERTEX_PROGRAM_NV
 ];
@@ -82,6 +130,8 @@ pub struct ArrayInfo {
     pub buffer_binding: GLenum,
     /// Size enum for `glGetInteger`.
     size: Option<GLenum>,
+    /// Type enum for `glGetInteger`.
+    type_: GLenum,
     /// Stride enum for `glGetInteger`.
     stride: GLenum,
     /// Pointer enum for `glGetPointer`.
@@ -90,18 +140,44 @@ pub struct ArrayInfo {
 
 struct ArrayStateBackup {
     size: Option<GLint>,
+    type_: GLenum,
+    stride: GLsizei,
+    pointer: *const GLvoid,
+    buffer_binding: GLuint,
+}
+
+/// State of the `OES_point_size_array` array, which OpenGL 2.1 has no
+/// equivalent of. Per-vertex point sizes are emulated at draw time by drawing
+/// each point separately.
+struct PointSizeArray {
+    enabled: bool,
+    type_: GLenum,
     stride: GLsizei,
     pointer: *const GLvoid,
+    buffer_binding: GLuint,
+}
+impl Default for PointSizeArray {
+    fn default() -> Self {
+        PointSizeArray {
+            enabled: false,
+            type_: gl21::FLOAT,
+            stride: 0,
+            pointer: std::ptr::null(),
+            buffer_binding: 0,
+        }
+    }
 }
 
 /// List of arrays shared by OpenGL ES 1.1 and OpenGL 2.1.
 ///
-/// TODO: GL_POINT_SIZE_ARRAY_OES?
+/// `GL_POINT_SIZE_ARRAY_OES` is not shared, so it is tracked separately (see
+/// [PointSizeArray]).
 pub const ARRAYS: &[ArrayInfo] = &[
     ArrayInfo {
         name: gl21::COLOR_ARRAY,
         buffer_binding: gl21::COLOR_ARRAY_BUFFER_BINDING,
         size: Some(gl21::COLOR_ARRAY_SIZE),
+        type_: gl21::COLOR_ARRAY_TYPE,
         stride: gl21::COLOR_ARRAY_STRIDE,
         pointer: gl21::COLOR_ARRAY_POINTER,
     },
@@ -109,6 +185,7 @@ pub const ARRAYS: &[ArrayInfo] = &[
         name: gl21::NORMAL_ARRAY,
         buffer_binding: gl21::NORMAL_ARRAY_BUFFER_BINDING,
         size: None,
+        type_: gl21::NORMAL_ARRAY_TYPE,
         stride: gl21::NORMAL_ARRAY_STRIDE,
         pointer: gl21::NORMAL_ARRAY_POINTER,
     },
@@ -116,6 +193,7 @@ pub const ARRAYS: &[ArrayInfo] = &[
         name: gl21::TEXTURE_COORD_ARRAY,
         buffer_binding: gl21::TEXTURE_COORD_ARRAY_BUFFER_BINDING,
         size: Some(gl21::TEXTURE_COORD_ARRAY_SIZE),
+        type_: gl21::TEXTURE_COORD_ARRAY_TYPE,
         stride: gl21::TEXTURE_COORD_ARRAY_STRIDE,
         pointer: gl21::TEXTURE_COORD_ARRAY_POINTER,
     },
@@ -123,6 +201,7 @@ pub const ARRAYS: &[ArrayInfo] = &[
         name: gl21::VERTEX_ARRAY,
         buffer_binding: gl21::VERTEX_ARRAY_BUFFER_BINDING,
         size: Some(gl21::VERTEX_ARRAY_SIZE),
+        type_: gl21::VERTEX_ARRAY_TYPE,
         stride: gl21::VERTEX_ARRAY_STRIDE,
         pointer: gl21::VERTEX_ARRAY_POINTER,
     },
@@ -130,38 +209,38 @@ pub const ARRAYS: &[ArrayInfo] = &[
 
 /// Table of `glGet` parameters shared by OpenGL ES 1.1 and OpenGL 2.1.
 const GET_PARAMS: ParamTable = ParamTable(&[
-    (gl21::ACTIVE_TEXTURE, ParamType::Int, 1),
+    (gl21::ACTIVE_TEXTURE, ParamType::Enum, 1),
     (gl21::ALIASED_POINT_SIZE_RANGE, ParamType::Float, 2),
     (gl21::ALIASED_LINE_WIDTH_RANGE, ParamType::Float, 2),
     (gl21::ALPHA_BITS, ParamType::Int, 1),
     (gl21::ALPHA_TEST, ParamType::Boolean, 1),
-    (gl21::ALPHA_TEST_FUNC, ParamType::Int, 1),
+    (gl21::ALPHA_TEST_FUNC, ParamType::Enum, 1),
     // TODO: ALPHA_TEST_REF (has special type conversion behavior)
     (gl21::ARRAY_BUFFER_BINDING, ParamType::Int, 1),
     (gl21::BLEND, ParamType::Boolean, 1),
-    (gl21::BLEND_DST, ParamType::Int, 1),
-    (gl21::BLEND_SRC, ParamType::Int, 1),
+    (gl21::BLEND_DST, ParamType::Enum, 1),
+    (gl21::BLEND_SRC, ParamType::Enum, 1),
     (gl21::BLUE_BITS, ParamType::Int, 1),
-    (gl21::CLIENT_ACTIVE_TEXTURE, ParamType::Int, 1),
+    (gl21::CLIENT_ACTIVE_TEXTURE, ParamType::Enum, 1),
     // TODO: arbitrary number of clip planes?
     (gl21::CLIP_PLANE0, ParamType::Boolean, 1),
     (gl21::COLOR_ARRAY, ParamType::Boolean, 1),
     (gl21::COLOR_ARRAY_BUFFER_BINDING, ParamType::Int, 1),
     (gl21::COLOR_ARRAY_SIZE, ParamType::Int, 1),
     (gl21::COLOR_ARRAY_STRIDE, ParamType::Int, 1),
-    (gl21::COLOR_ARRAY_TYPE, ParamType::Int, 1),
+    (gl21::COLOR_ARRAY_TYPE, ParamType::Enum, 1),
     (gl21::COLOR_CLEAR_VALUE, ParamType::FloatSpecial, 4), // TODO correct type
     (gl21::COLOR_LOGIC_OP, ParamType::Boolean, 1),
     (gl21::COLOR_MATERIAL, ParamType::Boolean, 1),
     (gl21::COLOR_WRITEMASK, ParamType::Boolean, 4),
     (gl21::CULL_FACE, ParamType::Boolean, 1),
-    (gl21::CULL_FACE_MODE, ParamType::Int, 1),
+    (gl21::CULL_FACE_MODE, ParamType::Enum, 1),
     (gl21::CURRENT_COLOR, ParamType::FloatSpecial, 4), // TODO correct type
     // TODO: CURRENT_NORMAL (has special type conversion behavior)
     (gl21::CURRENT_TEXTURE_COORDS, ParamType::Float, 4),
     (gl21::DEPTH_BITS, ParamType::Int, 1),
     // TODO: DEPTH_CLEAR_VALUE (has special type conversion behavior)
-    (gl21::DEPTH_FUNC, ParamType::Int, 1),
+    (gl21::DEPTH_FUNC, ParamType::Enum, 1),
     // TODO: DEPTH_RANGE (has special type conversion behavior)
     (gl21::DEPTH_TEST, ParamType::Boolean, 1),
     (gl21::DEPTH_WRITEMASK, ParamType::Boolean, 1),
@@ -169,15 +248,15 @@ const GET_PARAMS: ParamTable = ParamTable(&[
     (gl21::ELEMENT_ARRAY_BUFFER_BINDING, ParamType::Int, 1),
     (gl21::FOG, ParamType::Boolean, 1),
     // TODO: FOG_COLOR (has special type conversion behavior)
-    (gl21::FOG_HINT, ParamType::Int, 1),
-    (gl21::FOG_MODE, ParamType::Int, 1),
+    (gl21::FOG_HINT, ParamType::Enum, 1),
+    (gl21::FOG_MODE, ParamType::Enum, 1),
     (gl21::FOG_DENSITY, ParamType::Float, 1),
     (gl21::FOG_START, ParamType::Float, 1),
     (gl21::FOG_END, ParamType::Float, 1),
-    (gl21::FRONT_FACE, ParamType::Int, 1),
+    (gl21::FRONT_FACE, ParamType::Enum, 1),
     (gl21::GREEN_BITS, ParamType::Int, 1),
-    // TODO: IMPLEMENTATION_COLOR_READ_FORMAT_OES? (not shared)
-    // TODO: IMPLEMENTATION_COLOR_READ_TYPE_OES? (not shared)
+    // IMPLEMENTATION_COLOR_READ_FORMAT_OES and _TYPE_OES are handled
+    // separately, see IMPLEMENTATION_COLOR_READ_FORMAT.
     // TODO: LIGHT_MODEL_AMBIENT (has special type conversion behavior)
     (gl21::LIGHT_MODEL_TWO_SIDE, ParamType::Boolean, 1),
     // TODO: arbitrary number of lights?
@@ -191,10 +270,10 @@ const GET_PARAMS: ParamTable = ParamTable(&[
     (gl21::LIGHT7, ParamType::Boolean, 1),
     (gl21::LIGHTING, ParamType::Boolean, 1),
     (gl21::LINE_SMOOTH, ParamType::Boolean, 1),
-    (gl21::LINE_SMOOTH_HINT, ParamType::Int, 1),
+    (gl21::LINE_SMOOTH_HINT, ParamType::Enum, 1),
     (gl21::LINE_WIDTH, ParamType::Float, 1),
-    (gl21::LOGIC_OP_MODE, ParamType::Int, 1),
-    (gl21::MATRIX_MODE, ParamType::Int, 1),
+    (gl21::LOGIC_OP_MODE, ParamType::Enum, 1),
+    (gl21::MATRIX_MODE, ParamType::Enum, 1),
     (gl21::MAX_CLIP_PLANES, ParamType::Int, 1),
     (gl21::MAX_LIGHTS, ParamType::Int, 1),
     (gl21::MAX_MODELVIEW_STACK_DEPTH, ParamType::Int, 1),
@@ -210,10 +289,10 @@ const GET_PARAMS: ParamTable = ParamTable(&[
     (gl21::NORMAL_ARRAY, ParamType::Boolean, 1),
     (gl21::NORMAL_ARRAY_BUFFER_BINDING, ParamType::Int, 1),
     (gl21::NORMAL_ARRAY_STRIDE, ParamType::Int, 1),
-    (gl21::NORMAL_ARRAY_TYPE, ParamType::Int, 1),
+    (gl21::NORMAL_ARRAY_TYPE, ParamType::Enum, 1),
     (gl21::NORMALIZE, ParamType::Boolean, 1),
     (gl21::PACK_ALIGNMENT, ParamType::Int, 1),
-    (gl21::PERSPECTIVE_CORRECTION_HINT, ParamType::Int, 1),
+    (gl21::PERSPECTIVE_CORRECTION_HINT, ParamType::Enum, 1),
     (gl21::POINT_DISTANCE_ATTENUATION, ParamType::Float, 3),
     (gl21::POINT_FADE_THRESHOLD_SIZE, ParamType::Float, 1),
     (gl21::POINT_SIZE, ParamType::Float, 1),
@@ -222,7 +301,7 @@ const GET_PARAMS: ParamTable = ParamTable(&[
     (gl21::POINT_SIZE_MIN, ParamType::Float, 1),
     (gl21::POINT_SIZE_RANGE, ParamType::Float, 2),
     (gl21::POINT_SMOOTH, ParamType::Boolean, 2),
-    (gl21::POINT_SMOOTH_HINT, ParamType::Int, 2),
+    (gl21::POINT_SMOOTH_HINT, ParamType::Enum, 2),
     (gl21::POINT_SPRITE, ParamType::Boolean, 1),
     (gl21::POLYGON_OFFSET_FACTOR, ParamType::Float, 1),
     (gl21::POLYGON_OFFSET_FILL, ParamType::Boolean, 1),
@@ -240,15 +319,15 @@ const GET_PARAMS: ParamTable = ParamTable(&[
     (gl21::SAMPLES, ParamType::Int, 1),
     (gl21::SCISSOR_BOX, ParamType::Int, 4),
     (gl21::SCISSOR_TEST, ParamType::Boolean, 1),
-    (gl21::SHADE_MODEL, ParamType::Int, 1),
+    (gl21::SHADE_MODEL, ParamType::Enum, 1),
     (gl21::SMOOTH_LINE_WIDTH_RANGE, ParamType::Float, 2),
     (gl21::SMOOTH_POINT_SIZE_RANGE, ParamType::Float, 2),
     (gl21::STENCIL_BITS, ParamType::Int, 1),
     (gl21::STENCIL_CLEAR_VALUE, ParamType::Int, 1),
-    (gl21::STENCIL_FAIL, ParamType::Int, 1),
-    (gl21::STENCIL_FUNC, ParamType::Int, 1),
-    (gl21::STENCIL_PASS_DEPTH_FAIL, ParamType::Int, 1),
-    (gl21::STENCIL_PASS_DEPTH_PASS, ParamType::Int, 1),
+    (gl21::STENCIL_FAIL, ParamType::Enum, 1),
+    (gl21::STENCIL_FUNC, ParamType::Enum, 1),
+    (gl21::STENCIL_PASS_DEPTH_FAIL, ParamType::Enum, 1),
+    (gl21::STENCIL_PASS_DEPTH_PASS, ParamType::Enum, 1),
     (gl21::STENCIL_REF, ParamType::Int, 1),
     (gl21::STENCIL_TEST, ParamType::Boolean, 1),
     (gl21::STENCIL_VALUE_MASK, ParamType::Int, 1),
@@ -260,7 +339,7 @@ const GET_PARAMS: ParamTable = ParamTable(&[
     (gl21::TEXTURE_COORD_ARRAY_BUFFER_BINDING, ParamType::Int, 1),
     (gl21::TEXTURE_COORD_ARRAY_SIZE, ParamType::Int, 1),
     (gl21::TEXTURE_COORD_ARRAY_STRIDE, ParamType::Int, 1),
-    (gl21::TEXTURE_COORD_ARRAY_TYPE, ParamType::Int, 1),
+    (gl21::TEXTURE_COORD_ARRAY_TYPE, ParamType::Enum, 1),
     (gl21::TEXTURE_MATRIX, ParamType::Float, 16),
     (gl21::TEXTURE_STACK_DEPTH, ParamType::Int, 1),
     (gl21::UNPACK_ALIGNMENT, ParamType::Int, 1),
@@ -269,7 +348,7 @@ const GET_PARAMS: ParamTable = ParamTable(&[
     (gl21::VERTEX_ARRAY_BUFFER_BINDING, ParamType::Int, 1),
     (gl21::VERTEX_ARRAY_SIZE, ParamType::Int, 1),
     (gl21::VERTEX_ARRAY_STRIDE, ParamType::Int, 1),
-    (gl21::VERTEX_ARRAY_TYPE, ParamType::Int, 1),
+    (gl21::VERTEX_ARRAY_TYPE, ParamType::Enum, 1),
     // OES_framebuffer_object -> EXT_framebuffer_object
     (gl21::FRAMEBUFFER_BINDING_EXT, ParamType::Int, 1),
     (gl21::RENDERBUFFER_BINDING_EXT, ParamType::Int, 1),
@@ -293,7 +372,7 @@ const POINT_PARAMS: ParamTable = ParamTable(&[
 const FOG_PARAMS: ParamTable = ParamTable(&[
     // Despite only having f, fv, x and xv setters in OpenGL ES 1.1, this is
     // an integer! (You're meant to use the x/xv setter.)
-    (gl21::FOG_MODE, ParamType::Int, 1),
+    (gl21::FOG_MODE, ParamType::Enum, 1),
     (gl21::FOG_DENSITY, ParamType::Float, 1),
     (gl21::FOG_START, ParamType::Float, 1),
     (gl21::FOG_END, ParamType::Float, 1),
@@ -331,25 +410,34 @@ const MATERIAL_PARAMS: ParamTable = ParamTable(&[
     (gl21::AMBIENT_AND_DIFFUSE, ParamType::Float, 4),
 ]);
 
+/// Validate the arguments to `glGetMaterial`. Unlike the setters, which only
+/// accept `GL_FRONT_AND_BACK` in OpenGL ES 1.1, a single face must be queried,
+/// and `GL_AMBIENT_AND_DIFFUSE` can't be.
+fn assert_gettable_material(face: GLenum, pname: GLenum) {
+    assert!(face == gl21::FRONT || face == gl21::BACK);
+    assert!(pname != gl21::AMBIENT_AND_DIFFUSE);
+    MATERIAL_PARAMS.assert_known_param(pname);
+}
+
 /// Table of `glTexEnv` parameters for the `GL_TEXTURE_ENV` target shared by
 /// OpenGL ES 1.1 and OpenGL 2.1.
 const TEX_ENV_PARAMS: ParamTable = ParamTable(&[
-    (gl21::TEXTURE_ENV_MODE, ParamType::Int, 1),
+    (gl21::TEXTURE_ENV_MODE, ParamType::Enum, 1),
     (gl21::COORD_REPLACE, ParamType::Int, 1),
-    (gl21::COMBINE_RGB, ParamType::Int, 1),
-    (gl21::COMBINE_ALPHA, ParamType::Int, 1),
-    (gl21::SRC0_RGB, ParamType::Int, 1),
-    (gl21::SRC1_RGB, ParamType::Int, 1),
-    (gl21::SRC2_RGB, ParamType::Int, 1),
-    (gl21::SRC0_ALPHA, ParamType::Int, 1),
-    (gl21::SRC1_ALPHA, ParamType::Int, 1),
-    (gl21::SRC2_ALPHA, ParamType::Int, 1),
-    (gl21::OPERAND0_RGB, ParamType::Int, 1),
-    (gl21::OPERAND1_RGB, ParamType::Int, 1),
-    (gl21::OPERAND2_RGB, ParamType::Int, 1),
-    (gl21::OPERAND0_ALPHA, ParamType::Int, 1),
-    (gl21::OPERAND1_ALPHA, ParamType::Int, 1),
-    (gl21::OPERAND2_ALPHA, ParamType::Int, 1),
+    (gl21::COMBINE_RGB, ParamType::Enum, 1),
+    (gl21::COMBINE_ALPHA, ParamType::Enum, 1),
+    (gl21::SRC0_RGB, ParamType::Enum, 1),
+    (gl21::SRC1_RGB, ParamType::Enum, 1),
+    (gl21::SRC2_RGB, ParamType::Enum, 1),
+    (gl21::SRC0_ALPHA, ParamType::Enum, 1),
+    (gl21::SRC1_ALPHA, ParamType::Enum, 1),
+    (gl21::SRC2_ALPHA, ParamType::Enum, 1),
+    (gl21::OPERAND0_RGB, ParamType::Enum, 1),
+    (gl21::OPERAND1_RGB, ParamType::Enum, 1),
+    (gl21::OPERAND2_RGB, ParamType::Enum, 1),
+    (gl21::OPERAND0_ALPHA, ParamType::Enum, 1),
+    (gl21::OPERAND1_ALPHA, ParamType::Enum, 1),
+    (gl21::OPERAND2_ALPHA, ParamType::Enum, 1),
     (gl21::TEXTURE_ENV_COLOR, ParamType::Float, 4),
     (gl21::RGB_SCALE, ParamType::Float, 1),
     (gl21::ALPHA_SCALE, ParamType::Float, 1),
@@ -357,51 +445,145 @@ const TEX_ENV_PARAMS: ParamTable = ParamTable(&[
 
 /// Table of `glTexParameter` parameters.
 const TEX_PARAMS: ParamTable = ParamTable(&[
-    (gl21::TEXTURE_MIN_FILTER, ParamType::Int, 1),
-    (gl21::TEXTURE_MAG_FILTER, ParamType::Int, 1),
-    (gl21::TEXTURE_WRAP_S, ParamType::Int, 1),
-    (gl21::TEXTURE_WRAP_T, ParamType::Int, 1),
+    (gl21::TEXTURE_MIN_FILTER, ParamType::Enum, 1),
+    (gl21::TEXTURE_MAG_FILTER, ParamType::Enum, 1),
+    (gl21::TEXTURE_WRAP_S, ParamType::Enum, 1),
+    (gl21::TEXTURE_WRAP_T, ParamType::Enum, 1),
     (gl21::GENERATE_MIPMAP, ParamType::Int, 1),
     (gl21::TEXTURE_MAX_ANISOTROPY_EXT, ParamType::Float, 1),
     (gl21::MAX_TEXTURE_MAX_ANISOTROPY_EXT, ParamType::Float, 1),
 ]);
 
+/// The extra `glReadPixels` format that OpenGL ES 1.1 requires an
+/// implementation to support, alongside `GL_RGBA`. This isn't necessarily
+/// supported by the host, so it's converted from `GL_RGBA` by us.
+const IMPLEMENTATION_COLOR_READ_FORMAT: GLenum = gl21::RGB;
+/// Type that goes with [IMPLEMENTATION_COLOR_READ_FORMAT].
+const IMPLEMENTATION_COLOR_READ_TYPE: GLenum = gl21::UNSIGNED_SHORT_5_6_5;
+
 pub struct GLES1OnGL2 {
     gl_ctx: GLContext,
-    pointer_is_fixed_point: [bool; ARRAYS.len()],
-    fixed_point_texture_units: HashSet<GLenum>,
+    /// Whether each array's data must be translated to floating-point at draw
+    /// time, because it has a type OpenGL 2.1 doesn't support.
+    pointer_needs_translation: [bool; ARRAYS.len()],
+    /// The original type of each array's data, if it needs translating. See
+    /// [vectors_to_float]. Texture co-ordinates use
+    /// [Self::translated_texture_units] instead.
+    translated_pointer_types: [GLenum; ARRAYS.len()],
+    /// Texture units whose texture co-ordinates need translating, and the
+    /// original type of their data.
+    translated_texture_units: HashMap<GLenum, GLenum>,
     fixed_point_translation_buffers: [Vec<GLfloat>; ARRAYS.len()],
+    /// Scratch buffer objects used to hold the translated data for arrays
+    /// that come from a buffer object. Created on first use.
+    fixed_point_translation_vbos: [GLuint; ARRAYS.len()],
+    /// `GL_TEXTURE_CROP_RECT_OES` for each texture, used by `glDrawTexOES`.
+    /// This parameter doesn't exist in OpenGL 2.1, so we must track it.
+    texture_crop_rects: HashMap<GLuint, [GLint; 4]>,
+    point_size_array: PointSizeArray,
+    /// Features and limits of the host driver, see [Self::capabilities].
+    capabilities: GLCapabilities,
+    /// Scratch buffer holding BGRA pixel data converted to RGBA.
+    bgra_conversion_buffer: Vec<u8>,
+    /// Whether PVRTC textures are passed to the host as-is, see
+    /// [use_native_pvrtc].
+    native_pvrtc: bool,
+    /// Scratch buffer holding RGBA8 pixels read by `glReadPixels` that need
+    /// converting to another format.
+    read_pixels_buffer: Vec<u8>,
+    /// Error raised by touchHLE's own checks, which `glGetError` returns
+    /// before any of the host's errors. See [Self::raise_error].
+    error: GLenum,
+    /// Whether `glDrawElements` calls that need fixed-point translation are
+    /// turned into `glDrawArrays` calls, see [Self::deindex_arrays].
+    deindex_fixed_point_draws: bool,
 }
 impl GLES1OnGL2 {
-    /// If any arrays with fixed-point data are in use at the time of a draw
-    /// call, this function will convert the data to floating-point and
-    /// replace the pointers. [Self::restore_fixed_point_arrays] can be called
-    /// after to restore the original state.
+    /// Features and limits of the host driver, detected when the context was
+    /// created.
+    pub fn capabilities(&self) -> &GLCapabilities {
+        &self.capabilities
+    }
+
+    /// Raise an OpenGL error detected by touchHLE rather than the host. Like
+    /// the host's error flag, this keeps the first error until it's retrieved.
+    fn raise_error(&mut self, error: GLenum) {
+        log_dbg!("Raising GL error {:#x}", error);
+        if self.error == gl21::NO_ERROR {
+            self.error = error;
+        }
+    }
+
+    /// If `format` is BGRA and the host doesn't support it, convert the pixel
+    /// data to RGBA in [Self::bgra_conversion_buffer]. Returns the format and
+    /// pixel data that should be passed to the host.
+    unsafe fn convert_bgra_pixels(
+        &mut self,
+        width: GLsizei,
+        height: GLsizei,
+        format: GLenum,
+        type_: GLenum,
+        pixels: *const GLvoid,
+    ) -> (GLenum, *const GLvoid) {
+        if format != gl21::BGRA || self.capabilities.bgra {
+            return (format, pixels);
+        }
+        if pixels.is_null() || width == 0 || height == 0 {
+            return (gl21::RGBA, pixels);
+        }
+        assert!(type_ == gl21::UNSIGNED_BYTE);
+
+        let mut alignment: GLint = 0;
+        gl21::GetIntegerv(gl21::UNPACK_ALIGNMENT, &mut alignment);
+        let alignment = alignment as usize;
+        let (width, height) = (width as usize, height as usize);
+        let row_size = width * 4;
+        let row_stride = row_size.div_ceil(alignment) * alignment;
+        // The last row doesn't need to be padded.
+        let size = row_stride * (height - 1) + row_size;
+
+        let pixels = std::slice::from_raw_parts(pixels.cast::<u8>(), size);
+        log_dbg!("Converting {}x{} BGRA texture data to RGBA", width, height);
+        bgra_to_rgba(pixels, width, row_stride, &mut self.bgra_conversion_buffer);
+        (gl21::RGBA, self.bgra_conversion_buffer.as_ptr().cast())
+    }
+
+    /// If any arrays with fixed-point (or other unsupported) data are in use
+    /// at the time of a draw call, this function will convert the data to
+    /// floating-point and replace the pointers.
+    /// [Self::restore_fixed_point_arrays] can be called after to restore the
+    /// original state.
+    ///
+    /// If an array is too large to translate, `GL_INVALID_VALUE` is raised,
+    /// the original state is restored and [None] is returned, in which case
+    /// the draw call should be skipped.
     unsafe fn translate_fixed_point_arrays(
         &mut self,
         first: GLint,
         count: GLsizei,
-    ) -> [Option<ArrayStateBackup>; ARRAYS.len()] {
+    ) -> Option<[Option<ArrayStateBackup>; ARRAYS.len()]> {
         let mut backups: [Option<ArrayStateBackup>; ARRAYS.len()] = Default::default();
         for (i, array_info) in ARRAYS.iter().enumerate() {
             // Decide whether we need to do anything for this array
 
-            if !self.pointer_is_fixed_point[i] {
+            if !self.pointer_needs_translation[i] {
                 continue;
             }
+            let mut type_ = self.translated_pointer_types[i];
 
             // There is one texture co-ordinates pointer per texture unit.
             let old_client_active_texture = if array_info.name == gl21::TEXTURE_COORD_ARRAY {
-                // Is the texture unit involved in this draw call fixed-point?
-                // If not, we don't need to do anything.
+                // Does the texture unit involved in this draw call need
+                // translation? If not, we don't need to do anything.
                 let mut active_texture: GLenum = 0;
                 gl21::GetIntegerv(
                     gl21::ACTIVE_TEXTURE,
                     &mut active_texture as *mut _ as *mut _,
                 );
-                if !self.fixed_point_texture_units.contains(&active_texture) {
+                let Some(&unit_type) = self.translated_texture_units.get(&active_texture) else {
                     continue;
-                }
+                };
+                type_ = unit_type;
 
                 // Make sure our glTexCoordPointer call will affect that unit.
                 let mut old_client_active_texture: GLenum = 0;
@@ -421,13 +603,9 @@ impl GLES1OnGL2 {
                 continue;
             }
 
-            let mut buffer_binding = 0;
+            let mut buffer_binding: GLint = 0;
             gl21::GetIntegerv(array_info.buffer_binding, &mut buffer_binding);
-            if buffer_binding != 0 {
-                // TODO: translation for bound array buffers
-                todo!("TODO: GLES1-on-GL2 layer does not support buffer bindings yet. (Try OpenGL ES on Android.)");
-            }
-            assert!(buffer_binding == 0);
+            let buffer_binding = buffer_binding as GLuint;
 
             // Get and back up data
 
@@ -448,8 +626,10 @@ impl GLES1OnGL2 {
 
             backups[i] = Some(ArrayStateBackup {
                 size,
+                type_: gl21::FLOAT,
                 stride,
                 pointer,
+                buffer_binding,
             });
 
             // Create translated array and substitute pointer
@@ -458,52 +638,339 @@ impl GLES1OnGL2 {
                 assert!(array_info.name == gl21::NORMAL_ARRAY);
                 3
             });
+            let type_size = array_type_size(type_);
             let stride = if stride == 0 {
                 // tightly packed mode
-                size * 4 // sizeof(gl::FLOAT)
+                size * type_size as GLsizei
             } else {
                 stride
             };
 
-            let buffer = &mut self.fixed_point_translation_buffers[i];
-            buffer.clear();
-            buffer.resize(((first + count) * size).try_into().unwrap(), 0.0);
+            assert!(first >= 0 && count >= 0 && size >= 0 && stride >= 0);
+            let (first, count, size, stride) = (
+                first as usize,
+                count as usize,
+                size as usize,
+                stride as usize,
+            );
 
-            {
-                assert!(first >= 0 && count >= 0 && size >= 0 && stride >= 0);
-                let first = first as usize;
-                let count = count as usize;
-                let size = size as usize;
-                let stride = stride as usize;
-                for j in first..(first + count) {
-                    let vector_ptr: *const GLvoid = pointer.add(j * stride);
-                    let vector_ptr: *const GLfixed = vector_ptr.cast();
-                    for k in 0..size {
-                        buffer[j * size + k] = fixed_to_float(vector_ptr.add(k).read_unaligned());
-                    }
+            let Some(data_size) = array_data_size(count, size, type_size, stride) else {
+                log!(
+                    "Warning: Skipping draw call, array {:#x} is too large to translate",
+                    array_info.name
+                );
+                if let Some(old_client_active_texture) = old_client_active_texture {
+                    gl21::ClientActiveTexture(old_client_active_texture);
                 }
-            }
+                self.restore_fixed_point_arrays(backups);
+                self.raise_error(gl21::INVALID_VALUE);
+                return None;
+            };
+
+            // If the array comes from a buffer object, the pointer is an
+            // offset into it, and the data must be read back first.
+            let mut readback = Vec::new();
+            let first_vector_ptr: *const GLvoid = if buffer_binding != 0 {
+                if count != 0 {
+                    readback.resize(data_size, 0u8);
+                    with_array_buffer_bound(buffer_binding, || {
+                        gl21::GetBufferSubData(
+                            gl21::ARRAY_BUFFER,
+                            (pointer as usize + first * stride) as GLintptr,
+                            readback.len() as GLsizeiptr,
+                            readback.as_mut_ptr() as *mut GLvoid,
+                        )
+                    });
+                }
+                readback.as_ptr().cast()
+            } else {
+                pointer.add(first * stride)
+            };
 
+            let buffer = &mut self.fixed_point_translation_buffers[i];
+            vectors_to_float(type_, first_vector_ptr, first, count, size, stride, buffer);
+
+            let size = size as GLint;
             let buffer_ptr: *const GLfloat = buffer.as_ptr();
             let buffer_ptr: *const GLvoid = buffer_ptr.cast();
-            match array_info.name {
-                gl21::COLOR_ARRAY => gl21::ColorPointer(size, gl21::FLOAT, 0, buffer_ptr),
-                gl21::NORMAL_ARRAY => {
-                    assert!(size == 3);
-                    gl21::NormalPointer(gl21::FLOAT, 0, buffer_ptr)
-                }
-                gl21::TEXTURE_COORD_ARRAY => {
-                    gl21::TexCoordPointer(size, gl21::FLOAT, 0, buffer_ptr)
+            if buffer_binding != 0 {
+                // Upload the translated data to a scratch buffer object, so
+                // that the array still comes from a buffer object.
+                let vbo = &mut self.fixed_point_translation_vbos[i];
+                if *vbo == 0 {
+                    gl21::GenBuffers(1, vbo);
                 }
-                gl21::VERTEX_ARRAY => gl21::VertexPointer(size, gl21::FLOAT, 0, buffer_ptr),
-                _ => unreachable!(),
+                let vbo = *vbo;
+                with_array_buffer_bound(vbo, || {
+                    gl21::BufferData(
+                        gl21::ARRAY_BUFFER,
+                        (buffer.len() * 4) as GLsizeiptr,
+                        buffer_ptr,
+                        gl21::STREAM_DRAW,
+                    );
+                    set_array_pointer(array_info, Some(size), gl21::FLOAT, 0, std::ptr::null());
+                });
+            } else {
+                with_array_buffer_bound(0, || {
+                    set_array_pointer(array_info, Some(size), gl21::FLOAT, 0, buffer_ptr);
+                });
             }
 
             if let Some(old_client_active_texture) = old_client_active_texture {
                 gl21::ClientActiveTexture(old_client_active_texture);
             }
         }
-        backups
+        Some(backups)
+    }
+    /// Alternative to [Self::translate_fixed_point_arrays] for `glDrawElements`
+    /// calls: gather the vertices referenced by `indices` from every enabled
+    /// array, translating only those if needed, so that the draw can be done
+    /// with `glDrawArrays`. This avoids translating unreferenced vertices when
+    /// the indices are sparse.
+    ///
+    /// The gathered data is kept in `buffers`, which must outlive the draw
+    /// call. [Self::restore_deindexed_arrays] can be called after to restore
+    /// the original state. Like with [Self::translate_fixed_point_arrays],
+    /// [None] is returned if an array is too large.
+    unsafe fn deindex_arrays(
+        &mut self,
+        indices: &[usize],
+        buffers: &mut Vec<Vec<u8>>,
+    ) -> Option<Vec<(usize, Option<GLenum>, ArrayStateBackup)>> {
+        let (first, range_count) = index_range(indices);
+
+        let max_texture_units = self.capabilities.max_texture_units;
+        let mut old_client_active_texture: GLenum = 0;
+        gl21::GetIntegerv(
+            gl21::CLIENT_ACTIVE_TEXTURE,
+            &mut old_client_active_texture as *mut _ as *mut _,
+        );
+
+        let mut backups = Vec::new();
+        for (i, array_info) in ARRAYS.iter().enumerate() {
+            // Unlike with translation, every texture unit's co-ordinates must
+            // be gathered, not just the active one's.
+            let texture_units: Vec<Option<GLenum>> = if array_info.name == gl21::TEXTURE_COORD_ARRAY
+            {
+                (0..max_texture_units as GLenum)
+                    .map(|i| Some(gl21::TEXTURE0 + i))
+                    .collect()
+            } else {
+                vec![None]
+            };
+            for texture_unit in texture_units {
+                if let Some(texture_unit) = texture_unit {
+                    gl21::ClientActiveTexture(texture_unit);
+                }
+
+                let mut is_active = gl21::FALSE;
+                gl21::GetBooleanv(array_info.name, &mut is_active);
+                if is_active != gl21::TRUE {
+                    continue;
+                }
+
+                let translated_type = match texture_unit {
+                    Some(texture_unit) => self.translated_texture_units.get(&texture_unit).copied(),
+                    None => self.pointer_needs_translation[i]
+                        .then_some(self.translated_pointer_types[i]),
+                };
+
+                // Get and back up data
+
+                let mut buffer_binding: GLint = 0;
+                gl21::GetIntegerv(array_info.buffer_binding, &mut buffer_binding);
+                let buffer_binding = buffer_binding as GLuint;
+                let size = array_info.size.map(|size_enum| {
+                    let mut size: GLint = 0;
+                    gl21::GetIntegerv(size_enum, &mut size);
+                    size
+                });
+                let mut type_: GLint = 0;
+                gl21::GetIntegerv(array_info.type_, &mut type_);
+                let type_ = type_ as GLenum;
+                let mut stride: GLsizei = 0;
+                gl21::GetIntegerv(array_info.stride, &mut stride);
+                let mut pointer: *mut GLvoid = std::ptr::null_mut();
+                #[allow(clippy::unnecessary_mut_passed)]
+                gl21::GetPointerv(array_info.pointer, &mut pointer);
+                let pointer = pointer.cast_const();
+
+                backups.push((
+                    i,
+                    texture_unit,
+                    ArrayStateBackup {
+                        size,
+                        type_,
+                        stride,
+                        pointer,
+                        buffer_binding,
+                    },
+                ));
+
+                // Create de-indexed array and substitute pointer
+
+                let size = size.unwrap_or(3);
+                let source_type = translated_type.unwrap_or(type_);
+                let type_size = array_type_size(source_type);
+                assert!(size >= 0 && stride >= 0);
+                let (size, stride) = (size as usize, stride as usize);
+                let stride = if stride == 0 {
+                    size * type_size
+                } else {
+                    stride
+                };
+
+                let Some(data_size) = array_data_size(range_count, size, type_size, stride) else {
+                    log!(
+                        "Warning: Skipping draw call, array {:#x} is too large to de-index",
+                        array_info.name
+                    );
+                    gl21::ClientActiveTexture(old_client_active_texture);
+                    self.restore_deindexed_arrays(backups);
+                    self.raise_error(gl21::INVALID_VALUE);
+                    return None;
+                };
+
+                let mut readback = Vec::new();
+                let first_vector_ptr: *const GLvoid = if buffer_binding != 0 {
+                    if range_count != 0 {
+                        readback.resize(data_size, 0u8);
+                        with_array_buffer_bound(buffer_binding, || {
+                            gl21::GetBufferSubData(
+                                gl21::ARRAY_BUFFER,
+                                (pointer as usize + first * stride) as GLintptr,
+                                readback.len() as GLsizeiptr,
+                                readback.as_mut_ptr() as *mut GLvoid,
+                            )
+                        });
+                    }
+                    readback.as_ptr().cast()
+                } else {
+                    pointer.add(first * stride)
+                };
+
+                let mut buffer = Vec::new();
+                deindex_vectors(
+                    source_type,
+                    translated_type.is_some(),
+                    first_vector_ptr,
+                    first,
+                    indices,
+                    size,
+                    stride,
+                    &mut buffer,
+                );
+                let new_type = if translated_type.is_some() {
+                    gl21::FLOAT
+                } else {
+                    type_
+                };
+                with_array_buffer_bound(0, || {
+                    set_array_pointer(
+                        array_info,
+                        Some(size as GLint),
+                        new_type,
+                        0,
+                        buffer.as_ptr().cast(),
+                    );
+                });
+                buffers.push(buffer);
+            }
+        }
+        gl21::ClientActiveTexture(old_client_active_texture);
+        Some(backups)
+    }
+    unsafe fn restore_deindexed_arrays(
+        &mut self,
+        from_backup: Vec<(usize, Option<GLenum>, ArrayStateBackup)>,
+    ) {
+        let mut old_client_active_texture: GLenum = 0;
+        gl21::GetIntegerv(
+            gl21::CLIENT_ACTIVE_TEXTURE,
+            &mut old_client_active_texture as *mut _ as *mut _,
+        );
+        for (i, texture_unit, backup) in from_backup {
+            if let Some(texture_unit) = texture_unit {
+                gl21::ClientActiveTexture(texture_unit);
+            }
+            let ArrayStateBackup {
+                size,
+                type_,
+                stride,
+                pointer,
+                buffer_binding,
+            } = backup;
+            with_array_buffer_bound(buffer_binding, || {
+                set_array_pointer(&ARRAYS[i], size, type_, stride, pointer)
+            });
+        }
+        gl21::ClientActiveTexture(old_client_active_texture);
+    }
+    /// Whether a draw call must emulate `OES_point_size_array`.
+    fn point_size_array_in_use(&self, mode: GLenum) -> bool {
+        mode == gl21::POINTS && self.point_size_array.enabled
+    }
+    /// Read the point sizes for vertices `first` to `first + count - 1` from
+    /// the `OES_point_size_array` array.
+    unsafe fn read_point_sizes(&self, first: usize, count: usize) -> Vec<GLfloat> {
+        let PointSizeArray {
+            type_,
+            stride,
+            pointer,
+            buffer_binding,
+            ..
+        } = self.point_size_array;
+        let stride = if stride == 0 { 4 } else { stride as usize };
+
+        let mut readback = Vec::new();
+        let first_size_ptr: *const GLvoid = if buffer_binding != 0 {
+            if count != 0 {
+                readback.resize((count - 1) * stride + 4, 0u8);
+                with_array_buffer_bound(buffer_binding, || {
+                    gl21::GetBufferSubData(
+                        gl21::ARRAY_BUFFER,
+                        (pointer as usize + first * stride) as GLintptr,
+                        readback.len() as GLsizeiptr,
+                        readback.as_mut_ptr() as *mut GLvoid,
+                    )
+                });
+            }
+            readback.as_ptr().cast()
+        } else {
+            pointer.add(first * stride)
+        };
+
+        decode_point_sizes(type_, stride, first_size_ptr, count)
+    }
+    /// Emulate `OES_point_size_array` for a `GL_POINTS` draw call by drawing
+    /// each point on its own with the appropriate point size. `vertices` are
+    /// the vertex indices of the points, and `first` is the lowest of these.
+    unsafe fn draw_points_with_sizes(&mut self, first: usize, vertices: &[usize]) {
+        let last = vertices.iter().copied().max().unwrap_or(first);
+        let sizes = self.read_point_sizes(first, last + 1 - first);
+
+        let mut old_point_size: GLfloat = 0.0;
+        gl21::GetFloatv(gl21::POINT_SIZE, &mut old_point_size);
+        for &vertex in vertices {
+            gl21::PointSize(sizes[vertex - first]);
+            gl21::DrawArrays(gl21::POINTS, vertex as GLint, 1);
+        }
+        gl21::PointSize(old_point_size);
+    }
+    /// Store the `GL_TEXTURE_CROP_RECT_OES` of the texture currently bound to
+    /// `GL_TEXTURE_2D`.
+    unsafe fn set_texture_crop_rect(&mut self, crop_rect: [GLint; 4]) {
+        let mut texture: GLint = 0;
+        gl21::GetIntegerv(gl21::TEXTURE_BINDING_2D, &mut texture);
+        self.texture_crop_rects.insert(texture as GLuint, crop_rect);
+    }
+    unsafe fn get_texture_crop_rect(&self) -> [GLint; 4] {
+        let mut texture: GLint = 0;
+        gl21::GetIntegerv(gl21::TEXTURE_BINDING_2D, &mut texture);
+        self.texture_crop_rects
+            .get(&(texture as GLuint))
+            .copied()
+            .unwrap_or_default()
     }
     unsafe fn restore_fixed_point_arrays(
         &mut self,
@@ -513,57 +980,412 @@ impl GLES1OnGL2 {
             let array_info = &ARRAYS[i];
             let Some(ArrayStateBackup {
                 size,
+                type_,
                 stride,
                 pointer,
+                buffer_binding,
             }) = backup
             else {
                 continue;
             };
 
-            match array_info.name {
-                gl21::COLOR_ARRAY => {
-                    gl21::ColorPointer(size.unwrap(), gl21::FLOAT, stride, pointer)
-                }
-                gl21::NORMAL_ARRAY => {
-                    assert!(size.is_none());
-                    gl21::NormalPointer(gl21::FLOAT, stride, pointer)
-                }
-                gl21::TEXTURE_COORD_ARRAY => {
-                    let mut active_texture: GLenum = 0;
-                    gl21::GetIntegerv(
-                        gl21::ACTIVE_TEXTURE,
-                        &mut active_texture as *mut _ as *mut _,
-                    );
-                    assert!(self.fixed_point_texture_units.contains(&active_texture));
-                    let mut old_client_active_texture: GLenum = 0;
-                    gl21::GetIntegerv(
-                        gl21::CLIENT_ACTIVE_TEXTURE,
-                        &mut old_client_active_texture as *mut _ as *mut _,
-                    );
-                    gl21::ClientActiveTexture(active_texture);
-                    gl21::TexCoordPointer(size.unwrap(), gl21::FLOAT, stride, pointer);
-                    gl21::ClientActiveTexture(old_client_active_texture)
-                }
-                gl21::VERTEX_ARRAY => {
-                    gl21::VertexPointer(size.unwrap(), gl21::FLOAT, stride, pointer)
-                }
+            if array_info.name == gl21::TEXTURE_COORD_ARRAY {
+                let mut active_texture: GLenum = 0;
+                gl21::GetIntegerv(
+                    gl21::ACTIVE_TEXTURE,
+                    &mut active_texture as *mut _ as *mut _,
+                );
+                assert!(self.translated_texture_units.contains_key(&active_texture));
+                let mut old_client_active_texture: GLenum = 0;
+                gl21::GetIntegerv(
+                    gl21::CLIENT_ACTIVE_TEXTURE,
+                    &mut old_client_active_texture as *mut _ as *mut _,
+                );
+                gl21::ClientActiveTexture(active_texture);
+                with_array_buffer_bound(buffer_binding, || {
+                    set_array_pointer(array_info, size, type_, stride, pointer)
+                });
+                gl21::ClientActiveTexture(old_client_active_texture)
+            } else {
+                with_array_buffer_bound(buffer_binding, || {
+                    set_array_pointer(array_info, size, type_, stride, pointer)
+                });
+            }
+        }
+    }
+}
+/// Detect the [GLCapabilities] of the current context.
+unsafe fn detect_capabilities() -> GLCapabilities {
+    let extensions = CStr::from_ptr(gl21::GetString(gl21::EXTENSIONS) as *const _);
+    let mut capabilities = GLCapabilities::from_extensions(&extensions.to_string_lossy());
+    gl21::GetIntegerv(gl21::MAX_TEXTURE_SIZE, &mut capabilities.max_texture_size);
+    gl21::GetIntegerv(gl21::MAX_TEXTURE_UNITS, &mut capabilities.max_texture_units);
+    if capabilities.anisotropic_filtering {
+        gl21::GetFloatv(
+            gl21::MAX_TEXTURE_MAX_ANISOTROPY_EXT,
+            &mut capabilities.max_anisotropy,
+        );
+    }
+    capabilities
+}
+
+/// Calls the `gl*Pointer` function corresponding to an array.
+unsafe fn set_array_pointer(
+    array_info: &ArrayInfo,
+    size: Option<GLint>,
+    type_: GLenum,
+    stride: GLsizei,
+    pointer: *const GLvoid,
+) {
+    match array_info.name {
+        gl21::COLOR_ARRAY => gl21::ColorPointer(size.unwrap(), type_, stride, pointer),
+        gl21::NORMAL_ARRAY => {
+            assert!(size.is_none() || size == Some(3));
+            gl21::NormalPointer(type_, stride, pointer)
+        }
+        gl21::TEXTURE_COORD_ARRAY => gl21::TexCoordPointer(size.unwrap(), type_, stride, pointer),
+        gl21::VERTEX_ARRAY => gl21::VertexPointer(size.unwrap(), type_, stride, pointer),
+        _ => unreachable!(),
+    }
+}
+
+/// Calls `f` with `buffer` bound to `GL_ARRAY_BUFFER`, then restores the
+/// previous binding. This matters for the `gl*Pointer` functions, which
+/// capture the current binding.
+unsafe fn with_array_buffer_bound<F: FnOnce()>(buffer: GLuint, f: F) {
+    let mut old_binding: GLint = 0;
+    gl21::GetIntegerv(gl21::ARRAY_BUFFER_BINDING, &mut old_binding);
+    gl21::BindBuffer(gl21::ARRAY_BUFFER, buffer);
+    f();
+    gl21::BindBuffer(gl21::ARRAY_BUFFER, old_binding as GLuint);
+}
+
+/// Upper limit on how much data touchHLE will read from a single array when
+/// translating it for a draw call. No real app should get anywhere near this,
+/// but a garbage stride or index could otherwise cause a huge allocation.
+const MAX_ARRAY_DATA_SIZE: usize = 64 * 1024 * 1024;
+
+/// Size in bytes of the data for `count` vectors of `size` components,
+/// `type_size` bytes each, spaced `stride` bytes apart. Returns [None] if
+/// this is larger than [MAX_ARRAY_DATA_SIZE].
+fn array_data_size(count: usize, size: usize, type_size: usize, stride: usize) -> Option<usize> {
+    if count == 0 {
+        return Some(0);
+    }
+    let data_size = (count - 1)
+        .checked_mul(stride)?
+        .checked_add(size.checked_mul(type_size)?)?;
+    (data_size <= MAX_ARRAY_DATA_SIZE).then_some(data_size)
+}
+
+/// Check the `stride` argument of a `gl*Pointer` function, returning the
+/// error OpenGL ES would raise if it's invalid.
+fn check_stride(stride: GLsizei) -> Result<(), GLenum> {
+    if stride < 0 {
+        Err(gl21::INVALID_VALUE)
+    } else {
+        Ok(())
+    }
+}
+
+/// Size in bytes of a component of an array with type `type_`.
+fn array_type_size(type_: GLenum) -> usize {
+    match type_ {
+        gles11::FIXED | gl21::FLOAT => 4,
+        gl21::BYTE | gl21::UNSIGNED_BYTE => 1,
+        gl21::SHORT | gl21::UNSIGNED_SHORT => 2,
+        _ => unreachable!(),
+    }
+}
+
+/// Converts `count` vectors of `size` components each, spaced `stride` bytes
+/// apart starting at `first_vector_ptr`, to floating-point. The result is
+/// written tightly packed to `buffer` starting at vector index `first`, so
+/// that the indices used by the draw call can be kept.
+///
+/// The components can be `GL_FIXED`, `GL_BYTE` or `GL_UNSIGNED_BYTE`, or
+/// `GL_UNSIGNED_SHORT` for colors, which are normalized so that 65535 becomes
+/// 1.0.
+unsafe fn vectors_to_float(
+    type_: GLenum,
+    first_vector_ptr: *const GLvoid,
+    first: usize,
+    count: usize,
+    size: usize,
+    stride: usize,
+    buffer: &mut Vec<GLfloat>,
+) {
+    let type_size = array_type_size(type_);
+    buffer.clear();
+    buffer.resize((first + count) * size, 0.0);
+    for j in 0..count {
+        let vector_ptr: *const GLvoid = first_vector_ptr.add(j * stride);
+        for k in 0..size {
+            let component_ptr = vector_ptr.add(k * type_size);
+            buffer[(first + j) * size + k] = component_to_float(type_, component_ptr);
+        }
+    }
+}
+
+/// Converts a single component for [vectors_to_float].
+unsafe fn component_to_float(type_: GLenum, component_ptr: *const GLvoid) -> GLfloat {
+    match type_ {
+        gles11::FIXED => fixed_to_float(component_ptr.cast::<GLfixed>().read_unaligned()),
+        gl21::BYTE => component_ptr.cast::<GLbyte>().read().into(),
+        gl21::UNSIGNED_BYTE => component_ptr.cast::<GLubyte>().read().into(),
+        gl21::UNSIGNED_SHORT => {
+            GLfloat::from(component_ptr.cast::<GLushort>().read_unaligned()) / 65535.0
+        }
+        _ => unreachable!(),
+    }
+}
+
+/// Gathers the vectors referenced by `indices` into `buffer`, tightly packed
+/// and in index order, so that an indexed draw call can become a non-indexed
+/// one. The vectors have `size` components and are spaced `stride` bytes
+/// apart, starting at `first_vector_ptr` with vector index `first`.
+///
+/// If `translate` is [true], the components are converted to floating-point
+/// like in [vectors_to_float], otherwise they are copied as-is.
+#[allow(clippy::too_many_arguments)]
+unsafe fn deindex_vectors(
+    type_: GLenum,
+    translate: bool,
+    first_vector_ptr: *const GLvoid,
+    first: usize,
+    indices: &[usize],
+    size: usize,
+    stride: usize,
+    buffer: &mut Vec<u8>,
+) {
+    let type_size = array_type_size(type_);
+    buffer.clear();
+    for &index in indices {
+        let vector_ptr: *const GLvoid = first_vector_ptr.add((index - first) * stride);
+        if translate {
+            for k in 0..size {
+                let component = component_to_float(type_, vector_ptr.add(k * type_size));
+                buffer.extend_from_slice(&component.to_ne_bytes());
+            }
+        } else {
+            let vector = std::slice::from_raw_parts(vector_ptr.cast::<u8>(), size * type_size);
+            buffer.extend_from_slice(vector);
+        }
+    }
+}
+
+/// Decodes `count` point sizes of type `type_` (`GL_FLOAT` or `GL_FIXED`),
+/// spaced `stride` bytes apart starting at `first_size_ptr`.
+unsafe fn decode_point_sizes(
+    type_: GLenum,
+    stride: usize,
+    first_size_ptr: *const GLvoid,
+    count: usize,
+) -> Vec<GLfloat> {
+    (0..count)
+        .map(|i| {
+            let size_ptr = first_size_ptr.add(i * stride);
+            match type_ {
+                gl21::FLOAT => size_ptr.cast::<GLfloat>().read_unaligned(),
+                gles11::FIXED => fixed_to_float(size_ptr.cast::<GLfixed>().read_unaligned()),
                 _ => unreachable!(),
             }
+        })
+        .collect()
+}
+
+/// Reads a `glDrawElements` index array, which may be an offset into the
+/// buffer bound to `GL_ELEMENT_ARRAY_BUFFER`.
+unsafe fn read_indices(type_: GLenum, count: usize, indices: *const GLvoid) -> Vec<usize> {
+    let index_size = match type_ {
+        gl21::UNSIGNED_BYTE => 1,
+        gl21::UNSIGNED_SHORT => 2,
+        _ => unreachable!(),
+    };
+
+    let mut index_buffer_binding = 0;
+    gl21::GetIntegerv(
+        gl21::ELEMENT_ARRAY_BUFFER_BINDING,
+        &mut index_buffer_binding,
+    );
+    let mut readback = Vec::new();
+    let indices = if index_buffer_binding != 0 {
+        // The indices pointer is an offset into the bound buffer, so the
+        // indices must be read back.
+        readback.resize(count * index_size, 0u8);
+        gl21::GetBufferSubData(
+            gl21::ELEMENT_ARRAY_BUFFER,
+            indices as GLintptr,
+            readback.len() as GLsizeiptr,
+            readback.as_mut_ptr() as *mut GLvoid,
+        );
+        readback.as_ptr().cast()
+    } else {
+        indices
+    };
+
+    (0..count)
+        .map(|i| match type_ {
+            gl21::UNSIGNED_BYTE => indices.cast::<GLubyte>().add(i).read_unaligned() as usize,
+            gl21::UNSIGNED_SHORT => indices.cast::<GLushort>().add(i).read_unaligned() as usize,
+            _ => unreachable!(),
+        })
+        .collect()
+}
+
+/// Finds the range of vertices referenced by a `glDrawElements` index array.
+/// Returns the first index and the number of vertices in the range.
+fn index_range(indices: &[usize]) -> (usize, usize) {
+    match (indices.iter().min(), indices.iter().max()) {
+        (Some(&first), Some(&last)) => (first, last + 1 - first),
+        _ => (0, 0),
+    }
+}
+
+/// Computes the texture co-ordinates `[s0, t0, s1, t1]` used by
+/// `glDrawTexOES` for a texture with a particular size and crop rectangle.
+fn crop_rect_to_tex_coords(
+    [crop_x, crop_y, crop_width, crop_height]: [GLint; 4],
+    texture_width: GLint,
+    texture_height: GLint,
+) -> [GLfloat; 4] {
+    let texture_width = texture_width as GLfloat;
+    let texture_height = texture_height as GLfloat;
+    [
+        crop_x as GLfloat / texture_width,
+        crop_y as GLfloat / texture_height,
+        (crop_x + crop_width) as GLfloat / texture_width,
+        (crop_y + crop_height) as GLfloat / texture_height,
+    ]
+}
+
+/// Decodes an `OES_compressed_paletted_texture` image, which consists of a
+/// palette followed by the indices for `level_count` successive mip levels.
+/// Returns the width, height and pixels for each level, in the format given by
+/// [PalettedTextureFormat::palette_entry_format] and
+/// [PalettedTextureFormat::palette_entry_type].
+fn decode_paletted_texture(
+    &PalettedTextureFormat {
+        index_is_nibble,
+        palette_entry_format,
+        palette_entry_type,
+    }: &PalettedTextureFormat,
+    width: GLsizei,
+    height: GLsizei,
+    level_count: GLint,
+    data: &[u8],
+) -> Vec<(GLsizei, GLsizei, Vec<u8>)> {
+    let palette_entry_size = match palette_entry_type {
+        gl21::UNSIGNED_BYTE => match palette_entry_format {
+            gl21::RGB => 3,
+            gl21::RGBA => 4,
+            _ => unreachable!(),
+        },
+        gl21::UNSIGNED_SHORT_5_6_5
+        | gl21::UNSIGNED_SHORT_4_4_4_4
+        | gl21::UNSIGNED_SHORT_5_5_5_1 => 2,
+        _ => unreachable!(),
+    };
+    let palette_entry_count = match index_is_nibble {
+        true => 16,
+        false => 256,
+    };
+    let palette_size = palette_entry_size * palette_entry_count;
+
+    let (palette, mut indices) = data.split_at(palette_size);
+
+    let mut levels = Vec::with_capacity(level_count as usize);
+    let (mut width, mut height) = (width, height);
+    for _ in 0..level_count {
+        let index_count = width as usize * height as usize;
+        let (index_word_size, index_word_count) = match index_is_nibble {
+            true => (1, (index_count + 1) / 2),
+            false => (4, (index_count + 3) / 4),
+        };
+        let indices_size = index_word_size * index_word_count;
+        let (level_indices, next_indices) = indices.split_at(indices_size);
+
+        let mut decoded = Vec::<u8>::with_capacity(palette_entry_size * index_count);
+        for i in 0..index_count {
+            let index = if index_is_nibble {
+                (level_indices[i / 2] >> ((1 - (i % 2)) * 4)) & 0xf
+            } else {
+                level_indices[i]
+            } as usize;
+            let palette_entry = &palette[index * palette_entry_size..][..palette_entry_size];
+            decoded.extend_from_slice(palette_entry);
+        }
+        assert!(decoded.len() == palette_entry_size * index_count);
+        levels.push((width, height, decoded));
+
+        indices = next_indices;
+        width = (width / 2).max(1);
+        height = (height / 2).max(1);
+    }
+    assert!(indices.is_empty());
+
+    levels
+}
+
+/// Swap the red and blue channels of rows of 32-bit BGRA pixels `width` pixels
+/// wide and `row_stride` bytes apart, writing the resulting RGBA pixels to
+/// `out` with the same layout. Row padding is copied unchanged.
+fn bgra_to_rgba(pixels: &[u8], width: usize, row_stride: usize, out: &mut Vec<u8>) {
+    out.clear();
+    out.extend_from_slice(pixels);
+    for row in out.chunks_mut(row_stride) {
+        for pixel in row[..width * 4].chunks_exact_mut(4) {
+            pixel.swap(0, 2);
+        }
+    }
+}
+
+/// Convert tightly-packed RGBA8 pixels in rows `width` pixels wide to
+/// `GL_RGB`/`GL_UNSIGNED_SHORT_5_6_5` pixels in `out`, with rows `row_stride`
+/// bytes apart. Row padding in `out` is left untouched.
+fn rgba8_to_rgb565(rgba8: &[u8], width: usize, row_stride: usize, out: &mut [u8]) {
+    for (in_row, out_row) in rgba8.chunks(width * 4).zip(out.chunks_mut(row_stride)) {
+        for (in_pixel, out_pixel) in in_row.chunks_exact(4).zip(out_row.chunks_exact_mut(2)) {
+            let [r, g, b] = [in_pixel[0], in_pixel[1], in_pixel[2]].map(u16::from);
+            let packed = ((r >> 3) << 11) | ((g >> 2) << 5) | (b >> 3);
+            out_pixel.copy_from_slice(&packed.to_ne_bytes());
         }
     }
 }
+
 impl GLES for GLES1OnGL2 {
     fn description() -> &'static str {
         "OpenGL ES 1.1 via touchHLE GLES1-on-GL2 layer"
     }
 
-    fn new(window: &mut Window) -> Result<Self, String> {
-        Ok(Self {
+    fn new(window: &mut Window, options: &Options) -> Result<Self, String> {
+        let mut gles = Self {
             gl_ctx: window.create_gl_context(GLVersion::GL21Compat)?,
-            pointer_is_fixed_point: [false; ARRAYS.len()],
-            fixed_point_texture_units: HashSet::new(),
+            pointer_needs_translation: [false; ARRAYS.len()],
+            translated_pointer_types: [gles11::FIXED; ARRAYS.len()],
+            translated_texture_units: HashMap::new(),
             fixed_point_translation_buffers: [Vec::new(), Vec::new(), Vec::new(), Vec::new()],
-        })
+            fixed_point_translation_vbos: [0; ARRAYS.len()],
+            texture_crop_rects: HashMap::new(),
+            point_size_array: Default::default(),
+            capabilities: GLCapabilities::from_extensions(""),
+            bgra_conversion_buffer: Vec::new(),
+            native_pvrtc: false,
+            read_pixels_buffer: Vec::new(),
+            error: gl21::NO_ERROR,
+            deindex_fixed_point_draws: options.deindex_fixed_point_draws,
+        };
+        gles.make_current(window);
+        gles.capabilities = unsafe { detect_capabilities() };
+        log_dbg!("Host OpenGL capabilities: {:?}", gles.capabilities());
+        if !gles.capabilities.bgra {
+            log!("Host OpenGL doesn't support BGRA textures, they will be converted to RGBA.");
+        }
+        gles.native_pvrtc = use_native_pvrtc(&gles.capabilities, options.force_pvrtc_cpu_decode);
+        if gles.native_pvrtc {
+            log!("Host OpenGL supports PVRTC textures, they won't be decoded on the CPU.");
+        }
+        Ok(gles)
     }
 
     fn make_current(&self, window: &Window) {
@@ -586,6 +1408,9 @@ impl GLES for GLES1OnGL2 {
 
     // Generic state manipulation
     unsafe fn GetError(&mut self) -> GLenum {
+        if self.error != gl21::NO_ERROR {
+            return std::mem::replace(&mut self.error, gl21::NO_ERROR);
+        }
         gl21::GetError()
     }
     unsafe fn Enable(&mut self, cap: GLenum) {
@@ -597,8 +1422,14 @@ impl GLES for GLES1OnGL2 {
             assert!(CAPABILITIES.contains(&cap));
         }
         gl21::Enable(cap);
+        for &linked_cap in linked_capabilities(cap) {
+            gl21::Enable(linked_cap);
+        }
     }
     unsafe fn IsEnabled(&mut self, cap: GLenum) -> GLboolean {
+        if cap == gles11::POINT_SIZE_ARRAY_OES {
+            return self.point_size_array.enabled as GLboolean;
+        }
         assert!(
             CAPABILITIES.contains(&cap) || ARRAYS.iter().any(|&ArrayInfo { name, .. }| name == cap)
         );
@@ -613,11 +1444,18 @@ impl GLES for GLES1OnGL2 {
             assert!(CAPABILITIES.contains(&cap));
         }
         gl21::Disable(cap);
+        for &linked_cap in linked_capabilities(cap) {
+            gl21::Disable(linked_cap);
+        }
     }
     unsafe fn ClientActiveTexture(&mut self, texture: GLenum) {
         gl21::ClientActiveTexture(texture);
     }
     unsafe fn EnableClientState(&mut self, array: GLenum) {
+        if array == gles11::POINT_SIZE_ARRAY_OES {
+            self.point_size_array.enabled = true;
+            return;
+        }
         if CAPABILITIES.contains(&array) {
             log_dbg!(
                 "Tolerating glEnableClientState({:#x}) of a capability",
@@ -629,6 +1467,10 @@ impl GLES for GLES1OnGL2 {
         gl21::EnableClientState(array);
     }
     unsafe fn DisableClientState(&mut self, array: GLenum) {
+        if array == gles11::POINT_SIZE_ARRAY_OES {
+            self.point_size_array.enabled = false;
+            return;
+        }
         if CAPABILITIES.contains(&array) {
             log_dbg!(
                 "Tolerating glDisableClientState({:#x}) of a capability",
@@ -640,12 +1482,23 @@ impl GLES for GLES1OnGL2 {
         gl21::DisableClientState(array);
     }
     unsafe fn GetBooleanv(&mut self, pname: GLenum, params: *mut GLboolean) {
+        if pname == gles11::POINT_SIZE_ARRAY_OES {
+            params.write(self.point_size_array.enabled as GLboolean);
+            return;
+        }
         let (type_, _count) = GET_PARAMS.get_type_info(pname);
         // TODO: type conversion
         assert!(type_ == ParamType::Boolean);
         gl21::GetBooleanv(pname, params);
     }
-    // TODO: GetFixedv
+    unsafe fn GetFixedv(&mut self, pname: GLenum, params: *mut GLfixed) {
+        GET_PARAMS.getxv(
+            |params| gl21::GetFloatv(pname, params),
+            |params| gl21::GetIntegerv(pname, params),
+            pname,
+            params,
+        )
+    }
     unsafe fn GetFloatv(&mut self, pname: GLenum, params: *mut GLfloat) {
         let (type_, _count) = GET_PARAMS.get_type_info(pname);
         // TODO: type conversion
@@ -653,24 +1506,59 @@ impl GLES for GLES1OnGL2 {
         gl21::GetFloatv(pname, params);
     }
     unsafe fn GetIntegerv(&mut self, pname: GLenum, params: *mut GLint) {
+        match pname {
+            gles11::IMPLEMENTATION_COLOR_READ_FORMAT_OES => {
+                params.write(IMPLEMENTATION_COLOR_READ_FORMAT as GLint);
+                return;
+            }
+            gles11::IMPLEMENTATION_COLOR_READ_TYPE_OES => {
+                params.write(IMPLEMENTATION_COLOR_READ_TYPE as GLint);
+                return;
+            }
+            gles11::POINT_SIZE_ARRAY_TYPE_OES => {
+                params.write(self.point_size_array.type_ as GLint);
+                return;
+            }
+            gles11::POINT_SIZE_ARRAY_STRIDE_OES => {
+                params.write(self.point_size_array.stride);
+                return;
+            }
+            gles11::POINT_SIZE_ARRAY_BUFFER_BINDING_OES => {
+                params.write(self.point_size_array.buffer_binding as GLint);
+                return;
+            }
+            _ => (),
+        }
         let (type_, _count) = GET_PARAMS.get_type_info(pname);
         // TODO: type conversion
-        assert!(type_ == ParamType::Int);
+        assert!(matches!(type_, ParamType::Int | ParamType::Enum));
         gl21::GetIntegerv(pname, params);
     }
     unsafe fn GetTexEnviv(&mut self, target: GLenum, pname: GLenum, params: *mut GLint) {
+        if target == gl21::POINT_SPRITE {
+            assert!(pname == gl21::COORD_REPLACE);
+            return gl21::GetTexEnviv(target, pname, params);
+        }
         let (type_, _count) = TEX_ENV_PARAMS.get_type_info(pname);
-        assert!(type_ == ParamType::Int);
+        assert!(matches!(type_, ParamType::Int | ParamType::Enum));
         assert_eq!(target, gl21::TEXTURE_ENV);
         gl21::GetTexEnviv(target, pname, params);
     }
     unsafe fn GetTexEnvfv(&mut self, target: GLenum, pname: GLenum, params: *mut GLfloat) {
+        if target == gl21::POINT_SPRITE {
+            assert!(pname == gl21::COORD_REPLACE);
+            return gl21::GetTexEnvfv(target, pname, params);
+        }
         let (type_, _count) = TEX_ENV_PARAMS.get_type_info(pname);
         assert!(type_ == ParamType::Float);
         assert_eq!(target, gl21::TEXTURE_ENV);
         gl21::GetTexEnvfv(target, pname, params);
     }
     unsafe fn GetPointerv(&mut self, pname: GLenum, params: *mut *const GLvoid) {
+        if pname == gles11::POINT_SIZE_ARRAY_POINTER_OES {
+            params.write(self.point_size_array.pointer);
+            return;
+        }
         assert!(ARRAYS
             .iter()
             .any(|&ArrayInfo { pointer, .. }| pname == pointer));
@@ -701,6 +1589,15 @@ impl GLES for GLES1OnGL2 {
         gl21::GetString(name)
     }
 
+    unsafe fn PushAttrib(&mut self, mask: GLbitfield) {
+        // None of the state saved by this is emulated by us, so OpenGL 2.1 can
+        // be relied upon to save and restore all of it.
+        gl21::PushAttrib(mask);
+    }
+    unsafe fn PopAttrib(&mut self) {
+        gl21::PopAttrib();
+    }
+
     // Other state manipulation
     unsafe fn AlphaFunc(&mut self, func: GLenum, ref_: GLclampf) {
         assert!([
@@ -720,38 +1617,20 @@ impl GLES for GLES1OnGL2 {
         self.AlphaFunc(func, fixed_to_float(ref_))
     }
     unsafe fn BlendFunc(&mut self, sfactor: GLenum, dfactor: GLenum) {
-        let common_factors = [
-            gl21::ZERO,
-            gl21::ONE,
-            gl21::SRC_ALPHA,
-            gl21::ONE_MINUS_SRC_ALPHA,
-            gl21::DST_ALPHA,
-            gl21::ONE_MINUS_DST_ALPHA,
-        ];
-        let sfactors = [
-            gl21::DST_COLOR,
-            gl21::ONE_MINUS_DST_COLOR,
-            gl21::SRC_ALPHA_SATURATE,
-        ];
-        let dfactors = [gl21::SRC_COLOR, gl21::ONE_MINUS_SRC_COLOR];
-        assert!(
-            common_factors.contains(&sfactor)
-                || sfactors.contains(&sfactor)
-                || dfactors.contains(&sfactor)
-        );
-        assert!(
-            common_factors.contains(&dfactor)
-                || sfactors.contains(&dfactor)
-                || dfactors.contains(&dfactor)
-        );
-        if sfactors.contains(&dfactor) {
-            log_dbg!("Tolerating sfactor {:#x} in dfactor argument", dfactor);
-        }
-        if dfactors.contains(&sfactor) {
-            log_dbg!("Tolerating dfactor {:#x} in sfactor argument", sfactor);
-        }
+        check_blend_factors(sfactor, dfactor);
         gl21::BlendFunc(sfactor, dfactor);
     }
+    unsafe fn BlendFuncSeparateOES(
+        &mut self,
+        src_rgb: GLenum,
+        dst_rgb: GLenum,
+        src_alpha: GLenum,
+        dst_alpha: GLenum,
+    ) {
+        check_blend_factors(src_rgb, dst_rgb);
+        check_blend_factors(src_alpha, dst_alpha);
+        gl21::BlendFuncSeparate(src_rgb, dst_rgb, src_alpha, dst_alpha);
+    }
     unsafe fn ColorMask(
         &mut self,
         red: GLboolean,
@@ -986,6 +1865,31 @@ impl GLES for GLES1OnGL2 {
             params,
         )
     }
+    unsafe fn GetLightfv(&mut self, light: GLenum, pname: GLenum, params: *mut GLfloat) {
+        LIGHT_PARAMS.assert_known_param(pname);
+        gl21::GetLightfv(light, pname, params);
+    }
+    unsafe fn GetLightxv(&mut self, light: GLenum, pname: GLenum, params: *mut GLfixed) {
+        LIGHT_PARAMS.getxv(
+            |params| gl21::GetLightfv(light, pname, params),
+            |params| gl21::GetLightiv(light, pname, params),
+            pname,
+            params,
+        )
+    }
+    unsafe fn GetMaterialfv(&mut self, face: GLenum, pname: GLenum, params: *mut GLfloat) {
+        assert_gettable_material(face, pname);
+        gl21::GetMaterialfv(face, pname, params);
+    }
+    unsafe fn GetMaterialxv(&mut self, face: GLenum, pname: GLenum, params: *mut GLfixed) {
+        assert_gettable_material(face, pname);
+        MATERIAL_PARAMS.getxv(
+            |params| gl21::GetMaterialfv(face, pname, params),
+            |_| unreachable!(), // no integer parameters exist
+            pname,
+            params,
+        )
+    }
 
     // Buffers
     unsafe fn GenBuffers(&mut self, n: GLsizei, buffers: *mut GLuint) {
@@ -1050,25 +1954,46 @@ impl GLES for GLES1OnGL2 {
         stride: GLsizei,
         pointer: *const GLvoid,
     ) {
-        assert!(size == 4);
-        if type_ == gles11::FIXED {
+        if let Err(error) = check_stride(stride) {
+            self.raise_error(error);
+            return;
+        }
+        // OpenGL ES 1.1 only allows 4, but OpenGL 2.1 can also handle 3.
+        match size {
+            4 => (),
+            3 => log!("Warning: Tolerating glColorPointer() with size 3"),
+            _ => {
+                log!(
+                    "Warning: Ignoring glColorPointer() with unsupported size {}",
+                    size
+                );
+                return;
+            }
+        }
+        if type_ == gles11::FIXED || type_ == gl21::UNSIGNED_SHORT {
             // Translation deferred until draw call
-            self.pointer_is_fixed_point[0] = true;
+            self.pointer_needs_translation[0] = true;
+            self.translated_pointer_types[0] = type_;
             gl21::ColorPointer(size, gl21::FLOAT, stride, pointer)
         } else {
             assert!(type_ == gl21::UNSIGNED_BYTE || type_ == gl21::FLOAT);
-            self.pointer_is_fixed_point[0] = false;
+            self.pointer_needs_translation[0] = false;
             gl21::ColorPointer(size, type_, stride, pointer)
         }
     }
     unsafe fn NormalPointer(&mut self, type_: GLenum, stride: GLsizei, pointer: *const GLvoid) {
+        if let Err(error) = check_stride(stride) {
+            self.raise_error(error);
+            return;
+        }
         if type_ == gles11::FIXED {
             // Translation deferred until draw call
-            self.pointer_is_fixed_point[1] = true;
+            self.pointer_needs_translation[1] = true;
+            self.translated_pointer_types[1] = type_;
             gl21::NormalPointer(gl21::FLOAT, stride, pointer)
         } else {
             assert!(type_ == gl21::BYTE || type_ == gl21::SHORT || type_ == gl21::FLOAT);
-            self.pointer_is_fixed_point[1] = false;
+            self.pointer_needs_translation[1] = false;
             gl21::NormalPointer(type_, stride, pointer)
         }
     }
@@ -1079,24 +2004,31 @@ impl GLES for GLES1OnGL2 {
         stride: GLsizei,
         pointer: *const GLvoid,
     ) {
+        if let Err(error) = check_stride(stride) {
+            self.raise_error(error);
+            return;
+        }
         assert!(size == 2 || size == 3 || size == 4);
         let mut active_texture: GLenum = 0;
         gl21::GetIntegerv(
             gl21::CLIENT_ACTIVE_TEXTURE,
             &mut active_texture as *mut _ as *mut _,
         );
-        if type_ == gles11::FIXED {
+        if type_ == gl21::UNSIGNED_BYTE {
+            log!("Warning: Tolerating glTexCoordPointer() with type GL_UNSIGNED_BYTE");
+        }
+        // OpenGL 2.1 doesn't support fixed-point or byte texture co-ordinates.
+        if type_ == gles11::FIXED || type_ == gl21::BYTE || type_ == gl21::UNSIGNED_BYTE {
             // Translation deferred until draw call.
             // There is one texture co-ordinates pointer per texture unit.
-            self.fixed_point_texture_units.insert(active_texture);
-            self.pointer_is_fixed_point[2] = true;
+            self.translated_texture_units.insert(active_texture, type_);
+            self.pointer_needs_translation[2] = true;
             gl21::TexCoordPointer(size, gl21::FLOAT, stride, pointer)
         } else {
-            // TODO: byte
             assert!(type_ == gl21::SHORT || type_ == gl21::FLOAT);
-            self.fixed_point_texture_units.remove(&active_texture);
-            if self.fixed_point_texture_units.is_empty() {
-                self.pointer_is_fixed_point[2] = false;
+            self.translated_texture_units.remove(&active_texture);
+            if self.translated_texture_units.is_empty() {
+                self.pointer_needs_translation[2] = false;
             }
             gl21::TexCoordPointer(size, type_, stride, pointer)
         }
@@ -1108,18 +2040,49 @@ impl GLES for GLES1OnGL2 {
         stride: GLsizei,
         pointer: *const GLvoid,
     ) {
+        if let Err(error) = check_stride(stride) {
+            self.raise_error(error);
+            return;
+        }
         assert!(size == 2 || size == 3 || size == 4);
-        if type_ == gles11::FIXED {
+        if type_ == gl21::UNSIGNED_BYTE {
+            log!("Warning: Tolerating glVertexPointer() with type GL_UNSIGNED_BYTE");
+        }
+        // OpenGL 2.1 doesn't support fixed-point or byte vertices.
+        if type_ == gles11::FIXED || type_ == gl21::BYTE || type_ == gl21::UNSIGNED_BYTE {
             // Translation deferred until draw call
-            self.pointer_is_fixed_point[3] = true;
+            self.pointer_needs_translation[3] = true;
+            self.translated_pointer_types[3] = type_;
             gl21::VertexPointer(size, gl21::FLOAT, stride, pointer)
         } else {
-            // TODO: byte
             assert!(type_ == gl21::SHORT || type_ == gl21::FLOAT);
-            self.pointer_is_fixed_point[3] = false;
+            self.pointer_needs_translation[3] = false;
             gl21::VertexPointer(size, type_, stride, pointer)
         }
     }
+    unsafe fn PointSizePointerOES(
+        &mut self,
+        type_: GLenum,
+        stride: GLsizei,
+        pointer: *const GLvoid,
+    ) {
+        assert!(type_ == gl21::FLOAT || type_ == gles11::FIXED);
+        if let Err(error) = check_stride(stride) {
+            self.raise_error(error);
+            return;
+        }
+        let mut buffer_binding: GLint = 0;
+        gl21::GetIntegerv(gl21::ARRAY_BUFFER_BINDING, &mut buffer_binding);
+        // Both the fixed-point translation and the per-vertex point sizes are
+        // handled at draw time.
+        self.point_size_array = PointSizeArray {
+            enabled: self.point_size_array.enabled,
+            type_,
+            stride,
+            pointer,
+            buffer_binding: buffer_binding as GLuint,
+        };
+    }
 
     // Drawing
     unsafe fn DrawArrays(&mut self, mode: GLenum, first: GLint, count: GLsizei) {
@@ -1134,9 +2097,18 @@ impl GLES for GLES1OnGL2 {
         ]
         .contains(&mode));
 
-        let fixed_point_arrays_state_backup = self.translate_fixed_point_arrays(first, count);
+        let Some(fixed_point_arrays_state_backup) = self.translate_fixed_point_arrays(first, count)
+        else {
+            return;
+        };
 
-        gl21::DrawArrays(mode, first, count);
+        if self.point_size_array_in_use(mode) {
+            assert!(first >= 0 && count >= 0);
+            let vertices: Vec<usize> = (first as usize..(first + count) as usize).collect();
+            self.draw_points_with_sizes(first as usize, &vertices);
+        } else {
+            gl21::DrawArrays(mode, first, count);
+        }
 
         self.restore_fixed_point_arrays(fixed_point_arrays_state_backup);
     }
@@ -1159,65 +2131,49 @@ impl GLES for GLES1OnGL2 {
         .contains(&mode));
         assert!(type_ == gl21::UNSIGNED_BYTE || type_ == gl21::UNSIGNED_SHORT);
 
-        let fixed_point_arrays_state_backup = if self
-            .pointer_is_fixed_point
-            .iter()
-            .any(|&is_fixed| is_fixed)
-        {
-            // Scan the index buffer to find the range of data that may need
-            // fixed-point translation.
-            // TODO: Would it be more efficient to turn this into a
-            // non-indexed draw-call instead?
-
-            let mut index_buffer_binding = 0;
-            gl21::GetIntegerv(
-                gl21::ELEMENT_ARRAY_BUFFER_BINDING,
-                &mut index_buffer_binding,
-            );
-            if index_buffer_binding != 0 {
-                // TODO: translation for bound index array buffers
-                todo!("TODO: GLES1-on-GL2 layer does not support buffer bindings yet. (Try OpenGL ES on Android.)");
-            }
+        let uses_fixed_point = self.pointer_needs_translation.iter().any(|&needed| needed);
+        let uses_point_sizes = self.point_size_array_in_use(mode);
 
-            let mut first = usize::MAX;
-            let mut last = usize::MIN;
+        // Scan the index buffer to find the range of data that may need
+        // fixed-point translation, or the points to draw individually.
+        let index_list = if uses_fixed_point || uses_point_sizes {
             assert!(count >= 0);
-            match type_ {
-                gl21::UNSIGNED_BYTE => {
-                    let indices_ptr: *const GLubyte = indices.cast();
-                    for i in 0..(count as usize) {
-                        let index = indices_ptr.add(i).read_unaligned();
-                        first = first.min(index as usize);
-                        last = last.max(index as usize);
-                    }
-                }
-                gl21::UNSIGNED_SHORT => {
-                    let indices_ptr: *const GLushort = indices.cast();
-                    for i in 0..(count as usize) {
-                        let index = indices_ptr.add(i).read_unaligned();
-                        first = first.min(index as usize);
-                        last = last.max(index as usize);
-                    }
-                }
-                _ => unreachable!(),
-            }
+            read_indices(type_, count as usize, indices)
+        } else {
+            Vec::new()
+        };
 
-            let (first, count) = if first == usize::MAX && last == usize::MIN {
-                assert!(count == 0);
-                (0, 0)
-            } else {
-                (
-                    first.try_into().unwrap(),
-                    (last + 1 - first).try_into().unwrap(),
-                )
+        // Alternatively, only translate the vertices actually used, by
+        // turning this into a non-indexed draw call.
+        if uses_fixed_point && !uses_point_sizes && self.deindex_fixed_point_draws {
+            let mut buffers = Vec::new();
+            let Some(backups) = self.deindex_arrays(&index_list, &mut buffers) else {
+                return;
             };
+            gl21::DrawArrays(mode, 0, count);
+            self.restore_deindexed_arrays(backups);
+            return;
+        }
 
-            Some(self.translate_fixed_point_arrays(first, count))
+        let (first, range_count) = index_range(&index_list);
+
+        let fixed_point_arrays_state_backup = if uses_fixed_point {
+            let Some(backup) = self.translate_fixed_point_arrays(
+                first.try_into().unwrap(),
+                range_count.try_into().unwrap(),
+            ) else {
+                return;
+            };
+            Some(backup)
         } else {
             None
         };
 
-        gl21::DrawElements(mode, count, type_, indices);
+        if uses_point_sizes {
+            self.draw_points_with_sizes(first, &index_list);
+        } else {
+            gl21::DrawElements(mode, count, type_, indices);
+        }
 
         if let Some(fixed_point_arrays_state_backup) = fixed_point_arrays_state_backup {
             self.restore_fixed_point_arrays(fixed_point_arrays_state_backup);
@@ -1281,12 +2237,63 @@ impl GLES for GLES1OnGL2 {
         type_: GLenum,
         pixels: *mut GLvoid,
     ) {
-        gl21::ReadPixels(x, y, width, height, format, type_, pixels)
+        if (format, type_) == (gl21::RGBA, gl21::UNSIGNED_BYTE) {
+            gl21::ReadPixels(x, y, width, height, format, type_, pixels);
+            return;
+        }
+        // OpenGL ES 1.1 only allows GL_RGBA/GL_UNSIGNED_BYTE and the
+        // implementation-specific format and type.
+        // TODO: GL_INVALID_OPERATION
+        if (format, type_)
+            != (
+                IMPLEMENTATION_COLOR_READ_FORMAT,
+                IMPLEMENTATION_COLOR_READ_TYPE,
+            )
+        {
+            log!(
+                "Warning: Ignoring glReadPixels() with unsupported format {:#x} and type {:#x}",
+                format,
+                type_
+            );
+            return;
+        }
+        if width <= 0 || height <= 0 {
+            return;
+        }
+
+        let (width, height) = (width as usize, height as usize);
+        let mut buffer = std::mem::take(&mut self.read_pixels_buffer);
+        buffer.resize(width * height * 4, 0);
+        let mut old_alignment: GLint = 0;
+        gl21::GetIntegerv(gl21::PACK_ALIGNMENT, &mut old_alignment);
+        gl21::PixelStorei(gl21::PACK_ALIGNMENT, 1);
+        gl21::ReadPixels(
+            x,
+            y,
+            width as GLsizei,
+            height as GLsizei,
+            gl21::RGBA,
+            gl21::UNSIGNED_BYTE,
+            buffer.as_mut_ptr() as *mut _,
+        );
+        gl21::PixelStorei(gl21::PACK_ALIGNMENT, old_alignment);
+
+        let alignment = old_alignment as usize;
+        let row_size = width * 2;
+        let row_stride = row_size.div_ceil(alignment) * alignment;
+        // The last row doesn't need to be padded.
+        let size = row_stride * (height - 1) + row_size;
+        let out = std::slice::from_raw_parts_mut(pixels.cast::<u8>(), size);
+        rgba8_to_rgb565(&buffer, width, row_stride, out);
+        self.read_pixels_buffer = buffer;
     }
     unsafe fn GenTextures(&mut self, n: GLsizei, textures: *mut GLuint) {
         gl21::GenTextures(n, textures)
     }
     unsafe fn DeleteTextures(&mut self, n: GLsizei, textures: *const GLuint) {
+        for i in 0..n.max(0) as usize {
+            self.texture_crop_rects.remove(&textures.add(i).read());
+        }
         gl21::DeleteTextures(n, textures)
     }
     unsafe fn ActiveTexture(&mut self, texture: GLenum) {
@@ -1320,16 +2327,32 @@ impl GLES for GLES1OnGL2 {
     }
     unsafe fn TexParameteriv(&mut self, target: GLenum, pname: GLenum, params: *const GLint) {
         assert!(target == gl21::TEXTURE_2D);
+        if pname == gles11::TEXTURE_CROP_RECT_OES {
+            let crop_rect = std::array::from_fn(|i| params.add(i).read());
+            self.set_texture_crop_rect(crop_rect);
+            return;
+        }
         TEX_PARAMS.assert_known_param(pname);
         gl21::TexParameteriv(target, pname, params);
     }
     unsafe fn TexParameterfv(&mut self, target: GLenum, pname: GLenum, params: *const GLfloat) {
         assert!(target == gl21::TEXTURE_2D);
+        if pname == gles11::TEXTURE_CROP_RECT_OES {
+            let crop_rect = std::array::from_fn(|i| params.add(i).read() as GLint);
+            self.set_texture_crop_rect(crop_rect);
+            return;
+        }
         TEX_PARAMS.assert_known_param(pname);
         gl21::TexParameterfv(target, pname, params);
     }
     unsafe fn TexParameterxv(&mut self, target: GLenum, pname: GLenum, params: *const GLfixed) {
         assert!(target == gl21::TEXTURE_2D);
+        if pname == gles11::TEXTURE_CROP_RECT_OES {
+            // Integer parameter, so no fixed-point conversion (see setxv).
+            let crop_rect = std::array::from_fn(|i| params.add(i).read());
+            self.set_texture_crop_rect(crop_rect);
+            return;
+        }
         TEX_PARAMS.setxv(
             |params| gl21::TexParameterfv(target, pname, params),
             |params| gl21::TexParameteriv(target, pname, params),
@@ -1337,6 +2360,28 @@ impl GLES for GLES1OnGL2 {
             params,
         )
     }
+    unsafe fn GetTexParameteriv(&mut self, target: GLenum, pname: GLenum, params: *mut GLint) {
+        assert!(target == gl21::TEXTURE_2D);
+        if pname == gles11::TEXTURE_CROP_RECT_OES {
+            for (i, value) in self.get_texture_crop_rect().into_iter().enumerate() {
+                params.add(i).write(value);
+            }
+            return;
+        }
+        TEX_PARAMS.assert_known_param(pname);
+        gl21::GetTexParameteriv(target, pname, params);
+    }
+    unsafe fn GetTexParameterfv(&mut self, target: GLenum, pname: GLenum, params: *mut GLfloat) {
+        assert!(target == gl21::TEXTURE_2D);
+        if pname == gles11::TEXTURE_CROP_RECT_OES {
+            for (i, value) in self.get_texture_crop_rect().into_iter().enumerate() {
+                params.add(i).write(value as GLfloat);
+            }
+            return;
+        }
+        TEX_PARAMS.assert_known_param(pname);
+        gl21::GetTexParameterfv(target, pname, params);
+    }
     unsafe fn TexImage2D(
         &mut self,
         target: GLenum,
@@ -1373,6 +2418,7 @@ impl GLES for GLES1OnGL2 {
                 || type_ == gl21::UNSIGNED_SHORT_4_4_4_4
                 || type_ == gl21::UNSIGNED_SHORT_5_5_5_1
         );
+        let (format, pixels) = self.convert_bgra_pixels(width, height, format, type_, pixels);
         gl21::TexImage2D(
             target,
             level,
@@ -1405,6 +2451,7 @@ impl GLES for GLES1OnGL2 {
                 || format == gl21::RGBA
                 || format == gl21::LUMINANCE
                 || format == gl21::LUMINANCE_ALPHA
+                || format == gl21::BGRA
         );
         assert!(
             type_ == gl21::UNSIGNED_BYTE
@@ -1412,6 +2459,7 @@ impl GLES for GLES1OnGL2 {
                 || type_ == gl21::UNSIGNED_SHORT_4_4_4_4
                 || type_ == gl21::UNSIGNED_SHORT_5_5_5_1
         );
+        let (format, pixels) = self.convert_bgra_pixels(width, height, format, type_, pixels);
         gl21::TexSubImage2D(
             target, level, xoffset, yoffset, width, height, format, type_, pixels,
         )
@@ -1429,9 +2477,19 @@ impl GLES for GLES1OnGL2 {
     ) {
         let data = unsafe { std::slice::from_raw_parts(data.cast::<u8>(), image_size as usize) };
         // IMG_texture_compression_pvrtc (only on Imagination/Apple GPUs)
-        // TODO: It would be more efficient to use hardware decoding where
-        // available (I just don't have a suitable device to try this on)
-        if try_decode_pvrtc(
+        if self.native_pvrtc && is_pvrtc_format(internalformat) {
+            log_dbg!("Directly supported PVRTC format: {:#x}", internalformat);
+            gl21::CompressedTexImage2D(
+                target,
+                level,
+                internalformat,
+                width,
+                height,
+                border,
+                image_size,
+                data.as_ptr() as *const _,
+            )
+        } else if try_decode_pvrtc(
             self,
             target,
             level,
@@ -1442,70 +2500,44 @@ impl GLES for GLES1OnGL2 {
             data,
         ) {
             log_dbg!("Decoded PVRTC");
+        // OES_compressed_ETC1_RGB8_texture is also only in OpenGL ES.
+        } else if try_decode_etc1(
+            self,
+            target,
+            level,
+            internalformat,
+            width,
+            height,
+            border,
+            data,
+        ) {
+            log_dbg!("Decoded ETC1");
         // OES_compressed_paletted_texture is only in OpenGL ES, so we'll need
         // to decompress those formats.
-        } else if let Some(PalettedTextureFormat {
-            index_is_nibble,
-            palette_entry_format,
-            palette_entry_type,
-        }) = PalettedTextureFormat::get_info(internalformat)
-        {
+        } else if let Some(format) = PalettedTextureFormat::get_info(internalformat) {
             // This should be invalid use? (TODO)
             assert!(border == 0);
 
-            let palette_entry_size = match palette_entry_type {
-                gl21::UNSIGNED_BYTE => match palette_entry_format {
-                    gl21::RGB => 3,
-                    gl21::RGBA => 4,
-                    _ => unreachable!(),
-                },
-                gl21::UNSIGNED_SHORT_5_6_5
-                | gl21::UNSIGNED_SHORT_4_4_4_4
-                | gl21::UNSIGNED_SHORT_5_5_5_1 => 2,
-                _ => unreachable!(),
-            };
-            let palette_entry_count = match index_is_nibble {
-                true => 16,
-                false => 256,
-            };
-            let palette_size = palette_entry_size * palette_entry_count;
+            // For this extension, a negative level means that the image
+            // contains that many mip levels after the first.
+            assert!(level <= 0);
+            let level_count = 1 - level;
 
-            let index_count = width as usize * height as usize;
-            let (index_word_size, index_word_count) = match index_is_nibble {
-                true => (1, (index_count + 1) / 2),
-                false => (4, (index_count + 3) / 4),
-            };
-            let indices_size = index_word_size * index_word_count;
-
-            // TODO: support multiple miplevels in one image
-            assert!(level == 0);
-            assert_eq!(data.len(), palette_size + indices_size);
-            let (palette, indices) = data.split_at(palette_size);
-
-            let mut decoded = Vec::<u8>::with_capacity(palette_entry_size * index_count);
-            for i in 0..index_count {
-                let index = if index_is_nibble {
-                    (indices[i / 2] >> ((1 - (i % 2)) * 4)) & 0xf
-                } else {
-                    indices[i]
-                } as usize;
-                let palette_entry = &palette[index * palette_entry_size..][..palette_entry_size];
-                decoded.extend_from_slice(palette_entry);
+            let levels = decode_paletted_texture(&format, width, height, level_count, data);
+            log_dbg!("Decoded paletted texture ({} level(s))", level_count);
+            for (level, (width, height, decoded)) in levels.into_iter().enumerate() {
+                gl21::TexImage2D(
+                    target,
+                    level as GLint,
+                    format.palette_entry_format as _,
+                    width,
+                    height,
+                    border,
+                    format.palette_entry_format,
+                    format.palette_entry_type,
+                    decoded.as_ptr() as *const _,
+                )
             }
-            assert!(decoded.len() == palette_entry_size * index_count);
-
-            log_dbg!("Decoded paletted texture");
-            gl21::TexImage2D(
-                target,
-                level,
-                palette_entry_format as _,
-                width,
-                height,
-                border,
-                palette_entry_format,
-                palette_entry_type,
-                decoded.as_ptr() as *const _,
-            )
         } else {
             unimplemented!("CompressedTexImage2D internalformat: {:#x}", internalformat);
         }
@@ -1801,6 +2833,158 @@ impl GLES for GLES1OnGL2 {
         gl21::Translatef(fixed_to_float(x), fixed_to_float(y), fixed_to_float(z));
     }
 
+    // OES_draw_texture
+    unsafe fn DrawTexfOES(
+        &mut self,
+        x: GLfloat,
+        y: GLfloat,
+        z: GLfloat,
+        width: GLfloat,
+        height: GLfloat,
+    ) {
+        // This should be invalid use? (TODO)
+        assert!(width > 0.0 && height > 0.0);
+
+        // The rectangle is specified in window co-ordinates, so vertex
+        // processing must be bypassed. Saving these attributes lets us freely
+        // change the enabled capabilities, matrix mode, active texture and
+        // current texture co-ordinates.
+        gl21::PushAttrib(
+            gl21::ENABLE_BIT | gl21::TRANSFORM_BIT | gl21::TEXTURE_BIT | gl21::CURRENT_BIT,
+        );
+        gl21::Disable(gl21::LIGHTING);
+        gl21::Disable(gl21::CLIP_PLANE0);
+
+        let mut viewport: [GLint; 4] = [0; 4];
+        gl21::GetIntegerv(gl21::VIEWPORT, viewport.as_mut_ptr());
+        let [viewport_x, viewport_y, viewport_width, viewport_height] = viewport.map(f64::from);
+        gl21::MatrixMode(gl21::PROJECTION);
+        gl21::PushMatrix();
+        gl21::LoadIdentity();
+        // With these near and far values, z values from 0 to 1 are mapped to
+        // the near and far ends of the depth range, as the extension requires.
+        gl21::Ortho(
+            viewport_x,
+            viewport_x + viewport_width,
+            viewport_y,
+            viewport_y + viewport_height,
+            0.0,
+            -1.0,
+        );
+        gl21::MatrixMode(gl21::MODELVIEW);
+        gl21::PushMatrix();
+        gl21::LoadIdentity();
+
+        // Each texture unit with texturing enabled gets its own texture
+        // co-ordinates, taken from the bound texture's crop rectangle.
+        let mut texture_units = Vec::new();
+        for i in 0..(self.capabilities.max_texture_units as GLenum) {
+            let unit = gl21::TEXTURE0 + i;
+            gl21::ActiveTexture(unit);
+            if gl21::IsEnabled(gl21::TEXTURE_2D) != gl21::TRUE {
+                continue;
+            }
+            let crop_rect = self.get_texture_crop_rect();
+            let mut texture_width: GLint = 0;
+            let mut texture_height: GLint = 0;
+            gl21::GetTexLevelParameteriv(
+                gl21::TEXTURE_2D,
+                0,
+                gl21::TEXTURE_WIDTH,
+                &mut texture_width,
+            );
+            gl21::GetTexLevelParameteriv(
+                gl21::TEXTURE_2D,
+                0,
+                gl21::TEXTURE_HEIGHT,
+                &mut texture_height,
+            );
+            let tex_coords = crop_rect_to_tex_coords(crop_rect, texture_width, texture_height);
+            texture_units.push((unit, tex_coords));
+
+            // The texture matrix doesn't apply either.
+            gl21::MatrixMode(gl21::TEXTURE);
+            gl21::PushMatrix();
+            gl21::LoadIdentity();
+        }
+
+        let z = z.clamp(0.0, 1.0);
+        gl21::Begin(gl21::TRIANGLE_FAN);
+        for (right, top) in [(false, false), (true, false), (true, true), (false, true)] {
+            for &(unit, [s0, t0, s1, t1]) in &texture_units {
+                gl21::MultiTexCoord2f(unit, if right { s1 } else { s0 }, if top { t1 } else { t0 });
+            }
+            gl21::Vertex3f(
+                if right { x + width } else { x },
+                if top { y + height } else { y },
+                z,
+            );
+        }
+        gl21::End();
+
+        for &(unit, _) in &texture_units {
+            gl21::ActiveTexture(unit);
+            gl21::MatrixMode(gl21::TEXTURE);
+            gl21::PopMatrix();
+        }
+        gl21::MatrixMode(gl21::MODELVIEW);
+        gl21::PopMatrix();
+        gl21::MatrixMode(gl21::PROJECTION);
+        gl21::PopMatrix();
+        gl21::PopAttrib();
+    }
+    unsafe fn DrawTexiOES(&mut self, x: GLint, y: GLint, z: GLint, width: GLint, height: GLint) {
+        self.DrawTexfOES(
+            x as GLfloat,
+            y as GLfloat,
+            z as GLfloat,
+            width as GLfloat,
+            height as GLfloat,
+        )
+    }
+    unsafe fn DrawTexsOES(
+        &mut self,
+        x: GLshort,
+        y: GLshort,
+        z: GLshort,
+        width: GLshort,
+        height: GLshort,
+    ) {
+        self.DrawTexfOES(x.into(), y.into(), z.into(), width.into(), height.into())
+    }
+    unsafe fn DrawTexxOES(
+        &mut self,
+        x: GLfixed,
+        y: GLfixed,
+        z: GLfixed,
+        width: GLfixed,
+        height: GLfixed,
+    ) {
+        self.DrawTexfOES(
+            fixed_to_float(x),
+            fixed_to_float(y),
+            fixed_to_float(z),
+            fixed_to_float(width),
+            fixed_to_float(height),
+        )
+    }
+    unsafe fn DrawTexfvOES(&mut self, coords: *const GLfloat) {
+        let [x, y, z, width, height] = std::array::from_fn(|i| coords.add(i).read());
+        self.DrawTexfOES(x, y, z, width, height)
+    }
+    unsafe fn DrawTexivOES(&mut self, coords: *const GLint) {
+        let [x, y, z, width, height] = std::array::from_fn(|i| coords.add(i).read());
+        self.DrawTexiOES(x, y, z, width, height)
+    }
+    unsafe fn DrawTexsvOES(&mut self, coords: *const GLshort) {
+        let [x, y, z, width, height] = std::array::from_fn(|i| coords.add(i).read());
+        self.DrawTexsOES(x, y, z, width, height)
+    }
+    unsafe fn DrawTexxvOES(&mut self, coords: *const GLfixed) {
+        let [x, y, z, width, height] = std::array::from_fn(|i| coords.add(i).read());
+        self.DrawTexxOES(x, y, z, width, height)
+    }
+
     // OES_framebuffer_object -> EXT_framebuffer_object
     unsafe fn GenFramebuffersOES(&mut self, n: GLsizei, framebuffers: *mut GLuint) {
         gl21::GenFramebuffersEXT(n, framebuffers)
@@ -1881,3 +3065,379 @@ impl GLES for GLES1OnGL2 {
         gl21::UnmapBuffer(target)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[should_panic]
+    fn blend_factors_invalid() {
+        check_blend_factors(gl21::CONSTANT_COLOR, gl21::ZERO);
+    }
+
+    #[test]
+    fn light_diffuse_round_trip() {
+        // glLightxv(GL_LIGHT0, GL_DIFFUSE, ...) followed by glGetLightxv(),
+        // with a fake light standing in for the host's state.
+        let diffuse: [GLfixed; 4] = [0x10000, 0x8000, 0x4000, 0x10000];
+        let mut light = [0.0f32; 4];
+        unsafe {
+            LIGHT_PARAMS.setxv(
+                |params| light.copy_from_slice(std::slice::from_raw_parts(params, 4)),
+                |_| unreachable!(),
+                gl21::DIFFUSE,
+                diffuse.as_ptr(),
+            )
+        };
+        assert_eq!(light, [1.0, 0.5, 0.25, 1.0]);
+
+        let mut read_back = [0; 4];
+        unsafe {
+            LIGHT_PARAMS.getxv(
+                |params| params.copy_from_nonoverlapping(light.as_ptr(), 4),
+                |_| unreachable!(),
+                gl21::DIFFUSE,
+                read_back.as_mut_ptr(),
+            )
+        };
+        assert_eq!(read_back, diffuse);
+    }
+
+    #[test]
+    fn material_ambient_and_diffuse_settable() {
+        // glMaterialxv(GL_FRONT_AND_BACK, GL_AMBIENT_AND_DIFFUSE, ...) passes
+        // all four components on, and the two materials it sets can each be
+        // read back. The values read back are checked in the TestApp.
+        let color: [GLfixed; 4] = [0x4000, 0x8000, 0xC000, 0x10000];
+        let mut set = [0.0f32; 4];
+        unsafe {
+            MATERIAL_PARAMS.setxv(
+                |params| set.copy_from_slice(std::slice::from_raw_parts(params, 4)),
+                |_| unreachable!(),
+                gl21::AMBIENT_AND_DIFFUSE,
+                color.as_ptr(),
+            )
+        };
+        assert_eq!(set, [0.25, 0.5, 0.75, 1.0]);
+        assert_gettable_material(gl21::FRONT, gl21::AMBIENT);
+        assert_gettable_material(gl21::FRONT, gl21::DIFFUSE);
+    }
+
+    #[test]
+    #[should_panic]
+    fn material_ambient_and_diffuse_not_gettable() {
+        assert_gettable_material(gl21::FRONT, gl21::AMBIENT_AND_DIFFUSE);
+    }
+
+    #[test]
+    fn fixed_point_quad_from_buffer() {
+        // A quad as it might be stored in a buffer object: interleaved 2D
+        // fixed-point positions and 4-byte colors, with a 12-byte stride.
+        let positions: [[GLfixed; 2]; 4] =
+            [[0, 0], [0x10000, 0], [0, 0x18000], [-0x10000, 0x10000]];
+        let mut vbo = Vec::new();
+        for position in positions {
+            vbo.extend_from_slice(&position[0].to_le_bytes());
+            vbo.extend_from_slice(&position[1].to_le_bytes());
+            vbo.extend_from_slice(&[0xFF; 4]);
+        }
+        let indices: [GLushort; 6] = [1, 2, 3, 3, 2, 1];
+
+        let indices: Vec<usize> = indices.iter().map(|&i| i.into()).collect();
+        let (first, count) = index_range(&indices);
+        assert_eq!((first, count), (1, 3));
+
+        let mut buffer = Vec::new();
+        unsafe {
+            vectors_to_float(
+                gles11::FIXED,
+                vbo[first * 12..].as_ptr().cast(),
+                first,
+                count,
+                2,
+                12,
+                &mut buffer,
+            )
+        };
+        assert_eq!(buffer, [0.0, 0.0, 1.0, 0.0, 0.0, 1.5, -1.0, 1.0]);
+    }
+
+    #[test]
+    fn byte_vertices_for_draw_elements() {
+        // A quad with interleaved 3D byte positions and 2D byte texture
+        // co-ordinates, padded to a 6-byte stride.
+        let vertices: [[GLbyte; 6]; 4] = [
+            [-1, -1, 0, 0, 0, 0],
+            [1, -1, 0, 127, 0, 0],
+            [-1, 1, 0, 0, 127, 0],
+            [1, 1, 0, 127, 127, 0],
+        ];
+        let vertices: Vec<u8> = vertices.iter().flatten().map(|&b| b as u8).collect();
+        let indices: [GLubyte; 3] = [3, 2, 1];
+
+        let indices: Vec<usize> = indices.iter().map(|&i| i.into()).collect();
+        let (first, count) = index_range(&indices);
+        assert_eq!((first, count), (1, 3));
+
+        let mut positions = Vec::new();
+        let mut tex_coords = Vec::new();
+        unsafe {
+            let first_vertex: *const GLvoid = vertices[first * 6..].as_ptr().cast();
+            vectors_to_float(gl21::BYTE, first_vertex, first, count, 3, 6, &mut positions);
+            let first_tex_coord = first_vertex.add(3);
+            vectors_to_float(
+                gl21::BYTE,
+                first_tex_coord,
+                first,
+                count,
+                2,
+                6,
+                &mut tex_coords,
+            );
+        };
+        assert_eq!(
+            positions,
+            [0.0, 0.0, 0.0, 1.0, -1.0, 0.0, -1.0, 1.0, 0.0, 1.0, 1.0, 0.0]
+        );
+        assert_eq!(tex_coords, [0.0, 0.0, 127.0, 0.0, 0.0, 127.0, 127.0, 127.0]);
+    }
+
+    #[test]
+    fn negative_stride_rejected() {
+        assert_eq!(check_stride(-4), Err(gl21::INVALID_VALUE));
+        assert_eq!(check_stride(i32::MIN), Err(gl21::INVALID_VALUE));
+        assert_eq!(check_stride(0), Ok(()));
+        assert_eq!(check_stride(12), Ok(()));
+    }
+
+    #[test]
+    fn array_data_size_limit() {
+        // Three tightly-packed vec3 fixed-point positions
+        assert_eq!(array_data_size(3, 3, 4, 12), Some(36));
+        // The last vector doesn't need padding
+        assert_eq!(array_data_size(3, 3, 4, 16), Some(44));
+        assert_eq!(array_data_size(0, 3, 4, 16), Some(0));
+        // Absurd stride
+        assert_eq!(array_data_size(65536, 3, 4, i32::MAX as usize), None);
+        // Overflow
+        assert_eq!(array_data_size(usize::MAX, 3, 4, 16), None);
+        assert_eq!(array_data_size(2, usize::MAX, 4, 16), None);
+    }
+
+    #[test]
+    fn deindexed_draw_matches_indexed_draw() {
+        // A mesh with fixed-point 2D positions interleaved with float 2D
+        // texture co-ordinates, of which only some vertices are used.
+        let mut vertices = Vec::new();
+        for i in 0..8 {
+            let position: [GLfixed; 2] = [i << 16, -i << 15];
+            let tex_coord: [GLfloat; 2] = [i as GLfloat / 8.0, 1.0];
+            vertices.extend(position.iter().flat_map(|c| c.to_ne_bytes()));
+            vertices.extend(tex_coord.iter().flat_map(|c| c.to_ne_bytes()));
+        }
+        let indices: Vec<usize> = vec![6, 2, 5, 5, 2, 6];
+
+        let (first, count) = index_range(&indices);
+        assert_eq!((first, count), (2, 5));
+        let first_vertex: *const GLvoid = vertices[first * 16..].as_ptr().cast();
+
+        // Indexed: translate the whole range, then look up each index.
+        let mut translated = Vec::new();
+        unsafe {
+            vectors_to_float(
+                gles11::FIXED,
+                first_vertex,
+                first,
+                count,
+                2,
+                16,
+                &mut translated,
+            )
+        };
+        let indexed_positions: Vec<GLfloat> = indices
+            .iter()
+            .flat_map(|&i| [translated[i * 2], translated[i * 2 + 1]])
+            .collect();
+        let indexed_tex_coords: Vec<GLfloat> = indices
+            .iter()
+            .flat_map(|&i| [i as GLfloat / 8.0, 1.0])
+            .collect();
+
+        // De-indexed: gather just the referenced vertices.
+        let mut positions = Vec::new();
+        let mut tex_coords = Vec::new();
+        unsafe {
+            deindex_vectors(
+                gles11::FIXED,
+                true,
+                first_vertex,
+                first,
+                &indices,
+                2,
+                16,
+                &mut positions,
+            );
+            deindex_vectors(
+                gl21::FLOAT,
+                false,
+                first_vertex.add(8),
+                first,
+                &indices,
+                2,
+                16,
+                &mut tex_coords,
+            );
+        }
+        let to_floats = |bytes: Vec<u8>| -> Vec<GLfloat> {
+            bytes
+                .chunks_exact(4)
+                .map(|c| GLfloat::from_ne_bytes(c.try_into().unwrap()))
+                .collect()
+        };
+        assert_eq!(to_floats(positions), indexed_positions);
+        assert_eq!(to_floats(tex_coords), indexed_tex_coords);
+    }
+
+    #[test]
+    fn unsigned_short_colors() {
+        // A triangle with interleaved 2D float positions and RGB unsigned
+        // short colors, with a 14-byte stride.
+        let colors: [[GLushort; 3]; 3] = [[65535, 0, 0], [0, 65535, 0], [0, 0, 32768]];
+        let mut vertices = Vec::new();
+        for color in colors {
+            vertices.extend_from_slice(&[0; 8]);
+            for component in color {
+                vertices.extend_from_slice(&component.to_ne_bytes());
+            }
+        }
+
+        // glDrawArrays(GL_TRIANGLES, 1, 2)
+        let (first, count) = (1, 2);
+        let mut buffer = Vec::new();
+        unsafe {
+            vectors_to_float(
+                gl21::UNSIGNED_SHORT,
+                vertices[first * 14 + 8..].as_ptr().cast(),
+                first,
+                count,
+                3,
+                14,
+                &mut buffer,
+            )
+        };
+        assert_eq!(
+            buffer,
+            [0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 32768.0 / 65535.0]
+        );
+    }
+
+    #[test]
+    fn paletted_texture_mip_levels() {
+        let format = PalettedTextureFormat::get_info(gles11::PALETTE4_RGB8_OES).unwrap();
+        let mut data = Vec::new();
+        // Palette: entry n is (n, n, n)
+        for n in 0..16 {
+            data.extend_from_slice(&[n, n, n]);
+        }
+        // 4x4 level: indices 0..15
+        data.extend_from_slice(&[0x01, 0x23, 0x45, 0x67, 0x89, 0xAB, 0xCD, 0xEF]);
+        // 2x2 level: indices 15, 14, 13, 12
+        data.extend_from_slice(&[0xFE, 0xDC]);
+        // 1x1 level: index 7 (padded)
+        data.extend_from_slice(&[0x70]);
+
+        let levels = decode_paletted_texture(&format, 4, 4, 3, &data);
+        assert_eq!(levels.len(), 3);
+
+        let (width, height, ref pixels) = levels[0];
+        assert_eq!((width, height), (4, 4));
+        assert_eq!(pixels.len(), 4 * 4 * 3);
+        for (i, pixel) in pixels.chunks(3).enumerate() {
+            assert_eq!(pixel, [i as u8; 3]);
+        }
+
+        let (width, height, ref pixels) = levels[1];
+        assert_eq!((width, height), (2, 2));
+        assert_eq!(pixels, &[15, 15, 15, 14, 14, 14, 13, 13, 13, 12, 12, 12]);
+
+        let (width, height, ref pixels) = levels[2];
+        assert_eq!((width, height), (1, 1));
+        assert_eq!(pixels, &[7, 7, 7]);
+    }
+
+    #[test]
+    fn bgra_swizzle() {
+        // Two rows of 3 pixels with an unpack alignment of 8, so the first row
+        // has 4 bytes of padding. The last row is never padded.
+        let pixels: [u8; 28] = [
+            1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 0xAA, 0xAA, 0xAA, 0xAA, //
+            13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24,
+        ];
+        let mut out = vec![0xFF; 100];
+        bgra_to_rgba(&pixels, 3, 16, &mut out);
+        assert_eq!(
+            out,
+            [
+                3, 2, 1, 4, 7, 6, 5, 8, 11, 10, 9, 12, 0xAA, 0xAA, 0xAA, 0xAA, //
+                15, 14, 13, 16, 19, 18, 17, 20, 23, 22, 21, 24,
+            ]
+        );
+    }
+
+    #[test]
+    fn read_pixels_rgb565_conversion() {
+        // 3x2 image read with a pack alignment of 4, so each row of 6 bytes
+        // is padded to 8 bytes, except for the last row.
+        #[rustfmt::skip]
+        let rgba8: [u8; 24] = [
+            0xFF, 0x00, 0x00, 0xFF, 0x00, 0xFF, 0x00, 0xFF, 0x00, 0x00, 0xFF, 0xFF,
+            0xFF, 0xFF, 0xFF, 0xFF, 0x00, 0x00, 0x00, 0xFF, 0x84, 0x82, 0x08, 0x00,
+        ];
+        let mut out = [0xAA; 14];
+        rgba8_to_rgb565(&rgba8, 3, 8, &mut out);
+        let pixel = |i: usize| u16::from_ne_bytes([out[i * 2], out[i * 2 + 1]]);
+        assert_eq!([pixel(0), pixel(1), pixel(2)], [0xF800, 0x07E0, 0x001F]);
+        assert_eq!(&out[6..8], &[0xAA, 0xAA]);
+        assert_eq!([pixel(4), pixel(5), pixel(6)], [0xFFFF, 0x0000, 0x8401]);
+    }
+
+    #[test]
+    fn draw_texture_crop_rect() {
+        // A 64x32 sprite in the middle of a 256x128 texture atlas
+        let tex_coords = crop_rect_to_tex_coords([96, 48, 64, 32], 256, 128);
+        assert_eq!(tex_coords, [0.375, 0.375, 0.625, 0.625]);
+
+        // A negative height flips the image vertically
+        let tex_coords = crop_rect_to_tex_coords([0, 128, 256, -128], 256, 128);
+        assert_eq!(tex_coords, [0.0, 1.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn point_sizes_for_draw_arrays() {
+        // Interleaved fixed-point 2D positions and point sizes, as would be
+        // passed to glVertexPointer and glPointSizePointerOES before calling
+        // glDrawArrays(GL_POINTS, 1, 2).
+        let vertices: [[GLfixed; 3]; 3] =
+            [[0, 0, 0x10000], [0x10000, 0, 0x40000], [0, 0x10000, 0x8000]];
+        let stride = std::mem::size_of::<[GLfixed; 3]>();
+        let first = 1;
+        let sizes = unsafe {
+            decode_point_sizes(
+                gles11::FIXED,
+                stride,
+                vertices
+                    .as_ptr()
+                    .cast::<u8>()
+                    .add(first * stride + 8)
+                    .cast(),
+                2,
+            )
+        };
+        assert_eq!(sizes, [4.0, 0.5]);
+
+        let float_sizes: [GLfloat; 2] = [1.5, 8.0];
+        let sizes = unsafe { decode_point_sizes(gl21::FLOAT, 4, float_sizes.as_ptr().cast(), 2) };
+        assert_eq!(sizes, [1.5, 8.0]);
+    }
+}
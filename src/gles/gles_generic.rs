@@ -27,7 +27,10 @@ pub trait GLES {
     /// Construct a new context. This might fail if the host OS doesn't have a
     /// compatible driver, for example.
     #[allow(clippy::new_ret_no_self)]
-    fn new(window: &mut crate::window::Window) -> Result<Self, String>
+    fn new(
+        window: &mut crate::window::Window,
+        options: &crate::options::Options,
+    ) -> Result<Self, String>
     where
         Self: Sized;
 
@@ -48,6 +51,7 @@ pub trait GLES {
     unsafe fn EnableClientState(&mut self, array: GLenum);
     unsafe fn DisableClientState(&mut self, array: GLenum);
     unsafe fn GetBooleanv(&mut self, pname: GLenum, params: *mut GLboolean);
+    unsafe fn GetFixedv(&mut self, pname: GLenum, params: *mut GLfixed);
     unsafe fn GetFloatv(&mut self, pname: GLenum, params: *mut GLfloat);
     unsafe fn GetIntegerv(&mut self, pname: GLenum, params: *mut GLint);
     unsafe fn GetTexEnviv(&mut self, target: GLenum, pname: GLenum, params: *mut GLint);
@@ -59,10 +63,23 @@ pub trait GLES {
     #[allow(dead_code)]
     unsafe fn GetString(&mut self, name: GLenum) -> *const GLubyte;
 
+    // Desktop OpenGL attribute stack. This is not part of OpenGL ES 1.1, but
+    // some apps call it anyway. OpenGL 2.1 has it natively, other
+    // implementations may only partially emulate it.
+    unsafe fn PushAttrib(&mut self, mask: GLbitfield);
+    unsafe fn PopAttrib(&mut self);
+
     // Other state manipulation
     unsafe fn AlphaFunc(&mut self, func: GLenum, ref_: GLclampf);
     unsafe fn AlphaFuncx(&mut self, func: GLenum, ref_: GLclampx);
     unsafe fn BlendFunc(&mut self, sfactor: GLenum, dfactor: GLenum);
+    unsafe fn BlendFuncSeparateOES(
+        &mut self,
+        src_rgb: GLenum,
+        dst_rgb: GLenum,
+        src_alpha: GLenum,
+        dst_alpha: GLenum,
+    );
     unsafe fn ColorMask(
         &mut self,
         red: GLboolean,
@@ -112,6 +129,10 @@ pub trait GLES {
     unsafe fn Materialx(&mut self, face: GLenum, pname: GLenum, param: GLfixed);
     unsafe fn Materialfv(&mut self, face: GLenum, pname: GLenum, params: *const GLfloat);
     unsafe fn Materialxv(&mut self, face: GLenum, pname: GLenum, params: *const GLfixed);
+    unsafe fn GetLightfv(&mut self, light: GLenum, pname: GLenum, params: *mut GLfloat);
+    unsafe fn GetLightxv(&mut self, light: GLenum, pname: GLenum, params: *mut GLfixed);
+    unsafe fn GetMaterialfv(&mut self, face: GLenum, pname: GLenum, params: *mut GLfloat);
+    unsafe fn GetMaterialxv(&mut self, face: GLenum, pname: GLenum, params: *mut GLfixed);
 
     // Buffers
     unsafe fn GenBuffers(&mut self, n: GLsizei, buffers: *mut GLuint);
@@ -162,6 +183,12 @@ pub trait GLES {
         stride: GLsizei,
         pointer: *const GLvoid,
     );
+    unsafe fn PointSizePointerOES(
+        &mut self,
+        type_: GLenum,
+        stride: GLsizei,
+        pointer: *const GLvoid,
+    );
 
     // Drawing
     unsafe fn DrawArrays(&mut self, mode: GLenum, first: GLint, count: GLsizei);
@@ -216,6 +243,8 @@ pub trait GLES {
     unsafe fn TexParameteriv(&mut self, target: GLenum, pname: GLenum, params: *const GLint);
     unsafe fn TexParameterfv(&mut self, target: GLenum, pname: GLenum, params: *const GLfloat);
     unsafe fn TexParameterxv(&mut self, target: GLenum, pname: GLenum, params: *const GLfixed);
+    unsafe fn GetTexParameteriv(&mut self, target: GLenum, pname: GLenum, params: *mut GLint);
+    unsafe fn GetTexParameterfv(&mut self, target: GLenum, pname: GLenum, params: *mut GLfloat);
     unsafe fn TexImage2D(
         &mut self,
         target: GLenum,
@@ -332,6 +361,37 @@ pub trait GLES {
     unsafe fn Translatef(&mut self, x: GLfloat, y: GLfloat, z: GLfloat);
     unsafe fn Translatex(&mut self, x: GLfixed, y: GLfixed, z: GLfixed);
 
+    // OES_draw_texture
+    unsafe fn DrawTexfOES(
+        &mut self,
+        x: GLfloat,
+        y: GLfloat,
+        z: GLfloat,
+        width: GLfloat,
+        height: GLfloat,
+    );
+    unsafe fn DrawTexiOES(&mut self, x: GLint, y: GLint, z: GLint, width: GLint, height: GLint);
+    unsafe fn DrawTexsOES(
+        &mut self,
+        x: GLshort,
+        y: GLshort,
+        z: GLshort,
+        width: GLshort,
+        height: GLshort,
+    );
+    unsafe fn DrawTexxOES(
+        &mut self,
+        x: GLfixed,
+        y: GLfixed,
+        z: GLfixed,
+        width: GLfixed,
+        height: GLfixed,
+    );
+    unsafe fn DrawTexfvOES(&mut self, coords: *const GLfloat);
+    unsafe fn DrawTexivOES(&mut self, coords: *const GLint);
+    unsafe fn DrawTexsvOES(&mut self, coords: *const GLshort);
+    unsafe fn DrawTexxvOES(&mut self, coords: *const GLfixed);
+
     // OES_framebuffer_object (incomplete)
     unsafe fn GenFramebuffersOES(&mut self, n: GLsizei, framebuffers: *mut GLuint);
     unsafe fn GenRenderbuffersOES(&mut self, n: GLsizei, renderbuffers: *mut GLuint);
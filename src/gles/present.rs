@@ -9,7 +9,8 @@
 use super::gles11_raw as gles11; // constants and types only
 use super::GLES;
 use crate::matrix::Matrix;
-use std::time::{Duration, Instant};
+use crate::paths;
+use std::time::{Duration, Instant, SystemTime};
 
 pub struct FpsCounter {
     time: std::time::Instant,
@@ -107,3 +108,104 @@ pub unsafe fn present_frame(
         gles.DrawArrays(gles11::TRIANGLES, 0, 6);
     }
 }
+
+/// Read back the part of the window that displays the app content and save it
+/// to a PNG file in the screenshots directory. The resolution is that of the
+/// window's framebuffer, so it will be affected by the scale hack.
+///
+/// The provided context must be current, the default framebuffer (0) must be
+/// bound, and this must be called after [present_frame] but before the window
+/// is swapped.
+pub unsafe fn save_screenshot(gles: &mut dyn GLES, viewport: (u32, u32, u32, u32)) {
+    let (x, y, width, height) = viewport;
+    let mut pixels = vec![0u8; width as usize * height as usize * 4];
+    gles.ReadPixels(
+        x as _,
+        y as _,
+        width as _,
+        height as _,
+        gles11::RGBA,
+        gles11::UNSIGNED_BYTE,
+        pixels.as_mut_ptr().cast(),
+    );
+    // The alpha channel isn't meaningful for the window.
+    for pixel in pixels.chunks_exact_mut(4) {
+        pixel[3] = 255;
+    }
+
+    let png = match crate::image::encode_png(&pixels, (width, height), true) {
+        Ok(png) => png,
+        Err(e) => {
+            log!("Warning: Couldn't encode screenshot: {}", e);
+            return;
+        }
+    };
+
+    let dir = paths::user_data_base_path().join(paths::SCREENSHOTS_DIR);
+    if let Err(e) = std::fs::create_dir_all(&dir) {
+        log!("Warning: Couldn't create {}: {}", dir.display(), e);
+        return;
+    }
+    let path = dir.join(screenshot_file_name(SystemTime::now()));
+    match std::fs::write(&path, png) {
+        Ok(()) => echo!("Saved screenshot: {}", path.display()),
+        Err(e) => log!("Warning: Couldn't save {}: {}", path.display(), e),
+    }
+}
+
+/// Produce a file name like `touchHLE_screenshot_2008-07-11_12-34-56.789.png`
+/// from a (UTC) timestamp.
+fn screenshot_file_name(time: SystemTime) -> String {
+    let since_epoch = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let (days, secs_of_day) = (secs / 86400, secs % 86400);
+
+    // Convert days since the Unix epoch to a Gregorian calendar date.
+    // This is Howard Hinnant's civil_from_days algorithm.
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "touchHLE_screenshot_{:04}-{:02}-{:02}_{:02}-{:02}-{:02}.{:03}.png",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        (secs_of_day / 60) % 60,
+        secs_of_day % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn screenshot_file_names() {
+        let time = SystemTime::UNIX_EPOCH + Duration::from_millis(1215779696789);
+        assert_eq!(
+            screenshot_file_name(time),
+            "touchHLE_screenshot_2008-07-11_12-34-56.789.png"
+        );
+        let time = SystemTime::UNIX_EPOCH + Duration::from_secs(951782400);
+        assert_eq!(
+            screenshot_file_name(time),
+            "touchHLE_screenshot_2000-02-29_00-00-00.000.png"
+        );
+    }
+}
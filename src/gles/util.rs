@@ -6,7 +6,7 @@
 //! Shared utilities.
 
 use super::gles11_raw as gles11; // constants only
-use super::gles11_raw::types::{GLenum, GLfixed, GLfloat, GLint, GLsizei};
+use super::gles11_raw::types::{GLbitfield, GLenum, GLfixed, GLfloat, GLint, GLsizei};
 use super::GLES;
 
 /// Convert a fixed-point scalar to a floating-point scalar.
@@ -17,6 +17,13 @@ pub fn fixed_to_float(fixed: GLfixed) -> GLfloat {
     ((fixed as f64) / ((1 << 16) as f64)) as f32
 }
 
+/// Convert a floating-point scalar to a fixed-point scalar.
+///
+/// This is the inverse of [fixed_to_float]. Out-of-range values saturate.
+pub fn float_to_fixed(float: GLfloat) -> GLfixed {
+    ((float as f64) * ((1 << 16) as f64)) as GLfixed
+}
+
 /// Convert a fixed-point 4-by-4 matrix to floating-point.
 pub unsafe fn matrix_fixed_to_float(m: *const GLfixed) -> [GLfloat; 16] {
     let mut matrix = [0f32; 16];
@@ -26,6 +33,87 @@ pub unsafe fn matrix_fixed_to_float(m: *const GLfixed) -> [GLfloat; 16] {
     matrix
 }
 
+/// `GL_ENABLE_BIT` from desktop OpenGL, which OpenGL ES 1.1 lacks.
+pub const ENABLE_BIT: GLbitfield = 0x2000;
+
+/// Capabilities saved by [AttribStack] for [ENABLE_BIT]. Only the active
+/// texture unit's `GL_TEXTURE_2D` is saved.
+const ENABLE_BIT_CAPS: &[GLenum] = &[
+    gles11::ALPHA_TEST,
+    gles11::BLEND,
+    gles11::COLOR_LOGIC_OP,
+    gles11::COLOR_MATERIAL,
+    gles11::CULL_FACE,
+    gles11::DEPTH_TEST,
+    gles11::DITHER,
+    gles11::FOG,
+    gles11::LIGHT0,
+    gles11::LIGHT1,
+    gles11::LIGHT2,
+    gles11::LIGHT3,
+    gles11::LIGHT4,
+    gles11::LIGHT5,
+    gles11::LIGHT6,
+    gles11::LIGHT7,
+    gles11::LIGHTING,
+    gles11::LINE_SMOOTH,
+    gles11::MULTISAMPLE,
+    gles11::NORMALIZE,
+    gles11::POINT_SMOOTH,
+    gles11::POLYGON_OFFSET_FILL,
+    gles11::RESCALE_NORMAL,
+    gles11::SAMPLE_ALPHA_TO_COVERAGE,
+    gles11::SAMPLE_ALPHA_TO_ONE,
+    gles11::SAMPLE_COVERAGE,
+    gles11::SCISSOR_TEST,
+    gles11::STENCIL_TEST,
+    gles11::TEXTURE_2D,
+];
+
+/// Emulation of desktop OpenGL's attribute stack (`glPushAttrib()` and
+/// `glPopAttrib()`), for implementations that don't have one. Only
+/// [ENABLE_BIT] is supported so far, other state is not saved.
+#[derive(Default)]
+pub struct AttribStack(Vec<Vec<(GLenum, bool)>>);
+impl AttribStack {
+    /// Smallest `GL_MAX_ATTRIB_STACK_DEPTH` allowed by OpenGL.
+    pub const MAX_DEPTH: usize = 16;
+
+    /// Save the state selected by `mask`, using `is_enabled` to query it.
+    pub fn push(&mut self, mask: GLbitfield, mut is_enabled: impl FnMut(GLenum) -> bool) {
+        if self.0.len() == Self::MAX_DEPTH {
+            log!("Warning: glPushAttrib() stack overflow, ignoring");
+            return;
+        }
+        if mask & !ENABLE_BIT != 0 {
+            log!(
+                "TODO: glPushAttrib() of mask {:#x}, only GL_ENABLE_BIT is saved",
+                mask
+            );
+        }
+        let saved = if mask & ENABLE_BIT != 0 {
+            ENABLE_BIT_CAPS
+                .iter()
+                .map(|&cap| (cap, is_enabled(cap)))
+                .collect()
+        } else {
+            Vec::new()
+        };
+        self.0.push(saved);
+    }
+
+    /// Restore the most recently saved state, using `set_enabled` to apply it.
+    pub fn pop(&mut self, mut set_enabled: impl FnMut(GLenum, bool)) {
+        let Some(saved) = self.0.pop() else {
+            log!("Warning: glPopAttrib() stack underflow, ignoring");
+            return;
+        };
+        for (cap, enabled) in saved {
+            set_enabled(cap, enabled);
+        }
+    }
+}
+
 /// Type of a parameter, used in [ParamTable].
 #[derive(Copy, Clone, Eq, PartialEq)]
 pub enum ParamType {
@@ -35,6 +123,9 @@ pub enum ParamType {
     Float,
     /// `GLint`
     Int,
+    /// `GLenum`, accessed as a `GLint`. Unlike [ParamType::Int], this isn't
+    /// converted when read as fixed-point.
+    Enum,
     /// Placeholder type for things like colors which are floating-point
     /// but don't have the usual conversion behavior to/from integers etc.
     /// [ParamTable] will accept it for floating-point inputs only.
@@ -134,6 +225,108 @@ impl ParamTable {
             _ => setiv(params),
         }
     }
+
+    /// Implements a fixed-point vector (`xv`) getter by calling a provided
+    /// floating-point vector (`fv`) or integer vector (`iv`) getter as
+    /// appropriate.
+    ///
+    /// This will panic if the name is not recognized.
+    pub unsafe fn getxv<FFV, FIV>(
+        &self,
+        getfv: FFV,
+        getiv: FIV,
+        pname: GLenum,
+        params: *mut GLfixed,
+    ) where
+        FFV: FnOnce(*mut GLfloat),
+        FIV: FnOnce(*mut GLint),
+    {
+        let (type_, count) = self.get_type_info(pname);
+        let count = usize::from(count);
+        match type_ {
+            ParamType::Float | ParamType::FloatSpecial => {
+                let mut params_float = [0.0; 16]; // probably the max?
+                getfv(params_float.as_mut_ptr());
+                for (i, &param_float) in params_float[..count].iter().enumerate() {
+                    params.add(i).write(float_to_fixed(param_float))
+                }
+            }
+            // Enums aren't numbers, so they're returned as-is, like in the
+            // setters.
+            ParamType::Enum => getiv(params),
+            // Unlike the setters, integers (and booleans) are converted to
+            // the fixed-point value representing the same number. Values that
+            // are too big for fixed-point saturate.
+            _ => {
+                let mut params_int = [0; 16]; // probably the max?
+                getiv(params_int.as_mut_ptr());
+                for (i, &param_int) in params_int[..count].iter().enumerate() {
+                    params.add(i).write(param_int.saturating_mul(1 << 16))
+                }
+            }
+        }
+    }
+}
+
+/// Check whether an extension is listed in an OpenGL extension string
+/// (`GL_EXTENSIONS`).
+pub fn has_extension(extensions: &str, name: &str) -> bool {
+    extensions.split(' ').any(|extension| extension == name)
+}
+
+/// Features and limits of the host's OpenGL (ES) driver that touchHLE cares
+/// about. These are detected once when a context is created, so that they
+/// don't have to be queried again on every call that needs them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GLCapabilities {
+    /// `GL_EXT_bgra` or `GL_EXT_texture_format_BGRA8888`: BGRA pixel data can
+    /// be used for textures.
+    pub bgra: bool,
+    /// `GL_IMG_texture_compression_pvrtc`.
+    pub pvrtc: bool,
+    /// `GL_EXT_texture_filter_anisotropic`.
+    pub anisotropic_filtering: bool,
+    /// `GL_MAX_TEXTURE_SIZE`, or 0 if not queried.
+    pub max_texture_size: GLint,
+    /// `GL_MAX_TEXTURE_UNITS`, or 0 if not queried.
+    pub max_texture_units: GLint,
+    /// `GL_MAX_TEXTURE_MAX_ANISOTROPY_EXT`, or 1.0 if anisotropic filtering
+    /// isn't supported or this wasn't queried.
+    pub max_anisotropy: GLfloat,
+}
+impl GLCapabilities {
+    /// Detect the features listed in an OpenGL extension string
+    /// (`GL_EXTENSIONS`). The limits must be filled in by the caller.
+    pub fn from_extensions(extensions: &str) -> Self {
+        GLCapabilities {
+            bgra: has_extension(extensions, "GL_EXT_bgra")
+                || has_extension(extensions, "GL_EXT_texture_format_BGRA8888"),
+            pvrtc: has_extension(extensions, "GL_IMG_texture_compression_pvrtc"),
+            anisotropic_filtering: has_extension(extensions, "GL_EXT_texture_filter_anisotropic"),
+            max_texture_size: 0,
+            max_texture_units: 0,
+            max_anisotropy: 1.0,
+        }
+    }
+}
+
+/// Check whether `internalformat` is one of the
+/// `IMG_texture_compression_pvrtc` formats.
+pub fn is_pvrtc_format(internalformat: GLenum) -> bool {
+    matches!(
+        internalformat,
+        gles11::COMPRESSED_RGB_PVRTC_4BPPV1_IMG
+            | gles11::COMPRESSED_RGBA_PVRTC_4BPPV1_IMG
+            | gles11::COMPRESSED_RGB_PVRTC_2BPPV1_IMG
+            | gles11::COMPRESSED_RGBA_PVRTC_2BPPV1_IMG
+    )
+}
+
+/// Decide whether PVRTC textures can be passed to the host's
+/// `glCompressedTexImage2D` as-is, rather than decoded on the CPU with
+/// [try_decode_pvrtc].
+pub fn use_native_pvrtc(capabilities: &GLCapabilities, force_cpu_decode: bool) -> bool {
+    !force_cpu_decode && capabilities.pvrtc
 }
 
 /// Helper for implementing `glCompressedTexImage2D`: if `internalformat` is
@@ -181,6 +374,53 @@ pub fn try_decode_pvrtc(
     true
 }
 
+/// Helper for implementing `glCompressedTexImage2D`: if `internalformat` is
+/// `GL_ETC1_RGB8_OES`, decode it and call `glTexImage2D`. Returns `true` if
+/// this is done.
+///
+/// Note that this panics rather than create GL errors for invalid use (TODO?)
+#[allow(clippy::too_many_arguments)]
+pub fn try_decode_etc1(
+    gles: &mut dyn GLES,
+    target: GLenum,
+    level: GLint,
+    internalformat: GLenum,
+    width: GLsizei,
+    height: GLsizei,
+    border: GLint,
+    etc1_data: &[u8],
+) -> bool {
+    if internalformat != gles11::ETC1_RGB8_OES {
+        return false;
+    }
+
+    assert!(border == 0);
+    let pixels = crate::image::decode_etc1(
+        etc1_data,
+        width.try_into().unwrap(),
+        height.try_into().unwrap(),
+    );
+    unsafe {
+        // RGB8 rows aren't necessarily a multiple of 4 bytes long.
+        let mut old_alignment = 0;
+        gles.GetIntegerv(gles11::UNPACK_ALIGNMENT, &mut old_alignment);
+        gles.PixelStorei(gles11::UNPACK_ALIGNMENT, 1);
+        gles.TexImage2D(
+            target,
+            level,
+            gles11::RGB as _,
+            width,
+            height,
+            border,
+            gles11::RGB,
+            gles11::UNSIGNED_BYTE,
+            pixels.as_ptr() as *const _,
+        );
+        gles.PixelStorei(gles11::UNPACK_ALIGNMENT, old_alignment);
+    };
+    true
+}
+
 pub struct PalettedTextureFormat {
     /// * `true` for 4-bit (nibble) index, 16-color palette.
     /// * `false` for 8-bit (byte) index, 256-color palette.
@@ -250,3 +490,148 @@ impl PalettedTextureFormat {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn attrib_stack_restores_caps() {
+        let mut stack = AttribStack::default();
+        let mut enabled = HashSet::from([gles11::BLEND, gles11::TEXTURE_2D]);
+
+        stack.push(ENABLE_BIT, |cap| enabled.contains(&cap));
+        enabled.remove(&gles11::BLEND);
+        enabled.insert(gles11::DEPTH_TEST);
+        // Nested push that saves nothing
+        stack.push(0, |_| unreachable!());
+        enabled.insert(gles11::FOG);
+        stack.pop(|_, _| unreachable!());
+        assert!(enabled.contains(&gles11::FOG));
+
+        stack.pop(|cap, value| {
+            if value {
+                enabled.insert(cap);
+            } else {
+                enabled.remove(&cap);
+            }
+        });
+        assert_eq!(enabled, HashSet::from([gles11::BLEND, gles11::TEXTURE_2D]));
+
+        // Underflow is ignored
+        stack.pop(|_, _| unreachable!());
+    }
+
+    #[test]
+    fn attrib_stack_overflow() {
+        let mut stack = AttribStack::default();
+        for _ in 0..AttribStack::MAX_DEPTH {
+            stack.push(ENABLE_BIT, |_| true);
+        }
+        stack.push(ENABLE_BIT, |_| unreachable!());
+        let mut pops = 0;
+        while !stack.0.is_empty() {
+            stack.pop(|_, _| ());
+            pops += 1;
+        }
+        assert_eq!(pops, AttribStack::MAX_DEPTH);
+    }
+
+    #[test]
+    fn capabilities_from_extensions() {
+        let extensions = "GL_ARB_multitexture GL_EXT_bgra GL_EXT_texture_filter_anisotropic ";
+        assert_eq!(
+            GLCapabilities::from_extensions(extensions),
+            GLCapabilities {
+                bgra: true,
+                pvrtc: false,
+                anisotropic_filtering: true,
+                max_texture_size: 0,
+                max_texture_units: 0,
+                max_anisotropy: 1.0,
+            }
+        );
+
+        let extensions = "GL_IMG_texture_compression_pvrtc GL_EXT_texture_format_BGRA8888";
+        let capabilities = GLCapabilities::from_extensions(extensions);
+        assert!(capabilities.bgra);
+        assert!(capabilities.pvrtc);
+        assert!(!capabilities.anisotropic_filtering);
+
+        let capabilities = GLCapabilities::from_extensions("GL_EXT_bgra_lookalike");
+        assert!(!capabilities.bgra);
+    }
+
+    #[test]
+    fn pvrtc_upload_path() {
+        let desktop = "GL_ARB_multitexture GL_EXT_bgra GL_EXT_texture_compression_s3tc";
+        let powervr = "GL_OES_rgb8_rgba8 GL_IMG_texture_compression_pvrtc GL_OES_point_sprite";
+        let lookalike = "GL_IMG_texture_compression_pvrtc2";
+        let [desktop, powervr, lookalike, empty] =
+            [desktop, powervr, lookalike, ""].map(GLCapabilities::from_extensions);
+        assert!(!use_native_pvrtc(&desktop, false));
+        assert!(use_native_pvrtc(&powervr, false));
+        assert!(!use_native_pvrtc(&powervr, true));
+        assert!(!use_native_pvrtc(&lookalike, false));
+        assert!(!use_native_pvrtc(&empty, false));
+
+        assert!(is_pvrtc_format(gles11::COMPRESSED_RGBA_PVRTC_2BPPV1_IMG));
+        assert!(!is_pvrtc_format(gles11::PALETTE4_RGB8_OES));
+    }
+
+    #[test]
+    fn getxv_conversions() {
+        let table = ParamTable(&[
+            (gles11::MODELVIEW_MATRIX, ParamType::Float, 16),
+            (gles11::MATRIX_MODE, ParamType::Enum, 1),
+            (gles11::MAX_TEXTURE_SIZE, ParamType::Int, 1),
+        ]);
+
+        let mut matrix = [0.0; 16];
+        for (i, cell) in matrix.iter_mut().enumerate() {
+            *cell = (i as f32) * 0.5 - 2.0;
+        }
+        let mut fixed = [0; 16];
+        unsafe {
+            table.getxv(
+                |params| params.copy_from_nonoverlapping(matrix.as_ptr(), 16),
+                |_| unreachable!(),
+                gles11::MODELVIEW_MATRIX,
+                fixed.as_mut_ptr(),
+            )
+        };
+        for (i, &cell) in fixed.iter().enumerate() {
+            assert_eq!(cell, (i as GLfixed) * 0x8000 - 0x20000);
+            assert_eq!(fixed_to_float(cell), matrix[i]);
+        }
+
+        let mut fixed = [0; 1];
+        unsafe {
+            table.getxv(
+                |_| unreachable!(),
+                |params| params.write(gles11::MODELVIEW as GLint),
+                gles11::MATRIX_MODE,
+                fixed.as_mut_ptr(),
+            )
+        };
+        assert_eq!(fixed[0], gles11::MODELVIEW as GLfixed);
+
+        for (int, expected) in [
+            (1024, 1024 << 16),
+            (-3, -3 << 16),
+            (32768, GLfixed::MAX),
+            (-40000, GLfixed::MIN),
+        ] {
+            unsafe {
+                table.getxv(
+                    |_| unreachable!(),
+                    |params| params.write(int),
+                    gles11::MAX_TEXTURE_SIZE,
+                    fixed.as_mut_ptr(),
+                )
+            };
+            assert_eq!(fixed[0], expected);
+        }
+    }
+}
@@ -0,0 +1,218 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! A minimal HTTP client, used to implement `NSURLConnection`.
+//!
+//! Only GET requests to plain `http://` URLs are supported. Requests are made
+//! with HTTP/1.0, so the server closes the connection after sending the
+//! response and the body is never chunked.
+
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct HttpUrl {
+    pub host: String,
+    pub port: u16,
+    /// The path and query, always starting with `/`.
+    pub path: String,
+}
+
+#[derive(Debug)]
+pub struct Response {
+    pub status_code: u16,
+    pub body: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    UnsupportedUrl,
+    HostNotFound,
+    Io(std::io::Error),
+    BadResponse,
+}
+
+/// Split an `http://` URL into the parts needed to make a request.
+pub fn parse_url(url: &str) -> Result<HttpUrl, Error> {
+    let rest = url
+        .get(..7)
+        .filter(|scheme| scheme.eq_ignore_ascii_case("http://"))
+        .map(|_| &url[7..])
+        .ok_or(Error::UnsupportedUrl)?;
+    // The fragment is never sent to the server.
+    let rest = rest.split('#').next().unwrap();
+    let (authority, path) = match rest.find(['/', '?']) {
+        Some(idx) => rest.split_at(idx),
+        None => (rest, ""),
+    };
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) => (host, port.parse().map_err(|_| Error::UnsupportedUrl)?),
+        None => (authority, 80),
+    };
+    // TODO: IPv6 literals and user info
+    if host.is_empty() || host.contains(['@', '[']) {
+        return Err(Error::UnsupportedUrl);
+    }
+    let path = if path.starts_with('/') {
+        path.to_string()
+    } else {
+        format!("/{}", path)
+    };
+    Ok(HttpUrl {
+        host: host.to_string(),
+        port,
+        path,
+    })
+}
+
+/// Make a GET request. This blocks until the whole response has been received,
+/// so it should be called on a separate host thread.
+pub fn get(url: &HttpUrl, timeout: Duration) -> Result<Response, Error> {
+    let addr = (url.host.as_str(), url.port)
+        .to_socket_addrs()
+        .map_err(|_| Error::HostNotFound)?
+        .next()
+        .ok_or(Error::HostNotFound)?;
+    let mut stream = TcpStream::connect_timeout(&addr, timeout).map_err(Error::Io)?;
+    stream.set_read_timeout(Some(timeout)).map_err(Error::Io)?;
+    stream.set_write_timeout(Some(timeout)).map_err(Error::Io)?;
+
+    let host = if url.port == 80 {
+        url.host.clone()
+    } else {
+        format!("{}:{}", url.host, url.port)
+    };
+    let request = format!(
+        "GET {} HTTP/1.0\r\nHost: {}\r\nUser-Agent: touchHLE\r\nConnection: close\r\n\r\n",
+        url.path, host
+    );
+    stream.write_all(request.as_bytes()).map_err(Error::Io)?;
+
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).map_err(Error::Io)?;
+    parse_response(&response)
+}
+
+fn parse_response(response: &[u8]) -> Result<Response, Error> {
+    let header_end = response
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .ok_or(Error::BadResponse)?;
+    let header = std::str::from_utf8(&response[..header_end]).map_err(|_| Error::BadResponse)?;
+    let mut body = &response[header_end + 4..];
+
+    let mut lines = header.split("\r\n");
+    let status_line = lines.next().unwrap();
+    let status_code = status_line
+        .strip_prefix("HTTP/1.")
+        .and_then(|rest| rest.split(' ').nth(1))
+        .and_then(|code| code.parse().ok())
+        .ok_or(Error::BadResponse)?;
+
+    for line in lines {
+        let Some((name, value)) = line.split_once(':') else {
+            return Err(Error::BadResponse);
+        };
+        if name.eq_ignore_ascii_case("Content-Length") {
+            let length: usize = value.trim().parse().map_err(|_| Error::BadResponse)?;
+            body = body.get(..length).ok_or(Error::BadResponse)?;
+        }
+    }
+
+    Ok(Response {
+        status_code,
+        body: body.to_vec(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{Ipv4Addr, TcpListener};
+
+    #[test]
+    fn url_parsing() {
+        assert_eq!(
+            parse_url("http://example.com").unwrap(),
+            HttpUrl {
+                host: "example.com".to_string(),
+                port: 80,
+                path: "/".to_string(),
+            }
+        );
+        assert_eq!(
+            parse_url("HTTP://127.0.0.1:8080/scores?top=10#mine").unwrap(),
+            HttpUrl {
+                host: "127.0.0.1".to_string(),
+                port: 8080,
+                path: "/scores?top=10".to_string(),
+            }
+        );
+        assert!(matches!(
+            parse_url("example.com/"),
+            Err(Error::UnsupportedUrl)
+        ));
+        assert_eq!(parse_url("http://example.com?a=b").unwrap().path, "/?a=b");
+        assert!(matches!(
+            parse_url("https://example.com/"),
+            Err(Error::UnsupportedUrl)
+        ));
+        assert!(matches!(
+            parse_url("http://example.com:port/"),
+            Err(Error::UnsupportedUrl)
+        ));
+    }
+
+    #[test]
+    fn response_parsing() {
+        let response =
+            parse_response(b"HTTP/1.1 404 Not Found\r\nContent-Length: 4\r\n\r\nnope!!").unwrap();
+        assert_eq!(response.status_code, 404);
+        assert_eq!(response.body, b"nope");
+
+        let response = parse_response(b"HTTP/1.0 200 OK\r\n\r\n").unwrap();
+        assert_eq!(response.status_code, 200);
+        assert!(response.body.is_empty());
+
+        assert!(matches!(
+            parse_response(b"HTTP/1.0 200 OK\r\nContent-Length: 4\r\n\r\n"),
+            Err(Error::BadResponse)
+        ));
+        assert!(matches!(
+            parse_response(b"<html></html>"),
+            Err(Error::BadResponse)
+        ));
+    }
+
+    #[test]
+    fn get_from_local_server() {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0; 256];
+            while !request.ends_with(b"\r\n\r\n") {
+                let read = stream.read(&mut buf).unwrap();
+                assert_ne!(read, 0);
+                request.extend_from_slice(&buf[..read]);
+            }
+            stream
+                .write_all(b"HTTP/1.0 200 OK\r\nContent-Type: text/plain\r\n\r\nhello")
+                .unwrap();
+            String::from_utf8(request).unwrap()
+        });
+
+        let url = parse_url(&format!("http://127.0.0.1:{}/hello", port)).unwrap();
+        let response = get(&url, Duration::from_secs(10)).unwrap();
+        assert_eq!(response.status_code, 200);
+        assert_eq!(response.body, b"hello");
+
+        let request = server.join().unwrap();
+        assert!(request.starts_with("GET /hello HTTP/1.0\r\n"));
+        assert!(request.contains(&format!("\r\nHost: 127.0.0.1:{}\r\n", port)));
+    }
+}
@@ -3,16 +3,22 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/.
  */
-//! Image decoding. Currently only supports PNG files (treated as 8-bit sRGB).
+//! Image decoding. Currently only supports PNG files (treated as 8-bit sRGB),
+//! plus GIF files for animations (see [Image::frames_from_bytes]).
 //!
 //! Implemented as a wrapper around the C library stb_image, since it supports
 //! "CgBI" PNG files (an Apple proprietary extension used in iPhone OS apps).
+//! PNG encoding is similarly implemented with stb_image_write.
 //!
 //! This module also exposes decompression for Imagination Technologies' PVRTC
 //! format, implementing as a wrapper around their decoder from the PowerVR
-//! SDK.
+//! SDK, and for Ericsson's ETC1 format, which is simple enough to decode
+//! directly.
 
-use std::ffi::{c_int, c_uchar, CStr};
+use std::ffi::{c_int, c_uchar, c_void, CStr};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
 
 use touchHLE_pvrt_decompress_wrapper::*;
 use touchHLE_stb_image_wrapper::*;
@@ -27,6 +33,35 @@ enum PixelStore {
     Vec(Vec<u8>),
 }
 
+// SAFETY: The buffer allocated by stb_image is owned exclusively by the
+// `Image` and is only freed when it is dropped, so it can be moved between
+// threads like a `Vec`.
+unsafe impl Send for Image {}
+
+/// A frame of an animated image, see [Image::frames_from_bytes].
+pub struct Frame {
+    pub image: Image,
+    pub delay: Duration,
+}
+
+/// An image that may be in the process of being decoded on another thread,
+/// see [Image::from_bytes_async].
+pub enum PendingImage {
+    Decoding(JoinHandle<Result<Image, String>>),
+    Decoded(Result<Image, String>),
+}
+impl PendingImage {
+    /// Wait for decoding to finish, if it hasn't already.
+    pub fn join(self) -> Result<Image, String> {
+        match self {
+            PendingImage::Decoding(handle) => handle
+                .join()
+                .unwrap_or_else(|_| Err("Image decoding thread panicked".to_string())),
+            PendingImage::Decoded(result) => result,
+        }
+    }
+}
+
 impl Image {
     pub fn from_bytes(bytes: &[u8]) -> Result<Image, String> {
         let len: c_int = bytes.len().try_into().unwrap();
@@ -67,15 +102,7 @@ impl Image {
         // (Un-un-)premultiply pixels to match iPhone OS's image loading.
         {
             let len = width as usize * height as usize * 4;
-            let pixels = unsafe { std::slice::from_raw_parts_mut(pixels, len) };
-            let mut i = 0;
-            while i < pixels.len() {
-                let a = pixels[i + 3] as f32 / 255.0;
-                pixels[i] = (pixels[i] as f32 * a) as u8;
-                pixels[i + 1] = (pixels[i + 1] as f32 * a) as u8;
-                pixels[i + 2] = (pixels[i + 2] as f32 * a) as u8;
-                i += 4;
-            }
+            premultiply(unsafe { std::slice::from_raw_parts_mut(pixels, len) });
         }
 
         Ok(Image {
@@ -84,6 +111,108 @@ impl Image {
         })
     }
 
+    /// Decode all the frames of an animated GIF file, along with how long each
+    /// should be displayed for. Other files are decoded as a single frame with
+    /// no delay, like [Image::from_bytes].
+    pub fn frames_from_bytes(bytes: &[u8]) -> Result<Vec<Frame>, String> {
+        if !bytes.starts_with(b"GIF8") {
+            return Image::from_bytes(bytes).map(|image| {
+                vec![Frame {
+                    image,
+                    delay: Duration::ZERO,
+                }]
+            });
+        }
+
+        let len: c_int = bytes.len().try_into().unwrap();
+
+        let mut delays: *mut c_int = std::ptr::null_mut();
+        let mut x: c_int = 0;
+        let mut y: c_int = 0;
+        let mut z: c_int = 0;
+        let mut _channels_in_file: c_int = 0;
+
+        let pixels = unsafe {
+            stbi_load_gif_from_memory(
+                bytes.as_ptr(),
+                len,
+                &mut delays,
+                &mut x,
+                &mut y,
+                &mut z,
+                &mut _channels_in_file,
+                4,
+            )
+        };
+        if pixels.is_null() {
+            let reason = unsafe { CStr::from_ptr(stbi_failure_reason()) };
+            return Err(reason.to_str().unwrap().to_string());
+        }
+
+        let width: u32 = x.try_into().unwrap();
+        let height: u32 = y.try_into().unwrap();
+        let frame_count: usize = z.try_into().unwrap();
+        let frame_len = width as usize * height as usize * 4;
+
+        // stb_image returns all the frames in one allocation, so each is
+        // copied out into its own Vec.
+        let all_pixels = unsafe { std::slice::from_raw_parts(pixels, frame_len * frame_count) };
+        let delays_ms = if delays.is_null() {
+            &[][..]
+        } else {
+            unsafe { std::slice::from_raw_parts(delays, frame_count) }
+        };
+        let frames = all_pixels
+            .chunks_exact(frame_len)
+            .enumerate()
+            .map(|(i, frame_pixels)| {
+                let mut frame_pixels = frame_pixels.to_vec();
+                premultiply(&mut frame_pixels);
+                let delay_ms = delays_ms.get(i).copied().unwrap_or(0).max(0);
+                Frame {
+                    image: Image {
+                        pixels: PixelStore::Vec(frame_pixels),
+                        dimensions: (width, height),
+                    },
+                    delay: Duration::from_millis(delay_ms as u64),
+                }
+            })
+            .collect();
+
+        unsafe {
+            stbi_image_free(pixels.cast());
+            if !delays.is_null() {
+                stbi_image_free(delays.cast());
+            }
+        }
+
+        Ok(frames)
+    }
+
+    /// Like [Image::from_bytes], but decoding happens on a background thread so
+    /// the caller can get on with other work in the meantime. If there's only
+    /// one CPU core, or the thread can't be spawned, the image is decoded
+    /// synchronously instead.
+    pub fn from_bytes_async(bytes: Vec<u8>) -> PendingImage {
+        let single_core = std::thread::available_parallelism().map_or(true, |n| n.get() == 1);
+        if single_core {
+            return PendingImage::Decoded(Image::from_bytes(&bytes));
+        }
+
+        let bytes: Arc<[u8]> = bytes.into();
+        let bytes_for_thread = bytes.clone();
+        match std::thread::Builder::new()
+            .name("image decoding".to_string())
+            .spawn(move || Image::from_bytes(&bytes_for_thread))
+        {
+            Ok(handle) => PendingImage::Decoding(handle),
+            Err(e) => {
+                log!("Warning: Couldn't spawn image decoding thread: {}", e);
+                PendingImage::Decoded(Image::from_bytes(&bytes))
+            }
+        }
+    }
+
     /// TODO: This shouldn't really exist, it's a workaround for `CGImage`
     /// relying on this type and should be removed once it can be refactored.
     pub fn from_pixel_vec(pixels: Vec<u8>, dimensions: (u32, u32)) -> Image {
@@ -185,6 +314,111 @@ impl Drop for Image {
     }
 }
 
+/// Encode 8 bits per channel RGBA pixels as a PNG file. If `bottom_to_top` is
+/// [true], the rows are assumed to be in bottom-to-top order (as returned by
+/// `glReadPixels`) and will be flipped, otherwise they are assumed to be in
+/// top-to-bottom order (like [Image::pixels]).
+pub fn encode_png(
+    pixels: &[u8],
+    dimensions: (u32, u32),
+    bottom_to_top: bool,
+) -> Result<Vec<u8>, String> {
+    let (width, height) = dimensions;
+    let row_size = width as usize * 4;
+    assert!(row_size * height as usize == pixels.len());
+
+    let flipped;
+    let pixels = if bottom_to_top {
+        flipped = pixels
+            .chunks_exact(row_size)
+            .rev()
+            .flatten()
+            .copied()
+            .collect::<Vec<u8>>();
+        &flipped[..]
+    } else {
+        pixels
+    };
+
+    let mut out: Vec<u8> = Vec::new();
+    let success = unsafe {
+        stbi_write_png_to_func(
+            write_to_vec,
+            (&mut out as *mut Vec<u8>).cast(),
+            width.try_into().unwrap(),
+            height.try_into().unwrap(),
+            4,
+            pixels.as_ptr().cast(),
+            row_size.try_into().unwrap(),
+        )
+    };
+    if success == 0 {
+        return Err("stb_image_write couldn't encode PNG".to_string());
+    }
+    Ok(out)
+}
+
+/// Encode 8 bits per channel RGBA pixels (in top-to-bottom order) as a JPEG
+/// file. The alpha channel is ignored. `quality` ranges from 1 to 100.
+pub fn encode_jpeg(pixels: &[u8], dimensions: (u32, u32), quality: u8) -> Result<Vec<u8>, String> {
+    let (width, height) = dimensions;
+    assert!(width as usize * 4 * height as usize == pixels.len());
+    assert!((1..=100).contains(&quality));
+
+    let mut out: Vec<u8> = Vec::new();
+    let success = unsafe {
+        stbi_write_jpg_to_func(
+            write_to_vec,
+            (&mut out as *mut Vec<u8>).cast(),
+            width.try_into().unwrap(),
+            height.try_into().unwrap(),
+            4,
+            pixels.as_ptr().cast(),
+            quality.into(),
+        )
+    };
+    if success == 0 {
+        return Err("stb_image_write couldn't encode JPEG".to_string());
+    }
+    Ok(out)
+}
+
+/// Callback for stb_image_write that appends to the `Vec<u8>` pointed to by
+/// `context`.
+extern "C" fn write_to_vec(context: *mut c_void, data: *mut c_void, size: c_int) {
+    let out = unsafe { &mut *context.cast::<Vec<u8>>() };
+    let data = unsafe { std::slice::from_raw_parts(data.cast::<u8>(), size as usize) };
+    out.extend_from_slice(data);
+}
+
+/// Convert RGBA pixels with straight alpha to premultiplied alpha, like
+/// iPhone OS does when loading images.
+fn premultiply(pixels: &mut [u8]) {
+    for pixel in pixels.chunks_exact_mut(4) {
+        let a = pixel[3] as f32 / 255.0;
+        pixel[0] = (pixel[0] as f32 * a) as u8;
+        pixel[1] = (pixel[1] as f32 * a) as u8;
+        pixel[2] = (pixel[2] as f32 * a) as u8;
+    }
+}
+
+/// Convert RGBA pixels with premultiplied alpha (like [Image::pixels]) to
+/// straight alpha, which is what PNG files use.
+pub fn unpremultiply(pixels: &[u8]) -> Vec<u8> {
+    let mut out = pixels.to_vec();
+    for pixel in out.chunks_exact_mut(4) {
+        let a = pixel[3];
+        if a == 0 || a == 255 {
+            continue;
+        }
+        for channel in &mut pixel[..3] {
+            let value = (f32::from(*channel) * 255.0 / f32::from(a)).round();
+            *channel = value.min(255.0) as u8;
+        }
+    }
+    out
+}
+
 /// Approximate implementation of sRGB gamma encoding.
 pub fn gamma_encode(intensity: f32) -> f32 {
     // TODO: This doesn't implement the linear section near zero.
@@ -224,3 +458,234 @@ pub fn decode_pvrtc(pvrtc_data: &[u8], is_2bit: bool, width: u32, height: u32) -
     };
     rgba8_data
 }
+
+/// ETC1 modifier tables, indexed by the table codeword. The negative
+/// modifiers are the same values negated.
+const ETC1_MODIFIER_TABLES: [[i32; 2]; 8] = [
+    [2, 8],
+    [5, 17],
+    [9, 29],
+    [13, 42],
+    [18, 60],
+    [24, 80],
+    [33, 106],
+    [47, 183],
+];
+
+/// Decode a single 64-bit ETC1 block to 4x4 RGB8 pixels, indexed `[y][x]`.
+fn decode_etc1_block(block: [u8; 8]) -> [[[u8; 3]; 4]; 4] {
+    let high = u32::from_be_bytes(block[..4].try_into().unwrap());
+    let low = u32::from_be_bytes(block[4..].try_into().unwrap());
+
+    let bits = |shift: u32, count: u32| (high >> shift) & ((1 << count) - 1);
+    let extend_4 = |c: u32| ((c << 4) | c) as i32;
+    let extend_5 = |c: u32| ((c << 3) | (c >> 2)) as i32;
+
+    let diff = bits(1, 1) == 1;
+    let flip = bits(0, 1) == 1;
+    let tables = [bits(5, 3), bits(2, 3)];
+
+    let mut base_colors = [[0i32; 3]; 2];
+    for (channel, shift) in [(0, 24), (1, 16), (2, 8)] {
+        if diff {
+            let base = bits(shift + 3, 5);
+            // 3-bit two's complement delta
+            let delta = (bits(shift, 3) as i32) << 29 >> 29;
+            base_colors[0][channel] = extend_5(base);
+            // The sum is required to be in range by the spec.
+            base_colors[1][channel] = extend_5((base as i32 + delta) as u32 & 0x1f);
+        } else {
+            base_colors[0][channel] = extend_4(bits(shift + 4, 4));
+            base_colors[1][channel] = extend_4(bits(shift, 4));
+        }
+    }
+
+    let mut pixels = [[[0u8; 3]; 4]; 4];
+    for (y, row) in pixels.iter_mut().enumerate() {
+        for (x, pixel) in row.iter_mut().enumerate() {
+            let subblock = usize::from(if flip { y >= 2 } else { x >= 2 });
+            // Pixels are numbered in column-major order.
+            let i = x * 4 + y;
+            let lsb = (low >> i) & 1;
+            let msb = (low >> (16 + i)) & 1;
+            let [small, large] = ETC1_MODIFIER_TABLES[tables[subblock] as usize];
+            let modifier = match (msb, lsb) {
+                (0, 0) => small,
+                (0, 1) => large,
+                (1, 0) => -small,
+                _ => -large,
+            };
+            for (out, base) in pixel.iter_mut().zip(base_colors[subblock]) {
+                *out = (base + modifier).clamp(0, 255) as u8;
+            }
+        }
+    }
+    pixels
+}
+
+/// Decode an ETC1 (`GL_ETC1_RGB8_OES`) image to RGB8 pixels.
+pub fn decode_etc1(etc1_data: &[u8], width: u32, height: u32) -> Vec<u8> {
+    let (width, height) = (width as usize, height as usize);
+    let blocks_wide = width.div_ceil(4);
+    let blocks_high = height.div_ceil(4);
+    // This formula is from the OES_compressed_ETC1_RGB8_texture extension spec.
+    assert!(etc1_data.len() == blocks_wide * blocks_high * 8);
+
+    let mut rgb8_data = vec![0u8; width * height * 3];
+    for (block_idx, block) in etc1_data.chunks_exact(8).enumerate() {
+        let block_x = (block_idx % blocks_wide) * 4;
+        let block_y = (block_idx / blocks_wide) * 4;
+        let pixels = decode_etc1_block(block.try_into().unwrap());
+        // Blocks at the edges may extend past the image.
+        for (y, row) in pixels.iter().enumerate().take(height - block_y) {
+            for (x, pixel) in row.iter().enumerate().take(width - block_x) {
+                let idx = ((block_y + y) * width + block_x + x) * 3;
+                rgb8_data[idx..idx + 3].copy_from_slice(pixel);
+            }
+        }
+    }
+    rgb8_data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_etc1_blocks() {
+        // Individual mode, side-by-side subblocks. Base colors are 0x888888
+        // with table 0 and 0x444444 with table 1. Pixel (0, 1) uses the large
+        // positive modifier, (0, 2) the small negative one, (0, 3) and (2, 0)
+        // the large negative one, and the rest the small positive one.
+        let block = [0x84, 0x84, 0x84, 0x04, 0x01, 0x0C, 0x01, 0x0A];
+        let expected_luma = [
+            [138, 138, 51, 73],
+            [144, 138, 73, 73],
+            [134, 138, 73, 73],
+            [128, 138, 73, 73],
+        ];
+        let pixels = decode_etc1(&block, 4, 4);
+        for (i, pixel) in pixels.chunks(3).enumerate() {
+            assert_eq!(pixel, [expected_luma[i / 4][i % 4]; 3]);
+        }
+
+        // Differential mode, stacked subblocks. The base colors are
+        // (31, 0, 16) and (30, 1, 16) in 5-bit, and every pixel uses the small
+        // positive modifier (47 for table 7, 5 for table 0), with clamping.
+        let block = [0xFF, 0x01, 0x80, 0xE3, 0, 0, 0, 0];
+        // Only a 3x3 part of the block is used for a 3x3 image.
+        let pixels = decode_etc1(&block, 3, 3);
+        assert_eq!(pixels.len(), 3 * 3 * 3);
+        for (i, pixel) in pixels.chunks(3).enumerate() {
+            if i / 3 < 2 {
+                assert_eq!(pixel, [255, 47, 179]);
+            } else {
+                assert_eq!(pixel, [252, 13, 137]);
+            }
+        }
+    }
+
+    #[test]
+    fn async_decode_matches_sync() {
+        let pixels: Vec<u8> = (0..4 * 3 * 5).map(|i| (i * 7) as u8).collect();
+        let png = encode_png(&pixels, (3, 5), false).unwrap();
+
+        let sync = Image::from_bytes(&png).unwrap();
+        let async_ = Image::from_bytes_async(png.clone()).join().unwrap();
+        assert_eq!(async_.dimensions(), sync.dimensions());
+        assert_eq!(async_.pixels(), sync.pixels());
+
+        assert!(Image::from_bytes_async(b"not a PNG".to_vec())
+            .join()
+            .is_err());
+    }
+
+    #[test]
+    fn encode_png_premultiplied_round_trip() {
+        // 2x2 image with premultiplied alpha, as a UIImage would have it:
+        // opaque red, half-transparent green, transparent, and a
+        // half-transparent grey.
+        let pixels = [255, 0, 0, 255, 0, 128, 0, 128, 0, 0, 0, 0, 64, 64, 64, 128];
+        let straight = unpremultiply(&pixels);
+        assert_eq!(
+            straight,
+            [255, 0, 0, 255, 0, 255, 0, 128, 0, 0, 0, 0, 128, 128, 128, 128]
+        );
+
+        let png = encode_png(&straight, (2, 2), false).unwrap();
+        let image = Image::from_bytes(&png).unwrap();
+        assert_eq!(image.dimensions(), (2, 2));
+        // Premultiplying again when decoding may be off by one due to
+        // rounding.
+        for (&decoded, &original) in image.pixels().iter().zip(pixels.iter()) {
+            assert!(decoded.abs_diff(original) <= 1);
+        }
+    }
+
+    #[test]
+    fn encode_jpeg_round_trip() {
+        // 8x8 (a single JPEG block) flat orange, with alpha that gets ignored.
+        let pixels: Vec<u8> = [255, 128, 0, 255].repeat(8 * 8);
+        let jpeg = encode_jpeg(&pixels, (8, 8), 100).unwrap();
+        assert_eq!(&jpeg[..2], b"\xFF\xD8");
+        assert_eq!(&jpeg[jpeg.len() - 2..], b"\xFF\xD9");
+
+        let image = Image::from_bytes(&jpeg).unwrap();
+        assert_eq!(image.dimensions(), (8, 8));
+        // JPEG is lossy, but a flat color should survive at full quality.
+        for (&decoded, &original) in image.pixels().iter().zip(pixels.iter()) {
+            assert!(decoded.abs_diff(original) <= 4);
+        }
+    }
+
+    #[test]
+    fn decode_animated_gif() {
+        // 1x1 GIF89a with a two-color palette (red, blue) and two frames: red
+        // for 10 centiseconds, then blue for 20 centiseconds.
+        #[rustfmt::skip]
+        let gif = [
+            b'G', b'I', b'F', b'8', b'9', b'a', 1, 0, 1, 0, 0x80, 0, 0,
+            0xFF, 0x00, 0x00, 0x00, 0x00, 0xFF,
+            // Graphic control extension, image descriptor and LZW data
+            0x21, 0xF9, 4, 0, 10, 0, 0, 0,
+            0x2C, 0, 0, 0, 0, 1, 0, 1, 0, 0,
+            2, 2, 0x44, 0x01, 0,
+            0x21, 0xF9, 4, 0, 20, 0, 0, 0,
+            0x2C, 0, 0, 0, 0, 1, 0, 1, 0, 0,
+            2, 2, 0x4C, 0x01, 0,
+            0x3B,
+        ];
+
+        let frames = Image::frames_from_bytes(&gif).unwrap();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].delay, Duration::from_millis(100));
+        assert_eq!(frames[1].delay, Duration::from_millis(200));
+        assert_eq!(frames[0].image.dimensions(), (1, 1));
+        assert_eq!(frames[0].image.pixels(), [255, 0, 0, 255]);
+        assert_eq!(frames[1].image.pixels(), [0, 0, 255, 255]);
+
+        // The single-frame API still gets the first frame.
+        let image = Image::from_bytes(&gif).unwrap();
+        assert_eq!(image.pixels(), [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn encode_png_flips_rows() {
+        // 2x2 image, bottom row first as glReadPixels would return it.
+        let bottom = [255, 0, 0, 255, 0, 255, 0, 255];
+        let top = [0, 0, 255, 255, 255, 255, 255, 255];
+        let pixels: Vec<u8> = bottom.iter().chain(top.iter()).copied().collect();
+
+        let png = encode_png(&pixels, (2, 2), true).unwrap();
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+
+        let image = Image::from_bytes(&png).unwrap();
+        assert_eq!(image.dimensions(), (2, 2));
+        assert_eq!(&image.pixels()[..8], &top);
+        assert_eq!(&image.pixels()[8..], &bottom);
+
+        let png = encode_png(&pixels, (2, 2), false).unwrap();
+        let image = Image::from_bytes(&png).unwrap();
+        assert_eq!(image.pixels(), &pixels[..]);
+    }
+}
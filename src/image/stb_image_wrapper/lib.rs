@@ -4,7 +4,8 @@
  * file, You can obtain one at https://mozilla.org/MPL/2.0/.
  */
 //! This is separated out into its own package so that we can avoid rebuilding
-//! stb_image more often than necessary, and to improve build-time parallelism.
+//! stb_image and stb_image_write more often than necessary, and to improve
+//! build-time parallelism.
 
 // Allow the crate to have a non-snake-case name (touchHLE).
 // This also allows items in the crate to have non-snake-case names.
@@ -12,7 +13,11 @@
 
 use std::ffi::{c_char, c_int, c_uchar, c_void};
 
-// See build.rs, lib.c and ../../../vendor/stb/stb_image.h
+#[allow(non_camel_case_types)]
+pub type stbi_write_func = extern "C" fn(context: *mut c_void, data: *mut c_void, size: c_int);
+
+// See build.rs, lib.c, ../../../vendor/stb/stb_image.h and
+// ../../../vendor/stb/stb_image_write.h
 extern "C" {
     pub fn stbi_convert_iphone_png_to_rgb(flag_true_if_should_convert: c_int);
     pub fn stbi_set_unpremultiply_on_load(flag_true_if_should_unpremultiply: c_int);
@@ -24,6 +29,35 @@ extern "C" {
         channels_in_file: *mut c_int,
         desired_channels: c_int,
     ) -> *mut c_uchar;
+    pub fn stbi_load_gif_from_memory(
+        buffer: *const c_uchar,
+        len: c_int,
+        delays: *mut *mut c_int,
+        x: *mut c_int,
+        y: *mut c_int,
+        z: *mut c_int,
+        comp: *mut c_int,
+        req_comp: c_int,
+    ) -> *mut c_uchar;
     pub fn stbi_image_free(retval_from_stbi_load: *mut c_void);
     pub fn stbi_failure_reason() -> *const c_char;
+
+    pub fn stbi_write_png_to_func(
+        func: stbi_write_func,
+        context: *mut c_void,
+        w: c_int,
+        h: c_int,
+        comp: c_int,
+        data: *const c_void,
+        stride_in_bytes: c_int,
+    ) -> c_int;
+    pub fn stbi_write_jpg_to_func(
+        func: stbi_write_func,
+        context: *mut c_void,
+        x: c_int,
+        y: c_int,
+        comp: c_int,
+        data: *const c_void,
+        quality: c_int,
+    ) -> c_int;
 }
@@ -40,6 +40,7 @@ mod frameworks;
 mod fs;
 mod gdb;
 mod gles;
+mod http;
 mod image;
 mod libc;
 mod licenses;
@@ -49,7 +50,9 @@ mod mem;
 mod objc;
 mod options;
 mod paths;
+mod snapshot;
 mod stack;
+mod unimplemented;
 mod window;
 
 // Environment is used very frequently used and used to be in this module, so
@@ -198,7 +201,7 @@ pub fn main<T: Iterator<Item = String>>(mut args: T) -> Result<(), String> {
             let parse_result = options.parse_argument(option_arg);
             assert!(parse_result == Ok(true));
         }
-        if options.headless {
+        if !options.creates_window() {
             return Err(
                 "No app specified. Use the --help flag to see command-line usage.".to_string(),
             );
@@ -248,19 +251,11 @@ pub fn main<T: Iterator<Item = String>>(mut args: T) -> Result<(), String> {
     );
     echo!();
 
-    if let Some(version) = minimum_os_version {
-        let (major, minor_etc) = version.split_once('.').unwrap();
-        let minor = minor_etc
-            .split_once('.')
-            .map_or(minor_etc, |(minor, _etc)| minor);
-        let major: u32 = major.parse().unwrap();
-        let minor: u32 = minor.parse().unwrap();
-        if major > 3 || (major == 3 && minor > 0) {
-            echo!("Warning: app requires OS version {}. Only iPhone OS 2.x and iPhone OS 3.0 apps are currently supported.", version);
-        }
-    }
-
     if just_info {
+        match bundle::check_minimum_os_version(minimum_os_version, false) {
+            Ok(None) => (),
+            Ok(Some(warning)) | Err(warning) => echo!("Warning: {}", warning),
+        }
         return Ok(());
     }
 
@@ -321,6 +316,12 @@ pub fn main<T: Iterator<Item = String>>(mut args: T) -> Result<(), String> {
         assert!(parse_result == Ok(true));
     }
 
+    match bundle::check_minimum_os_version(minimum_os_version, options.force_unsupported_os) {
+        Ok(None) => (),
+        Ok(Some(warning)) => echo!("Warning: {}", warning),
+        Err(err) => return Err(err),
+    }
+
     let mut env = Environment::new(bundle, fs, options, env_for_salvage)?;
     env.run();
     Ok(())
@@ -11,11 +11,13 @@
 
 mod generic_char;
 
+pub mod blocks;
 pub mod clocale;
 pub mod crypto;
 pub mod ctype;
 pub mod cxxabi;
 pub mod dirent;
+pub mod dispatch;
 pub mod dlfcn;
 pub mod errno;
 pub mod ifaddrs;
@@ -35,6 +37,7 @@ pub mod sched;
 pub mod semaphore;
 pub mod setjmp;
 pub mod signal;
+pub mod socket;
 pub mod stdio;
 pub mod stdlib;
 pub mod string;
@@ -43,19 +46,40 @@ pub mod sysctl;
 pub mod time;
 pub mod unistd;
 pub mod wchar;
+pub mod zlib;
 
 /// Container for state of various child modules
 #[derive(Default)]
 pub struct State {
     dirent: dirent::State,
+    dispatch: dispatch::State,
     keymgr: keymgr::State,
+    mmap: mmap::State,
     mach_semaphore: mach_semaphore::State,
+    netdb: netdb::State,
     posix_io: posix_io::State,
     pub pthread: pthread::State,
     pub semaphore: semaphore::State,
+    signal: signal::State,
+    socket: socket::State,
+    stdio: stdio::State,
     stdlib: stdlib::State,
     string: string::State,
     time: time::State,
     errno: errno::State,
     clocale: clocale::State,
+    zlib: zlib::State,
+}
+impl State {
+    /// Forget allocations that won't exist once `mem` is restored, see
+    /// [crate::Environment::load_snapshot].
+    pub fn forget_pointers_not_in(&mut self, mem: &crate::mem::MemSnapshot) {
+        self.clocale.forget_pointers_not_in(mem);
+        self.dispatch.forget_pointers_not_in(mem);
+        self.errno.forget_pointers_not_in(mem);
+        self.netdb.forget_pointers_not_in(mem);
+        self.stdlib.forget_pointers_not_in(mem);
+        self.time.forget_pointers_not_in(mem);
+        self.zlib.forget_pointers_not_in(mem);
+    }
 }
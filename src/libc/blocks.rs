@@ -0,0 +1,113 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `Block.h` and the blocks ABI (closures, as used by `qsort_b` etc).
+//!
+//! Resources:
+//! - The [Block Implementation Specification](https://clang.llvm.org/docs/Block-ABI-Apple.html)
+//!
+//! Blocks can be called, and host code can keep a block around using
+//! [copy_block] and [release_block], but `Block_copy` and `Block_release` are
+//! not implemented for the guest yet.
+
+use crate::abi::{CallFromHost, GuestFunction};
+use crate::dyld::{ConstantExports, HostConstant};
+use crate::mem::{ConstPtr, ConstVoidPtr, GuestUSize, Mem, SafeRead};
+use crate::Environment;
+
+/// The common header of every block object.
+#[allow(dead_code)]
+#[repr(C, packed)]
+struct Block_literal {
+    isa: ConstVoidPtr,
+    flags: i32,
+    reserved: i32,
+    /// The block's function. Its first argument is a pointer to the block
+    /// itself, followed by the block's own arguments.
+    invoke: GuestFunction,
+    descriptor: ConstVoidPtr,
+    // captured variables follow the struct
+}
+unsafe impl SafeRead for Block_literal {}
+
+/// The descriptor of a block. The helper functions are only present if the
+/// block has [BLOCK_HAS_COPY_DISPOSE] set.
+#[allow(dead_code)]
+#[repr(C, packed)]
+struct Block_descriptor {
+    reserved: GuestUSize,
+    size: GuestUSize,
+    /// `void (*copy_helper)(void *dst, void *src)`
+    copy_helper: GuestFunction,
+    /// `void (*dispose_helper)(void *src)`
+    dispose_helper: GuestFunction,
+}
+unsafe impl SafeRead for Block_descriptor {}
+
+const BLOCK_HAS_COPY_DISPOSE: i32 = 1 << 25;
+const BLOCK_IS_GLOBAL: i32 = 1 << 28;
+
+/// Get the function that implements a block. When calling it, the block pointer
+/// must be passed as the first argument.
+pub fn block_invoke_function(mem: &Mem, block: ConstVoidPtr) -> GuestFunction {
+    assert!(!block.is_null());
+    mem.read(block.cast::<Block_literal>()).invoke
+}
+
+/// Copy a block so it can be called after the function it was passed to has
+/// returned, like `Block_copy`. Release it with [release_block].
+///
+/// Global blocks are returned as-is. Other blocks usually live on the stack, so
+/// they are copied to the heap, and their captured variables are copied using
+/// the block's copy helper, if any.
+pub fn copy_block(env: &mut Environment, block: ConstVoidPtr) -> ConstVoidPtr {
+    let literal: Block_literal = env.mem.read(block.cast());
+    if literal.flags & BLOCK_IS_GLOBAL != 0 {
+        return block;
+    }
+    let descriptor: ConstPtr<Block_descriptor> = literal.descriptor.cast();
+    // Only the size is read here, because the helpers might not be present.
+    let size = env.mem.read(descriptor.cast::<GuestUSize>() + 1);
+    let copy = env.mem.alloc(size);
+    env.mem.memmove(copy, block, size);
+    if literal.flags & BLOCK_HAS_COPY_DISPOSE != 0 {
+        let copy_helper = env.mem.read(descriptor).copy_helper;
+        () = copy_helper.call_from_host(env, (copy, block));
+    }
+    log_dbg!("Copied block {:?} to {:?}", block, copy);
+    copy.cast_const()
+}
+
+/// Release a block returned by [copy_block].
+pub fn release_block(env: &mut Environment, block: ConstVoidPtr) {
+    let literal: Block_literal = env.mem.read(block.cast());
+    if literal.flags & BLOCK_IS_GLOBAL != 0 {
+        return;
+    }
+    if literal.flags & BLOCK_HAS_COPY_DISPOSE != 0 {
+        let descriptor: ConstPtr<Block_descriptor> = literal.descriptor.cast();
+        let dispose_helper = env.mem.read(descriptor).dispose_helper;
+        () = dispose_helper.call_from_host(env, (block,));
+    }
+    env.mem.free(block.cast_mut());
+}
+
+/// The "classes" used as the `isa` of blocks. These aren't real Objective-C
+/// classes in our implementation, but the compiler emits references to them,
+/// so they need to exist. Apple declares them as `void *[32]`.
+fn concrete_block_class(mem: &mut Mem) -> ConstVoidPtr {
+    mem.alloc(32 * 4).cast_const()
+}
+
+pub const CONSTANTS: ConstantExports = &[
+    (
+        "__NSConcreteGlobalBlock",
+        HostConstant::Custom(concrete_block_class),
+    ),
+    (
+        "__NSConcreteStackBlock",
+        HostConstant::Custom(concrete_block_class),
+    ),
+];
@@ -10,7 +10,7 @@ use std::collections::hash_map::Entry;
 use crate::dyld::FunctionExports;
 use crate::environment::Environment;
 use crate::export_c_func;
-use crate::mem::{ConstPtr, MutPtr};
+use crate::mem::{ConstPtr, MemSnapshot, MutPtr};
 
 pub type LocaleCategory = i32;
 pub const LC_ALL: LocaleCategory = 0;
@@ -25,6 +25,14 @@ pub const LC_MESSAGES: LocaleCategory = 6;
 pub struct State {
     locale: std::collections::HashMap<LocaleCategory, MutPtr<u8>>,
 }
+impl State {
+    /// Forget allocations that won't exist once `mem` is restored, see
+    /// [crate::Environment::load_snapshot].
+    pub fn forget_pointers_not_in(&mut self, mem: &MemSnapshot) {
+        self.locale
+            .retain(|_, locale| mem.is_used(locale.to_bits()));
+    }
+}
 
 pub fn setlocale(
     env: &mut Environment,
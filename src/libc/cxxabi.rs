@@ -10,28 +10,32 @@
 
 use crate::abi::GuestFunction;
 use crate::dyld::{export_c_func, FunctionExports};
+use crate::libc::stdlib::{register_atexit_handler, run_atexit_handlers, AtExitHandler};
 use crate::mem::MutVoidPtr;
 use crate::Environment;
 
 fn __cxa_atexit(
-    _env: &mut Environment,
+    env: &mut Environment,
     func: GuestFunction, // void (*func)(void *)
     p: MutVoidPtr,
     d: MutVoidPtr,
 ) -> i32 {
-    // TODO: when this is implemented, make sure it's properly compatible with
-    // C atexit.
-    log!(
-        "TODO: __cxa_atexit({:?}, {:?}, {:?}) (unimplemented)",
-        func,
-        p,
-        d
+    log_dbg!("__cxa_atexit({:?}, {:?}, {:?})", func, p, d);
+    register_atexit_handler(
+        env,
+        AtExitHandler::Cxa {
+            func,
+            arg: p,
+            dso_handle: d,
+        },
     );
     0 // success
 }
 
-fn __cxa_finalize(_env: &mut Environment, d: MutVoidPtr) {
-    log!("TODO: __cxa_finalize({:?}) (unimplemented)", d);
+fn __cxa_finalize(env: &mut Environment, d: MutVoidPtr) {
+    log_dbg!("__cxa_finalize({:?})", d);
+    // A null handle means all handlers should be called.
+    run_atexit_handlers(env, (!d.is_null()).then_some(d));
 }
 
 pub const FUNCTIONS: FunctionExports = &[
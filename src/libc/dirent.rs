@@ -50,6 +50,11 @@ impl State {
     }
 }
 
+/// Returns [true] if the app has any directories open.
+pub fn has_open_dirs(env: &Environment) -> bool {
+    !env.libc_state.dirent.open_dirs.is_empty() || !env.libc_state.dirent.read_dirs.is_empty()
+}
+
 fn opendir(env: &mut Environment, filename: ConstPtr<u8>) -> MutPtr<DIR> {
     // TODO: handle errno properly
     set_errno(env, 0);
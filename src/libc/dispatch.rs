@@ -0,0 +1,451 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `dispatch/dispatch.h` (Grand Central Dispatch), minimal implementation.
+//!
+//! Only the main queue and the global queues exist so far. The main queue is
+//! drained by the main thread's run loop, see [drain_main_queue]. The global
+//! queues run work items on worker threads, which exit once their queue is
+//! empty.
+//!
+//! Queues are identified by the contents of their (guest-side) objects rather
+//! than their address, because the main queue also exists as a constant
+//! (`_dispatch_main_q`) that is linked separately for each binary.
+
+use crate::abi::{CallFromHost, GuestFunction};
+use crate::dyld::{export_c_func, ConstantExports, FunctionExports, HostConstant, HostFunction};
+use crate::libc::blocks::{block_invoke_function, copy_block, release_block};
+use crate::libc::pthread::thread::create_detached_thread;
+use crate::mem::{ConstVoidPtr, Mem, MemSnapshot, MutPtr, MutVoidPtr, Ptr, SafeRead};
+use crate::objc::{msg_class, release};
+use crate::Environment;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::Duration;
+
+const MAGIC_QUEUE: u32 = u32::from_be_bytes(*b"DSPQ");
+
+#[repr(C, packed)]
+pub struct dispatch_queue {
+    /// Magic number (must be [MAGIC_QUEUE])
+    magic: u32,
+    queue_id: QueueId,
+}
+unsafe impl SafeRead for dispatch_queue {}
+
+pub type dispatch_queue_t = MutPtr<dispatch_queue>;
+
+/// `long` in the SDK, which is 32-bit.
+type dispatch_once_t = i32;
+/// Value of a [dispatch_once_t] once the work has been done.
+const ONCE_DONE: dispatch_once_t = !0;
+/// Value of a [dispatch_once_t] while some thread is doing the work.
+const ONCE_RUNNING: dispatch_once_t = 1;
+
+const DISPATCH_QUEUE_PRIORITY_HIGH: i32 = 2;
+const DISPATCH_QUEUE_PRIORITY_DEFAULT: i32 = 0;
+const DISPATCH_QUEUE_PRIORITY_LOW: i32 = -2;
+const DISPATCH_QUEUE_PRIORITY_BACKGROUND: i32 = i16::MIN as i32;
+
+type QueueId = u32;
+const MAIN_QUEUE: QueueId = 0;
+
+/// Maximum number of worker threads per global queue.
+const MAX_WORKERS: usize = 4;
+
+/// How often threads waiting for other threads check whether they're done.
+const WAIT_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+#[derive(Default)]
+pub struct State {
+    /// Queue objects returned by `dispatch_get_main_queue()` and
+    /// `dispatch_get_global_queue()`, created lazily.
+    queue_objects: HashMap<QueueId, dispatch_queue_t>,
+    queues: HashMap<QueueId, Queue>,
+    /// Guest function for [_touchHLE_dispatch_worker], created lazily.
+    worker_function: Option<GuestFunction>,
+    next_sync_id: u32,
+    /// `dispatch_sync()` work items that have been run, but whose caller hasn't
+    /// noticed yet.
+    finished_sync: HashSet<u32>,
+}
+impl State {
+    fn get(env: &mut Environment) -> &mut Self {
+        &mut env.libc_state.dispatch
+    }
+    /// Forget allocations that won't exist once `mem` is restored, see
+    /// [crate::Environment::load_snapshot].
+    pub fn forget_pointers_not_in(&mut self, mem: &MemSnapshot) {
+        self.worker_function = self
+            .worker_function
+            .filter(|f| mem.is_used(f.addr_without_thumb_bit()));
+    }
+}
+
+/// Returns [true] if the app has used any dispatch queues.
+pub fn has_queues(env: &Environment) -> bool {
+    let state = &env.libc_state.dispatch;
+    !state.queue_objects.is_empty() || !state.queues.is_empty()
+}
+
+/// A block or function waiting to be run on a queue.
+#[derive(Debug)]
+struct WorkItem {
+    /// Blocks are called like functions with the block itself as the context.
+    function: GuestFunction,
+    context: MutVoidPtr,
+    /// Set if `context` is a block copy that must be released afterwards.
+    owns_block: bool,
+    /// Set for `dispatch_sync()`, so the caller knows when it's done.
+    sync_id: Option<u32>,
+}
+
+#[derive(Default)]
+struct Queue {
+    pending: VecDeque<WorkItem>,
+    /// Number of worker threads that haven't exited yet.
+    workers: usize,
+}
+impl Queue {
+    /// Take the work items that are currently pending, in the order they were
+    /// enqueued.
+    fn take_pending(&mut self) -> Vec<WorkItem> {
+        self.pending.drain(..).collect()
+    }
+}
+
+/// Get the ID of the global queue for a priority, if it's valid.
+fn global_queue_id(priority: i32) -> Option<QueueId> {
+    match priority {
+        DISPATCH_QUEUE_PRIORITY_HIGH => Some(1),
+        DISPATCH_QUEUE_PRIORITY_DEFAULT => Some(2),
+        DISPATCH_QUEUE_PRIORITY_LOW => Some(3),
+        DISPATCH_QUEUE_PRIORITY_BACKGROUND => Some(4),
+        _ => None,
+    }
+}
+
+fn queue_object(env: &mut Environment, queue_id: QueueId) -> dispatch_queue_t {
+    if let Some(&queue) = State::get(env).queue_objects.get(&queue_id) {
+        return queue;
+    }
+    let queue = env.mem.alloc_and_write(dispatch_queue {
+        magic: MAGIC_QUEUE,
+        queue_id,
+    });
+    State::get(env).queue_objects.insert(queue_id, queue);
+    queue
+}
+
+fn queue_id(env: &Environment, queue: dispatch_queue_t) -> QueueId {
+    let dispatch_queue { magic, queue_id } = env.mem.read(queue);
+    assert_eq!(magic, MAGIC_QUEUE, "{:?} is not a dispatch queue", queue);
+    queue_id
+}
+
+fn dispatch_get_main_queue(env: &mut Environment) -> dispatch_queue_t {
+    queue_object(env, MAIN_QUEUE)
+}
+
+fn dispatch_get_global_queue(
+    env: &mut Environment,
+    priority: i32, // long
+    flags: u32,    // unsigned long
+) -> dispatch_queue_t {
+    let Some(queue_id) = global_queue_id(priority).filter(|_| flags == 0) else {
+        log!(
+            "Warning: dispatch_get_global_queue() with invalid priority {} or flags {:#x}, returning NULL",
+            priority,
+            flags
+        );
+        return Ptr::null();
+    };
+    queue_object(env, queue_id)
+}
+
+/// `dispatch_retain()` and `dispatch_release()` have no effect on the main
+/// queue and the global queues, which are the only ones that exist so far.
+fn dispatch_retain(env: &mut Environment, object: dispatch_queue_t) {
+    queue_id(env, object);
+}
+fn dispatch_release(env: &mut Environment, object: dispatch_queue_t) {
+    queue_id(env, object);
+}
+
+fn block_work_item(env: &mut Environment, block: ConstVoidPtr, copy: bool) -> WorkItem {
+    let block = if copy { copy_block(env, block) } else { block };
+    WorkItem {
+        function: block_invoke_function(&env.mem, block),
+        context: block.cast_mut(),
+        owns_block: copy,
+        sync_id: None,
+    }
+}
+
+fn dispatch_async(
+    env: &mut Environment,
+    queue: dispatch_queue_t,
+    block: ConstVoidPtr, // dispatch_block_t
+) {
+    let work = block_work_item(env, block, /* copy: */ true);
+    enqueue(env, queue, work);
+}
+
+fn dispatch_async_f(
+    env: &mut Environment,
+    queue: dispatch_queue_t,
+    context: MutVoidPtr,
+    work: GuestFunction, // dispatch_function_t
+) {
+    let work = WorkItem {
+        function: work,
+        context,
+        owns_block: false,
+        sync_id: None,
+    };
+    enqueue(env, queue, work);
+}
+
+fn dispatch_sync(
+    env: &mut Environment,
+    queue: dispatch_queue_t,
+    block: ConstVoidPtr, // dispatch_block_t
+) {
+    // The caller waits for the block, so there's no need to copy it.
+    let work = block_work_item(env, block, /* copy: */ false);
+    run_sync(env, queue, work);
+}
+
+fn dispatch_sync_f(
+    env: &mut Environment,
+    queue: dispatch_queue_t,
+    context: MutVoidPtr,
+    work: GuestFunction, // dispatch_function_t
+) {
+    let work = WorkItem {
+        function: work,
+        context,
+        owns_block: false,
+        sync_id: None,
+    };
+    run_sync(env, queue, work);
+}
+
+fn dispatch_once(
+    env: &mut Environment,
+    predicate: MutPtr<dispatch_once_t>,
+    block: ConstVoidPtr, // dispatch_block_t
+) {
+    let work = block_work_item(env, block, /* copy: */ false);
+    run_once(env, predicate, work);
+}
+
+fn dispatch_once_f(
+    env: &mut Environment,
+    predicate: MutPtr<dispatch_once_t>,
+    context: MutVoidPtr,
+    function: GuestFunction, // dispatch_function_t
+) {
+    let work = WorkItem {
+        function,
+        context,
+        owns_block: false,
+        sync_id: None,
+    };
+    run_once(env, predicate, work);
+}
+
+fn run_once(env: &mut Environment, predicate: MutPtr<dispatch_once_t>, work: WorkItem) {
+    loop {
+        match env.mem.read(predicate) {
+            0 => break,
+            ONCE_DONE => return,
+            // Another thread is doing the work, wait for it.
+            _ => env.sleep(WAIT_POLL_INTERVAL, false),
+        }
+    }
+    log_dbg!("dispatch_once({:?}): running {:?}", predicate, work);
+    env.mem.write(predicate, ONCE_RUNNING);
+    () = work.function.call_from_host(env, (work.context,));
+    env.mem.write(predicate, ONCE_DONE);
+}
+
+fn enqueue(env: &mut Environment, queue: dispatch_queue_t, work: WorkItem) {
+    let queue_id = queue_id(env, queue);
+    log_dbg!("Enqueuing {:?} on dispatch queue {}", work, queue_id);
+    State::get(env)
+        .queues
+        .entry(queue_id)
+        .or_default()
+        .pending
+        .push_back(work);
+    if queue_id != MAIN_QUEUE {
+        spawn_workers(env, queue_id);
+    }
+}
+
+fn run_sync(env: &mut Environment, queue: dispatch_queue_t, mut work: WorkItem) {
+    let queue_id = queue_id(env, queue);
+    if queue_id != MAIN_QUEUE {
+        // The global queues are concurrent, so the work can just be done on the
+        // current thread.
+        () = work.function.call_from_host(env, (work.context,));
+        return;
+    }
+
+    assert!(
+        env.current_thread != 0,
+        "dispatch_sync() onto the main queue from the main thread would deadlock"
+    );
+    let state = State::get(env);
+    let sync_id = state.next_sync_id;
+    state.next_sync_id = state.next_sync_id.wrapping_add(1);
+    work.sync_id = Some(sync_id);
+    enqueue(env, queue, work);
+    while !State::get(env).finished_sync.remove(&sync_id) {
+        env.sleep(WAIT_POLL_INTERVAL, false);
+    }
+}
+
+/// Run a work item taken from a queue, then clean it up.
+fn run_work_item(env: &mut Environment, work: WorkItem) {
+    log_dbg!("Running dispatch work item {:?}", work);
+    // Objective-C code run from a queue expects there to be an autorelease
+    // pool, as with GCD's own worker threads.
+    let pool = msg_class![env; NSAutoreleasePool new];
+    () = work.function.call_from_host(env, (work.context,));
+    release(env, pool);
+    if work.owns_block {
+        release_block(env, work.context.cast_const());
+    }
+    if let Some(sync_id) = work.sync_id {
+        State::get(env).finished_sync.insert(sync_id);
+    }
+}
+
+/// Run the work items currently on the main queue. This is called by the main
+/// thread's run loop on each iteration. Work enqueued meanwhile is left for the
+/// next iteration.
+pub fn drain_main_queue(env: &mut Environment) {
+    if env.current_thread != 0 {
+        return;
+    }
+    let Some(queue) = State::get(env).queues.get_mut(&MAIN_QUEUE) else {
+        return;
+    };
+    for work in queue.take_pending() {
+        run_work_item(env, work);
+    }
+}
+
+/// Start enough worker threads for a global queue's pending work items, within
+/// the limit.
+fn spawn_workers(env: &mut Environment, queue_id: QueueId) {
+    loop {
+        let queue = State::get(env).queues.get_mut(&queue_id).unwrap();
+        if queue.workers >= queue.pending.len() || queue.workers >= MAX_WORKERS {
+            return;
+        }
+        queue.workers += 1;
+
+        let worker_function = if let Some(f) = State::get(env).worker_function {
+            f
+        } else {
+            let symb = "__touchHLE_dispatch_worker";
+            let hf: HostFunction = &(_touchHLE_dispatch_worker as fn(&mut Environment, _) -> _);
+            let f = env.dyld.create_guest_function(&mut env.mem, symb, hf);
+            State::get(env).worker_function = Some(f);
+            f
+        };
+        create_detached_thread(env, worker_function, Ptr::from_bits(queue_id));
+    }
+}
+
+/// Start routine of a global queue's worker thread. It runs work items from the
+/// queue until there are none left, then exits.
+pub fn _touchHLE_dispatch_worker(env: &mut Environment, queue_id: QueueId) {
+    log_dbg!("Dispatch queue {}: worker started", queue_id);
+    loop {
+        let queue = State::get(env).queues.get_mut(&queue_id).unwrap();
+        let Some(work) = queue.pending.pop_front() else {
+            queue.workers -= 1;
+            break;
+        };
+        run_work_item(env, work);
+    }
+    log_dbg!("Dispatch queue {}: worker exiting", queue_id);
+}
+
+fn main_queue_constant(mem: &mut Mem) -> ConstVoidPtr {
+    mem.alloc_and_write(dispatch_queue {
+        magic: MAGIC_QUEUE,
+        queue_id: MAIN_QUEUE,
+    })
+    .cast()
+    .cast_const()
+}
+
+pub const CONSTANTS: ConstantExports = &[
+    // `dispatch_get_main_queue()` is a macro that takes the address of this.
+    (
+        "__dispatch_main_q",
+        HostConstant::Custom(main_queue_constant),
+    ),
+];
+
+pub const FUNCTIONS: FunctionExports = &[
+    export_c_func!(dispatch_get_main_queue()),
+    export_c_func!(dispatch_get_global_queue(_, _)),
+    export_c_func!(dispatch_retain(_)),
+    export_c_func!(dispatch_release(_)),
+    export_c_func!(dispatch_async(_, _)),
+    export_c_func!(dispatch_async_f(_, _, _)),
+    export_c_func!(dispatch_sync(_, _)),
+    export_c_func!(dispatch_sync_f(_, _, _)),
+    export_c_func!(dispatch_once(_, _)),
+    export_c_func!(dispatch_once_f(_, _, _)),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn global_queue_ids() {
+        let ids: Vec<_> = [
+            DISPATCH_QUEUE_PRIORITY_HIGH,
+            DISPATCH_QUEUE_PRIORITY_DEFAULT,
+            DISPATCH_QUEUE_PRIORITY_LOW,
+            DISPATCH_QUEUE_PRIORITY_BACKGROUND,
+        ]
+        .into_iter()
+        .map(|priority| global_queue_id(priority).unwrap())
+        .collect();
+        assert!(!ids.contains(&MAIN_QUEUE));
+        let unique: HashSet<_> = ids.iter().collect();
+        assert_eq!(unique.len(), ids.len());
+        assert_eq!(global_queue_id(1), None);
+    }
+
+    #[test]
+    fn queue_order() {
+        let work = |i| WorkItem {
+            function: GuestFunction::from_addr_with_thumb_bit(0x1000),
+            context: Ptr::from_bits(i),
+            owns_block: false,
+            sync_id: None,
+        };
+        let mut queue = Queue::default();
+        queue.pending.push_back(work(1));
+        queue.pending.push_back(work(2));
+        queue.pending.push_back(work(3));
+        let batch = queue.take_pending();
+        // Work enqueued while the batch runs is left for the next batch.
+        queue.pending.push_back(work(4));
+        let contexts: Vec<_> = batch.iter().map(|w| w.context.to_bits()).collect();
+        assert_eq!(contexts, [1, 2, 3]);
+        assert_eq!(queue.take_pending().len(), 1);
+        assert!(queue.take_pending().is_empty());
+    }
+}
@@ -12,17 +12,43 @@ use crate::Environment;
 use std::io::Write;
 
 pub const EPERM: i32 = 1;
+pub const ENOENT: i32 = 2;
+pub const ESRCH: i32 = 3;
+pub const EIO: i32 = 5;
 pub const EBADF: i32 = 9;
 pub const EDEADLK: i32 = 11;
+pub const ENOMEM: i32 = 12;
 pub const EBUSY: i32 = 16;
 pub const EEXIST: i32 = 17;
+pub const ENODEV: i32 = 19;
+pub const ENOTDIR: i32 = 20;
 pub const EINVAL: i32 = 22;
+pub const EPIPE: i32 = 32;
+pub const ERANGE: i32 = 34;
+pub const EAGAIN: i32 = 35;
+pub const EINPROGRESS: i32 = 36;
+pub const EALREADY: i32 = 37;
+pub const EPROTONOSUPPORT: i32 = 43;
+pub const EAFNOSUPPORT: i32 = 47;
+pub const ENETUNREACH: i32 = 51;
+pub const ECONNABORTED: i32 = 53;
+pub const ECONNRESET: i32 = 54;
+pub const EISCONN: i32 = 56;
+pub const ENOTCONN: i32 = 57;
+pub const ETIMEDOUT: i32 = 60;
+pub const ECONNREFUSED: i32 = 61;
 
 #[derive(Default)]
 pub struct State {
     errnos: std::collections::HashMap<crate::ThreadId, MutPtr<i32>>,
 }
 impl State {
+    /// Forget allocations that won't exist once `mem` is restored, see
+    /// [crate::Environment::load_snapshot].
+    pub fn forget_pointers_not_in(&mut self, mem: &crate::mem::MemSnapshot) {
+        self.errnos.retain(|_, errno| mem.is_used(errno.to_bits()));
+    }
+
     fn errno_ptr_for_thread(
         &mut self,
         mem: &mut crate::mem::Mem,
@@ -43,6 +43,12 @@ impl State {
     }
 }
 
+/// Returns [true] if the app has any Mach semaphores that haven't been
+/// destroyed.
+pub fn has_semaphores(env: &Environment) -> bool {
+    !State::get(env).semaphores.is_empty()
+}
+
 struct MachSemaphoreHostObject {
     libc_sem_name: ConstPtr<u8>,
 }
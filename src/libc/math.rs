@@ -20,9 +20,6 @@ use crate::Environment;
 // TODO: These should also have `long double` variants, which can probably just
 // alias the `double` ones.
 
-fn abs(_env: &mut Environment, arg: i32) -> i32 {
-    arg.abs()
-}
 fn sin(env: &mut Environment, arg: f64) -> f64 {
     // TODO: handle errno properly
     set_errno(env, 0);
@@ -381,7 +378,6 @@ fn fminf(_env: &mut Environment, arg1: f32, arg2: f32) -> f32 {
 }
 
 pub const FUNCTIONS: FunctionExports = &[
-    export_c_func!(abs(_)),
     // Trigonometric functions
     export_c_func!(sin(_)),
     export_c_func!(sinf(_)),
@@ -3,42 +3,199 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/.
  */
+//! `sys/mman.h`
 
 use crate::dyld::FunctionExports;
 use crate::environment::Environment;
 use crate::export_c_func;
-use crate::libc::errno::set_errno;
+use crate::libc::errno::{set_errno, EBADF, EINVAL, ENODEV};
 use crate::libc::posix_io;
-use crate::libc::posix_io::{off_t, FileDescriptor, SEEK_SET};
-use crate::mem::{GuestUSize, MutVoidPtr};
+use crate::libc::posix_io::{off_t, FileDescriptor, SEEK_CUR, SEEK_SET};
+use crate::mem::{GuestUSize, MutVoidPtr, Ptr};
+use std::collections::HashMap;
+use std::io::{Seek, SeekFrom, Write};
 
+pub const PROT_READ: i32 = 0x1;
+pub const PROT_WRITE: i32 = 0x2;
+
+pub const MAP_SHARED: i32 = 0x0001;
+pub const MAP_PRIVATE: i32 = 0x0002;
+pub const MAP_FIXED: i32 = 0x0010;
 #[allow(dead_code)]
-const MAP_FILE: i32 = 0x0000;
-const MAP_ANON: i32 = 0x1000;
+pub const MAP_FILE: i32 = 0x0000;
+pub const MAP_ANON: i32 = 0x1000;
+
+fn map_failed() -> MutVoidPtr {
+    Ptr::from_bits(u32::MAX)
+}
 
-/// Our implementation of mmap is really simple: it's just load entirety of
-/// file in memory!
+#[derive(Default)]
+pub struct State {
+    /// Active mappings, keyed by their address.
+    mappings: HashMap<u32, Mapping>,
+}
+
+struct Mapping {
+    len: GuestUSize,
+    /// For writable `MAP_SHARED` file mappings: the file and offset the
+    /// content should be written back to on `munmap()`.
+    write_back: Option<(std::fs::File, off_t)>,
+}
+
+/// Returns [true] if the app has any memory mapped with `mmap()`.
+pub fn has_mappings(env: &Environment) -> bool {
+    !env.libc_state.mmap.mappings.is_empty()
+}
+
+/// Our implementation of mmap is really simple: rather than actually mapping
+/// the file, the requested range is copied into newly-allocated memory.
+/// Changes to a writable `MAP_SHARED` mapping are written back on `munmap()`,
+/// but not before then.
 fn mmap(
     env: &mut Environment,
     addr: MutVoidPtr,
     len: GuestUSize,
-    _prot: i32,
+    prot: i32,
     flags: i32,
     fd: FileDescriptor,
     offset: off_t,
 ) -> MutVoidPtr {
-    // TODO: handle errno properly
     set_errno(env, 0);
 
-    assert!(addr.is_null());
-    assert_eq!(offset, 0);
-    assert_eq!((flags & MAP_ANON), 0);
-    let new_offset = posix_io::lseek(env, fd, offset, SEEK_SET);
-    assert_eq!(new_offset, offset);
+    if len == 0 || (flags & (MAP_SHARED | MAP_PRIVATE)) == 0 {
+        set_errno(env, EINVAL);
+        return map_failed();
+    }
+    if (flags & MAP_FIXED) != 0 {
+        log!(
+            "TODO: mmap({:?}, {:#x}, {}, {:#x}, {}, {}) with MAP_FIXED",
+            addr,
+            len,
+            prot,
+            flags,
+            fd,
+            offset
+        );
+        set_errno(env, EINVAL);
+        return map_failed();
+    }
+    // Without MAP_FIXED, the address is only a hint and can be ignored.
+
+    if (flags & MAP_ANON) != 0 {
+        // Allocated memory is always zeroed.
+        let ptr = env.mem.alloc(len);
+        env.libc_state.mmap.mappings.insert(
+            ptr.to_bits(),
+            Mapping {
+                len,
+                write_back: None,
+            },
+        );
+        log_dbg!(
+            "mmap({:?}, {:#x}, {}, {:#x}, {}, {}) => {:?} (anonymous)",
+            addr,
+            len,
+            prot,
+            flags,
+            fd,
+            offset,
+            ptr
+        );
+        return ptr;
+    }
+
+    if !posix_io::is_open_file(env, fd) {
+        set_errno(env, EBADF);
+        return map_failed();
+    }
+
+    let write_back = if (flags & MAP_SHARED) != 0 && (prot & PROT_WRITE) != 0 {
+        let Some(file) = posix_io::try_clone_host_file(env, fd) else {
+            // Files in the app bundle are read-only.
+            set_errno(env, ENODEV);
+            return map_failed();
+        };
+        Some((file, offset))
+    } else {
+        None
+    };
+
+    // mmap() doesn't affect the file offset, so it has to be restored.
+    let old_offset = posix_io::lseek(env, fd, 0, SEEK_CUR);
+    if posix_io::lseek(env, fd, offset, SEEK_SET) != offset {
+        set_errno(env, EINVAL);
+        return map_failed();
+    }
     let ptr = env.mem.alloc(len);
+    // Reading less than `len` is fine: the rest of the mapping is zeroed, as
+    // it would be for the part of the last page past the end of the file.
     let read = posix_io::read(env, fd, ptr, len);
-    assert_eq!(read as u32, len);
+    posix_io::lseek(env, fd, old_offset, SEEK_SET);
+    if read < 0 {
+        env.mem.free(ptr);
+        set_errno(env, EINVAL);
+        return map_failed();
+    }
+
+    env.libc_state
+        .mmap
+        .mappings
+        .insert(ptr.to_bits(), Mapping { len, write_back });
+    log_dbg!(
+        "mmap({:?}, {:#x}, {}, {:#x}, {}, {}) => {:?} ({:#x} bytes read)",
+        addr,
+        len,
+        prot,
+        flags,
+        fd,
+        offset,
+        ptr,
+        read
+    );
+    set_errno(env, 0);
     ptr
 }
 
-pub const FUNCTIONS: FunctionExports = &[export_c_func!(mmap(_, _, _, _, _, _))];
+fn munmap(env: &mut Environment, addr: MutVoidPtr, len: GuestUSize) -> i32 {
+    set_errno(env, 0);
+
+    let Some(mapping) = env.libc_state.mmap.mappings.remove(&addr.to_bits()) else {
+        log!("munmap({:?}, {:#x}): no mapping at this address", addr, len);
+        set_errno(env, EINVAL);
+        return -1;
+    };
+    if len != mapping.len {
+        log!(
+            "TODO: munmap({:?}, {:#x}) of part of a {:#x} byte mapping, unmapping all of it",
+            addr,
+            len,
+            mapping.len
+        );
+    }
+
+    if let Some((mut file, offset)) = mapping.write_back {
+        let offset: u64 = offset.try_into().unwrap();
+        let res = file.metadata().and_then(|metadata| {
+            // The part of the mapping past the end of the file isn't written.
+            let len = metadata
+                .len()
+                .saturating_sub(offset)
+                .min(mapping.len.into());
+            let bytes = env.mem.bytes_at(addr.cast(), len.try_into().unwrap());
+            file.seek(SeekFrom::Start(offset))?;
+            file.write_all(bytes)
+        });
+        if let Err(e) = res {
+            log!("Warning: munmap({:?}) failed to write back: {}", addr, e);
+        }
+    }
+
+    env.mem.free(addr);
+    log_dbg!("munmap({:?}, {:#x}) => 0", addr, len);
+    0
+}
+
+pub const FUNCTIONS: FunctionExports = &[
+    export_c_func!(mmap(_, _, _, _, _, _)),
+    export_c_func!(munmap(_, _)),
+];
@@ -4,24 +4,343 @@
  * file, You can obtain one at https://mozilla.org/MPL/2.0/.
  */
 //! `netdb.h`
+//!
+//! Host names are resolved using the host OS's resolver, but only if network
+//! access has been allowed with the `--enable-networking` option, since
+//! otherwise an app could leak information by doing DNS lookups.
 
 use crate::dyld::FunctionExports;
 use crate::export_c_func;
-use crate::mem::{ConstPtr, MutPtr, Ptr};
+use crate::libc::socket::{socklen_t, AF_INET, AF_INET6, AF_UNSPEC, SOCK_DGRAM, SOCK_STREAM};
+use crate::mem::{
+    guest_size_of, ConstPtr, GuestUSize, MemSnapshot, MutPtr, MutVoidPtr, Ptr, SafeRead,
+};
 use crate::Environment;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, ToSocketAddrs};
+
+#[derive(Default)]
+pub struct State {
+    /// Allocations making up the result of the last `gethostbyname()` call.
+    /// From the app's perspective, this is static storage that is overwritten
+    /// by the next call, so they can be freed then.
+    hostent_allocations: Vec<MutVoidPtr>,
+}
+impl State {
+    fn get(env: &mut Environment) -> &mut Self {
+        &mut env.libc_state.netdb
+    }
+    /// Forget allocations that won't exist once `mem` is restored, see
+    /// [crate::Environment::load_snapshot].
+    pub fn forget_pointers_not_in(&mut self, mem: &MemSnapshot) {
+        self.hostent_allocations
+            .retain(|ptr| mem.is_used(ptr.to_bits()));
+    }
+}
+
+pub const IPPROTO_TCP: i32 = 6;
+const IPPROTO_UDP: i32 = 17;
+
+const AI_PASSIVE: i32 = 0x1;
+const AI_CANONNAME: i32 = 0x2;
+const AI_NUMERICHOST: i32 = 0x4;
+
+const EAI_FAIL: i32 = 4;
+const EAI_FAMILY: i32 = 5;
+const EAI_NONAME: i32 = 8;
+const EAI_SERVICE: i32 = 9;
+const EAI_SOCKTYPE: i32 = 10;
 
-// TODO: struct definition
 #[allow(non_camel_case_types)]
-struct hostent {}
+#[repr(C, packed)]
+pub struct hostent {
+    h_name: MutPtr<u8>,
+    h_aliases: MutPtr<MutPtr<u8>>,
+    h_addrtype: i32,
+    h_length: i32,
+    h_addr_list: MutPtr<MutPtr<u8>>,
+}
+unsafe impl SafeRead for hostent {}
 
-fn gethostbyname(env: &mut Environment, name: ConstPtr<u8>) -> MutPtr<hostent> {
-    log!(
-        "TODO: gethostbyname({:?} \"{}\") => NULL",
-        name,
-        env.mem.cstr_at_utf8(name).unwrap()
+/// Note that the order of `ai_canonname` and `ai_addr` differs from glibc.
+#[allow(non_camel_case_types)]
+#[repr(C, packed)]
+pub struct addrinfo {
+    ai_flags: i32,
+    ai_family: i32,
+    ai_socktype: i32,
+    ai_protocol: i32,
+    ai_addrlen: socklen_t,
+    ai_canonname: MutPtr<u8>,
+    /// `struct sockaddr *`
+    ai_addr: MutVoidPtr,
+    ai_next: MutPtr<addrinfo>,
+}
+unsafe impl SafeRead for addrinfo {}
+
+/// The port and address are in network byte order (big-endian).
+#[allow(non_camel_case_types)]
+#[repr(C, packed)]
+pub struct sockaddr_in {
+    pub sin_len: u8,
+    pub sin_family: u8,
+    pub sin_port: [u8; 2],
+    pub sin_addr: [u8; 4],
+    pub sin_zero: [u8; 8],
+}
+unsafe impl SafeRead for sockaddr_in {}
+
+/// The port, flow info and address are in network byte order (big-endian).
+#[allow(non_camel_case_types)]
+#[repr(C, packed)]
+struct sockaddr_in6 {
+    sin6_len: u8,
+    sin6_family: u8,
+    sin6_port: [u8; 2],
+    sin6_flowinfo: [u8; 4],
+    sin6_addr: [u8; 16],
+    sin6_scope_id: u32,
+}
+unsafe impl SafeRead for sockaddr_in6 {}
+
+/// Resolve a host name or numeric address. On failure, the `EAI_` error code is
+/// returned.
+fn resolve_host(env: &Environment, name: &str, numeric_only: bool) -> Result<Vec<IpAddr>, i32> {
+    if let Ok(addr) = name.parse::<IpAddr>() {
+        return Ok(vec![addr]);
+    }
+    if numeric_only {
+        return Err(EAI_NONAME);
+    }
+    if !env.options.network_access {
+        log!(
+            "Warning: The app tried to look up the host {:?}, but network access is disabled. Use the --enable-networking option to allow this.",
+            name
+        );
+        return Err(EAI_FAIL);
+    }
+    // TODO: This blocks the whole emulator until the lookup is done.
+    match (name, 0).to_socket_addrs() {
+        Ok(addrs) => Ok(addrs.map(|addr| addr.ip()).collect()),
+        Err(e) => {
+            log_dbg!("Host lookup for {:?} failed: {}", name, e);
+            Err(EAI_NONAME)
+        }
+    }
+}
+
+/// Allocate a `struct sockaddr_in` or `struct sockaddr_in6` in guest memory.
+fn alloc_sockaddr(env: &mut Environment, addr: IpAddr, port: u16) -> (MutVoidPtr, socklen_t) {
+    match addr {
+        IpAddr::V4(addr) => {
+            let size = guest_size_of::<sockaddr_in>();
+            let sockaddr = sockaddr_in {
+                sin_len: size as u8,
+                sin_family: AF_INET as u8,
+                sin_port: port.to_be_bytes(),
+                sin_addr: addr.octets(),
+                sin_zero: [0; 8],
+            };
+            (env.mem.alloc_and_write(sockaddr).cast(), size)
+        }
+        IpAddr::V6(addr) => {
+            let size = guest_size_of::<sockaddr_in6>();
+            let sockaddr = sockaddr_in6 {
+                sin6_len: size as u8,
+                sin6_family: AF_INET6 as u8,
+                sin6_port: port.to_be_bytes(),
+                sin6_flowinfo: [0; 4],
+                sin6_addr: addr.octets(),
+                sin6_scope_id: 0,
+            };
+            (env.mem.alloc_and_write(sockaddr).cast(), size)
+        }
+    }
+}
+
+fn getaddrinfo(
+    env: &mut Environment,
+    node: ConstPtr<u8>,
+    service: ConstPtr<u8>,
+    hints: ConstPtr<addrinfo>,
+    res: MutPtr<MutPtr<addrinfo>>,
+) -> i32 {
+    let node = (!node.is_null()).then(|| env.mem.cstr_at_utf8(node).unwrap().to_string());
+    let service = (!service.is_null()).then(|| env.mem.cstr_at_utf8(service).unwrap().to_string());
+    let (flags, family, socktype) = if hints.is_null() {
+        (0, AF_UNSPEC, 0)
+    } else {
+        let hints = env.mem.read(hints);
+        (hints.ai_flags, hints.ai_family, hints.ai_socktype)
+    };
+    log_dbg!(
+        "getaddrinfo({:?}, {:?}, flags {:#x}, family {}, socktype {})",
+        node,
+        service,
+        flags,
+        family,
+        socktype
     );
-    // TODO: set h_errno
-    Ptr::null()
+
+    if node.is_none() && service.is_none() {
+        return EAI_NONAME;
+    }
+    if ![AF_UNSPEC, AF_INET, AF_INET6].contains(&family) {
+        return EAI_FAMILY;
+    }
+    let socktypes: &[(i32, i32)] = match socktype {
+        0 => &[(SOCK_STREAM, IPPROTO_TCP), (SOCK_DGRAM, IPPROTO_UDP)],
+        SOCK_STREAM => &[(SOCK_STREAM, IPPROTO_TCP)],
+        SOCK_DGRAM => &[(SOCK_DGRAM, IPPROTO_UDP)],
+        _ => return EAI_SOCKTYPE,
+    };
+    let port: u16 = match service {
+        None => 0,
+        Some(ref service) => {
+            let Ok(port) = service.parse() else {
+                log!("TODO: getaddrinfo() with service name {:?}", service);
+                return EAI_SERVICE;
+            };
+            port
+        }
+    };
+
+    let addrs = match node {
+        // IPv6 is listed first, like on Darwin.
+        None if flags & AI_PASSIVE != 0 => {
+            vec![Ipv6Addr::UNSPECIFIED.into(), Ipv4Addr::UNSPECIFIED.into()]
+        }
+        None => vec![Ipv6Addr::LOCALHOST.into(), Ipv4Addr::LOCALHOST.into()],
+        Some(ref node) => match resolve_host(env, node, flags & AI_NUMERICHOST != 0) {
+            Ok(addrs) => addrs,
+            Err(error) => return error,
+        },
+    };
+    let mut addrs: Vec<IpAddr> = addrs
+        .into_iter()
+        .filter(|addr| match addr {
+            IpAddr::V4(_) => family != AF_INET6,
+            IpAddr::V6(_) => family != AF_INET,
+        })
+        .collect();
+    addrs.dedup();
+    if addrs.is_empty() {
+        return EAI_NONAME;
+    }
+
+    // Build the linked list back-to-front.
+    let mut next: MutPtr<addrinfo> = Ptr::null();
+    for &addr in addrs.iter().rev() {
+        for &(socktype, protocol) in socktypes.iter().rev() {
+            let (ai_addr, ai_addrlen) = alloc_sockaddr(env, addr, port);
+            next = env.mem.alloc_and_write(addrinfo {
+                ai_flags: flags,
+                ai_family: match addr {
+                    IpAddr::V4(_) => AF_INET,
+                    IpAddr::V6(_) => AF_INET6,
+                },
+                ai_socktype: socktype,
+                ai_protocol: protocol,
+                ai_addrlen,
+                ai_canonname: Ptr::null(),
+                ai_addr,
+                ai_next: next,
+            });
+        }
+    }
+    // Only the first result has the canonical name.
+    // TODO: Get the real canonical name from the resolver.
+    if flags & AI_CANONNAME != 0 {
+        if let Some(ref node) = node {
+            let canonname = env.mem.alloc_and_write_cstr(node.as_bytes());
+            let mut first = env.mem.read(next);
+            first.ai_canonname = canonname;
+            env.mem.write(next, first);
+        }
+    }
+
+    env.mem.write(res, next);
+    0 // success
+}
+
+fn freeaddrinfo(env: &mut Environment, ai: MutPtr<addrinfo>) {
+    let mut ai = ai;
+    while !ai.is_null() {
+        let addrinfo {
+            ai_canonname,
+            ai_addr,
+            ai_next,
+            ..
+        } = env.mem.read(ai);
+        if !ai_canonname.is_null() {
+            env.mem.free(ai_canonname.cast());
+        }
+        env.mem.free(ai_addr);
+        env.mem.free(ai.cast());
+        ai = ai_next;
+    }
+}
+
+fn gethostbyname(env: &mut Environment, name: ConstPtr<u8>) -> MutPtr<hostent> {
+    let name = env.mem.cstr_at_utf8(name).unwrap().to_string();
+    log_dbg!("gethostbyname({:?})", name);
+
+    for ptr in std::mem::take(&mut State::get(env).hostent_allocations) {
+        env.mem.free(ptr);
+    }
+
+    // gethostbyname() only supports IPv4.
+    let addrs: Vec<Ipv4Addr> = match resolve_host(env, &name, false) {
+        Ok(addrs) => addrs
+            .into_iter()
+            .filter_map(|addr| match addr {
+                IpAddr::V4(addr) => Some(addr),
+                IpAddr::V6(_) => None,
+            })
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    if addrs.is_empty() {
+        // TODO: set h_errno
+        log_dbg!("=> NULL");
+        return Ptr::null();
+    }
+
+    let mut allocations = Vec::new();
+    let h_name = env.mem.alloc_and_write_cstr(name.as_bytes());
+    allocations.push(h_name.cast());
+    let h_aliases: MutPtr<MutPtr<u8>> = env.mem.alloc_and_write(Ptr::null());
+    allocations.push(h_aliases.cast());
+    let addr_count = addrs.len() as GuestUSize;
+    let h_addr_list: MutPtr<MutPtr<u8>> = env
+        .mem
+        .alloc((addr_count + 1) * guest_size_of::<MutPtr<u8>>())
+        .cast();
+    allocations.push(h_addr_list.cast());
+    for (i, addr) in addrs.iter().enumerate() {
+        let addr_ptr: MutPtr<u8> = env.mem.alloc(4).cast();
+        allocations.push(addr_ptr.cast());
+        env.mem
+            .bytes_at_mut(addr_ptr, 4)
+            .copy_from_slice(&addr.octets());
+        env.mem.write(h_addr_list + i as GuestUSize, addr_ptr);
+    }
+    env.mem.write(h_addr_list + addr_count, Ptr::null());
+    let result = env.mem.alloc_and_write(hostent {
+        h_name,
+        h_aliases,
+        h_addrtype: AF_INET,
+        h_length: 4,
+        h_addr_list,
+    });
+    allocations.push(result.cast());
+    State::get(env).hostent_allocations = allocations;
+
+    log_dbg!("=> {:?} ({:?})", result, addrs);
+    result
 }
 
-pub const FUNCTIONS: FunctionExports = &[export_c_func!(gethostbyname(_))];
+pub const FUNCTIONS: FunctionExports = &[
+    export_c_func!(getaddrinfo(_, _, _, _)),
+    export_c_func!(freeaddrinfo(_)),
+    export_c_func!(gethostbyname(_)),
+];
@@ -11,6 +11,7 @@ use crate::abi::DotDotDot;
 use crate::dyld::{export_c_func, FunctionExports};
 use crate::fs::{GuestFile, GuestOpenOptions, GuestPath};
 use crate::libc::errno::{set_errno, EBADF};
+use crate::libc::socket;
 use crate::mem::{ConstPtr, ConstVoidPtr, GuestISize, GuestUSize, MutPtr, MutVoidPtr, Ptr};
 use crate::Environment;
 use std::io::{Read, Seek, SeekFrom, Write};
@@ -28,10 +29,33 @@ impl State {
     }
 }
 
+/// Returns [true] if the app has any files open (other than stdin, stdout and
+/// stderr).
+pub fn has_open_files(env: &Environment) -> bool {
+    env.libc_state.posix_io.files.iter().any(Option::is_some)
+}
+
+/// Returns [true] if `fd` is a file descriptor for an open file.
+pub fn is_open_file(env: &mut Environment, fd: FileDescriptor) -> bool {
+    fd >= NORMAL_FILENO_BASE && env.libc_state.posix_io.file_for_fd(fd).is_some()
+}
+
+/// For `mmap()`: get a duplicate handle for the host file behind a file
+/// descriptor, so that it can outlive the file descriptor. Returns [None] if
+/// the file isn't a host file (e.g. it's in the app bundle).
+pub fn try_clone_host_file(env: &mut Environment, fd: FileDescriptor) -> Option<std::fs::File> {
+    if !is_open_file(env, fd) {
+        return None;
+    }
+    match env.libc_state.posix_io.file_for_fd(fd)?.file {
+        GuestFile::File(ref file) => file.try_clone().ok(),
+        _ => None,
+    }
+}
+
 struct PosixFileHostObject {
     file: GuestFile,
     needs_flush: bool,
-    reached_eof: bool,
 }
 
 // TODO: stdin/stdout/stderr handling somehow
@@ -45,6 +69,19 @@ fn fd_to_file_idx(fd: FileDescriptor) -> usize {
     fd.checked_sub(NORMAL_FILENO_BASE).unwrap() as usize
 }
 
+/// Find the lowest file descriptor that is used by neither a file nor a socket.
+pub fn next_free_fd(env: &Environment) -> FileDescriptor {
+    (0..)
+        .map(file_idx_to_fd)
+        .find(|&fd| {
+            !matches!(
+                env.libc_state.posix_io.files.get(fd_to_file_idx(fd)),
+                Some(Some(_))
+            ) && !socket::is_socket(env, fd)
+        })
+        .unwrap()
+}
+
 /// File descriptor type. This alias is for readability, POSIX just uses `int`.
 pub type FileDescriptor = i32;
 pub const STDIN_FILENO: FileDescriptor = 0;
@@ -71,6 +108,8 @@ pub const O_EXCL: OpenFlag = 0x800;
 /// File control command flags.
 /// This alias is for readability, POSIX just uses `int`.
 pub type FileControlCommand = i32;
+const F_GETFL: FileControlCommand = 3;
+const F_SETFL: FileControlCommand = 4;
 const F_RDADVISE: FileControlCommand = 44;
 const F_NOCACHE: FileControlCommand = 48;
 
@@ -163,27 +202,16 @@ pub fn open_direct(env: &mut Environment, path: ConstPtr<u8>, flags: i32) -> Fil
         .open_with_options(GuestPath::new(&path_string), options)
     {
         Ok(file) => {
-            let host_object = PosixFileHostObject {
-                file,
-                needs_flush,
-                reached_eof: false,
-            };
-
-            let idx = if let Some(free_idx) = env
-                .libc_state
-                .posix_io
-                .files
-                .iter()
-                .position(|f| f.is_none())
-            {
-                env.libc_state.posix_io.files[free_idx] = Some(host_object);
-                free_idx
-            } else {
-                let idx = env.libc_state.posix_io.files.len();
-                env.libc_state.posix_io.files.push(Some(host_object));
-                idx
-            };
-            file_idx_to_fd(idx)
+            let host_object = PosixFileHostObject { file, needs_flush };
+
+            let fd = next_free_fd(env);
+            let idx = fd_to_file_idx(fd);
+            let files = &mut env.libc_state.posix_io.files;
+            if idx >= files.len() {
+                files.resize_with(idx + 1, || None);
+            }
+            files[idx] = Some(host_object);
+            fd
         }
         Err(()) => {
             // TODO: set errno
@@ -224,10 +252,6 @@ pub fn read(
     let buffer_slice = env.mem.bytes_at_mut(buffer.cast(), size);
     match file.file.read(buffer_slice) {
         Ok(bytes_read) => {
-            if bytes_read == 0 && size != 0 {
-                // need to set EOF
-                file.reached_eof = true;
-            }
             if bytes_read < buffer_slice.len() {
                 log!(
                     "Warning: read({:?}, {:?}, {:#x}) read only {:#x} bytes",
@@ -261,25 +285,6 @@ pub fn read(
     }
 }
 
-/// Helper for C `feof()`.
-pub(super) fn eof(env: &mut Environment, fd: FileDescriptor) -> i32 {
-    let file = env.libc_state.posix_io.file_for_fd(fd).unwrap();
-    if file.reached_eof {
-        1
-    } else {
-        0
-    }
-}
-
-/// Helper for C `clearerr()`.
-pub(super) fn clearerr(env: &mut Environment, fd: FileDescriptor) {
-    // TODO: handle errno properly
-    set_errno(env, 0);
-
-    let file = env.libc_state.posix_io.file_for_fd(fd).unwrap();
-    file.reached_eof = false;
-}
-
 /// Helper for C `fflush()`.
 pub(super) fn fflush(env: &mut Environment, fd: FileDescriptor) -> i32 {
     // TODO: handle errno properly
@@ -365,13 +370,7 @@ pub fn lseek(env: &mut Environment, fd: FileDescriptor, offset: off_t, whence: i
     };
 
     let res = match file.file.seek(from) {
-        Ok(new_offset) => {
-            // "A successful call to the fseek() function clears
-            // the end-of-file indicator for the stream..."
-            file.reached_eof = false;
-
-            new_offset.try_into().unwrap()
-        }
+        Ok(new_offset) => new_offset.try_into().unwrap(),
         // TODO: set errno
         Err(_) => -1,
     };
@@ -388,6 +387,10 @@ pub fn close(env: &mut Environment, fd: FileDescriptor) -> i32 {
         return 0;
     }
 
+    if socket::is_socket(env, fd) {
+        return socket::close(env, fd);
+    }
+
     let result = match env.libc_state.posix_io.files[fd_to_file_idx(fd)].take() {
         Some(file) => {
             // The actual closing of the file happens implicitly when `file`
@@ -521,6 +524,22 @@ fn fcntl(
     // TODO: handle errno properly
     set_errno(env, 0);
 
+    if socket::is_socket(env, fd) {
+        return match cmd {
+            F_GETFL => {
+                let non_blocking = socket::get_non_blocking(env, fd);
+                O_RDWR | if non_blocking { O_NONBLOCK } else { 0 }
+            }
+            F_SETFL => {
+                let mut args = args.start();
+                let flags: OpenFlag = args.next(env);
+                socket::set_non_blocking(env, fd, (flags & O_NONBLOCK) != 0);
+                0 // success
+            }
+            _ => unimplemented!("fcntl() command {} for socket {}", cmd, fd),
+        };
+    }
+
     if fd >= NORMAL_FILENO_BASE
         && env
             .libc_state
@@ -28,6 +28,9 @@ macro_rules! check_magic {
     }
 }
 
+use crate::environment::MutexId;
+use crate::ThreadId;
+
 pub mod cond;
 pub mod key;
 pub mod mutex;
@@ -40,3 +43,45 @@ pub struct State {
     key: key::State,
     thread: thread::State,
 }
+impl State {
+    /// Copy the state for [crate::snapshot], see [PthreadSnapshot].
+    pub fn snapshot(&self) -> PthreadSnapshot {
+        let (conds, cond_mutexes) = self.cond.snapshot();
+        PthreadSnapshot {
+            conds,
+            cond_mutexes,
+            keys: self.key.snapshot(),
+            threads: self.thread.snapshot(),
+        }
+    }
+
+    /// Check that `snapshot` can be restored. Thread objects aren't fully
+    /// saved, so the same ones must exist now as when the snapshot was made.
+    pub fn check_snapshot(&self, snapshot: &PthreadSnapshot) -> Result<(), String> {
+        self.thread.check_snapshot(&snapshot.threads)
+    }
+
+    /// Replace the state with a snapshot that has been checked with
+    /// [Self::check_snapshot].
+    pub fn restore_snapshot(&mut self, snapshot: &PthreadSnapshot) {
+        self.cond
+            .restore_snapshot(&snapshot.conds, &snapshot.cond_mutexes);
+        self.key.restore_snapshot(&snapshot.keys);
+        self.thread.restore_snapshot(&snapshot.threads);
+    }
+}
+
+/// Copy of the pthread state that is saved in a [crate::snapshot]. Addresses
+/// are in ascending order.
+#[derive(Debug, PartialEq)]
+pub struct PthreadSnapshot {
+    /// Address and "done" flag of each condition variable.
+    pub conds: Vec<(u32, bool)>,
+    /// Address of each condition variable that is being waited on, and the
+    /// mutex that was passed to `pthread_cond_wait()`.
+    pub cond_mutexes: Vec<(u32, MutexId)>,
+    /// Destructor and thread-specific values of each key, see [key::State].
+    pub keys: Vec<Option<(u32, Vec<(ThreadId, u32)>)>>,
+    /// Address, thread and joining thread (if any) of each `pthread_t`.
+    pub threads: Vec<(u32, ThreadId, Option<ThreadId>)>,
+}
@@ -8,11 +8,11 @@
 use super::mutex::pthread_mutex_t;
 use crate::dyld::FunctionExports;
 use crate::libc::pthread::mutex::pthread_mutex_unlock;
-use crate::mem::{ConstPtr, MutPtr, SafeRead};
+use crate::mem::{ConstPtr, MutPtr, Ptr, SafeRead};
 use crate::{export_c_func, Environment};
 use std::collections::HashMap;
 
-use crate::environment::ThreadBlock;
+use crate::environment::{MutexId, ThreadBlock};
 
 #[repr(C, packed)]
 struct pthread_condattr_t {}
@@ -38,6 +38,37 @@ impl State {
     fn get_mut(env: &mut Environment) -> &mut Self {
         &mut env.libc_state.pthread.cond
     }
+    /// See [super::State::snapshot].
+    pub(super) fn snapshot(&self) -> (Vec<(u32, bool)>, Vec<(u32, MutexId)>) {
+        let mut conds: Vec<_> = self
+            .condition_variables
+            .iter()
+            .map(|(cond, host_object)| (cond.to_bits(), host_object.done))
+            .collect();
+        conds.sort_unstable();
+        let mut cond_mutexes: Vec<_> = self
+            .mutexes
+            .iter()
+            .map(|(cond, mutex)| (cond.to_bits(), mutex.mutex_id))
+            .collect();
+        cond_mutexes.sort_unstable();
+        (conds, cond_mutexes)
+    }
+    /// See [super::State::restore_snapshot].
+    pub(super) fn restore_snapshot(
+        &mut self,
+        conds: &[(u32, bool)],
+        cond_mutexes: &[(u32, MutexId)],
+    ) {
+        self.condition_variables = conds
+            .iter()
+            .map(|&(cond, done)| (Ptr::from_bits(cond), CondHostObject { done }))
+            .collect();
+        self.mutexes = cond_mutexes
+            .iter()
+            .map(|&(cond, mutex_id)| (Ptr::from_bits(cond), pthread_mutex_t::with_id(mutex_id)))
+            .collect();
+    }
 }
 
 pub struct CondHostObject {
@@ -5,8 +5,9 @@
  */
 //! Thread-specific data keys.
 
-use crate::abi::GuestFunction;
+use crate::abi::{CallFromHost, GuestFunction};
 use crate::dyld::{export_c_func, FunctionExports};
+use crate::libc::errno::EINVAL;
 use crate::mem::{ConstVoidPtr, MutPtr, MutVoidPtr, Ptr};
 use crate::{Environment, ThreadId};
 use std::collections::HashMap;
@@ -15,8 +16,42 @@ use std::collections::HashMap;
 pub struct State {
     /// The `pthread_key_t` value, with 1 subtracted, is the index into this
     /// vector. The tuple contains the map of thread-specific data pointers plus
-    /// the destructor pointer.
-    keys: Vec<(HashMap<ThreadId, MutVoidPtr>, GuestFunction)>,
+    /// the destructor pointer. Deleted keys are [None].
+    keys: Vec<Option<(HashMap<ThreadId, MutVoidPtr>, GuestFunction)>>,
+}
+
+impl State {
+    /// See [super::State::snapshot].
+    pub(super) fn snapshot(&self) -> Vec<Option<(u32, Vec<(ThreadId, u32)>)>> {
+        self.keys
+            .iter()
+            .map(|key| {
+                key.as_ref().map(|(values, destructor)| {
+                    let mut values: Vec<_> = values
+                        .iter()
+                        .map(|(&thread, value)| (thread, value.to_bits()))
+                        .collect();
+                    values.sort_unstable();
+                    (destructor.addr_with_thumb_bit(), values)
+                })
+            })
+            .collect()
+    }
+    /// See [super::State::restore_snapshot].
+    pub(super) fn restore_snapshot(&mut self, keys: &[Option<(u32, Vec<(ThreadId, u32)>)>]) {
+        self.keys = keys
+            .iter()
+            .map(|key| {
+                key.as_ref().map(|(destructor, values)| {
+                    let values: HashMap<_, _> = values
+                        .iter()
+                        .map(|&(thread, value)| (thread, Ptr::from_bits(value)))
+                        .collect();
+                    (values, GuestFunction::from_addr_with_thumb_bit(*destructor))
+                })
+            })
+            .collect();
+    }
 }
 
 fn get_state(env: &mut Environment) -> &mut State {
@@ -25,23 +60,52 @@ fn get_state(env: &mut Environment) -> &mut State {
 
 type pthread_key_t = u32;
 
+/// Maximum number of times destructors are called for a thread, in case
+/// destructors set new values. Value taken from Apple's `limits.h`.
+const PTHREAD_DESTRUCTOR_ITERATIONS: usize = 4;
+
+fn key_to_idx(key: pthread_key_t) -> Option<usize> {
+    key.checked_sub(1).map(|idx| idx.try_into().unwrap())
+}
+
 fn pthread_key_create(
     env: &mut Environment,
     key_ptr: MutPtr<pthread_key_t>,
     destructor: GuestFunction, // void (*destructor)(void *), may be NULL
 ) -> i32 {
-    let idx = get_state(env).keys.len();
+    let keys = &mut get_state(env).keys;
+    // Reuse the slot of a deleted key if there is one.
+    let idx = keys
+        .iter()
+        .position(|key| key.is_none())
+        .unwrap_or_else(|| {
+            keys.push(None);
+            keys.len() - 1
+        });
+    keys[idx] = Some((HashMap::new(), destructor));
     let key: pthread_key_t = (idx + 1).try_into().unwrap();
-    get_state(env).keys.push((HashMap::new(), destructor));
     env.mem.write(key_ptr, key);
     0 // success
 }
 
+fn pthread_key_delete(env: &mut Environment, key: pthread_key_t) -> i32 {
+    // Destructors are not called when a key is deleted.
+    match key_to_idx(key).and_then(|idx| get_state(env).keys.get_mut(idx)) {
+        Some(key @ Some(_)) => {
+            *key = None;
+            0 // success
+        }
+        _ => EINVAL,
+    }
+}
+
 fn pthread_getspecific(env: &mut Environment, key: pthread_key_t) -> MutVoidPtr {
     // Use of invalid key is undefined, panicking is fine.
-    let idx: usize = key.checked_sub(1).unwrap().try_into().unwrap();
+    let idx = key_to_idx(key).unwrap();
     let current_thread = env.current_thread;
     get_state(env).keys[idx]
+        .as_ref()
+        .unwrap()
         .0
         .get(&current_thread)
         .copied()
@@ -49,17 +113,57 @@ fn pthread_getspecific(env: &mut Environment, key: pthread_key_t) -> MutVoidPtr
 }
 
 fn pthread_setspecific(env: &mut Environment, key: pthread_key_t, value: ConstVoidPtr) -> i32 {
-    // TODO: return error instead of panicking if key is invalid?
-    let idx: usize = key.checked_sub(1).unwrap().try_into().unwrap();
     let current_thread = env.current_thread;
-    get_state(env).keys[idx]
-        .0
-        .insert(current_thread, value.cast_mut());
+    let keys = &mut get_state(env).keys;
+    let Some(Some((values, _))) = key_to_idx(key).and_then(|idx| keys.get_mut(idx)) else {
+        return EINVAL;
+    };
+    values.insert(current_thread, value.cast_mut());
     0 // success
 }
 
+/// Call the destructors for the current thread's thread-specific data, as part
+/// of the thread finishing, and then forget about its data.
+pub fn run_destructors(env: &mut Environment) {
+    let current_thread = env.current_thread;
+    for _ in 0..PTHREAD_DESTRUCTOR_ITERATIONS {
+        let mut called_any = false;
+        for idx in 0..get_state(env).keys.len() {
+            let Some((values, destructor)) = &mut get_state(env).keys[idx] else {
+                continue;
+            };
+            let destructor = *destructor;
+            if destructor.to_ptr().is_null() {
+                continue;
+            }
+            // The value is set to NULL before the destructor is called.
+            let Some(value) = values.remove(&current_thread) else {
+                continue;
+            };
+            if value.is_null() {
+                continue;
+            }
+            log_dbg!(
+                "Calling destructor {:?} for key {} with {:?}",
+                destructor,
+                idx + 1,
+                value
+            );
+            () = destructor.call_from_host(env, (value,));
+            called_any = true;
+        }
+        if !called_any {
+            break;
+        }
+    }
+    for (values, _) in get_state(env).keys.iter_mut().flatten() {
+        values.remove(&current_thread);
+    }
+}
+
 pub const FUNCTIONS: FunctionExports = &[
     export_c_func!(pthread_key_create(_, _)),
+    export_c_func!(pthread_key_delete(_)),
     export_c_func!(pthread_getspecific(_)),
     export_c_func!(pthread_setspecific(_, _)),
 ];
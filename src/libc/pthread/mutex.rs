@@ -36,6 +36,15 @@ pub struct pthread_mutex_t {
     pub mutex_id: MutexId,
 }
 unsafe impl SafeRead for pthread_mutex_t {}
+impl pthread_mutex_t {
+    /// For restoring a [crate::snapshot].
+    pub(super) fn with_id(mutex_id: MutexId) -> Self {
+        pthread_mutex_t {
+            magic: MAGIC_MUTEX,
+            mutex_id,
+        }
+    }
+}
 
 /// Arbitrarily-chosen magic number for `pthread_mutexattr_t` (not Apple's).
 const MAGIC_MUTEXATTR: u32 = u32::from_be_bytes(*b"MuAt");
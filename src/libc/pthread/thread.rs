@@ -5,13 +5,14 @@
  */
 //! Threads.
 
-use crate::abi::GuestFunction;
+use crate::abi::{CallFromHost, GuestFunction};
 use crate::dyld::{export_c_func, FunctionExports};
-use crate::libc::errno::{EDEADLK, EINVAL};
+use crate::libc::errno::{EDEADLK, EINVAL, ESRCH};
 use crate::libc::mach_host::PAGE_SIZE;
-use crate::mem::{self, ConstPtr, ConstVoidPtr, GuestUSize, MutPtr, MutVoidPtr, SafeRead};
+use crate::mem::{self, ConstPtr, ConstVoidPtr, GuestUSize, MutPtr, MutVoidPtr, Ptr, SafeRead};
 use crate::{Environment, ThreadId};
 use std::collections::HashMap;
+use std::time::Duration;
 
 #[derive(Default)]
 pub struct State {
@@ -22,6 +23,42 @@ impl State {
     fn get(env: &mut Environment) -> &mut Self {
         &mut env.libc_state.pthread.thread
     }
+    /// See [super::State::snapshot].
+    pub(super) fn snapshot(&self) -> Vec<(u32, ThreadId, Option<ThreadId>)> {
+        let mut threads: Vec<_> = self
+            .threads
+            .iter()
+            .map(|(ptr, host_object)| (ptr.to_bits(), host_object.thread_id, host_object.joined_by))
+            .collect();
+        threads.sort_unstable();
+        threads
+    }
+    /// See [super::State::check_snapshot].
+    pub(super) fn check_snapshot(
+        &self,
+        threads: &[(u32, ThreadId, Option<ThreadId>)],
+    ) -> Result<(), String> {
+        let same = threads.len() == self.threads.len()
+            && threads.iter().all(|&(ptr, thread_id, _)| {
+                self.threads
+                    .get(&pthread_t::from_bits(ptr))
+                    .is_some_and(|host_object| host_object.thread_id == thread_id)
+            });
+        if same {
+            Ok(())
+        } else {
+            Err("pthread objects have been created since the snapshot was made".to_string())
+        }
+    }
+    /// See [super::State::restore_snapshot].
+    pub(super) fn restore_snapshot(&mut self, threads: &[(u32, ThreadId, Option<ThreadId>)]) {
+        for &(ptr, _, joined_by) in threads {
+            self.threads
+                .get_mut(&pthread_t::from_bits(ptr))
+                .unwrap()
+                .joined_by = joined_by;
+        }
+    }
 }
 
 /// Apple's implementation is a 4-byte magic number followed by an 36-byte
@@ -44,15 +81,31 @@ const DEFAULT_ATTR: pthread_attr_t = pthread_attr_t {
     _unused: [0; 7],
 };
 
-/// Apple's implementation is a 4-byte magic number followed by a massive
-/// (>4KiB) opaque region. We will store the actual data on the host instead.
+/// Apple's implementation is a 4-byte magic number, followed by the cleanup
+/// handler stack, followed by a massive (>4KiB) opaque region. We will store
+/// the actual data on the host instead.
+///
+/// The cleanup handler stack has to be in guest memory because the
+/// `pthread_cleanup_push` and `pthread_cleanup_pop` macros manipulate it
+/// directly.
 #[repr(C, packed)]
 pub struct OpaqueThread {
     /// Magic number (must be [MAGIC_THREAD])
     magic: u32,
+    cleanup_stack: MutPtr<darwin_pthread_handler_rec>,
 }
 unsafe impl SafeRead for OpaqueThread {}
 
+/// Cleanup handler, see [OpaqueThread::cleanup_stack].
+#[allow(non_camel_case_types)]
+#[repr(C, packed)]
+struct darwin_pthread_handler_rec {
+    routine: GuestFunction, // void (*)(void *)
+    arg: MutVoidPtr,
+    next: MutPtr<darwin_pthread_handler_rec>,
+}
+unsafe impl SafeRead for darwin_pthread_handler_rec {}
+
 #[allow(non_camel_case_types)]
 pub type pthread_t = MutPtr<OpaqueThread>;
 
@@ -72,6 +125,9 @@ type DetachState = i32;
 const PTHREAD_CREATE_JOINABLE: DetachState = 1;
 pub const PTHREAD_CREATE_DETACHED: DetachState = 2;
 
+/// Return value of a thread that was cancelled.
+pub const PTHREAD_CANCELED: MutVoidPtr = MutVoidPtr::from_bits(1);
+
 /// Value taken from an iOS 2.0 simulator
 const PTHREAD_STACK_MIN: GuestUSize = 2 * PAGE_SIZE;
 
@@ -137,6 +193,7 @@ pub fn pthread_create(
 
     let opaque = env.mem.alloc_and_write(OpaqueThread {
         magic: MAGIC_THREAD,
+        cleanup_stack: Ptr::null(),
     });
     env.mem.write(thread, opaque);
 
@@ -155,6 +212,23 @@ pub fn pthread_create(
     0 // success
 }
 
+/// Create a detached thread for use by host code, e.g. a worker thread for a
+/// queue of operations.
+pub fn create_detached_thread(
+    env: &mut Environment,
+    start_routine: GuestFunction, // (*void)(void *)
+    user_data: MutVoidPtr,
+) {
+    let attr = env.mem.alloc_and_write(pthread_attr_t {
+        detachstate: PTHREAD_CREATE_DETACHED,
+        ..DEFAULT_ATTR
+    });
+    let thread: MutPtr<pthread_t> = env.mem.alloc_and_write(Ptr::null());
+    assert!(pthread_create(env, thread, attr.cast_const(), start_routine, user_data) == 0);
+    env.mem.free(attr.cast());
+    env.mem.free(thread.cast());
+}
+
 fn pthread_self(env: &mut Environment) -> pthread_t {
     let current_thread = env.current_thread;
 
@@ -165,6 +239,7 @@ fn pthread_self(env: &mut Environment) -> pthread_t {
 
         let opaque = env.mem.alloc_and_write(OpaqueThread {
             magic: MAGIC_THREAD,
+            cleanup_stack: Ptr::null(),
         });
 
         assert!(!State::get(env).threads.contains_key(&opaque));
@@ -240,12 +315,63 @@ fn pthread_join(env: &mut Environment, thread: pthread_t, retval: MutPtr<MutVoid
     0
 }
 
+/// Request that a thread be cancelled. Only deferred cancellation is
+/// supported: the thread is cancelled once it is blocked at a cancellation
+/// point (e.g. sleeping, or waiting on a semaphore or condition variable), see
+/// [Environment::request_thread_cancellation].
+fn pthread_cancel(env: &mut Environment, thread: pthread_t) -> i32 {
+    let Some(host_object) = State::get(env).threads.get(&thread) else {
+        log_dbg!("pthread_cancel({:?}) => ESRCH", thread);
+        return ESRCH;
+    };
+    let thread_id = host_object.thread_id;
+    if thread_id == 0 {
+        // Cancelling the main thread is allowed by POSIX, but it would end the
+        // app, which isn't supported yet.
+        log!("TODO: pthread_cancel({:?}) for the main thread", thread);
+        return 0;
+    }
+    log_dbg!("pthread_cancel({:?}) (thread ID: {})", thread, thread_id);
+    env.request_thread_cancellation(thread_id);
+    0 // success
+}
+
 fn pthread_setcanceltype(_env: &mut Environment, type_: i32, oldtype: MutPtr<i32>) -> i32 {
     log!("TODO: pthread_setcanceltype({}, {:?})", type_, oldtype);
     0
 }
-fn pthread_testcancel(_env: &mut Environment) {
-    log!("TODO: pthread_testcancel()");
+fn pthread_testcancel(env: &mut Environment) {
+    testcancel(env);
+}
+
+/// Make the calling host function a cancellation point. If cancellation of the
+/// current thread has been requested, this puts it to sleep for zero time, so
+/// that it gets cancelled once the host function returns. Callers should
+/// return immediately if [true] is returned.
+pub fn testcancel(env: &mut Environment) -> bool {
+    if !env.thread_cancellation_requested(env.current_thread) {
+        return false;
+    }
+    env.sleep(Duration::ZERO, /* tail_call: */ true);
+    true
+}
+
+/// Pop and call each of the current thread's cleanup handlers (see
+/// [OpaqueThread::cleanup_stack]), as part of cancelling it.
+pub fn run_cleanup_handlers(env: &mut Environment) {
+    let thread = pthread_self(env);
+    loop {
+        let OpaqueThread { cleanup_stack, .. } = env.mem.read(thread);
+        if cleanup_stack.is_null() {
+            break;
+        }
+        let darwin_pthread_handler_rec { routine, arg, next } = env.mem.read(cleanup_stack);
+        let mut thread_copy = env.mem.read(thread);
+        thread_copy.cleanup_stack = next;
+        env.mem.write(thread, thread_copy);
+        log_dbg!("Calling cleanup handler {:?} with {:?}", routine, arg);
+        () = routine.call_from_host(env, (arg,));
+    }
 }
 
 #[allow(non_camel_case_types)]
@@ -296,6 +422,7 @@ pub const FUNCTIONS: FunctionExports = &[
     export_c_func!(pthread_create(_, _, _, _)),
     export_c_func!(pthread_self()),
     export_c_func!(pthread_join(_, _)),
+    export_c_func!(pthread_cancel(_)),
     export_c_func!(pthread_setcanceltype(_, _)),
     export_c_func!(pthread_testcancel()),
     export_c_func!(pthread_mach_thread_np(_)),
@@ -9,6 +9,7 @@ use crate::dyld::{export_c_func, FunctionExports};
 use crate::libc::errno::set_errno;
 use crate::libc::posix_io::stat::mode_t;
 use crate::libc::posix_io::{O_CREAT, O_EXCL};
+use crate::libc::pthread::thread::testcancel;
 use crate::mem::{ConstPtr, MutPtr};
 use crate::{Environment, ThreadId};
 use std::cell::RefCell;
@@ -32,6 +33,11 @@ impl State {
     }
 }
 
+/// Returns [true] if the app has any semaphores open.
+pub fn has_open_semaphores(env: &Environment) -> bool {
+    !State::get(env).open_semaphores.is_empty()
+}
+
 #[allow(non_camel_case_types)]
 pub type sem_t = i32;
 
@@ -99,6 +105,11 @@ pub fn sem_wait(env: &mut Environment, sem: MutPtr<sem_t>) -> i32 {
     // TODO: handle errno properly
     set_errno(env, 0);
 
+    // This is a cancellation point even if the semaphore doesn't block.
+    if testcancel(env) {
+        return 0;
+    }
+
     env.sem_decrement(sem, true);
     0 // success
 }
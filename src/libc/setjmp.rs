@@ -5,26 +5,128 @@
  */
 //! `setjmp.h`.
 //!
-//! We don't have a real implementation for this right now. It could be quite
-//! tricky to write one, considering that we would need to unwind through host
-//! code, and somehow do so selectively since we have a mix of stack frames from
-//! different guest threads. For the moment, we simply pray the app never throws
-//! exceptions.
+//! `setjmp()` saves the registers the ARM procedure call standard requires to
+//! be preserved (R4-R11, SP, LR, and the VFP registers D8-D15 plus FPSCR) in
+//! the guest's `jmp_buf`. `longjmp()` restores them, which means that when its
+//! stub returns to the restored LR, it is as if `setjmp()` returned again.
+//!
+//! Only jumps within guest code are supported. If a host function is on the
+//! call stack between the `longjmp()` and the `setjmp()` (e.g. `qsort()`
+//! calling a comparison function that jumps out), its host stack frames can't
+//! be unwound, so the jump is rejected with a panic rather than silently
+//! corrupting the state. Jumping to another thread's `jmp_buf` is rejected in
+//! the same way.
 //!
 //! Note that `setjmp` and `longjmp` are defined as macros in the C standard,
 //! but it seems like the implementation of these on iPhone OS uses real
-//! functions, at least for the former.
+//! functions.
 
-use crate::dyld::{export_c_func, FunctionExports};
+use crate::cpu::Cpu;
+use crate::dyld::{export_c_func, export_c_func_aliased, FunctionExports};
+use crate::environment::ThreadId;
+use crate::mem::{ConstPtr, MutPtr, SafeRead};
 use crate::Environment;
 
-/// The signature of this is incomplete because it's a stub (see module docs).
-fn setjmp(env: &mut Environment) -> i32 {
-    log_dbg!(
-        "TODO: setjmp() at {:#x}",
-        env.cpu.regs()[crate::cpu::Cpu::LR]
-    );
+/// Size of `jmp_buf` on iPhone OS, in 32-bit words (`_JBLEN`).
+const JBLEN: usize = 10 + 16 + 2;
+
+/// Layout of the data touchHLE stores in a `jmp_buf`. This doesn't match
+/// Apple's layout, but the contents are private to the implementation anyway;
+/// it just has to fit in [JBLEN] words.
+#[repr(C, packed)]
+struct JmpBufContents {
+    /// R4-R11, SP and LR.
+    core_regs: [u32; 10],
+    /// D8-D15, as pairs of 32-bit words.
+    ext_regs: [u32; 16],
+    fpscr: u32,
+    /// See [context_tag].
+    context: u32,
+}
+unsafe impl SafeRead for JmpBufContents {}
+
+/// Identifies the thread and the host-to-guest call depth at which `setjmp()`
+/// was called, so `longjmp()` can check it isn't crossing either boundary.
+fn context_tag(thread: ThreadId, host_to_guest_call_depth: u32) -> u32 {
+    ((thread as u32) << 16) | (host_to_guest_call_depth & 0xffff)
+}
+
+fn setjmp(env: &mut Environment, buf: MutPtr<JmpBufContents>) -> i32 {
+    let regs = env.cpu.regs();
+    let mut core_regs = [0; 10];
+    core_regs[..8].copy_from_slice(&regs[4..=11]);
+    core_regs[8] = regs[Cpu::SP];
+    core_regs[9] = regs[Cpu::LR];
+    let mut ext_regs = [0; 16];
+    ext_regs.copy_from_slice(&env.cpu.ext_regs()[16..32]);
+
+    let contents = JmpBufContents {
+        core_regs,
+        ext_regs,
+        fpscr: env.cpu.fpscr(),
+        context: context_tag(env.current_thread, env.host_to_guest_call_depth()),
+    };
+    log_dbg!("setjmp({:?}) at {:#x}", buf, core_regs[9]);
+    env.mem.write(buf, contents);
     0 // no longjmp() was performed
 }
 
-pub const FUNCTIONS: FunctionExports = &[export_c_func!(setjmp())];
+/// `sigsetjmp()`. Signals aren't supported, so the signal mask is never saved.
+fn sigsetjmp(env: &mut Environment, buf: MutPtr<JmpBufContents>, _savemask: i32) -> i32 {
+    setjmp(env, buf)
+}
+
+/// The return value is what the `setjmp()` call appears to return.
+fn longjmp(env: &mut Environment, buf: ConstPtr<JmpBufContents>, val: i32) -> i32 {
+    let JmpBufContents {
+        core_regs,
+        ext_regs,
+        fpscr,
+        context,
+    } = env.mem.read(buf);
+
+    let current_context = context_tag(env.current_thread, env.host_to_guest_call_depth());
+    if context != current_context {
+        panic!(
+            "longjmp({:?}, {}) would cross a host function or thread boundary (jmp_buf context {:#x}, current context {:#x}), which is not supported!",
+            buf, val, context, current_context
+        );
+    }
+    log_dbg!("longjmp({:?}, {}) to {:#x}", buf, val, core_regs[9]);
+
+    let regs = env.cpu.regs_mut();
+    regs[4..=11].copy_from_slice(&core_regs[..8]);
+    regs[Cpu::SP] = core_regs[8];
+    regs[Cpu::LR] = core_regs[9];
+    env.cpu.ext_regs_mut()[16..32].copy_from_slice(&ext_regs);
+    env.cpu.set_fpscr(fpscr);
+
+    // setjmp() can't appear to return zero a second time.
+    if val == 0 {
+        1
+    } else {
+        val
+    }
+}
+
+pub const FUNCTIONS: FunctionExports = &[
+    export_c_func!(setjmp(_)),
+    // Signals aren't supported, so the variants that don't save the signal
+    // mask are the same.
+    export_c_func_aliased!("_setjmp", setjmp(_)),
+    export_c_func!(sigsetjmp(_, _)),
+    export_c_func!(longjmp(_, _)),
+    export_c_func_aliased!("_longjmp", longjmp(_, _)),
+    export_c_func_aliased!("siglongjmp", longjmp(_, _)),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mem::guest_size_of;
+
+    #[test]
+    fn jmp_buf_size() {
+        assert!(guest_size_of::<JmpBufContents>() as usize <= JBLEN * 4);
+    }
+}
@@ -3,30 +3,187 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/.
  */
+//! `signal.h`
+//!
+//! touchHLE doesn't deliver real signals to the app. The dispositions the app
+//! sets up are recorded, but only signals touchHLE raises itself (see
+//! [raise_internal_signal]) are ever delivered.
 
+use crate::abi::{CallFromHost, GuestFunction};
 use crate::dyld::FunctionExports;
 use crate::environment::Environment;
 use crate::export_c_func;
-use crate::libc::errno::set_errno;
-use crate::mem::{ConstVoidPtr, MutVoidPtr, Ptr};
+use crate::libc::errno::{set_errno, EINVAL};
+use crate::mem::{ConstPtr, MutPtr, MutVoidPtr, Ptr, SafeRead};
+use std::collections::HashMap;
 
-fn sigaction(env: &mut Environment, signum: i32, act: ConstVoidPtr, oldact: MutVoidPtr) -> i32 {
-    // TODO: handle errno properly
+pub const SIGINT: i32 = 2;
+pub const SIGKILL: i32 = 9;
+pub const SIGPIPE: i32 = 13;
+pub const SIGSTOP: i32 = 17;
+/// Number of signals + 1.
+pub const NSIG: i32 = 32;
+
+/// Default action handler value.
+pub const SIG_DFL: u32 = 0;
+/// Ignore action handler value.
+pub const SIG_IGN: u32 = 1;
+/// Returned by `signal()` on error.
+pub const SIG_ERR: u32 = u32::MAX;
+
+/// `sa_flags` value indicating the handler takes three arguments.
+pub const SA_SIGINFO: i32 = 0x40;
+
+#[allow(non_camel_case_types)]
+type sigset_t = u32;
+
+/// `struct sigaction`
+#[allow(non_camel_case_types)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[repr(C, packed)]
+pub struct sigaction {
+    /// `SIG_DFL`, `SIG_IGN` or a function pointer. This is a union of
+    /// `sa_handler` and `sa_sigaction` in C.
+    sa_handler: u32,
+    sa_mask: sigset_t,
+    sa_flags: i32,
+}
+unsafe impl SafeRead for sigaction {}
+
+#[derive(Default)]
+pub struct State {
+    /// Signal dispositions set by the app. Signals not in here have the
+    /// default disposition.
+    actions: HashMap<i32, sigaction>,
+}
+impl State {
+    fn action(&self, signum: i32) -> sigaction {
+        self.actions.get(&signum).copied().unwrap_or_default()
+    }
+
+    /// Change the disposition of a signal, returning the old one, or [Err] if
+    /// the signal number is invalid or the disposition can't be changed.
+    fn set_action(&mut self, signum: i32, action: sigaction) -> Result<sigaction, ()> {
+        if !(1..NSIG).contains(&signum) || signum == SIGKILL || signum == SIGSTOP {
+            return Err(());
+        }
+        Ok(self.actions.insert(signum, action).unwrap_or_default())
+    }
+}
+
+fn sigaction(
+    env: &mut Environment,
+    signum: i32,
+    act: ConstPtr<sigaction>,
+    oldact: MutPtr<sigaction>,
+) -> i32 {
     set_errno(env, 0);
 
-    log!("TODO: sigaction({:?}, {:?}, {:?})", signum, act, oldact);
+    let old = env.libc_state.signal.action(signum);
+    if !act.is_null() {
+        let new = env.mem.read(act);
+        if env.libc_state.signal.set_action(signum, new).is_err() {
+            set_errno(env, EINVAL);
+            return -1;
+        }
+        log_dbg!("sigaction({}, {:?}, {:?}): {:?}", signum, act, oldact, new);
+    } else if !(1..NSIG).contains(&signum) {
+        set_errno(env, EINVAL);
+        return -1;
+    }
+    if !oldact.is_null() {
+        env.mem.write(oldact, old);
+    }
     0
 }
 
 fn signal(env: &mut Environment, signum: i32, handler: MutVoidPtr) -> MutVoidPtr {
-    // TODO: handle errno properly
     set_errno(env, 0);
 
-    log!("TODO: signal({:?}, {:?})", signum, handler);
-    Ptr::null()
+    let new = sigaction {
+        sa_handler: handler.to_bits(),
+        sa_mask: 0,
+        sa_flags: 0,
+    };
+    match env.libc_state.signal.set_action(signum, new) {
+        Ok(old) => {
+            log_dbg!("signal({}, {:?}) => {:#x}", signum, handler, {
+                old.sa_handler
+            });
+            Ptr::from_bits(old.sa_handler)
+        }
+        Err(()) => {
+            set_errno(env, EINVAL);
+            Ptr::from_bits(SIG_ERR)
+        }
+    }
+}
+
+/// Deliver a signal that touchHLE raises itself, e.g. `SIGPIPE` when writing to
+/// a socket that has been closed by the other end, to the app's handler, if it
+/// has one.
+pub fn raise_internal_signal(env: &mut Environment, signum: i32) {
+    let action = env.libc_state.signal.action(signum);
+    match action.sa_handler {
+        SIG_IGN => log_dbg!("Signal {} is ignored", signum),
+        SIG_DFL => {
+            // The default action for most signals is to terminate the app,
+            // which is unlikely to be helpful.
+            log!(
+                "Warning: signal {} has the default disposition, ignoring it",
+                signum
+            );
+        }
+        handler => {
+            let handler = GuestFunction::from_addr_with_thumb_bit(handler);
+            log_dbg!("Calling handler {:?} for signal {}", handler, signum);
+            if (action.sa_flags & SA_SIGINFO) != 0 {
+                // TODO: provide siginfo_t and ucontext_t
+                let info: MutVoidPtr = Ptr::null();
+                let context: MutVoidPtr = Ptr::null();
+                () = handler.call_from_host(env, (signum, info, context));
+            } else {
+                () = handler.call_from_host(env, (signum,));
+            }
+        }
+    }
 }
 
 pub const FUNCTIONS: FunctionExports = &[
     export_c_func!(sigaction(_, _, _)),
     export_c_func!(signal(_, _)),
 ];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn register_and_query() {
+        let mut state = State::default();
+        assert_eq!(state.action(SIGPIPE).sa_handler, SIG_DFL);
+
+        let ignore = sigaction {
+            sa_handler: SIG_IGN,
+            ..Default::default()
+        };
+        assert_eq!(state.set_action(SIGPIPE, ignore), Ok(sigaction::default()));
+        assert_eq!(state.action(SIGPIPE), ignore);
+
+        let handler = sigaction {
+            sa_handler: 0x1235,
+            sa_mask: 1 << (SIGINT - 1),
+            sa_flags: SA_SIGINFO,
+        };
+        assert_eq!(state.set_action(SIGPIPE, handler), Ok(ignore));
+        assert_eq!(state.action(SIGPIPE), handler);
+        // Other signals are unaffected
+        assert_eq!(state.action(SIGINT), sigaction::default());
+
+        // Invalid or unchangeable signals
+        assert_eq!(state.set_action(0, ignore), Err(()));
+        assert_eq!(state.set_action(NSIG, ignore), Err(()));
+        assert_eq!(state.set_action(SIGKILL, ignore), Err(()));
+        assert_eq!(state.set_action(SIGSTOP, ignore), Err(()));
+    }
+}
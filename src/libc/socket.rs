@@ -0,0 +1,384 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! BSD sockets (`sys/socket.h`).
+//!
+//! Only TCP over IPv4 (`AF_INET` and `SOCK_STREAM`) is currently supported.
+//! Each socket is backed by a host [TcpStream], which is always non-blocking
+//! on the host side. When the app makes a blocking call, the guest thread is
+//! blocked instead (see [Environment::block_on_socket]), so that other threads
+//! can keep running.
+//!
+//! Connections can only be made if networking has been enabled with the
+//! `--enable-networking` option.
+
+use crate::dyld::{export_c_func, FunctionExports};
+use crate::libc::errno::{
+    set_errno, EAFNOSUPPORT, EAGAIN, EALREADY, EBADF, ECONNABORTED, ECONNREFUSED, ECONNRESET,
+    EINPROGRESS, EINVAL, EIO, EISCONN, ENETUNREACH, ENOTCONN, EPIPE, EPROTONOSUPPORT, ETIMEDOUT,
+};
+use crate::libc::netdb::{sockaddr_in, IPPROTO_TCP};
+use crate::libc::posix_io::{next_free_fd, FileDescriptor};
+use crate::libc::signal::{self, SIGPIPE};
+use crate::mem::{guest_size_of, ConstPtr, ConstVoidPtr, GuestISize, GuestUSize, MutVoidPtr};
+use crate::Environment;
+use std::collections::HashMap;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{Ipv4Addr, SocketAddrV4, TcpStream};
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+use std::time::Duration;
+
+#[derive(Default)]
+pub struct State {
+    sockets: HashMap<FileDescriptor, Socket>,
+}
+impl State {
+    fn get(env: &mut Environment) -> &mut Self {
+        &mut env.libc_state.socket
+    }
+}
+
+/// Returns [true] if the app has any sockets open.
+pub fn has_open_sockets(env: &Environment) -> bool {
+    !env.libc_state.socket.sockets.is_empty()
+}
+
+/// Returns [true] if the file descriptor refers to a socket.
+pub fn is_socket(env: &Environment, fd: FileDescriptor) -> bool {
+    env.libc_state.socket.sockets.contains_key(&fd)
+}
+
+struct Socket {
+    connection: Connection,
+    non_blocking: bool,
+}
+
+enum Connection {
+    Unconnected,
+    /// `connect()` has been called and a host thread is trying to connect.
+    /// [TcpStream] has no non-blocking connect, so this avoids blocking the
+    /// whole emulator.
+    Connecting(Receiver<std::io::Result<TcpStream>>),
+    Connected(TcpStream),
+    /// The last connection attempt failed with this `errno` value, which
+    /// hasn't been reported to the app yet.
+    Failed(i32),
+}
+
+impl Socket {
+    /// Check whether a connection attempt in progress has finished.
+    fn poll_connection(&mut self) {
+        let Connection::Connecting(ref receiver) = self.connection else {
+            return;
+        };
+        let result = match receiver.try_recv() {
+            Ok(result) => result,
+            Err(TryRecvError::Empty) => return,
+            Err(TryRecvError::Disconnected) => panic!("Connection thread went away"),
+        };
+        self.connection = match result.and_then(|stream| {
+            stream.set_nonblocking(true)?;
+            Ok(stream)
+        }) {
+            Ok(stream) => Connection::Connected(stream),
+            Err(e) => Connection::Failed(errno_for_io_error(&e)),
+        };
+    }
+}
+
+/// What a thread blocked on a socket is waiting for, see
+/// [Environment::block_on_socket].
+#[derive(Debug, Clone, Copy)]
+pub enum SocketWait {
+    Connect,
+    Read,
+    Write,
+}
+
+/// How often the readiness of sockets is checked when all threads are blocked.
+pub const SOCKET_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Check whether a thread blocked on a socket can retry its operation. This is
+/// called by the thread scheduler.
+pub fn socket_is_ready(env: &mut Environment, fd: FileDescriptor, wait: SocketWait) -> bool {
+    let Some(socket) = State::get(env).sockets.get_mut(&fd) else {
+        // The socket was closed by another thread, so the retried operation
+        // will fail.
+        return true;
+    };
+    socket.poll_connection();
+    match (&socket.connection, wait) {
+        (Connection::Connecting(_), _) => false,
+        (Connection::Connected(stream), SocketWait::Read) => {
+            !matches!(stream.peek(&mut [0]), Err(e) if e.kind() == ErrorKind::WouldBlock)
+        }
+        // TODO: There's no way to check if a write would block using std, so
+        // the write is simply retried.
+        _ => true,
+    }
+}
+
+fn errno_for_io_error(e: &std::io::Error) -> i32 {
+    match e.kind() {
+        ErrorKind::WouldBlock => EAGAIN,
+        ErrorKind::ConnectionRefused => ECONNREFUSED,
+        ErrorKind::ConnectionReset => ECONNRESET,
+        ErrorKind::ConnectionAborted => ECONNABORTED,
+        ErrorKind::NotConnected => ENOTCONN,
+        ErrorKind::BrokenPipe => EPIPE,
+        ErrorKind::TimedOut => ETIMEDOUT,
+        _ => {
+            log!("Warning: Unexpected socket error {:?}, using EIO", e);
+            EIO
+        }
+    }
+}
+
+pub const AF_UNSPEC: i32 = 0;
+pub const AF_INET: i32 = 2;
+pub const AF_INET6: i32 = 30;
+
+pub const SOCK_STREAM: i32 = 1;
+pub const SOCK_DGRAM: i32 = 2;
+
+#[allow(non_camel_case_types)]
+pub type socklen_t = u32;
+
+fn socket(env: &mut Environment, domain: i32, type_: i32, protocol: i32) -> FileDescriptor {
+    if domain != AF_INET {
+        log!("TODO: socket() with domain {}, returning -1", domain);
+        set_errno(env, EAFNOSUPPORT);
+        return -1;
+    }
+    if type_ != SOCK_STREAM || ![0, IPPROTO_TCP].contains(&protocol) {
+        log!(
+            "TODO: socket() with type {} and protocol {}, returning -1",
+            type_,
+            protocol
+        );
+        set_errno(env, EPROTONOSUPPORT);
+        return -1;
+    }
+
+    let fd = next_free_fd(env);
+    State::get(env).sockets.insert(
+        fd,
+        Socket {
+            connection: Connection::Unconnected,
+            non_blocking: false,
+        },
+    );
+    log_dbg!("socket({}, {}, {}) => {}", domain, type_, protocol, fd);
+    fd
+}
+
+fn connect(
+    env: &mut Environment,
+    fd: FileDescriptor,
+    address: ConstVoidPtr, // const struct sockaddr *
+    address_len: socklen_t,
+) -> i32 {
+    let Some(socket) = State::get(env).sockets.get_mut(&fd) else {
+        set_errno(env, EBADF);
+        return -1;
+    };
+    socket.poll_connection();
+    match socket.connection {
+        Connection::Unconnected => (),
+        Connection::Connecting(_) => {
+            set_errno(env, EALREADY);
+            return -1;
+        }
+        Connection::Connected(_) => {
+            set_errno(env, EISCONN);
+            return -1;
+        }
+        // A previous non-blocking connection attempt failed.
+        Connection::Failed(errno) => {
+            socket.connection = Connection::Unconnected;
+            set_errno(env, errno);
+            return -1;
+        }
+    }
+    let non_blocking = socket.non_blocking;
+
+    if address_len < guest_size_of::<sockaddr_in>() {
+        set_errno(env, EINVAL);
+        return -1;
+    }
+    let address: ConstPtr<sockaddr_in> = address.cast();
+    let sockaddr_in {
+        sin_family,
+        sin_port,
+        sin_addr,
+        ..
+    } = env.mem.read(address);
+    if i32::from(sin_family) != AF_INET {
+        set_errno(env, EAFNOSUPPORT);
+        return -1;
+    }
+    let address = SocketAddrV4::new(Ipv4Addr::from(sin_addr), u16::from_be_bytes(sin_port));
+
+    if !env.options.network_access {
+        log!(
+            "Warning: The app tried to connect to {}, but networking is disabled. Use the --enable-networking option to allow this.",
+            address
+        );
+        set_errno(env, ENETUNREACH);
+        return -1;
+    }
+
+    log_dbg!("connect({}, {}): connecting", fd, address);
+    let (sender, receiver) = channel();
+    std::thread::spawn(move || {
+        // The socket might have been closed in the meantime, in which case
+        // nobody is listening.
+        let _ = sender.send(TcpStream::connect(address));
+    });
+    State::get(env).sockets.get_mut(&fd).unwrap().connection = Connection::Connecting(receiver);
+
+    if non_blocking {
+        set_errno(env, EINPROGRESS);
+        return -1;
+    }
+
+    loop {
+        env.block_on_socket(fd, SocketWait::Connect);
+        let Some(socket) = State::get(env).sockets.get_mut(&fd) else {
+            set_errno(env, EBADF);
+            return -1;
+        };
+        socket.poll_connection();
+        match socket.connection {
+            Connection::Connecting(_) => continue,
+            Connection::Connected(_) => {
+                log_dbg!("connect({}, {}) => 0", fd, address);
+                return 0;
+            }
+            Connection::Failed(errno) => {
+                log_dbg!("connect({}, {}) failed with errno {}", fd, address, errno);
+                socket.connection = Connection::Unconnected;
+                set_errno(env, errno);
+                return -1;
+            }
+            Connection::Unconnected => unreachable!(),
+        }
+    }
+}
+
+fn send(
+    env: &mut Environment,
+    fd: FileDescriptor,
+    buffer: ConstVoidPtr,
+    length: GuestUSize,
+    flags: i32,
+) -> GuestISize {
+    if flags != 0 {
+        log!("TODO: Ignoring send() flags {:#x}", flags);
+    }
+    loop {
+        let Some(socket) = env.libc_state.socket.sockets.get_mut(&fd) else {
+            set_errno(env, EBADF);
+            return -1;
+        };
+        socket.poll_connection();
+        let Connection::Connected(ref mut stream) = socket.connection else {
+            set_errno(env, ENOTCONN);
+            return -1;
+        };
+        match stream.write(env.mem.bytes_at(buffer.cast(), length)) {
+            Ok(bytes_sent) => {
+                log_dbg!(
+                    "send({}, {:?}, {:#x}) => {:#x}",
+                    fd,
+                    buffer,
+                    length,
+                    bytes_sent
+                );
+                return bytes_sent.try_into().unwrap();
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock && !socket.non_blocking => (),
+            Err(e) => {
+                log_dbg!("send({}, {:?}, {:#x}) failed: {}", fd, buffer, length, e);
+                let errno = errno_for_io_error(&e);
+                if errno == EPIPE {
+                    // TODO: SO_NOSIGPIPE
+                    signal::raise_internal_signal(env, SIGPIPE);
+                }
+                set_errno(env, errno);
+                return -1;
+            }
+        }
+        env.block_on_socket(fd, SocketWait::Write);
+    }
+}
+
+fn recv(
+    env: &mut Environment,
+    fd: FileDescriptor,
+    buffer: MutVoidPtr,
+    length: GuestUSize,
+    flags: i32,
+) -> GuestISize {
+    if flags != 0 {
+        log!("TODO: Ignoring recv() flags {:#x}", flags);
+    }
+    loop {
+        let Some(socket) = env.libc_state.socket.sockets.get_mut(&fd) else {
+            set_errno(env, EBADF);
+            return -1;
+        };
+        socket.poll_connection();
+        let Connection::Connected(ref mut stream) = socket.connection else {
+            set_errno(env, ENOTCONN);
+            return -1;
+        };
+        match stream.read(env.mem.bytes_at_mut(buffer.cast(), length)) {
+            Ok(bytes_received) => {
+                log_dbg!(
+                    "recv({}, {:?}, {:#x}) => {:#x}",
+                    fd,
+                    buffer,
+                    length,
+                    bytes_received
+                );
+                return bytes_received.try_into().unwrap();
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock && !socket.non_blocking => (),
+            Err(e) => {
+                log_dbg!("recv({}, {:?}, {:#x}) failed: {}", fd, buffer, length, e);
+                set_errno(env, errno_for_io_error(&e));
+                return -1;
+            }
+        }
+        env.block_on_socket(fd, SocketWait::Read);
+    }
+}
+
+/// Implementation of `close()` for sockets, see [is_socket].
+pub fn close(env: &mut Environment, fd: FileDescriptor) -> i32 {
+    // The host socket is closed when it's dropped. If a connection attempt is
+    // still in progress, the connection is dropped once it has been made.
+    State::get(env).sockets.remove(&fd).unwrap();
+    log_dbg!("close({}) => 0 (socket)", fd);
+    0
+}
+
+/// Implementation of `fcntl(fd, F_GETFL)` for sockets, see [is_socket].
+pub fn get_non_blocking(env: &mut Environment, fd: FileDescriptor) -> bool {
+    State::get(env).sockets[&fd].non_blocking
+}
+
+/// Implementation of `fcntl(fd, F_SETFL, ...)` for sockets, see [is_socket].
+pub fn set_non_blocking(env: &mut Environment, fd: FileDescriptor, non_blocking: bool) {
+    State::get(env).sockets.get_mut(&fd).unwrap().non_blocking = non_blocking;
+}
+
+pub const FUNCTIONS: FunctionExports = &[
+    export_c_func!(socket(_, _, _)),
+    export_c_func!(connect(_, _, _)),
+    export_c_func!(send(_, _, _, _)),
+    export_c_func!(recv(_, _, _, _)),
+];
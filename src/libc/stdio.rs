@@ -11,10 +11,11 @@ use super::posix_io::{
 };
 use crate::dyld::{export_c_func, ConstantExports, FunctionExports, HostConstant};
 use crate::fs::GuestPath;
-use crate::libc::errno::set_errno;
+use crate::libc::errno::{set_errno, EBADF};
 use crate::libc::string::strlen;
 use crate::mem::{ConstPtr, ConstVoidPtr, GuestUSize, Mem, MutPtr, MutVoidPtr, Ptr, SafeRead};
 use crate::Environment;
+use std::collections::HashMap;
 use std::io::Write;
 
 // Standard C functions
@@ -34,6 +35,81 @@ unsafe impl SafeRead for FILE {}
 #[allow(non_camel_case_types)]
 type fpos_t = off_t;
 
+/// Size of the buffer `setbuf()` expects, and the amount of written data that
+/// will be buffered before it is passed on to the file.
+const BUFSIZ: usize = 1024;
+
+const _IOFBF: i32 = 0;
+const _IOLBF: i32 = 1;
+const _IONBF: i32 = 2;
+
+/// Host-side state for a `FILE`.
+#[derive(Default)]
+struct FileHostObject {
+    /// Data written to the stream that hasn't been written to the file yet.
+    write_buffer: Vec<u8>,
+    unbuffered: bool,
+    read_only: bool,
+    /// End-of-file indicator (`feof()`).
+    eof: bool,
+    /// Error indicator (`ferror()`).
+    error: bool,
+}
+
+#[derive(Default)]
+pub struct State {
+    /// Entries are created on first use rather than by `fopen()`, so that the
+    /// standard streams are covered too.
+    files: HashMap<MutPtr<FILE>, FileHostObject>,
+}
+impl State {
+    fn file_for(&mut self, file_ptr: MutPtr<FILE>) -> &mut FileHostObject {
+        self.files.entry(file_ptr).or_default()
+    }
+}
+
+/// Write out any data in the stream's write buffer. Returns [Err] and sets the
+/// error indicator if this fails.
+fn flush_write_buffer(env: &mut Environment, file_ptr: MutPtr<FILE>) -> Result<(), ()> {
+    let file = env.libc_state.stdio.file_for(file_ptr);
+    if file.write_buffer.is_empty() {
+        return Ok(());
+    }
+    let data = std::mem::take(&mut file.write_buffer);
+
+    let FILE { fd } = env.mem.read(file_ptr);
+    let size: GuestUSize = data.len().try_into().unwrap();
+    let buffer: MutPtr<u8> = env.mem.alloc(size).cast();
+    env.mem.bytes_at_mut(buffer, size).copy_from_slice(&data);
+    let bytes_written = posix_io::write(env, fd, buffer.cast_const().cast(), size);
+    env.mem.free(buffer.cast());
+
+    if GuestUSize::try_from(bytes_written) == Ok(size) {
+        Ok(())
+    } else {
+        env.libc_state.stdio.file_for(file_ptr).error = true;
+        Err(())
+    }
+}
+
+/// Write out the buffered data for all streams. This should be called before
+/// the app exits.
+pub fn flush_all(env: &mut Environment) {
+    let file_ptrs: Vec<_> = env.libc_state.stdio.files.keys().copied().collect();
+    for file_ptr in file_ptrs {
+        let _ = flush_write_buffer(env, file_ptr);
+    }
+}
+
+/// Returns [true] if any stream has buffered data that hasn't been written out.
+pub fn has_buffered_data(env: &Environment) -> bool {
+    env.libc_state
+        .stdio
+        .files
+        .values()
+        .any(|file| !file.write_buffer.is_empty())
+}
+
 fn fopen(env: &mut Environment, filename: ConstPtr<u8>, mode: ConstPtr<u8>) -> MutPtr<FILE> {
     // Some testing on macOS suggests Apple's implementation will just ignore
     // flags it doesn't know about, and unfortunately real-world apps seem to
@@ -62,7 +138,7 @@ fn fopen(env: &mut Environment, filename: ConstPtr<u8>, mode: ConstPtr<u8>) -> M
 
     let flags = match (basic_mode, plus) {
         (b'r', false) => O_RDONLY,
-        (b'r', true) => O_RDWR | O_APPEND,
+        (b'r', true) => O_RDWR,
         (b'w', false) => O_WRONLY | O_CREAT | O_TRUNC,
         (b'w', true) => O_RDWR | O_CREAT | O_TRUNC,
         (b'a', false) => O_WRONLY | O_APPEND | O_CREAT,
@@ -72,7 +148,15 @@ fn fopen(env: &mut Environment, filename: ConstPtr<u8>, mode: ConstPtr<u8>) -> M
 
     match posix_io::open_direct(env, filename, flags) {
         -1 => Ptr::null(),
-        fd => env.mem.alloc_and_write(FILE { fd }),
+        fd => {
+            let file_ptr = env.mem.alloc_and_write(FILE { fd });
+            let host_object = FileHostObject {
+                read_only: flags == O_RDONLY,
+                ..Default::default()
+            };
+            env.libc_state.stdio.files.insert(file_ptr, host_object);
+            file_ptr
+        }
     }
 }
 
@@ -90,6 +174,10 @@ fn fread(
         return 0;
     }
 
+    if flush_write_buffer(env, file_ptr).is_err() {
+        return 0;
+    }
+
     let FILE { fd } = env.mem.read(file_ptr);
 
     // Yes, the item_size/n_items split doesn't mean anything. The C standard
@@ -97,10 +185,15 @@ fn fread(
     // attempt being made to ensure a whole number are read or written!
     let total_size = item_size.checked_mul(n_items).unwrap();
     match posix_io::read(env, fd, buffer, total_size) {
-        // TODO: ferror() support.
-        -1 => 0,
+        -1 => {
+            env.libc_state.stdio.file_for(file_ptr).error = true;
+            0
+        }
         bytes_read => {
             let bytes_read: GuestUSize = bytes_read.try_into().unwrap();
+            if bytes_read < total_size {
+                env.libc_state.stdio.file_for(file_ptr).eof = true;
+            }
             bytes_read / item_size
         }
     }
@@ -110,21 +203,13 @@ fn fgetc(env: &mut Environment, file_ptr: MutPtr<FILE>) -> i32 {
     // TODO: handle errno properly
     set_errno(env, 0);
 
-    let FILE { fd } = env.mem.read(file_ptr);
-    let buffer = env.mem.alloc(1);
-
-    match posix_io::read(env, fd, buffer, 1) {
-        -1 => EOF,
-        bytes_read => {
-            let bytes_read: GuestUSize = bytes_read.try_into().unwrap();
-            if bytes_read < 1 {
-                EOF
-            } else {
-                let buf: MutPtr<i32> = buffer.cast();
-                env.mem.read(buf)
-            }
-        }
-    }
+    let buffer: MutPtr<u8> = env.mem.alloc(1).cast();
+    let res = match fread(env, buffer.cast(), 1, 1, file_ptr) {
+        1 => env.mem.read(buffer).into(),
+        _ => EOF,
+    };
+    env.mem.free(buffer.cast());
+    res
 }
 
 fn fgets(
@@ -216,11 +301,29 @@ fn fwrite(
             }
         }
         _ => {
+            let file = env.libc_state.stdio.file_for(file_ptr);
+            if file.read_only {
+                file.error = true;
+                set_errno(env, EBADF);
+                return 0;
+            }
+            if !file.unbuffered {
+                let buffer_slice = env.mem.bytes_at(buffer.cast(), total_size);
+                file.write_buffer.extend_from_slice(buffer_slice);
+                if file.write_buffer.len() >= BUFSIZ && flush_write_buffer(env, file_ptr).is_err() {
+                    // TODO: Work out how much of this write made it?
+                    return 0;
+                }
+                return n_items;
+            }
+
             // The comment about the item_size/n_items split in fread() applies
             // here too.
             match posix_io::write(env, fd, buffer, total_size) {
-                // TODO: ferror() support.
-                -1 => 0,
+                -1 => {
+                    env.libc_state.stdio.file_for(file_ptr).error = true;
+                    0
+                }
                 bytes_written => {
                     let bytes_written: GuestUSize = bytes_written.try_into().unwrap();
                     bytes_written / item_size
@@ -237,12 +340,21 @@ fn fseek(env: &mut Environment, file_ptr: MutPtr<FILE>, offset: i32, whence: i32
     // TODO: handle errno properly
     set_errno(env, 0);
 
+    if flush_write_buffer(env, file_ptr).is_err() {
+        return -1;
+    }
+
     let FILE { fd } = env.mem.read(file_ptr);
 
     assert!([SEEK_SET, SEEK_CUR, SEEK_END].contains(&whence));
     match posix_io::lseek(env, fd, offset.into(), whence) {
         -1 => -1,
-        _cur_pos => 0,
+        _cur_pos => {
+            // "A successful call to the fseek() function clears
+            // the end-of-file indicator for the stream..."
+            env.libc_state.stdio.file_for(file_ptr).eof = false;
+            0
+        }
     }
 }
 
@@ -250,6 +362,10 @@ fn ftell(env: &mut Environment, file_ptr: MutPtr<FILE>) -> i32 {
     // TODO: handle errno properly
     set_errno(env, 0);
 
+    if flush_write_buffer(env, file_ptr).is_err() {
+        return -1;
+    }
+
     let FILE { fd } = env.mem.read(file_ptr);
 
     match posix_io::lseek(env, fd, 0, posix_io::SEEK_CUR) {
@@ -264,19 +380,23 @@ fn rewind(env: &mut Environment, file_ptr: MutPtr<FILE>) {
     set_errno(env, 0);
 
     fseek(env, file_ptr, 0, SEEK_SET);
+    env.libc_state.stdio.file_for(file_ptr).error = false;
 }
 
 fn fclose(env: &mut Environment, file_ptr: MutPtr<FILE>) -> i32 {
     // TODO: handle errno properly
     set_errno(env, 0);
 
+    let flushed = flush_write_buffer(env, file_ptr);
+
     let FILE { fd } = env.mem.read(file_ptr);
 
+    env.libc_state.stdio.files.remove(&file_ptr);
     env.mem.free(file_ptr.cast());
 
     match posix_io::close(env, fd) {
-        0 => 0,
-        -1 => EOF,
+        0 if flushed.is_ok() => 0,
+        0 | -1 => EOF,
         _ => unreachable!(),
     }
 }
@@ -285,12 +405,17 @@ fn fsetpos(env: &mut Environment, file_ptr: MutPtr<FILE>, pos: ConstPtr<fpos_t>)
     // TODO: handle errno properly
     set_errno(env, 0);
 
+    if flush_write_buffer(env, file_ptr).is_err() {
+        return -1;
+    }
+
     let FILE { fd } = env.mem.read(file_ptr);
 
     let res = posix_io::lseek(env, fd, env.mem.read(pos), SEEK_SET);
     if res == -1 {
         -1
     } else {
+        env.libc_state.stdio.file_for(file_ptr).eof = false;
         0
     }
 }
@@ -299,6 +424,10 @@ fn fgetpos(env: &mut Environment, file_ptr: MutPtr<FILE>, pos: MutPtr<fpos_t>) -
     // TODO: handle errno properly
     set_errno(env, 0);
 
+    if flush_write_buffer(env, file_ptr).is_err() {
+        return -1;
+    }
+
     let FILE { fd } = env.mem.read(file_ptr);
 
     let res = posix_io::lseek(env, fd, 0, posix_io::SEEK_CUR);
@@ -313,24 +442,47 @@ fn feof(env: &mut Environment, file_ptr: MutPtr<FILE>) -> i32 {
     // TODO: handle errno properly
     set_errno(env, 0);
 
-    let FILE { fd } = env.mem.read(file_ptr);
-    posix_io::eof(env, fd)
+    env.libc_state.stdio.file_for(file_ptr).eof.into()
+}
+
+fn ferror(env: &mut Environment, file_ptr: MutPtr<FILE>) -> i32 {
+    // TODO: handle errno properly
+    set_errno(env, 0);
+
+    env.libc_state.stdio.file_for(file_ptr).error.into()
 }
 
 fn clearerr(env: &mut Environment, file_ptr: MutPtr<FILE>) {
     // TODO: handle errno properly
     set_errno(env, 0);
 
-    let FILE { fd } = env.mem.read(file_ptr);
-    posix_io::clearerr(env, fd)
+    let file = env.libc_state.stdio.file_for(file_ptr);
+    file.eof = false;
+    file.error = false;
 }
 
 fn fflush(env: &mut Environment, file_ptr: MutPtr<FILE>) -> i32 {
     // TODO: handle errno properly
     set_errno(env, 0);
 
+    // A null stream means all streams should be flushed.
+    if file_ptr.is_null() {
+        flush_all(env);
+        let _ = std::io::stdout().flush();
+        let _ = std::io::stderr().flush();
+        return 0;
+    }
+
+    if flush_write_buffer(env, file_ptr).is_err() {
+        return EOF;
+    }
+
     let FILE { fd } = env.mem.read(file_ptr);
-    posix_io::fflush(env, fd)
+    match fd {
+        STDOUT_FILENO => std::io::stdout().flush().map_or(EOF, |_| 0),
+        STDERR_FILENO => std::io::stderr().flush().map_or(EOF, |_| 0),
+        _ => posix_io::fflush(env, fd),
+    }
 }
 
 fn puts(env: &mut Environment, s: ConstPtr<u8>) -> i32 {
@@ -378,20 +530,43 @@ fn remove(env: &mut Environment, path: ConstPtr<u8>) -> i32 {
     }
 }
 
-fn setbuf(env: &mut Environment, stream: MutPtr<FILE>, buf: ConstPtr<u8>) {
+fn setbuf(env: &mut Environment, stream: MutPtr<FILE>, buf: MutPtr<u8>) {
+    let mode = if buf.is_null() { _IONBF } else { _IOFBF };
+    setvbuf(env, stream, buf, mode, BUFSIZ.try_into().unwrap());
+}
+
+fn setvbuf(
+    env: &mut Environment,
+    stream: MutPtr<FILE>,
+    buf: MutPtr<u8>,
+    mode: i32,
+    size: GuestUSize,
+) -> i32 {
     // TODO: handle errno properly
     set_errno(env, 0);
 
-    assert!(buf.is_null());
-    log!(
-        "Warning: ignoring a setbuf() for {:?} with NULL (unbuffered)",
-        stream
-    );
+    // The app's buffer is never used, touchHLE buffers writes on the host.
+    log_dbg!("setvbuf({:?}, {:?}, {}, {:#x})", stream, buf, mode, size);
+
+    let unbuffered = match mode {
+        _IONBF => true,
+        // TODO: Flush line-buffered streams at each newline.
+        _IOFBF | _IOLBF => false,
+        _ => return EOF,
+    };
+    if flush_write_buffer(env, stream).is_err() {
+        return EOF;
+    }
+    env.libc_state.stdio.file_for(stream).unbuffered = unbuffered;
+    0
 }
 
 // POSIX-specific functions
 
 fn fileno(env: &mut Environment, file_ptr: MutPtr<FILE>) -> posix_io::FileDescriptor {
+    // The app might be about to use the file descriptor directly.
+    let _ = flush_write_buffer(env, file_ptr);
+
     let FILE { fd } = env.mem.read(file_ptr);
     fd
 }
@@ -436,6 +611,7 @@ pub const FUNCTIONS: FunctionExports = &[
     export_c_func!(fsetpos(_, _)),
     export_c_func!(fgetpos(_, _)),
     export_c_func!(feof(_)),
+    export_c_func!(ferror(_)),
     export_c_func!(clearerr(_)),
     export_c_func!(fflush(_)),
     export_c_func!(fclose(_)),
@@ -443,6 +619,7 @@ pub const FUNCTIONS: FunctionExports = &[
     export_c_func!(putchar(_)),
     export_c_func!(remove(_)),
     export_c_func!(setbuf(_, _)),
+    export_c_func!(setvbuf(_, _, _, _)),
     // POSIX-specific functions
     export_c_func!(fileno(_)),
 ];
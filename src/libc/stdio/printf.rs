@@ -24,6 +24,169 @@ use std::io::Write;
 const INTEGER_SPECIFIERS: [u8; 6] = [b'd', b'i', b'o', b'u', b'x', b'X'];
 const FLOAT_SPECIFIERS: [u8; 3] = [b'f', b'e', b'g'];
 
+/// Width or precision in a conversion specification.
+enum Amount {
+    Fixed(usize),
+    /// `*` or `*m$`: taken from an `int` argument, either the next one or the
+    /// one at the given position.
+    FromArg(Option<usize>),
+}
+
+/// The part of a conversion specification between the `%` and the conversion
+/// specifier character.
+struct ConversionSpec {
+    /// `n$`: the one-based position of the argument to convert, if given.
+    position: Option<usize>,
+    pad_char: char,
+    width: Amount,
+    precision: Option<Amount>,
+    length_modifier: Option<&'static str>,
+}
+
+/// Parse a [ConversionSpec], starting just after the `%`. `idx` is advanced
+/// to the conversion specifier character.
+fn parse_conversion_spec<F: Fn(&Mem, GuestUSize) -> u8>(
+    mem: &Mem,
+    get_format_char: &F,
+    idx: &mut GuestUSize,
+) -> ConversionSpec {
+    let parse_number = |idx: &mut GuestUSize| {
+        let mut number = None;
+        while let c @ b'0'..=b'9' = get_format_char(mem, *idx) {
+            number = Some(number.unwrap_or(0) * 10 + (c - b'0') as usize);
+            *idx += 1;
+        }
+        number
+    };
+    // An argument position is told apart from a width by the following `$`.
+    let parse_position = |idx: &mut GuestUSize| {
+        let start = *idx;
+        match parse_number(idx) {
+            Some(position) if get_format_char(mem, *idx) == b'$' => {
+                *idx += 1;
+                assert!(position > 0, "Invalid argument position 0 in format string");
+                Some(position)
+            }
+            _ => {
+                *idx = start;
+                None
+            }
+        }
+    };
+    let parse_amount = |idx: &mut GuestUSize| {
+        if get_format_char(mem, *idx) == b'*' {
+            *idx += 1;
+            Amount::FromArg(parse_position(idx))
+        } else {
+            Amount::Fixed(parse_number(idx).unwrap_or(0))
+        }
+    };
+
+    let position = parse_position(idx);
+
+    let pad_char = if get_format_char(mem, *idx) == b'0' {
+        *idx += 1;
+        '0'
+    } else {
+        ' '
+    };
+
+    let width = parse_amount(idx);
+
+    let precision = if get_format_char(mem, *idx) == b'.' {
+        *idx += 1;
+        Some(parse_amount(idx))
+    } else {
+        None
+    };
+
+    let length_modifier = if get_format_char(mem, *idx) == b'l' {
+        *idx += 1;
+        if get_format_char(mem, *idx) == b'l' {
+            *idx += 1;
+            Some("ll")
+        } else {
+            Some("l")
+        }
+    } else {
+        None
+    };
+
+    ConversionSpec {
+        position,
+        pad_char,
+        width,
+        precision,
+        length_modifier,
+    }
+}
+
+/// Find where each argument is, for format strings that use `n$` positional
+/// arguments. Since these can be used in any order, and arguments take up one
+/// or two words depending on their type, the whole format string has to be
+/// scanned for the types before any argument can be read. The result is a
+/// [VaList] starting at each argument.
+fn find_positional_args<F: Fn(&Mem, GuestUSize) -> u8>(
+    env: &mut Environment,
+    get_format_char: &F,
+    args: VaList,
+) -> Vec<VaList> {
+    // Whether each argument is 64 bits wide.
+    let mut wide = Vec::new();
+    let mut set_wide = |position: usize, is_wide: bool| {
+        if wide.len() < position {
+            wide.resize(position, false);
+        }
+        wide[position - 1] = is_wide;
+    };
+
+    let mut idx = 0;
+    loop {
+        let c = get_format_char(&env.mem, idx);
+        idx += 1;
+        if c == b'\0' {
+            break;
+        }
+        if c != b'%' {
+            continue;
+        }
+        let spec = parse_conversion_spec(&env.mem, get_format_char, &mut idx);
+        let specifier = get_format_char(&env.mem, idx);
+        idx += 1;
+        if specifier == b'\0' {
+            break;
+        }
+        if let Amount::FromArg(Some(position)) = spec.width {
+            set_wide(position, false);
+        }
+        if let Some(Amount::FromArg(Some(position))) = spec.precision {
+            set_wide(position, false);
+        }
+        if let Some(position) = spec.position {
+            let is_wide = FLOAT_SPECIFIERS.contains(&specifier)
+                || (INTEGER_SPECIFIERS.contains(&specifier) && spec.length_modifier == Some("ll"));
+            set_wide(position, is_wide);
+        }
+    }
+
+    let mut args = args;
+    wide.into_iter()
+        .map(|is_wide| {
+            let arg = args;
+            if is_wide {
+                let _: u64 = args.next(env);
+            } else {
+                let _: u32 = args.next(env);
+            }
+            arg
+        })
+        .collect()
+}
+
+fn positional_arg(positional_args: &[VaList], position: usize) -> VaList {
+    positional_args[position - 1]
+}
+
 /// String formatting implementation for `printf` and `NSLog` function families.
 ///
 /// `NS_LOG` is [true] for the `NSLog` format string type, or [false] for the
@@ -34,10 +197,14 @@ const FLOAT_SPECIFIERS: [u8; 3] = [b'f', b'e', b'g'];
 pub fn printf_inner<const NS_LOG: bool, F: Fn(&Mem, GuestUSize) -> u8>(
     env: &mut Environment,
     get_format_char: F,
-    mut args: VaList,
+    initial_args: VaList,
 ) -> Vec<u8> {
     let mut res = Vec::<u8>::new();
 
+    let mut sequential_args = initial_args;
+    // Only filled in if the format string uses `n$` positional arguments.
+    let mut positional_args = Vec::new();
+
     let mut format_char_idx = 0;
 
     loop {
@@ -52,57 +219,52 @@ pub fn printf_inner<const NS_LOG: bool, F: Fn(&Mem, GuestUSize) -> u8>(
             continue;
         }
 
-        let pad_char = if get_format_char(&env.mem, format_char_idx) == b'0' {
-            format_char_idx += 1;
-            '0'
-        } else {
-            ' '
-        };
+        let ConversionSpec {
+            position,
+            pad_char,
+            width,
+            precision,
+            length_modifier,
+        } = parse_conversion_spec(&env.mem, &get_format_char, &mut format_char_idx);
 
-        let pad_width = if get_format_char(&env.mem, format_char_idx) == b'*' {
-            let pad_width = args.next::<i32>(env);
-            assert!(pad_width >= 0); // TODO: Implement right-padding
-            format_char_idx += 1;
-            pad_width
-        } else {
-            let mut pad_width: i32 = 0;
-            while let c @ b'0'..=b'9' = get_format_char(&env.mem, format_char_idx) {
-                pad_width = pad_width * 10 + (c - b'0') as i32;
-                format_char_idx += 1;
+        if position.is_some() && positional_args.is_empty() {
+            positional_args = find_positional_args(env, &get_format_char, initial_args);
+        }
+
+        let pad_width = match width {
+            Amount::Fixed(pad_width) => pad_width as i32,
+            Amount::FromArg(position) => {
+                let pad_width: i32 = match position {
+                    Some(position) => positional_arg(&positional_args, position).next(env),
+                    None => sequential_args.next(env),
+                };
+                assert!(pad_width >= 0); // TODO: Implement right-padding
+                pad_width
             }
-            pad_width
         };
 
-        let precision = if get_format_char(&env.mem, format_char_idx) == b'.' {
-            format_char_idx += 1;
-            let precision = if get_format_char(&env.mem, format_char_idx) == b'*' {
-                let precision = args.next::<i32>(env);
+        let precision = match precision {
+            Some(Amount::Fixed(precision)) => Some(precision),
+            Some(Amount::FromArg(position)) => {
+                let precision: i32 = match position {
+                    Some(position) => positional_arg(&positional_args, position).next(env),
+                    None => sequential_args.next(env),
+                };
                 assert!(precision >= 0); // TODO: ignore negative
-                format_char_idx += 1;
-                precision as usize
-            } else {
-                let mut precision = 0;
-                while let c @ b'0'..=b'9' = get_format_char(&env.mem, format_char_idx) {
-                    precision = precision * 10 + (c - b'0') as usize;
-                    format_char_idx += 1;
-                }
-                precision
-            };
-            Some(precision)
-        } else {
-            None
+                Some(precision as usize)
+            }
+            None => None,
         };
 
-        let length_modifier = if get_format_char(&env.mem, format_char_idx) == b'l' {
-            format_char_idx += 1;
-            if get_format_char(&env.mem, format_char_idx) == b'l' {
-                format_char_idx += 1;
-                Some("ll")
-            } else {
-                Some("l")
+        // The argument to be converted is the next one, unless a position was
+        // given.
+        let mut positional_args_at;
+        let args: &mut VaList = match position {
+            Some(position) => {
+                positional_args_at = positional_arg(&positional_args, position);
+                &mut positional_args_at
             }
-        } else {
-            None
+            None => &mut sequential_args,
         };
 
         let specifier = get_format_char(&env.mem, format_char_idx);
@@ -6,34 +6,223 @@
 //! `stdlib.h`
 
 use crate::abi::{CallFromHost, GuestFunction};
+use crate::cpu::Cpu;
 use crate::dyld::{export_c_func, export_c_func_aliased, FunctionExports};
 use crate::fs::{resolve_path, GuestPath};
 use crate::libc::clocale::{setlocale, LC_CTYPE};
-use crate::libc::errno::set_errno;
+use crate::libc::errno::{set_errno, EINVAL, ENOENT, ENOTDIR, ERANGE};
+use crate::libc::stdio;
 use crate::libc::string::strlen;
 use crate::libc::wchar::wchar_t;
-use crate::mem::{ConstPtr, ConstVoidPtr, GuestUSize, MutPtr, MutVoidPtr, Ptr};
-use crate::Environment;
+use crate::mem::{
+    ConstPtr, ConstVoidPtr, GuestUSize, MemSnapshot, MutPtr, MutVoidPtr, Ptr, SafeRead,
+};
+use crate::{impl_GuestRet_for_large_struct, Environment};
+use std::collections::HashMap;
 use std::str::FromStr;
 
 pub mod qsort;
 
+use qsort::Comparator;
+
 #[derive(Default)]
 pub struct State {
     rand: u32,
     random: u32,
     arc4random: u32,
+    atexit_handlers: Vec<AtExitHandler>,
+    /// Allocations made by [posix_memalign] and friends that don't start at
+    /// the base of the underlying allocation, keyed by their aligned address.
+    /// The values are the base address and the size that was requested.
+    aligned_allocations: HashMap<u32, (MutVoidPtr, GuestUSize)>,
+    /// For `--heap-stats`: the return address of the call that made each
+    /// live allocation, and the size that was requested.
+    allocation_sites: HashMap<u32, (u32, GuestUSize)>,
+}
+impl State {
+    /// Forget allocations that won't exist once `mem` is restored, see
+    /// [crate::Environment::load_snapshot].
+    pub fn forget_pointers_not_in(&mut self, mem: &MemSnapshot) {
+        self.allocation_sites.retain(|&ptr, _| mem.is_used(ptr));
+    }
+    fn seed_rngs(&mut self, seed: Option<u32>) {
+        if let Some(seed) = seed {
+            self.rand = seed;
+            self.random = seed;
+            self.arc4random = seed;
+        } else {
+            self.arc4random = host_entropy();
+        }
+    }
+    fn next_rand(&mut self) -> i32 {
+        self.rand = prng(self.rand);
+        (self.rand as i32) & RAND_MAX
+    }
+    fn next_random(&mut self) -> i32 {
+        self.random = prng(self.random);
+        (self.random as i32) & RAND_MAX
+    }
+    fn next_arc4random(&mut self) -> u32 {
+        self.arc4random = prng(self.arc4random);
+        self.arc4random
+    }
+}
+
+/// Set up the initial state of the random number generators. If a seed was
+/// given with `--rng-seed=`, all of them use it, so that runs are
+/// reproducible. Otherwise `arc4random()`, which apps expect to be
+/// unpredictable, is seeded from the host, and the others keep the C
+/// standard's default seed.
+pub fn init_rngs(env: &mut Environment) {
+    if let Some(seed) = env.options.rng_seed {
+        log!(
+            "Seeding random number generators with {} (--rng-seed)",
+            seed
+        );
+    }
+    env.libc_state.stdlib.seed_rngs(env.options.rng_seed);
+}
+
+/// Get a random number from the host. The standard library's hash maps are
+/// seeded with randomness from the OS, which saves a dependency.
+fn host_entropy() -> u32 {
+    use std::hash::{BuildHasher, Hasher};
+    let hash = std::collections::hash_map::RandomState::new()
+        .build_hasher()
+        .finish();
+    (hash ^ (hash >> 32)) as u32
+}
+
+/// A function registered by `atexit()` or `__cxa_atexit()`. Both kinds share a
+/// single list, so they are called in the reverse order of registration
+/// regardless of which function registered them.
+#[derive(Copy, Clone)]
+pub(super) enum AtExitHandler {
+    /// `void (*func)(void)`, registered by `atexit()`.
+    Plain(GuestFunction),
+    /// `void (*func)(void *)`, registered by `__cxa_atexit()` with an argument
+    /// and the handle of the DSO it belongs to.
+    Cxa {
+        func: GuestFunction,
+        arg: MutVoidPtr,
+        dso_handle: MutVoidPtr,
+    },
+}
+
+pub(super) fn register_atexit_handler(env: &mut Environment, handler: AtExitHandler) {
+    env.libc_state.stdlib.atexit_handlers.push(handler);
+}
+
+/// Call and unregister the `atexit()` and `__cxa_atexit()` handlers, most
+/// recently registered first. If `dso_handle` is provided, only the
+/// `__cxa_atexit()` handlers registered with that handle are called, as for
+/// `__cxa_finalize()`.
+pub(super) fn run_atexit_handlers(env: &mut Environment, dso_handle: Option<MutVoidPtr>) {
+    // Handlers are removed one at a time because a handler could register
+    // further handlers.
+    while let Some(idx) = env
+        .libc_state
+        .stdlib
+        .atexit_handlers
+        .iter()
+        .rposition(|&handler| match (handler, dso_handle) {
+            (_, None) => true,
+            (
+                AtExitHandler::Cxa {
+                    dso_handle: handle, ..
+                },
+                Some(wanted),
+            ) => handle == wanted,
+            (AtExitHandler::Plain(_), Some(_)) => false,
+        })
+    {
+        let handler = env.libc_state.stdlib.atexit_handlers.remove(idx);
+        match handler {
+            AtExitHandler::Plain(func) => {
+                log_dbg!("Calling atexit() handler {:?}", func);
+                () = func.call_from_host(env, ());
+            }
+            AtExitHandler::Cxa { func, arg, .. } => {
+                log_dbg!("Calling __cxa_atexit() handler {:?}({:?})", func, arg);
+                () = func.call_from_host(env, (arg,));
+            }
+        }
+    }
 }
 
 // Sizes of zero are implementation-defined. macOS will happily give you back
 // an allocation for any of these, so presumably iPhone OS does too.
 // (touchHLE's allocator will round up allocations to at least 16 bytes.)
 
+/// For `--heap-stats`: record where an allocation was made from. This must be
+/// called directly from the host function the app called.
+fn record_allocation_site(env: &mut Environment, ptr: MutVoidPtr, size: GuestUSize) {
+    if !env.options.heap_stats {
+        return;
+    }
+    let return_address = env.cpu.regs()[Cpu::LR];
+    env.libc_state
+        .stdlib
+        .allocation_sites
+        .insert(ptr.to_bits(), (return_address, size));
+}
+
+/// Returns [true] if there are any allocations made by `posix_memalign()` or
+/// similar that haven't been freed.
+pub fn has_aligned_allocations(env: &Environment) -> bool {
+    !env.libc_state.stdlib.aligned_allocations.is_empty()
+}
+
+/// Format the summary for `--heap-stats` that is printed at exit.
+pub fn heap_stats_summary(env: &Environment) -> String {
+    let stats = env.mem.heap_stats();
+    let mut summary = format!(
+        "Heap statistics: {} bytes live in {} allocations; {} bytes allocated in total by {} allocations",
+        stats.live_bytes, stats.live_allocations, stats.total_allocated, stats.allocation_count
+    );
+
+    // Group the live allocations by the code that made them, so leaks can be
+    // attributed.
+    let mut sites: HashMap<u32, (u64, u64)> = HashMap::new();
+    for &(return_address, size) in env.libc_state.stdlib.allocation_sites.values() {
+        let (bytes, count) = sites.entry(return_address).or_default();
+        *bytes += u64::from(size);
+        *count += 1;
+    }
+    let mut sites: Vec<_> = sites.into_iter().collect();
+    sites.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    if !sites.is_empty() {
+        summary.push_str("\nLargest live allocations by return address of malloc() etc:");
+    }
+    for (return_address, (bytes, count)) in sites.into_iter().take(10) {
+        summary.push_str(&format!(
+            "\n- {:#x}: {} bytes in {} allocations",
+            return_address, bytes, count
+        ));
+    }
+    summary
+}
+
+/// For `--debug-alloc`: panic if `ptr` can't be freed.
+fn validate_free(env: &Environment, function: &str, ptr: MutVoidPtr) {
+    if let Err(problem) = env.mem.validate_free(ptr) {
+        panic!(
+            "{}({:?}) called from {:#x}: {}",
+            function,
+            ptr,
+            env.cpu.regs()[Cpu::LR],
+            problem
+        );
+    }
+}
+
 fn malloc(env: &mut Environment, size: GuestUSize) -> MutVoidPtr {
     // TODO: handle errno properly
     set_errno(env, 0);
 
-    env.mem.alloc(size)
+    let ptr = env.mem.alloc(size);
+    record_allocation_site(env, ptr, size);
+    ptr
 }
 
 fn calloc(env: &mut Environment, count: GuestUSize, size: GuestUSize) -> MutVoidPtr {
@@ -41,7 +230,9 @@ fn calloc(env: &mut Environment, count: GuestUSize, size: GuestUSize) -> MutVoid
     set_errno(env, 0);
 
     let total = size.checked_mul(count).unwrap();
-    env.mem.alloc(total)
+    let ptr = env.mem.alloc(total);
+    record_allocation_site(env, ptr, total);
+    ptr
 }
 
 fn realloc(env: &mut Environment, ptr: MutVoidPtr, size: GuestUSize) -> MutVoidPtr {
@@ -51,7 +242,28 @@ fn realloc(env: &mut Environment, ptr: MutVoidPtr, size: GuestUSize) -> MutVoidP
     if ptr.is_null() {
         return malloc(env, size);
     }
-    env.mem.realloc(ptr, size)
+    if let Some(&(_, old_size)) = env
+        .libc_state
+        .stdlib
+        .aligned_allocations
+        .get(&ptr.to_bits())
+    {
+        // The alignment doesn't need to be preserved.
+        let new_ptr = env.mem.alloc(size);
+        env.mem
+            .memmove(new_ptr, ptr.cast_const(), old_size.min(size));
+        free(env, ptr);
+        record_allocation_site(env, new_ptr, size);
+        return new_ptr;
+    }
+    validate_free(env, "realloc", ptr);
+    env.libc_state
+        .stdlib
+        .allocation_sites
+        .remove(&ptr.to_bits());
+    let new_ptr = env.mem.realloc(ptr, size);
+    record_allocation_site(env, new_ptr, size);
+    new_ptr
 }
 
 fn free(env: &mut Environment, ptr: MutVoidPtr) {
@@ -72,16 +284,92 @@ fn free(env: &mut Environment, ptr: MutVoidPtr) {
         // "If ptr is a NULL pointer, no operation is performed."
         return;
     }
+    env.libc_state
+        .stdlib
+        .allocation_sites
+        .remove(&ptr.to_bits());
+    if let Some((base, _)) = env
+        .libc_state
+        .stdlib
+        .aligned_allocations
+        .remove(&ptr.to_bits())
+    {
+        validate_free(env, "free", base);
+        env.mem.free(base);
+        return;
+    }
+    validate_free(env, "free", ptr);
     env.mem.free(ptr);
 }
 
+/// Shared implementation of [posix_memalign], [memalign] and [valloc].
+/// `alignment` must be a power of two.
+fn alloc_aligned(env: &mut Environment, alignment: GuestUSize, size: GuestUSize) -> MutVoidPtr {
+    assert!(alignment.is_power_of_two());
+    // touchHLE's allocator always returns 16-byte-aligned memory, so
+    // over-allocating by this much is always enough.
+    let padding = alignment.saturating_sub(16);
+    let base = env.mem.alloc(size.checked_add(padding).unwrap());
+    let aligned = align_up(base.to_bits(), alignment);
+    if aligned != base.to_bits() {
+        env.libc_state
+            .stdlib
+            .aligned_allocations
+            .insert(aligned, (base, size));
+    }
+    let ptr = Ptr::from_bits(aligned);
+    record_allocation_site(env, ptr, size);
+    ptr
+}
+
+/// Round `addr` up to a multiple of `alignment`, which must be a power of two.
+fn align_up(addr: u32, alignment: u32) -> u32 {
+    (addr + (alignment - 1)) & !(alignment - 1)
+}
+
+fn posix_memalign(
+    env: &mut Environment,
+    memptr: MutPtr<MutVoidPtr>,
+    alignment: GuestUSize,
+    size: GuestUSize,
+) -> i32 {
+    // "The value of alignment shall be a power of two multiple of
+    // sizeof(void *)."
+    if !alignment.is_power_of_two() || alignment < 4 {
+        return EINVAL;
+    }
+    let ptr = alloc_aligned(env, alignment, size);
+    log_dbg!(
+        "posix_memalign({:?}, {:#x}, {:#x}) => {:?}",
+        memptr,
+        alignment,
+        size,
+        ptr
+    );
+    env.mem.write(memptr, ptr);
+    0
+}
+
+fn memalign(env: &mut Environment, alignment: GuestUSize, size: GuestUSize) -> MutVoidPtr {
+    if !alignment.is_power_of_two() {
+        set_errno(env, EINVAL);
+        return Ptr::null();
+    }
+    set_errno(env, 0);
+    alloc_aligned(env, alignment, size)
+}
+
+fn valloc(env: &mut Environment, size: GuestUSize) -> MutVoidPtr {
+    set_errno(env, 0);
+    alloc_aligned(env, 4096, size) // page size
+}
+
 fn atexit(
-    _env: &mut Environment,
+    env: &mut Environment,
     func: GuestFunction, // void (*func)(void)
 ) -> i32 {
-    // TODO: when this is implemented, make sure it's properly compatible with
-    // __cxa_atexit.
-    log!("TODO: atexit({:?}) (unimplemented)", func);
+    log_dbg!("atexit({:?})", func);
+    register_atexit_handler(env, AtExitHandler::Plain(func));
     0 // success
 }
 
@@ -150,8 +438,7 @@ fn srand(env: &mut Environment, seed: u32) {
     env.libc_state.stdlib.rand = seed;
 }
 fn rand(env: &mut Environment) -> i32 {
-    env.libc_state.stdlib.rand = prng(env.libc_state.stdlib.rand);
-    (env.libc_state.stdlib.rand as i32) & RAND_MAX
+    env.libc_state.stdlib.next_rand()
 }
 
 // BSD's "better" random number generator, with an implementation that is not
@@ -166,13 +453,11 @@ fn random(env: &mut Environment) -> i32 {
     // TODO: handle errno properly
     set_errno(env, 0);
 
-    env.libc_state.stdlib.random = prng(env.libc_state.stdlib.random);
-    (env.libc_state.stdlib.random as i32) & RAND_MAX
+    env.libc_state.stdlib.next_random()
 }
 
 fn arc4random(env: &mut Environment) -> u32 {
-    env.libc_state.stdlib.arc4random = prng(env.libc_state.stdlib.arc4random);
-    env.libc_state.stdlib.arc4random
+    env.libc_state.stdlib.next_arc4random()
 }
 
 fn getenv(env: &mut Environment, name: ConstPtr<u8>) -> MutPtr<u8> {
@@ -224,6 +509,9 @@ fn exit(env: &mut Environment, exit_code: i32) {
     set_errno(env, 0);
 
     echo!("App called exit(), exiting.");
+    run_atexit_handlers(env, None);
+    stdio::flush_all(env);
+    env.print_exit_summaries();
     std::process::exit(exit_code);
 }
 
@@ -234,6 +522,30 @@ fn bsearch(
     item_count: GuestUSize,
     item_size: GuestUSize,
     compare_callback: GuestFunction, // (*int)(const void*, const void*)
+) -> ConstVoidPtr {
+    let compare = Comparator::Plain(compare_callback);
+    bsearch_inner(env, key, items, item_count, item_size, compare)
+}
+
+fn bsearch_b(
+    env: &mut Environment,
+    key: ConstVoidPtr,
+    items: ConstVoidPtr,
+    item_count: GuestUSize,
+    item_size: GuestUSize,
+    compare_block: ConstVoidPtr, // (^int)(const void*, const void*)
+) -> ConstVoidPtr {
+    let compare = Comparator::for_block(env, compare_block);
+    bsearch_inner(env, key, items, item_count, item_size, compare)
+}
+
+fn bsearch_inner(
+    env: &mut Environment,
+    key: ConstVoidPtr,
+    items: ConstVoidPtr,
+    item_count: GuestUSize,
+    item_size: GuestUSize,
+    compare: Comparator,
 ) -> ConstVoidPtr {
     log_dbg!(
         "binary search for {:?} in {} items of size {:#x} starting at {:?}",
@@ -248,7 +560,7 @@ fn bsearch(
         let half_len = len / 2;
         let item: ConstVoidPtr = (items.cast::<u8>() + item_size * (low + half_len)).cast();
         // key must be first argument
-        let cmp_result: i32 = compare_callback.call_from_host(env, (key, item));
+        let cmp_result = compare.call(env, key, item);
         (low, len) = match cmp_result.signum() {
             0 => {
                 log_dbg!("=> {:?}", item);
@@ -263,6 +575,53 @@ fn bsearch(
     Ptr::null()
 }
 
+// In all of these, the result for the most negative value is undefined. Since
+// ARM has no overflow trap, it's simply itself.
+fn abs(_env: &mut Environment, i: i32) -> i32 {
+    i.wrapping_abs()
+}
+fn labs(_env: &mut Environment, i: i32) -> i32 {
+    i.wrapping_abs()
+}
+fn llabs(_env: &mut Environment, i: i64) -> i64 {
+    i.wrapping_abs()
+}
+
+#[allow(non_camel_case_types)]
+#[derive(Debug)]
+#[repr(C, packed)]
+struct div_t {
+    quot: i32,
+    rem: i32,
+}
+unsafe impl SafeRead for div_t {}
+impl_GuestRet_for_large_struct!(div_t);
+
+/// `ldiv_t` has the same layout as `div_t`, since `long` is 32-bit.
+#[allow(non_camel_case_types)]
+type ldiv_t = div_t;
+
+fn div(_env: &mut Environment, numer: i32, denom: i32) -> div_t {
+    // Division by zero is undefined behavior in C, so any result will do, but
+    // Rust would panic.
+    if denom == 0 {
+        log!(
+            "App attempted to call div({}, 0), returning {{0, 0}}",
+            numer
+        );
+        return div_t { quot: 0, rem: 0 };
+    }
+    // Rust's integer division truncates towards zero and the remainder has the
+    // sign of the dividend, which is what C99 requires.
+    div_t {
+        quot: numer.wrapping_div(denom),
+        rem: numer.wrapping_rem(denom),
+    }
+}
+fn ldiv(env: &mut Environment, numer: i32, denom: i32) -> ldiv_t {
+    div(env, numer, denom)
+}
+
 fn strtof(env: &mut Environment, nptr: ConstPtr<u8>, endptr: MutPtr<ConstPtr<u8>>) -> f32 {
     // TODO: handle errno properly
     set_errno(env, 0);
@@ -274,7 +633,6 @@ fn strtof(env: &mut Environment, nptr: ConstPtr<u8>, endptr: MutPtr<ConstPtr<u8>
     number as f32
 }
 
-// TODO: fix same issues as for strtol()
 pub fn strtoul(
     env: &mut Environment,
     str: ConstPtr<u8>,
@@ -284,16 +642,22 @@ pub fn strtoul(
     // TODO: handle errno properly
     set_errno(env, 0);
 
-    let s = env.mem.cstr_at_utf8(str).unwrap();
-    log_dbg!("strtoul({:?} ({}), {:?}, {})", str, s, endptr, base);
-    assert_eq!(base, 16);
-    let without_prefix = s.trim_start_matches("0x");
-    let res = u32::from_str_radix(without_prefix, 16).unwrap_or(ULONG_MAX);
-    if !endptr.is_null() {
-        let len: GuestUSize = s.len().try_into().unwrap();
-        env.mem.write(endptr, (str + len).cast_mut());
+    match strtoul_inner(env, str, base as u32) {
+        Ok((res, len)) => {
+            if !endptr.is_null() {
+                env.mem.write(endptr, (str + len).cast_mut());
+            }
+            res
+        }
+        Err(_) => {
+            // No digits could be consumed
+            set_errno(env, EINVAL);
+            if !endptr.is_null() {
+                env.mem.write(endptr, str.cast_mut());
+            }
+            0
+        }
     }
-    res
 }
 
 fn strtol(env: &mut Environment, str: ConstPtr<u8>, endptr: MutPtr<MutPtr<u8>>, base: i32) -> i32 {
@@ -308,6 +672,8 @@ fn strtol(env: &mut Environment, str: ConstPtr<u8>, endptr: MutPtr<MutPtr<u8>>,
             res
         }
         Err(_) => {
+            // No digits could be consumed
+            set_errno(env, EINVAL);
             if !endptr.is_null() {
                 env.mem.write(endptr, str.cast_mut());
             }
@@ -324,12 +690,53 @@ fn realpath(
     assert!(!resolve_name.is_null());
 
     let file_name_str = env.mem.cstr_at_utf8(file_name).unwrap();
-    // TOD0: resolve symbolic links
-    let resolved = resolve_path(
-        GuestPath::new(file_name_str),
-        Some(env.fs.working_directory()),
-    );
-    let result = format!("/{}", resolved.join("/"));
+    // There are no symbolic links to resolve, because the guest filesystem
+    // doesn't have any (see crate::fs), so only `.` and `..` need resolving.
+    // This can't be done with resolve_path() alone though, because like on
+    // Darwin, each component must exist, even if a later `..` removes it.
+    let mut components = if file_name_str.starts_with('/') {
+        Vec::new()
+    } else {
+        resolve_path(env.fs.working_directory(), None)
+    };
+    let mut error = None;
+    for component in file_name_str.split('/') {
+        // Only directories can have further components after them.
+        if !env
+            .fs
+            .is_dir(GuestPath::new(&format!("/{}", components.join("/"))))
+        {
+            error = Some(ENOTDIR);
+            break;
+        }
+        match component {
+            "" | "." => (),
+            ".." => {
+                components.pop();
+            }
+            _ => {
+                components.push(component);
+                if !env
+                    .fs
+                    .exists(GuestPath::new(&format!("/{}", components.join("/"))))
+                {
+                    error = Some(ENOENT);
+                    break;
+                }
+            }
+        }
+    }
+    let result = format!("/{}", components.join("/"));
+    if let Some(error) = error {
+        log_dbg!(
+            "realpath file_name '{}' => NULL (errno {})",
+            file_name_str,
+            error
+        );
+        set_errno(env, error);
+        return Ptr::null();
+    }
+
     env.mem
         .bytes_at_mut(resolve_name, result.len() as GuestUSize)
         .copy_from_slice(result.as_bytes());
@@ -401,6 +808,9 @@ pub const FUNCTIONS: FunctionExports = &[
     export_c_func!(calloc(_, _)),
     export_c_func!(realloc(_, _)),
     export_c_func!(free(_)),
+    export_c_func!(posix_memalign(_, _, _)),
+    export_c_func!(memalign(_, _)),
+    export_c_func!(valloc(_)),
     export_c_func!(atexit(_)),
     export_c_func!(atoi(_)),
     export_c_func!(atol(_)),
@@ -415,6 +825,12 @@ pub const FUNCTIONS: FunctionExports = &[
     export_c_func!(setenv(_, _, _)),
     export_c_func!(exit(_)),
     export_c_func!(bsearch(_, _, _, _, _)),
+    export_c_func!(bsearch_b(_, _, _, _, _)),
+    export_c_func!(abs(_)),
+    export_c_func!(labs(_)),
+    export_c_func!(llabs(_)),
+    export_c_func!(div(_, _)),
+    export_c_func!(ldiv(_, _)),
     export_c_func!(strtof(_, _)),
     export_c_func!(strtoul(_, _, _)),
     export_c_func!(strtol(_, _, _)),
@@ -431,12 +847,58 @@ pub fn atof_inner(
     s: ConstPtr<u8>,
 ) -> Result<(f64, u32), <f64 as FromStr>::Err> {
     // atof() is similar to atoi().
-    // FIXME: no C99 hexfloat, INF, NAN support
     let start = skip_whitespace(env, s);
     let whitespace_len = Ptr::to_bits(start) - Ptr::to_bits(s);
+
+    let maybe_sign = env.mem.read(start);
+    let sign_len = if maybe_sign == b'+' || maybe_sign == b'-' {
+        1
+    } else {
+        0
+    };
+    let negative = maybe_sign == b'-';
+
+    // C99 infinity and NaN. Rust's parser accepts the same spellings, so only
+    // the length needs to be determined here.
+    for word in [&b"infinity"[..], &b"inf"[..], &b"nan"[..]] {
+        if !starts_with_ignore_case(env, start + sign_len, word) {
+            continue;
+        }
+        let mut len = sign_len + word.len() as GuestUSize;
+        // NAN(n-char-sequence) is also valid, the contents are ignored.
+        if word == b"nan" && env.mem.read(start + len) == b'(' {
+            let mut paren_len = 1;
+            loop {
+                let c = env.mem.read(start + len + paren_len);
+                if c == b')' {
+                    len += paren_len + 1;
+                    break;
+                } else if !(c.is_ascii_alphanumeric() || c == b'_') {
+                    break;
+                }
+                paren_len += 1;
+            }
+        }
+        let word_len = sign_len + word.len() as GuestUSize;
+        let s = std::str::from_utf8(env.mem.bytes_at(start, word_len)).unwrap();
+        return s.parse().map(|result| (result, whitespace_len + len));
+    }
+
+    // C99 hexadecimal floating-point, e.g. 0x1.8p3. Rust has no parser for
+    // this, so it's done by hand.
+    if env.mem.read(start + sign_len) == b'0'
+        && env.mem.read(start + sign_len + 1).to_ascii_lowercase() == b'x'
+    {
+        if let Some((result, len)) = parse_hex_float(env, start + sign_len + 2) {
+            let result = if negative { -result } else { result };
+            return Ok((result, whitespace_len + sign_len + 2 + len));
+        }
+        // If there are no digits after the 0x, only the 0 gets parsed,
+        // which the decimal path below will take care of.
+    }
+
     let mut len = 0;
-    let maybe_sign = env.mem.read(start + len);
-    if maybe_sign == b'+' || maybe_sign == b'-' || maybe_sign.is_ascii_digit() {
+    if sign_len != 0 || maybe_sign.is_ascii_digit() {
         len += 1;
     }
     while env.mem.read(start + len).is_ascii_digit() {
@@ -463,76 +925,221 @@ pub fn atof_inner(
     s.parse().map(|result| (result, whitespace_len + len))
 }
 
+/// Case-insensitive check for whether the string starts with an (ASCII,
+/// lowercase) word. This never reads past the string's null terminator.
+fn starts_with_ignore_case(env: &Environment, s: ConstPtr<u8>, word: &[u8]) -> bool {
+    word.iter()
+        .enumerate()
+        .all(|(i, &c)| env.mem.read(s + i as GuestUSize).to_ascii_lowercase() == c)
+}
+
+/// Parses the part of a C99 hexadecimal floating-point number after the `0x`
+/// prefix. Returns the (unsigned) value and the number of bytes consumed, or
+/// [None] if there are no hex digits.
+fn parse_hex_float(env: &Environment, s: ConstPtr<u8>) -> Option<(f64, GuestUSize)> {
+    let mut len = 0;
+    let mut mantissa = 0f64;
+    let mut exponent = 0i32;
+    let mut digit_count = 0;
+    while let Some(digit) = (env.mem.read(s + len) as char).to_digit(16) {
+        mantissa = mantissa * 16.0 + digit as f64;
+        digit_count += 1;
+        len += 1;
+    }
+    if env.mem.read(s + len) == b'.' {
+        len += 1;
+        while let Some(digit) = (env.mem.read(s + len) as char).to_digit(16) {
+            mantissa = mantissa * 16.0 + digit as f64;
+            exponent -= 4;
+            digit_count += 1;
+            len += 1;
+        }
+    }
+    if digit_count == 0 {
+        return None;
+    }
+
+    // The binary exponent is optional, and it's only consumed if it's valid.
+    if env.mem.read(s + len).to_ascii_lowercase() == b'p' {
+        let mut exp_len = 1;
+        let maybe_sign = env.mem.read(s + len + exp_len);
+        let exp_negative = maybe_sign == b'-';
+        if maybe_sign == b'+' || maybe_sign == b'-' {
+            exp_len += 1;
+        }
+        let mut exp_value = 0i32;
+        let mut exp_digit_count = 0;
+        while let Some(digit) = (env.mem.read(s + len + exp_len) as char).to_digit(10) {
+            exp_value = exp_value.saturating_mul(10).saturating_add(digit as i32);
+            exp_digit_count += 1;
+            exp_len += 1;
+        }
+        if exp_digit_count > 0 {
+            exponent = if exp_negative {
+                exponent.saturating_sub(exp_value)
+            } else {
+                exponent.saturating_add(exp_value)
+            };
+            len += exp_len;
+        }
+    }
+
+    // Applying the exponent in two steps avoids the intermediate power of two
+    // overflowing or underflowing when the final result wouldn't.
+    let half_exponent = exponent / 2;
+    let result = mantissa * 2f64.powi(half_exponent) * 2f64.powi(exponent - half_exponent);
+    Some((result, len))
+}
+
 /// Returns a tuple containing the parsed number in the given base and
-/// the length of the number in the string.
+/// the length of the number in the string. If the number is out of range, the
+/// result is saturated and `errno` is set to `ERANGE`.
+pub fn strtol_inner(env: &mut Environment, str: ConstPtr<u8>, base: u32) -> Result<(i32, u32), ()> {
+    let (magnitude, negative, len) = parse_integer(env, str, base)?;
+    let res = magnitude.map(|magnitude| {
+        if negative {
+            -(magnitude as i64)
+        } else {
+            magnitude as i64
+        }
+    });
+    let res = match res.and_then(|res| i32::try_from(res).ok()) {
+        Some(res) => res,
+        None => {
+            set_errno(env, ERANGE);
+            if negative {
+                LONG_MIN
+            } else {
+                LONG_MAX
+            }
+        }
+    };
+    Ok((res, len))
+}
+
+/// Like [strtol_inner], but for `unsigned long`. As in C, a negative number is
+/// negated in the unsigned type rather than being treated as a range error.
+fn strtoul_inner(env: &mut Environment, str: ConstPtr<u8>, base: u32) -> Result<(u32, u32), ()> {
+    let (magnitude, negative, len) = parse_integer(env, str, base)?;
+    let res = match magnitude {
+        Some(magnitude) if negative => magnitude.wrapping_neg(),
+        Some(magnitude) => magnitude,
+        None => {
+            set_errno(env, ERANGE);
+            ULONG_MAX
+        }
+    };
+    Ok((res, len))
+}
+
+/// Shared parsing for [strtol_inner] and [strtoul_inner]. Returns a tuple
+/// containing the magnitude of the number (or [None] if it doesn't fit in
+/// [u32]), whether it had a minus sign, and the length of the number in the
+/// string, or [Err] if there is no number or the base is invalid.
 /// Base is mutable because in case if base 0 we need to auto-detect it.
-pub fn strtol_inner(
+fn parse_integer(
     env: &mut Environment,
     str: ConstPtr<u8>,
     mut base: u32,
-) -> Result<(i32, u32), ()> {
+) -> Result<(Option<u32>, bool, u32), ()> {
     // strtol() doesn't work with a null-terminated string, instead it stops
     // once it hits something that's not a digit, so we have to do some parsing
     // ourselves.
     let start = skip_whitespace(env, str);
     let whitespace_len = Ptr::to_bits(start) - Ptr::to_bits(str);
+    if base != 0 && !(2..=36).contains(&base) {
+        return Err(());
+    }
     let mut len = 0;
     let maybe_sign = env.mem.read(start + len);
     let mut sign = None;
-    let mut prefix_length = 0;
     if maybe_sign == b'+' || maybe_sign == b'-' {
         sign = Some(maybe_sign);
-        prefix_length += 1;
         len += 1;
     }
+    // "0x" or "0X" is only a prefix if a hex digit follows it. Otherwise, the
+    // "0" is the whole number.
+    let has_hex_prefix = (base == 0 || base == 16)
+        && env.mem.read(start + len) == b'0'
+        && matches!(env.mem.read(start + len + 1), b'x' | b'X')
+        && env.mem.read(start + len + 2).is_ascii_hexdigit();
     // We need to do base detection before we can start counting
     // the number length, but after we maybe skipped the sign
     if base == 0 {
-        base = if env.mem.read(start + len) == b'0' {
-            let next = env.mem.read(start + len + 1);
-            if next == b'x' || next == b'X' {
-                16
-            } else {
-                8
-            }
+        base = if has_hex_prefix {
+            16
+        } else if env.mem.read(start + len) == b'0' {
+            8
         } else {
             10
         }
     }
-    // Skipping prefix if needed
-    if (base == 8 || base == 16) && env.mem.read(start + len) == b'0' {
-        len += 1;
-        prefix_length += 1;
-        if base == 16 {
-            let next = env.mem.read(start + len);
-            if next == b'x' || next == b'X' {
-                len += 1;
-                prefix_length += 1;
-            }
-        }
+    if has_hex_prefix {
+        len += 2;
     }
+    let digits_start = len;
     while (env.mem.read(start + len) as char).is_digit(base) {
         len += 1;
     }
+    if len == digits_start {
+        return Err(());
+    }
 
     let s =
-        std::str::from_utf8(env.mem.bytes_at(start + prefix_length, len - prefix_length)).unwrap();
-    log_dbg!("strtol_inner({:?} ({}), {})", str, s, base);
-    assert!((2..=36).contains(&base));
-    let magnitude_len = len - prefix_length;
-    let res = if magnitude_len > 0 {
-        // TODO: set errno on range errors
-        let mut res = i32::from_str_radix(s, base).unwrap_or(LONG_MAX);
-        if sign == Some(b'-') {
-            res = res.checked_mul(-1).unwrap_or(LONG_MIN);
-        }
-        res
-    } else {
-        // Special case - prefix of invalid octal number is a valid number 0
-        if base == 8 && prefix_length > 0 {
-            return Ok((0, whitespace_len + prefix_length));
-        }
-        return Err(());
-    };
-    Ok((res, whitespace_len + len))
+        std::str::from_utf8(env.mem.bytes_at(start + digits_start, len - digits_start)).unwrap();
+    log_dbg!("parse_integer({:?} ({}), {})", str, s, base);
+    // The only possible error at this point is overflow, since the digits
+    // were already validated.
+    let magnitude = u32::from_str_radix(s, base).ok();
+    Ok((magnitude, sign == Some(b'-'), whitespace_len + len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixed_rng_seed() {
+        let sequence = |seed| {
+            let mut state = State::default();
+            state.seed_rngs(seed);
+            (0..8)
+                .map(|_| {
+                    (
+                        state.next_rand(),
+                        state.next_random(),
+                        state.next_arc4random(),
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(sequence(Some(1234)), sequence(Some(1234)));
+        assert_ne!(sequence(Some(1234)), sequence(Some(5678)));
+
+        // The seed applies to all of the generators.
+        let mut state = State::default();
+        state.seed_rngs(Some(1234));
+        let first = prng(1234);
+        assert_eq!(state.next_rand(), (first as i32) & RAND_MAX);
+        assert_eq!(state.next_random(), (first as i32) & RAND_MAX);
+        assert_eq!(state.next_arc4random(), first);
+    }
+
+    #[test]
+    fn align_up_to_power_of_two() {
+        assert_eq!(align_up(0x1000, 64), 0x1000);
+        assert_eq!(align_up(0x1010, 64), 0x1040);
+        assert_eq!(align_up(0x1040, 64), 0x1040);
+        assert_eq!(align_up(0x1010, 16), 0x1010);
+        assert_eq!(align_up(0x1010, 4096), 0x2000);
+    }
+
+    #[test]
+    fn default_rng_seed() {
+        // Without --rng-seed, rand() behaves as if srand(1) was called.
+        let mut state = State::default();
+        state.seed_rngs(None);
+        assert_eq!(state.next_rand(), 0x42021);
+        assert_eq!(state.next_random(), 0x42021);
+    }
 }
@@ -3,19 +3,85 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/.
  */
-//! stdlib's qsort
+//! stdlib's qsort, qsort_r and qsort_b
 
 use crate::abi::{CallFromHost, GuestFunction};
 use crate::dyld::{export_c_func, FunctionExports};
-use crate::mem::MutPtr;
+use crate::libc::blocks::block_invoke_function;
+use crate::mem::{ConstVoidPtr, MutPtr, MutVoidPtr};
 use crate::Environment;
 
+/// A comparison callback, as used by `qsort`, `bsearch` and their variants.
+#[derive(Copy, Clone)]
+pub enum Comparator {
+    /// `int (*compar)(const void *, const void *)`, used by `qsort`.
+    Plain(GuestFunction),
+    /// `int (*compar)(void *thunk, const void *, const void *)`, used by
+    /// `qsort_r`. Note that glibc's `qsort_r` puts the context argument last
+    /// instead, but this is the Darwin order.
+    WithThunk(GuestFunction, MutVoidPtr),
+}
+impl Comparator {
+    /// `int (^compar)(const void *, const void *)`, used by `qsort_b`. A block
+    /// is called like a function with the block itself as a hidden first
+    /// argument, so this is just a special case of [Comparator::WithThunk].
+    pub fn for_block(env: &Environment, block: ConstVoidPtr) -> Comparator {
+        let invoke = block_invoke_function(&env.mem, block);
+        Comparator::WithThunk(invoke, block.cast_mut())
+    }
+
+    pub fn call(self, env: &mut Environment, a: ConstVoidPtr, b: ConstVoidPtr) -> i32 {
+        match self {
+            Comparator::Plain(compar) => compar.call_from_host(env, (a, b)),
+            Comparator::WithThunk(compar, thunk) => compar.call_from_host(env, (thunk, a, b)),
+        }
+    }
+}
+
 fn qsort(
     env: &mut Environment,
     base: MutPtr<u8>,
     nitems: u32,
     size: u32,
     compar: GuestFunction, // int (*compar)(const void *, const void*))
+) {
+    qsort_inner(env, base, nitems, size, Comparator::Plain(compar));
+}
+
+fn qsort_r(
+    env: &mut Environment,
+    base: MutPtr<u8>,
+    nitems: u32,
+    size: u32,
+    thunk: MutVoidPtr,
+    compar: GuestFunction, // int (*compar)(void *, const void *, const void*))
+) {
+    qsort_inner(
+        env,
+        base,
+        nitems,
+        size,
+        Comparator::WithThunk(compar, thunk),
+    );
+}
+
+fn qsort_b(
+    env: &mut Environment,
+    base: MutPtr<u8>,
+    nitems: u32,
+    size: u32,
+    compar: ConstVoidPtr, // int (^compar)(const void *, const void*))
+) {
+    let compar = Comparator::for_block(env, compar);
+    qsort_inner(env, base, nitems, size, compar);
+}
+
+fn qsort_inner(
+    env: &mut Environment,
+    base: MutPtr<u8>,
+    nitems: u32,
+    size: u32,
+    compar: Comparator,
 ) {
     if nitems < 2 {
         return;
@@ -28,7 +94,7 @@ fn qsort_rec(
     base: MutPtr<u8>,
     nitems: u32,
     size: u32,
-    compar: GuestFunction,
+    compar: Comparator,
     low: u32,
     hi: u32,
 ) {
@@ -55,13 +121,13 @@ fn compare(
     env: &mut Environment,
     base: MutPtr<u8>,
     size: u32,
-    compar: GuestFunction,
+    compar: Comparator,
     i: u32,
     j: u32,
 ) -> i32 {
-    let i_ptr = base + i * size;
-    let j_ptr = base + j * size;
-    compar.call_from_host(env, (i_ptr.cast_const(), j_ptr.cast_const()))
+    let i_ptr = (base + i * size).cast_const();
+    let j_ptr = (base + j * size).cast_const();
+    compar.call(env, i_ptr, j_ptr)
 }
 
 fn swap_slices(env: &mut Environment, base: MutPtr<u8>, nitems: u32, size: u32, i: u32, j: u32) {
@@ -79,4 +145,8 @@ fn swap_slices(env: &mut Environment, base: MutPtr<u8>, nitems: u32, size: u32,
     left[..size as usize].swap_with_slice(&mut right[..size as usize]);
 }
 
-pub const FUNCTIONS: FunctionExports = &[export_c_func!(qsort(_, _, _, _))];
+pub const FUNCTIONS: FunctionExports = &[
+    export_c_func!(qsort(_, _, _, _)),
+    export_c_func!(qsort_r(_, _, _, _, _)),
+    export_c_func!(qsort_b(_, _, _, _)),
+];
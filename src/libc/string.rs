@@ -86,6 +86,51 @@ fn memmove(
 ) -> MutVoidPtr {
     GenericChar::<u8>::memmove(env, dest.cast(), src.cast(), size).cast()
 }
+fn memccpy(
+    env: &mut Environment,
+    dest: MutVoidPtr,
+    src: ConstVoidPtr,
+    c: i32,
+    size: GuestUSize,
+) -> MutVoidPtr {
+    // Copying stops after the first occurrence of the character, if any.
+    let found = env
+        .mem
+        .bytes_at(src.cast(), size)
+        .iter()
+        .position(|&byte| byte == c as u8);
+    let count = found.map_or(size, |i| i as GuestUSize + 1);
+    env.mem.memmove(dest, src, count);
+    if found.is_some() {
+        (dest.cast::<u8>() + count).cast()
+    } else {
+        Ptr::null()
+    }
+}
+fn memmem(
+    env: &mut Environment,
+    big: ConstVoidPtr,
+    big_len: GuestUSize,
+    little: ConstVoidPtr,
+    little_len: GuestUSize,
+) -> ConstVoidPtr {
+    // An empty needle is found at the start of the haystack.
+    if little_len == 0 {
+        return big;
+    }
+    if little_len > big_len {
+        return Ptr::null();
+    }
+    let haystack = env.mem.bytes_at(big.cast(), big_len);
+    let needle = env.mem.bytes_at(little.cast(), little_len);
+    match haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+    {
+        Some(i) => (big.cast::<u8>() + i as GuestUSize).cast(),
+        None => Ptr::null(),
+    }
+}
 fn memchr(env: &mut Environment, string: ConstVoidPtr, c: i32, size: GuestUSize) -> ConstVoidPtr {
     GenericChar::<u8>::memchr(env, string.cast(), c as u8, size).cast()
 }
@@ -241,6 +286,8 @@ pub const FUNCTIONS: FunctionExports = &[
     export_c_func!(memset(_, _, _)),
     export_c_func!(memcpy(_, _, _)),
     export_c_func!(memmove(_, _, _)),
+    export_c_func!(memccpy(_, _, _, _)),
+    export_c_func!(memmem(_, _, _, _)),
     export_c_func!(memchr(_, _, _)),
     export_c_func!(memcmp(_, _, _)),
     export_c_func!(strlen(_)),
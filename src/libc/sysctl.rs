@@ -6,74 +6,66 @@
 //! `sys/sysctl.h`
 
 use crate::dyld::{export_c_func, FunctionExports};
-use crate::libc::errno::set_errno;
+use crate::libc::errno::{set_errno, ENOENT, ENOMEM, EPERM};
 use crate::libc::sysctl::SysInfoType::String;
 use crate::mem::{guest_size_of, ConstPtr, GuestUSize, MutPtr, MutVoidPtr};
 use crate::Environment;
+use std::borrow::Cow;
+
+const CTL_KERN: i32 = 1;
+const CTL_HW: i32 = 6;
+
+const KERN_OSTYPE: i32 = 1;
+const KERN_OSRELEASE: i32 = 2;
+const KERN_VERSION: i32 = 4;
+const KERN_HOSTNAME: i32 = 10;
+const KERN_OSVERSION: i32 = 65;
+
+const HW_MACHINE: i32 = 1;
+const HW_MODEL: i32 = 2;
+const HW_NCPU: i32 = 3;
+const HW_PHYSMEM: i32 = 5;
+const HW_USERMEM: i32 = 6;
+const HW_PAGESIZE: i32 = 7;
+const HW_BUS_FREQ: i32 = 14;
+const HW_CPU_FREQ: i32 = 15;
+const HW_MEMSIZE: i32 = 24;
 
 enum SysInfoType {
-    String(&'static [u8]),
+    String(Cow<'static, str>),
     Int32(i32),
     Int64(i64),
 }
 
-fn sysctl(
-    env: &mut Environment,
-    name: MutPtr<i32>,
-    name_len: u32,
-    oldp: MutVoidPtr,
-    oldlenp: MutPtr<GuestUSize>,
-    newp: MutVoidPtr,
-    newlen: GuestUSize,
-) -> i32 {
-    // TODO: handle errno properly
-    set_errno(env, 0);
-
-    log!(
-        "TODO: sysctl({:?}, {:#x}, {:?}, {:?}, {:?}, {:x})",
-        name,
-        name_len,
-        oldp,
-        oldlenp,
-        newp,
-        newlen
-    );
-    assert!(!oldp.is_null() && !oldlenp.is_null()); // TODO
-    assert!(newp.is_null()); // TODO
-    env.mem.write(oldlenp, 0);
-    0 // success
+/// Get the name used by `sysctlbyname()` for a "Management Information Base"
+/// style name used by `sysctl()`.
+fn name_for_mib(mib: &[i32]) -> Option<&'static str> {
+    Some(match mib {
+        [CTL_KERN, KERN_OSTYPE] => "kern.ostype",
+        [CTL_KERN, KERN_OSRELEASE] => "kern.osrelease",
+        [CTL_KERN, KERN_VERSION] => "kern.version",
+        [CTL_KERN, KERN_HOSTNAME] => "kern.hostname",
+        [CTL_KERN, KERN_OSVERSION] => "kern.osversion",
+        [CTL_HW, HW_MACHINE] => "hw.machine",
+        [CTL_HW, HW_MODEL] => "hw.model",
+        [CTL_HW, HW_NCPU] => "hw.ncpu",
+        [CTL_HW, HW_PHYSMEM] => "hw.physmem",
+        [CTL_HW, HW_USERMEM] => "hw.usermem",
+        [CTL_HW, HW_PAGESIZE] => "hw.pagesize",
+        [CTL_HW, HW_BUS_FREQ] => "hw.busfrequency",
+        [CTL_HW, HW_CPU_FREQ] => "hw.cpufrequency",
+        [CTL_HW, HW_MEMSIZE] => "hw.memsize",
+        _ => return None,
+    })
 }
 
-fn sysctlbyname(
-    env: &mut Environment,
-    name: ConstPtr<u8>,
-    oldp: MutVoidPtr,
-    oldlenp: MutPtr<GuestUSize>,
-    newp: MutVoidPtr,
-    newlen: GuestUSize,
-) -> i32 {
-    // TODO: handle errno properly
-    set_errno(env, 0);
-
-    let name_str = env.mem.cstr_at_utf8(name).unwrap();
-    log_dbg!(
-        "sysctlbyname({:?}, {:?}, {:?}, {:?}, {:x})",
-        name_str,
-        oldp,
-        oldlenp,
-        newp,
-        newlen
-    );
-
-    assert!(newp.is_null());
-    assert_eq!(newlen, 0);
-
+fn value_for_name(env: &Environment, name: &str) -> Option<SysInfoType> {
     // Below values corresponds to the original iPhone.
     // Reference https://www.mail-archive.com/misc@openbsd.org/msg80988.html
-    let val: SysInfoType = match name_str {
+    Some(match name {
         // Generic CPU, I/O
-        "hw.machine" => String(b"iPhone1,1"),
-        "hw.model" => String(b"M68AP"),
+        "hw.machine" => String(env.options.hw_machine.clone().into()),
+        "hw.model" => String("M68AP".into()),
         "hw.ncpu" => SysInfoType::Int32(1),
         "hw.cpufrequency" => SysInfoType::Int64(412000000),
         "hw.busfrequency" => SysInfoType::Int64(103000000),
@@ -82,33 +74,62 @@ fn sysctlbyname(
         "hw.memsize" => SysInfoType::Int64(121634816),
         "hw.pagesize" => SysInfoType::Int64(4096),
         // High kernel limits
-        "kern.ostype" => String(b"Darwin"),
-        "kern.osrelease" => String(b"10.0.0d3"),
-        "kern.osversion" => String(b"7A341"),
-        "kern.hostname" => String(b"touchHLE"), // this is arbitrary
-        "kern.version" => String(b"Darwin Kernel Version 10.0.0d3: Wed May 13 22:11:58 PDT 2009; root:xnu-1357.2.89~4/RELEASE_ARM_S5L8900X"),
-        _str => unimplemented!("{}", _str)
+        "kern.ostype" => String("Darwin".into()),
+        "kern.osrelease" => String("10.0.0d3".into()),
+        "kern.osversion" => String("7A341".into()),
+        "kern.hostname" => String("touchHLE".into()), // this is arbitrary
+        "kern.version" => String("Darwin Kernel Version 10.0.0d3: Wed May 13 22:11:58 PDT 2009; root:xnu-1357.2.89~4/RELEASE_ARM_S5L8900X".into()),
+        _ => return None,
+    })
+}
+
+/// Shared implementation of `sysctl()` and `sysctlbyname()`.
+fn get_value(
+    env: &mut Environment,
+    name: &str,
+    oldp: MutVoidPtr,
+    oldlenp: MutPtr<GuestUSize>,
+    newp: MutVoidPtr,
+) -> i32 {
+    if !newp.is_null() {
+        log!(
+            "Warning: app tried to set sysctl value {:?}, ignoring",
+            name
+        );
+        set_errno(env, EPERM);
+        return -1;
+    }
+
+    let Some(val) = value_for_name(env, name) else {
+        log!("TODO: sysctl value {:?}", name);
+        set_errno(env, ENOENT);
+        return -1;
     };
     let len: GuestUSize = match val {
-        String(str) => str.len() as GuestUSize + 1,
+        String(ref str) => str.len() as GuestUSize + 1,
         SysInfoType::Int32(_) => guest_size_of::<i32>(),
         SysInfoType::Int64(_) => guest_size_of::<i64>(),
     };
+    // A null pointer means the app only wants to know the size.
     if oldp.is_null() {
         env.mem.write(oldlenp, len);
         return 0;
     }
-    assert!(!oldp.is_null() && !oldlenp.is_null());
     let oldlen = env.mem.read(oldlenp);
     if oldlen < len {
-        // TODO: set errno
         // TODO: write partial data
-        log!("sysctlbyname for '{}': the buffer of size {} is too low to fit the value of size {}, returning -1", name_str, oldlen, len);
+        log!(
+            "sysctl for {:?}: the buffer of size {} is too small to fit the value of size {}, returning -1",
+            name,
+            oldlen,
+            len
+        );
+        set_errno(env, ENOMEM);
         return -1;
     }
     match val {
         String(str) => {
-            let sysctl_str = env.mem.alloc_and_write_cstr(str);
+            let sysctl_str = env.mem.alloc_and_write_cstr(str.as_bytes());
             env.mem.memmove(oldp, sysctl_str.cast().cast_const(), len);
             env.mem.free(sysctl_str.cast());
         }
@@ -123,7 +144,68 @@ fn sysctlbyname(
     0 // success
 }
 
+fn sysctl(
+    env: &mut Environment,
+    name: MutPtr<i32>,
+    name_len: u32,
+    oldp: MutVoidPtr,
+    oldlenp: MutPtr<GuestUSize>,
+    newp: MutVoidPtr,
+    newlen: GuestUSize,
+) -> i32 {
+    let mib: Vec<i32> = (0..name_len).map(|i| env.mem.read(name + i)).collect();
+    log_dbg!(
+        "sysctl({:?}, {:?}, {:?}, {:?}, {:x})",
+        mib,
+        oldp,
+        oldlenp,
+        newp,
+        newlen
+    );
+
+    let Some(name_str) = name_for_mib(&mib) else {
+        log!("TODO: sysctl name {:?}", mib);
+        set_errno(env, ENOENT);
+        return -1;
+    };
+    get_value(env, name_str, oldp, oldlenp, newp)
+}
+
+fn sysctlbyname(
+    env: &mut Environment,
+    name: ConstPtr<u8>,
+    oldp: MutVoidPtr,
+    oldlenp: MutPtr<GuestUSize>,
+    newp: MutVoidPtr,
+    newlen: GuestUSize,
+) -> i32 {
+    let name_str = env.mem.cstr_at_utf8(name).unwrap().to_owned();
+    log_dbg!(
+        "sysctlbyname({:?}, {:?}, {:?}, {:?}, {:x})",
+        name_str,
+        oldp,
+        oldlenp,
+        newp,
+        newlen
+    );
+    get_value(env, &name_str, oldp, oldlenp, newp)
+}
+
 pub const FUNCTIONS: FunctionExports = &[
     export_c_func!(sysctl(_, _, _, _, _, _)),
     export_c_func!(sysctlbyname(_, _, _, _, _)),
 ];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mib_names() {
+        assert_eq!(name_for_mib(&[CTL_HW, HW_MACHINE]), Some("hw.machine"));
+        assert_eq!(name_for_mib(&[CTL_HW, HW_NCPU]), Some("hw.ncpu"));
+        assert_eq!(name_for_mib(&[CTL_KERN, KERN_OSTYPE]), Some("kern.ostype"));
+        assert_eq!(name_for_mib(&[CTL_HW]), None);
+        assert_eq!(name_for_mib(&[CTL_HW, HW_MACHINE, 0]), None);
+    }
+}
@@ -6,10 +6,10 @@
 //! `time.h` (C) and `sys/time.h` (POSIX)
 
 use crate::dyld::{export_c_func, FunctionExports};
-use crate::libc::errno::set_errno;
-use crate::mem::{guest_size_of, ConstPtr, MutPtr, Ptr, SafeRead};
+use crate::libc::errno::{set_errno, EINVAL};
+use crate::mem::{guest_size_of, ConstPtr, MemSnapshot, MutPtr, Ptr, SafeRead};
 use crate::Environment;
-use std::time::{Duration, Instant, SystemTime};
+use std::time::{Duration, SystemTime};
 
 #[derive(Default)]
 pub struct State {
@@ -18,6 +18,13 @@ pub struct State {
     /// `localtime`. The standard allows calls to either to overwrite it.
     gmtime_tmp: Option<MutPtr<tm>>,
 }
+impl State {
+    /// Forget allocations that won't exist once `mem` is restored, see
+    /// [crate::Environment::load_snapshot].
+    pub fn forget_pointers_not_in(&mut self, mem: &MemSnapshot) {
+        self.gmtime_tmp = self.gmtime_tmp.filter(|tm| mem.is_used(tm.to_bits()));
+    }
+}
 
 // time.h (C)
 
@@ -31,28 +38,42 @@ type clock_t = u64;
 const CLOCKS_PER_SEC: clock_t = 1000000;
 
 fn clock(env: &mut Environment) -> clock_t {
-    Instant::now()
-        .duration_since(env.startup_time)
-        .as_secs()
-        .wrapping_mul(CLOCKS_PER_SEC)
+    monotonic_time(env).as_secs().wrapping_mul(CLOCKS_PER_SEC)
 }
 
 fn time(env: &mut Environment, out: MutPtr<time_t>) -> time_t {
     // TODO: handle errno properly
     set_errno(env, 0);
 
-    let time64 = SystemTime::now()
+    let time = secs_to_time_t(env, wall_clock_time(env).as_secs());
+    if !out.is_null() {
+        env.mem.write(out, time);
+    }
+    time
+}
+
+/// Time since the UNIX epoch according to the app's wall clock (see
+/// [crate::environment::clock]). This can jump if the host's clock is changed,
+/// unlike [monotonic_time].
+fn wall_clock_time(env: &Environment) -> Duration {
+    env.clock
+        .system_time()
         .duration_since(SystemTime::UNIX_EPOCH)
         .unwrap()
-        .as_secs();
-    let time = time64 as time_t;
-    if !env.libc_state.time.y2k38_warned && time64 != time as u64 {
+}
+
+/// Time since touchHLE started. This is the same clock as
+/// `mach_absolute_time()`.
+fn monotonic_time(env: &Environment) -> Duration {
+    env.clock.now().duration_since(env.startup_time)
+}
+
+fn secs_to_time_t(env: &mut Environment, secs: u64) -> time_t {
+    let time = secs as time_t;
+    if !env.libc_state.time.y2k38_warned && secs != time as u64 {
         env.libc_state.time.y2k38_warned = true;
         log!("Warning: system clock is beyond Y2K38 and might confuse the app");
     }
-    if !out.is_null() {
-        env.mem.write(out, time);
-    }
     time
 }
 
@@ -327,16 +348,8 @@ fn gettimeofday(
         return 0; // success
     }
 
-    let time = SystemTime::now()
-        .duration_since(SystemTime::UNIX_EPOCH)
-        .unwrap();
-
-    let time_s_64: u64 = time.as_secs();
-    let tv_sec = time_s_64 as time_t;
-    if !env.libc_state.time.y2k38_warned && time_s_64 != tv_sec as u64 {
-        env.libc_state.time.y2k38_warned = true;
-        log!("Warning: system clock is beyond Y2K38 and might confuse the app");
-    }
+    let time = wall_clock_time(env);
+    let tv_sec = secs_to_time_t(env, time.as_secs());
     let tv_usec: suseconds_t = time.subsec_micros().try_into().unwrap();
 
     env.mem.write(timeval_ptr, timeval { tv_sec, tv_usec });
@@ -344,6 +357,31 @@ fn gettimeofday(
     0 // success
 }
 
+#[allow(non_camel_case_types)]
+type clockid_t = i32;
+
+const CLOCK_REALTIME: clockid_t = 0;
+const CLOCK_MONOTONIC_RAW: clockid_t = 4;
+const CLOCK_MONOTONIC: clockid_t = 6;
+const CLOCK_UPTIME_RAW: clockid_t = 8;
+
+fn clock_gettime(env: &mut Environment, clock_id: clockid_t, tp: MutPtr<timespec>) -> i32 {
+    let time = match clock_id {
+        CLOCK_REALTIME => wall_clock_time(env),
+        // touchHLE doesn't suspend, so uptime and monotonic time are the same.
+        CLOCK_MONOTONIC | CLOCK_MONOTONIC_RAW | CLOCK_UPTIME_RAW => monotonic_time(env),
+        _ => {
+            log!("TODO: clock_gettime() with clock {}", clock_id);
+            set_errno(env, EINVAL);
+            return -1;
+        }
+    };
+    let tv_sec = secs_to_time_t(env, time.as_secs());
+    let tv_nsec = time.subsec_nanos().try_into().unwrap();
+    env.mem.write(tp, timespec { tv_sec, tv_nsec });
+    0 // success
+}
+
 fn nanosleep(env: &mut Environment, rqtp: ConstPtr<timespec>, _rmtp: MutPtr<timespec>) -> i32 {
     // TODO: handle errno properly
     set_errno(env, 0);
@@ -367,5 +405,6 @@ pub const FUNCTIONS: FunctionExports = &[
     export_c_func!(localtime_r(_, _)),
     export_c_func!(localtime(_)),
     export_c_func!(gettimeofday(_, _)),
+    export_c_func!(clock_gettime(_, _)),
     export_c_func!(nanosleep(_, _)),
 ];
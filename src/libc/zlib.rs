@@ -0,0 +1,602 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `zlib.h`, which on iPhone OS is provided by `libz.dylib` rather than
+//! libSystem.
+//!
+//! Implemented as a wrapper around miniz_oxide. The zlib and raw deflate
+//! formats are supported, and the gzip format is supported for decompression
+//! only (see [gzip]).
+//!
+//! Each stream's state lives on the host side. The `state` field of the guest's
+//! `z_stream` points to a dummy allocation that identifies it.
+
+use crate::abi::GuestFunction;
+use crate::dyld::{export_c_func, FunctionExports};
+use crate::mem::{
+    guest_size_of, ConstPtr, GuestUSize, MemSnapshot, MutPtr, MutVoidPtr, Ptr, SafeRead,
+};
+use crate::Environment;
+use miniz_oxide::deflate::core::{create_comp_flags_from_zip_params, CompressorOxide};
+use miniz_oxide::inflate::stream::InflateState;
+use miniz_oxide::{DataFormat, MZError, MZFlush, MZStatus};
+use std::collections::HashMap;
+
+mod gzip;
+
+const Z_OK: i32 = 0;
+const Z_STREAM_END: i32 = 1;
+const Z_NEED_DICT: i32 = 2;
+const Z_ERRNO: i32 = -1;
+const Z_STREAM_ERROR: i32 = -2;
+const Z_DATA_ERROR: i32 = -3;
+const Z_MEM_ERROR: i32 = -4;
+const Z_BUF_ERROR: i32 = -5;
+const Z_VERSION_ERROR: i32 = -6;
+
+const Z_DEFAULT_COMPRESSION: i32 = -1;
+const Z_DEFAULT_STRATEGY: i32 = 0;
+const Z_DEFLATED: i32 = 8;
+const MAX_WBITS: i32 = 15;
+
+/// The version of zlib shipped with iPhone OS 2 and 3.
+const ZLIB_VERSION: &[u8] = b"1.2.3";
+
+#[derive(Default)]
+pub struct State {
+    /// Keyed by the `state` field of the `z_stream`.
+    streams: HashMap<MutVoidPtr, ZStream>,
+    version: Option<ConstPtr<u8>>,
+}
+impl State {
+    /// Forget allocations that won't exist once `mem` is restored, see
+    /// [crate::Environment::load_snapshot].
+    pub fn forget_pointers_not_in(&mut self, mem: &MemSnapshot) {
+        self.version = self
+            .version
+            .filter(|version| mem.is_used(version.to_bits()));
+    }
+}
+
+/// Returns [true] if the app has any compression or decompression streams that
+/// haven't been ended.
+pub fn has_open_streams(env: &Environment) -> bool {
+    !env.libc_state.zlib.streams.is_empty()
+}
+
+#[allow(dead_code)]
+#[repr(C, packed)]
+struct z_stream {
+    next_in: ConstPtr<u8>,
+    avail_in: u32,
+    total_in: u32,
+    next_out: MutPtr<u8>,
+    avail_out: u32,
+    total_out: u32,
+    msg: ConstPtr<u8>,
+    state: MutVoidPtr,
+    /// Ignored: host memory is used for the stream state.
+    zalloc: GuestFunction,
+    /// Ignored: host memory is used for the stream state.
+    zfree: GuestFunction,
+    opaque: MutVoidPtr,
+    data_type: i32,
+    adler: u32,
+    reserved: u32,
+}
+unsafe impl SafeRead for z_stream {}
+
+enum ZStream {
+    Deflate(Box<CompressorOxide>),
+    Inflate(Box<InflateState>),
+    /// Inflating the gzip format, or either the gzip or zlib format.
+    GzipInflate(Box<gzip::GzipInflate>),
+}
+
+impl ZStream {
+    fn new_deflate(level: i32, window_bits: i32, strategy: i32) -> Option<ZStream> {
+        let level = if level == Z_DEFAULT_COMPRESSION {
+            6
+        } else {
+            level
+        };
+        if !(0..=9).contains(&level) || !(8..=15).contains(&window_bits.abs()) {
+            return None;
+        }
+        let flags = create_comp_flags_from_zip_params(level, window_bits, strategy);
+        Some(ZStream::Deflate(Box::new(CompressorOxide::new(flags))))
+    }
+
+    fn new_inflate(window_bits: i32) -> Option<ZStream> {
+        // miniz_oxide always takes the window size from the header, so other
+        // than selecting the format, windowBits only needs to be validated.
+        // 0 means the window size should be taken from the zlib header.
+        match window_bits {
+            0 | 8..=15 => Some(ZStream::Inflate(InflateState::new_boxed(DataFormat::Zlib))),
+            -15..=-8 => Some(ZStream::Inflate(InflateState::new_boxed(DataFormat::Raw))),
+            // Adding 16 selects the gzip format.
+            16 | 24..=31 => Some(ZStream::GzipInflate(Box::new(gzip::GzipInflate::new(
+                /* detect_zlib: */ false,
+            )))),
+            // Adding 32 selects automatic detection of the zlib or gzip format.
+            32 | 40..=47 => Some(ZStream::GzipInflate(Box::new(gzip::GzipInflate::new(
+                /* detect_zlib: */ true,
+            )))),
+            _ => {
+                log!("Invalid windowBits {} for inflate", window_bits);
+                None
+            }
+        }
+    }
+
+    /// Run the stream on host buffers. Returns the number of bytes consumed
+    /// and written, and the zlib return code.
+    fn step(&mut self, input: &[u8], output: &mut [u8], flush: MZFlush) -> (usize, usize, i32) {
+        match self {
+            ZStream::Deflate(compressor) => {
+                let result =
+                    miniz_oxide::deflate::stream::deflate(compressor, input, output, flush);
+                (
+                    result.bytes_consumed,
+                    result.bytes_written,
+                    status_code(result.status),
+                )
+            }
+            ZStream::Inflate(state) => inflate_step(state, input, output, flush),
+            ZStream::GzipInflate(gzip) => gzip.step(input, output, flush),
+        }
+    }
+
+    /// Process all of `input` in one go, like `compress()` and `uncompress()`.
+    /// Returns the number of bytes written and the zlib return code.
+    fn one_shot(&mut self, input: &[u8], output: &mut [u8]) -> (usize, i32) {
+        let (_, written, code) = self.step(input, output, MZFlush::Finish);
+        let code = match (self, code) {
+            (_, Z_STREAM_END) => Z_OK,
+            (ZStream::Deflate(_), Z_OK) => Z_BUF_ERROR,
+            (ZStream::Inflate(_) | ZStream::GzipInflate(_), Z_OK | Z_BUF_ERROR | Z_NEED_DICT) => {
+                if written == output.len() && code != Z_NEED_DICT {
+                    Z_BUF_ERROR
+                } else {
+                    // The input ended before the end of the stream.
+                    Z_DATA_ERROR
+                }
+            }
+            (_, code) => code,
+        };
+        (written, code)
+    }
+
+    fn adler(&mut self) -> u32 {
+        match self {
+            ZStream::Deflate(compressor) => compressor.adler32(),
+            ZStream::Inflate(state) => state.decompressor().adler32().unwrap_or(1),
+            ZStream::GzipInflate(gzip) => gzip.adler(),
+        }
+    }
+}
+
+/// Run miniz_oxide's inflate. Returns the number of bytes consumed and written,
+/// and the zlib return code.
+fn inflate_step(
+    state: &mut InflateState,
+    input: &[u8],
+    output: &mut [u8],
+    flush: MZFlush,
+) -> (usize, usize, i32) {
+    // miniz_oxide treats Z_FINISH on the first call as a promise that the
+    // output buffer is big enough, and fails for good if it isn't, whereas
+    // zlib treats it as a hint.
+    let flush = if flush == MZFlush::Finish {
+        MZFlush::None
+    } else {
+        flush
+    };
+    let result = miniz_oxide::inflate::stream::inflate(state, input, output, flush);
+    (
+        result.bytes_consumed,
+        result.bytes_written,
+        status_code(result.status),
+    )
+}
+
+fn status_code(status: Result<MZStatus, MZError>) -> i32 {
+    match status {
+        Ok(MZStatus::Ok) => Z_OK,
+        Ok(MZStatus::StreamEnd) => Z_STREAM_END,
+        Ok(MZStatus::NeedDict) => Z_NEED_DICT,
+        Err(MZError::ErrNo) => Z_ERRNO,
+        Err(MZError::Data) => Z_DATA_ERROR,
+        Err(MZError::Mem) => Z_MEM_ERROR,
+        Err(MZError::Buf) => Z_BUF_ERROR,
+        Err(MZError::Version) => Z_VERSION_ERROR,
+        Err(_) => Z_STREAM_ERROR,
+    }
+}
+
+fn zlibVersion(env: &mut Environment) -> ConstPtr<u8> {
+    if let Some(version) = env.libc_state.zlib.version {
+        return version;
+    }
+    let version = env.mem.alloc_and_write_cstr(ZLIB_VERSION).cast_const();
+    env.libc_state.zlib.version = Some(version);
+    version
+}
+
+fn check_version(env: &Environment, version: ConstPtr<u8>, stream_size: i32) -> bool {
+    !version.is_null()
+        && env.mem.read(version) == ZLIB_VERSION[0]
+        && stream_size == guest_size_of::<z_stream>() as i32
+}
+
+fn compressBound(_env: &mut Environment, source_len: GuestUSize) -> GuestUSize {
+    source_len + (source_len >> 12) + (source_len >> 14) + 11
+}
+
+fn compress(
+    env: &mut Environment,
+    dest: MutPtr<u8>,
+    dest_len: MutPtr<GuestUSize>,
+    source: ConstPtr<u8>,
+    source_len: GuestUSize,
+) -> i32 {
+    compress2(
+        env,
+        dest,
+        dest_len,
+        source,
+        source_len,
+        Z_DEFAULT_COMPRESSION,
+    )
+}
+
+fn compress2(
+    env: &mut Environment,
+    dest: MutPtr<u8>,
+    dest_len: MutPtr<GuestUSize>,
+    source: ConstPtr<u8>,
+    source_len: GuestUSize,
+    level: i32,
+) -> i32 {
+    let Some(stream) = ZStream::new_deflate(level, MAX_WBITS, Z_DEFAULT_STRATEGY) else {
+        return Z_STREAM_ERROR;
+    };
+    one_shot(env, stream, dest, dest_len, source, source_len)
+}
+
+fn uncompress(
+    env: &mut Environment,
+    dest: MutPtr<u8>,
+    dest_len: MutPtr<GuestUSize>,
+    source: ConstPtr<u8>,
+    source_len: GuestUSize,
+) -> i32 {
+    let stream = ZStream::new_inflate(MAX_WBITS).unwrap();
+    one_shot(env, stream, dest, dest_len, source, source_len)
+}
+
+fn one_shot(
+    env: &mut Environment,
+    mut stream: ZStream,
+    dest: MutPtr<u8>,
+    dest_len: MutPtr<GuestUSize>,
+    source: ConstPtr<u8>,
+    source_len: GuestUSize,
+) -> i32 {
+    let input = env.mem.bytes_at(source, source_len).to_vec();
+    let output_len = env.mem.read(dest_len);
+    let output = env.mem.bytes_at_mut(dest, output_len);
+    let (written, code) = stream.one_shot(&input, output);
+    env.mem.write(dest_len, written.try_into().unwrap());
+    code
+}
+
+/// Set up a new stream for a `z_stream` provided by the app.
+fn init_stream(env: &mut Environment, strm: MutPtr<z_stream>, stream: ZStream) -> i32 {
+    if strm.is_null() {
+        return Z_STREAM_ERROR;
+    }
+    let mut z_stream = env.mem.read(strm);
+    let state = env.mem.alloc(1);
+    z_stream.state = state;
+    z_stream.total_in = 0;
+    z_stream.total_out = 0;
+    z_stream.msg = Ptr::null();
+    z_stream.adler = 1;
+    env.mem.write(strm, z_stream);
+    env.libc_state.zlib.streams.insert(state, stream);
+    Z_OK
+}
+
+/// Run a stream set up by [init_stream] on the app's buffers.
+fn run_stream(env: &mut Environment, strm: MutPtr<z_stream>, flush: i32, deflate: bool) -> i32 {
+    if strm.is_null() {
+        return Z_STREAM_ERROR;
+    }
+    let mut z_stream = env.mem.read(strm);
+    let state = z_stream.state;
+    let (next_in, avail_in) = (z_stream.next_in, z_stream.avail_in);
+    let (next_out, avail_out) = (z_stream.next_out, z_stream.avail_out);
+    let Ok(flush) = MZFlush::new(flush) else {
+        return Z_STREAM_ERROR;
+    };
+
+    let input = if avail_in == 0 {
+        Vec::new()
+    } else {
+        env.mem.bytes_at(next_in, avail_in).to_vec()
+    };
+    let stream = match env.libc_state.zlib.streams.get_mut(&state) {
+        Some(stream @ ZStream::Deflate(_)) if deflate => stream,
+        Some(stream @ (ZStream::Inflate(_) | ZStream::GzipInflate(_))) if !deflate => stream,
+        _ => return Z_STREAM_ERROR,
+    };
+    let output: &mut [u8] = if avail_out == 0 {
+        &mut []
+    } else {
+        env.mem.bytes_at_mut(next_out, avail_out)
+    };
+    let (consumed, written, code) = stream.step(&input, output, flush);
+    let adler = stream.adler();
+
+    let consumed: GuestUSize = consumed.try_into().unwrap();
+    let written: GuestUSize = written.try_into().unwrap();
+    z_stream.next_in = next_in + consumed;
+    z_stream.avail_in = avail_in - consumed;
+    z_stream.total_in += consumed;
+    z_stream.next_out = next_out + written;
+    z_stream.avail_out = avail_out - written;
+    z_stream.total_out += written;
+    z_stream.adler = adler;
+    env.mem.write(strm, z_stream);
+    code
+}
+
+fn end_stream(env: &mut Environment, strm: MutPtr<z_stream>) -> i32 {
+    if strm.is_null() {
+        return Z_STREAM_ERROR;
+    }
+    let mut z_stream = env.mem.read(strm);
+    let state = z_stream.state;
+    if env.libc_state.zlib.streams.remove(&state).is_none() {
+        return Z_STREAM_ERROR;
+    }
+    env.mem.free(state);
+    z_stream.state = Ptr::null();
+    env.mem.write(strm, z_stream);
+    Z_OK
+}
+
+fn deflateInit_(
+    env: &mut Environment,
+    strm: MutPtr<z_stream>,
+    level: i32,
+    version: ConstPtr<u8>,
+    stream_size: i32,
+) -> i32 {
+    deflateInit2_(
+        env,
+        strm,
+        level,
+        Z_DEFLATED,
+        MAX_WBITS,
+        8,
+        Z_DEFAULT_STRATEGY,
+        version,
+        stream_size,
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+fn deflateInit2_(
+    env: &mut Environment,
+    strm: MutPtr<z_stream>,
+    level: i32,
+    method: i32,
+    window_bits: i32,
+    _mem_level: i32,
+    strategy: i32,
+    version: ConstPtr<u8>,
+    stream_size: i32,
+) -> i32 {
+    if !check_version(env, version, stream_size) {
+        return Z_VERSION_ERROR;
+    }
+    if method != Z_DEFLATED {
+        return Z_STREAM_ERROR;
+    }
+    let Some(stream) = ZStream::new_deflate(level, window_bits, strategy) else {
+        return Z_STREAM_ERROR;
+    };
+    init_stream(env, strm, stream)
+}
+
+fn deflate(env: &mut Environment, strm: MutPtr<z_stream>, flush: i32) -> i32 {
+    run_stream(env, strm, flush, /* deflate: */ true)
+}
+
+fn deflateEnd(env: &mut Environment, strm: MutPtr<z_stream>) -> i32 {
+    end_stream(env, strm)
+}
+
+fn inflateInit_(
+    env: &mut Environment,
+    strm: MutPtr<z_stream>,
+    version: ConstPtr<u8>,
+    stream_size: i32,
+) -> i32 {
+    inflateInit2_(env, strm, MAX_WBITS, version, stream_size)
+}
+
+fn inflateInit2_(
+    env: &mut Environment,
+    strm: MutPtr<z_stream>,
+    window_bits: i32,
+    version: ConstPtr<u8>,
+    stream_size: i32,
+) -> i32 {
+    if !check_version(env, version, stream_size) {
+        return Z_VERSION_ERROR;
+    }
+    let Some(stream) = ZStream::new_inflate(window_bits) else {
+        return Z_STREAM_ERROR;
+    };
+    init_stream(env, strm, stream)
+}
+
+fn inflate(env: &mut Environment, strm: MutPtr<z_stream>, flush: i32) -> i32 {
+    run_stream(env, strm, flush, /* deflate: */ false)
+}
+
+fn inflateEnd(env: &mut Environment, strm: MutPtr<z_stream>) -> i32 {
+    end_stream(env, strm)
+}
+
+pub const FUNCTIONS: FunctionExports = &[
+    export_c_func!(zlibVersion()),
+    export_c_func!(compressBound(_)),
+    export_c_func!(compress(_, _, _, _)),
+    export_c_func!(compress2(_, _, _, _, _)),
+    export_c_func!(uncompress(_, _, _, _)),
+    export_c_func!(deflateInit_(_, _, _, _)),
+    export_c_func!(deflateInit2_(_, _, _, _, _, _, _, _)),
+    export_c_func!(deflate(_, _)),
+    export_c_func!(deflateEnd(_)),
+    export_c_func!(inflateInit_(_, _, _)),
+    export_c_func!(inflateInit2_(_, _, _, _)),
+    export_c_func!(inflate(_, _)),
+    export_c_func!(inflateEnd(_)),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feed the input and provide space for output a little at a time, like an
+    /// app with small buffers would, until the stream ends.
+    fn run_in_chunks(stream: &mut ZStream, input: &[u8], chunk_size: usize) -> Vec<u8> {
+        let mut output = Vec::new();
+        let mut input_pos = 0;
+        loop {
+            let input_end = (input_pos + chunk_size).min(input.len());
+            let flush = if input_end == input.len() {
+                MZFlush::Finish
+            } else {
+                MZFlush::None
+            };
+            let mut buffer = vec![0; chunk_size];
+            let (consumed, written, code) =
+                stream.step(&input[input_pos..input_end], &mut buffer, flush);
+            assert!(consumed > 0 || written > 0, "no progress (code {})", code);
+            input_pos += consumed;
+            output.extend_from_slice(&buffer[..written]);
+            match code {
+                Z_STREAM_END => return output,
+                Z_OK | Z_BUF_ERROR => (),
+                _ => panic!("unexpected code {}", code),
+            }
+        }
+    }
+
+    fn test_data() -> Vec<u8> {
+        (0..10_000u32).map(|i| (i * i % 251) as u8).collect()
+    }
+
+    #[test]
+    fn deflate_inflate_round_trip() {
+        let data = test_data();
+        for window_bits in [MAX_WBITS, -MAX_WBITS] {
+            let mut deflate =
+                ZStream::new_deflate(Z_DEFAULT_COMPRESSION, window_bits, Z_DEFAULT_STRATEGY)
+                    .unwrap();
+            let compressed = run_in_chunks(&mut deflate, &data, 64);
+            assert!(compressed.len() < data.len());
+
+            let mut inflate = ZStream::new_inflate(window_bits).unwrap();
+            assert_eq!(run_in_chunks(&mut inflate, &compressed, 64), data);
+        }
+    }
+
+    /// `"touchHLE"` repeated 8 times, compressed by Python's gzip module with
+    /// the file name `a.txt`.
+    const GZIP_DATA: &[u8] = &[
+        0x1f, 0x8b, 0x08, 0x08, 0x00, 0x00, 0x00, 0x00, 0x02, 0xff, 0x61, 0x2e, 0x74, 0x78, 0x74,
+        0x00, 0x2b, 0xc9, 0x2f, 0x4d, 0xce, 0xf0, 0xf0, 0x71, 0x2d, 0x21, 0x93, 0x06, 0x00, 0xd9,
+        0xb6, 0x41, 0x9e, 0x40, 0x00, 0x00, 0x00,
+    ];
+
+    #[test]
+    fn inflate_window_bits() {
+        let data = test_data();
+        let mut deflate = ZStream::new_deflate(9, MAX_WBITS, Z_DEFAULT_STRATEGY).unwrap();
+        let zlib_data = run_in_chunks(&mut deflate, &data, 64);
+
+        // 0 means zlib with the window size from the header, not raw deflate.
+        let mut inflate = ZStream::new_inflate(0).unwrap();
+        assert_eq!(run_in_chunks(&mut inflate, &zlib_data, 64), data);
+
+        let gzip_output = b"touchHLE".repeat(8);
+        for window_bits in [MAX_WBITS + 16, MAX_WBITS + 32] {
+            // A chunk size of 1 means the header and trailer are split up.
+            for chunk_size in [1, 64] {
+                let mut inflate = ZStream::new_inflate(window_bits).unwrap();
+                assert_eq!(
+                    run_in_chunks(&mut inflate, GZIP_DATA, chunk_size),
+                    gzip_output
+                );
+                assert_eq!(inflate.adler(), 0x9e41b6d9);
+            }
+        }
+
+        // Automatic detection also accepts zlib, but gzip alone doesn't.
+        let mut inflate = ZStream::new_inflate(MAX_WBITS + 32).unwrap();
+        assert_eq!(run_in_chunks(&mut inflate, &zlib_data, 64), data);
+        let mut inflate = ZStream::new_inflate(MAX_WBITS + 16).unwrap();
+        let mut output = vec![0; data.len()];
+        assert_eq!(
+            inflate.step(&zlib_data, &mut output, MZFlush::Finish).2,
+            Z_DATA_ERROR
+        );
+
+        // The trailer is checked.
+        let mut corrupted = GZIP_DATA.to_vec();
+        *corrupted.last_mut().unwrap() ^= 1;
+        let mut inflate = ZStream::new_inflate(MAX_WBITS + 16).unwrap();
+        let (_, written, code) = inflate.step(&corrupted, &mut output, MZFlush::Finish);
+        assert_eq!((written, code), (gzip_output.len(), Z_DATA_ERROR));
+
+        for window_bits in [1, 7, 16 + 7, 64, -1, -16 - MAX_WBITS] {
+            assert!(ZStream::new_inflate(window_bits).is_none());
+        }
+    }
+
+    #[test]
+    fn compress_uncompress_round_trip() {
+        let data = test_data();
+        let mut compressed = vec![0; data.len() * 2];
+        let mut deflate = ZStream::new_deflate(9, MAX_WBITS, Z_DEFAULT_STRATEGY).unwrap();
+        let (compressed_len, code) = deflate.one_shot(&data, &mut compressed);
+        assert_eq!(code, Z_OK);
+        compressed.truncate(compressed_len);
+
+        let mut output = vec![0; data.len()];
+        let mut inflate = ZStream::new_inflate(MAX_WBITS).unwrap();
+        assert_eq!(
+            inflate.one_shot(&compressed, &mut output),
+            (data.len(), Z_OK)
+        );
+        assert_eq!(output, data);
+
+        // Not enough space for the output
+        let mut inflate = ZStream::new_inflate(MAX_WBITS).unwrap();
+        let (_, code) = inflate.one_shot(&compressed, &mut output[..100]);
+        assert_eq!(code, Z_BUF_ERROR);
+
+        // Truncated input
+        let mut inflate = ZStream::new_inflate(MAX_WBITS).unwrap();
+        let (_, code) = inflate.one_shot(&compressed[..compressed_len / 2], &mut output);
+        assert_eq!(code, Z_DATA_ERROR);
+    }
+}
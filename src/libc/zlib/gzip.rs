@@ -0,0 +1,238 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! Decompression of the gzip format (RFC 1952), which miniz_oxide doesn't
+//! handle itself. The header and trailer are parsed here, and the deflate data
+//! in between is passed to miniz_oxide.
+
+use super::{inflate_step, Z_BUF_ERROR, Z_DATA_ERROR, Z_OK, Z_STREAM_END};
+use miniz_oxide::inflate::stream::InflateState;
+use miniz_oxide::{DataFormat, MZFlush};
+
+const MAGIC: [u8; 2] = [0x1f, 0x8b];
+/// The only compression method, deflate.
+const CM_DEFLATE: u8 = 8;
+
+const FHCRC: u8 = 1 << 1;
+const FEXTRA: u8 = 1 << 2;
+const FNAME: u8 = 1 << 3;
+const FCOMMENT: u8 = 1 << 4;
+const FRESERVED: u8 = 0xe0;
+
+pub(super) struct GzipInflate {
+    phase: Phase,
+    /// Whether the zlib format is also accepted (`windowBits` + 32).
+    detect_zlib: bool,
+    state: Box<InflateState>,
+    /// CRC-32 and size (modulo 2^32) of the output so far, which the trailer
+    /// is checked against.
+    crc: u32,
+    size: u32,
+}
+
+enum Phase {
+    /// Contains the bytes of the header read so far.
+    Header(Vec<u8>),
+    Body,
+    /// Contains the bytes of the trailer read so far.
+    Trailer(Vec<u8>),
+    End,
+    /// The data turned out to be in the zlib format, so [GzipInflate::state]
+    /// handles everything.
+    Zlib,
+}
+
+impl GzipInflate {
+    pub(super) fn new(detect_zlib: bool) -> GzipInflate {
+        GzipInflate {
+            phase: Phase::Header(Vec::new()),
+            detect_zlib,
+            state: InflateState::new_boxed(DataFormat::Raw),
+            crc: 0,
+            size: 0,
+        }
+    }
+
+    /// See [super::ZStream::step].
+    pub(super) fn step(
+        &mut self,
+        input: &[u8],
+        output: &mut [u8],
+        flush: MZFlush,
+    ) -> (usize, usize, i32) {
+        let mut consumed = 0;
+        let mut written = 0;
+        loop {
+            match self.phase {
+                Phase::Header(ref mut header) => {
+                    // A zlib header can never start with the gzip magic number,
+                    // because the compression method would be invalid.
+                    if self.detect_zlib
+                        && header.is_empty()
+                        && input.get(consumed).is_some_and(|&byte| byte != MAGIC[0])
+                    {
+                        self.state = InflateState::new_boxed(DataFormat::Zlib);
+                        self.phase = Phase::Zlib;
+                        continue;
+                    }
+                    let Some(&byte) = input.get(consumed) else {
+                        return out_of_input(consumed, written);
+                    };
+                    consumed += 1;
+                    header.push(byte);
+                    match header_len(header) {
+                        Ok(Some(_)) => self.phase = Phase::Body,
+                        Ok(None) => (),
+                        Err(()) => return (consumed, written, Z_DATA_ERROR),
+                    }
+                }
+                Phase::Body => {
+                    let (body_consumed, body_written, code) = inflate_step(
+                        &mut self.state,
+                        &input[consumed..],
+                        &mut output[written..],
+                        flush,
+                    );
+                    self.crc = crc32(self.crc, &output[written..][..body_written]);
+                    self.size = self.size.wrapping_add(body_written as u32);
+                    consumed += body_consumed;
+                    written += body_written;
+                    match code {
+                        Z_STREAM_END => self.phase = Phase::Trailer(Vec::new()),
+                        Z_BUF_ERROR => return out_of_input(consumed, written),
+                        _ => return (consumed, written, code),
+                    }
+                }
+                Phase::Trailer(ref mut trailer) => {
+                    let Some(&byte) = input.get(consumed) else {
+                        return out_of_input(consumed, written);
+                    };
+                    consumed += 1;
+                    trailer.push(byte);
+                    if trailer.len() == 8 {
+                        let crc = u32::from_le_bytes(trailer[..4].try_into().unwrap());
+                        let size = u32::from_le_bytes(trailer[4..].try_into().unwrap());
+                        if (crc, size) != (self.crc, self.size) {
+                            return (consumed, written, Z_DATA_ERROR);
+                        }
+                        self.phase = Phase::End;
+                    }
+                }
+                Phase::End => return (consumed, written, Z_STREAM_END),
+                Phase::Zlib => {
+                    let (zlib_consumed, zlib_written, code) = inflate_step(
+                        &mut self.state,
+                        &input[consumed..],
+                        &mut output[written..],
+                        flush,
+                    );
+                    return (consumed + zlib_consumed, written + zlib_written, code);
+                }
+            }
+        }
+    }
+
+    /// The value of the `adler` field of the `z_stream`, which is the CRC-32
+    /// of the output for the gzip format.
+    pub(super) fn adler(&self) -> u32 {
+        match self.phase {
+            Phase::Zlib => self.state.decompressor().adler32().unwrap_or(1),
+            _ => self.crc,
+        }
+    }
+}
+
+/// Result of [GzipInflate::step] when it needs more input to continue.
+fn out_of_input(consumed: usize, written: usize) -> (usize, usize, i32) {
+    if consumed == 0 && written == 0 {
+        (consumed, written, Z_BUF_ERROR)
+    } else {
+        (consumed, written, Z_OK)
+    }
+}
+
+/// Parse a gzip header. Returns its length, [None] if `header` doesn't contain
+/// all of it yet, or an error if it's invalid. The header CRC isn't checked.
+fn header_len(header: &[u8]) -> Result<Option<usize>, ()> {
+    let expected_start = [MAGIC[0], MAGIC[1], CM_DEFLATE];
+    if header.iter().zip(expected_start).any(|(&a, b)| a != b) {
+        return Err(());
+    }
+    // Magic number, compression method, flags, time, extra flags and OS.
+    let mut len = 10;
+    if header.len() < len {
+        return Ok(None);
+    }
+    let flags = header[3];
+    if flags & FRESERVED != 0 {
+        return Err(());
+    }
+    if flags & FEXTRA != 0 {
+        let Some(&[lo, hi]) = header.get(len..len + 2) else {
+            return Ok(None);
+        };
+        len += 2 + usize::from(u16::from_le_bytes([lo, hi]));
+    }
+    for flag in [FNAME, FCOMMENT] {
+        if flags & flag != 0 {
+            // Null-terminated string
+            let Some(terminator) = header
+                .get(len..)
+                .and_then(|rest| rest.iter().position(|&byte| byte == 0))
+            else {
+                return Ok(None);
+            };
+            len += terminator + 1;
+        }
+    }
+    if flags & FHCRC != 0 {
+        len += 2;
+    }
+    Ok((header.len() >= len).then_some(len))
+}
+
+fn crc32(crc: u32, bytes: &[u8]) -> u32 {
+    let mut crc = !crc;
+    for &byte in bytes {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xedb88320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(0, b"123456789"), 0xcbf43926);
+        assert_eq!(crc32(crc32(0, b"1234"), b"56789"), 0xcbf43926);
+    }
+
+    #[test]
+    fn header_lengths() {
+        let fixed = [0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 0xff];
+        assert_eq!(header_len(&fixed[..5]), Ok(None));
+        assert_eq!(header_len(&fixed), Ok(Some(10)));
+        assert_eq!(header_len(&[0x1f, 0x8c]), Err(()));
+
+        let mut with_name = fixed.to_vec();
+        with_name[3] = FNAME;
+        with_name.extend_from_slice(b"a.txt");
+        assert_eq!(header_len(&with_name), Ok(None));
+        with_name.push(0);
+        assert_eq!(header_len(&with_name), Ok(Some(16)));
+
+        let mut with_extra = fixed.to_vec();
+        with_extra[3] = FEXTRA | FHCRC;
+        with_extra.extend_from_slice(&[3, 0, 1, 2, 3, 0xab]);
+        assert_eq!(header_len(&with_extra), Ok(None));
+        with_extra.push(0xcd);
+        assert_eq!(header_len(&with_extra), Ok(Some(17)));
+    }
+}
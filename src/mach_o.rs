@@ -23,10 +23,10 @@ use crate::fs::{Fs, GuestPath};
 use crate::mem::{Mem, Ptr};
 use mach_object::{
     cpu_subtype_t, vm_prot_t, DyLib, LoadCommand, MachCommand, OFile, Symbol, SymbolIter,
-    ThreadState, N_ARM_THUMB_DEF, S_LAZY_SYMBOL_POINTERS, S_MOD_INIT_FUNC_POINTERS,
+    ThreadState, N_ARM_THUMB_DEF, N_WEAK_REF, S_LAZY_SYMBOL_POINTERS, S_MOD_INIT_FUNC_POINTERS,
     S_NON_LAZY_SYMBOL_POINTERS, S_SYMBOL_STUBS,
 };
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::io::{Cursor, Seek, SeekFrom};
 
 const VM_PROT_READ: vm_prot_t = 1;
@@ -49,6 +49,10 @@ pub struct MachO {
     /// List of addresses and names of external relocations for the dynamic
     /// linker to resolve.
     pub external_relocations: Vec<(u32, String)>,
+    /// Symbols imported by the binary that are weak references
+    /// (`__attribute__((weak_import))`), which should be resolved to NULL if
+    /// they are unavailable.
+    pub weak_imports: HashSet<String>,
     /// Address/program counter value for the entry point.
     pub entry_point_pc: Option<u32>,
 }
@@ -308,6 +312,7 @@ impl MachO {
         let mut exported_symbols = HashMap::new();
         let mut indirect_undef_symbols: Vec<Option<String>> = Vec::new();
         let mut external_relocations: Vec<(u32, String)> = Vec::new();
+        let mut weak_imports = HashSet::new();
         let mut entry_point_pc: Option<u32> = None;
 
         for MachCommand(command, _size) in commands {
@@ -397,6 +402,17 @@ impl MachO {
                             if let Symbol::Debug { .. } = symbol {
                                 continue;
                             }
+                            if let Symbol::Undefined {
+                                name: Some(name),
+                                desc,
+                                ..
+                            } = symbol
+                            {
+                                if desc & N_WEAK_REF != 0 {
+                                    weak_imports.insert(name.to_string());
+                                }
+                                continue;
+                            }
                             if let Symbol::Defined {
                                 name: Some(name),
                                 external: true,
@@ -612,6 +628,7 @@ impl MachO {
             sections,
             exported_symbols,
             external_relocations,
+            weak_imports,
             entry_point_pc,
         })
     }
@@ -15,8 +15,10 @@
 //! * [Memory Usage Performance Guidelines](https://developer.apple.com/library/archive/documentation/Performance/Conceptual/ManagingMemory/ManagingMemory.html)
 
 use crate::libc::wchar::wchar_t;
+use std::collections::BTreeSet;
 
 mod allocator;
+pub use allocator::HeapStats;
 
 /// Equivalent of `usize` for guest memory.
 pub type GuestUSize = u32;
@@ -205,6 +207,37 @@ impl<T: SafeRead> SafeWrite for T {}
 
 type Bytes = [u8; 1 << 32];
 
+/// Copy of the content of guest memory and the allocator state, used by
+/// [crate::snapshot].
+#[derive(Debug, PartialEq)]
+pub struct MemSnapshot {
+    pub null_segment_size: VAddr,
+    /// Base address and content of each used (allocated or reserved) chunk,
+    /// in ascending address order. The null segment is not included.
+    pub chunks: Vec<(VAddr, Vec<u8>)>,
+}
+impl MemSnapshot {
+    /// Returns [true] if `addr` was in a used chunk when the snapshot was made,
+    /// i.e. if it will still be valid after the snapshot is restored.
+    pub fn is_used(&self, addr: VAddr) -> bool {
+        let idx = self.chunks.partition_point(|&(base, _)| base <= addr);
+        idx > 0 && {
+            let (base, ref content) = self.chunks[idx - 1];
+            u64::from(addr) < u64::from(base) + content.len() as u64
+        }
+    }
+}
+
+/// Kind of guest memory access caught by a watchpoint, see
+/// [Mem::add_watchpoint].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum WatchpointKind {
+    Write,
+    Read,
+    /// Either a read or a write.
+    Access,
+}
+
 /// The type that owns the guest memory and provides accessors for it.
 pub struct Mem {
     /// This array is 4GiB in size so that it can cover the entire 32-bit
@@ -238,6 +271,20 @@ pub struct Mem {
     null_segment_size: VAddr,
 
     allocator: allocator::Allocator,
+
+    /// Address ranges being watched for the debugger. These are only checked
+    /// for accesses by the CPU, and only for pages the CPU doesn't access
+    /// directly, see [crate::cpu::Cpu::set_page_direct_access].
+    watchpoints: Vec<(WatchpointKind, VAddr, GuestUSize)>,
+    /// The most recent access that hit a watchpoint, if it hasn't been
+    /// reported yet.
+    watchpoint_hit: Option<(WatchpointKind, VAddr)>,
+
+    /// Whether `--debug-alloc` is in use, see [Self::set_debug_alloc].
+    debug_alloc: bool,
+    /// With `--debug-alloc`, the base addresses of freed allocations whose
+    /// memory hasn't been allocated again, for detecting double frees.
+    freed_bases: BTreeSet<VAddr>,
 }
 
 impl Drop for Mem {
@@ -275,6 +322,10 @@ impl Mem {
             bytes,
             null_segment_size: 0,
             allocator,
+            watchpoints: Vec::new(),
+            watchpoint_hit: None,
+            debug_alloc: false,
+            freed_bases: BTreeSet::new(),
         }
     }
 
@@ -288,7 +339,14 @@ impl Mem {
             bytes: _,
             null_segment_size: _,
             ref mut allocator,
+            ref mut watchpoints,
+            ref mut watchpoint_hit,
+            debug_alloc: _,
+            ref mut freed_bases,
         } = mem;
+        watchpoints.clear();
+        *watchpoint_hit = None;
+        freed_bases.clear();
         let used_chunks = allocator.reset_and_drain_used_chunks();
         for allocator::Chunk { base, size } in used_chunks {
             mem.bytes_mut()[base as usize..][..size.get() as usize].fill(0);
@@ -501,8 +559,19 @@ impl Mem {
         unsafe { ptr.write_unaligned(value) }
     }
 
-    /// C-style `memmove`.
+    /// C-style `memmove`. Overlapping source and destination ranges are
+    /// handled correctly.
+    ///
+    /// Unlike [Self::bytes_at], this only panics at null-page access if `size`
+    /// is not 0.
     pub fn memmove(&mut self, dest: MutVoidPtr, src: ConstVoidPtr, size: GuestUSize) {
+        if size != 0 {
+            for addr in [dest.to_bits(), src.to_bits()] {
+                if addr < self.null_segment_size {
+                    Self::null_check_fail(addr, size)
+                }
+            }
+        }
         let src = src.to_bits() as usize;
         let dest = dest.to_bits() as usize;
         let size = size as usize;
@@ -510,10 +579,36 @@ impl Mem {
             .copy_within(src..src.checked_add(size).unwrap(), dest)
     }
 
+    /// Byte that freed memory is filled with when `--debug-alloc` is in use,
+    /// so that use-after-free is easier to spot.
+    pub const FREED_MEMORY_POISON: u8 = 0xdd;
+
+    /// Enable or disable `--debug-alloc` checking. When enabled, freed memory
+    /// is poisoned with [Self::FREED_MEMORY_POISON] rather than zeroed, and
+    /// [Self::validate_free] can detect double frees and invalid frees. This is
+    /// off by default because it's slower.
+    pub fn set_debug_alloc(&mut self, enabled: bool) {
+        self.debug_alloc = enabled;
+    }
+
     /// Allocate `size` bytes.
     pub fn alloc(&mut self, size: GuestUSize) -> MutVoidPtr {
         let ptr = Ptr::from_bits(self.allocator.alloc(size));
         log_dbg!("Allocated {:?} ({:#x} bytes)", ptr, size);
+        if self.debug_alloc {
+            // Freed memory isn't zeroed in this mode, so the allocation must
+            // be. It also can't be double-freed any more.
+            let size = self.allocator.find_allocated_size(ptr.to_bits());
+            self.bytes_at_mut(ptr.cast(), size).fill(0);
+            let reused: Vec<VAddr> = self
+                .freed_bases
+                .range(ptr.to_bits()..=(ptr.to_bits() + (size - 1)))
+                .copied()
+                .collect();
+            for base in reused {
+                self.freed_bases.remove(&base);
+            }
+        }
         ptr
     }
 
@@ -533,10 +628,36 @@ impl Mem {
         new_ptr
     }
 
+    /// Get the allocator's counters, for `--heap-stats`.
+    pub fn heap_stats(&self) -> HeapStats {
+        self.allocator.stats()
+    }
+
+    /// With `--debug-alloc`, check that `ptr` can be freed, i.e. it is the
+    /// base of a live allocation. Returns a description of the problem if not.
+    /// Without `--debug-alloc`, this always succeeds.
+    pub fn validate_free(&self, ptr: MutVoidPtr) -> Result<(), &'static str> {
+        if !self.debug_alloc || self.allocator.is_used(ptr.to_bits()) {
+            Ok(())
+        } else if self.freed_bases.contains(&ptr.to_bits()) {
+            Err("double free")
+        } else {
+            Err("not a pointer to a live allocation")
+        }
+    }
+
     /// Free an allocation made with one of the `alloc` methods on this type.
     pub fn free(&mut self, ptr: MutVoidPtr) {
         let size = self.allocator.free(ptr.to_bits());
-        self.bytes_at_mut(ptr.cast(), size).fill(0);
+        if self.debug_alloc {
+            self.bytes_at_mut(ptr.cast(), size)
+                .fill(Self::FREED_MEMORY_POISON);
+            if size != 0 {
+                self.freed_bases.insert(ptr.to_bits());
+            }
+        } else {
+            self.bytes_at_mut(ptr.cast(), size).fill(0);
+        }
         log_dbg!("Freed {:?} ({:#x} bytes)", ptr, size);
     }
 
@@ -596,4 +717,159 @@ impl Mem {
     pub fn reserve(&mut self, base: VAddr, size: GuestUSize) {
         self.allocator.reserve(allocator::Chunk::new(base, size));
     }
+
+    /// Copy the content of all used memory and the allocator state.
+    pub fn snapshot(&self) -> MemSnapshot {
+        let chunks = self
+            .allocator
+            .used_chunks()
+            .filter(|chunk| self.null_segment_size == 0 || chunk.base != 0)
+            .map(|allocator::Chunk { base, size }| {
+                let content = self.bytes()[base as usize..][..size.get() as usize].to_vec();
+                (base, content)
+            })
+            .collect();
+        MemSnapshot {
+            null_segment_size: self.null_segment_size,
+            chunks,
+        }
+    }
+
+    /// Replace the content of memory and the allocator state with a snapshot
+    /// made by [Self::snapshot]. Memory not in the snapshot is zeroed. The null
+    /// segment must have the same size as when the snapshot was made.
+    pub fn restore_snapshot(&mut self, snapshot: &MemSnapshot) {
+        assert_eq!(self.null_segment_size, snapshot.null_segment_size);
+
+        let old_chunks: Vec<_> = self.allocator.used_chunks().collect();
+        for allocator::Chunk { base, size } in old_chunks {
+            if self.null_segment_size == 0 || base != 0 {
+                self.bytes_mut()[base as usize..][..size.get() as usize].fill(0);
+            }
+        }
+
+        let mut new_chunks = Vec::with_capacity(snapshot.chunks.len() + 1);
+        if snapshot.null_segment_size != 0 {
+            new_chunks.push(allocator::Chunk::new(0, snapshot.null_segment_size));
+        }
+        for (base, content) in &snapshot.chunks {
+            new_chunks.push(allocator::Chunk::new(
+                *base,
+                content.len().try_into().unwrap(),
+            ));
+            self.bytes_mut()[*base as usize..][..content.len()].copy_from_slice(content);
+        }
+        self.allocator = allocator::Allocator::from_used_chunks(&new_chunks);
+    }
+
+    /// Start watching a range of addresses for the debugger. The CPU must have
+    /// direct access disabled for the pages in this range for accesses to be
+    /// caught, see [crate::cpu::Cpu::set_page_direct_access].
+    pub fn add_watchpoint(&mut self, kind: WatchpointKind, addr: VAddr, size: GuestUSize) {
+        assert!(size > 0);
+        self.watchpoints.push((kind, addr, size));
+    }
+
+    /// Stop watching a range of addresses. Returns [false] if there was no
+    /// such watchpoint.
+    pub fn remove_watchpoint(
+        &mut self,
+        kind: WatchpointKind,
+        addr: VAddr,
+        size: GuestUSize,
+    ) -> bool {
+        let Some(idx) = self
+            .watchpoints
+            .iter()
+            .position(|&watchpoint| watchpoint == (kind, addr, size))
+        else {
+            return false;
+        };
+        self.watchpoints.remove(idx);
+        true
+    }
+
+    /// Check if any watchpoint overlaps the page starting at `page_addr`.
+    pub fn page_has_watchpoint(&self, page_addr: VAddr) -> bool {
+        let page_start = u64::from(page_addr & !0xfff);
+        let page_end = page_start + 0x1000;
+        self.watchpoints.iter().any(|&(_, addr, size)| {
+            u64::from(addr) < page_end && u64::from(addr) + u64::from(size) > page_start
+        })
+    }
+
+    /// Check a CPU memory access against the watchpoints. If one is hit, this
+    /// returns [true] and the hit is remembered until
+    /// [Self::take_watchpoint_hit] is called.
+    pub fn check_watchpoints(&mut self, addr: VAddr, size: GuestUSize, is_write: bool) -> bool {
+        let access_start = u64::from(addr);
+        let access_end = access_start + u64::from(size);
+        let hit = self
+            .watchpoints
+            .iter()
+            .find(|&&(kind, watch_addr, watch_size)| {
+                let kind_matches = match kind {
+                    WatchpointKind::Write => is_write,
+                    WatchpointKind::Read => !is_write,
+                    WatchpointKind::Access => true,
+                };
+                kind_matches
+                    && access_start < u64::from(watch_addr) + u64::from(watch_size)
+                    && access_end > u64::from(watch_addr)
+            });
+        if let Some(&(kind, watch_addr, _)) = hit {
+            // GDB wants the address of the watched data, not of the access.
+            self.watchpoint_hit = Some((kind, watch_addr.max(addr)));
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Get the watchpoint hit recorded by [Self::check_watchpoints], if any,
+    /// and clear it.
+    pub fn take_watchpoint_hit(&mut self) -> Option<(WatchpointKind, VAddr)> {
+        self.watchpoint_hit.take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn debug_alloc() {
+        let mut mem = Mem::new();
+        mem.set_debug_alloc(true);
+
+        let ptr = mem.alloc(32);
+        assert_eq!(mem.validate_free(ptr), Ok(()));
+        mem.write(ptr.cast::<u8>(), 1);
+        mem.free(ptr);
+        // Freed memory is poisoned
+        assert_eq!(mem.read(ptr.cast::<u8>()), Mem::FREED_MEMORY_POISON);
+        assert_eq!(mem.validate_free(ptr), Err("double free"));
+
+        // Not the base of an allocation
+        let ptr2 = mem.alloc(32);
+        assert_eq!(
+            mem.validate_free((ptr2.cast::<u8>() + 4).cast()),
+            Err("not a pointer to a live allocation")
+        );
+        assert_eq!(
+            mem.validate_free(Ptr::from_bits(0x1234)),
+            Err("not a pointer to a live allocation")
+        );
+
+        // Reused memory is zeroed and can be freed again
+        mem.free(ptr2);
+        let ptr3 = mem.alloc(32);
+        assert_eq!(mem.read(ptr3.cast::<u8>()), 0);
+        assert_eq!(mem.validate_free(ptr3), Ok(()));
+        mem.free(ptr3);
+
+        // No checking by default
+        mem.set_debug_alloc(false);
+        assert_eq!(mem.validate_free(Ptr::from_bits(0x1234)), Ok(()));
+    }
 }
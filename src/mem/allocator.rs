@@ -134,6 +134,12 @@ mod collections {
         pub fn get_size_with_base(&self, base: VAddr) -> Option<NonZeroU32> {
             self.chunks.get(&base).copied()
         }
+        /// Iterate over chunks in ascending address order.
+        pub fn iter(&self) -> impl Iterator<Item = Chunk> + '_ {
+            self.chunks
+                .iter()
+                .map(|(&base, &size)| Chunk { base, size })
+        }
     }
 
     #[derive(Default, Debug)]
@@ -250,11 +256,26 @@ mod collections {
 }
 use collections::{ChunkMap, SizeBucketedChunkMap};
 
+/// Counters for `--heap-stats`. Sizes are after rounding up to a multiple of
+/// [MIN_CHUNK_SIZE]. Reserved chunks (e.g. stacks) aren't counted.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct HeapStats {
+    /// Total bytes ever allocated.
+    pub total_allocated: u64,
+    /// Bytes currently allocated.
+    pub live_bytes: u64,
+    /// Number of allocations ever made.
+    pub allocation_count: u64,
+    /// Number of allocations not yet freed.
+    pub live_allocations: u64,
+}
+
 /// Tracks which memory is in use and makes allocations from it.
 #[derive(Debug)]
 pub struct Allocator {
     used_chunks: ChunkMap,
     unused_chunks: SizeBucketedChunkMap,
+    stats: HeapStats,
 }
 
 impl Allocator {
@@ -272,6 +293,7 @@ impl Allocator {
         Allocator {
             used_chunks,
             unused_chunks,
+            stats: Default::default(),
         }
     }
 
@@ -315,9 +337,20 @@ impl Allocator {
         };
         self.used_chunks.insert(alloc);
 
+        self.stats.total_allocated += u64::from(size);
+        self.stats.live_bytes += u64::from(size);
+        self.stats.allocation_count += 1;
+        self.stats.live_allocations += 1;
+
         alloc.base
     }
 
+    /// Returns [true] if `base` is the base of a used (allocated or reserved)
+    /// chunk.
+    pub fn is_used(&self, base: VAddr) -> bool {
+        self.used_chunks.get_size_with_base(base).is_some()
+    }
+
     /// This is used for realloc
     pub fn find_allocated_size(&mut self, base: VAddr) -> GuestUSize {
         let Some(size) = self.used_chunks.get_size_with_base(base) else {
@@ -334,6 +367,14 @@ impl Allocator {
             return 0;
         };
 
+        // Freeing a reserved chunk, or a chunk allocated before a snapshot was
+        // restored, would otherwise make these underflow.
+        self.stats.live_bytes = self
+            .stats
+            .live_bytes
+            .saturating_sub(freed.size.get().into());
+        self.stats.live_allocations = self.stats.live_allocations.saturating_sub(1);
+
         if let Some(adjacent) = self
             .unused_chunks
             .remove_with_base(freed.last_byte() + 1)
@@ -351,9 +392,101 @@ impl Allocator {
         freed.size.get()
     }
 
+    pub fn stats(&self) -> HeapStats {
+        self.stats
+    }
+
+    /// Iterate over the used (allocated or reserved) chunks in ascending
+    /// address order.
+    pub fn used_chunks(&self) -> impl Iterator<Item = Chunk> + '_ {
+        self.used_chunks.iter()
+    }
+
+    /// Recreate an allocator from a list of used chunks (as returned by
+    /// [Self::used_chunks]). All other memory is considered unused.
+    pub fn from_used_chunks(chunks: &[Chunk]) -> Allocator {
+        let mut used_chunks: ChunkMap = Default::default();
+        let mut unused_chunks: SizeBucketedChunkMap = Default::default();
+
+        let mut add_gap = |start: u64, end: u64| {
+            // Gaps too small to allocate from are simply lost.
+            if end - start >= MIN_CHUNK_SIZE as u64 {
+                unused_chunks.insert(Chunk::new(start as VAddr, (end - start) as GuestUSize));
+            }
+        };
+        let mut gap_start: u64 = 0;
+        for &chunk in chunks {
+            assert!(chunk.base as u64 >= gap_start);
+            add_gap(gap_start, chunk.base as u64);
+            gap_start = chunk.last_byte() as u64 + 1;
+            used_chunks.insert(chunk);
+        }
+        add_gap(gap_start, 1 << 32);
+
+        Allocator {
+            used_chunks,
+            unused_chunks,
+            stats: Default::default(),
+        }
+    }
+
     pub(super) fn reset_and_drain_used_chunks(&mut self) -> impl Iterator<Item = Chunk> {
         let chunks = std::mem::take(&mut self.used_chunks);
         *self = Allocator::new();
         chunks.drain()
     }
 }
+
+#[cfg(test)]
+mod stats_tests {
+    use super::*;
+
+    #[test]
+    fn test() {
+        let mut mem = Mem::new();
+        assert_eq!(mem.heap_stats(), HeapStats::default());
+
+        let a = mem.alloc(10); // rounded up to 16
+        let b = mem.alloc(32);
+        assert_eq!(
+            mem.heap_stats(),
+            HeapStats {
+                total_allocated: 48,
+                live_bytes: 48,
+                allocation_count: 2,
+                live_allocations: 2,
+            }
+        );
+
+        // Growing is an allocation and a free
+        let a = mem.realloc(a, 64);
+        assert_eq!(
+            mem.heap_stats(),
+            HeapStats {
+                total_allocated: 112,
+                live_bytes: 96,
+                allocation_count: 3,
+                live_allocations: 2,
+            }
+        );
+        // Shrinking does nothing
+        let a = mem.realloc(a, 16);
+        assert_eq!(mem.heap_stats().allocation_count, 3);
+
+        mem.free(a);
+        mem.free(b);
+        assert_eq!(
+            mem.heap_stats(),
+            HeapStats {
+                total_allocated: 112,
+                live_bytes: 0,
+                allocation_count: 3,
+                live_allocations: 0,
+            }
+        );
+
+        // Unknown allocations don't affect the counters
+        mem.free(b);
+        assert_eq!(mem.heap_stats().live_allocations, 0);
+    }
+}
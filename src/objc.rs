@@ -36,7 +36,8 @@ pub use messages::{
 };
 pub use methods::{HostIMP, IMP};
 pub use objects::{
-    id, impl_HostObject_with_superclass, nil, AnyHostObject, HostObject, TrivialHostObject,
+    id, impl_HostObject_with_superclass, nil, AnyHostObject, HostObject, ObjCSnapshot,
+    TrivialHostObject,
 };
 pub use selectors::{selector, SEL};
 
@@ -14,6 +14,7 @@ use crate::frameworks::{
 /// All the lists of classes that the runtime should search through.
 pub const CLASS_LISTS: &[super::ClassExports] = &[
     crate::app_picker::CLASSES, // Not a framework! Special internal classes.
+    core_animation::ca_display_link::CLASSES,
     core_animation::ca_eagl_layer::CLASSES,
     core_animation::ca_layer::CLASSES,
     core_graphics::cg_data_provider::CLASSES,
@@ -21,7 +22,8 @@ pub const CLASS_LISTS: &[super::ClassExports] = &[
     core_graphics::cg_color_space::CLASSES,
     core_graphics::cg_context::CLASSES,
     core_graphics::cg_image::CLASSES,
-    core_foundation::cf_run_loop_timer::CLASSES, // Special internal classes.
+    core_foundation::cf_run_loop_source::CLASSES, // Special internal classes.
+    core_foundation::cf_run_loop_timer::CLASSES,  // Special internal classes.
     game_kit::gk_local_player::CLASSES,
     foundation::ns_array::CLASSES,
     foundation::ns_autorelease_pool::CLASSES,
@@ -43,6 +45,7 @@ pub const CLASS_LISTS: &[super::ClassExports] = &[
     foundation::ns_notification_center::CLASSES,
     foundation::ns_null::CLASSES,
     foundation::ns_object::CLASSES,
+    foundation::ns_operation::CLASSES,
     foundation::ns_process_info::CLASSES,
     foundation::ns_property_list_serialization::CLASSES,
     foundation::ns_run_loop::CLASSES,
@@ -54,6 +57,7 @@ pub const CLASS_LISTS: &[super::ClassExports] = &[
     foundation::ns_url::CLASSES,
     foundation::ns_url_connection::CLASSES,
     foundation::ns_url_request::CLASSES,
+    foundation::ns_url_response::CLASSES,
     foundation::ns_user_defaults::CLASSES,
     foundation::ns_value::CLASSES,
     foundation::ns_xml_parser::CLASSES,
@@ -75,6 +79,7 @@ pub const CLASS_LISTS: &[super::ClassExports] = &[
     uikit::ui_image::CLASSES,
     uikit::ui_image_picker_controller::CLASSES,
     uikit::ui_nib::CLASSES,
+    uikit::ui_pasteboard::CLASSES,
     uikit::ui_responder::CLASSES,
     uikit::ui_screen::CLASSES,
     uikit::ui_touch::CLASSES,
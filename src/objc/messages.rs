@@ -29,6 +29,29 @@ use std::any::TypeId;
 /// Similarly, the return value of `objc_msgSend` is whatever value is returned
 /// by the method implementation. We are relying on CallFromGuest not
 /// overwriting it.
+/// With `--log-unimplemented`, record an unimplemented method and behave as
+/// if the message was sent to nil. See [crate::unimplemented].
+fn log_unimplemented_method(env: &mut Environment, class: &str, is_metaclass: bool, sel: SEL) {
+    let method = format!(
+        "{}[{} {}]",
+        if is_metaclass { "+" } else { "-" },
+        class,
+        sel.as_str(&env.mem)
+    );
+    if env
+        .unimplemented_log
+        .as_mut()
+        .unwrap()
+        .record(method.clone())
+    {
+        log!(
+            "Warning: call to unimplemented method {}, returning nil",
+            method
+        );
+    }
+    env.cpu.regs_mut()[0..2].fill(0);
+}
+
 #[allow(non_snake_case)]
 fn objc_msgSend_inner(env: &mut Environment, receiver: id, selector: SEL, super2: Option<Class>) {
     let message_type_info = env.objc.message_type_info.take();
@@ -57,6 +80,12 @@ fn objc_msgSend_inner(env: &mut Environment, receiver: id, selector: SEL, super2
                 ..
             } = class_host_object.as_any().downcast_ref().unwrap();
 
+            if env.unimplemented_log.is_some() {
+                let name = name.clone();
+                log_unimplemented_method(env, &name, is_metaclass, selector);
+                return;
+            }
+
             panic!(
                 "{} {:?} ({}class \"{}\", {:?}){} does not respond to selector \"{}\"!",
                 if is_metaclass { "Class" } else { "Object" },
@@ -127,6 +156,12 @@ Type mismatch when sending message {} to {:?}!
             is_metaclass,
         }) = host_object.as_any().downcast_ref()
         {
+            if env.unimplemented_log.is_some() {
+                let name = name.clone();
+                log_unimplemented_method(env, &name, is_metaclass, selector);
+                return;
+            }
+
             panic!(
                 "Class \"{}\" ({:?}) is unimplemented. Call to {} method \"{}\".",
                 name,
@@ -21,7 +21,8 @@
 //! See also: [crate::frameworks::foundation::ns_object].
 
 use super::{Class, ClassHostObject};
-use crate::mem::{guest_size_of, GuestUSize, Mem, MutPtr, Ptr, SafeRead};
+use crate::environment::MutexId;
+use crate::mem::{guest_size_of, GuestUSize, Mem, MemSnapshot, MutPtr, Ptr, SafeRead};
 use std::any::Any;
 use std::num::NonZeroU32;
 
@@ -140,6 +141,18 @@ impl<T: HostObject> AnyHostObject for T {
     }
 }
 
+/// Copy of the parts of the Objective-C runtime state that can be saved in a
+/// [crate::snapshot]. Host objects themselves are not included.
+#[derive(Debug, PartialEq)]
+pub struct ObjCSnapshot {
+    /// Address, `isa` and refcount (0 for static objects) of each object, in
+    /// ascending address order.
+    pub objects: Vec<(u32, u32, u32)>,
+    /// Objects used with `@synchronized` and their mutexes, in ascending
+    /// address order.
+    pub sync_mutexes: Vec<(u32, MutexId)>,
+}
+
 /// Empty host object used by `[NSObject alloc]`.
 pub struct TrivialHostObject;
 impl HostObject for TrivialHostObject {}
@@ -232,6 +245,82 @@ impl super::ObjC {
         );
     }
 
+    /// Get the number of objects (including classes) that currently exist.
+    pub fn object_count(&self) -> usize {
+        self.objects.len()
+    }
+
+    /// Returns [true] if any object has a host object of type `T`.
+    pub fn has_host_objects_of_type<T: AnyHostObject + 'static>(&self) -> bool {
+        self.objects
+            .values()
+            .any(|entry| entry.host_object.as_any().is::<T>())
+    }
+
+    /// Copy the refcounts etc of all objects, see [ObjCSnapshot].
+    pub fn snapshot(&self, mem: &Mem) -> ObjCSnapshot {
+        let mut objects: Vec<_> = self
+            .objects
+            .iter()
+            .map(|(&object, entry)| {
+                let isa = Self::read_isa(object, mem).to_bits();
+                let refcount = entry.refcount.map_or(0, NonZeroU32::get);
+                (object.to_bits(), isa, refcount)
+            })
+            .collect();
+        objects.sort_unstable();
+        let mut sync_mutexes: Vec<_> = self
+            .sync_mutexes
+            .iter()
+            .map(|(&object, &mutex_id)| (object.to_bits(), mutex_id))
+            .collect();
+        sync_mutexes.sort_unstable();
+        ObjCSnapshot {
+            objects,
+            sync_mutexes,
+        }
+    }
+
+    /// Check that exactly the same objects exist now as when `snapshot` was
+    /// made. Host objects aren't part of the snapshot, so it can't be restored
+    /// otherwise.
+    pub fn check_snapshot(&self, snapshot: &ObjCSnapshot, mem: &Mem) -> Result<(), String> {
+        let changed = snapshot
+            .objects
+            .iter()
+            .filter(|&&(object, isa, _)| {
+                let object = id::from_bits(object);
+                !self.objects.contains_key(&object) || Self::read_isa(object, mem).to_bits() != isa
+            })
+            .count();
+        let created = self.objects.len() - (snapshot.objects.len() - changed);
+        if changed != 0 || created != 0 {
+            return Err(format!(
+                "{} Objective-C objects have been created and {} destroyed since the snapshot was \
+                 made",
+                created, changed
+            ));
+        }
+        Ok(())
+    }
+
+    /// Restore the refcounts etc from a snapshot that has been checked with
+    /// [Self::check_snapshot]. Selectors registered since the snapshot was
+    /// made are forgotten, because their strings are freed when memory is
+    /// restored from `mem_snapshot`.
+    pub fn restore_snapshot(&mut self, snapshot: &ObjCSnapshot, mem_snapshot: &MemSnapshot) {
+        for &(object, _, refcount) in &snapshot.objects {
+            let entry = self.objects.get_mut(&id::from_bits(object)).unwrap();
+            entry.refcount = NonZeroU32::new(refcount);
+        }
+        self.sync_mutexes = snapshot
+            .sync_mutexes
+            .iter()
+            .map(|&(object, mutex_id)| (id::from_bits(object), mutex_id))
+            .collect();
+        self.forget_selectors_not_in(mem_snapshot);
+    }
+
     /// Get a reference to a host object, if the object exists.
     pub fn get_host_object(&self, object: id) -> Option<&dyn AnyHostObject> {
         self.objects.get(&object).map(|entry| &*entry.host_object)
@@ -17,7 +17,7 @@
 use super::ObjC;
 use crate::abi::{GuestArg, GuestRet};
 use crate::mach_o::MachO;
-use crate::mem::{ConstPtr, Mem, MutPtr, Ptr};
+use crate::mem::{ConstPtr, Mem, MemSnapshot, MutPtr, Ptr};
 use crate::Environment;
 
 /// Create a string literal for a selector from Objective-C message syntax
@@ -86,6 +86,13 @@ impl ObjC {
         sel
     }
 
+    /// Forget selectors whose strings won't exist once `mem_snapshot` is
+    /// restored, see [ObjC::restore_snapshot].
+    pub(super) fn forget_selectors_not_in(&mut self, mem_snapshot: &MemSnapshot) {
+        self.selectors
+            .retain(|_, sel| mem_snapshot.is_used(sel.0.to_bits()));
+    }
+
     /// Register and deduplicate all the selectors of host classes.
     ///
     /// To avoid wasting guest memory, call this after calling
@@ -10,12 +10,13 @@ use crate::window::DeviceOrientation;
 use std::collections::HashMap;
 use std::io::{BufRead, BufReader, Read};
 use std::net::{SocketAddr, ToSocketAddrs};
-use std::num::NonZeroU32;
+use std::time::Duration;
 
 pub const OPTIONS_HELP: &str =
     include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/OPTIONS_HELP.txt"));
 
-/// Game controller button for `--button-to-touch=` option.
+/// Game controller button for `--button-to-touch=` and `--button-to-tilt=`
+/// options.
 #[derive(Copy, Clone, Hash, PartialEq, Eq, Debug)]
 pub enum Button {
     DPadLeft,
@@ -29,26 +30,255 @@ pub enum Button {
     Y,
     LeftShoulder,
 }
+impl Button {
+    fn from_name(name: &str) -> Option<Button> {
+        match name {
+            "DPadLeft" => Some(Button::DPadLeft),
+            "DPadUp" => Some(Button::DPadUp),
+            "DPadRight" => Some(Button::DPadRight),
+            "DPadDown" => Some(Button::DPadDown),
+            "Start" => Some(Button::Start),
+            "A" => Some(Button::A),
+            "B" => Some(Button::B),
+            "X" => Some(Button::X),
+            "Y" => Some(Button::Y),
+            "LeftShoulder" => Some(Button::LeftShoulder),
+            _ => None,
+        }
+    }
+}
+
+/// What to do about OpenGL ES errors, see `--gl-debug`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum GLDebug {
+    Off,
+    /// Log each error along with the function that raised it.
+    Log,
+    /// Like [GLDebug::Log], but then panic.
+    Panic,
+}
+
+/// Scaling factor for the app's framebuffer, see `--scale-hack=`. This is not
+/// necessarily an integer, so values are rounded to the nearest pixel when
+/// scaling.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct ScaleHack(f32);
+impl ScaleHack {
+    pub fn new(factor: f32) -> Result<ScaleHack, String> {
+        // Scaling down isn't supported: with a factor below 1, distinct sizes
+        // could be rounded to the same size and wouldn't survive scaling back.
+        if factor.is_finite() && factor >= 1.0 {
+            Ok(ScaleHack(factor))
+        } else {
+            Err("Scale hack factor must be at least 1".to_string())
+        }
+    }
+
+    pub fn get(self) -> f32 {
+        self.0
+    }
+
+    /// Scale a size or co-ordinate in the app's pixels to host pixels.
+    pub fn scale(self, value: i32) -> i32 {
+        (f64::from(value) * f64::from(self.0)).round() as i32
+    }
+
+    /// Inverse of [Self::scale]. Because the factor is at least 1, the rounding
+    /// error from scaling up is always less than half a pixel once scaled back
+    /// down, so `unscale(scale(x)) == x`.
+    pub fn unscale(self, value: i32) -> i32 {
+        (f64::from(value) / f64::from(self.0)).round() as i32
+    }
+
+    /// Scale a rectangle. The edges are scaled rather than the size, so that
+    /// adjacent rectangles stay adjacent despite rounding.
+    pub fn scale_rect(self, x: i32, y: i32, width: i32, height: i32) -> (i32, i32, i32, i32) {
+        let (x1, y1) = (self.scale(x), self.scale(y));
+        let (x2, y2) = (self.scale(x + width), self.scale(y + height));
+        (x1, y1, x2 - x1, y2 - y1)
+    }
+}
+
+/// Mapping of game controller buttons and keyboard keys to simulated touches
+/// and accelerometer tilt, built from the `--button-to-touch=`,
+/// `--key-to-touch=`, `--button-to-tilt=` and `--key-to-tilt=` options.
+///
+/// Keys are identified by their SDL key name (e.g. `Space` or `Left`),
+/// lowercased so that they can be matched case-insensitively.
+#[derive(Default, Debug)]
+pub struct InputMap {
+    /// Touch co-ordinates for each button.
+    pub button_to_touch: HashMap<Button, (f32, f32)>,
+    /// Touch co-ordinates for each key.
+    pub key_to_touch: HashMap<String, (f32, f32)>,
+    /// Simulated left analog stick position (from -1 to 1 on each axis) for
+    /// each button.
+    pub button_to_tilt: HashMap<Button, (f32, f32)>,
+    /// Simulated left analog stick position (from -1 to 1 on each axis) for
+    /// each key.
+    pub key_to_tilt: HashMap<String, (f32, f32)>,
+}
+impl InputMap {
+    /// Parse the value of one of the mapping options, e.g. `A,470,310` for
+    /// `--button-to-touch=A,470,310`. Returns the button or key name and the
+    /// two co-ordinates.
+    fn parse_mapping<'a>(value: &'a str, option: &str) -> Result<(&'a str, f32, f32), String> {
+        let mut parts = value.split(',');
+        let (Some(name), Some(x), Some(y), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(format!("{} requires three values", option));
+        };
+        let x: f32 = x
+            .parse()
+            .ok()
+            .filter(|x: &f32| x.is_finite())
+            .ok_or_else(|| format!("Invalid X co-ordinate for {}", option))?;
+        let y: f32 = y
+            .parse()
+            .ok()
+            .filter(|y: &f32| y.is_finite())
+            .ok_or_else(|| format!("Invalid Y co-ordinate for {}", option))?;
+        Ok((name, x, y))
+    }
+
+    fn parse_button(name: &str, option: &str) -> Result<Button, String> {
+        Button::from_name(name).ok_or_else(|| format!("Invalid button for {}", option))
+    }
+
+    fn parse_key(name: &str, option: &str) -> Result<String, String> {
+        if name.is_empty() {
+            Err(format!("Missing key name for {}", option))
+        } else {
+            Ok(name.to_lowercase())
+        }
+    }
+
+    fn check_tilt(x: f32, y: f32, option: &str) -> Result<(), String> {
+        if (-1.0..=1.0).contains(&x) && (-1.0..=1.0).contains(&y) {
+            Ok(())
+        } else {
+            Err(format!(
+                "Tilt values for {} must be between -1 and 1",
+                option
+            ))
+        }
+    }
+
+    /// Parse one of the input mapping options. Returns `Ok(true)` if the
+    /// option was valid and has been applied, or `Ok(false)` if the option
+    /// was not recognized.
+    pub fn parse_argument(&mut self, arg: &str) -> Result<bool, String> {
+        if let Some(value) = arg.strip_prefix("--button-to-touch=") {
+            let option = "--button-to-touch=";
+            let (button, x, y) = Self::parse_mapping(value, option)?;
+            let button = Self::parse_button(button, option)?;
+            self.button_to_touch.insert(button, (x, y));
+        } else if let Some(value) = arg.strip_prefix("--key-to-touch=") {
+            let option = "--key-to-touch=";
+            let (key, x, y) = Self::parse_mapping(value, option)?;
+            let key = Self::parse_key(key, option)?;
+            self.key_to_touch.insert(key, (x, y));
+        } else if let Some(value) = arg.strip_prefix("--button-to-tilt=") {
+            let option = "--button-to-tilt=";
+            let (button, x, y) = Self::parse_mapping(value, option)?;
+            let button = Self::parse_button(button, option)?;
+            Self::check_tilt(x, y, option)?;
+            self.button_to_tilt.insert(button, (x, y));
+        } else if let Some(value) = arg.strip_prefix("--key-to-tilt=") {
+            let option = "--key-to-tilt=";
+            let (key, x, y) = Self::parse_mapping(value, option)?;
+            let key = Self::parse_key(key, option)?;
+            Self::check_tilt(x, y, option)?;
+            self.key_to_tilt.insert(key, (x, y));
+        } else {
+            return Ok(false);
+        }
+        Ok(true)
+    }
+}
 
 /// Struct containing all user-configurable options.
 pub struct Options {
     pub fullscreen: bool,
     pub initial_orientation: DeviceOrientation,
-    pub scale_hack: NonZeroU32,
+    pub scale_hack: ScaleHack,
     pub deadzone: f32,
     pub x_tilt_range: f32,
     pub y_tilt_range: f32,
     pub x_tilt_offset: f32,
     pub y_tilt_offset: f32,
-    pub button_to_touch: HashMap<Button, (f32, f32)>,
+    /// Low-pass filter factor for accelerometer values, between 0 (no
+    /// smoothing) and 1 (exclusive).
+    pub accelerometer_smoothing: f32,
+    /// Size of the dead zone for the accelerometer's X and Y axes, in units of
+    /// g-force, between 0 and 1 (exclusive).
+    pub accelerometer_deadzone: f32,
+    /// Factor applied to the accelerometer's X and Y axes, between
+    /// [Self::MIN_ACCELEROMETER_GAIN] and [Self::MAX_ACCELEROMETER_GAIN].
+    pub accelerometer_gain: f32,
+    pub accelerometer_invert_x: bool,
+    pub accelerometer_invert_y: bool,
+    pub input_map: InputMap,
+    pub haptics: bool,
+    /// Lowercase SDL2 key name, or [None] if screenshots are disabled.
+    pub screenshot_key: Option<String>,
+    /// Lowercase SDL2 key name, or [None] if the mouse can't simulate a second
+    /// finger.
+    pub second_finger_key: Option<String>,
+    /// Lowercase SDL2 key name, or [None] if fast-forwarding is disabled.
+    pub fast_forward_key: Option<String>,
+    /// How many times faster the app runs while fast-forwarding: the factor
+    /// applied to [Self::cpu_tick_slice] (see [Self::tick_slice]) and to the
+    /// speed of the app's clock.
+    pub fast_forward_speed: u32,
+    /// Mute audio while fast-forwarding, rather than playing it normally.
+    pub fast_forward_mute: bool,
+    /// Take a screenshot once this much time has passed since startup, and
+    /// then exit. See [crate::window::Window::take_screenshot_request].
+    pub screenshot_after: Option<Duration>,
     pub stabilize_virtual_cursor: Option<(f32, f32)>,
     pub gles1_implementation: Option<GLESImplementation>,
+    pub force_pvrtc_cpu_decode: bool,
+    /// See [crate::gles::gles1_on_gl2::GLES1OnGL2::deindex_arrays].
+    pub deindex_fixed_point_draws: bool,
+    /// Advertise a real device's `GL_EXTENSIONS` string rather than only the
+    /// extensions touchHLE implements.
+    pub legacy_gl_extensions: bool,
+    pub gl_debug: GLDebug,
     pub direct_memory_access: bool,
+    /// See [crate::fs::Fs::set_case_insensitive_fallback].
+    pub case_insensitive_fs: bool,
     pub gdb_listen_addrs: Option<Vec<SocketAddr>>,
+    /// Fixed seed for the libc random number generators, see
+    /// [crate::libc::stdlib::init_rngs].
+    pub rng_seed: Option<u32>,
+    /// See [crate::unimplemented].
+    pub log_unimplemented: bool,
+    /// See [crate::mem::HeapStats].
+    pub heap_stats: bool,
+    /// See [crate::mem::Mem::set_debug_alloc].
+    pub debug_alloc: bool,
+    /// Save and load snapshots with F9 and F10, see [crate::snapshot].
+    pub snapshot_keys: bool,
     pub preferred_languages: Option<Vec<String>>,
+    /// Reported as `hw.machine` by `sysctl()`.
+    pub hw_machine: String,
+    /// Environment variables to add to or override the app's initial
+    /// environment, in the order they were given.
+    pub env_vars: Vec<(String, String)>,
+    pub network_access: bool,
+    pub microphone_access: bool,
+    /// Run apps even if they require a newer OS version than is supported.
+    pub force_unsupported_os: bool,
+    /// Stop running the app while its window is minimized.
+    pub pause_in_background: bool,
     pub headless: bool,
     pub print_fps: bool,
     pub fps_limit: Option<f64>,
+    /// Number of CPU ticks a guest thread runs for before touchHLE polls for
+    /// events and switches threads, see [crate::Environment::run].
+    pub cpu_tick_slice: u64,
 }
 
 impl Default for Options {
@@ -56,26 +286,87 @@ impl Default for Options {
         Options {
             fullscreen: false,
             initial_orientation: DeviceOrientation::Portrait,
-            scale_hack: NonZeroU32::new(1).unwrap(),
+            scale_hack: ScaleHack(1.0),
             deadzone: 0.1,
             x_tilt_range: 60.0,
             y_tilt_range: 60.0,
             x_tilt_offset: 0.0,
             y_tilt_offset: 0.0,
-            button_to_touch: HashMap::new(),
+            accelerometer_smoothing: 0.0,
+            accelerometer_deadzone: 0.0,
+            accelerometer_gain: 1.0,
+            accelerometer_invert_x: false,
+            accelerometer_invert_y: false,
+            input_map: InputMap::default(),
+            haptics: true,
+            screenshot_key: Some("f11".to_string()),
+            second_finger_key: None,
+            fast_forward_key: None,
+            fast_forward_speed: 4,
+            fast_forward_mute: true,
+            screenshot_after: None,
             stabilize_virtual_cursor: None,
             gles1_implementation: None,
+            force_pvrtc_cpu_decode: false,
+            deindex_fixed_point_draws: false,
+            legacy_gl_extensions: false,
+            gl_debug: GLDebug::Off,
             direct_memory_access: true,
+            case_insensitive_fs: true,
             gdb_listen_addrs: None,
+            rng_seed: None,
+            log_unimplemented: false,
+            heap_stats: false,
+            debug_alloc: false,
+            snapshot_keys: false,
             preferred_languages: None,
+            hw_machine: "iPhone1,1".to_string(), // Original iPhone
+            env_vars: Vec::new(),
+            network_access: false,
+            microphone_access: false,
+            force_unsupported_os: false,
+            pause_in_background: false,
             headless: false,
             print_fps: false,
             fps_limit: Some(60.0), // Original iPhone is 60Hz and uses v-sync
+            cpu_tick_slice: 100_000,
         }
     }
 }
 
 impl Options {
+    /// Smallest allowed value for [Self::cpu_tick_slice]. Smaller values would
+    /// mean spending more time polling for events than running the app.
+    pub const MIN_CPU_TICK_SLICE: u64 = 1_000;
+
+    /// Range that [Self::accelerometer_gain] is clamped to. Beyond this, the
+    /// accelerometer would be either useless or unusable.
+    pub const MIN_ACCELEROMETER_GAIN: f32 = 0.1;
+    pub const MAX_ACCELEROMETER_GAIN: f32 = 10.0;
+    pub const MAX_FAST_FORWARD_SPEED: u32 = 32;
+
+    /// Number of CPU ticks a guest thread runs for at a time (see
+    /// [Self::cpu_tick_slice]), taking fast-forwarding into account.
+    ///
+    /// The app's clock is sped up by the same factor while fast-forwarding
+    /// (see [crate::environment::clock]), so that it gets enough CPU time to
+    /// keep up with its timers.
+    pub fn tick_slice(&self, fast_forwarding: bool) -> u64 {
+        if fast_forwarding {
+            self.cpu_tick_slice
+                .saturating_mul(self.fast_forward_speed.into())
+        } else {
+            self.cpu_tick_slice
+        }
+    }
+
+    /// Whether touchHLE should create a window. There's none in headless mode,
+    /// unless it's needed to render a screenshot for `--screenshot-after=`, in
+    /// which case the window is hidden and rendered offscreen.
+    pub fn creates_window(&self) -> bool {
+        !self.headless || self.screenshot_after.is_some()
+    }
+
     /// Parse the command-line argument syntax for an option. Returns `Ok(true)`
     /// if the option was valid and has been applied, or `Ok(false)` if the
     /// option was not recognized.
@@ -97,9 +388,10 @@ impl Options {
         } else if arg == "--landscape-right" {
             self.initial_orientation = DeviceOrientation::LandscapeRight;
         } else if let Some(value) = arg.strip_prefix("--scale-hack=") {
-            self.scale_hack = value
+            let factor = value
                 .parse()
                 .map_err(|_| "Invalid scale hack factor".to_string())?;
+            self.scale_hack = ScaleHack::new(factor)?;
         } else if let Some(value) = arg.strip_prefix("--deadzone=") {
             self.deadzone = parse_degrees(value, "deadzone")?;
         } else if let Some(value) = arg.strip_prefix("--x-tilt-range=") {
@@ -110,33 +402,73 @@ impl Options {
             self.x_tilt_offset = parse_degrees(value, "X tilt offset")?;
         } else if let Some(value) = arg.strip_prefix("--y-tilt-offset=") {
             self.y_tilt_offset = parse_degrees(value, "Y tilt offset")?;
-        } else if let Some(values) = arg.strip_prefix("--button-to-touch=") {
-            let (button, coords) = values
-                .split_once(',')
-                .ok_or_else(|| "--button-to-touch= requires three values".to_string())?;
-            let (x, y) = coords
-                .split_once(',')
-                .ok_or_else(|| "--button-to-touch= requires three values".to_string())?;
-            let button = match button {
-                "DPadLeft" => Ok(Button::DPadLeft),
-                "DPadUp" => Ok(Button::DPadUp),
-                "DPadRight" => Ok(Button::DPadRight),
-                "DPadDown" => Ok(Button::DPadDown),
-                "Start" => Ok(Button::Start),
-                "A" => Ok(Button::A),
-                "B" => Ok(Button::B),
-                "X" => Ok(Button::X),
-                "Y" => Ok(Button::Y),
-                "LeftShoulder" => Ok(Button::LeftShoulder),
-                _ => Err("Invalid button for --button-to-touch=".to_string()),
-            }?;
-            let x: f32 = x
+        } else if let Some(value) = arg.strip_prefix("--accelerometer-smoothing=") {
+            let value: f32 = value
                 .parse()
-                .map_err(|_| "Invalid X co-ordinate for --button-to-touch=".to_string())?;
-            let y: f32 = y
+                .map_err(|_| "Value for accelerometer smoothing is invalid".to_string())?;
+            if !(0.0..1.0).contains(&value) {
+                return Err("Value for accelerometer smoothing is out of range".to_string());
+            }
+            self.accelerometer_smoothing = value;
+        } else if let Some(value) = arg.strip_prefix("--accelerometer-deadzone=") {
+            let value: f32 = value
                 .parse()
-                .map_err(|_| "Invalid Y co-ordinate for --button-to-touch=".to_string())?;
-            self.button_to_touch.insert(button, (x, y));
+                .map_err(|_| "Value for accelerometer dead zone is invalid".to_string())?;
+            if !(0.0..1.0).contains(&value) {
+                return Err("Value for accelerometer dead zone is out of range".to_string());
+            }
+            self.accelerometer_deadzone = value;
+        } else if let Some(value) = arg.strip_prefix("--accelerometer-gain=") {
+            let value: f32 = value
+                .parse()
+                .ok()
+                .filter(|value: &f32| !value.is_nan() && *value > 0.0)
+                .ok_or_else(|| "Value for accelerometer gain is invalid".to_string())?;
+            self.accelerometer_gain =
+                value.clamp(Self::MIN_ACCELEROMETER_GAIN, Self::MAX_ACCELEROMETER_GAIN);
+        } else if arg == "--accelerometer-invert-x" {
+            self.accelerometer_invert_x = true;
+        } else if arg == "--accelerometer-invert-y" {
+            self.accelerometer_invert_y = true;
+        } else if self.input_map.parse_argument(arg)? {
+            // Handled by InputMap
+        } else if let Some(value) = arg.strip_prefix("--screenshot-key=") {
+            self.screenshot_key = match value {
+                "" => return Err("--screenshot-key= requires a key name".to_string()),
+                "off" => None,
+                _ => Some(value.to_lowercase()),
+            };
+        } else if let Some(value) = arg.strip_prefix("--second-finger-key=") {
+            self.second_finger_key = match value {
+                "" => return Err("--second-finger-key= requires a key name".to_string()),
+                "off" => None,
+                _ => Some(value.to_lowercase()),
+            };
+        } else if let Some(value) = arg.strip_prefix("--fast-forward-key=") {
+            self.fast_forward_key = match value {
+                "" => return Err("--fast-forward-key= requires a key name".to_string()),
+                "off" => None,
+                _ => Some(value.to_lowercase()),
+            };
+        } else if let Some(value) = arg.strip_prefix("--fast-forward-speed=") {
+            self.fast_forward_speed = value
+                .parse()
+                .ok()
+                .filter(|speed| (1..=Self::MAX_FAST_FORWARD_SPEED).contains(speed))
+                .ok_or_else(|| "Invalid value for --fast-forward-speed=".to_string())?;
+        } else if let Some(value) = arg.strip_prefix("--fast-forward-audio=") {
+            self.fast_forward_mute = match value {
+                "mute" => true,
+                "normal" => false,
+                _ => return Err("Value for --fast-forward-audio= is invalid".to_string()),
+            };
+        } else if let Some(value) = arg.strip_prefix("--screenshot-after=") {
+            let seconds: f64 = value
+                .parse()
+                .ok()
+                .filter(|&s: &f64| s.is_finite() && s >= 0.0)
+                .ok_or_else(|| "Invalid value for --screenshot-after=".to_string())?;
+            self.screenshot_after = Some(Duration::from_secs_f64(seconds));
         } else if let Some(value) = arg.strip_prefix("--stabilize-virtual-cursor=") {
             let (smoothing_strength, sticky_radius) = value
                 .split_once(',')
@@ -156,27 +488,73 @@ impl Options {
                     "Invalid sticky radius for --stabilize-virtual-cursor=".to_string()
                 })?;
             self.stabilize_virtual_cursor = Some((smoothing_strength, sticky_radius));
+        } else if arg == "--disable-haptics" {
+            self.haptics = false;
         } else if let Some(value) = arg.strip_prefix("--gles1=") {
             self.gles1_implementation = Some(
                 GLESImplementation::from_short_name(value)
                     .map_err(|_| "Unrecognized --gles1= value".to_string())?,
             );
+        } else if arg == "--force-pvrtc-cpu-decode" {
+            self.force_pvrtc_cpu_decode = true;
+        } else if arg == "--deindex-fixed-point-draws" {
+            self.deindex_fixed_point_draws = true;
+        } else if arg == "--legacy-gl-extensions" {
+            self.legacy_gl_extensions = true;
+        } else if arg == "--gl-debug" {
+            self.gl_debug = GLDebug::Log;
+        } else if arg == "--gl-debug=panic" {
+            self.gl_debug = GLDebug::Panic;
         } else if arg == "--disable-direct-memory-access" {
             self.direct_memory_access = false;
+        } else if arg == "--case-sensitive-fs" {
+            self.case_insensitive_fs = false;
         } else if let Some(address) = arg.strip_prefix("--gdb=") {
             let addrs = address
                 .to_socket_addrs()
                 .map_err(|e| format!("Could not resolve GDB server listen address: {}", e))?
                 .collect();
             self.gdb_listen_addrs = Some(addrs);
+        } else if let Some(value) = arg.strip_prefix("--rng-seed=") {
+            let seed = value
+                .parse()
+                .map_err(|_| "Invalid value for --rng-seed=".to_string())?;
+            self.rng_seed = Some(seed);
+        } else if arg == "--log-unimplemented" {
+            self.log_unimplemented = true;
+        } else if arg == "--heap-stats" {
+            self.heap_stats = true;
+        } else if arg == "--debug-alloc" {
+            self.debug_alloc = true;
+        } else if arg == "--snapshot-keys" {
+            self.snapshot_keys = true;
         } else if let Some(value) = arg.strip_prefix("--preferred-languages=") {
             self.preferred_languages = Some(value.split(',').map(ToOwned::to_owned).collect());
+        } else if let Some(value) = arg.strip_prefix("--hw-machine=") {
+            if value.is_empty() || !value.is_ascii() {
+                return Err("Invalid value for --hw-machine=".to_string());
+            }
+            self.hw_machine = value.to_string();
+        } else if let Some(value) = arg.strip_prefix("--env-var=") {
+            let Some((name, value)) = value.split_once('=').filter(|(name, _)| !name.is_empty())
+            else {
+                return Err("--env-var= requires a name and a value".to_string());
+            };
+            self.env_vars.push((name.to_string(), value.to_string()));
+        } else if arg == "--enable-networking" {
+            self.network_access = true;
+        } else if arg == "--enable-microphone" {
+            self.microphone_access = true;
+        } else if arg == "--force-unsupported-os" {
+            self.force_unsupported_os = true;
+        } else if arg == "--pause-in-background" {
+            self.pause_in_background = true;
         } else if arg == "--headless" {
             self.headless = true;
         } else if arg == "--print-fps" {
             self.print_fps = true;
         } else if let Some(value) = arg.strip_prefix("--fps-limit=") {
-            if value == "off" {
+            if value == "off" || value == "0" {
                 self.fps_limit = None;
             } else {
                 let limit: f64 = value
@@ -186,6 +564,11 @@ impl Options {
                     .ok_or_else(|| "Invalid value for --fps-limit=".to_string())?;
                 self.fps_limit = Some(limit);
             }
+        } else if let Some(value) = arg.strip_prefix("--cpu-tick-slice=") {
+            let ticks: u64 = value
+                .parse()
+                .map_err(|_| "Invalid value for --cpu-tick-slice=".to_string())?;
+            self.cpu_tick_slice = ticks.max(Self::MIN_CPU_TICK_SLICE);
         } else {
             return Ok(false);
         };
@@ -235,3 +618,338 @@ pub fn get_options_from_file<F: Read>(file: F, app_id: &str) -> Result<Option<St
     }
     Ok(None)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<InputMap, String> {
+        let mut options = Options::default();
+        for arg in args {
+            assert!(options.parse_argument(arg)?);
+        }
+        Ok(options.input_map)
+    }
+
+    #[test]
+    fn scale_hack() {
+        let mut options = Options::default();
+        assert_eq!(options.scale_hack.get(), 1.0);
+        assert!(options.parse_argument("--scale-hack=2").unwrap());
+        assert_eq!(options.scale_hack.get(), 2.0);
+        assert!(options.parse_argument("--scale-hack=1.5").unwrap());
+        assert_eq!(options.scale_hack.get(), 1.5);
+        assert!(options.parse_argument("--scale-hack=0.5").is_err());
+        assert!(options.parse_argument("--scale-hack=0").is_err());
+        assert!(options.parse_argument("--scale-hack=NaN").is_err());
+        assert!(options.parse_argument("--scale-hack=big").is_err());
+    }
+
+    #[test]
+    fn scale_hack_round_trip() {
+        // glRenderbufferStorageOES() followed by
+        // glGetRenderbufferParameterivOES() for a 320x480 renderbuffer.
+        let scale_hack = ScaleHack::new(1.5).unwrap();
+        let (width, height) = (scale_hack.scale(320), scale_hack.scale(480));
+        assert_eq!((width, height), (480, 720));
+        assert_eq!(
+            (scale_hack.unscale(width), scale_hack.unscale(height)),
+            (320, 480)
+        );
+        // A full-screen viewport covers the whole renderbuffer.
+        assert_eq!(scale_hack.scale_rect(0, 0, 320, 480), (0, 0, 480, 720));
+
+        // Odd values don't scale exactly, but still survive the round trip.
+        for factor in [1.0, 1.5, 2.5, 1.1, 3.0] {
+            let scale_hack = ScaleHack::new(factor).unwrap();
+            for value in -1000..=1000 {
+                assert_eq!(scale_hack.unscale(scale_hack.scale(value)), value);
+            }
+        }
+
+        // Adjacent rectangles don't gain gaps or overlaps from rounding.
+        let scale_hack = ScaleHack::new(2.5).unwrap();
+        let (x1, _, w1, _) = scale_hack.scale_rect(0, 0, 1, 1);
+        let (x2, _, w2, _) = scale_hack.scale_rect(1, 0, 2, 1);
+        assert_eq!(x1 + w1, x2);
+        assert_eq!(x2 + w2, scale_hack.scale(3));
+    }
+
+    #[test]
+    fn cpu_tick_slice() {
+        let mut options = Options::default();
+        assert_eq!(options.cpu_tick_slice, 100_000);
+        assert!(options.parse_argument("--cpu-tick-slice=250000").unwrap());
+        assert_eq!(options.cpu_tick_slice, 250_000);
+        // Values that are too small are clamped
+        assert!(options.parse_argument("--cpu-tick-slice=10").unwrap());
+        assert_eq!(options.cpu_tick_slice, Options::MIN_CPU_TICK_SLICE);
+        assert!(options.parse_argument("--cpu-tick-slice=-1").is_err());
+        assert!(options.parse_argument("--cpu-tick-slice=fast").is_err());
+    }
+
+    #[test]
+    fn accelerometer_smoothing() {
+        let mut options = Options::default();
+        assert_eq!(options.accelerometer_smoothing, 0.0);
+        assert!(options
+            .parse_argument("--accelerometer-smoothing=0.5")
+            .unwrap());
+        assert_eq!(options.accelerometer_smoothing, 0.5);
+        assert!(options
+            .parse_argument("--accelerometer-smoothing=1")
+            .is_err());
+        assert!(options
+            .parse_argument("--accelerometer-smoothing=-0.1")
+            .is_err());
+        assert!(options
+            .parse_argument("--accelerometer-smoothing=NaN")
+            .is_err());
+    }
+
+    #[test]
+    fn accelerometer_calibration() {
+        let mut options = Options::default();
+        assert_eq!(options.accelerometer_deadzone, 0.0);
+        assert_eq!(options.accelerometer_gain, 1.0);
+        assert!(!options.accelerometer_invert_x);
+        assert!(!options.accelerometer_invert_y);
+
+        let arg = "--accelerometer-deadzone=0.05";
+        assert!(options.parse_argument(arg).unwrap());
+        assert_eq!(options.accelerometer_deadzone, 0.05);
+        assert!(options
+            .parse_argument("--accelerometer-deadzone=1")
+            .is_err());
+        assert!(options
+            .parse_argument("--accelerometer-deadzone=-1")
+            .is_err());
+
+        assert!(options.parse_argument("--accelerometer-gain=0.5").unwrap());
+        assert_eq!(options.accelerometer_gain, 0.5);
+        // Out-of-range gains are clamped
+        assert!(options.parse_argument("--accelerometer-gain=100").unwrap());
+        assert_eq!(options.accelerometer_gain, Options::MAX_ACCELEROMETER_GAIN);
+        assert!(options.parse_argument("--accelerometer-gain=inf").unwrap());
+        assert_eq!(options.accelerometer_gain, Options::MAX_ACCELEROMETER_GAIN);
+        assert!(options.parse_argument("--accelerometer-gain=0.01").unwrap());
+        assert_eq!(options.accelerometer_gain, Options::MIN_ACCELEROMETER_GAIN);
+        assert!(options.parse_argument("--accelerometer-gain=0").is_err());
+        assert!(options.parse_argument("--accelerometer-gain=NaN").is_err());
+
+        assert!(options.parse_argument("--accelerometer-invert-x").unwrap());
+        assert!(options.accelerometer_invert_x);
+        assert!(!options.accelerometer_invert_y);
+        assert!(options.parse_argument("--accelerometer-invert-y").unwrap());
+        assert!(options.accelerometer_invert_y);
+    }
+
+    #[test]
+    fn hw_machine() {
+        let mut options = Options::default();
+        assert_eq!(options.hw_machine, "iPhone1,1");
+        assert!(options.parse_argument("--hw-machine=iPod2,1").unwrap());
+        assert_eq!(options.hw_machine, "iPod2,1");
+        assert!(options.parse_argument("--hw-machine=").is_err());
+    }
+
+    #[test]
+    fn rng_seed() {
+        let mut options = Options::default();
+        assert_eq!(options.rng_seed, None);
+        assert!(options.parse_argument("--rng-seed=1234").unwrap());
+        assert_eq!(options.rng_seed, Some(1234));
+        assert!(options.parse_argument("--rng-seed=-1").is_err());
+        assert!(options.parse_argument("--rng-seed=").is_err());
+    }
+
+    #[test]
+    fn log_unimplemented() {
+        let mut options = Options::default();
+        assert!(!options.log_unimplemented);
+        assert!(options.parse_argument("--log-unimplemented").unwrap());
+        assert!(options.log_unimplemented);
+    }
+
+    #[test]
+    fn heap_stats() {
+        let mut options = Options::default();
+        assert!(!options.heap_stats);
+        assert!(options.parse_argument("--heap-stats").unwrap());
+        assert!(options.heap_stats);
+    }
+
+    #[test]
+    fn debug_alloc() {
+        let mut options = Options::default();
+        assert!(!options.debug_alloc);
+        assert!(options.parse_argument("--debug-alloc").unwrap());
+        assert!(options.debug_alloc);
+    }
+
+    #[test]
+    fn snapshot_keys() {
+        let mut options = Options::default();
+        assert!(!options.snapshot_keys);
+        assert!(options.parse_argument("--snapshot-keys").unwrap());
+        assert!(options.snapshot_keys);
+    }
+
+    #[test]
+    fn screenshot_after() {
+        let mut options = Options::default();
+        assert_eq!(options.screenshot_after, None);
+        assert!(options.creates_window());
+        assert!(options.parse_argument("--headless").unwrap());
+        assert!(!options.creates_window());
+        assert!(options.parse_argument("--screenshot-after=1.5").unwrap());
+        assert_eq!(options.screenshot_after, Some(Duration::from_millis(1500)));
+        assert!(options.creates_window());
+        assert!(options.parse_argument("--screenshot-after=-1").is_err());
+        assert!(options.parse_argument("--screenshot-after=inf").is_err());
+    }
+
+    #[test]
+    fn deindex_fixed_point_draws() {
+        let mut options = Options::default();
+        assert!(!options.deindex_fixed_point_draws);
+        assert!(options
+            .parse_argument("--deindex-fixed-point-draws")
+            .unwrap());
+        assert!(options.deindex_fixed_point_draws);
+    }
+
+    #[test]
+    fn legacy_gl_extensions() {
+        let mut options = Options::default();
+        assert!(!options.legacy_gl_extensions);
+        assert!(options.parse_argument("--legacy-gl-extensions").unwrap());
+        assert!(options.legacy_gl_extensions);
+    }
+
+    #[test]
+    fn gl_debug() {
+        let mut options = Options::default();
+        assert_eq!(options.gl_debug, GLDebug::Off);
+        assert!(options.parse_argument("--gl-debug").unwrap());
+        assert_eq!(options.gl_debug, GLDebug::Log);
+        assert!(options.parse_argument("--gl-debug=panic").unwrap());
+        assert_eq!(options.gl_debug, GLDebug::Panic);
+        assert!(!options.parse_argument("--gl-debug=sometimes").unwrap());
+    }
+
+    #[test]
+    fn env_vars() {
+        let mut options = Options::default();
+        assert!(options.parse_argument("--env-var=TMPDIR=/tmp").unwrap());
+        assert!(options.parse_argument("--env-var=EMPTY=").unwrap());
+        assert!(options.parse_argument("--env-var=A=B=C").unwrap());
+        assert_eq!(
+            options.env_vars,
+            [
+                ("TMPDIR".to_string(), "/tmp".to_string()),
+                ("EMPTY".to_string(), "".to_string()),
+                ("A".to_string(), "B=C".to_string()),
+            ]
+        );
+        assert!(options.parse_argument("--env-var=NOVALUE").is_err());
+        assert!(options.parse_argument("--env-var==value").is_err());
+    }
+
+    #[test]
+    fn second_finger_key() {
+        let mut options = Options::default();
+        assert_eq!(options.second_finger_key, None);
+        let arg = "--second-finger-key=Left Ctrl";
+        assert!(options.parse_argument(arg).unwrap());
+        assert_eq!(options.second_finger_key.as_deref(), Some("left ctrl"));
+        assert!(options.parse_argument("--second-finger-key=off").unwrap());
+        assert_eq!(options.second_finger_key, None);
+        assert!(options.parse_argument("--second-finger-key=").is_err());
+    }
+
+    #[test]
+    fn fast_forward() {
+        let mut options = Options::default();
+        // Off unless the user opts in
+        assert_eq!(options.fast_forward_key, None);
+        assert_eq!(options.tick_slice(false), 100_000);
+        assert_eq!(options.tick_slice(true), 400_000);
+
+        assert!(options.parse_argument("--fast-forward-speed=10").unwrap());
+        assert!(options.parse_argument("--cpu-tick-slice=50000").unwrap());
+        assert_eq!(options.tick_slice(false), 50_000);
+        assert_eq!(options.tick_slice(true), 500_000);
+        assert!(options.parse_argument("--fast-forward-speed=0").is_err());
+        assert!(options.parse_argument("--fast-forward-speed=x").is_err());
+
+        let arg = format!(
+            "--fast-forward-speed={}",
+            Options::MAX_FAST_FORWARD_SPEED + 1
+        );
+        assert!(options.parse_argument(&arg).is_err());
+
+        // Huge values don't overflow
+        let arg = format!("--cpu-tick-slice={}", u64::MAX);
+        assert!(options.parse_argument(&arg).unwrap());
+        assert_eq!(options.tick_slice(true), u64::MAX);
+
+        assert!(options.fast_forward_mute);
+        assert!(options
+            .parse_argument("--fast-forward-audio=normal")
+            .unwrap());
+        assert!(!options.fast_forward_mute);
+        assert!(options.parse_argument("--fast-forward-audio=loud").is_err());
+
+        assert!(options.parse_argument("--fast-forward-key=F7").unwrap());
+        assert_eq!(options.fast_forward_key.as_deref(), Some("f7"));
+        assert!(options.parse_argument("--fast-forward-key=off").unwrap());
+        assert_eq!(options.fast_forward_key, None);
+    }
+
+    #[test]
+    fn input_map_valid() {
+        let map = parse(&[
+            "--button-to-touch=A,470,310",
+            "--button-to-touch=DPadLeft,0.5,-2",
+            "--key-to-touch=Space,160,240",
+            "--button-to-tilt=LeftShoulder,-1,0",
+            "--key-to-tilt=Up,0,0.5",
+        ])
+        .unwrap();
+        assert_eq!(map.button_to_touch.get(&Button::A), Some(&(470.0, 310.0)));
+        assert_eq!(
+            map.button_to_touch.get(&Button::DPadLeft),
+            Some(&(0.5, -2.0))
+        );
+        assert_eq!(map.key_to_touch.get("space"), Some(&(160.0, 240.0)));
+        assert_eq!(
+            map.button_to_tilt.get(&Button::LeftShoulder),
+            Some(&(-1.0, 0.0))
+        );
+        assert_eq!(map.key_to_tilt.get("up"), Some(&(0.0, 0.5)));
+
+        // Later mappings for the same input replace earlier ones
+        let map = parse(&["--key-to-touch=Left,1,1", "--key-to-touch=LEFT,2,2"]).unwrap();
+        assert_eq!(map.key_to_touch.len(), 1);
+        assert_eq!(map.key_to_touch.get("left"), Some(&(2.0, 2.0)));
+    }
+
+    #[test]
+    fn input_map_malformed() {
+        for arg in [
+            "--button-to-touch=A,470",
+            "--button-to-touch=A,470,310,1",
+            "--button-to-touch=Z,470,310",
+            "--button-to-touch=A,x,310",
+            "--button-to-touch=A,470,inf",
+            "--key-to-touch=,1,2",
+            "--key-to-touch=Space",
+            "--button-to-tilt=A,1.5,0",
+            "--key-to-tilt=Down,0,-2",
+            "--key-to-tilt=Down,0,NaN",
+        ] {
+            assert!(parse(&[arg]).is_err(), "{} should be rejected", arg);
+        }
+    }
+}
@@ -15,8 +15,8 @@
 //!   [USER_OPTIONS_FILE], [WALLPAPER_FILES]. These are ordinary files and are
 //!   found in [user_data_base_path].
 //! * Files that touchHLE will create and modify, and the user may modify if
-//!   they want to: [SANDBOX_DIR]. These are ordinary files and are found in
-//!   [user_data_base_path].
+//!   they want to: [SANDBOX_DIR], [LAST_APP_FILE]. These are ordinary files and
+//!   are found in [user_data_base_path].
 //!
 //! See also [crate::fs], which provides a virtual filesystem for the guest app
 //! and defines path types.
@@ -76,6 +76,14 @@ pub const APPS_DIR: &str = "touchHLE_apps";
 /// Name of the file intended for the user's own options.
 pub const USER_OPTIONS_FILE: &str = "touchHLE_options.txt";
 
+/// Name of the directory where screenshots taken with the screenshot key (see
+/// `--screenshot-key=`) are saved.
+pub const SCREENSHOTS_DIR: &str = "touchHLE_screenshots";
+
+/// Name of the directory where snapshots of running apps are saved (see
+/// [crate::snapshot]).
+pub const SNAPSHOTS_DIR: &str = "touchHLE_snapshots";
+
 /// Names of files the user can put a wallpaper image (for the app picker) in.
 #[allow(unused)]
 pub const WALLPAPER_FILES: &[&str] = &[
@@ -88,6 +96,10 @@ pub const WALLPAPER_FILES: &[&str] = &[
 /// the `Documents` directory.
 pub const SANDBOX_DIR: &str = "touchHLE_sandbox";
 
+/// Name of the file where the app picker records the file name (within
+/// [APPS_DIR]) of the app that was last launched from it.
+pub const LAST_APP_FILE: &str = "touchHLE_last_app.txt";
+
 /// Get a platform-specific base path needed for accessing touchHLE's
 /// user-modifiable files. This is empty on platforms other than Android.
 pub fn user_data_base_path() -> &'static Path {
@@ -0,0 +1,574 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! Snapshots ("save states") of a running app, so it can be resumed later.
+//!
+//! A snapshot contains the content of guest memory, the allocator state, the
+//! state of each thread (CPU registers and what it is blocked on), mutexes,
+//! condition variables, pthread keys and Objective-C refcounts. Much other
+//! host-side state is not saved:
+//!
+//! - Host objects (e.g. the elements of an `NSArray`) aren't saved, so a
+//!   snapshot can only be restored if exactly the same Objective-C objects
+//!   exist as when it was made, and any changes to them since then won't be
+//!   undone.
+//! - Threads and pthread objects must also be the same.
+//! - Various subsystems (files, sockets, OpenGL ES, audio, GCD queues, etc) are
+//!   not saved at all, so snapshots can't be made or restored while the app is
+//!   using them. See [crate::Environment::save_snapshot] for the full list.
+//!
+//! The state of host functions can't be saved either, so snapshots can only be
+//! made at certain points, see [ResumePoint].
+
+use crate::cpu::Cpu;
+use crate::environment::{MutexId, MutexSnapshot, MutexType};
+use crate::libc::pthread::PthreadSnapshot;
+use crate::mem::MemSnapshot;
+use crate::objc::ObjCSnapshot;
+use crate::ThreadId;
+use std::num::NonZeroU32;
+use std::time::Duration;
+
+const MAGIC: &[u8; 16] = b"touchHLEsnapshot";
+const VERSION: u32 = 2;
+
+/// Where a snapshot was made. A snapshot can only be restored at the same kind
+/// of point, because the host's call stack must be the same.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ResumePoint {
+    /// Between guest instructions when no host function is executing, i.e.
+    /// directly in [crate::Environment::run]. This is for apps that don't
+    /// use `UIApplicationMain()`.
+    Root,
+    /// At the start of an iteration of the main thread's outermost run loop,
+    /// which is where `UIApplicationMain()` spends its time.
+    MainRunLoop,
+}
+
+/// Copy of the state of the CPU (registers etc).
+#[derive(Debug, PartialEq)]
+pub struct CpuSnapshot {
+    pub regs: [u32; 16],
+    pub cpsr: u32,
+    pub ext_regs: [u32; 64],
+    pub fpscr: u32,
+}
+
+impl CpuSnapshot {
+    pub fn capture(cpu: &Cpu) -> CpuSnapshot {
+        CpuSnapshot {
+            regs: *cpu.regs(),
+            cpsr: cpu.cpsr(),
+            ext_regs: *cpu.ext_regs(),
+            fpscr: cpu.fpscr(),
+        }
+    }
+
+    pub fn restore(&self, cpu: &mut Cpu) {
+        *cpu.regs_mut() = self.regs;
+        cpu.set_cpsr(self.cpsr);
+        *cpu.ext_regs_mut() = self.ext_regs;
+        cpu.set_fpscr(self.fpscr);
+    }
+}
+
+/// What a thread is waiting for, see [crate::environment::ThreadBlock]. Only
+/// the kinds of blocking that can be saved are included.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum BlockSnapshot {
+    NotBlocked,
+    /// The remaining (guest) time to sleep for.
+    Sleeping(Duration),
+    Mutex(MutexId),
+    /// Address of the condition variable.
+    Condition(u32),
+    /// Thread being joined, and the address to write its return value to.
+    Joining(ThreadId, u32),
+}
+
+/// Copy of the state of a thread, see [crate::environment::Thread].
+#[derive(Debug, PartialEq)]
+pub struct ThreadSnapshot {
+    pub active: bool,
+    pub in_start_routine: bool,
+    pub return_value: Option<u32>,
+    pub blocked_by: BlockSnapshot,
+    pub cpu: CpuSnapshot,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Snapshot {
+    /// Bundle identifier of the app the snapshot was made from.
+    pub bundle_id: String,
+    pub resume_point: ResumePoint,
+    /// State of every thread, including ones that have finished.
+    pub threads: Vec<ThreadSnapshot>,
+    pub mem: MemSnapshot,
+    pub mutexes: MutexSnapshot,
+    pub pthread: PthreadSnapshot,
+    pub objc: ObjCSnapshot,
+}
+
+impl Snapshot {
+    /// Serialize the snapshot so it can be written to a file.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(MAGIC);
+        write_u32(&mut out, VERSION);
+
+        write_u32(&mut out, self.bundle_id.len().try_into().unwrap());
+        out.extend_from_slice(self.bundle_id.as_bytes());
+        write_u32(
+            &mut out,
+            match self.resume_point {
+                ResumePoint::Root => 0,
+                ResumePoint::MainRunLoop => 1,
+            },
+        );
+
+        write_usize(&mut out, self.threads.len());
+        for thread in &self.threads {
+            write_bool(&mut out, thread.active);
+            write_bool(&mut out, thread.in_start_routine);
+            write_option_u32(&mut out, thread.return_value);
+            match thread.blocked_by {
+                BlockSnapshot::NotBlocked => write_u32(&mut out, 0),
+                BlockSnapshot::Sleeping(duration) => {
+                    write_u32(&mut out, 1);
+                    write_u64(&mut out, duration.as_nanos().try_into().unwrap());
+                }
+                BlockSnapshot::Mutex(mutex_id) => {
+                    write_u32(&mut out, 2);
+                    write_u64(&mut out, mutex_id);
+                }
+                BlockSnapshot::Condition(cond) => {
+                    write_u32(&mut out, 3);
+                    write_u32(&mut out, cond);
+                }
+                BlockSnapshot::Joining(thread, retval) => {
+                    write_u32(&mut out, 4);
+                    write_usize(&mut out, thread);
+                    write_u32(&mut out, retval);
+                }
+            }
+            let cpu = &thread.cpu;
+            for &reg in cpu.regs.iter() {
+                write_u32(&mut out, reg);
+            }
+            write_u32(&mut out, cpu.cpsr);
+            for &reg in cpu.ext_regs.iter() {
+                write_u32(&mut out, reg);
+            }
+            write_u32(&mut out, cpu.fpscr);
+        }
+
+        write_u32(&mut out, self.mem.null_segment_size);
+        write_usize(&mut out, self.mem.chunks.len());
+        for (base, content) in &self.mem.chunks {
+            write_u32(&mut out, *base);
+            write_usize(&mut out, content.len());
+            out.extend_from_slice(content);
+        }
+
+        write_u64(&mut out, self.mutexes.mutex_count);
+        write_usize(&mut out, self.mutexes.mutexes.len());
+        for &(mutex_id, type_, locked, waiting_count) in &self.mutexes.mutexes {
+            write_u64(&mut out, mutex_id);
+            write_u32(&mut out, type_ as i32 as u32);
+            let (owner, lock_count) = locked.map_or((0, 0), |(owner, count)| (owner, count.get()));
+            write_usize(&mut out, owner);
+            write_u32(&mut out, lock_count);
+            write_u32(&mut out, waiting_count);
+        }
+
+        let pthread = &self.pthread;
+        write_usize(&mut out, pthread.conds.len());
+        for &(cond, done) in &pthread.conds {
+            write_u32(&mut out, cond);
+            write_bool(&mut out, done);
+        }
+        write_usize(&mut out, pthread.cond_mutexes.len());
+        for &(cond, mutex_id) in &pthread.cond_mutexes {
+            write_u32(&mut out, cond);
+            write_u64(&mut out, mutex_id);
+        }
+        write_usize(&mut out, pthread.keys.len());
+        for key in &pthread.keys {
+            write_bool(&mut out, key.is_some());
+            if let Some((destructor, values)) = key {
+                write_u32(&mut out, *destructor);
+                write_usize(&mut out, values.len());
+                for &(thread, value) in values {
+                    write_usize(&mut out, thread);
+                    write_u32(&mut out, value);
+                }
+            }
+        }
+        write_usize(&mut out, pthread.threads.len());
+        for &(ptr, thread, joined_by) in &pthread.threads {
+            write_u32(&mut out, ptr);
+            write_usize(&mut out, thread);
+            write_option_u32(&mut out, joined_by.map(|thread| thread.try_into().unwrap()));
+        }
+
+        write_usize(&mut out, self.objc.objects.len());
+        for &(object, isa, refcount) in &self.objc.objects {
+            write_u32(&mut out, object);
+            write_u32(&mut out, isa);
+            write_u32(&mut out, refcount);
+        }
+        write_usize(&mut out, self.objc.sync_mutexes.len());
+        for &(object, mutex_id) in &self.objc.sync_mutexes {
+            write_u32(&mut out, object);
+            write_u64(&mut out, mutex_id);
+        }
+
+        out
+    }
+
+    /// Parse a snapshot produced by [Self::to_bytes].
+    pub fn from_bytes(bytes: &[u8]) -> Result<Snapshot, String> {
+        let mut reader = Reader(bytes);
+
+        if reader.bytes(MAGIC.len())? != MAGIC {
+            return Err("Not a touchHLE snapshot file".to_string());
+        }
+        let version = reader.u32()?;
+        if version != VERSION {
+            return Err(format!("Unsupported snapshot version {}", version));
+        }
+
+        let bundle_id_len = reader.usize()?;
+        let bundle_id = std::str::from_utf8(reader.bytes(bundle_id_len)?)
+            .map_err(|_| "Snapshot bundle identifier is not UTF-8".to_string())?
+            .to_string();
+        let resume_point = match reader.u32()? {
+            0 => ResumePoint::Root,
+            1 => ResumePoint::MainRunLoop,
+            _ => return Err("Snapshot resume point is invalid".to_string()),
+        };
+
+        let thread_count = reader.usize()?;
+        let mut threads = Vec::new();
+        for _ in 0..thread_count {
+            let active = reader.bool()?;
+            let in_start_routine = reader.bool()?;
+            let return_value = reader.option_u32()?;
+            let blocked_by = match reader.u32()? {
+                0 => BlockSnapshot::NotBlocked,
+                1 => BlockSnapshot::Sleeping(Duration::from_nanos(reader.u64()?)),
+                2 => BlockSnapshot::Mutex(reader.u64()?),
+                3 => BlockSnapshot::Condition(reader.u32()?),
+                4 => BlockSnapshot::Joining(reader.usize()?, reader.u32()?),
+                _ => return Err("Snapshot thread state is invalid".to_string()),
+            };
+            let mut regs = [0u32; 16];
+            for reg in regs.iter_mut() {
+                *reg = reader.u32()?;
+            }
+            let cpsr = reader.u32()?;
+            let mut ext_regs = [0u32; 64];
+            for reg in ext_regs.iter_mut() {
+                *reg = reader.u32()?;
+            }
+            let fpscr = reader.u32()?;
+            threads.push(ThreadSnapshot {
+                active,
+                in_start_routine,
+                return_value,
+                blocked_by,
+                cpu: CpuSnapshot {
+                    regs,
+                    cpsr,
+                    ext_regs,
+                    fpscr,
+                },
+            });
+        }
+        if threads.is_empty() {
+            return Err("Snapshot has no threads".to_string());
+        }
+
+        let null_segment_size = reader.u32()?;
+        let chunk_count = reader.usize()?;
+        let mut chunks = Vec::new();
+        let mut next_free_addr: u64 = null_segment_size.into();
+        for _ in 0..chunk_count {
+            let base = reader.u32()?;
+            let size = reader.u32()?;
+            // Chunks must be non-empty, in order, and not overlap, otherwise
+            // the allocator state would be nonsense.
+            if size == 0 || u64::from(base) < next_free_addr {
+                return Err("Snapshot memory chunks are invalid".to_string());
+            }
+            next_free_addr = u64::from(base) + u64::from(size);
+            if next_free_addr > 1 << 32 {
+                return Err("Snapshot memory chunks are invalid".to_string());
+            }
+            chunks.push((base, reader.bytes(size as usize)?.to_vec()));
+        }
+
+        let mutex_count = reader.u64()?;
+        let mutex_len = reader.usize()?;
+        let mut mutexes = Vec::new();
+        for _ in 0..mutex_len {
+            let mutex_id = reader.u64()?;
+            let type_ = MutexType::try_from(reader.u32()? as i32)?;
+            let owner = reader.usize()?;
+            let locked = NonZeroU32::new(reader.u32()?).map(|count| (owner, count));
+            let waiting_count = reader.u32()?;
+            mutexes.push((mutex_id, type_, locked, waiting_count));
+        }
+
+        let cond_count = reader.usize()?;
+        let mut conds = Vec::new();
+        for _ in 0..cond_count {
+            conds.push((reader.u32()?, reader.bool()?));
+        }
+        let cond_mutex_count = reader.usize()?;
+        let mut cond_mutexes = Vec::new();
+        for _ in 0..cond_mutex_count {
+            cond_mutexes.push((reader.u32()?, reader.u64()?));
+        }
+        let key_count = reader.usize()?;
+        let mut keys = Vec::new();
+        for _ in 0..key_count {
+            if !reader.bool()? {
+                keys.push(None);
+                continue;
+            }
+            let destructor = reader.u32()?;
+            let value_count = reader.usize()?;
+            let mut values = Vec::new();
+            for _ in 0..value_count {
+                values.push((reader.usize()?, reader.u32()?));
+            }
+            keys.push(Some((destructor, values)));
+        }
+        let pthread_thread_count = reader.usize()?;
+        let mut pthread_threads = Vec::new();
+        for _ in 0..pthread_thread_count {
+            let ptr = reader.u32()?;
+            let thread = reader.usize()?;
+            let joined_by = reader.option_u32()?.map(|thread| thread as ThreadId);
+            pthread_threads.push((ptr, thread, joined_by));
+        }
+
+        let object_count = reader.usize()?;
+        let mut objects = Vec::new();
+        for _ in 0..object_count {
+            objects.push((reader.u32()?, reader.u32()?, reader.u32()?));
+        }
+        let sync_mutex_count = reader.usize()?;
+        let mut sync_mutexes = Vec::new();
+        for _ in 0..sync_mutex_count {
+            sync_mutexes.push((reader.u32()?, reader.u64()?));
+        }
+
+        if !reader.0.is_empty() {
+            return Err("Unexpected data at end of snapshot".to_string());
+        }
+
+        Ok(Snapshot {
+            bundle_id,
+            resume_point,
+            threads,
+            mem: MemSnapshot {
+                null_segment_size,
+                chunks,
+            },
+            mutexes: MutexSnapshot {
+                mutex_count,
+                mutexes,
+            },
+            pthread: PthreadSnapshot {
+                conds,
+                cond_mutexes,
+                keys,
+                threads: pthread_threads,
+            },
+            objc: ObjCSnapshot {
+                objects,
+                sync_mutexes,
+            },
+        })
+    }
+}
+
+fn write_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+fn write_u64(out: &mut Vec<u8>, value: u64) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+fn write_usize(out: &mut Vec<u8>, value: usize) {
+    write_u32(out, value.try_into().unwrap());
+}
+fn write_bool(out: &mut Vec<u8>, value: bool) {
+    write_u32(out, value.into());
+}
+fn write_option_u32(out: &mut Vec<u8>, value: Option<u32>) {
+    write_bool(out, value.is_some());
+    write_u32(out, value.unwrap_or(0));
+}
+
+struct Reader<'a>(&'a [u8]);
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, count: usize) -> Result<&'a [u8], String> {
+        if self.0.len() < count {
+            return Err("Snapshot is truncated".to_string());
+        }
+        let (bytes, rest) = self.0.split_at(count);
+        self.0 = rest;
+        Ok(bytes)
+    }
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+    fn u64(&mut self) -> Result<u64, String> {
+        Ok(u64::from_le_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+    fn usize(&mut self) -> Result<usize, String> {
+        Ok(self.u32()? as usize)
+    }
+    fn bool(&mut self) -> Result<bool, String> {
+        match self.u32()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err("Snapshot contains an invalid boolean".to_string()),
+        }
+    }
+    fn option_u32(&mut self) -> Result<Option<u32>, String> {
+        let is_some = self.bool()?;
+        let value = self.u32()?;
+        Ok(is_some.then_some(value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::abi::GuestFunction;
+    use crate::cpu::CpuState;
+    use crate::mem::{Mem, MutPtr};
+
+    #[test]
+    fn round_trip() {
+        let mut mem = Mem::new();
+        mem.set_null_segment_size(0x1000);
+
+        let code: MutPtr<u32> = mem.alloc(16).cast();
+        let program = [
+            0xE3A0002A, // mov r0, #42
+            0xE2801001, // add r1, r0, #1
+            0xE5821000, // str r1, [r2]
+            0xEAFFFFFE, // b .
+        ];
+        for (i, &instruction) in program.iter().enumerate() {
+            mem.write(code + i as u32, instruction);
+        }
+        let data: MutPtr<u32> = mem.alloc(4).cast();
+
+        let mut cpu = Cpu::new(Some(&mut mem));
+        cpu.set_cpsr(Cpu::CPSR_USER_MODE);
+        cpu.branch(GuestFunction::from_addr_with_thumb_bit(code.to_bits()));
+        cpu.regs_mut()[2] = data.to_bits();
+        cpu.ext_regs_mut()[5] = 0x3F800000;
+        for _ in 0..3 {
+            assert!(matches!(cpu.run_or_step(&mut mem, None), CpuState::Normal));
+        }
+        assert_eq!(mem.read(data), 43);
+
+        let snapshot = Snapshot {
+            bundle_id: "org.touchhle.test".to_string(),
+            resume_point: ResumePoint::MainRunLoop,
+            threads: vec![
+                ThreadSnapshot {
+                    active: true,
+                    in_start_routine: false,
+                    return_value: None,
+                    blocked_by: BlockSnapshot::NotBlocked,
+                    cpu: CpuSnapshot::capture(&cpu),
+                },
+                ThreadSnapshot {
+                    active: true,
+                    in_start_routine: true,
+                    return_value: Some(0x2000),
+                    blocked_by: BlockSnapshot::Sleeping(Duration::from_millis(1500)),
+                    cpu: CpuSnapshot::capture(&cpu),
+                },
+            ],
+            mem: mem.snapshot(),
+            mutexes: MutexSnapshot {
+                mutex_count: 2,
+                mutexes: vec![(
+                    1,
+                    MutexType::PTHREAD_MUTEX_RECURSIVE,
+                    Some((1, NonZeroU32::new(3).unwrap())),
+                    1,
+                )],
+            },
+            pthread: PthreadSnapshot {
+                conds: vec![(0x3000, true)],
+                cond_mutexes: vec![(0x3000, 1)],
+                keys: vec![None, Some((0x4001, vec![(0, 0x5000), (1, 0x6000)]))],
+                threads: vec![(0x7000, 0, None), (0x8000, 1, Some(0))],
+            },
+            objc: ObjCSnapshot {
+                objects: vec![(0x9000, 0xA000, 0), (0xB000, 0x9000, 2)],
+                sync_mutexes: vec![(0xB000, 1)],
+            },
+        };
+        let bytes = snapshot.to_bytes();
+        let parsed = Snapshot::from_bytes(&bytes).unwrap();
+        assert_eq!(parsed, snapshot);
+        let snapshot = parsed;
+        let regs = *cpu.regs();
+
+        // Mess everything up
+        mem.write(data, 0);
+        let new_alloc = mem.alloc(100);
+        mem.write(new_alloc.cast(), 0xDEADBEEFu32);
+        mem.free(code.cast());
+        cpu.regs_mut()[0] = 0;
+        cpu.regs_mut()[1] = 0;
+        cpu.ext_regs_mut()[5] = 0;
+        assert!(matches!(cpu.run_or_step(&mut mem, None), CpuState::Normal));
+        assert!(snapshot.mem.is_used(data.to_bits() + 3));
+        assert!(!snapshot.mem.is_used(new_alloc.to_bits()));
+
+        mem.restore_snapshot(&snapshot.mem);
+        snapshot.threads[0].cpu.restore(&mut cpu);
+        cpu.invalidate_cache_range(0, u32::MAX);
+
+        assert_eq!(*cpu.regs(), regs);
+        assert_eq!(cpu.regs()[0], 42);
+        assert_eq!(cpu.ext_regs()[5], 0x3F800000);
+        assert_eq!(mem.read(data), 43);
+        assert_eq!(mem.read(code + 3), 0xEAFFFFFE);
+        // The allocation made after the snapshot was discarded and zeroed.
+        assert_eq!(mem.read(new_alloc.cast::<u32>()), 0);
+        assert_eq!(mem.alloc(100), new_alloc);
+        assert_eq!(mem.snapshot().chunks.len(), snapshot.mem.chunks.len() + 1);
+
+        // The restored program can keep running.
+        assert!(matches!(cpu.run_or_step(&mut mem, None), CpuState::Normal));
+        assert_eq!(cpu.regs()[15], (code + 3).to_bits());
+    }
+
+    #[test]
+    fn rejects_bad_files() {
+        assert!(Snapshot::from_bytes(b"not a snapshot").is_err());
+        let mut bytes = MAGIC.to_vec();
+        write_u32(&mut bytes, VERSION + 1);
+        assert!(Snapshot::from_bytes(&bytes).is_err());
+        let mut bytes = MAGIC.to_vec();
+        write_u32(&mut bytes, VERSION);
+        assert_eq!(
+            Snapshot::from_bytes(&bytes),
+            Err("Snapshot is truncated".to_string())
+        );
+    }
+}
@@ -0,0 +1,147 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! Collection of unimplemented functionality used by the app, for
+//! `--log-unimplemented`.
+//!
+//! Normally, touchHLE panics when the app calls a function or sends a message
+//! that isn't implemented. With `--log-unimplemented`, these are instead
+//! recorded here, the app gets a zero/nil result, and a summary is printed
+//! when touchHLE exits, so that a single run can find all of the missing
+//! things rather than only the first one.
+
+/// Deduplicated list of unimplemented things, in the order they were first
+/// used.
+#[derive(Default)]
+pub struct UnimplementedLog {
+    entries: Vec<String>,
+}
+
+impl UnimplementedLog {
+    /// Record use of something unimplemented, e.g. `-[NSFoo bar]`. Returns
+    /// [true] if this is the first time it has been recorded.
+    pub fn record(&mut self, what: String) -> bool {
+        if self.entries.contains(&what) {
+            return false;
+        }
+        self.entries.push(what);
+        true
+    }
+
+    /// Format the summary printed at exit, or [None] if nothing was recorded.
+    pub fn summary(&self) -> Option<String> {
+        if self.entries.is_empty() {
+            return None;
+        }
+        let mut summary = format!(
+            "The app used {} unimplemented function(s) or method(s):",
+            self.entries.len()
+        );
+        for entry in &self.entries {
+            summary.push_str("\n- ");
+            summary.push_str(entry);
+        }
+        Some(summary)
+    }
+}
+
+/// Guess which framework or library would provide a C function, based on its
+/// (mangled) symbol name, e.g. `_CFRunLoopTimerCreate` is from CoreFoundation.
+/// This is only a heuristic to speed up triage.
+pub fn guess_framework(symbol: &str) -> Option<&'static str> {
+    // The prefix must be followed by an uppercase letter, so that e.g.
+    // `_glob` isn't mistaken for an OpenGL ES function.
+    const PREFIXES: &[(&str, &str)] = &[
+        ("AudioComponent", "AudioUnit"),
+        ("AudioUnit", "AudioUnit"),
+        ("Audio", "AudioToolbox"),
+        ("ExtAudioFile", "AudioToolbox"),
+        ("AB", "AddressBook"),
+        ("AV", "AVFoundation"),
+        ("CA", "QuartzCore"),
+        ("CF", "CoreFoundation"),
+        ("CG", "CoreGraphics"),
+        ("CL", "CoreLocation"),
+        ("GK", "GameKit"),
+        ("MK", "MapKit"),
+        ("MP", "MediaPlayer"),
+        ("NS", "Foundation"),
+        ("SC", "SystemConfiguration"),
+        ("Sec", "Security"),
+        ("UI", "UIKit"),
+        ("alc", "OpenAL"),
+        ("al", "OpenAL"),
+        ("gl", "OpenGLES"),
+    ];
+
+    let name = symbol.strip_prefix('_')?;
+    PREFIXES.iter().find_map(|&(prefix, framework)| {
+        let rest = name.strip_prefix(prefix)?;
+        rest.starts_with(|c: char| c.is_ascii_uppercase())
+            .then_some(framework)
+    })
+}
+
+/// Describe a C function for a log or panic message, including the framework
+/// it's probably from (see [guess_framework]), e.g.
+/// `_CFRunLoopTimerCreate (CoreFoundation)`.
+pub fn describe_function(symbol: &str) -> String {
+    match guess_framework(symbol) {
+        Some(framework) => format!("{} ({})", symbol, framework),
+        None => symbol.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dedupes_and_formats() {
+        let mut log = UnimplementedLog::default();
+        assert_eq!(log.summary(), None);
+        assert!(log.record("-[NSFoo bar]".to_string()));
+        assert!(log.record("_baz".to_string()));
+        assert!(!log.record("-[NSFoo bar]".to_string()));
+        assert!(log.record("+[NSFoo bar]".to_string()));
+        assert_eq!(
+            log.summary().unwrap(),
+            "\
+The app used 3 unimplemented function(s) or method(s):
+- -[NSFoo bar]
+- _baz
+- +[NSFoo bar]"
+        );
+    }
+
+    #[test]
+    fn guesses_frameworks() {
+        assert_eq!(
+            guess_framework("_CFRunLoopTimerCreate"),
+            Some("CoreFoundation")
+        );
+        assert_eq!(guess_framework("_CGContextFillRect"), Some("CoreGraphics"));
+        assert_eq!(guess_framework("_NSLog"), Some("Foundation"));
+        assert_eq!(guess_framework("_UIApplicationMain"), Some("UIKit"));
+        assert_eq!(guess_framework("_AudioQueueStart"), Some("AudioToolbox"));
+        assert_eq!(
+            guess_framework("_AudioComponentFindNext"),
+            Some("AudioUnit")
+        );
+        assert_eq!(guess_framework("_alcOpenDevice"), Some("OpenAL"));
+        assert_eq!(guess_framework("_alGenSources"), Some("OpenAL"));
+        assert_eq!(guess_framework("_glDrawArrays"), Some("OpenGLES"));
+        // Not a framework prefix
+        assert_eq!(guess_framework("_glob"), None);
+        assert_eq!(guess_framework("_malloc"), None);
+        assert_eq!(guess_framework("__Znwm"), None);
+
+        assert_eq!(
+            describe_function("_CFRunLoopTimerCreate"),
+            "_CFRunLoopTimerCreate (CoreFoundation)"
+        );
+        assert_eq!(describe_function("_malloc"), "_malloc");
+    }
+}
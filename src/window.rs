@@ -16,28 +16,34 @@ use crate::gles::present::present_frame;
 use crate::gles::{create_gles1_ctx, GLES};
 use crate::image::Image;
 use crate::matrix::Matrix;
-use crate::options::Options;
+use crate::options::{Options, ScaleHack};
+use sdl2::event::WindowEvent;
 use sdl2::mouse::MouseButton;
 use sdl2::pixels::PixelFormatEnum;
 use sdl2::surface::Surface;
 use std::collections::{HashMap, VecDeque};
 use std::env;
 use std::f32::consts::FRAC_PI_2;
-use std::num::NonZeroU32;
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
 use std::time::{Duration, Instant};
 
-#[derive(Copy, Clone, Eq, PartialEq)]
+/// How long touchHLE waits for the app to present a frame once it's time to
+/// take the screenshot for `--screenshot-after=`, before giving up and exiting
+/// with an error.
+const SCREENSHOT_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
 pub enum DeviceOrientation {
     Portrait,
     LandscapeLeft,
     LandscapeRight,
 }
-fn size_for_orientation(orientation: DeviceOrientation, scale_hack: NonZeroU32) -> (u32, u32) {
-    let scale_hack = scale_hack.get();
+fn size_for_orientation(orientation: DeviceOrientation, scale_hack: ScaleHack) -> (u32, u32) {
+    let (short, long) = (scale_hack.scale(320) as u32, scale_hack.scale(480) as u32);
     match orientation {
-        DeviceOrientation::Portrait => (320 * scale_hack, 480 * scale_hack),
-        DeviceOrientation::LandscapeLeft => (480 * scale_hack, 320 * scale_hack),
-        DeviceOrientation::LandscapeRight => (480 * scale_hack, 320 * scale_hack),
+        DeviceOrientation::Portrait => (short, long),
+        DeviceOrientation::LandscapeLeft => (long, short),
+        DeviceOrientation::LandscapeRight => (long, short),
     }
 }
 fn rotate_fullscreen_size(orientation: DeviceOrientation, screen_size: (u32, u32)) -> (u32, u32) {
@@ -71,12 +77,62 @@ fn set_sdl2_orientation(orientation: DeviceOrientation) {
 #[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
 pub enum FingerId {
     Mouse,
+    /// See [MouseFingers].
+    MouseSecondFinger,
     Touch(i64),
     VirtualCursor,
     ButtonToTouch(crate::options::Button),
+    KeyToTouch(sdl2::keyboard::Keycode),
 }
 pub type Coords = (f32, f32);
 
+/// Tracks which simulated touches the mouse is controlling. Normally that's
+/// just [FingerId::Mouse], but if the second finger key (see
+/// `--second-finger-key=`) is held when the button is pressed, there is also a
+/// second touch mirrored around the centre of the screen, so that pinch and
+/// rotation gestures can be performed with a mouse.
+#[derive(Default, Debug)]
+struct MouseFingers {
+    second_finger: bool,
+}
+impl MouseFingers {
+    fn touches(&self, coords: Coords, (width, height): (u32, u32)) -> HashMap<FingerId, Coords> {
+        let mut map = HashMap::from([(FingerId::Mouse, coords)]);
+        if self.second_finger {
+            let (x, y) = coords;
+            let mirrored = (width as f32 - x, height as f32 - y);
+            map.insert(FingerId::MouseSecondFinger, mirrored);
+        }
+        map
+    }
+    fn down(
+        &mut self,
+        coords: Coords,
+        second_finger_key_held: bool,
+        screen_size: (u32, u32),
+    ) -> HashMap<FingerId, Coords> {
+        self.second_finger = second_finger_key_held;
+        self.touches(coords, screen_size)
+    }
+    fn moved(&self, coords: Coords, screen_size: (u32, u32)) -> HashMap<FingerId, Coords> {
+        // The key doesn't need to stay held once the touches have begun.
+        self.touches(coords, screen_size)
+    }
+    fn up(&mut self, coords: Coords, screen_size: (u32, u32)) -> HashMap<FingerId, Coords> {
+        let map = self.touches(coords, screen_size);
+        self.second_finger = false;
+        map
+    }
+}
+
+/// A game controller button or keyboard key that can be mapped by
+/// [crate::options::InputMap].
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+enum MappedInput {
+    Button(crate::options::Button),
+    Key(sdl2::keyboard::Keycode),
+}
+
 #[derive(Debug)]
 pub enum TextInputEvent {
     Text(String),
@@ -84,6 +140,28 @@ pub enum TextInputEvent {
     Return,
 }
 
+/// Host-side equivalent of `UIApplicationState`: whether the touchHLE window
+/// has focus, or is minimized.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AppState {
+    Active,
+    Inactive,
+    Background,
+}
+
+/// Work out the new [AppState] after a window event.
+fn app_state_after_window_event(current: AppState, win_event: &WindowEvent) -> AppState {
+    match win_event {
+        WindowEvent::FocusLost if current == AppState::Active => AppState::Inactive,
+        WindowEvent::Minimized | WindowEvent::Hidden => AppState::Background,
+        WindowEvent::Restored | WindowEvent::Shown if current == AppState::Background => {
+            AppState::Inactive
+        }
+        WindowEvent::FocusGained => AppState::Active,
+        _ => current,
+    }
+}
+
 #[derive(Debug)]
 pub enum Event {
     /// User requested quit.
@@ -101,6 +179,20 @@ pub enum Event {
     /// take over.
     EnterDebugger,
     TextInput(TextInputEvent),
+    /// User pressed F8, requesting that the device be rotated to the next
+    /// orientation the app supports.
+    RotateDevice,
+    /// The window gained or lost focus, or was minimized or restored.
+    AppStateChanged(AppState),
+}
+
+/// See [Window::take_snapshot_request].
+#[derive(Debug, Copy, Clone)]
+pub enum SnapshotRequest {
+    /// User pressed F9.
+    Save,
+    /// User pressed F10.
+    Load,
 }
 
 pub enum GLVersion {
@@ -112,6 +204,25 @@ pub enum GLVersion {
 
 pub struct GLContext(sdl2::video::GLContext);
 
+/// Start a thread that exits touchHLE with an error if the screenshot for
+/// `--screenshot-after=` hasn't been taken within [SCREENSHOT_TIMEOUT] of when
+/// it's due. Dropping the returned [Sender] stops it.
+fn spawn_screenshot_watchdog(after: Duration) -> Sender<()> {
+    let (sender, receiver) = channel();
+    std::thread::spawn(move || {
+        // Nothing is ever sent, so this only returns early if the sender is
+        // dropped.
+        if receiver.recv_timeout(after + SCREENSHOT_TIMEOUT) == Err(RecvTimeoutError::Timeout) {
+            log!(
+                "Error: No frame was presented within {:?} for --screenshot-after=, exiting.",
+                SCREENSHOT_TIMEOUT
+            );
+            std::process::exit(1);
+        }
+    });
+    sender
+}
+
 fn surface_from_image(image: &Image) -> Surface {
     let src_pixels = image.pixels();
     let (width, height) = image.dimensions();
@@ -134,7 +245,7 @@ fn surface_from_image(image: &Image) -> Surface {
 }
 
 pub struct Window {
-    _sdl_ctx: sdl2::Sdl,
+    sdl_ctx: sdl2::Sdl,
     video_ctx: sdl2::VideoSubsystem,
     window: sdl2::video::Window,
     event_pump: sdl2::EventPump,
@@ -144,6 +255,9 @@ pub struct Window {
     /// terminate).
     high_priority_event: Option<Event>,
     enable_event_polling: bool,
+    app_state: AppState,
+    /// See [Self::pause_while_in_background].
+    paused_in_background: bool,
     #[cfg(target_os = "macos")]
     max_height: u32,
     #[cfg(target_os = "macos")]
@@ -151,7 +265,7 @@ pub struct Window {
     /// Copy of `fullscreen` on [Options]. Note that this is meaningless when
     /// [Self::rotatable_fullscreen] returns [true].
     fullscreen: bool,
-    scale_hack: NonZeroU32,
+    scale_hack: ScaleHack,
     internal_gl_ctx: Option<Box<dyn GLES>>,
     splash_image: Option<Image>,
     device_orientation: DeviceOrientation,
@@ -163,6 +277,25 @@ pub struct Window {
     virtual_cursor_last: Option<(f32, f32, bool, bool)>,
     virtual_cursor_last_unsticky: Option<(f32, f32, Instant)>,
     virtual_accelerometer_last: Option<(f32, f32, bool)>,
+    /// Buttons and keys mapped to accelerometer tilt (see
+    /// [crate::options::InputMap]) that are currently held, and the simulated
+    /// analog stick position for each.
+    held_tilt_inputs: HashMap<MappedInput, (f32, f32)>,
+    /// Whether the key set by `--second-finger-key=` is currently held.
+    second_finger_key_held: bool,
+    /// Whether the key set by `--fast-forward-key=` is currently held.
+    fast_forward_key_held: bool,
+    mouse_fingers: MouseFingers,
+    /// Set when the screenshot key is pressed, see
+    /// [Self::take_screenshot_request].
+    screenshot_requested: bool,
+    /// When to take a screenshot and then quit (see `--screenshot-after=`).
+    screenshot_and_exit_at: Option<Instant>,
+    exit_after_screenshot: bool,
+    /// Dropped once the screenshot for `--screenshot-after=` has been taken,
+    /// which stops the thread that enforces [SCREENSHOT_TIMEOUT].
+    screenshot_watchdog: Option<Sender<()>>,
+    snapshot_request: Option<SnapshotRequest>,
 }
 impl Window {
     /// Returns [true] if touchHLE is running on a device where we should always
@@ -172,12 +305,16 @@ impl Window {
         env::consts::OS == "android"
     }
 
-    pub fn new(
-        title: &str,
-        icon: Option<Image>,
-        launch_image: Option<Image>,
-        options: &Options,
-    ) -> Window {
+    pub fn new(title: &str, icon: Option<Image>, options: &Options) -> Window {
+        // In headless mode, a window is only created to render a screenshot
+        // (see Options::creates_window). SDL's offscreen driver provides a
+        // hidden window with an OpenGL context, without needing a display,
+        // which is useful for CI.
+        let offscreen = options.headless;
+        if offscreen {
+            sdl2::hint::set("SDL_VIDEODRIVER", "offscreen");
+        }
+
         let sdl_ctx = sdl2::init().unwrap();
         let video_ctx = sdl_ctx.video().unwrap();
 
@@ -213,7 +350,16 @@ impl Window {
         let device_orientation = options.initial_orientation;
         let fullscreen = options.fullscreen;
 
-        let mut window = if Self::rotatable_fullscreen() {
+        let mut window = if offscreen {
+            let (width, height) = size_for_orientation(device_orientation, scale_hack);
+            let window = video_ctx
+                .window(title, width, height)
+                .hidden()
+                .opengl()
+                .build()
+                .unwrap();
+            window
+        } else if Self::rotatable_fullscreen() {
             // Without this, SDL will force fullscreen mode to be portrait.
             set_sdl2_orientation(device_orientation);
             let screen_size = video_ctx.display_bounds(0).unwrap().size();
@@ -278,7 +424,7 @@ impl Window {
         let max_height = window.size().1;
 
         let mut window = Window {
-            _sdl_ctx: sdl_ctx,
+            sdl_ctx,
             video_ctx,
             window,
             event_pump,
@@ -286,6 +432,8 @@ impl Window {
             last_polled: Instant::now() - Duration::from_secs(1),
             high_priority_event: None,
             enable_event_polling: true,
+            app_state: AppState::Active,
+            paused_in_background: false,
             #[cfg(target_os = "macos")]
             max_height,
             #[cfg(target_os = "macos")]
@@ -293,7 +441,7 @@ impl Window {
             fullscreen,
             scale_hack,
             internal_gl_ctx: None,
-            splash_image: launch_image,
+            splash_image: None,
             device_orientation,
             app_gl_ctx_no_longer_current: false,
             controller_ctx,
@@ -303,6 +451,15 @@ impl Window {
             virtual_cursor_last: None,
             virtual_cursor_last_unsticky: None,
             virtual_accelerometer_last: None,
+            held_tilt_inputs: HashMap::new(),
+            second_finger_key_held: false,
+            fast_forward_key_held: false,
+            mouse_fingers: MouseFingers::default(),
+            screenshot_requested: false,
+            screenshot_and_exit_at: options.screenshot_after.map(|after| Instant::now() + after),
+            exit_after_screenshot: false,
+            screenshot_watchdog: options.screenshot_after.map(spawn_screenshot_watchdog),
+            snapshot_request: None,
         };
 
         // Set up OpenGL ES context used for splash screen and app UI rendering
@@ -314,13 +471,16 @@ impl Window {
         log!("Driver info: {}", unsafe { gl_ctx.driver_description() });
         window.internal_gl_ctx = Some(gl_ctx);
 
-        if window.splash_image.is_some() {
-            window.display_splash();
-        }
-
         window
     }
 
+    /// Set the image (usually the app's launch image) to display until the
+    /// app presents its first frame, and display it.
+    pub fn set_splash_image(&mut self, image: Image) {
+        self.splash_image = Some(image);
+        self.display_splash();
+    }
+
     /// Poll for events from the OS. This needs to be done reasonably often
     /// (60Hz is probably fine) so that the host OS doesn't consider touchHLE
     /// to be unresponsive. Note that events are not returned by this function,
@@ -336,6 +496,12 @@ impl Window {
         }
         self.last_polled = now;
 
+        if self.screenshot_and_exit_at.is_some_and(|at| now >= at) {
+            self.screenshot_and_exit_at = None;
+            self.screenshot_requested = true;
+            self.exit_after_screenshot = true;
+        }
+
         fn transform_input_coords(
             window: &Window,
             (in_x, in_y): (f32, f32),
@@ -343,7 +509,7 @@ impl Window {
         ) -> (f32, f32) {
             let (vx, vy, vw, vh) = if independent_of_viewport {
                 let (width, height) =
-                    size_for_orientation(window.device_orientation, NonZeroU32::new(1).unwrap());
+                    size_for_orientation(window.device_orientation, ScaleHack::new(1.0).unwrap());
                 (0, 0, width, height)
             } else {
                 window.viewport()
@@ -383,6 +549,14 @@ impl Window {
                 _ => None,
             }
         }
+        fn key_is_mapped(options: &Options, keycode: sdl2::keyboard::Keycode) -> bool {
+            let input_map = &options.input_map;
+            if input_map.key_to_touch.is_empty() && input_map.key_to_tilt.is_empty() {
+                return false;
+            }
+            let name = keycode.name().to_lowercase();
+            input_map.key_to_touch.contains_key(&name) || input_map.key_to_tilt.contains_key(&name)
+        }
         fn finger_absolute_coords(window: &Window, (x, y): (f32, f32)) -> (f32, f32) {
             let (screen_width, screen_height) = window.window.drawable_size();
             (screen_width as f32 * x, screen_height as f32 * y)
@@ -395,6 +569,7 @@ impl Window {
         let mut previous_event: Option<sdl2::event::Event> = None;
         while self.enable_event_polling {
             use sdl2::event::Event as E;
+            use sdl2::keyboard::Keycode;
             let event = if let Some(e) = previous_event.take() {
                 match e {
                     E::Unknown { .. } => (),
@@ -443,7 +618,22 @@ impl Window {
             }
 
             self.event_queue.push_back(match event {
-                E::Quit { .. } => Event::Quit,
+                E::Quit { .. } => {
+                    self.paused_in_background = false;
+                    Event::Quit
+                }
+                E::Window { win_event, .. } => {
+                    let new_state = app_state_after_window_event(self.app_state, &win_event);
+                    if new_state == self.app_state {
+                        continue;
+                    }
+                    log_dbg!("App state changed: {:?} => {:?}", self.app_state, new_state);
+                    self.app_state = new_state;
+                    if new_state != AppState::Background {
+                        self.paused_in_background = false;
+                    }
+                    Event::AppStateChanged(new_state)
+                }
                 E::MouseButtonDown {
                     x,
                     y,
@@ -452,14 +642,17 @@ impl Window {
                 } => {
                     let coords = transform_input_coords(self, (x as f32, y as f32), false);
                     log_dbg!("MouseButtonDown x {}, y {}, coords {:?}", x, y, coords);
-                    Event::TouchesDown(HashMap::from([(FingerId::Mouse, coords)]))
+                    let screen_size = self.size_unrotated_unscaled();
+                    let held = self.second_finger_key_held;
+                    Event::TouchesDown(self.mouse_fingers.down(coords, held, screen_size))
                 }
                 E::MouseMotion {
                     x, y, mousestate, ..
                 } if mousestate.left() => {
                     let coords = transform_input_coords(self, (x as f32, y as f32), false);
                     log_dbg!("MouseMotion x {}, y {}, coords {:?}", x, y, coords);
-                    Event::TouchesMove(HashMap::from([(FingerId::Mouse, coords)]))
+                    let screen_size = self.size_unrotated_unscaled();
+                    Event::TouchesMove(self.mouse_fingers.moved(coords, screen_size))
                 }
                 E::MouseButtonUp {
                     x,
@@ -469,7 +662,8 @@ impl Window {
                 } => {
                     let coords = transform_input_coords(self, (x as f32, y as f32), false);
                     log_dbg!("MouseButtonUp x {}, y {}, coords {:?}", x, y, coords);
-                    Event::TouchesUp(HashMap::from([(FingerId::Mouse, coords)]))
+                    let screen_size = self.size_unrotated_unscaled();
+                    Event::TouchesUp(self.mouse_fingers.up(coords, screen_size))
                 }
                 E::ControllerDeviceAdded { which, .. } => {
                     self.controller_added(which);
@@ -486,7 +680,11 @@ impl Window {
                     let Some(button) = translate_button(button) else {
                         continue;
                     };
-                    let Some(&(x, y)) = options.button_to_touch.get(&button) else {
+                    if let Some(&tilt) = options.input_map.button_to_tilt.get(&button) {
+                        let pressed = matches!(event, E::ControllerButtonDown { .. });
+                        self.set_tilt_input(MappedInput::Button(button), tilt, pressed);
+                    }
+                    let Some(&(x, y)) = options.input_map.button_to_touch.get(&button) else {
                         continue;
                     };
                     match event {
@@ -614,6 +812,105 @@ impl Window {
                         _ => unreachable!(),
                     }
                 }
+                E::KeyDown {
+                    keycode: Some(keycode),
+                    repeat: false,
+                    ..
+                } if options
+                    .screenshot_key
+                    .as_ref()
+                    .is_some_and(|key| *key == keycode.name().to_lowercase()) =>
+                {
+                    // The screenshot is taken when the next frame is presented.
+                    echo!("{} pressed, screenshot requested.", keycode.name());
+                    self.screenshot_requested = true;
+                    continue;
+                }
+                E::KeyDown {
+                    keycode: Some(keycode),
+                    ..
+                }
+                | E::KeyUp {
+                    keycode: Some(keycode),
+                    ..
+                } if options
+                    .second_finger_key
+                    .as_ref()
+                    .is_some_and(|key| *key == keycode.name().to_lowercase()) =>
+                {
+                    self.second_finger_key_held = matches!(event, E::KeyDown { .. });
+                    continue;
+                }
+                E::KeyDown {
+                    keycode: Some(keycode),
+                    ..
+                }
+                | E::KeyUp {
+                    keycode: Some(keycode),
+                    ..
+                } if options
+                    .fast_forward_key
+                    .as_ref()
+                    .is_some_and(|key| *key == keycode.name().to_lowercase()) =>
+                {
+                    let held = matches!(event, E::KeyDown { .. });
+                    if held == self.fast_forward_key_held {
+                        continue;
+                    }
+                    self.fast_forward_key_held = held;
+                    echo!(
+                        "{} {}, fast-forwarding {}.",
+                        keycode.name(),
+                        if held { "pressed" } else { "released" },
+                        if held { "started" } else { "stopped" }
+                    );
+                    continue;
+                }
+                E::KeyDown {
+                    keycode: Some(keycode),
+                    repeat,
+                    ..
+                }
+                | E::KeyUp {
+                    keycode: Some(keycode),
+                    repeat,
+                    ..
+                } if key_is_mapped(options, keycode) => {
+                    if repeat {
+                        continue;
+                    }
+                    let pressed = matches!(event, E::KeyDown { .. });
+                    let name = keycode.name().to_lowercase();
+                    if let Some(&tilt) = options.input_map.key_to_tilt.get(&name) {
+                        self.set_tilt_input(MappedInput::Key(keycode), tilt, pressed);
+                    }
+                    let Some(&(x, y)) = options.input_map.key_to_touch.get(&name) else {
+                        continue;
+                    };
+                    let coords = transform_input_coords(self, (x, y), true);
+                    let map = HashMap::from([(FingerId::KeyToTouch(keycode), coords)]);
+                    if pressed {
+                        Event::TouchesDown(map)
+                    } else {
+                        Event::TouchesUp(map)
+                    }
+                }
+                E::KeyDown {
+                    keycode: Some(keycode @ (Keycode::F9 | Keycode::F10)),
+                    repeat: false,
+                    ..
+                } if options.snapshot_keys => {
+                    // This isn't an event because snapshots can only be
+                    // handled at certain points (see
+                    // Environment::handle_snapshot_request).
+                    echo!("{} pressed, snapshot requested.", keycode.name());
+                    self.snapshot_request = Some(if keycode == Keycode::F9 {
+                        SnapshotRequest::Save
+                    } else {
+                        SnapshotRequest::Load
+                    });
+                    continue;
+                }
                 E::KeyDown {
                     keycode: Some(sdl2::keyboard::Keycode::F12),
                     ..
@@ -623,6 +920,10 @@ impl Window {
                     echo!("F12 pressed, EnterDebugger event queued.");
                     Event::EnterDebugger
                 }
+                E::KeyDown {
+                    keycode: Some(sdl2::keyboard::Keycode::F8),
+                    ..
+                } => Event::RotateDevice,
                 E::KeyDown {
                     keycode: Some(sdl2::keyboard::Keycode::Backspace),
                     ..
@@ -667,6 +968,27 @@ impl Window {
         }
     }
 
+    /// Stop running the app until the window is no longer in the background
+    /// (see `--pause-in-background`). Does nothing if the window isn't in the
+    /// background. [crate::Environment::run] checks this with
+    /// [Self::is_paused_in_background].
+    pub fn pause_while_in_background(&mut self) {
+        if self.app_state == AppState::Background {
+            echo!("App is in the background, pausing.");
+            self.paused_in_background = true;
+        }
+    }
+
+    pub fn is_paused_in_background(&self) -> bool {
+        self.paused_in_background
+    }
+
+    /// Returns [true] while the fast-forward key (see `--fast-forward-key=`) is
+    /// held. See [crate::Environment::update_fast_forward].
+    pub fn is_fast_forwarding(&self) -> bool {
+        self.fast_forward_key_held
+    }
+
     /// Pop an event from the queue (in FIFO order, except for high priority
     /// events)
     pub fn pop_event(&mut self) -> Option<Event> {
@@ -703,6 +1025,20 @@ impl Window {
         let controller = self.controllers.remove(idx);
         log!("Warning: Controller disconnected: {}", controller.name());
     }
+    /// Make the connected game controllers rumble, to simulate the device's
+    /// vibration motor. `intensity` ranges from 0.0 to 1.0.
+    ///
+    /// TODO: Also use the host device's own vibration motor where there is one
+    /// (e.g. Android phones).
+    pub fn vibrate(&mut self, intensity: f32, duration: Duration) {
+        let (low_frequency, high_frequency, duration_ms) = rumble_params(intensity, duration);
+        for controller in self.controllers.iter_mut() {
+            if let Err(e) = controller.set_rumble(low_frequency, high_frequency, duration_ms) {
+                // Not all controllers support rumble, that's fine.
+                log_dbg!("Couldn't rumble controller {}: {}", controller.name(), e);
+            }
+        }
+    }
     pub fn print_accelerometer_notice(&self) {
         log!("This app uses the accelerometer.");
         if !self.controllers.is_empty() {
@@ -719,10 +1055,43 @@ impl Window {
         log!("You can also hold right click and move the cursor to simulate the accelerometer.");
     }
 
-    /// Get the real or simulated accelerometer output.
+    /// Record that a button or key mapped to accelerometer tilt was pressed or
+    /// released.
+    fn set_tilt_input(&mut self, input: MappedInput, tilt: (f32, f32), pressed: bool) {
+        if pressed {
+            self.held_tilt_inputs.insert(input, tilt);
+        } else {
+            self.held_tilt_inputs.remove(&input);
+        }
+    }
+
+    /// Get the combined simulated analog stick position from the buttons and
+    /// keys mapped to accelerometer tilt, if any are held.
+    fn get_tilt_from_inputs(&self) -> Option<(f32, f32)> {
+        if self.held_tilt_inputs.is_empty() {
+            return None;
+        }
+        let (x, y) = self
+            .held_tilt_inputs
+            .values()
+            .fold((0.0, 0.0), |(x, y), &(dx, dy)| (x + dx, y + dy));
+        Some((x.clamp(-1.0, 1.0), y.clamp(-1.0, 1.0)))
+    }
+
+    /// Get the real or simulated accelerometer output, in units of g-force,
+    /// with the user's calibration options applied (see
+    /// [calibrate_acceleration]).
     /// See also [crate::frameworks::uikit::ui_accelerometer].
     pub fn get_acceleration(&self, options: &Options) -> (f32, f32, f32) {
-        if self.controllers.is_empty() {
+        calibrate_acceleration(self.get_raw_acceleration(options), options)
+    }
+
+    /// Get the real or simulated accelerometer output before calibration. The
+    /// simulated output already has the tilt range and offset options applied.
+    fn get_raw_acceleration(&self, options: &Options) -> (f32, f32, f32) {
+        let tilt_from_inputs = self.get_tilt_from_inputs();
+
+        if self.controllers.is_empty() && tilt_from_inputs.is_none() {
             if let Some(ref accelerometer) = self.accelerometer {
                 let data = accelerometer.get_data().unwrap();
                 let sdl2::sensor::SensorData::Accel(data) = data else {
@@ -747,6 +1116,9 @@ impl Window {
             self.virtual_accelerometer_last
                 .map(|(x, y, _right_click_hold)| (x, y))
                 .unwrap()
+        } else if let Some((x, y)) = tilt_from_inputs {
+            // Mapped buttons or keys are held.
+            (x, y)
         } else {
             // Get left analog stick input. The range is [-1, 1] on each axis.
             let (x, y, _) = self.get_controller_stick(options, true);
@@ -1050,6 +1422,30 @@ impl Window {
         // onto image so we can rotate later if necessary
     }
 
+    /// Returns [true] if the user has pressed the screenshot key since the last
+    /// call. The caller should then use [crate::gles::present::save_screenshot]
+    /// before swapping the window.
+    ///
+    /// If the screenshot was requested by `--screenshot-after=`, a
+    /// [Event::Quit] is also queued, so touchHLE will exit once it has been
+    /// taken. If the app doesn't present a frame within [SCREENSHOT_TIMEOUT],
+    /// so that this is never called, touchHLE exits with an error instead.
+    pub fn take_screenshot_request(&mut self) -> bool {
+        let requested = std::mem::take(&mut self.screenshot_requested);
+        if requested && std::mem::take(&mut self.exit_after_screenshot) {
+            echo!("Taking screenshot for --screenshot-after=, then exiting.");
+            self.event_queue.push_back(Event::Quit);
+            self.screenshot_watchdog = None;
+        }
+        requested
+    }
+
+    /// Returns [Some] if the user has pressed the key to save (F9) or load
+    /// (F10) a snapshot since the last call. See [crate::snapshot].
+    pub fn take_snapshot_request(&mut self) -> Option<SnapshotRequest> {
+        self.snapshot_request.take()
+    }
+
     /// Swap front-buffer and back-buffer so the result of OpenGL rendering is
     /// presented.
     pub fn swap_window(&self) {
@@ -1129,7 +1525,7 @@ impl Window {
     /// The aspect ratio, scale and orientation reflect the guest app's view of
     /// the world.
     pub fn size_unrotated_unscaled(&self) -> (u32, u32) {
-        size_for_orientation(DeviceOrientation::Portrait, NonZeroU32::new(1).unwrap())
+        size_for_orientation(DeviceOrientation::Portrait, ScaleHack::new(1.0).unwrap())
     }
 
     /// Get the size in pixels of the window without rotation but with the
@@ -1204,6 +1600,229 @@ impl Window {
     }
 }
 
+/// Convert a vibration intensity (0.0 to 1.0) and duration to the parameters
+/// of SDL2's rumble API: the strength of the low-frequency and high-frequency
+/// motors, and the duration in milliseconds.
+fn rumble_params(intensity: f32, duration: Duration) -> (u16, u16, u32) {
+    // NaN becomes 0 here.
+    let strength = (intensity.clamp(0.0, 1.0) * f32::from(u16::MAX)).round() as u16;
+    let duration_ms = duration.as_millis().min(u32::MAX.into()) as u32;
+    (strength, strength, duration_ms)
+}
+
+/// Access to a text clipboard. [Window] implements this for the host's
+/// clipboard. It's a trait so that code using it can be tested without SDL2.
+pub trait Clipboard {
+    /// Get the text on the clipboard, if there is any.
+    fn get_text(&self) -> Option<String>;
+    fn set_text(&self, text: &str) -> Result<(), String>;
+}
+impl Clipboard for Window {
+    fn get_text(&self) -> Option<String> {
+        let clipboard = self.video_ctx.clipboard();
+        if !clipboard.has_clipboard_text() {
+            return None;
+        }
+        clipboard.clipboard_text().ok()
+    }
+    fn set_text(&self, text: &str) -> Result<(), String> {
+        // SDL2 uses C strings.
+        if text.contains('\0') {
+            return Err("Text contains a null character".to_string());
+        }
+        self.video_ctx.clipboard().set_clipboard_text(text)
+    }
+}
+
+/// An open host audio capture device (microphone), see
+/// [Window::open_audio_capture]. The device is closed when this is dropped.
+pub struct AudioCapture {
+    device: sdl2::audio::AudioDevice<AudioCaptureCallback>,
+    receiver: Receiver<Vec<i16>>,
+}
+struct AudioCaptureCallback {
+    sender: Sender<Vec<i16>>,
+}
+impl sdl2::audio::AudioCallback for AudioCaptureCallback {
+    type Channel = i16;
+
+    fn callback(&mut self, input: &mut [i16]) {
+        // This runs on SDL's audio thread. Nobody is listening any more if the
+        // device is being closed.
+        let _ = self.sender.send(input.to_vec());
+    }
+}
+impl AudioCapture {
+    /// Get the sample rate and channel count of the captured audio, which may
+    /// differ from what was requested.
+    pub fn format(&self) -> (u32, u8) {
+        let spec = self.device.spec();
+        (spec.freq.try_into().unwrap(), spec.channels)
+    }
+    /// Get the interleaved samples captured since the last call.
+    pub fn read(&mut self) -> Vec<i16> {
+        self.receiver.try_iter().flatten().collect()
+    }
+    pub fn pause(&mut self) {
+        self.device.pause();
+    }
+    pub fn resume(&mut self) {
+        self.device.resume();
+    }
+}
+
+impl Window {
+    /// Open the host's default audio capture device, preferring the given
+    /// sample rate and channel count. Capture starts paused.
+    pub fn open_audio_capture(
+        &self,
+        sample_rate: u32,
+        channels: u8,
+    ) -> Result<AudioCapture, String> {
+        let audio_ctx = self.sdl_ctx.audio()?;
+        let desired = sdl2::audio::AudioSpecDesired {
+            freq: Some(sample_rate.try_into().unwrap()),
+            channels: Some(channels),
+            samples: None,
+        };
+        let (sender, receiver) = channel();
+        let device = audio_ctx.open_capture(None, &desired, |spec| {
+            log_dbg!(
+                "Opened audio capture device: {} Hz, {} channel(s)",
+                spec.freq,
+                spec.channels
+            );
+            AudioCaptureCallback { sender }
+        })?;
+        Ok(AudioCapture { device, receiver })
+    }
+}
+
+/// Apply the `--accelerometer-deadzone=`, `--accelerometer-gain=` and
+/// `--accelerometer-invert-*` options to an acceleration vector. Only the X and
+/// Y axes are affected, since those are what tilting the device changes.
+fn calibrate_acceleration((x, y, z): (f32, f32, f32), options: &Options) -> (f32, f32, f32) {
+    let calibrate_axis = |axis: f32, invert: bool| {
+        let deadzone = options.accelerometer_deadzone;
+        // Like analog stick dead zones, the remaining range is rescaled so
+        // that there's no sudden jump at the edge of the dead zone.
+        let axis = axis.signum() * (axis.abs().max(deadzone) - deadzone) / (1.0 - deadzone);
+        let axis = axis * options.accelerometer_gain;
+        if invert {
+            -axis
+        } else {
+            axis
+        }
+    };
+    (
+        calibrate_axis(x, options.accelerometer_invert_x),
+        calibrate_axis(y, options.accelerometer_invert_y),
+        z,
+    )
+}
+
 pub fn open_url(url: &str) -> Result<(), String> {
     sdl2::url::open_url(url).map_err(|e| e.to_string())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mouse_second_finger() {
+        let size = (320, 480);
+        let mut fingers = MouseFingers::default();
+
+        // Without the key, only the mouse touch is used.
+        let down = fingers.down((10.0, 20.0), false, size);
+        assert_eq!(down, HashMap::from([(FingerId::Mouse, (10.0, 20.0))]));
+        let up = fingers.up((10.0, 20.0), size);
+        assert_eq!(up, HashMap::from([(FingerId::Mouse, (10.0, 20.0))]));
+
+        // With the key, a mirrored second touch follows the mouse until the
+        // button is released, even if the key is released first.
+        let down = fingers.down((100.0, 200.0), true, size);
+        assert_eq!(
+            down,
+            HashMap::from([
+                (FingerId::Mouse, (100.0, 200.0)),
+                (FingerId::MouseSecondFinger, (220.0, 280.0)),
+            ])
+        );
+        let moved = fingers.moved((110.0, 190.0), size);
+        assert_eq!(
+            moved,
+            HashMap::from([
+                (FingerId::Mouse, (110.0, 190.0)),
+                (FingerId::MouseSecondFinger, (210.0, 290.0)),
+            ])
+        );
+        let up = fingers.up((120.0, 180.0), size);
+        assert_eq!(up.len(), 2);
+        assert_eq!(up[&FingerId::MouseSecondFinger], (200.0, 300.0));
+
+        // The IDs are reused by the next press.
+        let down = fingers.down((5.0, 5.0), false, size);
+        assert_eq!(down, HashMap::from([(FingerId::Mouse, (5.0, 5.0))]));
+    }
+
+    #[test]
+    fn acceleration_calibration() {
+        let sample = (0.5, -0.04, -0.8);
+
+        let mut options = Options::default();
+        assert_eq!(calibrate_acceleration(sample, &options), sample);
+
+        options.accelerometer_deadzone = 0.2;
+        let (x, y, z) = calibrate_acceleration(sample, &options);
+        assert!((x - 0.375).abs() < 1e-6);
+        assert_eq!((y, z), (0.0, -0.8));
+
+        options.accelerometer_gain = 2.0;
+        options.accelerometer_invert_x = true;
+        let (x, y, z) = calibrate_acceleration(sample, &options);
+        assert!((x + 0.75).abs() < 1e-6);
+        assert_eq!((y, z), (0.0, -0.8));
+
+        options.accelerometer_deadzone = 0.0;
+        options.accelerometer_gain = 0.5;
+        options.accelerometer_invert_x = false;
+        options.accelerometer_invert_y = true;
+        let (x, y, z) = calibrate_acceleration(sample, &options);
+        assert_eq!((x, y, z), (0.25, 0.02, -0.8));
+    }
+
+    #[test]
+    fn rumble_params_mapping() {
+        assert_eq!(
+            rumble_params(1.0, Duration::from_millis(400)),
+            (u16::MAX, u16::MAX, 400)
+        );
+        assert_eq!(
+            rumble_params(0.5, Duration::from_secs(2)),
+            (32768, 32768, 2000)
+        );
+        assert_eq!(rumble_params(0.0, Duration::from_micros(1500)), (0, 0, 1));
+        // Out-of-range values are clamped
+        assert_eq!(rumble_params(-1.0, Duration::ZERO), (0, 0, 0));
+        assert_eq!(rumble_params(f32::NAN, Duration::ZERO), (0, 0, 0));
+        assert_eq!(
+            rumble_params(2.0, Duration::MAX),
+            (u16::MAX, u16::MAX, u32::MAX)
+        );
+    }
+
+    #[test]
+    fn app_state_transitions() {
+        use AppState::*;
+        let after = app_state_after_window_event;
+        assert_eq!(after(Active, &WindowEvent::FocusLost), Inactive);
+        assert_eq!(after(Background, &WindowEvent::FocusLost), Background);
+        assert_eq!(after(Active, &WindowEvent::Minimized), Background);
+        assert_eq!(after(Background, &WindowEvent::Restored), Inactive);
+        assert_eq!(after(Inactive, &WindowEvent::Restored), Inactive);
+        assert_eq!(after(Background, &WindowEvent::FocusGained), Active);
+        assert_eq!(after(Active, &WindowEvent::Moved(0, 0)), Active);
+    }
+}
@@ -27,6 +27,12 @@ typedef int wchar_t;
 // <errno.h>
 int *__error(void);
 #define errno (*__error())
+#define ENOENT 2
+#define ENOMEM 12
+#define ENOTDIR 20
+#define EINVAL 22
+#define ERANGE 34
+#define EAGAIN 35
 
 // <stdarg.h>
 typedef __builtin_va_list va_list;
@@ -38,6 +44,17 @@ typedef __builtin_va_list va_list;
 typedef struct FILE FILE;
 FILE *fopen(const char *, const char *);
 int fclose(FILE *);
+size_t fread(void *, size_t, size_t, FILE *);
+int fseek(FILE *, long, int);
+long ftell(FILE *);
+void rewind(FILE *);
+int feof(FILE *);
+int ferror(FILE *);
+int fgetc(FILE *);
+int remove(const char *);
+#define EOF (-1)
+#define SEEK_SET 0
+#define SEEK_END 2
 int sscanf(const char *, const char *, ...);
 int printf(const char *, ...);
 int vsnprintf(char *, size_t, const char *, va_list);
@@ -48,22 +65,51 @@ size_t fwrite(const void *, size_t, size_t, FILE *);
 #define EXIT_SUCCESS 0
 #define EXIT_FAILURE 1
 void exit(int);
+int atexit(void (*)(void));
 void free(void *);
 void *malloc(size_t);
 void qsort(void *, size_t, size_t, int (*)(const void *, const void *));
+void qsort_r(void *, size_t, size_t, void *,
+             int (*)(void *, const void *, const void *));
+void qsort_b(void *, size_t, size_t, int (^)(const void *, const void *));
+void *bsearch_b(const void *, const void *, size_t, size_t,
+                int (^)(const void *, const void *));
 void *realloc(void *, size_t);
+int posix_memalign(void **, size_t, size_t);
+void *valloc(size_t);
 double atof(const char *);
 float strtof(const char *, char **);
+double strtod(const char *, char **);
 long strtol(const char *, char **, int);
 unsigned long strtoul(const char *, char **, int);
 char *realpath(const char *, char *);
+char *getenv(const char *);
 size_t mbstowcs(wchar_t *, const char *, size_t);
 size_t wcstombs(char *, const wchar_t *, size_t);
+typedef struct {
+  int quot;
+  int rem;
+} div_t;
+typedef struct {
+  long quot;
+  long rem;
+} ldiv_t;
+int abs(int);
+long labs(long);
+long long llabs(long long);
+div_t div(int, int);
+ldiv_t ldiv(long, long);
+
+// <cxxabi.h>
+int __cxa_atexit(void (*)(void *), void *, void *);
+void __cxa_finalize(void *);
 
 // <string.h>
 void *memset(void *, int, size_t);
 int memcmp(const void *, const void *, size_t);
 void *memmove(void *, const void *, size_t);
+void *memccpy(void *, const void *, int, size_t);
+void *memmem(const void *, size_t, const void *, size_t);
 int strcmp(const char *, const char *);
 char *strncpy(char *, const char *, size_t);
 char *strncat(char *, const char *, size_t);
@@ -85,12 +131,52 @@ int usleep(useconds_t);
 #define O_RDONLY 0x00000000
 #define O_WRONLY 0x00000001
 #define O_RDWR 0x00000002
+#define O_NONBLOCK 0x00000004
 #define O_CREAT 0x00000200
+#define F_GETFL 3
+#define F_SETFL 4
 
 int open(const char *, int, ...);
 int close(int);
+int fcntl(int, int, ...);
+
+// <sys/mman.h>
+#define PROT_READ 0x01
+#define PROT_WRITE 0x02
+#define MAP_SHARED 0x0001
+#define MAP_PRIVATE 0x0002
+#define MAP_ANON 0x1000
+#define MAP_FAILED ((void *)-1)
+typedef long long off_t;
+void *mmap(void *, size_t, int, int, int, off_t);
+int munmap(void *, size_t);
+
+// <signal.h>
+#define SIGKILL 9
+#define SIGPIPE 13
+#define SIG_DFL ((void (*)(int))0)
+#define SIG_IGN ((void (*)(int))1)
+#define SIG_ERR ((void (*)(int)) - 1)
+typedef unsigned int sigset_t;
+struct sigaction {
+  void (*sa_handler)(int);
+  sigset_t sa_mask;
+  int sa_flags;
+};
+void (*signal(int, void (*)(int)))(int);
+int sigaction(int, const struct sigaction *, struct sigaction *);
 
 // <pthread.h>
+struct __darwin_pthread_handler_rec {
+  void (*__routine)(void *);
+  void *__arg;
+  struct __darwin_pthread_handler_rec *__next;
+};
+struct opaque_pthread_t {
+  long __sig;
+  struct __darwin_pthread_handler_rec *__cleanup_stack;
+  char __opaque[596];
+};
 typedef struct opaque_pthread_t opaque_pthread_t;
 typedef struct opaque_pthread_t *__pthread_t;
 typedef __pthread_t pthread_t;
@@ -120,6 +206,28 @@ typedef __pthread_condattr_t pthread_condattr_t;
 
 int pthread_create(pthread_t *, const pthread_attr_t *, void *(*)(void *),
                    void *);
+pthread_t pthread_self(void);
+int pthread_join(pthread_t, void **);
+int pthread_cancel(pthread_t);
+typedef unsigned long pthread_key_t;
+int pthread_key_create(pthread_key_t *, void (*)(void *));
+int pthread_key_delete(pthread_key_t);
+void *pthread_getspecific(pthread_key_t);
+int pthread_setspecific(pthread_key_t, const void *);
+#define PTHREAD_CANCELED ((void *)1)
+#define pthread_cleanup_push(func, val)                                        \
+  {                                                                            \
+    struct __darwin_pthread_handler_rec __handler;                             \
+    pthread_t __self = pthread_self();                                         \
+    __handler.__routine = func;                                                \
+    __handler.__arg = val;                                                     \
+    __handler.__next = __self->__cleanup_stack;                                \
+    __self->__cleanup_stack = &__handler;
+#define pthread_cleanup_pop(execute)                                           \
+  __self->__cleanup_stack = __handler.__next;                                  \
+  if (execute)                                                                 \
+    (__handler.__routine)(__handler.__arg);                                    \
+  }
 
 int pthread_cond_init(pthread_cond_t *, const pthread_condattr_t *);
 int pthread_cond_signal(pthread_cond_t *);
@@ -166,6 +274,116 @@ int scandir(const char *, struct dirent ***, int (*)(struct dirent *),
 // <wchar.h>
 int swscanf(const wchar_t *, const wchar_t *, ...);
 
+// <netdb.h>
+struct hostent {
+  char *h_name;
+  char **h_aliases;
+  int h_addrtype;
+  int h_length;
+  char **h_addr_list;
+};
+struct sockaddr;
+struct addrinfo {
+  int ai_flags;
+  int ai_family;
+  int ai_socktype;
+  int ai_protocol;
+  unsigned int ai_addrlen;
+  char *ai_canonname;
+  struct sockaddr *ai_addr;
+  struct addrinfo *ai_next;
+};
+#define AF_INET 2
+#define SOCK_STREAM 1
+#define AI_NUMERICHOST 0x4
+int getaddrinfo(const char *, const char *, const struct addrinfo *,
+                struct addrinfo **);
+void freeaddrinfo(struct addrinfo *);
+struct hostent *gethostbyname(const char *);
+
+// <sys/socket.h>
+typedef unsigned int socklen_t;
+typedef long ssize_t;
+int socket(int, int, int);
+int connect(int, const struct sockaddr *, socklen_t);
+ssize_t send(int, const void *, size_t, int);
+ssize_t recv(int, void *, size_t, int);
+
+// <netinet/in.h>
+struct in_addr {
+  unsigned int s_addr;
+};
+struct sockaddr_in {
+  unsigned char sin_len;
+  unsigned char sin_family;
+  unsigned short sin_port;
+  struct in_addr sin_addr;
+  char sin_zero[8];
+};
+
+// <time.h>, <sys/time.h> and <mach/mach_time.h>
+struct timespec {
+  long tv_sec;
+  long tv_nsec;
+};
+struct timeval {
+  long tv_sec;
+  int tv_usec;
+};
+#define CLOCK_REALTIME 0
+#define CLOCK_MONOTONIC 6
+int clock_gettime(int, struct timespec *);
+int gettimeofday(struct timeval *, void *);
+unsigned long long mach_absolute_time(void);
+struct mach_timebase_info {
+  unsigned int numer;
+  unsigned int denom;
+};
+int mach_timebase_info(struct mach_timebase_info *);
+
+// <sys/sysctl.h>
+#define CTL_HW 6
+#define HW_MACHINE 1
+#define HW_NCPU 3
+int sysctl(int *, unsigned int, void *, size_t *, void *, size_t);
+int sysctlbyname(const char *, void *, size_t *, void *, size_t);
+
+// <zlib.h>
+typedef struct {
+  const unsigned char *next_in;
+  unsigned int avail_in;
+  unsigned long total_in;
+  unsigned char *next_out;
+  unsigned int avail_out;
+  unsigned long total_out;
+  const char *msg;
+  void *state;
+  void *zalloc;
+  void *zfree;
+  void *opaque;
+  int data_type;
+  unsigned long adler;
+  unsigned long reserved;
+} z_stream;
+#define Z_OK 0
+#define Z_STREAM_END 1
+#define Z_STREAM_ERROR (-2)
+#define Z_DATA_ERROR (-3)
+#define Z_NO_FLUSH 0
+#define Z_FINISH 4
+const char *zlibVersion(void);
+int compress(unsigned char *, unsigned long *, const unsigned char *,
+             unsigned long);
+int uncompress(unsigned char *, unsigned long *, const unsigned char *,
+               unsigned long);
+int deflateInit_(z_stream *, int, const char *, int);
+int deflate(z_stream *, int);
+int deflateEnd(z_stream *);
+int inflateInit_(z_stream *, const char *, int);
+int inflateInit2_(z_stream *, int, const char *, int);
+int inflate(z_stream *, int);
+int inflateEnd(z_stream *);
+
 // `CFBase.h`
 
 typedef const void *CFTypeRef;
@@ -218,6 +436,160 @@ CFIndex CFDictionaryGetCount(CFDictionaryRef dict);
 void CFDictionaryGetKeysAndValues(CFDictionaryRef dict, const void **keys,
                                   const void **values);
 
+// `CFRunLoop.h`
+
+typedef double CFTimeInterval;
+typedef CFTimeInterval CFAbsoluteTime;
+typedef struct __CFRunLoop *CFRunLoopRef;
+typedef struct __CFRunLoopSource *CFRunLoopSourceRef;
+typedef struct __CFRunLoopTimer *CFRunLoopTimerRef;
+typedef CFStringRef CFRunLoopMode;
+typedef struct {
+  CFIndex version;
+  void *info;
+  const void *(*retain)(const void *);
+  void (*release)(const void *);
+  CFStringRef (*copyDescription)(const void *);
+} CFRunLoopTimerContext;
+typedef struct {
+  CFIndex version;
+  void *info;
+  const void *(*retain)(const void *);
+  void (*release)(const void *);
+  CFStringRef (*copyDescription)(const void *);
+  unsigned char (*equal)(const void *, const void *);
+  unsigned long (*hash)(const void *);
+  void (*schedule)(void *, CFRunLoopRef, CFRunLoopMode);
+  void (*cancel)(void *, CFRunLoopRef, CFRunLoopMode);
+  void (*perform)(void *);
+} CFRunLoopSourceContext;
+
+extern const CFRunLoopMode kCFRunLoopDefaultMode;
+
+CFAbsoluteTime CFAbsoluteTimeGetCurrent(void);
+CFRunLoopRef CFRunLoopGetCurrent(void);
+void CFRunLoopRun(void);
+int CFRunLoopRunInMode(CFRunLoopMode, CFTimeInterval, unsigned char);
+void CFRunLoopStop(CFRunLoopRef);
+CFRunLoopTimerRef CFRunLoopTimerCreate(CFAllocatorRef, CFAbsoluteTime,
+                                       CFTimeInterval, CFOptionFlags, CFIndex,
+                                       void (*)(CFRunLoopTimerRef, void *),
+                                       CFRunLoopTimerContext *);
+void CFRunLoopAddTimer(CFRunLoopRef, CFRunLoopTimerRef, CFRunLoopMode);
+void CFRunLoopTimerInvalidate(CFRunLoopTimerRef);
+CFRunLoopSourceRef CFRunLoopSourceCreate(CFAllocatorRef, CFIndex,
+                                         CFRunLoopSourceContext *);
+void CFRunLoopSourceSignal(CFRunLoopSourceRef);
+void CFRunLoopSourceInvalidate(CFRunLoopSourceRef);
+void CFRunLoopAddSource(CFRunLoopRef, CFRunLoopSourceRef, CFRunLoopMode);
+
+// <setjmp.h>
+typedef int jmp_buf[10 + 16 + 2];
+int setjmp(jmp_buf);
+void longjmp(jmp_buf, int) __attribute__((noreturn));
+
+// <dispatch/dispatch.h>
+
+typedef struct dispatch_queue_s *dispatch_queue_t;
+typedef long dispatch_once_t;
+dispatch_queue_t dispatch_get_global_queue(long, unsigned long);
+void dispatch_async(dispatch_queue_t, void (^)(void));
+void dispatch_sync(dispatch_queue_t, void (^)(void));
+void dispatch_once(dispatch_once_t *, void (^)(void));
+void dispatch_once_f(dispatch_once_t *, void *, void (*)(void *));
+
+// <objc/runtime.h>, <objc/message.h> and `NSObjCRuntime.h`
+
+typedef struct objc_object *id;
+typedef struct objc_selector *SEL;
+typedef struct objc_class *Class;
+
+SEL sel_registerName(const char *);
+// Must be cast to the right function pointer type before calling.
+void objc_msgSend(void);
+Class NSClassFromString(id);
+
+// <OpenGLES/ES1/gl.h> and <OpenGLES/ES1/glext.h>
+
+typedef unsigned int GLenum;
+typedef unsigned char GLboolean;
+typedef unsigned int GLbitfield;
+typedef int GLint;
+typedef int GLsizei;
+typedef unsigned int GLuint;
+typedef float GLfloat;
+typedef unsigned char GLubyte;
+#define GL_NO_ERROR 0
+#define GL_ZERO 0
+#define GL_ONE 1
+#define GL_TRUE 1
+#define GL_POINTS 0x0000
+#define GL_TRIANGLE_STRIP 0x0005
+#define GL_SRC_ALPHA 0x0302
+#define GL_COLOR_BUFFER_BIT 0x4000
+#define GL_FRONT 0x0404
+#define GL_FRONT_AND_BACK 0x0408
+#define GL_BLEND 0x0BE2
+#define GL_TEXTURE_2D 0x0DE1
+#define GL_UNSIGNED_BYTE 0x1401
+#define GL_FLOAT 0x1406
+#define GL_AMBIENT 0x1200
+#define GL_DIFFUSE 0x1201
+#define GL_AMBIENT_AND_DIFFUSE 0x1602
+#define GL_RGBA 0x1908
+#define GL_REPLACE 0x1E01
+#define GL_TEXTURE_ENV_MODE 0x2200
+#define GL_TEXTURE_ENV 0x2300
+#define GL_NEAREST 0x2600
+#define GL_TEXTURE_MAG_FILTER 0x2800
+#define GL_TEXTURE_MIN_FILTER 0x2801
+#define GL_TEXTURE_WRAP_S 0x2802
+#define GL_CLAMP_TO_EDGE 0x812F
+#define GL_GENERATE_MIPMAP 0x8191
+#define GL_VERTEX_ARRAY 0x8074
+#define GL_POINT_SPRITE_OES 0x8861
+#define GL_COORD_REPLACE_OES 0x8862
+#define GL_FRAMEBUFFER_OES 0x8D40
+#define GL_RENDERBUFFER_OES 0x8D41
+#define GL_RGBA8_OES 0x8058
+#define GL_COLOR_ATTACHMENT0_OES 0x8CE0
+#define GL_FRAMEBUFFER_COMPLETE_OES 0x8CD5
+GLenum glGetError(void);
+void glEnable(GLenum);
+void glEnableClientState(GLenum);
+void glBlendFuncSeparateOES(GLenum, GLenum, GLenum, GLenum);
+void glClearColor(GLfloat, GLfloat, GLfloat, GLfloat);
+void glClear(GLbitfield);
+void glColor4f(GLfloat, GLfloat, GLfloat, GLfloat);
+void glVertexPointer(GLint, GLenum, GLsizei, const void *);
+void glDrawArrays(GLenum, GLint, GLsizei);
+void glViewport(GLint, GLint, GLsizei, GLsizei);
+void glReadPixels(GLint, GLint, GLsizei, GLsizei, GLenum, GLenum, void *);
+void glGenTextures(GLsizei, GLuint *);
+void glDeleteTextures(GLsizei, const GLuint *);
+void glBindTexture(GLenum, GLuint);
+void glTexParameteri(GLenum, GLenum, GLint);
+void glGetTexParameteriv(GLenum, GLenum, GLint *);
+void glGetTexParameterfv(GLenum, GLenum, GLfloat *);
+void glTexImage2D(GLenum, GLint, GLint, GLsizei, GLsizei, GLint, GLenum,
+                  GLenum, const void *);
+void glTexEnvi(GLenum, GLenum, GLint);
+void glPointSize(GLfloat);
+void glMaterialfv(GLenum, GLenum, const GLfloat *);
+void glGetMaterialfv(GLenum, GLenum, GLfloat *);
+void glGenFramebuffersOES(GLsizei, GLuint *);
+void glDeleteFramebuffersOES(GLsizei, const GLuint *);
+void glBindFramebufferOES(GLenum, GLuint);
+void glGenRenderbuffersOES(GLsizei, GLuint *);
+void glDeleteRenderbuffersOES(GLsizei, const GLuint *);
+void glBindRenderbufferOES(GLenum, GLuint);
+void glRenderbufferStorageOES(GLenum, GLenum, GLsizei, GLsizei);
+void glFramebufferRenderbufferOES(GLenum, GLenum, GLenum, GLuint);
+GLenum glCheckFramebufferStatusOES(GLenum);
+
+// `EAGL.h`
+#define kEAGLRenderingAPIOpenGLES1 1
+
 // === Main code ===
 
 int int_compar(const void *a, const void *b) { return *(int *)a - *(int *)b; }
@@ -248,6 +620,44 @@ int test_qsort() {
   return 0;
 }
 
+int int_compar_r(void *descending, const void *a, const void *b) {
+  int res = *(int *)a - *(int *)b;
+  return *(int *)descending ? -res : res;
+}
+
+int test_qsort_r() {
+  int descending = 0;
+  int arr[] = {3, -7, 12, 0, 5};
+  qsort_r(arr, 5, sizeof(int), &descending, &int_compar_r);
+  if (memcmp(arr, (int[]){-7, 0, 3, 5, 12}, sizeof(arr)) != 0)
+    return -1;
+  descending = 1;
+  qsort_r(arr, 5, sizeof(int), &descending, &int_compar_r);
+  if (memcmp(arr, (int[]){12, 5, 3, 0, -7}, sizeof(arr)) != 0)
+    return -2;
+  return 0;
+}
+
+int test_qsort_b_bsearch_b() {
+  int descending = 1;
+  int (^compar)(const void *, const void *) = ^(const void *a, const void *b) {
+    int res = *(int *)a - *(int *)b;
+    return descending ? -res : res;
+  };
+  int arr[] = {3, -7, 12, 0, 5};
+  qsort_b(arr, 5, sizeof(int), compar);
+  if (memcmp(arr, (int[]){12, 5, 3, 0, -7}, sizeof(arr)) != 0)
+    return -1;
+  int key = 0;
+  int *found = bsearch_b(&key, arr, 5, sizeof(int), compar);
+  if (found != &arr[3])
+    return -2;
+  key = 4;
+  if (bsearch_b(&key, arr, 5, sizeof(int), compar) != NULL)
+    return -3;
+  return 0;
+}
+
 char *str_format(const char *format, ...) {
   char *str = malloc(256);
   if (str == NULL) {
@@ -380,6 +790,20 @@ int test_vsnprintf() {
                    4294967296);
   res += !!strcmp(str, "10 100 4294967296 10 100 4294967296");
   free(str);
+  // Test width and precision arguments
+  str = str_format("%*d|%.*f", 5, 42, 2, 3.14159);
+  res += !!strcmp(str, "   42|3.14");
+  free(str);
+  // Test positional arguments
+  str = str_format("%2$s %1$s", "world", "hello");
+  res += !!strcmp(str, "hello world");
+  free(str);
+  str = str_format("%3$.*1$f %2$lld %3$.1f", 2, 4294967296, 1.5);
+  res += !!strcmp(str, "1.50 4294967296 1.5");
+  free(str);
+  str = str_format("%1$*2$d|%1$d", 7, 4);
+  res += !!strcmp(str, "   7|7");
+  free(str);
 
   return res;
 }
@@ -459,6 +883,83 @@ int test_realloc() {
   return res == 0 ? 0 : -1;
 }
 
+int test_memmove_overlap() {
+  char buf[11] = "0123456789";
+  // Forward: destination after source
+  memmove(buf + 2, buf, 6);
+  if (memcmp(buf, "0101234589", 10))
+    return -1;
+  // Backward: destination before source
+  memmove(buf, "0123456789", 10);
+  memmove(buf, buf + 3, 7);
+  if (memcmp(buf, "3456789789", 10))
+    return -2;
+  return 0;
+}
+
+int test_memccpy_memmem() {
+  char dst[8];
+  memset(dst, 'x', 8);
+  char *end = memccpy(dst, "ab,cd", ',', 5);
+  if (end != dst + 3 || memcmp(dst, "ab,xx", 5))
+    return -1;
+  if (memccpy(dst, "abcd", ',', 4) != NULL || memcmp(dst, "abcdx", 5))
+    return -2;
+
+  const char *haystack = "key=value;key2=value2";
+  if (memmem(haystack, 21, "key2", 4) != haystack + 10)
+    return -3;
+  if (memmem(haystack, 21, "value3", 6) != NULL)
+    return -4;
+  // The needle must be entirely within the given length.
+  if (memmem(haystack, 12, "key2", 4) != NULL)
+    return -5;
+  if (memmem(haystack, 21, "", 0) != haystack)
+    return -6;
+  return 0;
+}
+
+void cf_run_loop_timer_callback(CFRunLoopTimerRef timer, void *info) {
+  (*(int *)info)++;
+  CFRunLoopStop(CFRunLoopGetCurrent());
+}
+
+void cf_run_loop_source_perform(void *info) { (*(int *)info)++; }
+
+int test_CFRunLoop() {
+  // CFRunLoopRun() keeps running until the timer stops it.
+  int timer_count = 0;
+  CFRunLoopTimerContext timer_context = {0, &timer_count, NULL, NULL, NULL};
+  CFRunLoopTimerRef timer = CFRunLoopTimerCreate(
+      NULL, CFAbsoluteTimeGetCurrent() + 0.01, 0.01, 0, 0,
+      &cf_run_loop_timer_callback, &timer_context);
+  CFRunLoopAddTimer(CFRunLoopGetCurrent(), timer, kCFRunLoopDefaultMode);
+  CFRunLoopRun();
+  CFRunLoopTimerInvalidate(timer);
+  CFRelease(timer);
+  if (timer_count != 1)
+    return -1;
+
+  // A signalled source is performed, and can make the run loop return early.
+  int source_count = 0;
+  CFRunLoopSourceContext source_context = {0};
+  source_context.info = &source_count;
+  source_context.perform = &cf_run_loop_source_perform;
+  CFRunLoopSourceRef source = CFRunLoopSourceCreate(NULL, 0, &source_context);
+  CFRunLoopAddSource(CFRunLoopGetCurrent(), source, kCFRunLoopDefaultMode);
+  CFRunLoopSourceSignal(source);
+  int res = CFRunLoopRunInMode(kCFRunLoopDefaultMode, 1.0, 1);
+  if (res != 4 || source_count != 1) // kCFRunLoopRunHandledSource
+    return -2;
+  // Nothing is signalled now, so this times out.
+  res = CFRunLoopRunInMode(kCFRunLoopDefaultMode, 0.01, 1);
+  if (res != 3 || source_count != 1) // kCFRunLoopRunTimedOut
+    return -3;
+  CFRunLoopSourceInvalidate(source);
+  CFRelease(source);
+  return 0;
+}
+
 int test_atof() {
   if (atof("1") != 1)
     return -1;
@@ -601,12 +1102,113 @@ int test_strtof() {
   return 0;
 }
 
+int test_strtod_special() {
+  char *text = "inf";
+  char *endptr;
+  double d = strtod(text, &endptr);
+  if (d != 1.0 / 0.0 || endptr != text + 3)
+    return -1;
+  text = "  -Infinity and beyond";
+  d = strtod(text, &endptr);
+  if (d != -1.0 / 0.0 || endptr != text + 11)
+    return -2;
+  text = "+INFINITE";
+  d = strtod(text, &endptr);
+  if (d != 1.0 / 0.0 || endptr != text + 4)
+    return -3;
+  text = "nan";
+  d = strtod(text, &endptr);
+  if (d == d || endptr != text + 3)
+    return -4;
+  text = "-NaN(123)x";
+  d = strtod(text, &endptr);
+  if (d == d || endptr != text + 9)
+    return -5;
+  text = "nan(";
+  d = strtod(text, &endptr);
+  if (d == d || endptr != text + 3)
+    return -6;
+  text = "0x1.8p3";
+  d = strtod(text, &endptr);
+  if (d != 12.0 || endptr != text + 7)
+    return -7;
+  text = "-0X10";
+  d = strtod(text, &endptr);
+  if (d != -16.0 || endptr != text + 5)
+    return -8;
+  text = "0x.8P-1";
+  d = strtod(text, &endptr);
+  if (d != 0.25 || endptr != text + 7)
+    return -9;
+  text = "0x1p";
+  d = strtod(text, &endptr);
+  if (d != 1.0 || endptr != text + 3)
+    return -10;
+  text = "0xg";
+  d = strtod(text, &endptr);
+  if (d != 0.0 || endptr != text + 1)
+    return -11;
+  return 0;
+}
+
 int test_strtoul() {
   char *text = "0xcccccccc";
   char *endptr;
   if (strtoul(text, &endptr, 16) != 3435973836 || endptr != text + 10) {
     return -1;
   }
+  text = "1234 rest";
+  if (strtoul(text, &endptr, 10) != 1234 || endptr != text + 4) {
+    return -2;
+  }
+  text = "0755";
+  if (strtoul(text, &endptr, 8) != 493 || endptr != text + 4) {
+    return -3;
+  }
+  text = "0x1F";
+  if (strtoul(text, &endptr, 0) != 31 || endptr != text + 4) {
+    return -4;
+  }
+  text = "  010";
+  if (strtoul(text, &endptr, 0) != 8 || endptr != text + 5) {
+    return -5;
+  }
+  text = "99999999999";
+  if (strtoul(text, &endptr, 10) != 4294967295 || endptr != text + 11) {
+    return -6;
+  }
+  text = "-1";
+  if (strtoul(text, &endptr, 10) != 4294967295 || endptr != text + 2) {
+    return -7;
+  }
+  text = "junk";
+  if (strtoul(text, &endptr, 10) != 0 || endptr != text) {
+    return -8;
+  }
+  // A "0x" without hex digits after it isn't a prefix, so just "0" is parsed.
+  errno = 0;
+  text = "0";
+  if (strtoul(text, &endptr, 16) != 0 || endptr != text + 1 || errno != 0) {
+    return -9;
+  }
+  text = "0x";
+  if (strtoul(text, &endptr, 16) != 0 || endptr != text + 1 || errno != 0) {
+    return -10;
+  }
+  text = "0xg";
+  if (strtoul(text, &endptr, 0) != 0 || endptr != text + 1 || errno != 0) {
+    return -11;
+  }
+  // A sign alone isn't a number.
+  text = "+";
+  if (strtoul(text, &endptr, 8) != 0 || endptr != text || errno != EINVAL) {
+    return -12;
+  }
+  errno = 0;
+  text = "12";
+  if (strtoul(text, &endptr, 1) != 0 || endptr != text || errno != EINVAL) {
+    return -13;
+  }
   return 0;
 }
 
@@ -658,6 +1260,53 @@ int test_strtol() {
       return -(count + 2 + i + 1);
     }
   }
+  errno = 0;
+  p = "99999999999999";
+  l = strtol(p, NULL, 10);
+  if (l != MAX_LONG || errno != ERANGE) {
+    return -(count + count2 + 3);
+  }
+  errno = 0;
+  p = "-99999999999999";
+  l = strtol(p, NULL, 10);
+  if (l != -MAX_LONG - 1 || errno != ERANGE) {
+    return -(count + count2 + 4);
+  }
+  errno = 0;
+  p = "junk";
+  l = strtol(p, NULL, 10);
+  if (l != 0 || errno != EINVAL) {
+    return -(count + count2 + 5);
+  }
+  errno = 0;
+  if (strtoul("99999999999", NULL, 10) != 4294967295 || errno != ERANGE) {
+    return -(count + count2 + 6);
+  }
+  errno = 0;
+  return 0;
+}
+
+int test_div() {
+  if (abs(-5) != 5 || abs(5) != 5 || labs(-7L) != 7L ||
+      llabs(-5000000000LL) != 5000000000LL)
+    return -1;
+  div_t d = div(7, 2);
+  if (d.quot != 3 || d.rem != 1)
+    return -2;
+  // The quotient is truncated towards zero and the remainder takes the sign
+  // of the dividend.
+  d = div(-7, 2);
+  if (d.quot != -3 || d.rem != -1)
+    return -3;
+  d = div(7, -2);
+  if (d.quot != -3 || d.rem != 1)
+    return -4;
+  d = div(-7, -2);
+  if (d.quot != 3 || d.rem != -1)
+    return -5;
+  ldiv_t ld = ldiv(-100000L, 7L);
+  if (ld.quot != -14285L || ld.rem != -5L)
+    return -6;
   return 0;
 }
 
@@ -815,6 +1464,84 @@ int test_cond_var() {
   return done == 1 ? 0 : -1;
 }
 
+int cancel_cleanup_arg = 0;
+
+void cancel_cleanup(void *arg) { cancel_cleanup_arg = (int)arg; }
+
+void *cancel_thread_func(void *arg) {
+  pthread_cleanup_push(cancel_cleanup, (void *)42);
+  while (1) {
+    usleep(1000);
+  }
+  pthread_cleanup_pop(0);
+  return NULL;
+}
+
+int test_pthread_cancel() {
+  pthread_t thread;
+  void *retval = NULL;
+
+  pthread_create(&thread, NULL, cancel_thread_func, NULL);
+  // Let the thread start and go to sleep
+  usleep(5000);
+
+  if (pthread_cancel(thread) != 0) {
+    return -1;
+  }
+  if (pthread_join(thread, &retval) != 0) {
+    return -2;
+  }
+  if (retval != PTHREAD_CANCELED) {
+    return -3;
+  }
+  return cancel_cleanup_arg == 42 ? 0 : -4;
+}
+
+pthread_key_t tls_key;
+int tls_destructor_arg = 0;
+
+void tls_destructor(void *value) { tls_destructor_arg = (int)value; }
+
+void *tls_thread_func(void *arg) {
+  // Values set by other threads aren't visible
+  if (pthread_getspecific(tls_key) != NULL) {
+    return (void *)-1;
+  }
+  pthread_setspecific(tls_key, (void *)2);
+  if (pthread_getspecific(tls_key) != (void *)2) {
+    return (void *)-1;
+  }
+  return NULL;
+}
+
+int test_pthread_key() {
+  pthread_t thread;
+  void *retval = (void *)-1;
+
+  if (pthread_key_create(&tls_key, tls_destructor) != 0) {
+    return -1;
+  }
+  pthread_setspecific(tls_key, (void *)1);
+
+  pthread_create(&thread, NULL, tls_thread_func, NULL);
+  pthread_join(thread, &retval);
+  if (retval != NULL) {
+    return -2;
+  }
+  // The other thread's value doesn't replace this thread's
+  if (pthread_getspecific(tls_key) != (void *)1) {
+    return -3;
+  }
+  // The destructor is called with the other thread's value when it exits
+  if (tls_destructor_arg != 2) {
+    return -4;
+  }
+  if (pthread_key_delete(tls_key) != 0) {
+    return -5;
+  }
+  return 0;
+}
+
 int test_strncpy() {
   char *src = "test\0abcd";
   char dst[10];
@@ -1035,16 +1762,45 @@ int test_realpath() {
   if (!res || strcmp(res, "/usr") != 0)
     return -2;
   // relative path
-  res = realpath("TestApp", buf);
   char *cwd = getcwd(NULL, 0);
-  if (!res || strncmp(cwd, res, strlen(cwd)) != 0 ||
-      strncmp("/TestApp", res + strlen(cwd), 8) != 0)
+  size_t cwd_len = strlen(cwd);
+  res = realpath("TestApp", buf);
+  if (!res || strncmp(cwd, res, cwd_len) != 0 ||
+      strcmp("/TestApp", res + cwd_len) != 0) {
+    free(cwd);
     return -3;
+  }
   // `..` and `.` resolution
   res = realpath("../TestApp.app/./TestApp", buf);
-  if (!res || strncmp(cwd, res, strlen(cwd)) != 0 ||
-      strncmp("/TestApp", res + strlen(cwd), 8) != 0)
+  if (!res || strncmp(cwd, res, cwd_len) != 0 ||
+      strcmp("/TestApp", res + cwd_len) != 0) {
+    free(cwd);
     return -4;
+  }
+  // `..` going above the working directory
+  res = realpath(
+      "../../00000000-0000-0000-0000-000000000000/TestApp.app/TestApp", buf);
+  if (!res || strncmp(cwd, res, cwd_len) != 0 ||
+      strcmp("/TestApp", res + cwd_len) != 0) {
+    free(cwd);
+    return -5;
+  }
+  free(cwd);
+  // nonexistent final component
+  errno = 0;
+  res = realpath("does_not_exist", buf);
+  if (res || errno != ENOENT)
+    return -6;
+  // nonexistent intermediate component
+  errno = 0;
+  res = realpath("does_not_exist/../TestApp", buf);
+  if (res || errno != ENOENT)
+    return -7;
+  // file used as a directory
+  errno = 0;
+  res = realpath("TestApp/foo", buf);
+  if (res || errno != ENOTDIR)
+    return -8;
   return 0;
 }
 
@@ -1146,6 +1902,69 @@ int test_fwrite() {
   return 0;
 }
 
+int stdio_file_write_and_read(const char *path) {
+  FILE *file = fopen(path, "wb+");
+  if (file == NULL) {
+    return -1;
+  }
+  char buf[16];
+  if (fwrite("0123456789", 1, 10, file) != 10 || ftell(file) != 10) {
+    fclose(file);
+    return -2;
+  }
+  // Writes are buffered, but must be visible to a following read.
+  rewind(file);
+  if (fread(buf, 1, 10, file) != 10 || memcmp(buf, "0123456789", 10) != 0 ||
+      feof(file)) {
+    fclose(file);
+    return -3;
+  }
+  // Reading past the end sets the end-of-file indicator.
+  if (fgetc(file) != EOF || !feof(file) || ferror(file)) {
+    fclose(file);
+    return -4;
+  }
+  // Seeking clears it.
+  if (fseek(file, -4, SEEK_END) != 0 || feof(file) || ftell(file) != 6) {
+    fclose(file);
+    return -5;
+  }
+  if (fwrite("ab", 2, 1, file) != 1 || fseek(file, 3, SEEK_SET) != 0 ||
+      fgetc(file) != '3') {
+    fclose(file);
+    return -6;
+  }
+  if (fclose(file) != 0) {
+    return -7;
+  }
+
+  // The data must have reached the file.
+  file = fopen(path, "r");
+  if (file == NULL) {
+    return -8;
+  }
+  if (fread(buf, 1, sizeof(buf), file) != 10 ||
+      memcmp(buf, "012345ab89", 10) != 0 || !feof(file)) {
+    fclose(file);
+    return -9;
+  }
+  // Writing to a read-only stream fails and sets the error indicator.
+  if (fwrite("x", 1, 1, file) != 0 || !ferror(file)) {
+    fclose(file);
+    return -10;
+  }
+  fclose(file);
+  return 0;
+}
+
+int test_stdio_file() {
+  char *path = str_format("%sstdio_test", getenv("TMPDIR"));
+  int res = stdio_file_write_and_read(path);
+  remove(path);
+  free(path);
+  return res;
+}
+
 int test_open() {
   int fd;
   // Test opening directories
@@ -1170,6 +1989,122 @@ int test_open() {
   return 0;
 }
 
+int test_posix_memalign() {
+  size_t alignments[] = {4, 16, 32, 64, 256, 4096};
+  void *ptrs[6];
+  for (int i = 0; i < 6; i++) {
+    if (posix_memalign(&ptrs[i], alignments[i], 100) != 0) {
+      return -1;
+    }
+    if ((size_t)ptrs[i] % alignments[i] != 0) {
+      return -2;
+    }
+    memset(ptrs[i], 0xff, 100);
+  }
+  // Resizing doesn't need to preserve the alignment, but must keep the data.
+  ptrs[3] = realloc(ptrs[3], 200);
+  if (((unsigned char *)ptrs[3])[99] != 0xff) {
+    return -3;
+  }
+  for (int i = 0; i < 6; i++) {
+    free(ptrs[i]);
+  }
+
+  void *ptr = NULL;
+  if (posix_memalign(&ptr, 48, 100) != EINVAL || ptr != NULL) {
+    return -4;
+  }
+  if (posix_memalign(&ptr, 2, 100) != EINVAL || ptr != NULL) {
+    return -5;
+  }
+
+  ptr = valloc(10);
+  if (ptr == NULL || (size_t)ptr % 4096 != 0) {
+    return -6;
+  }
+  free(ptr);
+
+  return 0;
+}
+
+int test_mmap_file() {
+  int fd = open("PkgInfo", O_RDONLY);
+  if (fd == -1) {
+    return -1;
+  }
+  // Map more than the size of the file: the rest should be zeroed.
+  char *mapped = mmap(NULL, 4096, PROT_READ, MAP_PRIVATE, fd, 0);
+  // The mapping should outlive the file descriptor.
+  close(fd);
+  if (mapped == MAP_FAILED) {
+    return -2;
+  }
+  if (memcmp(mapped, "APPL????", 8) != 0 || mapped[8] != 0 ||
+      mapped[4095] != 0) {
+    munmap(mapped, 4096);
+    return -3;
+  }
+  if (munmap(mapped, 4096) != 0) {
+    return -4;
+  }
+  return 0;
+}
+
+int test_mmap_anonymous() {
+  char *mapped =
+      mmap(NULL, 4096, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANON, -1, 0);
+  if (mapped == MAP_FAILED) {
+    return -1;
+  }
+  for (int i = 0; i < 4096; i++) {
+    if (mapped[i] != 0) {
+      munmap(mapped, 4096);
+      return -2;
+    }
+  }
+  mapped[0] = 1;
+  mapped[4095] = 2;
+  if (mapped[0] != 1 || mapped[4095] != 2) {
+    munmap(mapped, 4096);
+    return -3;
+  }
+  if (munmap(mapped, 4096) != 0) {
+    return -4;
+  }
+  // Already unmapped
+  if (munmap(mapped, 4096) != -1) {
+    return -5;
+  }
+  return 0;
+}
+
+int test_signal() {
+  if (signal(SIGPIPE, SIG_IGN) != SIG_DFL) {
+    return -1;
+  }
+  struct sigaction old;
+  if (sigaction(SIGPIPE, NULL, &old) != 0 || old.sa_handler != SIG_IGN) {
+    return -2;
+  }
+  struct sigaction act = {.sa_handler = SIG_DFL, .sa_mask = 0, .sa_flags = 0};
+  if (sigaction(SIGPIPE, &act, &old) != 0 || old.sa_handler != SIG_IGN) {
+    return -3;
+  }
+  if (signal(SIGPIPE, SIG_DFL) != SIG_DFL) {
+    return -4;
+  }
+  // SIGKILL can't be caught or ignored
+  errno = 0;
+  if (signal(SIGKILL, SIG_IGN) != SIG_ERR || errno != EINVAL) {
+    return -5;
+  }
+  errno = 0;
+  if (sigaction(SIGKILL, &act, NULL) != -1 || errno != EINVAL) {
+    return -6;
+  }
+  return 0;
+}
+
 int test_CFMutableDictionary() {
   CFMutableDictionaryRef dict = CFDictionaryCreateMutable(NULL, 0, NULL, NULL);
   if (dict == NULL) {
@@ -1256,6 +2191,911 @@ int test_CFMutableDictionary() {
   return 0;
 }
 
+int atexit_calls[3];
+int atexit_call_count;
+void record_atexit_call(void *arg) {
+  if (atexit_call_count < 3) {
+    atexit_calls[atexit_call_count] = (int)arg;
+  }
+  atexit_call_count++;
+}
+
+int test_cxa_atexit() {
+  static char dso_handle;
+  static char other_dso_handle;
+  atexit_call_count = 0;
+  __cxa_atexit(&record_atexit_call, (void *)1, &dso_handle);
+  __cxa_atexit(&record_atexit_call, (void *)2, &other_dso_handle);
+  __cxa_atexit(&record_atexit_call, (void *)3, &dso_handle);
+  // Only the handlers for this DSO should run, last-in-first-out
+  __cxa_finalize(&dso_handle);
+  if (atexit_call_count != 2 || atexit_calls[0] != 3 || atexit_calls[1] != 1) {
+    return -1;
+  }
+  // Handlers must only be run once
+  __cxa_finalize(&dso_handle);
+  if (atexit_call_count != 2) {
+    return -2;
+  }
+  __cxa_finalize(&other_dso_handle);
+  if (atexit_call_count != 3 || atexit_calls[2] != 2) {
+    return -3;
+  }
+  return 0;
+}
+
+int test_NSUserDefaults() {
+  id (*msg_id)(id, SEL) = (id(*)(id, SEL))objc_msgSend;
+  void (*msg_void)(id, SEL) = (void (*)(id, SEL))objc_msgSend;
+  void (*msg_void_id)(id, SEL, id) = (void (*)(id, SEL, id))objc_msgSend;
+  _Bool (*msg_bool)(id, SEL) = (_Bool(*)(id, SEL))objc_msgSend;
+  long (*msg_long_id)(id, SEL, id) = (long (*)(id, SEL, id))objc_msgSend;
+  void (*msg_void_long_id)(id, SEL, long, id) =
+      (void (*)(id, SEL, long, id))objc_msgSend;
+
+  id class = (id)NSClassFromString(
+      (id)CFStringCreateWithCString(NULL, "NSUserDefaults", 0x0600));
+  id key = (id)CFStringCreateWithCString(NULL, "TestAppDefaultsKey", 0x0600);
+
+  // Set a value and save it
+  id defaults = msg_id(msg_id(class, sel_registerName("alloc")),
+                       sel_registerName("init"));
+  msg_void_long_id(defaults, sel_registerName("setInteger:forKey:"), 42, key);
+  if (!msg_bool(defaults, sel_registerName("synchronize")))
+    return -1;
+  msg_void(defaults, sel_registerName("release"));
+
+  // A new defaults object should load the value from the file
+  defaults = msg_id(msg_id(class, sel_registerName("alloc")),
+                    sel_registerName("init"));
+  long value = msg_long_id(defaults, sel_registerName("integerForKey:"), key);
+  // Clean up, so a rerun doesn't see the value
+  msg_void_id(defaults, sel_registerName("removeObjectForKey:"), key);
+  msg_bool(defaults, sel_registerName("synchronize"));
+  msg_void(defaults, sel_registerName("release"));
+  if (value != 42)
+    return -2;
+  return 0;
+}
+
+int test_UIDevice_orientation() {
+  id (*msg_id)(id, SEL) = (id(*)(id, SEL))objc_msgSend;
+  void (*msg_void)(id, SEL) = (void (*)(id, SEL))objc_msgSend;
+  void (*msg_void_long)(id, SEL, long) = (void (*)(id, SEL, long))objc_msgSend;
+  long (*msg_long)(id, SEL) = (long (*)(id, SEL))objc_msgSend;
+  void (*msg_void_id_id_id)(id, SEL, id, id, id) =
+      (void (*)(id, SEL, id, id, id))objc_msgSend;
+  void (*msg_add_observer)(id, SEL, id, SEL, id, id) =
+      (void (*)(id, SEL, id, SEL, id, id))objc_msgSend;
+
+  id device_class = (id)NSClassFromString(
+      (id)CFStringCreateWithCString(NULL, "UIDevice", 0x0600));
+  id center_class = (id)NSClassFromString(
+      (id)CFStringCreateWithCString(NULL, "NSNotificationCenter", 0x0600));
+  id array_class = (id)NSClassFromString(
+      (id)CFStringCreateWithCString(NULL, "NSMutableArray", 0x0600));
+  id name = (id)CFStringCreateWithCString(
+      NULL, "UIDeviceOrientationDidChangeNotification", 0x0600);
+
+  id device = msg_id(device_class, sel_registerName("currentDevice"));
+  id center = msg_id(center_class, sel_registerName("defaultCenter"));
+  // Each notification gets added to the array.
+  id observer = msg_id(array_class, sel_registerName("new"));
+  msg_add_observer(center,
+                   sel_registerName("addObserver:selector:name:object:"),
+                   observer, sel_registerName("addObject:"), name, NULL);
+
+  long original = msg_long(device, sel_registerName("orientation"));
+  long other = original == 3 ? 4 : 3; // UIDeviceOrientationLandscapeLeft/Right
+  SEL set_orientation = sel_registerName("setOrientation:");
+  SEL count = sel_registerName("count");
+
+  // No notifications until they're requested.
+  msg_void_long(device, set_orientation, other);
+  if (msg_long(observer, count) != 0 ||
+      msg_long(device, sel_registerName("orientation")) != other)
+    return -1;
+  msg_void(device,
+           sel_registerName("beginGeneratingDeviceOrientationNotifications"));
+  msg_void_long(device, set_orientation, original);
+  if (msg_long(observer, count) != 1)
+    return -2;
+  // Not changing the orientation doesn't post a notification.
+  msg_void_long(device, set_orientation, original);
+  if (msg_long(observer, count) != 1)
+    return -3;
+  msg_void(device,
+           sel_registerName("endGeneratingDeviceOrientationNotifications"));
+  msg_void_long(device, set_orientation, other);
+  msg_void_long(device, set_orientation, original);
+  long final_count = msg_long(observer, count);
+
+  msg_void_id_id_id(center, sel_registerName("removeObserver:name:object:"),
+                    observer, name, NULL);
+  msg_void(observer, sel_registerName("release"));
+  if (final_count != 1)
+    return -4;
+  return 0;
+}
+
+int test_getaddrinfo() {
+  // struct sockaddr_in: length, family, port and address, big-endian
+  unsigned char expected[8] = {16, AF_INET, 0, 80, 127, 0, 0, 1};
+  struct addrinfo hints;
+  memset(&hints, 0, sizeof(hints));
+  hints.ai_family = AF_INET;
+  hints.ai_socktype = SOCK_STREAM;
+  struct addrinfo *res = NULL;
+  if (getaddrinfo("localhost", "80", &hints, &res) != 0 || res == NULL)
+    return -1;
+  if (res->ai_family != AF_INET || res->ai_socktype != SOCK_STREAM ||
+      res->ai_addrlen != 16 || memcmp(res->ai_addr, expected, 8) != 0)
+    return -2;
+  freeaddrinfo(res);
+
+  hints.ai_flags = AI_NUMERICHOST;
+  res = NULL;
+  if (getaddrinfo("127.0.0.1", "80", &hints, &res) != 0 || res == NULL)
+    return -3;
+  if (memcmp(res->ai_addr, expected, 8) != 0 || res->ai_next != NULL)
+    return -4;
+  freeaddrinfo(res);
+  // AI_NUMERICHOST forbids looking up names
+  if (getaddrinfo("localhost", "80", &hints, &res) == 0)
+    return -5;
+  return 0;
+}
+
+int test_gethostbyname() {
+  struct hostent *host = gethostbyname("localhost");
+  if (host == NULL || host->h_addrtype != AF_INET || host->h_length != 4)
+    return -1;
+  if (memcmp(host->h_addr_list[0], (char[]){127, 0, 0, 1}, 4) != 0)
+    return -2;
+  if (strcmp(host->h_name, "localhost") != 0 || host->h_aliases[0] != NULL)
+    return -3;
+  return 0;
+}
+
+// integration.rs runs a relay on this port that accepts two connections and
+// forwards everything received on one to the other.
+#define SOCKET_RELAY_PORT 37120
+
+int socket_sender_fd;
+
+void *socket_sender_thread(void *arg) {
+  usleep(10000);
+  send(socket_sender_fd, "ping", 4, 0);
+  return NULL;
+}
+
+int test_socket() {
+  struct sockaddr_in addr;
+  memset(&addr, 0, sizeof(addr));
+  addr.sin_len = sizeof(addr);
+  addr.sin_family = AF_INET;
+  // Network byte order (big-endian)
+  addr.sin_port = (SOCKET_RELAY_PORT >> 8) | ((SOCKET_RELAY_PORT & 0xFF) << 8);
+  addr.sin_addr.s_addr = 0x0100007F; // 127.0.0.1
+
+  int a = socket(AF_INET, SOCK_STREAM, 0);
+  int b = socket(AF_INET, SOCK_STREAM, 0);
+  if (a < 0 || b < 0)
+    return -1;
+  if (connect(a, (struct sockaddr *)&addr, sizeof(addr)) != 0 ||
+      connect(b, (struct sockaddr *)&addr, sizeof(addr)) != 0)
+    return -2;
+
+  // Nothing has been sent yet, so a non-blocking receive fails.
+  char buf[8];
+  if (fcntl(b, F_SETFL, fcntl(b, F_GETFL) | O_NONBLOCK) != 0)
+    return -3;
+  if (recv(b, buf, sizeof(buf), 0) != -1 || errno != EAGAIN)
+    return -4;
+  if (fcntl(b, F_SETFL, fcntl(b, F_GETFL) & ~O_NONBLOCK) != 0)
+    return -5;
+
+  // A blocking receive lets the other thread run until it has sent something.
+  socket_sender_fd = a;
+  pthread_t thread;
+  pthread_create(&thread, NULL, socket_sender_thread, NULL);
+  if (recv(b, buf, sizeof(buf), 0) != 4 || memcmp(buf, "ping", 4) != 0)
+    return -6;
+  pthread_join(thread, NULL);
+
+  if (send(b, "pong", 4, 0) != 4)
+    return -7;
+  if (recv(a, buf, sizeof(buf), 0) != 4 || memcmp(buf, "pong", 4) != 0)
+    return -8;
+
+  if (close(a) != 0 || close(b) != 0)
+    return -9;
+  return 0;
+}
+
+// integration.rs runs an HTTP server on this port that answers one request.
+#define HTTP_SERVER_URL "http://127.0.0.1:37121/hello"
+
+int test_NSURLConnection() {
+  id (*msg_id_id)(id, SEL, id) = (id(*)(id, SEL, id))objc_msgSend;
+  id (*msg_id_id_ptr_ptr)(id, SEL, id, id *, id *) =
+      (id(*)(id, SEL, id, id *, id *))objc_msgSend;
+  long (*msg_long)(id, SEL) = (long (*)(id, SEL))objc_msgSend;
+  const void *(*msg_ptr)(id, SEL) = (const void *(*)(id, SEL))objc_msgSend;
+
+  id url_class = (id)NSClassFromString(
+      (id)CFStringCreateWithCString(NULL, "NSURL", 0x0600));
+  id request_class = (id)NSClassFromString(
+      (id)CFStringCreateWithCString(NULL, "NSURLRequest", 0x0600));
+  id connection_class = (id)NSClassFromString(
+      (id)CFStringCreateWithCString(NULL, "NSURLConnection", 0x0600));
+  id url_string = (id)CFStringCreateWithCString(NULL, HTTP_SERVER_URL, 0x0600);
+
+  id url = msg_id_id(url_class, sel_registerName("URLWithString:"), url_string);
+  id request =
+      msg_id_id(request_class, sel_registerName("requestWithURL:"), url);
+  if (url == NULL || request == NULL)
+    return -1;
+
+  id response = NULL;
+  id error = NULL;
+  id data = msg_id_id_ptr_ptr(
+      connection_class,
+      sel_registerName("sendSynchronousRequest:returningResponse:error:"),
+      request, &response, &error);
+  if (data == NULL || response == NULL || error != NULL)
+    return -2;
+  if (msg_long(response, sel_registerName("statusCode")) != 200)
+    return -3;
+  if (msg_long(data, sel_registerName("length")) != 5 ||
+      memcmp(msg_ptr(data, sel_registerName("bytes")), "hello", 5) != 0)
+    return -4;
+  return 0;
+}
+
+#define APP_BUNDLE_PATH                                                        \
+  "/var/mobile/Applications/00000000-0000-0000-0000-000000000000/TestApp.app"
+
+int test_NSData_contentsOfURL() {
+  id (*msg_id_id)(id, SEL, id) = (id(*)(id, SEL, id))objc_msgSend;
+  id (*msg_id_id_ulong_ptr)(id, SEL, id, unsigned long, id *) =
+      (id(*)(id, SEL, id, unsigned long, id *))objc_msgSend;
+  long (*msg_long)(id, SEL) = (long (*)(id, SEL))objc_msgSend;
+  const void *(*msg_ptr)(id, SEL) = (const void *(*)(id, SEL))objc_msgSend;
+
+  id url_class = (id)NSClassFromString(
+      (id)CFStringCreateWithCString(NULL, "NSURL", 0x0600));
+  id data_class = (id)NSClassFromString(
+      (id)CFStringCreateWithCString(NULL, "NSData", 0x0600));
+
+  // A file URL built from a string
+  id url_string = (id)CFStringCreateWithCString(
+      NULL, "file://localhost" APP_BUNDLE_PATH "/PkgInfo", 0x0600);
+  id url = msg_id_id(url_class, sel_registerName("URLWithString:"), url_string);
+  if (url == NULL)
+    return -1;
+  id data =
+      msg_id_id(data_class, sel_registerName("dataWithContentsOfURL:"), url);
+  if (data == NULL || msg_long(data, sel_registerName("length")) != 8 ||
+      memcmp(msg_ptr(data, sel_registerName("bytes")), "APPL????", 8) != 0)
+    return -2;
+
+  // A missing file
+  id missing_string = (id)CFStringCreateWithCString(
+      NULL, "file://" APP_BUNDLE_PATH "/does_not_exist", 0x0600);
+  id missing_url =
+      msg_id_id(url_class, sel_registerName("URLWithString:"), missing_string);
+  id error = NULL;
+  data = msg_id_id_ulong_ptr(
+      data_class, sel_registerName("dataWithContentsOfURL:options:error:"),
+      missing_url, 0, &error);
+  if (data != NULL || error == NULL)
+    return -3;
+  if (msg_long(error, sel_registerName("code")) != 260) // no such file
+    return -4;
+  return 0;
+}
+
+int test_zlib() {
+  char data[1000];
+  for (int i = 0; i < 1000; i++)
+    data[i] = "touchHLE"[i % 8];
+
+  // One-shot functions
+  unsigned char compressed[1000];
+  unsigned long compressed_len = sizeof(compressed);
+  if (compress(compressed, &compressed_len, (unsigned char *)data, 1000) !=
+          Z_OK ||
+      compressed_len >= 1000)
+    return -1;
+  char output[1000];
+  unsigned long output_len = sizeof(output);
+  if (uncompress((unsigned char *)output, &output_len, compressed,
+                 compressed_len) != Z_OK ||
+      output_len != 1000 || memcmp(output, data, 1000) != 0)
+    return -2;
+
+  // Streaming, with the input given all at once and the output collected in
+  // small pieces.
+  z_stream stream;
+  memset(&stream, 0, sizeof(stream));
+  if (deflateInit_(&stream, 9, zlibVersion(), sizeof(stream)) != Z_OK)
+    return -3;
+  stream.next_in = (unsigned char *)data;
+  stream.avail_in = 1000;
+  int ret;
+  do {
+    stream.next_out = compressed + stream.total_out;
+    stream.avail_out = 16;
+    ret = deflate(&stream, Z_FINISH);
+  } while (ret == Z_OK);
+  compressed_len = stream.total_out;
+  if (ret != Z_STREAM_END || stream.avail_in != 0 ||
+      deflateEnd(&stream) != Z_OK)
+    return -4;
+
+  // Streaming, with the input given in small pieces and the output collected
+  // all at once.
+  memset(&stream, 0, sizeof(stream));
+  memset(output, 0, sizeof(output));
+  if (inflateInit_(&stream, zlibVersion(), sizeof(stream)) != Z_OK)
+    return -5;
+  stream.next_out = (unsigned char *)output;
+  stream.avail_out = sizeof(output);
+  do {
+    stream.next_in = compressed + stream.total_in;
+    stream.avail_in = compressed_len - stream.total_in;
+    if (stream.avail_in > 16)
+      stream.avail_in = 16;
+    ret = inflate(&stream, Z_NO_FLUSH);
+  } while (ret == Z_OK);
+  if (ret != Z_STREAM_END || stream.total_out != 1000 ||
+      memcmp(output, data, 1000) != 0 || inflateEnd(&stream) != Z_OK)
+    return -6;
+  return 0;
+}
+
+// Inflate all of `input` at once with the given windowBits, and check that the
+// output is `expected`.
+int inflate_with_window_bits(int window_bits, const unsigned char *input,
+                             unsigned int input_len, const char *expected,
+                             unsigned int expected_len) {
+  z_stream stream;
+  memset(&stream, 0, sizeof(stream));
+  int ret = inflateInit2_(&stream, window_bits, zlibVersion(), sizeof(stream));
+  if (ret != Z_OK)
+    return ret;
+  char output[1000];
+  stream.next_in = input;
+  stream.avail_in = input_len;
+  stream.next_out = (unsigned char *)output;
+  stream.avail_out = sizeof(output);
+  ret = inflate(&stream, Z_FINISH);
+  inflateEnd(&stream);
+  if (ret != Z_STREAM_END)
+    return ret;
+  if (stream.total_out != expected_len ||
+      memcmp(output, expected, expected_len) != 0)
+    return Z_DATA_ERROR;
+  return Z_OK;
+}
+
+int test_zlib_window_bits() {
+  char data[64];
+  for (int i = 0; i < 64; i++)
+    data[i] = "touchHLE"[i % 8];
+
+  unsigned char zlib_data[100];
+  unsigned long zlib_len = sizeof(zlib_data);
+  if (compress(zlib_data, &zlib_len, (unsigned char *)data, 64) != Z_OK)
+    return -1;
+  // The same data, compressed by Python's gzip module with a file name.
+  const unsigned char gzip_data[] = {
+      0x1f, 0x8b, 0x08, 0x08, 0x00, 0x00, 0x00, 0x00, 0x02, 0xff,
+      0x61, 0x2e, 0x74, 0x78, 0x74, 0x00, 0x2b, 0xc9, 0x2f, 0x4d,
+      0xce, 0xf0, 0xf0, 0x71, 0x2d, 0x21, 0x93, 0x06, 0x00, 0xd9,
+      0xb6, 0x41, 0x9e, 0x40, 0x00, 0x00, 0x00};
+
+  // 0 means zlib, with the window size taken from the header.
+  if (inflate_with_window_bits(0, zlib_data, zlib_len, data, 64) != Z_OK)
+    return -2;
+  // Adding 16 means gzip.
+  if (inflate_with_window_bits(15 + 16, gzip_data, sizeof(gzip_data), data,
+                               64) != Z_OK)
+    return -3;
+  if (inflate_with_window_bits(15 + 16, zlib_data, zlib_len, data, 64) !=
+      Z_DATA_ERROR)
+    return -4;
+  // Adding 32 means either zlib or gzip.
+  if (inflate_with_window_bits(15 + 32, gzip_data, sizeof(gzip_data), data,
+                               64) != Z_OK)
+    return -5;
+  if (inflate_with_window_bits(15 + 32, zlib_data, zlib_len, data, 64) != Z_OK)
+    return -6;
+  // Too small
+  if (inflate_with_window_bits(7, zlib_data, zlib_len, data, 64) !=
+      Z_STREAM_ERROR)
+    return -7;
+  return 0;
+}
+
+int test_sysctl() {
+  // Query the size, then fetch the value.
+  size_t len = 0;
+  if (sysctlbyname("hw.machine", NULL, &len, NULL, 0) != 0 || len < 2)
+    return -1;
+  char machine[32];
+  if (len > sizeof(machine))
+    return -2;
+  if (sysctlbyname("hw.machine", machine, &len, NULL, 0) != 0 ||
+      strlen(machine) + 1 != len)
+    return -3;
+
+  // The same value by MIB
+  int mib[2] = {CTL_HW, HW_MACHINE};
+  char machine2[32];
+  size_t len2 = sizeof(machine2);
+  if (sysctl(mib, 2, machine2, &len2, NULL, 0) != 0 || len2 != len ||
+      strcmp(machine, machine2) != 0)
+    return -4;
+
+  int ncpu = 0;
+  len = sizeof(ncpu);
+  mib[1] = HW_NCPU;
+  if (sysctl(mib, 2, &ncpu, &len, NULL, 0) != 0 || len != sizeof(ncpu) ||
+      ncpu != 1)
+    return -5;
+
+  // Buffer too small
+  len = 2;
+  if (sysctlbyname("hw.machine", machine, &len, NULL, 0) != -1 ||
+      errno != ENOMEM)
+    return -6;
+  // Unknown name
+  len = sizeof(machine);
+  if (sysctlbyname("hw.touchhle", machine, &len, NULL, 0) != -1 ||
+      errno != ENOENT)
+    return -7;
+  return 0;
+}
+
+int test_clocks() {
+  struct mach_timebase_info timebase;
+  if (mach_timebase_info(&timebase) != 0 || timebase.numer == 0 ||
+      timebase.denom == 0)
+    return -1;
+  unsigned long long abs1 = mach_absolute_time();
+  unsigned long long abs2 = mach_absolute_time();
+  if (abs2 < abs1)
+    return -2;
+
+  struct timespec mono1, mono2;
+  if (clock_gettime(CLOCK_MONOTONIC, &mono1) != 0 ||
+      clock_gettime(CLOCK_MONOTONIC, &mono2) != 0)
+    return -3;
+  if (mono1.tv_nsec < 0 || mono1.tv_nsec >= 1000000000 ||
+      mono2.tv_sec < mono1.tv_sec ||
+      (mono2.tv_sec == mono1.tv_sec && mono2.tv_nsec < mono1.tv_nsec))
+    return -4;
+
+  // The wall clock should agree with gettimeofday() to within a second or so.
+  struct timespec real;
+  struct timeval tv;
+  if (clock_gettime(CLOCK_REALTIME, &real) != 0 ||
+      gettimeofday(&tv, NULL) != 0)
+    return -5;
+  if (tv.tv_sec - real.tv_sec > 1 || real.tv_sec - tv.tv_sec > 1)
+    return -6;
+
+  if (clock_gettime(12345, &real) != -1 || errno != EINVAL)
+    return -7;
+  return 0;
+}
+
+int test_env_vars() {
+  const char *home = getenv("HOME");
+  const char *fixed_home = getenv("CFFIXED_USER_HOME");
+  const char *tmpdir = getenv("TMPDIR");
+  if (!home || !fixed_home || !tmpdir || !getenv("PATH") || !getenv("USER"))
+    return -1;
+  if (strcmp(home, fixed_home) != 0)
+    return -2;
+
+  // TMPDIR should be the app's own tmp directory, which must exist.
+  size_t home_len = strlen(home);
+  if (strncmp(home, tmpdir, home_len) != 0 ||
+      strcmp(tmpdir + home_len, "/tmp/") != 0)
+    return -3;
+  DIR *dirp = opendir(tmpdir);
+  if (!dirp)
+    return -4;
+  closedir(dirp);
+  dirp = opendir(home);
+  if (!dirp)
+    return -5;
+  closedir(dirp);
+  return 0;
+}
+
+int test_NSThread() {
+  id (*msg_id)(id, SEL) = (id(*)(id, SEL))objc_msgSend;
+  _Bool (*msg_bool)(id, SEL) = (_Bool(*)(id, SEL))objc_msgSend;
+  unsigned (*msg_uint)(id, SEL) = (unsigned (*)(id, SEL))objc_msgSend;
+  void (*msg_void)(id, SEL) = (void (*)(id, SEL))objc_msgSend;
+  void (*msg_void_double)(id, SEL, double) =
+      (void (*)(id, SEL, double))objc_msgSend;
+  void (*msg_detach)(id, SEL, SEL, id, id) =
+      (void (*)(id, SEL, SEL, id, id))objc_msgSend;
+
+  id thread_class = (id)NSClassFromString(
+      (id)CFStringCreateWithCString(NULL, "NSThread", 0x0600));
+  id array_class = (id)NSClassFromString(
+      (id)CFStringCreateWithCString(NULL, "NSMutableArray", 0x0600));
+
+  if (!msg_bool(thread_class, sel_registerName("isMainThread")))
+    return -1;
+  id current = msg_id(thread_class, sel_registerName("currentThread"));
+  id main = msg_id(thread_class, sel_registerName("mainThread"));
+  if (current == NULL || current != main ||
+      !msg_bool(current, sel_registerName("isMainThread")))
+    return -2;
+
+  // There's no way to define a method here, so use -[NSMutableArray addObject:]
+  // as the thread's entry point and wait for the object to show up.
+  id array = msg_id(array_class, sel_registerName("new"));
+  msg_detach(thread_class,
+             sel_registerName("detachNewThreadSelector:toTarget:withObject:"),
+             sel_registerName("addObject:"), array,
+             (id)CFStringCreateWithCString(NULL, "object", 0x0600));
+  int tries = 0;
+  while (msg_uint(array, sel_registerName("count")) == 0) {
+    if (++tries > 100) {
+      msg_void(array, sel_registerName("release"));
+      return -3;
+    }
+    msg_void_double(thread_class, sel_registerName("sleepForTimeInterval:"),
+                    0.01);
+  }
+  msg_void(array, sel_registerName("release"));
+  if (!msg_bool(thread_class, sel_registerName("isMultiThreaded")))
+    return -4;
+  return 0;
+}
+
+int test_NSOperationQueue() {
+  id (*msg_id)(id, SEL) = (id(*)(id, SEL))objc_msgSend;
+  id (*msg_id_init)(id, SEL, id, SEL, id) =
+      (id(*)(id, SEL, id, SEL, id))objc_msgSend;
+  unsigned (*msg_uint)(id, SEL) = (unsigned (*)(id, SEL))objc_msgSend;
+  void (*msg_void)(id, SEL) = (void (*)(id, SEL))objc_msgSend;
+  void (*msg_void_id)(id, SEL, id) = (void (*)(id, SEL, id))objc_msgSend;
+  void (*msg_void_long)(id, SEL, long) =
+      (void (*)(id, SEL, long))objc_msgSend;
+
+  id queue_class = (id)NSClassFromString(
+      (id)CFStringCreateWithCString(NULL, "NSOperationQueue", 0x0600));
+  id op_class = (id)NSClassFromString(
+      (id)CFStringCreateWithCString(NULL, "NSInvocationOperation", 0x0600));
+  id array_class = (id)NSClassFromString(
+      (id)CFStringCreateWithCString(NULL, "NSMutableArray", 0x0600));
+
+  id array = msg_id(array_class, sel_registerName("new"));
+  id queue = msg_id(queue_class, sel_registerName("new"));
+  msg_void_long(queue, sel_registerName("setMaxConcurrentOperationCount:"),
+                2);
+  for (int i = 0; i < 2; i++) {
+    id op = msg_id_init(
+        msg_id(op_class, sel_registerName("alloc")),
+        sel_registerName("initWithTarget:selector:object:"), array,
+        sel_registerName("addObject:"),
+        (id)CFStringCreateWithCString(NULL, "object", 0x0600));
+    msg_void_id(queue, sel_registerName("addOperation:"), op);
+    msg_void(op, sel_registerName("release"));
+  }
+  msg_void(queue, sel_registerName("waitUntilAllOperationsAreFinished"));
+  unsigned op_count = msg_uint(queue, sel_registerName("operationCount"));
+  unsigned count = msg_uint(array, sel_registerName("count"));
+  msg_void(queue, sel_registerName("release"));
+  msg_void(array, sel_registerName("release"));
+  if (op_count != 0)
+    return -1;
+  if (count != 2)
+    return -2;
+  return 0;
+}
+
+void increment(void *counter) { (*(int *)counter)++; }
+
+// Block_object_assign() isn't available, so these can't be __block variables.
+static int dispatch_once_count;
+static int dispatch_sync_value;
+static int dispatch_async_count;
+
+int test_dispatch() {
+  dispatch_queue_t queue = dispatch_get_global_queue(0, 0);
+  if (queue == NULL)
+    return -1;
+
+  // dispatch_once: the block must run exactly once
+  static dispatch_once_t once;
+  for (int i = 0; i < 3; i++) {
+    dispatch_once(&once, ^{
+      dispatch_once_count++;
+    });
+  }
+  if (dispatch_once_count != 1)
+    return -2;
+  static dispatch_once_t once_f;
+  int once_f_count = 0;
+  for (int i = 0; i < 3; i++)
+    dispatch_once_f(&once_f, &once_f_count, &increment);
+  if (once_f_count != 1)
+    return -3;
+
+  // dispatch_sync onto a global queue runs the block before returning
+  dispatch_sync(queue, ^{
+    dispatch_sync_value = 42;
+  });
+  if (dispatch_sync_value != 42)
+    return -4;
+
+  // dispatch_async onto a global queue runs the blocks on another thread
+  for (int i = 0; i < 2; i++) {
+    dispatch_async(queue, ^{
+      dispatch_async_count++;
+    });
+  }
+  int tries = 0;
+  while (dispatch_async_count != 2) {
+    if (++tries > 100)
+      return -5;
+    usleep(10000);
+  }
+  return 0;
+}
+
+static jmp_buf test_jmp_buf;
+
+void jump_from_nested_call(int depth, int val) {
+  if (depth > 0)
+    jump_from_nested_call(depth - 1, val);
+  longjmp(test_jmp_buf, val);
+}
+
+int test_setjmp() {
+  volatile int stage = 0;
+  volatile double saved_double = 1.5;
+  int ret = setjmp(test_jmp_buf);
+  stage++;
+  if (stage == 1) {
+    if (ret != 0)
+      return -1;
+    jump_from_nested_call(3, 42);
+    return -2;
+  }
+  if (stage == 2) {
+    if (ret != 42 || saved_double != 1.5)
+      return -3;
+    // longjmp() with 0 must make setjmp() return 1
+    jump_from_nested_call(0, 0);
+    return -4;
+  }
+  if (stage == 3 && ret == 1)
+    return 0;
+  return -5;
+}
+
+// Width and height of the framebuffer used by the OpenGL ES tests.
+#define GLES_TEST_SIZE 4
+
+struct gles_test_context {
+  id context;
+  GLuint framebuffer;
+  GLuint renderbuffer;
+};
+
+// Create an OpenGL ES 1.1 context, make it current and bind a
+// GLES_TEST_SIZE x GLES_TEST_SIZE RGBA framebuffer to render into. Returns 0
+// on success. gles_test_end() must be called afterwards either way.
+int gles_test_begin(struct gles_test_context *ctx) {
+  id (*msg_id)(id, SEL) = (id(*)(id, SEL))objc_msgSend;
+  id (*msg_id_long)(id, SEL, long) = (id(*)(id, SEL, long))objc_msgSend;
+  signed char (*msg_bool_id)(id, SEL, id) =
+      (signed char (*)(id, SEL, id))objc_msgSend;
+
+  ctx->framebuffer = 0;
+  ctx->renderbuffer = 0;
+  id context_class = (id)NSClassFromString(
+      (id)CFStringCreateWithCString(NULL, "EAGLContext", 0x0600));
+  ctx->context = msg_id_long(msg_id(context_class, sel_registerName("alloc")),
+                             sel_registerName("initWithAPI:"),
+                             kEAGLRenderingAPIOpenGLES1);
+  if (ctx->context == NULL ||
+      !msg_bool_id(context_class, sel_registerName("setCurrentContext:"),
+                   ctx->context))
+    return -1;
+
+  glGenFramebuffersOES(1, &ctx->framebuffer);
+  glBindFramebufferOES(GL_FRAMEBUFFER_OES, ctx->framebuffer);
+  glGenRenderbuffersOES(1, &ctx->renderbuffer);
+  glBindRenderbufferOES(GL_RENDERBUFFER_OES, ctx->renderbuffer);
+  glRenderbufferStorageOES(GL_RENDERBUFFER_OES, GL_RGBA8_OES, GLES_TEST_SIZE,
+                           GLES_TEST_SIZE);
+  glFramebufferRenderbufferOES(GL_FRAMEBUFFER_OES, GL_COLOR_ATTACHMENT0_OES,
+                               GL_RENDERBUFFER_OES, ctx->renderbuffer);
+  if (glCheckFramebufferStatusOES(GL_FRAMEBUFFER_OES) !=
+      GL_FRAMEBUFFER_COMPLETE_OES)
+    return -2;
+  glViewport(0, 0, GLES_TEST_SIZE, GLES_TEST_SIZE);
+  return 0;
+}
+
+void gles_test_end(struct gles_test_context *ctx) {
+  void (*msg_void)(id, SEL) = (void (*)(id, SEL))objc_msgSend;
+  signed char (*msg_bool_id)(id, SEL, id) =
+      (signed char (*)(id, SEL, id))objc_msgSend;
+
+  if (ctx->context == NULL)
+    return;
+  glDeleteRenderbuffersOES(1, &ctx->renderbuffer);
+  glDeleteFramebuffersOES(1, &ctx->framebuffer);
+  id context_class = (id)NSClassFromString(
+      (id)CFStringCreateWithCString(NULL, "EAGLContext", 0x0600));
+  msg_bool_id(context_class, sel_registerName("setCurrentContext:"), NULL);
+  msg_void(ctx->context, sel_registerName("release"));
+}
+
+// Check that each component of an RGBA8 pixel is within 1 of the expected
+// value, allowing for rounding differences between drivers.
+int pixel_matches(const GLubyte *pixel, const GLubyte *expected) {
+  for (int i = 0; i < 4; i++) {
+    int diff = pixel[i] - expected[i];
+    if (diff < -1 || diff > 1)
+      return 0;
+  }
+  return 1;
+}
+
+int test_gles_blend_func_separate() {
+  struct gles_test_context ctx;
+  int res = gles_test_begin(&ctx);
+  if (res != 0) {
+    gles_test_end(&ctx);
+    return res;
+  }
+
+  // Blend a half-transparent white quad onto transparent black, with the
+  // color scaled by the source alpha, but the alpha copied unchanged.
+  glClearColor(0, 0, 0, 0);
+  glClear(GL_COLOR_BUFFER_BIT);
+  glEnable(GL_BLEND);
+  glBlendFuncSeparateOES(GL_SRC_ALPHA, GL_ZERO, GL_ONE, GL_ZERO);
+  const GLfloat quad[] = {-1, -1, 1, -1, -1, 1, 1, 1};
+  glEnableClientState(GL_VERTEX_ARRAY);
+  glVertexPointer(2, GL_FLOAT, 0, quad);
+  glColor4f(1, 1, 1, 0.5);
+  glDrawArrays(GL_TRIANGLE_STRIP, 0, 4);
+
+  GLubyte pixel[4];
+  glReadPixels(0, 0, 1, 1, GL_RGBA, GL_UNSIGNED_BYTE, pixel);
+  gles_test_end(&ctx);
+  // If the factor pairs were swapped, this would be (255, 255, 255, 64), and
+  // if only the RGB pair were used, it would be (128, 128, 128, 64).
+  const GLubyte expected[4] = {128, 128, 128, 128};
+  if (!pixel_matches(pixel, expected))
+    return -3;
+  return 0;
+}
+
+int test_gles_point_sprite() {
+  struct gles_test_context ctx;
+  int res = gles_test_begin(&ctx);
+  if (res != 0) {
+    gles_test_end(&ctx);
+    return res;
+  }
+
+  // A 2x2 texture with a different color in each corner.
+  const GLubyte texels[2][2][4] = {
+      {{255, 0, 0, 255}, {0, 255, 0, 255}},
+      {{0, 0, 255, 255}, {255, 255, 255, 255}},
+  };
+  GLuint texture;
+  glGenTextures(1, &texture);
+  glBindTexture(GL_TEXTURE_2D, texture);
+  glTexParameteri(GL_TEXTURE_2D, GL_TEXTURE_MIN_FILTER, GL_NEAREST);
+  glTexParameteri(GL_TEXTURE_2D, GL_TEXTURE_MAG_FILTER, GL_NEAREST);
+  glTexImage2D(GL_TEXTURE_2D, 0, GL_RGBA, 2, 2, 0, GL_RGBA, GL_UNSIGNED_BYTE,
+               texels);
+  glEnable(GL_TEXTURE_2D);
+  glTexEnvi(GL_TEXTURE_ENV, GL_TEXTURE_ENV_MODE, GL_REPLACE);
+
+  // Draw a particle covering the whole framebuffer. With GL_COORD_REPLACE_OES,
+  // the texture is stretched across the point, rather than the whole point
+  // using the texture co-ordinates of its vertex.
+  glClearColor(0, 0, 0, 0);
+  glClear(GL_COLOR_BUFFER_BIT);
+  glEnable(GL_POINT_SPRITE_OES);
+  glTexEnvi(GL_POINT_SPRITE_OES, GL_COORD_REPLACE_OES, GL_TRUE);
+  glPointSize(GLES_TEST_SIZE);
+  const GLfloat point[] = {0, 0};
+  glEnableClientState(GL_VERTEX_ARRAY);
+  glVertexPointer(2, GL_FLOAT, 0, point);
+  glDrawArrays(GL_POINTS, 0, 1);
+
+  GLubyte pixels[GLES_TEST_SIZE][GLES_TEST_SIZE][4];
+  glReadPixels(0, 0, GLES_TEST_SIZE, GLES_TEST_SIZE, GL_RGBA, GL_UNSIGNED_BYTE,
+               pixels);
+  glDeleteTextures(1, &texture);
+  gles_test_end(&ctx);
+  // The first row of the texture is at the top of the point sprite, but the
+  // first row read back is at the bottom of the framebuffer.
+  const int last = GLES_TEST_SIZE - 1;
+  if (!pixel_matches(pixels[last][0], texels[0][0]) ||
+      !pixel_matches(pixels[last][last], texels[0][1]) ||
+      !pixel_matches(pixels[0][0], texels[1][0]) ||
+      !pixel_matches(pixels[0][last], texels[1][1]))
+    return -3;
+  return 0;
+}
+
+int test_gles_material_ambient_and_diffuse() {
+  struct gles_test_context ctx;
+  int res = gles_test_begin(&ctx);
+  if (res != 0) {
+    gles_test_end(&ctx);
+    return res;
+  }
+
+  // Setting GL_AMBIENT_AND_DIFFUSE sets both, but they're read separately.
+  const GLfloat color[4] = {0.25, 0.5, 0.75, 1};
+  glMaterialfv(GL_FRONT_AND_BACK, GL_AMBIENT_AND_DIFFUSE, color);
+  GLfloat ambient[4], diffuse[4];
+  glGetMaterialfv(GL_FRONT, GL_AMBIENT, ambient);
+  glGetMaterialfv(GL_FRONT, GL_DIFFUSE, diffuse);
+  GLenum error = glGetError();
+  gles_test_end(&ctx);
+  if (error != GL_NO_ERROR)
+    return -3;
+  if (memcmp(ambient, color, sizeof(color)) != 0)
+    return -4;
+  if (memcmp(diffuse, color, sizeof(color)) != 0)
+    return -5;
+  return 0;
+}
+
+int test_gles_tex_parameter() {
+  struct gles_test_context ctx;
+  int res = gles_test_begin(&ctx);
+  if (res != 0) {
+    gles_test_end(&ctx);
+    return res;
+  }
+
+  GLuint texture;
+  glGenTextures(1, &texture);
+  glBindTexture(GL_TEXTURE_2D, texture);
+  glTexParameteri(GL_TEXTURE_2D, GL_TEXTURE_WRAP_S, GL_CLAMP_TO_EDGE);
+  glTexParameteri(GL_TEXTURE_2D, GL_GENERATE_MIPMAP, GL_TRUE);
+  GLint wrap_s, generate_mipmap;
+  GLfloat wrap_s_float, generate_mipmap_float;
+  glGetTexParameteriv(GL_TEXTURE_2D, GL_TEXTURE_WRAP_S, &wrap_s);
+  glGetTexParameteriv(GL_TEXTURE_2D, GL_GENERATE_MIPMAP, &generate_mipmap);
+  glGetTexParameterfv(GL_TEXTURE_2D, GL_TEXTURE_WRAP_S, &wrap_s_float);
+  glGetTexParameterfv(GL_TEXTURE_2D, GL_GENERATE_MIPMAP,
+                      &generate_mipmap_float);
+  GLenum error = glGetError();
+  glDeleteTextures(1, &texture);
+  gles_test_end(&ctx);
+  if (error != GL_NO_ERROR)
+    return -3;
+  if (wrap_s != GL_CLAMP_TO_EDGE || generate_mipmap != GL_TRUE)
+    return -4;
+  if (wrap_s_float != GL_CLAMP_TO_EDGE || generate_mipmap_float != GL_TRUE)
+    return -5;
+  return 0;
+}
+
 // clang-format off
 #define FUNC_DEF(func)                                                         \
   { &func, #func }
@@ -1264,6 +3104,8 @@ struct {
   const char *name;
 } test_func_array[] = {
     FUNC_DEF(test_qsort),
+    FUNC_DEF(test_qsort_r),
+    FUNC_DEF(test_qsort_b_bsearch_b),
     FUNC_DEF(test_vsnprintf),
     FUNC_DEF(test_sscanf),
     FUNC_DEF(test_swscanf),
@@ -1271,6 +3113,7 @@ struct {
     FUNC_DEF(test_realloc),
     FUNC_DEF(test_atof),
     FUNC_DEF(test_strtof),
+    FUNC_DEF(test_strtod_special),
     FUNC_DEF(test_getcwd_chdir),
     FUNC_DEF(test_sem),
     FUNC_DEF(test_CGAffineTransform),
@@ -1290,9 +3133,41 @@ struct {
     FUNC_DEF(test_mbstowcs),
     FUNC_DEF(test_CFMutableString),
     FUNC_DEF(test_fwrite),
+    FUNC_DEF(test_stdio_file),
     FUNC_DEF(test_open),
+    FUNC_DEF(test_posix_memalign),
+    FUNC_DEF(test_mmap_file),
+    FUNC_DEF(test_mmap_anonymous),
+    FUNC_DEF(test_signal),
     FUNC_DEF(test_cond_var),
+    FUNC_DEF(test_pthread_cancel),
+    FUNC_DEF(test_pthread_key),
     FUNC_DEF(test_CFMutableDictionary),
+    FUNC_DEF(test_cxa_atexit),
+    FUNC_DEF(test_div),
+    FUNC_DEF(test_NSUserDefaults),
+    FUNC_DEF(test_UIDevice_orientation),
+    FUNC_DEF(test_getaddrinfo),
+    FUNC_DEF(test_gethostbyname),
+    FUNC_DEF(test_socket),
+    FUNC_DEF(test_NSURLConnection),
+    FUNC_DEF(test_NSData_contentsOfURL),
+    FUNC_DEF(test_zlib),
+    FUNC_DEF(test_zlib_window_bits),
+    FUNC_DEF(test_sysctl),
+    FUNC_DEF(test_clocks),
+    FUNC_DEF(test_env_vars),
+    FUNC_DEF(test_NSThread),
+    FUNC_DEF(test_NSOperationQueue),
+    FUNC_DEF(test_dispatch),
+    FUNC_DEF(test_setjmp),
+    FUNC_DEF(test_memmove_overlap),
+    FUNC_DEF(test_memccpy_memmem),
+    FUNC_DEF(test_CFRunLoop),
+    FUNC_DEF(test_gles_blend_func_separate),
+    FUNC_DEF(test_gles_point_sprite),
+    FUNC_DEF(test_gles_material_ambient_and_diffuse),
+    FUNC_DEF(test_gles_tex_parameter),
 };
 // clang-format on
 
@@ -1,7 +1,8 @@
 use std::env;
 use std::env::current_dir;
 use std::error::Error;
-use std::io::Write;
+use std::io::{Read, Write};
+use std::net::{Ipv4Addr, Shutdown, TcpListener, TcpStream};
 use std::path::{Path, PathBuf};
 use std::process::Command;
 
@@ -87,6 +88,59 @@ fn build_test_app(tests_dir: &Path, test_app_path: &Path) -> Result<(), Box<dyn
     Ok(())
 }
 
+/// Port for [spawn_socket_relay]. This must match `SOCKET_RELAY_PORT` in
+/// `TestApp_source/main.c`.
+const SOCKET_RELAY_PORT: u16 = 37120;
+
+/// Accept two connections on the loopback interface and forward everything
+/// received on each to the other, so that the test app can connect two of its
+/// sockets to each other.
+fn spawn_socket_relay() -> Result<(), Box<dyn Error>> {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, SOCKET_RELAY_PORT))?;
+    std::thread::spawn(move || {
+        let (a, _) = listener.accept().unwrap();
+        let (b, _) = listener.accept().unwrap();
+        let forward = |mut from: TcpStream, mut to: TcpStream| {
+            std::thread::spawn(move || {
+                let _ = std::io::copy(&mut from, &mut to);
+                let _ = to.shutdown(Shutdown::Write);
+            });
+        };
+        forward(a.try_clone().unwrap(), b.try_clone().unwrap());
+        forward(b, a);
+    });
+    Ok(())
+}
+
+/// Port for [spawn_http_server]. This must match `HTTP_SERVER_URL` in
+/// `TestApp_source/main.c`.
+const HTTP_SERVER_PORT: u16 = 37121;
+
+/// Answer a single HTTP request on the loopback interface. A GET request for
+/// `/hello` gets `hello` as the response body, anything else gets a 404.
+fn spawn_http_server() -> Result<(), Box<dyn Error>> {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, HTTP_SERVER_PORT))?;
+    std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut request = Vec::new();
+        let mut buf = [0; 256];
+        while find_subsequence(&request, b"\r\n\r\n").is_none() {
+            let read = stream.read(&mut buf).unwrap();
+            if read == 0 {
+                return;
+            }
+            request.extend_from_slice(&buf[..read]);
+        }
+        let response: &[u8] = if request.starts_with(b"GET /hello HTTP/1.") {
+            b"HTTP/1.0 200 OK\r\nContent-Length: 5\r\n\r\nhello"
+        } else {
+            b"HTTP/1.0 404 Not Found\r\n\r\n"
+        };
+        let _ = stream.write_all(response);
+    });
+    Ok(())
+}
+
 #[test]
 fn run_test_app() -> Result<(), Box<dyn Error>> {
     let tests_dir = current_dir()?.join("tests");
@@ -95,6 +149,9 @@ fn run_test_app() -> Result<(), Box<dyn Error>> {
 
     build_test_app(&tests_dir, &test_app_path)?;
 
+    spawn_socket_relay()?;
+    spawn_http_server()?;
+
     let binary_name = "touchHLE";
     let binary_path = target_dir().join(format!("{}{}", binary_name, env::consts::EXE_SUFFIX));
 
@@ -102,9 +159,15 @@ fn run_test_app() -> Result<(), Box<dyn Error>> {
 
     let output = cmd
         .arg(test_app_path)
-        // headless mode avoids a distracting window briefly appearing during
-        // testing, and works in CI.
-        .arg("--headless")
+        // The OpenGL ES tests need a window, which headless mode doesn't have.
+        // SDL's offscreen driver avoids a distracting window briefly appearing
+        // during testing, and works in CI.
+        .env("SDL_VIDEODRIVER", "offscreen")
+        // The OpenGL ES tests are for touchHLE's own implementation, rather
+        // than the host's native OpenGL ES.
+        .arg("--gles1=gles1_on_gl2")
+        // The networking tests only use localhost.
+        .arg("--enable-networking")
         .output()
         .expect("failed to execute touchHLE process");
 
@@ -120,3 +183,37 @@ fn run_test_app() -> Result<(), Box<dyn Error>> {
 
     Ok(())
 }
+
+// The app picker needs a window to render to, but in headless mode this is a
+// hidden window, so this doesn't need a display.
+#[test]
+fn screenshot_app_picker() -> Result<(), Box<dyn Error>> {
+    let binary_name = "touchHLE";
+    let binary_path = target_dir().join(format!("{}{}", binary_name, env::consts::EXE_SUFFIX));
+
+    let mut cmd = Command::new(binary_path);
+
+    let output = cmd
+        // No app path, so the app picker is displayed.
+        .arg("--headless")
+        .arg("--screenshot-after=1")
+        .output()
+        .expect("failed to execute touchHLE process");
+
+    std::io::stdout().write_all(&output.stdout).unwrap();
+    std::io::stderr().write_all(&output.stderr).unwrap();
+
+    assert!(output.status.success());
+
+    let prefix = "Saved screenshot: ";
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let screenshot_path = stderr
+        .lines()
+        .find_map(|line| line.strip_prefix(prefix))
+        .expect("no screenshot was saved");
+    let screenshot = std::fs::read(screenshot_path)?;
+    std::fs::remove_file(screenshot_path)?;
+    assert!(screenshot.starts_with(b"\x89PNG\r\n\x1a\n"));
+
+    Ok(())
+}